@@ -9,7 +9,8 @@ echo ""
 
 # Compile runtime
 echo "[Runtime] Compiling Rust Runtime (with threading support)..."
-rustc --crate-type staticlib -O -o libruntime_rust.a self-host/runtime.rs
+cargo build --release -p cryo-runtime
+cp target/release/libcryo_runtime.a libruntime_rust.a
 
 # Use Rust interpreter to compile the Cryo compiler
 echo ""
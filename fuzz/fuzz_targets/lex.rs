@@ -0,0 +1,8 @@
+#![no_main]
+
+use cryo::fuzz::{exercise, Limits, Target};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    exercise(Target::Lex, data, &Limits::default());
+});
@@ -7,13 +7,17 @@ let client;
 function activate(context) {
     console.log('Cryo Language extension activated');
 
-    // Path to the language server
-    // Path to the language server
+    // Path to the language server: `cryo lsp` when a binary is configured,
+    // otherwise the bundled Node.js server
+    const cryoPath = vscode.workspace.getConfiguration('cryo').get('server.path');
     const serverModule = context.asAbsolutePath(
         path.join('cryo-lsp.js')
     );
 
-    const serverOptions = {
+    const serverOptions = cryoPath ? {
+        run: { command: cryoPath, args: ['lsp'], transport: TransportKind.stdio },
+        debug: { command: cryoPath, args: ['lsp'], transport: TransportKind.stdio }
+    } : {
         run: { module: serverModule, transport: TransportKind.stdio },
         debug: { module: serverModule, transport: TransportKind.stdio }
     };
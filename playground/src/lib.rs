@@ -0,0 +1,130 @@
+// Cryo Playground - the interpreter compiled to WebAssembly
+// Build with:
+//   cargo build --release --target wasm32-unknown-unknown
+//   wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/cryo_playground.wasm
+// then serve this directory and open index.html
+
+use cryo::interpreter::Interpreter;
+use cryo::platform::{DirEntry, MemoryPlatform, Platform, Socket};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+
+/// Builtins denied on top of the `eval` sandbox list: stdin reads, which
+/// would block the page
+const PLAYGROUND_DENIED: &[&str] = &["input", "read_line", "readLine", "read_key", "tcp_read_line"];
+
+/// Statements and loop iterations a program may run before it's stopped, so an
+/// infinite loop can't hang the page
+const STEP_LIMIT: u64 = 5_000_000;
+
+/// Run `source` as a whole program and return everything it printed, followed
+/// by the error that stopped it, if any. Exported to JavaScript as `evaluate`,
+/// since `eval` can't be bound there.
+#[wasm_bindgen(js_name = evaluate)]
+pub fn eval(source: &str) -> String {
+    let mut interp = Interpreter::new();
+    interp.set_platform(Arc::new(BrowserPlatform { memory: MemoryPlatform::new(), slept: Default::default() }));
+    interp.set_sandbox(PLAYGROUND_DENIED);
+    interp.set_step_limit(Some(STEP_LIMIT));
+    let (result, mut output) = interp.run_source(source);
+    match result {
+        Ok(0) => {}
+        Ok(code) => output.push_str(&format!("[exit code {}]\n", code)),
+        Err(e) => {
+            output.push_str(&e);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// An in-memory platform whose clock and randomness are the browser's.
+/// `sleep` moves the clock forward instead of blocking the page.
+struct BrowserPlatform {
+    memory: MemoryPlatform,
+    slept: std::sync::Mutex<Duration>,
+}
+
+impl Platform for BrowserPlatform {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.memory.read_file(path)
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.memory.write_file(path, data)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.memory.exists(path)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        self.memory.read_dir(path)
+    }
+
+    fn connect(&self, addr: &str) -> io::Result<Box<dyn Socket>> {
+        self.memory.connect(addr)
+    }
+
+    fn now(&self) -> Duration {
+        let slept = *self.slept.lock().unwrap();
+        if cfg!(target_arch = "wasm32") {
+            Duration::from_secs_f64(js_sys::Date::now() / 1000.0) + slept
+        } else {
+            self.memory.now() + slept
+        }
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *self.slept.lock().unwrap() += duration;
+    }
+
+    fn random_bytes(&self, n: usize) -> io::Result<Vec<u8>> {
+        if cfg!(target_arch = "wasm32") {
+            browser_random(n)
+        } else {
+            self.memory.random_bytes(n)
+        }
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.memory.env_var(name)
+    }
+
+    fn write_stdout(&self, text: &str) {
+        self.memory.write_stdout(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("fn main() { print(1 + 2); }"), "3\n");
+        assert!(eval("fn main() { read_line(); }").contains("not permitted"));
+        assert_eq!(eval("fn main() { let t = timestamp_ms(); sleep(1500); print(timestamp_ms() - t); }"), "1500\n");
+        assert!(eval("fn main() { while (true) {} }").contains("step limit exceeded"));
+    }
+}
+
+/// `n` bytes from the browser's `crypto.getRandomValues`, the same kind of
+/// source `NativePlatform` uses; a browser without it is an error
+fn browser_random(n: usize) -> io::Result<Vec<u8>> {
+    let missing = |_| io::Error::other("this browser has no crypto.getRandomValues");
+    let crypto = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("crypto")).map_err(missing)?;
+    let fill = js_sys::Reflect::get(&crypto, &JsValue::from_str("getRandomValues")).map_err(missing)?
+        .dyn_into::<js_sys::Function>()
+        .map_err(missing)?;
+    let mut out = Vec::with_capacity(n);
+    // getRandomValues fills at most 64 KiB per call
+    while out.len() < n {
+        let chunk = js_sys::Uint8Array::new_with_length((n - out.len()).min(65536) as u32);
+        fill.call1(&crypto, &chunk).map_err(missing)?;
+        out.extend(chunk.to_vec());
+    }
+    Ok(out)
+}
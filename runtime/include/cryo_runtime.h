@@ -0,0 +1,295 @@
+#ifndef CRYO_RUNTIME_H
+#define CRYO_RUNTIME_H
+
+/* Generated with cbindgen:0.26.0 */
+
+/* Generated by cbindgen from runtime/src/lib.rs; do not edit. See docs/runtime_abi.md. */
+
+#include <stdint.h>
+
+/* Values are tagged 64-bit words: ints are (n << 1) | 1, heap objects are
+   8-aligned pointers (low bit 0), null is 0. */
+#define CRYO_NULL ((int64_t)0)
+#define CRYO_INT(n) ((int64_t)(((uint64_t)(int64_t)(n) << 1) | 1))
+#define CRYO_IS_INT(v) ((((int64_t)(v)) & 1) == 1)
+#define CRYO_TO_INT(v) (((int64_t)(v)) >> 1)
+#define CRYO_TRUE CRYO_INT(1)
+#define CRYO_FALSE CRYO_INT(0)
+
+typedef struct DlInfo {
+  const char *fname;
+  void *fbase;
+  const char *sname;
+  void *saddr;
+} DlInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+int64_t cryo_add(int64_t a, int64_t b);
+
+int64_t cryo_sub(int64_t a, int64_t b);
+
+int64_t cryo_mul(int64_t a, int64_t b);
+
+int64_t cryo_div(int64_t a, int64_t b);
+
+int64_t cryo_lt(int64_t a, int64_t b);
+
+int64_t cryo_gt(int64_t a, int64_t b);
+
+int64_t cryo_eq(int64_t a, int64_t b);
+
+/**
+ * Copy a NUL-terminated C string into a new runtime string.
+ *
+ * # Safety
+ * `s` must point to a valid NUL-terminated string.
+ */
+int64_t cryo_str_new(const char *s);
+
+int64_t cryo_arr_new(void);
+
+int64_t cryo_push(int64_t arr, int64_t val);
+
+int64_t cryo_get(int64_t arr, int64_t idx);
+
+int64_t cryo_set(int64_t arr, int64_t idx, int64_t val);
+
+int64_t cryo_len(int64_t val);
+
+int64_t cryo_get_args(void);
+
+int64_t cryo_char_code_at(int64_t s, int64_t idx);
+
+int64_t cryo_parse_int(int64_t s);
+
+void cryo_print(int64_t val);
+
+int64_t cryo_read_file(int64_t path);
+
+int64_t cryo_write_file(int64_t path, int64_t content);
+
+int64_t cryo_file_exists(int64_t path);
+
+int64_t cryo_listen(int64_t port);
+
+int64_t cryo_accept(int64_t id);
+
+int64_t cryo_socket_read(int64_t id);
+
+int64_t cryo_socket_write(int64_t id, int64_t str_val);
+
+int64_t cryo_socket_close(int64_t id);
+
+/**
+ * Spawn a new thread that calls a function pointer
+ * The function must take no arguments and return i64
+ * Returns: thread_id (tagged integer)
+ */
+int64_t cryo_thread_spawn(int64_t func_ptr);
+
+/**
+ * Wait for a thread to complete and get its result
+ * Returns: the return value of the thread function
+ */
+int64_t cryo_thread_join(int64_t thread_id);
+
+/**
+ * Create a new mutex
+ * Returns: mutex_id (tagged integer)
+ */
+int64_t cryo_mutex_new(void);
+
+/**
+ * Lock a mutex (blocking)
+ * Returns: 1 on success, -1 on failure
+ */
+int64_t cryo_mutex_lock(int64_t mutex_id);
+
+/**
+ * Unlock a mutex
+ * Returns: 1 on success
+ */
+int64_t cryo_mutex_unlock(int64_t mutex_id);
+
+/**
+ * Create a new atomic integer
+ * Returns: atomic_id (tagged integer)
+ */
+int64_t cryo_atomic_new(int64_t initial_value);
+
+/**
+ * Load value from atomic
+ * Returns: tagged integer value
+ */
+int64_t cryo_atomic_load(int64_t atomic_id);
+
+/**
+ * Store value to atomic
+ * Returns: 1 on success
+ */
+int64_t cryo_atomic_store(int64_t atomic_id, int64_t value);
+
+/**
+ * Atomically add to value and return previous value
+ * Returns: previous value (tagged)
+ */
+int64_t cryo_atomic_add(int64_t atomic_id, int64_t delta);
+
+/**
+ * Atomic compare-and-swap
+ * Returns: 1 if successful, 0 if not
+ */
+int64_t cryo_atomic_cas(int64_t atomic_id, int64_t expected, int64_t new_value);
+
+/**
+ * Sleep for specified milliseconds
+ */
+int64_t cryo_sleep(int64_t ms);
+
+/**
+ * Get current thread ID (for debugging)
+ */
+int64_t cryo_thread_id(void);
+
+int64_t cryo_char_from_code(int64_t code);
+
+int64_t cryo_exit(int64_t code);
+
+int64_t cryo_get_env(int64_t name);
+
+int64_t cryo_system(int64_t cmd);
+
+int64_t cryo_stdin_read(void);
+
+/**
+ * Box a C double
+ */
+int64_t cryo_float_new(double value);
+
+/**
+ * Unbox a float or widen an int; NaN for anything else
+ */
+double cryo_float_value(int64_t val);
+
+/**
+ * `a + b` as a float (ints are promoted); null if either isn't a number
+ */
+int64_t cryo_fadd(int64_t a, int64_t b);
+
+int64_t cryo_fsub(int64_t a, int64_t b);
+
+int64_t cryo_fmul(int64_t a, int64_t b);
+
+int64_t cryo_fdiv(int64_t a, int64_t b);
+
+/**
+ * Truncate toward zero to a tagged int (saturating; NaN becomes 0)
+ */
+int64_t cryo_float_to_int(int64_t val);
+
+int64_t cryo_float_to_str(int64_t val);
+
+/**
+ * Parse a string as a float; null when it isn't one
+ */
+int64_t cryo_parse_float(int64_t s);
+
+/**
+ * Push the address of a local slot onto the shadow stack. Whatever the
+ * slot holds at collection time is kept alive.
+ *
+ * # Safety
+ * `slot` must stay valid until it is popped with `cryo_gc_pop_roots`.
+ */
+void cryo_gc_push_root(const int64_t *slot);
+
+/**
+ * Pop the `count` most recently pushed shadow-stack roots
+ */
+void cryo_gc_pop_roots(int64_t count);
+
+/**
+ * Register a slot that is a root for the rest of the program, such as a
+ * global variable.
+ *
+ * # Safety
+ * `slot` must stay valid for as long as collections can happen.
+ */
+void cryo_gc_add_global_root(const int64_t *slot);
+
+/**
+ * Free every object not reachable from a root. Returns null, like the
+ * interpreter's `gc_collect`.
+ */
+int64_t cryo_gc_collect(void);
+
+/**
+ * `[heap_size, allocated_since_last_gc]` as a runtime array of ints, the
+ * same shape as the interpreter's `gc_stats`
+ */
+int64_t cryo_gc_stats(void);
+
+extern int32_t fflush(void *stream);
+
+/**
+ * Report a failed check and exit. `file` may be null and `line` 0 when
+ * the location is unknown.
+ *
+ * # Safety
+ * `msg` and `file` must be null or valid NUL-terminated strings.
+ */
+void cryo_panic(const char *msg, const char *file, int64_t line);
+
+/**
+ * `cryo_panic` for code built with frame pointers: `frame` is the
+ * caller's frame address (`llvm.frameaddress(0)`), where the backtrace
+ * starts.
+ *
+ * # Safety
+ * As for `cryo_panic`; `frame` must be null or the start of a frame
+ * pointer chain.
+ */
+void cryo_panic_at(const char *msg, const char *file, int64_t line, const void *frame);
+
+extern int32_t dladdr(const void *addr, struct DlInfo *info);
+
+int64_t cryo_to_string(int64_t val);
+
+/**
+ * Concatenate `count` values, converting non-strings as `cryo_to_string`
+ * does, in one allocation.
+ *
+ * # Safety
+ * `parts` must point to `count` readable values.
+ */
+int64_t cryo_str_concat_n(const int64_t *parts, int64_t count);
+
+/**
+ * `len` chars of `s` starting at char `start`
+ */
+int64_t cryo_str_substr(int64_t s, int64_t start, int64_t len);
+
+/**
+ * Array of the pieces of `s` between occurrences of `sep`
+ */
+int64_t cryo_str_split(int64_t s, int64_t sep);
+
+/**
+ * Byte offset of the first `needle` in `s`, or -1
+ */
+int64_t cryo_str_find(int64_t s, int64_t needle);
+
+/**
+ * Replace each `{}` in `fmt` with the next element of the `args` array;
+ * `{{` and `}}` are literal braces. Missing arguments leave `{}` in place.
+ */
+int64_t cryo_format(int64_t fmt, int64_t args);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* CRYO_RUNTIME_H */
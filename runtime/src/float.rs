@@ -0,0 +1,157 @@
+// ============================================
+// FLOATS
+// Boxed f64 objects (tag OBJ_FLOAT). Mixed int/float arithmetic promotes
+// the int, as in C; division follows IEEE 754, so x / 0.0 is inf or NaN.
+// ============================================
+
+use crate::{alloc_obj, from_int, is_int, is_ptr, str_new, to_int, ObjHeader, ObjString, OBJ_FLOAT, OBJ_STRING};
+
+#[repr(C)]
+pub(crate) struct ObjFloat {
+    pub header: ObjHeader,
+    pub value: f64,
+}
+
+pub(crate) fn float_new(value: f64) -> i64 {
+    let ptr = alloc_obj(std::mem::size_of::<ObjFloat>(), OBJ_FLOAT) as *mut ObjFloat;
+    unsafe { (*ptr).value = value };
+    ptr as i64
+}
+
+pub(crate) fn is_float(val: i64) -> bool {
+    is_ptr(val) && unsafe { (*(val as *const ObjHeader)).type_tag == OBJ_FLOAT }
+}
+
+/// The value of a float, or an int widened to one
+pub(crate) fn as_float(val: i64) -> Option<f64> {
+    if is_int(val) {
+        Some(to_int(val) as f64)
+    } else if is_float(val) {
+        Some(unsafe { (*(val as *const ObjFloat)).value })
+    } else {
+        None
+    }
+}
+
+/// Printed like the interpreter prints it: whole numbers keep a `.0` so a
+/// float never looks like an int, and very large or small ones use exponent form
+pub(crate) fn format_float(value: f64) -> String {
+    let magnitude = value.abs();
+    if !value.is_finite() {
+        format!("{}", value)
+    } else if magnitude != 0.0 && !(1e-7..1e21).contains(&magnitude) {
+        format!("{:e}", value)
+    } else if value.fract() == 0.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{}", value)
+    }
+}
+
+/// Both operands as floats, when either one is a float and neither is a
+/// non-number
+pub(crate) fn float_operands(a: i64, b: i64) -> Option<(f64, f64)> {
+    if !is_float(a) && !is_float(b) {
+        return None;
+    }
+    Some((as_float(a)?, as_float(b)?))
+}
+
+fn arith(a: i64, b: i64, op: fn(f64, f64) -> f64) -> i64 {
+    match (as_float(a), as_float(b)) {
+        (Some(x), Some(y)) => float_new(op(x, y)),
+        _ => 0,
+    }
+}
+
+/// Box a C double
+#[no_mangle]
+pub extern "C" fn cryo_float_new(value: f64) -> i64 {
+    float_new(value)
+}
+
+/// Unbox a float or widen an int; NaN for anything else
+#[no_mangle]
+pub extern "C" fn cryo_float_value(val: i64) -> f64 {
+    as_float(val).unwrap_or(f64::NAN)
+}
+
+/// `a + b` as a float (ints are promoted); null if either isn't a number
+#[no_mangle]
+pub extern "C" fn cryo_fadd(a: i64, b: i64) -> i64 {
+    arith(a, b, |x, y| x + y)
+}
+
+#[no_mangle]
+pub extern "C" fn cryo_fsub(a: i64, b: i64) -> i64 {
+    arith(a, b, |x, y| x - y)
+}
+
+#[no_mangle]
+pub extern "C" fn cryo_fmul(a: i64, b: i64) -> i64 {
+    arith(a, b, |x, y| x * y)
+}
+
+#[no_mangle]
+pub extern "C" fn cryo_fdiv(a: i64, b: i64) -> i64 {
+    arith(a, b, |x, y| x / y)
+}
+
+/// Truncate toward zero to a tagged int (saturating; NaN becomes 0)
+#[no_mangle]
+pub extern "C" fn cryo_float_to_int(val: i64) -> i64 {
+    match as_float(val) {
+        Some(f) => from_int((f as i64).clamp(i64::MIN >> 1, i64::MAX >> 1)),
+        None => from_int(0),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn cryo_float_to_str(val: i64) -> i64 {
+    match as_float(val) {
+        Some(f) => str_new(format_float(f)),
+        None => str_new(""),
+    }
+}
+
+/// Parse a string as a float; null when it isn't one
+#[no_mangle]
+pub extern "C" fn cryo_parse_float(s: i64) -> i64 {
+    if is_ptr(s) && unsafe { (*(s as *const ObjHeader)).type_tag == OBJ_STRING } {
+        let data = unsafe { &(*(s as *const ObjString)).data };
+        if let Ok(f) = data.trim().parse::<f64>() {
+            return float_new(f);
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cryo_add, cryo_eq, cryo_lt, cryo_sub};
+
+    fn text(val: i64) -> String {
+        unsafe { (*(val as *const ObjString)).data.clone() }
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        let _heap = crate::TEST_HEAP.lock().unwrap_or_else(|e| e.into_inner());
+        let third = cryo_fdiv(from_int(1), from_int(3));
+        assert!((cryo_float_value(third) - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(text(cryo_float_to_str(cryo_fmul(float_new(2.5), from_int(2)))), "5.0");
+        assert_eq!(text(cryo_float_to_str(float_new(1e300))), "1e300");
+        assert!(cryo_float_value(cryo_fdiv(from_int(1), from_int(0))).is_infinite());
+
+        // The generic operators promote as soon as one side is a float
+        assert_eq!(cryo_float_value(cryo_sub(float_new(0.5), from_int(2))), -1.5);
+        assert_eq!(cryo_eq(float_new(2.0), from_int(2)), from_int(1));
+        assert_eq!(cryo_lt(from_int(1), float_new(1.5)), from_int(1));
+        assert_eq!(text(cryo_add(str_new("pi="), float_new(3.25))), "pi=3.25");
+
+        assert_eq!(cryo_float_to_int(cryo_parse_float(str_new(" -7.9 "))), from_int(-7));
+        assert_eq!(cryo_parse_float(str_new("abc")), 0);
+        assert_eq!(cryo_fadd(str_new("x"), from_int(1)), 0);
+    }
+}
@@ -0,0 +1,180 @@
+// ============================================
+// GARBAGE COLLECTOR
+// Mark-sweep over every object alloc_obj hands out. Roots are slots
+// registered on the shadow stack (cryo_gc_push_root / cryo_gc_pop_roots)
+// or as globals (cryo_gc_add_global_root); only objects reachable from
+// them survive a collection.
+// ============================================
+
+use crate::float::ObjFloat;
+use crate::{from_int, is_ptr, ObjArray, ObjHeader, ObjString, OBJ_ARRAY, OBJ_FLOAT, OBJ_STRING};
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+struct Heap {
+    /// Addresses of live (not yet swept) objects
+    objects: Vec<usize>,
+    /// Allocations since the last collection
+    allocated: usize,
+    /// Shadow stack: addresses of i64 slots holding values
+    roots: Vec<usize>,
+    globals: Vec<usize>,
+}
+
+static HEAP: Mutex<Heap> = Mutex::new(Heap { objects: Vec::new(), allocated: 0, roots: Vec::new(), globals: Vec::new() });
+
+fn heap() -> MutexGuard<'static, Heap> {
+    HEAP.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Allocations between automatic collections, from `CRYO_GC_THRESHOLD`
+/// (or `ARGON_GC_THRESHOLD`). Unset means collections only happen through
+/// `cryo_gc_collect`, since code that doesn't register its roots would
+/// otherwise lose objects it still holds.
+fn threshold() -> Option<usize> {
+    static THRESHOLD: OnceLock<Option<usize>> = OnceLock::new();
+    *THRESHOLD.get_or_init(|| {
+        let value = std::env::var("CRYO_GC_THRESHOLD").or_else(|_| std::env::var("ARGON_GC_THRESHOLD")).ok()?;
+        value.trim().parse().ok().filter(|&n| n > 0)
+    })
+}
+
+/// Record a fresh object, collecting first when the threshold is reached
+pub(crate) fn track(obj: *mut ObjHeader) {
+    let mut heap = heap();
+    if threshold().is_some_and(|t| heap.allocated >= t) {
+        collect(&mut heap);
+    }
+    heap.objects.push(obj as usize);
+    heap.allocated += 1;
+}
+
+fn collect(heap: &mut Heap) -> usize {
+    let objects: HashSet<usize> = heap.objects.iter().copied().collect();
+    let mut marked = HashSet::new();
+    let slots = heap.roots.iter().chain(&heap.globals);
+    let mut pending: Vec<i64> = slots.map(|&slot| unsafe { *(slot as *const i64) }).collect();
+    while let Some(val) = pending.pop() {
+        // Arrays can hold arbitrary words, so only trust addresses we handed out
+        let addr = val as usize;
+        if !is_ptr(val) || !objects.contains(&addr) || !marked.insert(addr) {
+            continue;
+        }
+        unsafe {
+            if (*(addr as *const ObjHeader)).type_tag == OBJ_ARRAY {
+                pending.extend_from_slice(&(*(addr as *const ObjArray)).items);
+            }
+        }
+    }
+
+    let before = heap.objects.len();
+    heap.objects.retain(|&addr| {
+        if marked.contains(&addr) {
+            return true;
+        }
+        unsafe { free(addr as *mut ObjHeader) };
+        false
+    });
+    heap.allocated = 0;
+    before - heap.objects.len()
+}
+
+unsafe fn free(obj: *mut ObjHeader) {
+    let size = match (*obj).type_tag {
+        OBJ_STRING => {
+            std::ptr::drop_in_place(&mut (*(obj as *mut ObjString)).data);
+            std::mem::size_of::<ObjString>()
+        }
+        OBJ_ARRAY => {
+            std::ptr::drop_in_place(&mut (*(obj as *mut ObjArray)).items);
+            std::mem::size_of::<ObjArray>()
+        }
+        OBJ_FLOAT => std::mem::size_of::<ObjFloat>(),
+        _ => return,
+    };
+    std::alloc::dealloc(obj as *mut u8, std::alloc::Layout::from_size_align(size, 8).unwrap());
+}
+
+/// Push the address of a local slot onto the shadow stack. Whatever the
+/// slot holds at collection time is kept alive.
+///
+/// # Safety
+/// `slot` must stay valid until it is popped with `cryo_gc_pop_roots`.
+#[no_mangle]
+pub unsafe extern "C" fn cryo_gc_push_root(slot: *const i64) {
+    heap().roots.push(slot as usize);
+}
+
+/// Pop the `count` most recently pushed shadow-stack roots
+#[no_mangle]
+pub extern "C" fn cryo_gc_pop_roots(count: i64) {
+    let mut heap = heap();
+    let keep = heap.roots.len().saturating_sub(count.max(0) as usize);
+    heap.roots.truncate(keep);
+}
+
+/// Register a slot that is a root for the rest of the program, such as a
+/// global variable.
+///
+/// # Safety
+/// `slot` must stay valid for as long as collections can happen.
+#[no_mangle]
+pub unsafe extern "C" fn cryo_gc_add_global_root(slot: *const i64) {
+    heap().globals.push(slot as usize);
+}
+
+/// Free every object not reachable from a root. Returns null, like the
+/// interpreter's `gc_collect`.
+#[no_mangle]
+pub extern "C" fn cryo_gc_collect() -> i64 {
+    collect(&mut heap());
+    0
+}
+
+/// `[heap_size, allocated_since_last_gc]` as a runtime array of ints, the
+/// same shape as the interpreter's `gc_stats`
+#[no_mangle]
+pub extern "C" fn cryo_gc_stats() -> i64 {
+    let (size, allocated) = {
+        let heap = heap();
+        (heap.objects.len(), heap.allocated)
+    };
+    let stats = crate::cryo_arr_new();
+    crate::cryo_push(stats, from_int(size as i64));
+    crate::cryo_push(stats, from_int(allocated as i64));
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cryo_arr_new, cryo_get, cryo_len, cryo_push, str_new, to_int};
+
+    fn live() -> usize {
+        heap().objects.len()
+    }
+
+    #[test]
+    fn test_collect_keeps_rooted_objects() {
+        let _heap = crate::TEST_HEAP.lock().unwrap_or_else(|e| e.into_inner());
+        // Drop whatever earlier tests left behind
+        cryo_gc_collect();
+        let before = live();
+        let arr = cryo_arr_new();
+        cryo_push(arr, str_new("kept"));
+        cryo_push(arr, from_int(7));
+        str_new("garbage");
+        let root = arr;
+        unsafe { cryo_gc_push_root(&root) };
+
+        assert_eq!(collect(&mut heap()), 1);
+        assert_eq!(live(), before + 2);
+        assert_eq!(to_int(cryo_len(cryo_get(root, from_int(0)))), 4);
+
+        cryo_gc_pop_roots(1);
+        let stats = cryo_gc_stats();
+        assert_eq!(to_int(cryo_get(stats, from_int(0))), live() as i64 - 1);
+        cryo_gc_collect();
+        assert_eq!(live(), before);
+    }
+}
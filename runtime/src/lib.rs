@@ -12,6 +12,17 @@ use std::sync::{Arc, Mutex, atomic::{AtomicI64, Ordering}};
 use std::thread::{self, JoinHandle};
 use std::collections::HashMap;
 
+mod float;
+mod gc;
+mod panic;
+mod string;
+
+use float::{float_new, float_operands, format_float};
+
+/// Tests that allocate or collect share one heap, so they take turns
+#[cfg(test)]
+static TEST_HEAP: Mutex<()> = Mutex::new(());
+
 // ============================================
 // CRYO RUNTIME LIBRARY (RUST EDITION)
 // ============================================
@@ -19,6 +30,7 @@ use std::collections::HashMap;
 // --- TYPES ---
 const OBJ_STRING: u64 = 0;
 const OBJ_ARRAY: u64 = 1;
+const OBJ_FLOAT: u64 = 2;
 
 #[repr(C)]
 struct ObjHeader {
@@ -61,6 +73,18 @@ pub extern "C" fn cryo_add(a: i64, b: i64) -> i64 {
     if is_int(a) && is_int(b) {
         return from_int(to_int(a) + to_int(b));
     }
+    if let Some((x, y)) = float_operands(a, b) {
+        return float_new(x + y);
+    }
+    // String + Float / Float + String
+    if float::is_float(a) || float::is_float(b) {
+        let (s, f, float_first) = if float::is_float(a) { (b, a, true) } else { (a, b, false) };
+        if is_ptr(s) && unsafe { (*(s as *const ObjHeader)).type_tag } == OBJ_STRING {
+            let text = unsafe { &(*(s as *const ObjString)).data };
+            let num = format_float(float::as_float(f).unwrap_or(f64::NAN));
+            return str_new(if float_first { num + text } else { format!("{}{}", text, num) });
+        }
+    }
     // String concatenation
     if is_ptr(a) && is_ptr(b) {
         unsafe {
@@ -111,16 +135,25 @@ pub extern "C" fn cryo_add(a: i64, b: i64) -> i64 {
 
 #[no_mangle]
 pub extern "C" fn cryo_sub(a: i64, b: i64) -> i64 {
+    if let Some((x, y)) = float_operands(a, b) {
+        return float_new(x - y);
+    }
     from_int(to_int(a) - to_int(b))
 }
 
 #[no_mangle]
 pub extern "C" fn cryo_mul(a: i64, b: i64) -> i64 {
+    if let Some((x, y)) = float_operands(a, b) {
+        return float_new(x * y);
+    }
     from_int(to_int(a) * to_int(b))
 }
 
 #[no_mangle]
 pub extern "C" fn cryo_div(a: i64, b: i64) -> i64 {
+    if let Some((x, y)) = float_operands(a, b) {
+        return float_new(x / y);
+    }
     let vb = to_int(b);
     if vb == 0 { return from_int(0); }
     from_int(to_int(a) / vb)
@@ -128,11 +161,17 @@ pub extern "C" fn cryo_div(a: i64, b: i64) -> i64 {
 
 #[no_mangle]
 pub extern "C" fn cryo_lt(a: i64, b: i64) -> i64 {
+    if let Some((x, y)) = float_operands(a, b) {
+        return from_int((x < y) as i64);
+    }
     if to_int(a) < to_int(b) { from_int(1) } else { from_int(0) }
 }
 
 #[no_mangle]
 pub extern "C" fn cryo_gt(a: i64, b: i64) -> i64 {
+    if let Some((x, y)) = float_operands(a, b) {
+        return from_int((x > y) as i64);
+    }
     if to_int(a) > to_int(b) { from_int(1) } else { from_int(0) }
 }
 
@@ -142,6 +181,9 @@ pub extern "C" fn cryo_eq(a: i64, b: i64) -> i64 {
     if a == b { 
         return from_int(1); 
     }
+    if let Some((x, y)) = float_operands(a, b) {
+        return from_int((x == y) as i64);
+    }
     
     // Both are pointers - check if ObjStrings with same content
     if is_ptr(a) && is_ptr(b) {
@@ -162,26 +204,34 @@ pub extern "C" fn cryo_eq(a: i64, b: i64) -> i64 {
     from_int(0)
 }
 
-// --- ALLOCATION STUBS (Minimal) ---
+// --- ALLOCATION ---
+// Every object is tracked by the collector in gc.rs
 fn alloc_obj(size: usize, tag: u64) -> *mut ObjHeader {
     let layout = std::alloc::Layout::from_size_align(size, 8).unwrap();
     let ptr = unsafe { std::alloc::alloc(layout) as *mut ObjHeader };
     unsafe { (*ptr).type_tag = tag };
+    gc::track(ptr);
     ptr
 }
 
-#[no_mangle]
-pub extern "C" fn cryo_str_new(s: *const c_char) -> i64 {
-    let c_str = unsafe { CStr::from_ptr(s) };
-    let r_str = c_str.to_string_lossy().into_owned();
+fn str_new(data: impl Into<String>) -> i64 {
     let size = std::mem::size_of::<ObjString>();
     let ptr = alloc_obj(size, OBJ_STRING) as *mut ObjString;
     unsafe {
-        ptr::write(&mut (*ptr).data, r_str);
+        ptr::write(&mut (*ptr).data, data.into());
     }
     ptr as i64
 }
 
+/// Copy a NUL-terminated C string into a new runtime string.
+///
+/// # Safety
+/// `s` must point to a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cryo_str_new(s: *const c_char) -> i64 {
+    str_new(CStr::from_ptr(s).to_string_lossy().into_owned())
+}
+
 #[no_mangle]
 pub extern "C" fn cryo_arr_new() -> i64 {
     let size = std::mem::size_of::<ObjArray>();
@@ -198,7 +248,7 @@ pub extern "C" fn cryo_push(arr: i64, val: i64) -> i64 {
         let ptr = arr as *mut ObjArray;
         unsafe {
              if (*ptr).header.type_tag == OBJ_ARRAY {
-                 (&mut (*ptr).items).push(val);
+                 (*ptr).items.push(val);
              }
         }
     }
@@ -224,8 +274,7 @@ pub extern "C" fn cryo_get(arr: i64, idx: i64) -> i64 {
                  let idx = to_int(idx) as usize;
                  if idx < data.len() {
                      let ch = &data[idx..idx+1];
-                     let cstr = std::ffi::CString::new(ch).unwrap();
-                     return cryo_str_new(cstr.as_ptr());
+                     return str_new(ch);
                  }
              }
         }
@@ -239,9 +288,9 @@ pub extern "C" fn cryo_set(arr: i64, idx: i64, val: i64) -> i64 {
         let ptr = arr as *mut ObjArray;
         unsafe {
              if (*ptr).header.type_tag == OBJ_ARRAY {
-                 let idx = to_int(idx) as usize;
-                 if idx < (&(*ptr).items).len() {
-                     (&mut (*ptr).items)[idx] = val;
+                 let items = &mut (*ptr).items;
+                 if let Some(slot) = items.get_mut(to_int(idx) as usize) {
+                     *slot = val;
                  }
              }
         }
@@ -258,8 +307,8 @@ pub extern "C" fn cryo_len(val: i64) -> i64 {
                 let arr = val as *mut ObjArray;
                 return from_int((*arr).items.len() as i64);
             } else if (*header).type_tag == OBJ_STRING {
-                let s = val as *mut ObjString;
-                return from_int((&(*s).data).len() as i64);
+                let s = &*(val as *mut ObjString);
+                return from_int(s.data.len() as i64);
             }
         }
     }
@@ -270,8 +319,7 @@ pub extern "C" fn cryo_len(val: i64) -> i64 {
 pub extern "C" fn cryo_get_args() -> i64 {
     let arr = cryo_arr_new();
     for arg in std::env::args() {
-        let s_ptr = std::ffi::CString::new(arg).unwrap();
-        let s_obj = cryo_str_new(s_ptr.as_ptr());
+        let s_obj = str_new(arg);
         cryo_push(arr, s_obj);
     }
     arr
@@ -317,21 +365,7 @@ pub extern "C" fn cryo_parse_int(s: i64) -> i64 {
 
 #[no_mangle]
 pub extern "C" fn cryo_print(val: i64) {
-    if is_int(val) {
-        println!("{}", to_int(val));
-    } else if is_ptr(val) {
-        unsafe {
-            let header = val as *mut ObjHeader;
-            if (*header).type_tag == OBJ_STRING {
-                let obj = val as *mut ObjString;
-                println!("{}", &(*obj).data);
-            } else {
-                println!("[Array]");
-            }
-        }
-    } else {
-        println!("[Null]");
-    }
+    println!("{}", string::display(val));
 }
 
 #[no_mangle]
@@ -343,8 +377,7 @@ pub extern "C" fn cryo_read_file(path: i64) -> i64 {
                 let obj = path as *mut ObjString;
                 let path_str = &(*obj).data;
                 if let Ok(content) = std::fs::read_to_string(path_str) {
-                    let cstr = std::ffi::CString::new(content).unwrap();
-                    return cryo_str_new(cstr.as_ptr());
+                    return str_new(content);
                 }
             }
         }
@@ -428,10 +461,9 @@ pub extern "C" fn cryo_socket_read(id: i64) -> i64 {
             if let Some(s) = &mut STREAMS[idx] {
                 let mut buf = [0u8; 1024];
                 if let Ok(n) = s.read(&mut buf) {
-                     if n == 0 { return cryo_str_new(std::ffi::CString::new("").unwrap().as_ptr()); }
+                     if n == 0 { return str_new(""); }
                      let s_str = String::from_utf8_lossy(&buf[..n]).to_string();
-                     let cstr = std::ffi::CString::new(s_str).unwrap();
-                     return cryo_str_new(cstr.as_ptr());
+                     return str_new(s_str);
                 }
             }
         }
@@ -651,8 +683,7 @@ pub extern "C" fn cryo_thread_id() -> i64 {
 pub extern "C" fn cryo_char_from_code(code: i64) -> i64 {
     let c = to_int(code) as u8 as char;
     let s = c.to_string();
-    let cstr = std::ffi::CString::new(s).unwrap();
-    cryo_str_new(cstr.as_ptr())
+    str_new(s)
 }
 
 #[no_mangle]
@@ -670,19 +701,16 @@ pub extern "C" fn cryo_get_env(name: i64) -> i64 {
                 let key = &(*obj).data;
                 match std::env::var(key) {
                     Ok(val) => {
-                        let cstr = std::ffi::CString::new(val).unwrap();
-                        return cryo_str_new(cstr.as_ptr());
+                        return str_new(val);
                     },
                     Err(_) => {
-                        let cstr = std::ffi::CString::new("").unwrap();
-                        return cryo_str_new(cstr.as_ptr());
+                        return str_new("");
                     }
                 }
             }
         }
     }
-    let cstr = std::ffi::CString::new("").unwrap();
-    cryo_str_new(cstr.as_ptr())
+    str_new("")
 }
 
 #[no_mangle]
@@ -718,7 +746,7 @@ pub extern "C" fn cryo_system(cmd: i64) -> i64 {
 #[no_mangle]
 pub extern "C" fn cryo_stdin_read() -> i64 {
     let mut buffer = String::new();
-    if let Ok(_) = std::io::stdin().read_line(&mut buffer) {
+    if std::io::stdin().read_line(&mut buffer).is_ok() {
         // Remove trailing newline if present
         if buffer.ends_with('\n') {
             buffer.pop();
@@ -726,9 +754,7 @@ pub extern "C" fn cryo_stdin_read() -> i64 {
                 buffer.pop();
             }
         }
-        let cstr = std::ffi::CString::new(buffer).unwrap();
-        return cryo_str_new(cstr.as_ptr());
+        return str_new(buffer);
     }
-    let cstr = std::ffi::CString::new("").unwrap();
-    cryo_str_new(cstr.as_ptr())
+    str_new("")
 }
@@ -0,0 +1,120 @@
+// ============================================
+// PANICS
+// What compiled code calls when a runtime check fails: print a Cryo-level
+// message with its source location, optionally a backtrace from walking
+// frame pointers (CRYO_BACKTRACE=1), and exit with status 101.
+// ============================================
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+
+extern "C" {
+    fn fflush(stream: *mut c_void) -> i32;
+}
+
+/// Report a failed check and exit. `file` may be null and `line` 0 when
+/// the location is unknown.
+///
+/// # Safety
+/// `msg` and `file` must be null or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cryo_panic(msg: *const c_char, file: *const c_char, line: i64) -> ! {
+    cryo_panic_at(msg, file, line, std::ptr::null())
+}
+
+/// `cryo_panic` for code built with frame pointers: `frame` is the
+/// caller's frame address (`llvm.frameaddress(0)`), where the backtrace
+/// starts.
+///
+/// # Safety
+/// As for `cryo_panic`; `frame` must be null or the start of a frame
+/// pointer chain.
+#[no_mangle]
+pub unsafe extern "C" fn cryo_panic_at(msg: *const c_char, file: *const c_char, line: i64, frame: *const c_void) -> ! {
+    let text = |s: *const c_char| if s.is_null() { String::new() } else { CStr::from_ptr(s).to_string_lossy().into_owned() };
+    // Compiled code prints with printf, so flush C stdio before writing to stderr
+    fflush(std::ptr::null_mut());
+    eprintln!("panic: {}", text(msg));
+    let file = text(file);
+    match (file.is_empty(), line > 0) {
+        (false, true) => eprintln!("  --> {}:{}", file, line),
+        (false, false) => eprintln!("  --> {}", file),
+        _ => {}
+    }
+    if frame.is_null() {
+        // No frame chain to follow
+    } else if backtrace_enabled() {
+        print_backtrace(frame as *const usize);
+    } else {
+        eprintln!("note: run with CRYO_BACKTRACE=1 for a backtrace");
+    }
+    std::process::exit(101)
+}
+
+fn backtrace_enabled() -> bool {
+    let value = std::env::var("CRYO_BACKTRACE").or_else(|_| std::env::var("ARGON_BACKTRACE")).unwrap_or_default();
+    !matches!(value.trim(), "" | "0" | "off" | "false")
+}
+
+/// Most frames a backtrace shows
+const MAX_FRAMES: usize = 64;
+/// A saved frame pointer further up the stack than this is taken to be garbage
+const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// Each frame holds the caller's frame pointer, then the return address.
+/// Stops at `main`, since the C startup code below it has no frame pointers.
+fn print_backtrace(mut fp: *const usize) {
+    eprintln!("backtrace:");
+    for depth in 0..MAX_FRAMES {
+        if fp.is_null() || !fp.is_aligned() {
+            break;
+        }
+        let (next, ret) = unsafe { (*fp as *const usize, *fp.add(1)) };
+        if ret == 0 {
+            break;
+        }
+        let (name, location) = symbolize(ret);
+        eprintln!("  {:>2}: {}{}", depth, name.as_deref().unwrap_or("<unknown>"), location);
+        let next_addr = next as usize;
+        if name.as_deref() == Some("main") || next_addr <= fp as usize || next_addr - fp as usize > MAX_FRAME_SIZE {
+            break;
+        }
+        fp = next;
+    }
+}
+
+#[cfg(unix)]
+#[repr(C)]
+struct DlInfo {
+    fname: *const c_char,
+    fbase: *mut c_void,
+    sname: *const c_char,
+    saddr: *mut c_void,
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn dladdr(addr: *const c_void, info: *mut DlInfo) -> i32;
+}
+
+/// Function containing a return address and where it is in its binary, for
+/// `addr2line -e FILE OFFSET`. Executables need `-rdynamic` for their own
+/// function names to show up.
+#[cfg(unix)]
+fn symbolize(ret: usize) -> (Option<String>, String) {
+    let mut info = DlInfo { fname: std::ptr::null(), fbase: std::ptr::null_mut(), sname: std::ptr::null(), saddr: std::ptr::null_mut() };
+    // ret points after the call; look up the call instruction itself
+    let pc = ret - 1;
+    if unsafe { dladdr(pc as *const c_void, &mut info) } == 0 {
+        return (None, format!(" ({:#x})", ret));
+    }
+    let cstr = |s: *const c_char| (!s.is_null()).then(|| unsafe { CStr::from_ptr(s).to_string_lossy().into_owned() });
+    let name = cstr(info.sname);
+    let file = cstr(info.fname).unwrap_or_default();
+    (name, format!(" ({}+{:#x})", file, pc - info.fbase as usize))
+}
+
+#[cfg(not(unix))]
+fn symbolize(ret: usize) -> (Option<String>, String) {
+    (None, format!(" ({:#x})", ret))
+}
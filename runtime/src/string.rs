@@ -0,0 +1,161 @@
+// ============================================
+// STRINGS
+// Conversions and the string functions backends lower the interpreter's
+// builtins to. Semantics follow the interpreter: substr counts chars,
+// find returns a byte offset or -1, split keeps empty pieces.
+// ============================================
+
+use crate::float::{as_float, format_float, is_float};
+use crate::{cryo_arr_new, cryo_push, from_int, is_int, is_ptr, str_new, to_int, ObjArray, ObjHeader, ObjString, OBJ_ARRAY, OBJ_STRING};
+
+/// The text of a string object
+pub(crate) fn str_ref<'a>(val: i64) -> Option<&'a str> {
+    if is_ptr(val) && unsafe { (*(val as *const ObjHeader)).type_tag } == OBJ_STRING {
+        Some(unsafe { &(*(val as *const ObjString)).data })
+    } else {
+        None
+    }
+}
+
+/// Any value as text, like the interpreter's `str()`
+pub(crate) fn display(val: i64) -> String {
+    if is_int(val) {
+        return to_int(val).to_string();
+    }
+    if let Some(s) = str_ref(val) {
+        return s.to_string();
+    }
+    if is_float(val) {
+        return format_float(as_float(val).unwrap_or(f64::NAN));
+    }
+    if is_ptr(val) && unsafe { (*(val as *const ObjHeader)).type_tag } == OBJ_ARRAY {
+        let items = unsafe { &(*(val as *const ObjArray)).items };
+        return format!("[{}]", items.iter().map(|&v| display(v)).collect::<Vec<_>>().join(", "));
+    }
+    "null".to_string()
+}
+
+#[no_mangle]
+pub extern "C" fn cryo_to_string(val: i64) -> i64 {
+    str_new(display(val))
+}
+
+/// Concatenate `count` values, converting non-strings as `cryo_to_string`
+/// does, in one allocation.
+///
+/// # Safety
+/// `parts` must point to `count` readable values.
+#[no_mangle]
+pub unsafe extern "C" fn cryo_str_concat_n(parts: *const i64, count: i64) -> i64 {
+    if parts.is_null() || count <= 0 {
+        return str_new("");
+    }
+    let parts = std::slice::from_raw_parts(parts, count as usize);
+    let mut out = String::new();
+    for &part in parts {
+        match str_ref(part) {
+            Some(s) => out.push_str(s),
+            None => out.push_str(&display(part)),
+        }
+    }
+    str_new(out)
+}
+
+/// `len` chars of `s` starting at char `start`
+#[no_mangle]
+pub extern "C" fn cryo_str_substr(s: i64, start: i64, len: i64) -> i64 {
+    let Some(text) = str_ref(s) else { return str_new("") };
+    let start = to_int(start).max(0) as usize;
+    let len = to_int(len).max(0) as usize;
+    str_new(text.chars().skip(start).take(len).collect::<String>())
+}
+
+/// Array of the pieces of `s` between occurrences of `sep`
+#[no_mangle]
+pub extern "C" fn cryo_str_split(s: i64, sep: i64) -> i64 {
+    let arr = cryo_arr_new();
+    if let (Some(text), Some(sep)) = (str_ref(s), str_ref(sep)) {
+        for piece in text.split(sep) {
+            cryo_push(arr, str_new(piece));
+        }
+    }
+    arr
+}
+
+/// Byte offset of the first `needle` in `s`, or -1
+#[no_mangle]
+pub extern "C" fn cryo_str_find(s: i64, needle: i64) -> i64 {
+    match (str_ref(s), str_ref(needle)) {
+        (Some(text), Some(needle)) => from_int(text.find(needle).map_or(-1, |i| i as i64)),
+        _ => from_int(-1),
+    }
+}
+
+/// Replace each `{}` in `fmt` with the next element of the `args` array;
+/// `{{` and `}}` are literal braces. Missing arguments leave `{}` in place.
+#[no_mangle]
+pub extern "C" fn cryo_format(fmt: i64, args: i64) -> i64 {
+    let Some(fmt) = str_ref(fmt) else { return str_new("") };
+    let args: &[i64] = if is_ptr(args) && unsafe { (*(args as *const ObjHeader)).type_tag } == OBJ_ARRAY {
+        unsafe { &(*(args as *const ObjArray)).items }
+    } else {
+        &[]
+    };
+    let mut next = args.iter();
+    let mut out = String::with_capacity(fmt.len());
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                out.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                match next.next() {
+                    Some(&arg) => out.push_str(&display(arg)),
+                    None => out.push_str("{}"),
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    str_new(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cryo_get, cryo_len};
+    use crate::float::float_new;
+
+    fn text(val: i64) -> &'static str {
+        str_ref(val).unwrap()
+    }
+
+    #[test]
+    fn test_string_functions() {
+        let _heap = crate::TEST_HEAP.lock().unwrap_or_else(|e| e.into_inner());
+        let list = cryo_arr_new();
+        cryo_push(list, from_int(1));
+        cryo_push(list, str_new("two"));
+        assert_eq!(text(cryo_to_string(list)), "[1, two]");
+        assert_eq!(text(cryo_to_string(float_new(0.5))), "0.5");
+        assert_eq!(text(cryo_to_string(0)), "null");
+
+        let parts = [str_new("n="), from_int(42), str_new(", "), float_new(2.0)];
+        assert_eq!(text(unsafe { cryo_str_concat_n(parts.as_ptr(), 4) }), "n=42, 2.0");
+
+        assert_eq!(text(cryo_str_substr(str_new("héllo"), from_int(1), from_int(3))), "éll");
+        let pieces = cryo_str_split(str_new("a,,b"), str_new(","));
+        assert_eq!(to_int(cryo_len(pieces)), 3);
+        assert_eq!(text(cryo_get(pieces, from_int(2))), "b");
+        assert_eq!(cryo_str_find(str_new("hello"), str_new("llo")), from_int(2));
+        assert_eq!(cryo_str_find(str_new("hello"), str_new("z")), from_int(-1));
+
+        let args = cryo_arr_new();
+        cryo_push(args, str_new("x"));
+        cryo_push(args, from_int(3));
+        assert_eq!(text(cryo_format(str_new("{} = {} {{ok}} {}"), args)), "x = 3 {ok} {}");
+    }
+}
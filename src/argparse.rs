@@ -0,0 +1,326 @@
+// ============================================
+// Cryo Argument Parsing Module
+// Flags, options and positionals for script CLIs, with generated help
+// ============================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// `--verbose` / `-v`, true when present
+    Flag,
+    /// `--output FILE`, `--output=FILE`, `-o FILE` or `-oFILE`
+    Option,
+    Positional,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArgSpec {
+    pub name: String,
+    pub kind: ArgKind,
+    pub short: Option<char>,
+    pub help: String,
+    pub default: Option<String>,
+    pub required: bool,
+    /// Options may repeat and positionals soak up the remaining arguments;
+    /// either way the value is a list
+    pub multiple: bool,
+    /// Value must parse as an integer
+    pub int: bool,
+}
+
+impl ArgSpec {
+    pub fn new(name: &str, kind: ArgKind) -> Self {
+        ArgSpec {
+            name: name.trim_start_matches('-').to_string(),
+            kind,
+            short: None,
+            help: String::new(),
+            default: None,
+            required: kind == ArgKind::Positional,
+            multiple: false,
+            int: false,
+        }
+    }
+
+    /// Result key: `dry-run` becomes `dry_run` so scripts can use field access
+    pub fn key(&self) -> String {
+        self.name.replace('-', "_")
+    }
+
+    fn metavar(&self) -> String {
+        self.key().to_uppercase()
+    }
+
+    /// `-o, --output OUTPUT` / `input` as shown in help
+    fn label(&self) -> String {
+        match self.kind {
+            ArgKind::Positional => self.name.clone(),
+            _ => {
+                let long = match self.kind {
+                    ArgKind::Option => format!("--{} {}", self.name, self.metavar()),
+                    _ => format!("--{}", self.name),
+                };
+                match self.short {
+                    Some(c) => format!("-{}, {}", c, long),
+                    None => long,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Bool(bool),
+    Str(String),
+    Int(i64),
+    List(Vec<ArgValue>),
+    Missing,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    Values(Vec<(String, ArgValue)>),
+    /// `-h` / `--help` was given; the caller prints this and exits
+    Help(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ArgParser {
+    pub prog: String,
+    pub description: String,
+    pub args: Vec<ArgSpec>,
+}
+
+impl ArgParser {
+    pub fn usage(&self) -> String {
+        let mut parts = vec![format!("usage: {} [-h]", self.prog)];
+        for arg in &self.args {
+            let shown = match (arg.kind, arg.short) {
+                (ArgKind::Flag, Some(c)) => format!("-{}", c),
+                (ArgKind::Flag, None) => format!("--{}", arg.name),
+                (ArgKind::Option, Some(c)) => format!("-{} {}", c, arg.metavar()),
+                (ArgKind::Option, None) => format!("--{} {}", arg.name, arg.metavar()),
+                (ArgKind::Positional, _) if arg.multiple => format!("{}...", arg.name),
+                (ArgKind::Positional, _) => arg.name.clone(),
+            };
+            parts.push(if arg.required { shown } else { format!("[{}]", shown) });
+        }
+        parts.join(" ")
+    }
+
+    pub fn help(&self) -> String {
+        let mut out = self.usage();
+        out.push('\n');
+        if !self.description.is_empty() {
+            out.push('\n');
+            out.push_str(&self.description);
+            out.push('\n');
+        }
+        let mut help_arg = ArgSpec::new("help", ArgKind::Flag);
+        help_arg.short = Some('h');
+        help_arg.help = "show this help message and exit".to_string();
+        let positionals: Vec<&ArgSpec> = self.args.iter().filter(|a| a.kind == ArgKind::Positional).collect();
+        let options: Vec<&ArgSpec> = std::iter::once(&help_arg)
+            .chain(self.args.iter().filter(|a| a.kind != ArgKind::Positional))
+            .collect();
+        let width = positionals.iter().chain(&options).map(|a| a.label().len()).max().unwrap_or(0);
+        for (title, group) in [("positional arguments", &positionals), ("options", &options)] {
+            if group.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\n{}:\n", title));
+            for arg in group.iter() {
+                let mut help = arg.help.clone();
+                if let Some(default) = &arg.default {
+                    help.push_str(&format!(" (default: {})", default));
+                }
+                out.push_str(format!("  {:width$}  {}", arg.label(), help.trim(), width = width).trim_end());
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    fn find_long(&self, name: &str) -> Result<&ArgSpec, String> {
+        self.args.iter()
+            .find(|a| a.kind != ArgKind::Positional && a.name == name)
+            .ok_or_else(|| format!("unrecognized argument: --{}", name))
+    }
+
+    fn find_short(&self, c: char) -> Result<&ArgSpec, String> {
+        self.args.iter()
+            .find(|a| a.kind != ArgKind::Positional && a.short == Some(c))
+            .ok_or_else(|| format!("unrecognized argument: -{}", c))
+    }
+
+    /// Parse `argv` (without the program name). Errors are messages for the user.
+    pub fn parse(&self, argv: &[String]) -> Result<Parsed, String> {
+        let mut given: Vec<(usize, String)> = Vec::new(); // (spec index, raw value)
+        let mut flags: Vec<usize> = Vec::new();
+        let mut positionals: Vec<String> = Vec::new();
+        let index = |spec: &ArgSpec| self.args.iter().position(|a| std::ptr::eq(a, spec)).unwrap();
+
+        let mut i = 0;
+        while i < argv.len() {
+            let arg = &argv[i];
+            i += 1;
+            if arg == "--" {
+                positionals.extend(argv[i..].iter().cloned());
+                break;
+            }
+            if arg == "-h" || arg == "--help" {
+                return Ok(Parsed::Help(self.help()));
+            }
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline) = match long.split_once('=') {
+                    Some((n, v)) => (n, Some(v.to_string())),
+                    None => (long, None),
+                };
+                let spec = self.find_long(name)?;
+                match spec.kind {
+                    ArgKind::Flag if inline.is_some() => return Err(format!("argument --{}: takes no value", name)),
+                    ArgKind::Flag => flags.push(index(spec)),
+                    _ => {
+                        let value = match inline {
+                            Some(v) => v,
+                            None => argv.get(i).cloned().inspect(|_| i += 1)
+                                .ok_or_else(|| format!("argument --{}: expected a value", name))?,
+                        };
+                        given.push((index(spec), value));
+                    }
+                }
+            } else if arg.len() > 1 && arg.starts_with('-') && arg.parse::<i64>().is_err() {
+                // Short cluster: `-vq` is two flags, `-ofile` is an option with its value
+                let chars: Vec<char> = arg[1..].chars().collect();
+                for (j, &c) in chars.iter().enumerate() {
+                    let spec = self.find_short(c)?;
+                    if spec.kind == ArgKind::Flag {
+                        flags.push(index(spec));
+                        continue;
+                    }
+                    let rest: String = chars[j + 1..].iter().collect();
+                    let value = if !rest.is_empty() {
+                        rest
+                    } else {
+                        argv.get(i).cloned().inspect(|_| i += 1)
+                            .ok_or_else(|| format!("argument -{}: expected a value", c))?
+                    };
+                    given.push((index(spec), value));
+                    break;
+                }
+            } else {
+                positionals.push(arg.clone());
+            }
+        }
+
+        let mut values = Vec::with_capacity(self.args.len());
+        let mut positionals = positionals.into_iter();
+        for (idx, spec) in self.args.iter().enumerate() {
+            let raw: Vec<String> = match spec.kind {
+                ArgKind::Flag => {
+                    values.push((spec.key(), ArgValue::Bool(flags.contains(&idx))));
+                    continue;
+                }
+                ArgKind::Option => given.iter().filter(|(g, _)| *g == idx).map(|(_, v)| v.clone()).collect(),
+                ArgKind::Positional if spec.multiple => positionals.by_ref().collect(),
+                ArgKind::Positional => positionals.next().into_iter().collect(),
+            };
+            let label = match spec.kind {
+                ArgKind::Positional => spec.name.clone(),
+                _ => format!("--{}", spec.name),
+            };
+            let convert = |v: &str| -> Result<ArgValue, String> {
+                if spec.int {
+                    v.trim().parse().map(ArgValue::Int)
+                        .map_err(|_| format!("argument {}: invalid int value: '{}'", label, v))
+                } else {
+                    Ok(ArgValue::Str(v.to_string()))
+                }
+            };
+            let value = if raw.is_empty() {
+                match &spec.default {
+                    Some(d) if spec.multiple => ArgValue::List(vec![convert(d)?]),
+                    Some(d) => convert(d)?,
+                    None if spec.required => return Err(format!("the following argument is required: {}", label)),
+                    None if spec.multiple => ArgValue::List(Vec::new()),
+                    None => ArgValue::Missing,
+                }
+            } else if spec.multiple {
+                ArgValue::List(raw.iter().map(|v| convert(v)).collect::<Result<_, _>>()?)
+            } else {
+                // A repeated single-valued option keeps the last occurrence
+                convert(raw.last().unwrap())?
+            };
+            values.push((spec.key(), value));
+        }
+        let extra: Vec<String> = positionals.collect();
+        if !extra.is_empty() {
+            return Err(format!("unrecognized arguments: {}", extra.join(" ")));
+        }
+        Ok(Parsed::Values(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> ArgParser {
+        let mut verbose = ArgSpec::new("verbose", ArgKind::Flag);
+        verbose.short = Some('v');
+        let mut dry_run = ArgSpec::new("--dry-run", ArgKind::Flag);
+        dry_run.help = "print actions only".to_string();
+        let mut output = ArgSpec::new("output", ArgKind::Option);
+        output.short = Some('o');
+        output.default = Some("out.txt".to_string());
+        let mut jobs = ArgSpec::new("jobs", ArgKind::Option);
+        jobs.short = Some('j');
+        jobs.int = true;
+        let input = ArgSpec::new("input", ArgKind::Positional);
+        let mut rest = ArgSpec::new("files", ArgKind::Positional);
+        rest.multiple = true;
+        rest.required = false;
+        ArgParser {
+            prog: "tool".to_string(),
+            description: "Does things.".to_string(),
+            args: vec![verbose, dry_run, output, jobs, input, rest],
+        }
+    }
+
+    fn parse(argv: &[&str]) -> Result<Vec<(String, ArgValue)>, String> {
+        let argv: Vec<String> = argv.iter().map(|s| s.to_string()).collect();
+        match parser().parse(&argv)? {
+            Parsed::Values(v) => Ok(v),
+            Parsed::Help(h) => Err(h),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let values = parse(&["-vj4", "in.txt", "--dry-run", "a", "--", "-b"]).unwrap();
+        assert_eq!(values, vec![
+            ("verbose".to_string(), ArgValue::Bool(true)),
+            ("dry_run".to_string(), ArgValue::Bool(true)),
+            ("output".to_string(), ArgValue::Str("out.txt".to_string())),
+            ("jobs".to_string(), ArgValue::Int(4)),
+            ("input".to_string(), ArgValue::Str("in.txt".to_string())),
+            ("files".to_string(), ArgValue::List(vec![ArgValue::Str("a".to_string()), ArgValue::Str("-b".to_string())])),
+        ]);
+        let values = parse(&["--output=x", "-o", "y", "-5"]).unwrap();
+        assert_eq!(values[2].1, ArgValue::Str("y".to_string()));
+        assert_eq!(values[3].1, ArgValue::Missing);
+        assert_eq!(values[4].1, ArgValue::Str("-5".to_string()));
+    }
+
+    #[test]
+    fn test_errors_and_help() {
+        assert_eq!(parse(&[]).unwrap_err(), "the following argument is required: input");
+        assert_eq!(parse(&["x", "--nope"]).unwrap_err(), "unrecognized argument: --nope");
+        assert_eq!(parse(&["x", "-j", "many"]).unwrap_err(), "argument --jobs: invalid int value: 'many'");
+        assert_eq!(parse(&["x", "-o"]).unwrap_err(), "argument -o: expected a value");
+        let help = parse(&["x", "--help"]).unwrap_err();
+        assert!(help.starts_with("usage: tool [-h] [-v] [--dry-run] [-o OUTPUT] [-j JOBS] input [files...]\n\nDoes things.\n"));
+        assert!(help.contains("\n  -o, --output OUTPUT  (default: out.txt)\n"));
+        assert!(help.contains("\n  --dry-run            print actions only\n"));
+    }
+}
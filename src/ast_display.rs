@@ -0,0 +1,343 @@
+// ============================================
+// Cryo AST Display
+// A readable, stable rendering of the syntax tree for `cryo parse` and the
+// parser snapshots in tests/parser/. Statements go one per line, indented
+// two spaces per block; expressions are fully parenthesized prefix forms,
+// so precedence changes show up as moved parentheses:
+//
+//   fn main(n: int) -> int
+//     let x = (+ 1 (* 2 n))
+//     if (< x 10)
+//       return (call fib x)
+//
+// Line numbers are left out so that adding a line doesn't touch every
+// snapshot below it. For machine-readable output use `to_json`.
+// ============================================
+
+use crate::parser::{Expr, Function, Param, Pattern, Stmt, TopLevel};
+use std::fmt::{self, Display, Formatter, Write};
+
+/// Every item, in source order
+pub fn program(items: &[TopLevel]) -> String {
+    let mut out = String::new();
+    for item in items {
+        let _ = write!(out, "{}", item);
+    }
+    out
+}
+
+/// The same tree as JSON, in the shape `parse_ast()` returns to scripts
+pub fn to_json(items: &[TopLevel]) -> String {
+    crate::ast_value::items_to_value(items).to_json()
+}
+
+fn indent(f: &mut Formatter<'_>, depth: usize) -> fmt::Result {
+    write!(f, "{:width$}", "", width = depth * 2)
+}
+
+fn comma_list<T: Display>(items: &[T]) -> String {
+    items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn args(exprs: &[Expr]) -> String {
+    exprs.iter().map(|e| format!(" {}", e)).collect()
+}
+
+fn fields(fields: &[(crate::intern::Symbol, Expr)]) -> String {
+    fields.iter().map(|(name, value)| format!(" {}: {}", name, value)).collect()
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            // Debug keeps the `.0` on whole floats
+            Expr::Float(x) => write!(f, "{:?}", x),
+            Expr::String(s) => write!(f, "{:?}", s),
+            Expr::Bool(b) => write!(f, "{}", b),
+            Expr::Null => f.write_str("null"),
+            Expr::Identifier(name) => write!(f, "{}", name),
+            Expr::BinOp(left, op, right) => write!(f, "({} {} {})", op, left, right),
+            Expr::UnaryOp(op, operand) => write!(f, "({} {})", op, operand),
+            Expr::Call(name, call_args) => write!(f, "(call {}{})", name, args(call_args)),
+            Expr::MethodCall(object, method, call_args) => write!(f, "(method {} {}{})", object, method, args(call_args)),
+            Expr::StaticMethodCall(type_name, method, call_args) => write!(f, "(static {}::{}{})", type_name, method, args(call_args)),
+            Expr::Index(object, index) => write!(f, "(index {} {})", object, index),
+            Expr::Field(object, name) => write!(f, "(field {} {})", object, name),
+            Expr::Array(items) => write!(f, "[{}]", items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(" ")),
+            Expr::StructInit(name, values) => write!(f, "(new {}{})", name, fields(values)),
+            Expr::ObjectLiteral(values) => write!(f, "(object{})", fields(values)),
+            Expr::MapLiteral(entries) => write!(f, "(map{})", entries.iter().map(|(key, value)| format!(" {:?}: {}", key, value)).collect::<String>()),
+            Expr::Await(inner) => write!(f, "(await {})", inner),
+            Expr::Propagate(inner) => write!(f, "(? {})", inner),
+            Expr::At(_, inner) => write!(f, "{}", inner),
+            Expr::Variant(name, variant) => write!(f, "{}::{}", name, variant),
+            Expr::Match(subject, arms) => {
+                write!(f, "(match {}", subject)?;
+                for arm in arms {
+                    write!(f, " (arm {}", arm.pattern)?;
+                    if let Some(guard) = &arm.guard {
+                        write!(f, " if {}", guard)?;
+                    }
+                    for stmt in &arm.body {
+                        match stmt {
+                            Stmt::Expr(body) => write!(f, " {}", body)?,
+                            other => write!(f, " {}", other.to_string().trim_end())?,
+                        }
+                    }
+                    f.write_str(")")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => f.write_str("_"),
+            Pattern::Literal(value) => write!(f, "{}", value),
+            Pattern::Binding(name) => write!(f, "{}", name),
+            Pattern::Variant(name, variant, fields) if fields.is_empty() => write!(f, "{}::{}", name, variant),
+            Pattern::Variant(name, variant, fields) => {
+                let fields: Vec<String> = fields.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}::{}({})", name, variant, fields.join(", "))
+            }
+        }
+    }
+}
+
+fn write_block(f: &mut Formatter<'_>, stmts: &[Stmt], depth: usize) -> fmt::Result {
+    for stmt in stmts {
+        write_stmt(f, stmt, depth)?;
+    }
+    Ok(())
+}
+
+fn write_stmt(f: &mut Formatter<'_>, stmt: &Stmt, depth: usize) -> fmt::Result {
+    // Positions are left out, like line numbers
+    let stmt = stmt.inner();
+    indent(f, depth)?;
+    match stmt {
+        Stmt::Let(name, Some(typ), value) => writeln!(f, "let {}: {} = {}", name, typ, value),
+        Stmt::Let(name, None, value) => writeln!(f, "let {} = {}", name, value),
+        Stmt::Assign(name, value) => writeln!(f, "{} = {}", name, value),
+        Stmt::IndexAssign(object, index, value) => writeln!(f, "(index {} {}) = {}", object, index, value),
+        Stmt::FieldAssign(object, name, value) => writeln!(f, "(field {} {}) = {}", object, name, value),
+        Stmt::Return(Some(value)) => writeln!(f, "return {}", value),
+        Stmt::Return(None) => writeln!(f, "return"),
+        Stmt::Print(value) => writeln!(f, "print {}", value),
+        Stmt::If(cond, then_body, else_body) => {
+            writeln!(f, "if {}", cond)?;
+            write_block(f, then_body, depth + 1)?;
+            if let Some(else_body) = else_body {
+                indent(f, depth)?;
+                writeln!(f, "else")?;
+                write_block(f, else_body, depth + 1)?;
+            }
+            Ok(())
+        }
+        Stmt::While(cond, body) => {
+            writeln!(f, "while {}", cond)?;
+            write_block(f, body, depth + 1)
+        }
+        Stmt::Break => writeln!(f, "break"),
+        Stmt::Continue => writeln!(f, "continue"),
+        Stmt::Expr(expr) => writeln!(f, "{}", expr),
+        Stmt::Block(body) => {
+            writeln!(f, "block")?;
+            write_block(f, body, depth + 1)
+        }
+        Stmt::Defer(inner) => {
+            writeln!(f, "defer")?;
+            write_stmt(f, inner, depth + 1)
+        }
+        Stmt::Match(subject, arms) => {
+            writeln!(f, "match {}", subject)?;
+            for arm in arms {
+                indent(f, depth + 1)?;
+                match &arm.guard {
+                    Some(guard) => writeln!(f, "arm {} if {}", arm.pattern, guard)?,
+                    None => writeln!(f, "arm {}", arm.pattern)?,
+                }
+                write_block(f, &arm.body, depth + 2)?;
+            }
+            Ok(())
+        }
+        Stmt::Try(body, name, handler) => {
+            writeln!(f, "try")?;
+            write_block(f, body, depth + 1)?;
+            indent(f, depth)?;
+            writeln!(f, "catch {}", name)?;
+            write_block(f, handler, depth + 1)
+        }
+        Stmt::Throw(value) => writeln!(f, "throw {}", value),
+        Stmt::With(name, value, body) => {
+            writeln!(f, "with {} = {}", name, value)?;
+            write_block(f, body, depth + 1)
+        }
+        Stmt::At(..) => unreachable!("inner() strips positions"),
+    }
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_stmt(f, self, 0)
+    }
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.typ {
+            Some(typ) => write!(f, "{}: {}", self.name, typ),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Header line, then the body one level deeper; functions without a body
+/// (trait methods, externs) end in `;`
+fn write_function(f: &mut Formatter<'_>, func: &Function, depth: usize) -> fmt::Result {
+    for decorator in &func.decorators {
+        indent(f, depth)?;
+        writeln!(f, "@{}({:?})", decorator.name, decorator.arg)?;
+    }
+    indent(f, depth)?;
+    if func.is_async {
+        f.write_str("async ")?;
+    }
+    write!(f, "fn {}({})", func.name, comma_list(&func.params))?;
+    if let Some(ret) = &func.return_type {
+        write!(f, " -> {}", ret)?;
+    }
+    match &func.body {
+        Some(body) => {
+            writeln!(f)?;
+            write_block(f, body, depth + 1)
+        }
+        None => writeln!(f, ";"),
+    }
+}
+
+impl Display for TopLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TopLevel::Function(func) => write_function(f, func, 0),
+            TopLevel::Struct(def) => {
+                for decorator in &def.decorators {
+                    writeln!(f, "@{}({:?})", decorator.name, decorator.arg)?;
+                }
+                writeln!(f, "struct {}", def.name)?;
+                for (name, typ) in &def.fields {
+                    writeln!(f, "  {}: {}", name, typ)?;
+                }
+                Ok(())
+            }
+            TopLevel::Enum(def) => {
+                let variants: Vec<String> = def.variants.iter().map(|(name, fields)| match fields.is_empty() {
+                    true => name.clone(),
+                    false => format!("{}({})", name, fields.join(", ")),
+                }).collect();
+                writeln!(f, "enum {} {{ {} }}", def.name, variants.join(", "))
+            }
+            TopLevel::Let(name, value) => writeln!(f, "let {} = {}", name, value),
+            TopLevel::Import(path, names) => writeln!(f, "import {{ {} }} from {:?}", names.join(", "), path),
+            TopLevel::Trait(def) => {
+                writeln!(f, "trait {}", def.name)?;
+                def.methods.iter().try_for_each(|m| write_function(f, m, 1))
+            }
+            TopLevel::Impl(def) => {
+                match def.trait_name.as_str() {
+                    "" => writeln!(f, "impl {}", def.type_name)?,
+                    trait_name => writeln!(f, "impl {} for {}", trait_name, def.type_name)?,
+                }
+                def.methods.iter().try_for_each(|m| write_function(f, m, 1))
+            }
+            TopLevel::Extern(block) => {
+                writeln!(f, "extern {:?}", block.abi)?;
+                block.functions.iter().try_for_each(|func| write_function(f, func, 1))
+            }
+            TopLevel::Macro(def) => {
+                writeln!(f, "macro {}({})", def.name, comma_list(&def.params))?;
+                write_block(f, &def.body, 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn render(source: &str) -> String {
+        let tokens = crate::lexer::tokenize(source);
+        match crate::parser::Parser::new(&tokens).parse() {
+            Ok(items) => program(&items),
+            Err(e) => format!("parse error: {}\n", e),
+        }
+    }
+
+    #[test]
+    fn test_precedence_is_explicit() {
+        assert_eq!(render("let x = 1 + 2 * 3 - -4;"), "let x = (- (+ 1 (* 2 3)) (- 4))\n");
+        assert_eq!(render("fn f() { if (a && !b) { return; } }"), "fn f()\n  if (&& a (! b))\n    return\n");
+    }
+
+    #[test]
+    fn test_positions_are_left_out() {
+        let source = "fn main() {\n    let x = 1;\n\tif (x) { print(x); }\n    let y = f(x)[0] + 2;\n}\n";
+        let (tokens, lines, columns, spans) = crate::lexer::tokenize_with_positions(source);
+        let items = crate::parser::Parser::with_lines(&tokens, &lines).with_columns(&columns).with_spans(&spans).parse().unwrap();
+        assert_eq!(program(&items), render(source));
+        let TopLevel::Function(main) = &items[0] else { panic!("expected main") };
+        let body = main.body.as_deref().unwrap();
+        assert!(matches!(body[0], Stmt::At(2, 5, _)));
+        let Stmt::At(3, 2, stmt) = &body[1] else { panic!("expected the if at 3:2") };
+        let Stmt::If(_, then, _) = &**stmt else { panic!("expected an if") };
+        assert!(matches!(then[0], Stmt::At(3, 11, _)));
+        // Operations carry the source they span; literals and names don't
+        let Stmt::Let(_, _, Expr::At(sum, add)) = body[2].inner() else { panic!("expected a spanned let") };
+        let Expr::BinOp(left, _, right) = &**add else { panic!("expected the +") };
+        let Expr::At(index, indexed) = &**left else { panic!("expected a spanned index") };
+        let Expr::Index(call, _) = &**indexed else { panic!("expected f(x)[0]") };
+        let Expr::At(call, _) = &**call else { panic!("expected a spanned call") };
+        let text = |span: &crate::lexer::Span| &source[span.start..span.end];
+        assert_eq!((text(sum), text(index), text(call)), ("f(x)[0] + 2", "f(x)[0]", "f(x)"));
+        assert!(matches!(**right, Expr::Number(2)));
+    }
+
+    /// Each tests/parser/NAME.cryo is parsed and compared with NAME.ast.
+    /// After an intended parser change, rerun with CRYO_BLESS=1 to rewrite
+    /// the snapshots, then review them with `git diff`.
+    #[test]
+    fn test_parser_snapshots() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/parser");
+        let bless = std::env::var_os("CRYO_BLESS").or_else(|| std::env::var_os("ARGON_BLESS")).is_some();
+        let mut sources: Vec<_> = std::fs::read_dir(&dir)
+            .expect("tests/parser")
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|x| x == "cryo"))
+            .collect();
+        sources.sort();
+        assert!(!sources.is_empty(), "no snapshots in {}", dir.display());
+
+        let mut failures = Vec::new();
+        for source in &sources {
+            let actual = render(&std::fs::read_to_string(source).unwrap());
+            let snapshot = source.with_extension("ast");
+            let expected = std::fs::read_to_string(&snapshot).unwrap_or_default();
+            if actual == expected {
+                continue;
+            }
+            if bless {
+                std::fs::write(&snapshot, &actual).unwrap();
+                continue;
+            }
+            failures.push(format!("{}:", snapshot.display()));
+            failures.extend(crate::conformance::diff_lines(&expected, &actual));
+        }
+        assert!(failures.is_empty(), "AST snapshots differ (-snapshot +parsed); rerun with CRYO_BLESS=1 to accept\n{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,546 @@
+// ============================================
+// Cryo AST Values
+// The syntax tree as plain script data, for parse_ast() / run_ast() and the
+// self-hosting builtins (cryo_lex, cryo_parse, make_*, emit_llvm)
+// ============================================
+
+use crate::interpreter::Value;
+use crate::intern::Symbol;
+use crate::lexer::{StrPart, Token};
+use crate::parser::{Decorator, EnumDef, ExternBlock, Expr, Function, ImplDef, MacroDef, MatchArm, Param, Pattern, Stmt, StructDef, TopLevel, TraitDef};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Every node is an object with a `kind` naming its variant, e.g.
+//   { kind: "BinOp", op: "+", left: { kind: "Number", value: 1 }, right: ... }
+// Lists of nodes are arrays and optional parts are null. The field names are
+// documented in docs/stdlib_reference.md.
+
+fn node(kind: &str, fields: Vec<(&str, Value)>) -> Value {
+    let mut all = vec![("kind".to_string(), string(kind))];
+    all.extend(fields.into_iter().map(|(k, v)| (k.to_string(), v)));
+    Value::object(all)
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.into())
+}
+
+fn sym(s: Symbol) -> Value {
+    string(s.as_str())
+}
+
+fn opt_string(s: &Option<String>) -> Value {
+    s.as_deref().map(string).unwrap_or(Value::Null)
+}
+
+fn list<T>(items: &[T], f: impl Fn(&T) -> Value) -> Value {
+    Value::Array(Rc::new(RefCell::new(items.iter().map(f).collect())))
+}
+
+pub fn items_to_value(items: &[TopLevel]) -> Value {
+    list(items, item_to_value)
+}
+
+fn decorators_to_value(decorators: &[Decorator]) -> Value {
+    list(decorators, |d| Value::object([("name".to_string(), string(&d.name)), ("arg".to_string(), string(&d.arg))]))
+}
+
+fn function_to_value(f: &Function) -> Value {
+    node("Function", vec![
+        ("name", sym(f.name)),
+        ("params", list(&f.params, |p| Value::object([("name".to_string(), sym(p.name)), ("type".to_string(), opt_string(&p.typ))]))),
+        ("body", f.body.as_deref().map(stmts_to_value).unwrap_or(Value::Null)),
+        ("async", Value::Bool(f.is_async)),
+        ("return_type", opt_string(&f.return_type)),
+        ("decorators", decorators_to_value(&f.decorators)),
+        ("line", Value::Int(f.line as i64)),
+    ])
+}
+
+fn item_to_value(item: &TopLevel) -> Value {
+    match item {
+        TopLevel::Function(f) => function_to_value(f),
+        TopLevel::Struct(s) => node("Struct", vec![
+            ("name", sym(s.name)),
+            ("fields", list(&s.fields, |(n, t)| Value::object([("name".to_string(), string(n)), ("type".to_string(), string(t))]))),
+            ("decorators", decorators_to_value(&s.decorators)),
+        ]),
+        // A unit variant is its name; one with a payload is { name, fields }
+        TopLevel::Enum(e) => node("Enum", vec![("name", string(&e.name)), ("variants", list(&e.variants, |(name, fields)| match fields.is_empty() {
+            true => string(name),
+            false => Value::object([("name".to_string(), string(name)), ("fields".to_string(), list(fields, |f| string(f)))]),
+        }))]),
+        TopLevel::Let(name, value) => node("Let", vec![("name", sym(*name)), ("value", expr_to_value(value))]),
+        TopLevel::Import(path, names) => node("Import", vec![("path", string(path)), ("names", list(names, |n| string(n)))]),
+        TopLevel::Trait(t) => node("Trait", vec![("name", string(&t.name)), ("methods", list(&t.methods, function_to_value))]),
+        TopLevel::Impl(i) => node("Impl", vec![
+            ("trait", string(&i.trait_name)),
+            ("type", sym(i.type_name)),
+            ("methods", list(&i.methods, function_to_value)),
+        ]),
+        TopLevel::Extern(e) => node("Extern", vec![("abi", string(&e.abi)), ("functions", list(&e.functions, function_to_value))]),
+        TopLevel::Macro(m) => node("Macro", vec![
+            ("name", sym(m.name)),
+            ("params", list(&m.params, |p| sym(*p))),
+            ("body", stmts_to_value(&m.body)),
+        ]),
+    }
+}
+
+pub fn stmts_to_value(stmts: &[Stmt]) -> Value {
+    list(stmts, stmt_to_value)
+}
+
+fn stmt_to_value(stmt: &Stmt) -> Value {
+    match stmt {
+        Stmt::Let(name, typ, value) => node("Let", vec![("name", sym(*name)), ("type", opt_string(typ)), ("value", expr_to_value(value))]),
+        Stmt::Assign(name, value) => node("Assign", vec![("name", sym(*name)), ("value", expr_to_value(value))]),
+        Stmt::IndexAssign(object, index, value) => node("IndexAssign", vec![
+            ("object", expr_to_value(object)),
+            ("index", expr_to_value(index)),
+            ("value", expr_to_value(value)),
+        ]),
+        Stmt::FieldAssign(object, field, value) => node("FieldAssign", vec![
+            ("object", expr_to_value(object)),
+            ("field", sym(*field)),
+            ("value", expr_to_value(value)),
+        ]),
+        Stmt::Return(value) => node("Return", vec![("value", value.as_ref().map(expr_to_value).unwrap_or(Value::Null))]),
+        Stmt::Print(value) => node("Print", vec![("value", expr_to_value(value))]),
+        Stmt::If(cond, then, otherwise) => node("If", vec![
+            ("cond", expr_to_value(cond)),
+            ("then", stmts_to_value(then)),
+            ("else", otherwise.as_deref().map(stmts_to_value).unwrap_or(Value::Null)),
+        ]),
+        Stmt::While(cond, body) => node("While", vec![("cond", expr_to_value(cond)), ("body", stmts_to_value(body))]),
+        Stmt::Break => node("Break", vec![]),
+        Stmt::Continue => node("Continue", vec![]),
+        Stmt::Expr(e) => node("Expr", vec![("expr", expr_to_value(e))]),
+        Stmt::Block(body) => node("Block", vec![("body", stmts_to_value(body))]),
+        Stmt::Defer(s) => node("Defer", vec![("stmt", stmt_to_value(s))]),
+        Stmt::Match(subject, arms) => match_to_value(subject, arms),
+        Stmt::Try(body, name, handler) => node("Try", vec![
+            ("body", stmts_to_value(body)),
+            ("name", sym(*name)),
+            ("handler", stmts_to_value(handler)),
+        ]),
+        Stmt::Throw(value) => node("Throw", vec![("value", expr_to_value(value))]),
+        Stmt::With(name, value, body) => node("With", vec![
+            ("name", sym(*name)),
+            ("value", expr_to_value(value)),
+            ("body", stmts_to_value(body)),
+        ]),
+        // Positions aren't part of the tree scripts see
+        Stmt::At(_, _, s) => stmt_to_value(s),
+    }
+}
+
+fn pattern_to_value(pattern: &Pattern) -> Value {
+    match pattern {
+        Pattern::Wildcard => node("Wildcard", vec![]),
+        Pattern::Literal(value) => node("Literal", vec![("value", expr_to_value(value))]),
+        Pattern::Binding(name) => node("Binding", vec![("name", sym(*name))]),
+        Pattern::Variant(name, variant, fields) => {
+            node("Variant", vec![("name", sym(*name)), ("variant", sym(*variant)), ("fields", list(fields, pattern_to_value))])
+        }
+    }
+}
+
+/// Statement and expression matches are both `{ kind: "Match", subject, arms }`,
+/// each arm `{ pattern, guard, body }`
+fn match_to_value(subject: &Expr, arms: &[MatchArm]) -> Value {
+    let arm = |arm: &MatchArm| {
+        Value::object([
+            ("pattern".to_string(), pattern_to_value(&arm.pattern)),
+            ("guard".to_string(), arm.guard.as_ref().map(expr_to_value).unwrap_or(Value::Null)),
+            ("body".to_string(), stmts_to_value(&arm.body)),
+        ])
+    };
+    node("Match", vec![("subject", expr_to_value(subject)), ("arms", list(arms, arm))])
+}
+
+fn fields_to_value(fields: &[(Symbol, Expr)]) -> Value {
+    list(fields, |(n, e)| Value::object([("name".to_string(), sym(*n)), ("value".to_string(), expr_to_value(e))]))
+}
+
+fn expr_to_value(expr: &Expr) -> Value {
+    match expr {
+        Expr::Number(n) => node("Number", vec![("value", Value::Int(*n))]),
+        Expr::Float(f) => node("Float", vec![("value", Value::Float(*f))]),
+        Expr::String(s) => node("String", vec![("value", string(s))]),
+        Expr::Bool(b) => node("Bool", vec![("value", Value::Bool(*b))]),
+        Expr::Null => node("Null", vec![]),
+        Expr::Identifier(name) => node("Identifier", vec![("name", sym(*name))]),
+        Expr::BinOp(l, op, r) => node("BinOp", vec![("op", string(op)), ("left", expr_to_value(l)), ("right", expr_to_value(r))]),
+        Expr::UnaryOp(op, e) => node("UnaryOp", vec![("op", string(op)), ("operand", expr_to_value(e))]),
+        Expr::Call(name, args) => node("Call", vec![("name", sym(*name)), ("args", list(args, expr_to_value))]),
+        Expr::MethodCall(obj, method, args) => node("MethodCall", vec![
+            ("object", expr_to_value(obj)),
+            ("method", sym(*method)),
+            ("args", list(args, expr_to_value)),
+        ]),
+        Expr::Index(obj, index) => node("Index", vec![("object", expr_to_value(obj)), ("index", expr_to_value(index))]),
+        Expr::Field(obj, field) => node("Field", vec![("object", expr_to_value(obj)), ("field", sym(*field))]),
+        Expr::Array(items) => node("Array", vec![("items", list(items, expr_to_value))]),
+        Expr::StructInit(name, fields) => node("StructInit", vec![("name", sym(*name)), ("fields", fields_to_value(fields))]),
+        Expr::ObjectLiteral(fields) => node("ObjectLiteral", vec![("fields", fields_to_value(fields))]),
+        Expr::MapLiteral(entries) => node("MapLiteral", vec![(
+            "entries",
+            list(entries, |(k, e)| Value::object([("key".to_string(), string(k)), ("value".to_string(), expr_to_value(e))])),
+        )]),
+        Expr::Await(e) => node("Await", vec![("expr", expr_to_value(e))]),
+        Expr::StaticMethodCall(typ, method, args) => node("StaticMethodCall", vec![
+            ("type", sym(*typ)),
+            ("method", sym(*method)),
+            ("args", list(args, expr_to_value)),
+        ]),
+        Expr::Variant(name, variant) => node("Variant", vec![("name", sym(*name)), ("variant", sym(*variant))]),
+        Expr::Match(subject, arms) => match_to_value(subject, arms),
+        Expr::Propagate(e) => node("Propagate", vec![("expr", expr_to_value(e))]),
+        Expr::At(_, e) => expr_to_value(e),
+    }
+}
+
+/// Tokens as `{ kind, value, line }`; `kind` is the token name (`Identifier`,
+/// `LParen`, `DecGet`, ...) and `value` the literal, name or decorator argument
+pub fn tokens_to_value(tokens: &[Token], lines: &[usize]) -> Value {
+    let items = tokens.iter().zip(lines).map(|(token, line)| {
+        let (kind, value) = match token {
+            Token::Number(n) => ("Number".to_string(), Value::Int(*n)),
+            Token::Float(f) => ("Float".to_string(), Value::Float(*f)),
+            Token::String(s) => ("String".to_string(), string(s)),
+            // Text parts as strings, each `${...}` as its own token list
+            Token::Interpolated(parts) => {
+                let parts = parts.iter().map(|part| match part {
+                    StrPart::Text(text) => string(text),
+                    StrPart::Code(code) => tokens_to_value(code, &vec![*line; code.len()]),
+                });
+                ("Interpolated".to_string(), Value::Array(Rc::new(RefCell::new(parts.collect()))))
+            }
+            Token::Identifier(name) => ("Identifier".to_string(), sym(*name)),
+            Token::DecController(arg) | Token::DecGet(arg) | Token::DecPost(arg) | Token::DecPut(arg)
+            | Token::DecDelete(arg) | Token::DecPatch(arg) | Token::DecParam(arg) | Token::DecQuery(arg)
+            | Token::DecGuard(arg) | Token::DecMiddleware(arg) => {
+                let debug = format!("{:?}", token);
+                (debug.split('(').next().unwrap_or_default().to_string(), string(arg))
+            }
+            // Every other token is a unit variant, whose Debug form is its name
+            other => (format!("{:?}", other), Value::Null),
+        };
+        Value::object([("kind".to_string(), string(&kind)), ("value".to_string(), value), ("line".to_string(), Value::Int(*line as i64))])
+    });
+    Value::Array(Rc::new(RefCell::new(items.collect())))
+}
+
+/// Node constructors for scripts that build trees by hand (`make_binop(op, l, r)`
+/// and friends). Arguments are positional in the order of the node's fields.
+pub fn build(ctor: &str, args: &[Value]) -> Result<Value, String> {
+    let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Null);
+    let array = |i: usize| match arg(i) {
+        Value::Null => Value::Array(Rc::new(RefCell::new(Vec::new()))),
+        v => v,
+    };
+    let str_arg = |i: usize| string(&arg(i).to_string_val());
+    // Names are accepted in place of { name, ... } objects for params and fields
+    let named = |i: usize, typ: Value| match array(i) {
+        Value::Array(items) => Value::Array(Rc::new(RefCell::new(items.borrow().iter().map(|item| match item {
+            Value::String(_) => Value::object([("name".to_string(), item.clone()), ("type".to_string(), typ.clone())]),
+            other => other.clone(),
+        }).collect()))),
+        other => other,
+    };
+    Ok(match ctor {
+        "make_token" => Value::object([
+            ("kind".to_string(), str_arg(0)),
+            ("value".to_string(), arg(1)),
+            ("line".to_string(), Value::Int(arg(2).as_int())),
+        ]),
+        "make_ast_num" => node("Number", vec![("value", Value::Int(arg(0).as_int()))]),
+        "make_ast_str" => node("String", vec![("value", str_arg(0))]),
+        "make_ast_id" => node("Identifier", vec![("name", str_arg(0))]),
+        "make_ast_array" => node("Array", vec![("items", array(0))]),
+        "make_binop" => node("BinOp", vec![("op", str_arg(0)), ("left", arg(1)), ("right", arg(2))]),
+        "make_unary" => node("UnaryOp", vec![("op", str_arg(0)), ("operand", arg(1))]),
+        "make_call" => node("Call", vec![("name", str_arg(0)), ("args", array(1))]),
+        "make_index" => node("Index", vec![("object", arg(0)), ("index", arg(1))]),
+        "make_struct_init" => node("StructInit", vec![("name", str_arg(0)), ("fields", array(1))]),
+        "make_let" => node("Let", vec![("name", str_arg(0)), ("type", Value::Null), ("value", arg(1))]),
+        "make_assign" => node("Assign", vec![("name", str_arg(0)), ("value", arg(1))]),
+        "make_return" => node("Return", vec![("value", arg(0))]),
+        "make_print" => node("Print", vec![("value", arg(0))]),
+        "make_if" => node("If", vec![("cond", arg(0)), ("then", array(1)), ("else", arg(2))]),
+        "make_while" => node("While", vec![("cond", arg(0)), ("body", array(1))]),
+        "make_block" => node("Block", vec![("body", array(0))]),
+        "make_func" => node("Function", vec![
+            ("name", str_arg(0)),
+            ("params", named(1, Value::Null)),
+            ("body", array(2)),
+            ("async", Value::Bool(false)),
+            ("return_type", Value::Null),
+            ("decorators", array(usize::MAX)),
+        ]),
+        "make_struct_def" => node("Struct", vec![("name", str_arg(0)), ("fields", named(1, string("any"))), ("decorators", array(usize::MAX))]),
+        "make_enum_def" => node("Enum", vec![("name", str_arg(0)), ("variants", array(1))]),
+        "make_match" => node("Match", vec![("subject", arg(0)), ("arms", array(1))]),
+        other => return Err(format!("{}: the AST has no such node", other)),
+    })
+}
+
+// ---- Back to the AST ----
+// Errors name the node kind and field so hand-built trees are easy to fix.
+
+fn kind(v: &Value) -> Result<String, String> {
+    match v.field("kind") {
+        Some(Value::String(k)) => Ok(k.to_string()),
+        _ => Err(format!("run_ast: expected an AST node object, got {}", v.to_string_val())),
+    }
+}
+
+fn get(v: &Value, name: &str) -> Result<Value, String> {
+    v.field(name).ok_or_else(|| format!("run_ast: {} node is missing `{}`", v.field("kind").map(|k| k.to_string_val()).unwrap_or_default(), name))
+}
+
+fn get_string(v: &Value, name: &str) -> Result<String, String> {
+    match get(v, name)? {
+        Value::String(s) => Ok(s.to_string()),
+        other => Err(format!("run_ast: `{}` must be a string, got {}", name, other.to_string_val())),
+    }
+}
+
+fn get_sym(v: &Value, name: &str) -> Result<Symbol, String> {
+    Ok(Symbol::intern(&get_string(v, name)?))
+}
+
+fn get_opt_string(v: &Value, name: &str) -> Result<Option<String>, String> {
+    match v.field(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(_) => get_string(v, name).map(Some),
+    }
+}
+
+fn get_list<T>(v: &Value, name: &str, f: impl Fn(&Value) -> Result<T, String>) -> Result<Vec<T>, String> {
+    match get(v, name)? {
+        Value::Array(items) => items.borrow().iter().map(f).collect(),
+        other => Err(format!("run_ast: `{}` must be an array, got {}", name, other.to_string_val())),
+    }
+}
+
+fn get_expr(v: &Value, name: &str) -> Result<Expr, String> {
+    value_to_expr(&get(v, name)?)
+}
+
+fn get_box(v: &Value, name: &str) -> Result<Box<Expr>, String> {
+    Ok(Box::new(get_expr(v, name)?))
+}
+
+fn get_stmts(v: &Value, name: &str) -> Result<Vec<Stmt>, String> {
+    get_list(v, name, value_to_stmt)
+}
+
+pub fn value_to_items(v: &Value) -> Result<Vec<TopLevel>, String> {
+    match v {
+        Value::Array(items) => items.borrow().iter().map(value_to_item).collect(),
+        other => Err(format!("run_ast: expected an array of items, got {}", other.to_string_val())),
+    }
+}
+
+fn value_to_decorators(v: &Value) -> Result<Vec<Decorator>, String> {
+    match v.field("decorators") {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(_) => get_list(v, "decorators", |d| Ok(Decorator { name: get_string(d, "name")?, arg: get_string(d, "arg")? })),
+    }
+}
+
+fn value_to_function(v: &Value) -> Result<Function, String> {
+    Ok(Function {
+        name: get_sym(v, "name")?,
+        params: get_list(v, "params", |p| Ok(Param { name: get_sym(p, "name")?, typ: get_opt_string(p, "type")? }))?,
+        body: match v.field("body") {
+            None | Some(Value::Null) => None,
+            Some(_) => Some(get_stmts(v, "body")?),
+        },
+        is_async: v.field("async").map(|a| a.is_truthy()).unwrap_or(false),
+        return_type: get_opt_string(v, "return_type")?,
+        decorators: value_to_decorators(v)?,
+        line: v.field("line").map(|l| l.as_int().max(0) as usize).unwrap_or(0),
+    })
+}
+
+fn value_to_item(v: &Value) -> Result<TopLevel, String> {
+    Ok(match kind(v)?.as_str() {
+        "Function" => TopLevel::Function(value_to_function(v)?),
+        "Struct" => TopLevel::Struct(StructDef {
+            name: get_sym(v, "name")?,
+            fields: get_list(v, "fields", |f| Ok((get_string(f, "name")?, get_string(f, "type")?)))?,
+            decorators: value_to_decorators(v)?,
+        }),
+        "Enum" => TopLevel::Enum(EnumDef {
+            name: get_string(v, "name")?,
+            variants: get_list(v, "variants", |variant| match variant {
+                Value::Struct(..) => Ok((get_string(variant, "name")?, get_list(variant, "fields", |f| Ok(f.to_string_val()))?)),
+                name => Ok((name.to_string_val(), Vec::new())),
+            })?,
+        }),
+        "Let" => TopLevel::Let(get_sym(v, "name")?, get_expr(v, "value")?),
+        "Import" => TopLevel::Import(get_string(v, "path")?, get_list(v, "names", |s| Ok(s.to_string_val()))?),
+        "Trait" => TopLevel::Trait(TraitDef { name: get_string(v, "name")?, methods: get_list(v, "methods", value_to_function)? }),
+        "Impl" => TopLevel::Impl(ImplDef {
+            trait_name: get_opt_string(v, "trait")?.unwrap_or_default(),
+            type_name: get_sym(v, "type")?,
+            methods: get_list(v, "methods", value_to_function)?,
+        }),
+        "Extern" => TopLevel::Extern(ExternBlock { abi: get_string(v, "abi")?, functions: get_list(v, "functions", value_to_function)? }),
+        "Macro" => TopLevel::Macro(MacroDef {
+            name: get_sym(v, "name")?,
+            params: get_list(v, "params", |p| Ok(Symbol::intern(&p.to_string_val())))?,
+            body: get_stmts(v, "body")?,
+        }),
+        other => return Err(format!("run_ast: unknown item kind \"{}\"", other)),
+    })
+}
+
+pub fn value_to_stmt(v: &Value) -> Result<Stmt, String> {
+    Ok(match kind(v)?.as_str() {
+        "Let" => Stmt::Let(get_sym(v, "name")?, get_opt_string(v, "type")?, get_expr(v, "value")?),
+        "Assign" => Stmt::Assign(get_sym(v, "name")?, get_expr(v, "value")?),
+        "IndexAssign" => Stmt::IndexAssign(get_expr(v, "object")?, get_expr(v, "index")?, get_expr(v, "value")?),
+        "FieldAssign" => Stmt::FieldAssign(get_expr(v, "object")?, get_sym(v, "field")?, get_expr(v, "value")?),
+        "Return" => Stmt::Return(match v.field("value") {
+            None | Some(Value::Null) => None,
+            Some(e) => Some(value_to_expr(&e)?),
+        }),
+        "Print" => Stmt::Print(get_expr(v, "value")?),
+        "If" => Stmt::If(get_expr(v, "cond")?, get_stmts(v, "then")?, match v.field("else") {
+            None | Some(Value::Null) => None,
+            Some(_) => Some(get_stmts(v, "else")?),
+        }),
+        "While" => Stmt::While(get_expr(v, "cond")?, get_stmts(v, "body")?),
+        "Break" => Stmt::Break,
+        "Continue" => Stmt::Continue,
+        "Expr" => Stmt::Expr(get_expr(v, "expr")?),
+        "Block" => Stmt::Block(get_stmts(v, "body")?),
+        "Defer" => Stmt::Defer(Box::new(value_to_stmt(&get(v, "stmt")?)?)),
+        "Match" => Stmt::Match(get_expr(v, "subject")?, value_to_arms(v)?),
+        "Try" => Stmt::Try(get_stmts(v, "body")?, get_sym(v, "name")?, get_stmts(v, "handler")?),
+        "Throw" => Stmt::Throw(get_expr(v, "value")?),
+        "With" => Stmt::With(get_sym(v, "name")?, get_expr(v, "value")?, get_stmts(v, "body")?),
+        other => return Err(format!("run_ast: unknown statement kind \"{}\"", other)),
+    })
+}
+
+fn value_to_pattern(pattern: &Value) -> Result<Pattern, String> {
+    Ok(match kind(pattern)?.as_str() {
+        "Wildcard" => Pattern::Wildcard,
+        "Literal" => Pattern::Literal(get_expr(pattern, "value")?),
+        "Binding" => Pattern::Binding(get_sym(pattern, "name")?),
+        "Variant" => {
+            let fields = match pattern.field("fields") {
+                None | Some(Value::Null) => Vec::new(),
+                Some(_) => get_list(pattern, "fields", value_to_pattern)?,
+            };
+            Pattern::Variant(get_sym(pattern, "name")?, get_sym(pattern, "variant")?, fields)
+        }
+        other => return Err(format!("run_ast: unknown pattern kind \"{}\"", other)),
+    })
+}
+
+fn value_to_arms(v: &Value) -> Result<Vec<MatchArm>, String> {
+    get_list(v, "arms", |arm| {
+        let pattern = value_to_pattern(&get(arm, "pattern")?)?;
+        let guard = match arm.field("guard") {
+            None | Some(Value::Null) => None,
+            Some(guard) => Some(value_to_expr(&guard)?),
+        };
+        Ok(MatchArm { pattern, guard, body: get_stmts(arm, "body")? })
+    })
+}
+
+fn value_to_fields(v: &Value) -> Result<Vec<(Symbol, Expr)>, String> {
+    get_list(v, "fields", |f| Ok((get_sym(f, "name")?, get_expr(f, "value")?)))
+}
+
+pub fn value_to_expr(v: &Value) -> Result<Expr, String> {
+    Ok(match kind(v)?.as_str() {
+        "Number" => Expr::Number(get(v, "value")?.as_int()),
+        "Float" => Expr::Float(get(v, "value")?.as_float()),
+        "String" => Expr::String(get_string(v, "value")?),
+        "Bool" => Expr::Bool(get(v, "value")?.is_truthy()),
+        "Null" => Expr::Null,
+        "Identifier" => Expr::Identifier(get_sym(v, "name")?),
+        "BinOp" => Expr::BinOp(get_box(v, "left")?, get_string(v, "op")?, get_box(v, "right")?),
+        "UnaryOp" => Expr::UnaryOp(get_string(v, "op")?, get_box(v, "operand")?),
+        "Call" => Expr::Call(get_sym(v, "name")?, get_list(v, "args", value_to_expr)?),
+        "MethodCall" => Expr::MethodCall(get_box(v, "object")?, get_sym(v, "method")?, get_list(v, "args", value_to_expr)?),
+        "Index" => Expr::Index(get_box(v, "object")?, get_box(v, "index")?),
+        "Field" => Expr::Field(get_box(v, "object")?, get_sym(v, "field")?),
+        "Array" => Expr::Array(get_list(v, "items", value_to_expr)?),
+        "StructInit" => Expr::StructInit(get_sym(v, "name")?, value_to_fields(v)?),
+        "ObjectLiteral" => Expr::ObjectLiteral(value_to_fields(v)?),
+        "MapLiteral" => Expr::MapLiteral(get_list(v, "entries", |e| Ok((get_string(e, "key")?, get_expr(e, "value")?)))?),
+        "Await" => Expr::Await(get_box(v, "expr")?),
+        "StaticMethodCall" => Expr::StaticMethodCall(get_sym(v, "type")?, get_sym(v, "method")?, get_list(v, "args", value_to_expr)?),
+        "Variant" => Expr::Variant(get_sym(v, "name")?, get_sym(v, "variant")?),
+        "Match" => Expr::Match(get_box(v, "subject")?, value_to_arms(v)?),
+        "Propagate" => Expr::Propagate(get_box(v, "expr")?),
+        other => return Err(format!("run_ast: unknown expression kind \"{}\"", other)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let source = r#"
+import { a } from "lib";
+struct P { x: int }
+enum R { Ok(v), Err(code, msg), None }
+impl P { fn get(self) -> int { return self.x; } }
+let G = [1, "s", null, true, { k: -2 }, { "m": 1 }];
+fn main(n: int) {
+    let p = P { x: n };
+    while (n > 0) { n = n - 1; if (n == 2) { break; } else { continue; } }
+    p.x = p.get() * 2;
+    G[0] = Math::abs(n);
+    defer print(G);
+    try { throw R::Err(1, "x"); } catch (e) { print(e); }
+    with (let f = open("x")) { print(f.path); }
+    let v = R::Ok(n)?;
+    match n { 0 => print(0), -1 => { return; } x if x > 1 => print(x), R::Err(1, m) => print(m), R::Ok => print(R::Ok(n)), _ => print(Color::Red), }
+    return;
+}
+"#;
+        let tokens = crate::lexer::tokenize(source);
+        let items = crate::parser::Parser::new(&tokens).parse().unwrap();
+        let value = items_to_value(&items);
+        let back = value_to_items(&value).unwrap();
+        assert_eq!(format!("{:?}", back), format!("{:?}", items));
+        let first = match &value { Value::Array(a) => a.borrow()[0].clone(), _ => unreachable!() };
+        assert_eq!(kind(&first).unwrap(), "Import");
+        assert!(value_to_expr(&Value::object([("kind".to_string(), string("Nope"))])).is_err());
+    }
+
+    #[test]
+    fn test_tokens_and_builders() {
+        let (tokens, lines) = crate::lexer::tokenize_with_lines("let a = 1;\n@Get(\"/x\")\n@bytecode");
+        let value = tokens_to_value(&tokens, &lines);
+        let toks = match &value { Value::Array(a) => a.borrow().clone(), _ => unreachable!() };
+        let summary: Vec<String> = toks.iter()
+            .map(|t| format!("{}:{}@{}", kind(t).unwrap(), t.field("value").unwrap().to_string_val(), t.field("line").unwrap().as_int()))
+            .collect();
+        assert_eq!(summary, [
+            "Let:null@1", "Identifier:a@1", "Eq:null@1", "Number:1@1", "Semi:null@1",
+            "DecGet:/x@2", "At:null@3", "Identifier:bytecode@3", "Eof:null@3",
+        ]);
+
+        let body = build("make_return", &[build("make_binop", &[string("+"), build("make_ast_id", &[string("n")]).unwrap(), build("make_ast_num", &[Value::Int(1)]).unwrap()]).unwrap()]).unwrap();
+        let func = build("make_func", &[string("inc"), Value::Array(Rc::new(RefCell::new(vec![string("n")]))), Value::Array(Rc::new(RefCell::new(vec![body])))]).unwrap();
+        let items = value_to_items(&Value::Array(Rc::new(RefCell::new(vec![func])))).unwrap();
+        let tokens = crate::lexer::tokenize("fn inc(n) { return n + 1; }");
+        let parsed = crate::parser::Parser::new(&tokens).parse().unwrap();
+        assert_eq!(format!("{:?}", items), format!("{:?}", parsed));
+        assert!(build("make_switch", &[]).is_err());
+    }
+}
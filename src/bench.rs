@@ -0,0 +1,167 @@
+// Cryo Benchmarks
+// Synthetic workloads for the --parse-bench and --interp-bench flags, and
+// the statistics the bench() builtin reports
+
+use crate::expander::Expander;
+use crate::interpreter::Interpreter;
+use crate::lexer;
+use crate::optimizer::Optimizer;
+use crate::parser::Parser;
+use std::time::{Duration, Instant};
+
+/// Result of a lex + parse run
+pub struct ParseBenchResult {
+    pub lines: usize,
+    pub bytes: usize,
+    pub tokens: usize,
+    pub items: usize,
+    pub lex_time: Duration,
+    pub parse_time: Duration,
+}
+
+/// Generate a machine-generated-looking program of roughly `lines` lines:
+/// many small functions, struct literals, calls and long expressions.
+pub fn generate_source(lines: usize) -> String {
+    let mut out = String::with_capacity(lines * 40);
+    let mut written = 0;
+    let mut i = 0;
+    while written < lines {
+        out.push_str(&format!("struct Rec{} {{ id: int, name: string, score: int }}\n", i));
+        out.push_str(&format!("fn handler_{}(req, count: int) -> int {{\n", i));
+        out.push_str(&format!("    let rec = Rec{} {{ id: {}, name: \"item_{}\", score: count * {} }};\n", i, i, i, i % 7 + 1));
+        out.push_str("    let total = rec.score + count * 2 - (count / 3) % 5;\n");
+        out.push_str("    if (total > 100 && rec.id != 0) {\n");
+        out.push_str("        print(\"big \" + rec.name);\n");
+        out.push_str("    } else {\n");
+        out.push_str(&format!("        total = handler_{}(req, count - 1);\n", if i > 0 { i - 1 } else { 0 }));
+        out.push_str("    }\n");
+        out.push_str("    return total;\n");
+        out.push_str("}\n");
+        written += 11;
+        i += 1;
+    }
+    out
+}
+
+/// Lex and parse `source`, timing each phase separately
+pub fn run_parse_bench(source: &str) -> Result<ParseBenchResult, String> {
+    let start = Instant::now();
+    let tokens = lexer::tokenize(source);
+    let lex_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut parser = Parser::new(&tokens);
+    let ast = parser.parse()?;
+    let parse_time = start.elapsed();
+
+    Ok(ParseBenchResult {
+        lines: source.lines().count(),
+        bytes: source.len(),
+        tokens: tokens.len(),
+        items: ast.len(),
+        lex_time,
+        parse_time,
+    })
+}
+
+/// Interpreter workloads: call-heavy, string-heavy and struct-heavy.
+/// `{N}` is replaced with the size parameter.
+const INTERP_WORKLOADS: &[(&str, &str)] = &[
+    ("fib", r#"
+fn fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn main() { return fib({N}); }
+"#),
+    ("strings", r#"
+fn main() {
+    let i = 0;
+    let total = 0;
+    while (i < {N} * 1000) {
+        let s = "item_" + toString(i);
+        let parts = split(s + "_x", "_");
+        total = total + len(parts[1]) + len(upper(s));
+        i = i + 1;
+    }
+    return total;
+}
+"#),
+    ("structs", r#"
+struct Point { x: int, y: int }
+impl Point {
+    fn add(self, other) { return Point { x: self.x + other.x, y: self.y + other.y }; }
+}
+fn main() {
+    let acc = Point { x: 0, y: 0 };
+    let step = Point { x: 1, y: 2 };
+    let i = 0;
+    while (i < {N} * 1000) {
+        acc = acc.add(step);
+        i = i + 1;
+    }
+    return acc.x + acc.y;
+}
+"#),
+];
+
+/// Run each interpreter workload at size `n` through the full pipeline,
+/// returning (name, result, elapsed) per workload
+pub fn run_interp_bench(n: i64) -> Result<Vec<(&'static str, String, Duration)>, String> {
+    let mut results = Vec::new();
+    for (name, template) in INTERP_WORKLOADS {
+        let source = template.replace("{N}", &n.to_string());
+        let tokens = lexer::tokenize(&source);
+        let ast = Parser::new(&tokens).parse()?;
+        let ast = Optimizer::new().optimize(Expander::new().expand(ast));
+
+        let start = Instant::now();
+        let value = Interpreter::new().run(&ast)?;
+        results.push((*name, value.to_string_val(), start.elapsed()));
+    }
+    Ok(results)
+}
+
+/// Summary of per-run times in milliseconds, as bench() reports them:
+/// total, mean, median, min and max. `times` must not be empty.
+pub fn stats(times: &[f64]) -> [(&'static str, f64); 5] {
+    let mut sorted = times.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let total: f64 = sorted.iter().sum();
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) { (sorted[mid - 1] + sorted[mid]) / 2.0 } else { sorted[mid] };
+    [
+        ("total_ms", total),
+        ("mean_ms", total / sorted.len() as f64),
+        ("median_ms", median),
+        ("min_ms", sorted[0]),
+        ("max_ms", sorted[sorted.len() - 1]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_source_parses() {
+        let source = generate_source(1000);
+        let result = run_parse_bench(&source).expect("generated source should parse");
+        assert!(result.lines >= 1000);
+        assert_eq!(result.items % 2, 0); // struct + fn per block
+    }
+
+    #[test]
+    fn test_interp_workloads() {
+        let results = run_interp_bench(10).expect("workloads should run");
+        assert_eq!(results[0].1, "55");
+        assert_eq!(results[2].1, "30000");
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = stats(&[4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(stats, [("total_ms", 10.0), ("mean_ms", 2.5), ("median_ms", 2.5), ("min_ms", 1.0), ("max_ms", 4.0)]);
+        assert_eq!(super::stats(&[7.0])[2], ("median_ms", 7.0));
+    }
+}
@@ -0,0 +1,485 @@
+// ============================================
+// Cryo BigInt Module
+// Arbitrary-precision signed integers
+// ============================================
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Sign and magnitude, the magnitude as little-endian base 2^32 limbs with no
+/// trailing zero limbs (zero is an empty vector and never negative)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    mag: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> BigInt {
+        BigInt { negative: false, mag: Vec::new() }
+    }
+
+    fn from_parts(negative: bool, mut mag: Vec<u32>) -> BigInt {
+        while mag.last() == Some(&0) {
+            mag.pop();
+        }
+        let negative = negative && !mag.is_empty();
+        BigInt { negative, mag }
+    }
+
+    pub fn from_i64(n: i64) -> BigInt {
+        BigInt::from_i128(n as i128)
+    }
+
+    pub fn from_i128(n: i128) -> BigInt {
+        let mut abs = n.unsigned_abs();
+        let mut mag = Vec::with_capacity(4);
+        while abs > 0 {
+            mag.push(abs as u32);
+            abs >>= 32;
+        }
+        BigInt::from_parts(n < 0, mag)
+    }
+
+    /// The value as an i64, if it fits
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let abs = self.mag.iter().rev().fold(0u64, |acc, &limb| acc << 32 | limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(abs)
+        } else {
+            i64::try_from(abs).ok()
+        }
+    }
+
+    /// The value as an i128, if it fits
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.mag.len() > 4 {
+            return None;
+        }
+        let abs = self.mag.iter().rev().fold(0u128, |acc, &limb| acc << 32 | limb as u128);
+        if self.negative {
+            0i128.checked_sub_unsigned(abs)
+        } else {
+            i128::try_from(abs).ok()
+        }
+    }
+
+    /// The low 64 bits of the value in two's complement, like `as u64` on
+    /// a primitive
+    pub fn low_u64(&self) -> u64 {
+        let abs = self.mag.iter().take(2).rev().fold(0u64, |acc, &limb| acc << 32 | limb as u64);
+        if self.negative { abs.wrapping_neg() } else { abs }
+    }
+
+    /// The value as an i64, clamped to `i64::MIN..=i64::MAX`
+    pub fn to_i64_saturating(&self) -> i64 {
+        self.to_i64().unwrap_or(if self.negative { i64::MIN } else { i64::MAX })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_odd(&self) -> bool {
+        self.mag.first().is_some_and(|limb| limb & 1 == 1)
+    }
+
+    /// Parse an optionally signed decimal integer. `_` separators between
+    /// digits are allowed (`1_000_000`).
+    pub fn parse(text: &str) -> Option<BigInt> {
+        let text = text.trim();
+        let (negative, digits) = match text.as_bytes().first()? {
+            b'-' => (true, &text[1..]),
+            b'+' => (false, &text[1..]),
+            _ => (false, text),
+        };
+        if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+            return None;
+        }
+        let mut mag: Vec<u32> = Vec::with_capacity(digits.len() / 9 + 1);
+        // Nine decimal digits at a time fit in a limb: mag = mag * 10^k + chunk
+        let mut chunk = 0u32;
+        let mut chunk_len = 0;
+        for b in digits.bytes() {
+            match b {
+                b'0'..=b'9' => {
+                    chunk = chunk * 10 + (b - b'0') as u32;
+                    chunk_len += 1;
+                    if chunk_len == 9 {
+                        mul_small_add(&mut mag, 1_000_000_000, chunk);
+                        chunk = 0;
+                        chunk_len = 0;
+                    }
+                }
+                b'_' => {}
+                _ => return None,
+            }
+        }
+        if chunk_len > 0 {
+            mul_small_add(&mut mag, 10u32.pow(chunk_len), chunk);
+        }
+        Some(BigInt::from_parts(negative, mag))
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.mag.clone())
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::from_parts(false, self.mag.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_mag(&self.mag, &other.mag));
+        }
+        // Opposite signs: subtract the smaller magnitude from the larger
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::from_parts(other.negative, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::from_parts(self.negative, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        if self.is_zero() || other.is_zero() {
+            return BigInt::zero();
+        }
+        let mut out = vec![0u32; self.mag.len() + other.mag.len()];
+        for (i, &a) in self.mag.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.mag.iter().enumerate() {
+                let t = a as u64 * b as u64 + out[i + j] as u64 + carry;
+                out[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            out[i + other.mag.len()] = carry as u32;
+        }
+        BigInt::from_parts(self.negative != other.negative, out)
+    }
+
+    /// Truncating division like i64's `/` and `%`: the quotient rounds toward
+    /// zero and the remainder takes the dividend's sign. None when dividing by zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = div_rem_mag(&self.mag, &other.mag);
+        Some((
+            BigInt::from_parts(self.negative != other.negative, q),
+            BigInt::from_parts(self.negative, r),
+        ))
+    }
+
+    pub fn pow(&self, mut exp: u32) -> BigInt {
+        let mut base = self.clone();
+        let mut out = BigInt::from_i64(1);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                out = out.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+        out
+    }
+
+    /// `self^exp mod modulus` by square-and-multiply, result in `0..|modulus|`.
+    /// None for a zero modulus or a negative exponent.
+    pub fn pow_mod(&self, exp: &BigInt, modulus: &BigInt) -> Option<BigInt> {
+        if modulus.is_zero() || exp.negative {
+            return None;
+        }
+        let m = modulus.abs();
+        let reduce = |x: &BigInt| -> BigInt {
+            let r = x.div_rem(&m).map(|(_, r)| r).unwrap_or_else(BigInt::zero);
+            if r.negative { r.add(&m) } else { r }
+        };
+        let mut base = reduce(self);
+        let mut out = reduce(&BigInt::from_i64(1));
+        for i in 0..exp.mag.len() * 32 {
+            if exp.mag[i / 32] >> (i % 32) & 1 == 1 {
+                out = reduce(&out.mul(&base));
+            }
+            base = reduce(&base.mul(&base));
+        }
+        Some(out)
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return f.write_str("0");
+        }
+        // Peel off nine decimal digits at a time, least significant first
+        let mut mag = self.mag.clone();
+        let mut chunks = Vec::with_capacity(mag.len() * 32 / 29 + 1);
+        while !mag.is_empty() {
+            chunks.push(div_small(&mut mag, 1_000_000_000));
+        }
+        let mut out = String::with_capacity(chunks.len() * 9 + 1);
+        if self.negative {
+            out.push('-');
+        }
+        out.push_str(&chunks.pop().unwrap_or(0).to_string());
+        for chunk in chunks.iter().rev() {
+            out.push_str(&format!("{:09}", chunk));
+        }
+        f.write_str(&out)
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut out = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &x) in long.iter().enumerate() {
+        let t = x as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        out.push(t as u32);
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        out.push(carry as u32);
+    }
+    out
+}
+
+/// `a - b` for `a >= b`
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let mut t = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (t < 0) as i64;
+        if t < 0 {
+            t += 1 << 32;
+        }
+        out.push(t as u32);
+    }
+    out
+}
+
+/// `mag = mag * mul + add` in place
+fn mul_small_add(mag: &mut Vec<u32>, mul: u32, add: u32) {
+    let mut carry = add as u64;
+    for limb in mag.iter_mut() {
+        let t = *limb as u64 * mul as u64 + carry;
+        *limb = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        mag.push(carry as u32);
+    }
+}
+
+/// Divide in place by a single limb, returning the remainder
+fn div_small(mag: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut rem = 0u64;
+    for limb in mag.iter_mut().rev() {
+        let cur = rem << 32 | *limb as u64;
+        *limb = (cur / divisor as u64) as u32;
+        rem = cur % divisor as u64;
+    }
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+    rem as u32
+}
+
+/// Schoolbook long division (Knuth's algorithm D) on magnitudes
+fn div_rem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let mut q = a.to_vec();
+        let r = div_small(&mut q, b[0]);
+        return (q, vec![r]);
+    }
+    // Normalize so the divisor's top limb has its high bit set; that keeps
+    // each estimated quotient digit at most two too large
+    let shift = b[b.len() - 1].leading_zeros();
+    let b = shl_bits(b, shift);
+    let mut a = shl_bits(a, shift);
+    a.push(0);
+    let n = b.len();
+    let m = a.len() - n;
+    let mut q = vec![0u32; m];
+    let top = b[n - 1] as u64;
+    let next = b[n - 2] as u64;
+    for j in (0..m).rev() {
+        let num = (a[j + n] as u64) << 32 | a[j + n - 1] as u64;
+        let mut qhat = num / top;
+        let mut rhat = num % top;
+        while qhat > u32::MAX as u64 || qhat * next > (rhat << 32 | a[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += top;
+            if rhat > u32::MAX as u64 {
+                break;
+            }
+        }
+        // a[j..j+n+1] -= qhat * b
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let p = qhat * b[i] as u64 + carry;
+            carry = p >> 32;
+            let t = a[i + j] as i64 - borrow - (p & 0xffff_ffff) as i64;
+            a[i + j] = t as u32;
+            borrow = -(t >> 32);
+        }
+        let t = a[j + n] as i64 - borrow - carry as i64;
+        a[j + n] = t as u32;
+        if t < 0 {
+            // qhat was one too large: add the divisor back
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let s = a[i + j] as u64 + b[i] as u64 + carry;
+                a[i + j] = s as u32;
+                carry = s >> 32;
+            }
+            a[j + n] = a[j + n].wrapping_add(carry as u32);
+        }
+        q[j] = qhat as u32;
+    }
+    a.truncate(n);
+    let r = shr_bits(&a, shift);
+    (q, r)
+}
+
+fn shl_bits(mag: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return mag.to_vec();
+    }
+    let mut out = Vec::with_capacity(mag.len() + 1);
+    let mut carry = 0u32;
+    for &limb in mag {
+        out.push(limb << shift | carry);
+        carry = limb >> (32 - shift);
+    }
+    if carry > 0 {
+        out.push(carry);
+    }
+    out
+}
+
+fn shr_bits(mag: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return mag.to_vec();
+    }
+    let mut out = vec![0u32; mag.len()];
+    for i in 0..mag.len() {
+        let high = mag.get(i + 1).map_or(0, |&next| next << (32 - shift));
+        out[i] = mag[i] >> shift | high;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        BigInt::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for s in ["0", "1", "-1", "4294967296", "-18446744073709551616", "123456789012345678901234567890"] {
+            assert_eq!(big(s).to_string(), s);
+        }
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(big("+0001_000").to_string(), "1000");
+        assert_eq!(BigInt::parse("12a"), None);
+        assert_eq!(BigInt::parse("-"), None);
+        assert_eq!(BigInt::from_i64(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(BigInt::from_i64(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(a.add(&b).to_string(), "-864197532086419753208641975320");
+        assert_eq!(a.sub(&b).to_string(), "1111111110111111111011111111100");
+        assert_eq!(a.mul(&b).to_string(), "-121932631137021795226185032733622923332237463801111263526900");
+        let (q, r) = b.div_rem(&a).unwrap();
+        assert_eq!((q.to_string(), r.to_string()), ("-8".to_string(), "-9000000000900000000090".to_string()));
+        assert!(a.div_rem(&BigInt::zero()).is_none());
+        // 30! / 20! exercises multi-limb division
+        let fact = |n: i64| (1..=n).fold(BigInt::from_i64(1), |acc, k| acc.mul(&BigInt::from_i64(k)));
+        let (q, r) = fact(30).div_rem(&fact(20)).unwrap();
+        assert_eq!(q.to_string(), "109027350432000");
+        assert!(r.is_zero());
+        assert_eq!(BigInt::from_i64(2).pow(100).to_string(), "1267650600228229401496703205376");
+        assert!(big("-5") < big("3") && big("-5") < big("-4") && a > big("99"));
+    }
+
+    #[test]
+    fn test_div_matches_i128() {
+        let samples: [i128; 8] = [1, -7, 4294967295, 4294967297, -18446744073709551615, 1 << 100, -(1 << 90) + 12345, i128::MAX];
+        for &x in &samples {
+            for &y in &samples {
+                let (q, r) = BigInt::from_i128(x).div_rem(&BigInt::from_i128(y)).unwrap();
+                assert_eq!(q, BigInt::from_i128(x / y), "{} / {}", x, y);
+                assert_eq!(r, BigInt::from_i128(x % y), "{} % {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(big("-170141183460469231731687303715884105728").to_i128(), Some(i128::MIN));
+        assert_eq!(big("170141183460469231731687303715884105728").to_i128(), None);
+        assert_eq!(big("18446744073709551615").low_u64(), u64::MAX);
+        assert_eq!(big("-1").low_u64(), u64::MAX);
+        assert_eq!(big("-18446744073709551617").low_u64(), u64::MAX);
+        assert_eq!(big("36893488147419103237").low_u64(), 5);
+    }
+
+    #[test]
+    fn test_pow_mod() {
+        // Fermat: a^(p-1) = 1 mod p for the prime 2^127 - 1
+        let p = BigInt::from_i64(2).pow(127).sub(&BigInt::from_i64(1));
+        let e = p.sub(&BigInt::from_i64(1));
+        assert_eq!(BigInt::from_i64(3).pow_mod(&e, &p).unwrap().to_string(), "1");
+        assert_eq!(BigInt::from_i64(-2).pow_mod(&BigInt::from_i64(3), &BigInt::from_i64(5)).unwrap().to_string(), "2");
+        assert!(BigInt::from_i64(2).pow_mod(&BigInt::from_i64(-1), &p).is_none());
+    }
+}
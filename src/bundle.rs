@@ -0,0 +1,603 @@
+// ============================================
+// Cryo Bundle Module
+// Single-file executables: the interpreter with a program appended to it
+// ============================================
+
+use crate::intern::Symbol;
+use crate::lexer::Span;
+use crate::parser::{Decorator, EnumDef, ExternBlock, Expr, Function, ImplDef, MacroDef, MatchArm, Param, Pattern, Stmt, StructDef, TopLevel, TraitDef};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Marks the last bytes of a bundled executable
+const MAGIC: &[u8; 8] = b"CRYOBNDL";
+/// Bumped whenever the AST encoding changes
+const FORMAT_VERSION: u32 = 11;
+/// payload length (u64 LE) + magic
+const TRAILER_LEN: u64 = 16;
+
+/// A program with every module it imports, each already expanded and
+/// optimized. Modules are keyed by the path exactly as written in `import`.
+#[derive(Debug)]
+pub struct Bundle {
+    pub main: Vec<TopLevel>,
+    pub modules: Vec<(String, Vec<TopLevel>)>,
+}
+
+/// Lexer -> Parser -> Expander -> Optimizer, as run on every source file
+fn compile(source: &str) -> Result<Vec<TopLevel>, String> {
+    let tokens = crate::lexer::tokenize(source);
+    let ast = crate::parser::Parser::new(&tokens).parse()?;
+    let expanded = crate::expander::Expander::new().expand(ast);
+    Ok(crate::optimizer::Optimizer::new().optimize(expanded))
+}
+
+impl Bundle {
+    /// Compile `entry` and, transitively, every module it imports.
+    /// `resolve` maps an import path to the file it loads.
+    pub fn collect(entry: &str, resolve: impl Fn(&str) -> Option<String>) -> Result<Bundle, String> {
+        let source = fs::read_to_string(entry).map_err(|e| format!("{}: {}", entry, e))?;
+        let main = compile(&source).map_err(|e| format!("{}: {}", entry, e))?;
+        let mut modules = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = imports(&main);
+        while let Some(path) = pending.pop() {
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            let file = resolve(&path).ok_or_else(|| format!("Module not found: {}", path))?;
+            let source = fs::read_to_string(&file).map_err(|e| format!("{}: {}", file, e))?;
+            let ast = compile(&source).map_err(|e| format!("{}: {}", file, e))?;
+            pending.extend(imports(&ast));
+            modules.push((path, ast));
+        }
+        Ok(Bundle { main, modules })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.u32(FORMAT_VERSION);
+        w.items(&self.main);
+        w.u32(self.modules.len() as u32);
+        for (path, ast) in &self.modules {
+            w.str(path);
+            w.items(ast);
+        }
+        w.buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Bundle, String> {
+        let mut r = Reader { data, pos: 0 };
+        let version = r.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(format!("bundle format {} is not supported by this runtime (expected {})", version, FORMAT_VERSION));
+        }
+        let main = r.items()?;
+        let mut modules = Vec::new();
+        for _ in 0..r.u32()? {
+            modules.push((r.string()?, r.items()?));
+        }
+        Ok(Bundle { main, modules })
+    }
+
+    /// Write `runtime` (the interpreter executable) to `output` with this
+    /// program appended, and mark the result executable
+    pub fn write_executable(&self, runtime: &str, output: &str) -> Result<(), String> {
+        let mut image = fs::read(runtime).map_err(|e| format!("{}: {}", runtime, e))?;
+        // Bundling from a bundled app replaces its program instead of stacking another
+        if let Some(start) = payload_start(&image) {
+            image.truncate(start);
+        }
+        let payload = self.encode();
+        image.extend_from_slice(&payload);
+        image.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        image.extend_from_slice(MAGIC);
+        fs::write(output, &image).map_err(|e| format!("{}: {}", output, e))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(output, fs::Permissions::from_mode(0o755)).map_err(|e| format!("{}: {}", output, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Offset where an appended payload starts, if `image` ends with one
+fn payload_start(image: &[u8]) -> Option<usize> {
+    let trailer = image.len().checked_sub(TRAILER_LEN as usize)?;
+    if &image[trailer + 8..] != MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(image[trailer..trailer + 8].try_into().ok()?) as usize;
+    trailer.checked_sub(len)
+}
+
+/// The program appended to the running executable. None for a plain `cryo`
+/// binary; only the trailer is read in that case.
+pub fn read_embedded() -> Option<Result<Bundle, String>> {
+    let exe = std::env::current_exe().ok()?;
+    let mut file = File::open(exe).ok()?;
+    let size = file.seek(SeekFrom::End(0)).ok()?;
+    if size < TRAILER_LEN {
+        return None;
+    }
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.seek(SeekFrom::Start(size - TRAILER_LEN)).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    if &trailer[8..] != MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(trailer[..8].try_into().ok()?);
+    let mut load = || {
+        let start = (size - TRAILER_LEN).checked_sub(len).ok_or("bundle payload is truncated")?;
+        let mut payload = vec![0u8; len as usize];
+        file.seek(SeekFrom::Start(start)).map_err(|e| e.to_string())?;
+        file.read_exact(&mut payload).map_err(|e| e.to_string())?;
+        Bundle::decode(&payload)
+    };
+    Some(load())
+}
+
+fn imports(ast: &[TopLevel]) -> Vec<String> {
+    ast.iter().filter_map(|item| match item {
+        TopLevel::Import(path, _) => Some(path.clone()),
+        _ => None,
+    }).collect()
+}
+
+// ---- AST encoding ----
+// Length-prefixed little-endian fields, one tag byte per enum variant.
+// Tags follow declaration order in parser.rs.
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i64(&mut self, v: i64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn sym(&mut self, s: Symbol) {
+        self.str(s.as_str());
+    }
+
+    fn opt_str(&mut self, s: &Option<String>) {
+        self.bool(s.is_some());
+        if let Some(s) = s {
+            self.str(s);
+        }
+    }
+
+    fn list<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        self.u32(items.len() as u32);
+        for item in items {
+            f(self, item);
+        }
+    }
+
+    fn items(&mut self, items: &[TopLevel]) {
+        self.list(items, Self::item);
+    }
+
+    fn item(&mut self, item: &TopLevel) {
+        match item {
+            TopLevel::Function(f) => { self.u8(0); self.function(f); }
+            TopLevel::Struct(s) => {
+                self.u8(1);
+                self.sym(s.name);
+                self.list(&s.fields, |w, (name, typ)| { w.str(name); w.str(typ); });
+                self.decorators(&s.decorators);
+            }
+            TopLevel::Enum(e) => {
+                self.u8(2);
+                self.str(&e.name);
+                self.list(&e.variants, |w, (name, fields)| { w.str(name); w.list(fields, |w, f| w.str(f)); });
+            }
+            TopLevel::Let(name, value) => { self.u8(3); self.sym(*name); self.expr(value); }
+            TopLevel::Import(path, names) => {
+                self.u8(4);
+                self.str(path);
+                self.list(names, |w, n| w.str(n));
+            }
+            TopLevel::Trait(t) => {
+                self.u8(5);
+                self.str(&t.name);
+                self.list(&t.methods, Self::function);
+            }
+            TopLevel::Impl(i) => {
+                self.u8(6);
+                self.str(&i.trait_name);
+                self.sym(i.type_name);
+                self.list(&i.methods, Self::function);
+            }
+            TopLevel::Extern(e) => {
+                self.u8(7);
+                self.str(&e.abi);
+                self.list(&e.functions, Self::function);
+            }
+            TopLevel::Macro(m) => {
+                self.u8(8);
+                self.sym(m.name);
+                self.list(&m.params, |w, p| w.sym(*p));
+                self.stmts(&m.body);
+            }
+        }
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) {
+        self.list(decorators, |w, d| { w.str(&d.name); w.str(&d.arg); });
+    }
+
+    fn function(&mut self, f: &Function) {
+        self.sym(f.name);
+        self.list(&f.params, |w, p| { w.sym(p.name); w.opt_str(&p.typ); });
+        self.bool(f.body.is_some());
+        if let Some(body) = &f.body {
+            self.stmts(body);
+        }
+        self.bool(f.is_async);
+        self.opt_str(&f.return_type);
+        self.decorators(&f.decorators);
+        self.u32(f.line as u32);
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        self.list(stmts, Self::stmt);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let(name, typ, value) => { self.u8(0); self.sym(*name); self.opt_str(typ); self.expr(value); }
+            Stmt::Assign(name, value) => { self.u8(1); self.sym(*name); self.expr(value); }
+            Stmt::IndexAssign(target, index, value) => { self.u8(2); self.expr(target); self.expr(index); self.expr(value); }
+            Stmt::FieldAssign(target, field, value) => { self.u8(3); self.expr(target); self.sym(*field); self.expr(value); }
+            Stmt::Return(value) => {
+                self.u8(4);
+                self.bool(value.is_some());
+                if let Some(v) = value {
+                    self.expr(v);
+                }
+            }
+            Stmt::Print(e) => { self.u8(5); self.expr(e); }
+            Stmt::If(cond, then, otherwise) => {
+                self.u8(6);
+                self.expr(cond);
+                self.stmts(then);
+                self.bool(otherwise.is_some());
+                if let Some(o) = otherwise {
+                    self.stmts(o);
+                }
+            }
+            Stmt::While(cond, body) => { self.u8(7); self.expr(cond); self.stmts(body); }
+            Stmt::Break => self.u8(8),
+            Stmt::Continue => self.u8(9),
+            Stmt::Expr(e) => { self.u8(10); self.expr(e); }
+            Stmt::Block(body) => { self.u8(11); self.stmts(body); }
+            Stmt::Defer(s) => { self.u8(12); self.stmt(s); }
+            Stmt::Match(subject, arms) => { self.u8(13); self.expr(subject); self.arms(arms); }
+            Stmt::Try(body, name, handler) => { self.u8(14); self.stmts(body); self.sym(*name); self.stmts(handler); }
+            Stmt::Throw(e) => { self.u8(15); self.expr(e); }
+            Stmt::With(name, e, body) => { self.u8(16); self.sym(*name); self.expr(e); self.stmts(body); }
+            Stmt::At(line, col, s) => { self.u8(17); self.u32(*line as u32); self.u32(*col as u32); self.stmt(s); }
+        }
+    }
+
+    fn arms(&mut self, arms: &[MatchArm]) {
+        self.list(arms, |w, arm| {
+            w.pattern(&arm.pattern);
+            w.bool(arm.guard.is_some());
+            if let Some(guard) = &arm.guard {
+                w.expr(guard);
+            }
+            w.stmts(&arm.body);
+        });
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.u8(0),
+            Pattern::Literal(e) => { self.u8(1); self.expr(e); }
+            Pattern::Binding(name) => { self.u8(2); self.sym(*name); }
+            Pattern::Variant(name, variant, fields) => { self.u8(3); self.sym(*name); self.sym(*variant); self.list(fields, Self::pattern); }
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        self.list(exprs, Self::expr);
+    }
+
+    fn fields(&mut self, fields: &[(Symbol, Expr)]) {
+        self.list(fields, |w, (name, value)| { w.sym(*name); w.expr(value); });
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(n) => { self.u8(0); self.i64(*n); }
+            Expr::String(s) => { self.u8(1); self.str(s); }
+            Expr::Bool(b) => { self.u8(2); self.bool(*b); }
+            Expr::Null => self.u8(3),
+            Expr::Identifier(name) => { self.u8(4); self.sym(*name); }
+            Expr::BinOp(l, op, r) => { self.u8(5); self.expr(l); self.str(op); self.expr(r); }
+            Expr::UnaryOp(op, e) => { self.u8(6); self.str(op); self.expr(e); }
+            Expr::Call(name, args) => { self.u8(7); self.sym(*name); self.exprs(args); }
+            Expr::MethodCall(obj, name, args) => { self.u8(8); self.expr(obj); self.sym(*name); self.exprs(args); }
+            Expr::Index(obj, index) => { self.u8(9); self.expr(obj); self.expr(index); }
+            Expr::Field(obj, name) => { self.u8(10); self.expr(obj); self.sym(*name); }
+            Expr::Array(items) => { self.u8(11); self.exprs(items); }
+            Expr::StructInit(name, fields) => { self.u8(12); self.sym(*name); self.fields(fields); }
+            Expr::ObjectLiteral(fields) => { self.u8(13); self.fields(fields); }
+            Expr::Await(e) => { self.u8(14); self.expr(e); }
+            Expr::StaticMethodCall(typ, name, args) => { self.u8(15); self.sym(*typ); self.sym(*name); self.exprs(args); }
+            Expr::Float(f) => { self.u8(16); self.i64(f.to_bits() as i64); }
+            Expr::Variant(name, variant) => { self.u8(17); self.sym(*name); self.sym(*variant); }
+            Expr::Match(subject, arms) => { self.u8(18); self.expr(subject); self.arms(arms); }
+            Expr::MapLiteral(entries) => { self.u8(19); self.list(entries, |w, (key, value)| { w.str(key); w.expr(value); }); }
+            Expr::Propagate(e) => { self.u8(20); self.expr(e); }
+            Expr::At(span, e) => { self.u8(21); self.u32(span.start as u32); self.u32(span.end as u32); self.expr(e); }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+const TRUNCATED: &str = "bundle payload is truncated";
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len()).ok_or(TRUNCATED)?;
+        let out = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().map_err(|_| TRUNCATED)?))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.bytes(8)?.try_into().map_err(|_| TRUNCATED)?))
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| "bundle payload has invalid UTF-8".to_string())
+    }
+
+    fn sym(&mut self) -> Result<Symbol, String> {
+        Ok(Symbol::intern(&self.string()?))
+    }
+
+    fn opt_string(&mut self) -> Result<Option<String>, String> {
+        Ok(if self.bool()? { Some(self.string()?) } else { None })
+    }
+
+    fn list<T>(&mut self, mut f: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let len = self.u32()? as usize;
+        // Every element takes at least a byte, which bounds a corrupt length
+        let mut out = Vec::with_capacity(len.min(self.data.len() - self.pos));
+        for _ in 0..len {
+            out.push(f(self)?);
+        }
+        Ok(out)
+    }
+
+    fn items(&mut self) -> Result<Vec<TopLevel>, String> {
+        self.list(Self::item)
+    }
+
+    fn item(&mut self) -> Result<TopLevel, String> {
+        Ok(match self.u8()? {
+            0 => TopLevel::Function(self.function()?),
+            1 => TopLevel::Struct(StructDef {
+                name: self.sym()?,
+                fields: self.list(|r| Ok((r.string()?, r.string()?)))?,
+                decorators: self.decorators()?,
+            }),
+            2 => TopLevel::Enum(EnumDef { name: self.string()?, variants: self.list(|r| Ok((r.string()?, r.list(Self::string)?)))? }),
+            3 => TopLevel::Let(self.sym()?, self.expr()?),
+            4 => TopLevel::Import(self.string()?, self.list(Self::string)?),
+            5 => TopLevel::Trait(TraitDef { name: self.string()?, methods: self.list(Self::function)? }),
+            6 => TopLevel::Impl(ImplDef { trait_name: self.string()?, type_name: self.sym()?, methods: self.list(Self::function)? }),
+            7 => TopLevel::Extern(ExternBlock { abi: self.string()?, functions: self.list(Self::function)? }),
+            8 => TopLevel::Macro(MacroDef { name: self.sym()?, params: self.list(Self::sym)?, body: self.stmts()? }),
+            tag => return Err(format!("bundle payload: unknown item tag {}", tag)),
+        })
+    }
+
+    fn decorators(&mut self) -> Result<Vec<Decorator>, String> {
+        self.list(|r| Ok(Decorator { name: r.string()?, arg: r.string()? }))
+    }
+
+    fn function(&mut self) -> Result<Function, String> {
+        Ok(Function {
+            name: self.sym()?,
+            params: self.list(|r| Ok(Param { name: r.sym()?, typ: r.opt_string()? }))?,
+            body: if self.bool()? { Some(self.stmts()?) } else { None },
+            is_async: self.bool()?,
+            return_type: self.opt_string()?,
+            decorators: self.decorators()?,
+            line: self.u32()? as usize,
+        })
+    }
+
+    fn stmts(&mut self) -> Result<Vec<Stmt>, String> {
+        self.list(Self::stmt)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, String> {
+        Ok(match self.u8()? {
+            0 => Stmt::Let(self.sym()?, self.opt_string()?, self.expr()?),
+            1 => Stmt::Assign(self.sym()?, self.expr()?),
+            2 => Stmt::IndexAssign(self.expr()?, self.expr()?, self.expr()?),
+            3 => Stmt::FieldAssign(self.expr()?, self.sym()?, self.expr()?),
+            4 => Stmt::Return(if self.bool()? { Some(self.expr()?) } else { None }),
+            5 => Stmt::Print(self.expr()?),
+            6 => Stmt::If(self.expr()?, self.stmts()?, if self.bool()? { Some(self.stmts()?) } else { None }),
+            7 => Stmt::While(self.expr()?, self.stmts()?),
+            8 => Stmt::Break,
+            9 => Stmt::Continue,
+            10 => Stmt::Expr(self.expr()?),
+            11 => Stmt::Block(self.stmts()?),
+            12 => Stmt::Defer(Box::new(self.stmt()?)),
+            13 => Stmt::Match(self.expr()?, self.arms()?),
+            14 => Stmt::Try(self.stmts()?, self.sym()?, self.stmts()?),
+            15 => Stmt::Throw(self.expr()?),
+            16 => Stmt::With(self.sym()?, self.expr()?, self.stmts()?),
+            17 => Stmt::At(self.u32()? as usize, self.u32()? as usize, Box::new(self.stmt()?)),
+            tag => return Err(format!("bundle payload: unknown statement tag {}", tag)),
+        })
+    }
+
+    fn arms(&mut self) -> Result<Vec<MatchArm>, String> {
+        self.list(|r| {
+            let pattern = r.pattern()?;
+            let guard = if r.bool()? { Some(r.expr()?) } else { None };
+            Ok(MatchArm { pattern, guard, body: r.stmts()? })
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, String> {
+        Ok(match self.u8()? {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Literal(self.expr()?),
+            2 => Pattern::Binding(self.sym()?),
+            3 => Pattern::Variant(self.sym()?, self.sym()?, self.list(Self::pattern)?),
+            tag => return Err(format!("bundle payload: unknown pattern tag {}", tag)),
+        })
+    }
+
+    fn exprs(&mut self) -> Result<Vec<Expr>, String> {
+        self.list(Self::expr)
+    }
+
+    fn fields(&mut self) -> Result<Vec<(Symbol, Expr)>, String> {
+        self.list(|r| Ok((r.sym()?, r.expr()?)))
+    }
+
+    fn boxed(&mut self) -> Result<Box<Expr>, String> {
+        Ok(Box::new(self.expr()?))
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        Ok(match self.u8()? {
+            0 => Expr::Number(self.i64()?),
+            1 => Expr::String(self.string()?),
+            2 => Expr::Bool(self.bool()?),
+            3 => Expr::Null,
+            4 => Expr::Identifier(self.sym()?),
+            5 => Expr::BinOp(self.boxed()?, self.string()?, self.boxed()?),
+            6 => Expr::UnaryOp(self.string()?, self.boxed()?),
+            7 => Expr::Call(self.sym()?, self.exprs()?),
+            8 => Expr::MethodCall(self.boxed()?, self.sym()?, self.exprs()?),
+            9 => Expr::Index(self.boxed()?, self.boxed()?),
+            10 => Expr::Field(self.boxed()?, self.sym()?),
+            11 => Expr::Array(self.exprs()?),
+            12 => Expr::StructInit(self.sym()?, self.fields()?),
+            13 => Expr::ObjectLiteral(self.fields()?),
+            14 => Expr::Await(self.boxed()?),
+            15 => Expr::StaticMethodCall(self.sym()?, self.sym()?, self.exprs()?),
+            16 => Expr::Float(f64::from_bits(self.i64()? as u64)),
+            17 => Expr::Variant(self.sym()?, self.sym()?),
+            18 => Expr::Match(self.boxed()?, self.arms()?),
+            19 => Expr::MapLiteral(self.list(|r| Ok((r.string()?, r.expr()?)))?),
+            20 => Expr::Propagate(self.boxed()?),
+            21 => Expr::At(Span { start: self.u32()? as usize, end: self.u32()? as usize }, self.boxed()?),
+            tag => return Err(format!("bundle payload: unknown expression tag {}", tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r#"
+import { greet } from "lib/greeting";
+
+struct Point { x: int, y: int }
+enum Color { Red, Green }
+enum Shape { Circle(r), Rect(w, h) }
+
+impl Point {
+    fn sum(self) -> int { return self.x + self.y; }
+}
+
+let ORIGIN = Point { x: 0, y: 0 };
+
+fn main() {
+    let p: Point = Point { x: 3, y: -4 };
+    let items = [1, "two", null, true, { k: p.sum() }, { "a b": 1 }];
+    let i = 0;
+    while (i < len(items)) {
+        if (i == 2) { i = i + 1; continue; } else { print(items[i]); }
+        i = i + 1;
+    }
+    defer print("done");
+    try { throw { code: 1 }; } catch (e) { print(e.code); }
+    with (let f = open("log.txt", "a")) { write(f, "x"); }
+    let port = Result::Ok(p.x)?;
+    match Color::Red {
+        Color::Red => print("red"),
+        Shape::Rect(w, 2) => print(Shape::Circle(w)),
+        n if n == -1 => { print(n); }
+        _ => print(match p.x { 3 => "three", _ => null }),
+    }
+    return;
+}
+"#;
+
+    #[test]
+    fn test_roundtrip() {
+        let bundle = Bundle {
+            main: compile(PROGRAM).unwrap(),
+            modules: vec![("lib/greeting".to_string(), compile("fn greet(name) { return \"hi \" + name; }").unwrap())],
+        };
+        let encoded = bundle.encode();
+        let decoded = Bundle::decode(&encoded).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", bundle));
+        assert!(Bundle::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_payload_trailer() {
+        let payload = Bundle { main: Vec::new(), modules: Vec::new() }.encode();
+        let mut image = b"\x7fELF runtime".to_vec();
+        let runtime_len = image.len();
+        assert_eq!(payload_start(&image), None);
+        image.extend_from_slice(&payload);
+        image.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        image.extend_from_slice(MAGIC);
+        assert_eq!(payload_start(&image), Some(runtime_len));
+    }
+}
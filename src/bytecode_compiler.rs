@@ -0,0 +1,460 @@
+// ============================================
+// Cryo Bytecode Compiler
+// Lowers a single function's AST to a CompiledFunc for the bytecode VM
+// ============================================
+
+use crate::bytecode_vm::{CompiledFunc, ConstantPool, OpCode};
+use crate::int_types::IntType;
+use crate::intern::Symbol;
+use crate::parser::{Expr, Function, Stmt};
+use rustc_hash::FxHashMap;
+
+/// Decorator that asks for a function to run in the bytecode VM
+pub const DECORATOR: &str = "bytecode";
+
+/// Builtins that look at the caller's variables, which bytecode keeps in VM slots
+const SCOPE_BUILTINS: &[&str] = &["eval"];
+
+/// Builtins whose effect can't survive the copy a VM value makes on its way
+/// to the host and back
+const IDENTITY_BUILTINS: &[&str] = &["freeze"];
+
+pub fn is_annotated(func: &Function) -> bool {
+    func.decorators.iter().any(|d| d.name == DECORATOR)
+}
+
+/// Compile `func` for the VM. Calls to names `resolve` maps to a VM function
+/// index (with matching arity) become direct `Call`s; every other call goes
+/// back to the host. Anything the VM can't express (globals, methods, enums,
+/// defer, ...) is an error and the function stays interpreted.
+pub fn compile(func: &Function, resolve: impl Fn(Symbol) -> Option<(usize, usize)>) -> Result<CompiledFunc, String> {
+    let body = func.body.as_ref().ok_or("function has no body")?;
+    // The VM doesn't check sized int annotations
+    let sized = |typ: &Option<String>| typ.as_deref().and_then(IntType::parse).is_some();
+    if func.params.iter().any(|p| sized(&p.typ)) || sized(&func.return_type) {
+        return Err("has sized int annotations".to_string());
+    }
+    let mut c = Compiler {
+        resolve: &resolve,
+        code: Vec::new(),
+        pool: ConstantPool::new(),
+        scopes: vec![FxHashMap::default()],
+        locals: 0,
+        loops: Vec::new(),
+        lines: Vec::new(),
+        position: None,
+    };
+    for param in &func.params {
+        c.declare(param.name);
+    }
+    c.stmts(body)?;
+    c.code.push(OpCode::ConstNull);
+    c.code.push(OpCode::Return);
+    Ok(CompiledFunc {
+        name: func.name.as_str().to_string(),
+        arity: func.params.len(),
+        locals: c.locals,
+        code: c.code,
+        constants: c.pool.into_constants(),
+        lines: c.lines,
+    })
+}
+
+/// Jump offsets to patch once the loop's end is known
+struct LoopLabels {
+    start: usize,
+    breaks: Vec<usize>,
+}
+
+struct Compiler<'a> {
+    resolve: &'a dyn Fn(Symbol) -> Option<(usize, usize)>,
+    code: Vec<OpCode>,
+    pool: ConstantPool,
+    /// Block scopes, innermost last, mapping names to local slots
+    scopes: Vec<FxHashMap<Symbol, usize>>,
+    locals: usize,
+    loops: Vec<LoopLabels>,
+    /// Line table so far, see `CompiledFunc::lines`
+    lines: Vec<(usize, usize, usize)>,
+    /// Line and column of the statement being compiled
+    position: Option<(usize, usize)>,
+}
+
+impl Compiler<'_> {
+    /// Every declaration gets its own slot, so shadowing in an inner block
+    /// never clobbers the outer variable
+    fn declare(&mut self, name: Symbol) -> usize {
+        let slot = self.locals;
+        self.locals += 1;
+        self.scopes.last_mut().unwrap().insert(name, slot);
+        slot
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|s| s.get(&name).copied())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        self.scopes.push(FxHashMap::default());
+        let result = self.stmts(stmts);
+        self.scopes.pop();
+        result
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        stmts.iter().try_for_each(|s| self.stmt(s))
+    }
+
+    /// Attribute the instructions from here on to the current statement
+    fn mark(&mut self) {
+        let Some((line, col)) = self.position else { return };
+        let start = self.code.len();
+        match self.lines.last_mut() {
+            Some(last) if (last.1, last.2) == (line, col) => {}
+            Some(last) if last.0 == start => *last = (start, line, col),
+            _ => self.lines.push((start, line, col)),
+        }
+    }
+
+    /// Emit a jump with a placeholder target, returning where to patch it
+    fn jump(&mut self, op: fn(usize) -> OpCode) -> usize {
+        self.code.push(op(usize::MAX));
+        self.code.len() - 1
+    }
+
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len();
+        self.code[at] = match self.code[at] {
+            OpCode::Jump(_) => OpCode::Jump(target),
+            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(target),
+            OpCode::JumpIfTrue(_) => OpCode::JumpIfTrue(target),
+            op => op,
+        };
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Let(_, Some(typ), _) if IntType::parse(typ).is_some() => return Err("has sized int annotations".to_string()),
+            Stmt::Let(name, _, expr) => {
+                self.expr(expr)?;
+                let slot = self.declare(*name);
+                self.code.push(OpCode::StoreLocal(slot));
+            }
+            Stmt::Assign(name, expr) => {
+                let slot = self.lookup(*name).ok_or_else(|| format!("assigns to global '{}'", name))?;
+                self.expr(expr)?;
+                self.code.push(OpCode::StoreLocal(slot));
+            }
+            Stmt::Return(expr) => {
+                match expr {
+                    Some(e) => self.expr(e)?,
+                    None => self.code.push(OpCode::ConstNull),
+                }
+                self.code.push(OpCode::Return);
+            }
+            Stmt::Print(expr) => {
+                // Through the host, so output goes wherever the interpreter's does
+                let name = self.pool.add_str("print");
+                self.expr(expr)?;
+                self.code.push(OpCode::CallHost(name, 1));
+                self.code.push(OpCode::Pop);
+            }
+            Stmt::If(cond, then_block, else_block) => {
+                self.expr(cond)?;
+                let to_else = self.jump(OpCode::JumpIfFalse);
+                self.block(then_block)?;
+                match else_block {
+                    Some(else_block) => {
+                        let to_end = self.jump(OpCode::Jump);
+                        self.patch(to_else);
+                        self.block(else_block)?;
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            Stmt::While(cond, body) => {
+                let start = self.code.len();
+                self.expr(cond)?;
+                let exit = self.jump(OpCode::JumpIfFalse);
+                self.loops.push(LoopLabels { start, breaks: vec![exit] });
+                self.block(body)?;
+                self.code.push(OpCode::Jump(start));
+                for at in self.loops.pop().unwrap().breaks {
+                    self.patch(at);
+                }
+            }
+            Stmt::Break => {
+                let at = self.jump(OpCode::Jump);
+                self.loops.last_mut().ok_or("break outside a loop")?.breaks.push(at);
+            }
+            Stmt::Continue => {
+                let start = self.loops.last().ok_or("continue outside a loop")?.start;
+                self.code.push(OpCode::Jump(start));
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr)?;
+                self.code.push(OpCode::Pop);
+            }
+            Stmt::Block(stmts) => self.block(stmts)?,
+            Stmt::IndexAssign(target, index, value) => {
+                self.expr(target)?;
+                self.expr(index)?;
+                self.expr(value)?;
+                self.code.push(OpCode::SetIndex);
+            }
+            Stmt::FieldAssign(target, field, value) => {
+                self.expr(target)?;
+                self.expr(value)?;
+                let name = self.pool.add_str(field.as_str());
+                self.code.push(OpCode::SetField(name));
+            }
+            Stmt::Defer(_) => return Err("uses defer".to_string()),
+            Stmt::Match(..) => return Err("uses match".to_string()),
+            Stmt::Try(..) | Stmt::Throw(_) => return Err("uses try/throw".to_string()),
+            Stmt::With(..) => return Err("uses with".to_string()),
+            Stmt::At(line, col, inner) => {
+                // Whatever the enclosing statement emits after this one is its own again
+                let outer = self.position.replace((*line, *col));
+                self.mark();
+                self.stmt(inner)?;
+                self.position = outer;
+                self.mark();
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::Number(n) => self.code.push(OpCode::Const(*n)),
+            Expr::String(s) => {
+                let idx = self.pool.add_str(s);
+                self.code.push(OpCode::ConstStr(idx));
+            }
+            Expr::Bool(true) => self.code.push(OpCode::ConstTrue),
+            Expr::Bool(false) => self.code.push(OpCode::ConstFalse),
+            Expr::Null => self.code.push(OpCode::ConstNull),
+            Expr::Identifier(name) => {
+                let slot = self.lookup(*name).ok_or_else(|| format!("reads global '{}'", name))?;
+                self.code.push(OpCode::LoadLocal(slot));
+            }
+            Expr::BinOp(left, op, right) => {
+                let op = match op.as_str() {
+                    "+" => OpCode::Add,
+                    "-" => OpCode::Sub,
+                    "*" => OpCode::Mul,
+                    "/" => OpCode::Div,
+                    "%" => OpCode::Mod,
+                    "<" => OpCode::Lt,
+                    ">" => OpCode::Gt,
+                    "<=" => OpCode::Le,
+                    ">=" => OpCode::Ge,
+                    "==" => OpCode::Eq,
+                    "!=" => OpCode::Ne,
+                    // Both sides are evaluated, as in the interpreter
+                    "&&" => OpCode::And,
+                    "||" => OpCode::Or,
+                    other => return Err(format!("uses operator {}", other)),
+                };
+                self.expr(left)?;
+                self.expr(right)?;
+                self.code.push(op);
+            }
+            Expr::UnaryOp(op, inner) => {
+                self.expr(inner)?;
+                self.code.push(if op == "!" { OpCode::Not } else { OpCode::Neg });
+            }
+            Expr::Call(name, args) => {
+                if self.lookup(*name).is_some() {
+                    return Err(format!("calls the local '{}'", name));
+                }
+                if SCOPE_BUILTINS.contains(&name.as_str()) || IDENTITY_BUILTINS.contains(&name.as_str()) {
+                    return Err(format!("calls {}()", name));
+                }
+                for arg in args {
+                    self.expr(arg)?;
+                }
+                match (self.resolve)(*name) {
+                    Some((idx, arity)) if arity == args.len() => self.code.push(OpCode::Call(idx, args.len())),
+                    _ => {
+                        let name = self.pool.add_str(name.as_str());
+                        self.code.push(OpCode::CallHost(name, args.len()));
+                    }
+                }
+            }
+            Expr::MethodCall(..) | Expr::StaticMethodCall(..) => return Err("calls a method".to_string()),
+            Expr::Index(target, index) => {
+                self.expr(target)?;
+                self.expr(index)?;
+                self.code.push(OpCode::Index);
+            }
+            Expr::Field(target, field) => {
+                self.expr(target)?;
+                let name = self.pool.add_str(field.as_str());
+                self.code.push(OpCode::GetField(name));
+            }
+            Expr::Array(items) => {
+                for item in items {
+                    self.expr(item)?;
+                }
+                self.code.push(OpCode::NewArray(items.len()));
+            }
+            Expr::StructInit(name, fields) => self.build_struct(name.as_str(), fields)?,
+            Expr::ObjectLiteral(fields) => self.build_struct("", fields)?,
+            Expr::MapLiteral(entries) => {
+                self.code.push(OpCode::NewMap);
+                for (key, value) in entries {
+                    let key = self.pool.add_str(key);
+                    self.code.push(OpCode::Dup);
+                    self.code.push(OpCode::ConstStr(key));
+                    self.expr(value)?;
+                    self.code.push(OpCode::SetIndex);
+                }
+            }
+            Expr::Await(_) => return Err("uses await".to_string()),
+            Expr::Float(_) => return Err("uses a float".to_string()),
+            Expr::Variant(..) => return Err("uses an enum".to_string()),
+            Expr::Match(..) => return Err("uses match".to_string()),
+            Expr::Propagate(_) => return Err("uses ?".to_string()),
+            Expr::At(_, inner) => self.expr(inner)?,
+        }
+        Ok(())
+    }
+
+    /// An empty struct, then each field set in order on a copy of the reference
+    fn build_struct(&mut self, name: &str, fields: &[(Symbol, Expr)]) -> Result<(), String> {
+        let name = self.pool.add_str(name);
+        self.code.push(OpCode::NewStruct(name));
+        for (field, value) in fields {
+            let field = self.pool.add_str(field.as_str());
+            self.code.push(OpCode::Dup);
+            self.expr(value)?;
+            self.code.push(OpCode::SetField(field));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode_vm::{BytecodeVM, VMValue, VmHost};
+
+    fn parse_fn(src: &str) -> Function {
+        let tokens = crate::lexer::tokenize(src);
+        match crate::parser::Parser::new(&tokens).parse().unwrap().remove(0) {
+            crate::parser::TopLevel::Function(f) => f,
+            other => panic!("expected a function, got {:?}", other),
+        }
+    }
+
+    struct Doubler(Vec<String>);
+
+    impl VmHost for Doubler {
+        fn call_host(&mut self, name: &str, args: Vec<VMValue>) -> Result<VMValue, String> {
+            self.0.push(name.to_string());
+            match args.first() {
+                Some(VMValue::Int(n)) => Ok(VMValue::Int(n * 2)),
+                _ => Err(format!("{}: expected an int", name)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_and_run() {
+        let func = parse_fn("@bytecode fn sum_to(n) { let total = 0; let i = 0; while (i < n) { i = i + 1; if (i % 2 == 0) { continue; } total = total + double(i); } return total; }");
+        assert!(is_annotated(&func));
+        let compiled = compile(&func, |_| None).unwrap();
+        assert!(compiled.calls_host());
+
+        let mut vm = BytecodeVM::new();
+        let idx = vm.add_function(compiled);
+        let mut host = Doubler(Vec::new());
+        // 2 * (1 + 3 + 5 + 7 + 9)
+        let result = vm.invoke(idx, vec![VMValue::Int(10)], &mut host).unwrap();
+        assert!(matches!(result, VMValue::Int(50)));
+        assert_eq!(host.0.len(), 5);
+
+        // A host error unwinds the VM, which stays usable
+        let fails = parse_fn("fn fails() { return double(\"x\"); }");
+        let fails = vm.add_function(compile(&fails, |_| None).unwrap());
+        assert!(vm.invoke(fails, vec![], &mut host).is_err());
+        assert!(matches!(vm.invoke(idx, vec![VMValue::Int(3)], &mut host), Ok(VMValue::Int(8))));
+    }
+
+    #[test]
+    fn test_error_position() {
+        let src = "fn f(n) {\n    let a = n + 1;\n    if (a > 1) {\n        return double(\"x\");\n    }\n    return a;\n}";
+        let (tokens, lines, columns, _) = crate::lexer::tokenize_with_positions(src);
+        let func = match crate::parser::Parser::with_lines(&tokens, &lines).with_columns(&columns).parse().unwrap().remove(0) {
+            crate::parser::TopLevel::Function(f) => f,
+            other => panic!("expected a function, got {:?}", other),
+        };
+        let compiled = compile(&func, |_| None).unwrap();
+        assert_eq!((compiled.position(0), compiled.position(compiled.code.len() - 1)), (Some((2, 5)), Some((6, 5))));
+
+        let mut vm = BytecodeVM::new();
+        let idx = vm.add_function(compiled);
+        assert!(vm.invoke(idx, vec![VMValue::Int(5)], &mut Doubler(Vec::new())).is_err());
+        assert_eq!(vm.error_position(), Some((4, 9)));
+    }
+
+    #[test]
+    fn test_direct_calls_and_rejections() {
+        let fib = parse_fn("fn fib(n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }");
+        let compiled = compile(&fib, |name| (name.as_str() == "fib").then_some((0, 1))).unwrap();
+        assert!(!compiled.calls_host());
+        let mut vm = BytecodeVM::new();
+        vm.add_function(compiled);
+        assert!(matches!(vm.call("fib", vec![VMValue::Int(15)]), VMValue::Int(610)));
+
+        let uses_global = parse_fn("fn f() { return LIMIT + 1; }");
+        assert_eq!(compile(&uses_global, |_| None).unwrap_err(), "reads global 'LIMIT'");
+        let freezes = parse_fn("fn f(a) { return freeze(a); }");
+        assert_eq!(compile(&freezes, |_| None).unwrap_err(), "calls freeze()");
+    }
+
+    #[test]
+    fn test_heap_values() {
+        let func = parse_fn(r#"fn f(n) {
+            let items = [1, 2];
+            items[n] = "x" + n;
+            let p = Point { x: items[-1], y: 0 };
+            p.y = { tag: "t" };
+            let m = { "k": [p.x, p.y.tag] };
+            m["z"] = len(items);
+            return [items, p.x == "x3", p.missing, m];
+        }"#);
+        let compiled = compile(&func, |_| None).unwrap();
+        assert!(compiled.indexes());
+        let mut vm = BytecodeVM::new();
+        let idx = vm.add_function(compiled);
+        let mut host = Lengths(Vec::new());
+        let result = vm.invoke(idx, vec![VMValue::Int(3)], &mut host).unwrap();
+        assert_eq!(result.to_string(), r#"[[1, 2, null, x3], true, null, {"k": [x3, t], "z": 4}]"#);
+        assert_eq!(host.0.len(), 1);
+        assert!(host.0[0].starts_with("Missing field 'missing'"));
+
+        // Reads past either end are errors, as in the interpreter
+        let past_end = parse_fn("fn f() { let a = [1]; return a[-2]; }");
+        let past_end = vm.add_function(compile(&past_end, |_| None).unwrap());
+        assert_eq!(vm.invoke(past_end, vec![], &mut host).unwrap_err(), "index -2 out of range for array of length 1");
+    }
+
+    /// Host with only `len`, recording runtime errors
+    struct Lengths(Vec<String>);
+
+    impl VmHost for Lengths {
+        fn call_host(&mut self, name: &str, args: Vec<VMValue>) -> Result<VMValue, String> {
+            match args.first() {
+                Some(VMValue::Array(items)) if name == "len" => Ok(VMValue::Int(items.borrow().len() as i64)),
+                _ => Err(format!("no host function {}", name)),
+            }
+        }
+
+        fn runtime_error(&mut self, message: &str) {
+            self.0.push(message.to_string());
+        }
+    }
+}
@@ -0,0 +1,507 @@
+// ============================================
+// Cryo Bytecode Files
+// `.arb` files: a whole program compiled for the bytecode VM, so it can
+// ship without its source and start without lexing or parsing.
+//
+//   cryo build app.cryo --emit=arb -o app.arb
+//   cryo app.arb
+//
+// Every function must compile (no globals, methods, enums, ...); builtins
+// still run in the interpreter the VM is hosted by.
+// ============================================
+
+use crate::bytecode_compiler;
+use crate::bytecode_vm::{CompiledFunc, Constant, ConstantPool, OpCode};
+use crate::intern::Symbol;
+use crate::parser::TopLevel;
+use rustc_hash::FxHashMap;
+use std::rc::Rc;
+
+/// First bytes of every `.arb` file
+const MAGIC: &[u8; 8] = b"CRYOARB\0";
+/// Bumped whenever the layout or the opcode or constant encoding changes
+const FORMAT_VERSION: u32 = 2;
+
+pub const EXTENSION: &str = "arb";
+
+/// Most locals (or parameters) a function may declare; the VM reserves
+/// stack slots for every one on each call
+const MAX_LOCALS: usize = 1 << 16;
+
+/// Compile every function in `ast` for the VM, in order, so `Call` indices
+/// are positions in the returned list. Fails on anything outside a
+/// function, or a function the VM can't run, naming it and why.
+pub fn compile_program(ast: &[TopLevel]) -> Result<Vec<CompiledFunc>, String> {
+    let mut funcs = Vec::new();
+    for item in ast {
+        match item {
+            TopLevel::Function(f) => funcs.push(f),
+            // Struct literals don't need the declaration, and macros are already expanded
+            TopLevel::Struct(_) | TopLevel::Macro(_) => {}
+            TopLevel::Let(name, _) => return Err(format!("global '{}' can't be precompiled", name)),
+            TopLevel::Import(path, _) => return Err(format!("import \"{}\" can't be precompiled; use `cryo bundle`", path)),
+            TopLevel::Enum(e) => return Err(format!("enum {} can't be precompiled", e.name)),
+            TopLevel::Trait(t) => return Err(format!("trait {} can't be precompiled", t.name)),
+            TopLevel::Impl(i) => return Err(format!("impl {} can't be precompiled", i.type_name)),
+            TopLevel::Extern(_) => return Err("extern blocks can't be precompiled".to_string()),
+        }
+    }
+    if !funcs.iter().any(|f| f.name == Symbol::MAIN) {
+        return Err("no main function".to_string());
+    }
+    let slots: FxHashMap<Symbol, (usize, usize)> = funcs.iter().enumerate().map(|(i, f)| (f.name, (i, f.params.len()))).collect();
+    funcs
+        .iter()
+        .map(|f| bytecode_compiler::compile(f, |name| slots.get(&name).copied()).map_err(|e| format!("fn {}: {}", f.name, e)))
+        .collect()
+}
+
+/// Whether `data` starts like an `.arb` file
+pub fn is_bytecode(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn encode(funcs: &[CompiledFunc]) -> Vec<u8> {
+    let mut w = Writer { buf: MAGIC.to_vec() };
+    w.u32(FORMAT_VERSION);
+    w.u32(funcs.len() as u32);
+    for func in funcs {
+        w.str(&func.name);
+        w.u32(func.arity as u32);
+        w.u32(func.locals as u32);
+        w.u32(func.constants.len() as u32);
+        for constant in &func.constants {
+            w.constant(constant);
+        }
+        w.u32(func.code.len() as u32);
+        for op in &func.code {
+            w.op(*op);
+        }
+        w.u32(func.lines.len() as u32);
+        for &(start, line, col) in &func.lines {
+            w.u32(start as u32);
+            w.u32(line as u32);
+            w.u32(col as u32);
+        }
+    }
+    w.buf
+}
+
+/// Read an `.arb` file back. Every operand is checked against the function
+/// and constant tables, so a damaged file is an error rather than a crash.
+pub fn decode(data: &[u8]) -> Result<Vec<CompiledFunc>, String> {
+    if !is_bytecode(data) {
+        return Err("not a Cryo bytecode file".to_string());
+    }
+    let mut r = Reader { data, pos: MAGIC.len() };
+    let version = r.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(format!("bytecode format {} is not supported by this runtime (expected {}); rebuild it", version, FORMAT_VERSION));
+    }
+    let mut funcs = Vec::new();
+    for _ in 0..r.u32()? {
+        let name = r.string()?;
+        let arity = r.u32()? as usize;
+        let locals = r.u32()? as usize;
+        if arity > MAX_LOCALS || locals > MAX_LOCALS {
+            return Err(format!("bytecode file: fn {} has too many locals", name));
+        }
+        let mut pool = ConstantPool::new();
+        for _ in 0..r.u32()? {
+            // Through the pool so the table comes back exactly as written
+            let constant = r.constant()?;
+            if pool.add(constant) != pool.len() - 1 {
+                return Err(format!("bytecode file: fn {} repeats a constant", name));
+            }
+        }
+        let mut code = Vec::new();
+        for _ in 0..r.u32()? {
+            code.push(r.op()?);
+        }
+        let mut lines: Vec<(usize, usize, usize)> = Vec::new();
+        for _ in 0..r.u32()? {
+            let entry = (r.index()?, r.index()?, r.index()?);
+            if lines.last().is_some_and(|last| last.0 > entry.0) {
+                return Err(format!("bytecode file: fn {} has its line table out of order", name));
+            }
+            lines.push(entry);
+        }
+        funcs.push(CompiledFunc { name, arity, locals, code, constants: pool.into_constants(), lines });
+    }
+    if r.pos != data.len() {
+        return Err("bytecode file has trailing bytes".to_string());
+    }
+    for func in &funcs {
+        check(func, &funcs)?;
+    }
+    Ok(funcs)
+}
+
+fn check(func: &CompiledFunc, funcs: &[CompiledFunc]) -> Result<(), String> {
+    let bad = |at: usize, op: &OpCode| Err(format!("bytecode file: fn {} has a bad operand at {}: {:?}", func.name, at, op));
+    let is_str = |idx: usize| matches!(func.constants.get(idx), Some(Constant::Str(_)));
+    for (at, op) in func.code.iter().enumerate() {
+        let ok = match *op {
+            OpCode::LoadConst(idx) => idx < func.constants.len(),
+            OpCode::ConstStr(idx) | OpCode::NewStruct(idx) | OpCode::GetField(idx) | OpCode::SetField(idx) | OpCode::CallHost(idx, _) => is_str(idx),
+            OpCode::LoadLocal(slot) | OpCode::StoreLocal(slot) => slot < func.locals.max(func.arity),
+            OpCode::Jump(target) | OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) => target <= func.code.len(),
+            OpCode::Call(idx, argc) => funcs.get(idx).is_some_and(|callee| callee.arity == argc),
+            _ => true,
+        };
+        if !ok {
+            return bad(at, op);
+        }
+    }
+    check_stack(func)
+}
+
+/// Follow every path through `func` keeping count of the values on its
+/// stack: no instruction may pop more than is there, and wherever paths
+/// meet they must agree, so a loop can't grow the stack either.
+fn check_stack(func: &CompiledFunc) -> Result<(), String> {
+    let mut depth_at: Vec<Option<usize>> = vec![None; func.code.len() + 1];
+    let mut pending = vec![(0, 0)];
+    while let Some((at, depth)) = pending.pop() {
+        match depth_at[at] {
+            Some(seen) if seen == depth => continue,
+            Some(seen) => return Err(format!("bytecode file: fn {} reaches {} with {} values on the stack and with {}", func.name, at, seen, depth)),
+            None => depth_at[at] = Some(depth),
+        }
+        // Falling off the end returns null
+        let Some(&op) = func.code.get(at) else { continue };
+        let (pops, pushes) = stack_effect(op);
+        if depth < pops {
+            return Err(format!("bytecode file: fn {} pops an empty stack at {}: {:?}", func.name, at, op));
+        }
+        let depth = depth - pops + pushes;
+        match op {
+            OpCode::Return | OpCode::Halt => {}
+            OpCode::Jump(target) => pending.push((target, depth)),
+            OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) => {
+                pending.push((target, depth));
+                pending.push((at + 1, depth));
+            }
+            _ => pending.push((at + 1, depth)),
+        }
+    }
+    Ok(())
+}
+
+/// How many values `op` takes off the stack and how many it leaves
+fn stack_effect(op: OpCode) -> (usize, usize) {
+    match op {
+        OpCode::Const(_) | OpCode::LoadConst(_) | OpCode::ConstTrue | OpCode::ConstFalse | OpCode::ConstNull
+        | OpCode::ConstStr(_) | OpCode::NewStruct(_) | OpCode::NewMap | OpCode::LoadLocal(_) => (0, 1),
+        OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div | OpCode::Mod
+        | OpCode::Lt | OpCode::Gt | OpCode::Le | OpCode::Ge | OpCode::Eq | OpCode::Ne
+        | OpCode::And | OpCode::Or | OpCode::Index => (2, 1),
+        OpCode::Neg | OpCode::Not | OpCode::GetField(_) => (1, 1),
+        OpCode::Jump(_) | OpCode::Halt => (0, 0),
+        OpCode::JumpIfFalse(_) | OpCode::JumpIfTrue(_) | OpCode::StoreLocal(_) | OpCode::Pop | OpCode::Print => (1, 0),
+        OpCode::Call(_, argc) | OpCode::CallHost(_, argc) | OpCode::NewArray(argc) => (argc, 1),
+        OpCode::Return => (1, 0),
+        OpCode::SetIndex => (3, 0),
+        OpCode::SetField(_) => (2, 0),
+        OpCode::Dup => (1, 2),
+    }
+}
+
+// ---- Encoding ----
+// Little-endian fields, one tag byte per opcode or constant, in declaration
+// order in bytecode_vm.rs
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i64(&mut self, v: i64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn constant(&mut self, constant: &Constant) {
+        match constant {
+            Constant::Int(n) => {
+                self.u8(0);
+                self.i64(*n);
+            }
+            Constant::Float(f) => {
+                self.u8(1);
+                self.i64(f.to_bits() as i64);
+            }
+            Constant::Str(s) => {
+                self.u8(2);
+                self.str(s);
+            }
+            Constant::Array(items) => {
+                self.u8(3);
+                self.u32(items.len() as u32);
+                for item in items {
+                    self.constant(item);
+                }
+            }
+        }
+    }
+
+    fn op(&mut self, op: OpCode) {
+        let (tag, operands): (u8, &[usize]) = match op {
+            OpCode::Const(n) => {
+                self.u8(0);
+                self.i64(n);
+                return;
+            }
+            OpCode::LoadConst(i) => (1, &[i]),
+            OpCode::ConstTrue => (2, &[]),
+            OpCode::ConstFalse => (3, &[]),
+            OpCode::ConstNull => (4, &[]),
+            OpCode::Add => (5, &[]),
+            OpCode::Sub => (6, &[]),
+            OpCode::Mul => (7, &[]),
+            OpCode::Div => (8, &[]),
+            OpCode::Mod => (9, &[]),
+            OpCode::Neg => (10, &[]),
+            OpCode::Lt => (11, &[]),
+            OpCode::Gt => (12, &[]),
+            OpCode::Le => (13, &[]),
+            OpCode::Ge => (14, &[]),
+            OpCode::Eq => (15, &[]),
+            OpCode::Ne => (16, &[]),
+            OpCode::Not => (17, &[]),
+            OpCode::And => (18, &[]),
+            OpCode::Or => (19, &[]),
+            OpCode::Jump(t) => (20, &[t]),
+            OpCode::JumpIfFalse(t) => (21, &[t]),
+            OpCode::JumpIfTrue(t) => (22, &[t]),
+            OpCode::LoadLocal(s) => (23, &[s]),
+            OpCode::StoreLocal(s) => (24, &[s]),
+            OpCode::Call(f, n) => {
+                self.u8(25);
+                self.u32(f as u32);
+                self.u32(n as u32);
+                return;
+            }
+            OpCode::CallHost(f, n) => {
+                self.u8(26);
+                self.u32(f as u32);
+                self.u32(n as u32);
+                return;
+            }
+            OpCode::Return => (27, &[]),
+            OpCode::ConstStr(i) => (28, &[i]),
+            OpCode::NewArray(n) => (29, &[n]),
+            OpCode::NewStruct(i) => (30, &[i]),
+            OpCode::NewMap => (31, &[]),
+            OpCode::Index => (32, &[]),
+            OpCode::SetIndex => (33, &[]),
+            OpCode::GetField(i) => (34, &[i]),
+            OpCode::SetField(i) => (35, &[i]),
+            OpCode::Pop => (36, &[]),
+            OpCode::Dup => (37, &[]),
+            OpCode::Print => (38, &[]),
+            OpCode::Halt => (39, &[]),
+        };
+        self.u8(tag);
+        for &operand in operands {
+            self.u32(operand as u32);
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+const TRUNCATED: &str = "bytecode file is truncated";
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len()).ok_or(TRUNCATED)?;
+        let out = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().map_err(|_| TRUNCATED)?))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.bytes(8)?.try_into().map_err(|_| TRUNCATED)?))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| "bytecode file has invalid UTF-8".to_string())
+    }
+
+    fn index(&mut self) -> Result<usize, String> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn constant(&mut self) -> Result<Constant, String> {
+        Ok(match self.u8()? {
+            0 => Constant::Int(self.i64()?),
+            1 => Constant::Float(f64::from_bits(self.i64()? as u64)),
+            2 => Constant::Str(Rc::from(self.string()?)),
+            3 => {
+                let len = self.u32()? as usize;
+                // Every element takes at least a byte, which bounds a corrupt length
+                let mut items = Vec::with_capacity(len.min(self.data.len() - self.pos));
+                for _ in 0..len {
+                    items.push(self.constant()?);
+                }
+                Constant::Array(items)
+            }
+            tag => return Err(format!("bytecode file: unknown constant tag {}", tag)),
+        })
+    }
+
+    fn op(&mut self) -> Result<OpCode, String> {
+        Ok(match self.u8()? {
+            0 => OpCode::Const(self.i64()?),
+            1 => OpCode::LoadConst(self.index()?),
+            2 => OpCode::ConstTrue,
+            3 => OpCode::ConstFalse,
+            4 => OpCode::ConstNull,
+            5 => OpCode::Add,
+            6 => OpCode::Sub,
+            7 => OpCode::Mul,
+            8 => OpCode::Div,
+            9 => OpCode::Mod,
+            10 => OpCode::Neg,
+            11 => OpCode::Lt,
+            12 => OpCode::Gt,
+            13 => OpCode::Le,
+            14 => OpCode::Ge,
+            15 => OpCode::Eq,
+            16 => OpCode::Ne,
+            17 => OpCode::Not,
+            18 => OpCode::And,
+            19 => OpCode::Or,
+            20 => OpCode::Jump(self.index()?),
+            21 => OpCode::JumpIfFalse(self.index()?),
+            22 => OpCode::JumpIfTrue(self.index()?),
+            23 => OpCode::LoadLocal(self.index()?),
+            24 => OpCode::StoreLocal(self.index()?),
+            25 => OpCode::Call(self.index()?, self.index()?),
+            26 => OpCode::CallHost(self.index()?, self.index()?),
+            27 => OpCode::Return,
+            28 => OpCode::ConstStr(self.index()?),
+            29 => OpCode::NewArray(self.index()?),
+            30 => OpCode::NewStruct(self.index()?),
+            31 => OpCode::NewMap,
+            32 => OpCode::Index,
+            33 => OpCode::SetIndex,
+            34 => OpCode::GetField(self.index()?),
+            35 => OpCode::SetField(self.index()?),
+            36 => OpCode::Pop,
+            37 => OpCode::Dup,
+            38 => OpCode::Print,
+            39 => OpCode::Halt,
+            tag => return Err(format!("bytecode file: unknown opcode tag {}", tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode_vm::{BytecodeVM, VMValue, VmHost};
+
+    fn compile(source: &str) -> Result<Vec<CompiledFunc>, String> {
+        let tokens = crate::lexer::tokenize(source);
+        compile_program(&crate::parser::Parser::new(&tokens).parse()?)
+    }
+
+    struct Silent;
+
+    impl VmHost for Silent {
+        fn call_host(&mut self, name: &str, _args: Vec<VMValue>) -> Result<VMValue, String> {
+            Err(format!("no host function {}", name))
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let funcs = compile(r#"
+            struct Point { x: int, y: int }
+            fn fib(n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }
+            fn main() {
+                let p = Point { x: fib(10), y: -1 };
+                let m = { "k": [p.x, "s"] };
+                return "" + p.x + " " + m["k"][1];
+            }
+        "#).unwrap();
+        let encoded = encode(&funcs);
+        assert!(is_bytecode(&encoded));
+        let decoded = decode(&encoded).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", funcs));
+
+        let mut vm = BytecodeVM::new();
+        for func in decoded {
+            vm.add_function(func);
+        }
+        assert_eq!(vm.invoke(1, vec![], &mut Silent).unwrap().to_string(), "55 s");
+
+        assert!(decode(&encoded[..encoded.len() - 1]).is_err());
+        let mut newer = encoded.clone();
+        newer[MAGIC.len()] = 99;
+        assert!(decode(&newer).unwrap_err().contains("rebuild"));
+        assert_eq!(decode(b"fn main() {}").unwrap_err(), "not a Cryo bytecode file");
+    }
+
+    #[test]
+    fn test_checks_operands() {
+        let mut funcs = compile("fn main() { let a = 1; return a; }").unwrap();
+        funcs[0].code.insert(0, OpCode::LoadLocal(7));
+        assert!(decode(&encode(&funcs)).unwrap_err().contains("bad operand at 0"));
+    }
+
+    #[test]
+    fn test_checks_stack_depth() {
+        let funcs = compile("fn add(a, b) { return a + b; } fn main() { return add(1, 2); }").unwrap();
+        let encoded = encode(&funcs);
+        assert!(decode(&encoded).is_ok());
+
+        // One argument fewer than `add` takes leaves Call popping past the frame
+        let mut short = funcs.clone();
+        let at = short[1].code.iter().position(|op| matches!(op, OpCode::Call(..))).unwrap();
+        short[1].code.remove(at - 1);
+        assert!(decode(&encode(&short)).unwrap_err().contains("pops an empty stack"));
+
+        // A loop that pushes on every trip
+        let mut growing = funcs.clone();
+        growing[1].code = vec![OpCode::Const(1), OpCode::Jump(0)];
+        assert!(decode(&encode(&growing)).unwrap_err().contains("values on the stack"));
+
+        let mut huge = funcs;
+        huge[1].locals = u32::MAX as usize;
+        assert!(decode(&encode(&huge)).unwrap_err().contains("too many locals"));
+    }
+
+    #[test]
+    fn test_rejects_what_the_vm_cannot_run() {
+        assert_eq!(compile("let LIMIT = 3; fn main() {}").unwrap_err(), "global 'LIMIT' can't be precompiled");
+        assert_eq!(compile("fn helper() {}").unwrap_err(), "no main function");
+        assert_eq!(compile("fn main() { defer print(1); }").unwrap_err(), "fn main: uses defer");
+    }
+}
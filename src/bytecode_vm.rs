@@ -3,13 +3,19 @@
 
 #![allow(dead_code)]
 
+use crate::intern::Symbol;
 use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
 /// Bytecode instructions for the VM
 #[derive(Debug, Clone, Copy)]
 pub enum OpCode {
     // Stack operations
     Const(i64),          // Push constant integer
+    LoadConst(usize),    // Push entry from the function's constant pool
     ConstTrue,           // Push true
     ConstFalse,          // Push false
     ConstNull,           // Push null
@@ -46,8 +52,19 @@ pub enum OpCode {
     
     // Function calls
     Call(usize, usize),  // Call function at index with N args
+    CallHost(usize, usize), // Call the host function named by a constant with N args
     Return,              // Return from function
     
+    // Heap values
+    ConstStr(usize),     // Push the string constant at a pool index
+    NewArray(usize),     // Pop N values into a new array
+    NewStruct(usize),    // Push an empty struct named by a string constant ("" for an object literal)
+    NewMap,              // Push an empty hash map
+    Index,               // Pop index and container, push the element
+    SetIndex,            // Pop value, index and container; store the element
+    GetField(usize),     // Pop a struct, push the field named by a string constant
+    SetField(usize),     // Pop value and struct; set the field named by a string constant
+    
     // Stack management
     Pop,                 // Pop top of stack
     Dup,                 // Duplicate top of stack
@@ -57,12 +74,70 @@ pub enum OpCode {
     Halt,                // Stop execution
 }
 
-/// Stack-based value for VM
+impl OpCode {
+    /// Mnemonic without operands, used as the key in profiles
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpCode::Const(_) => "Const",
+            OpCode::LoadConst(_) => "LoadConst",
+            OpCode::ConstTrue => "ConstTrue",
+            OpCode::ConstFalse => "ConstFalse",
+            OpCode::ConstNull => "ConstNull",
+            OpCode::Add => "Add",
+            OpCode::Sub => "Sub",
+            OpCode::Mul => "Mul",
+            OpCode::Div => "Div",
+            OpCode::Mod => "Mod",
+            OpCode::Neg => "Neg",
+            OpCode::Lt => "Lt",
+            OpCode::Gt => "Gt",
+            OpCode::Le => "Le",
+            OpCode::Ge => "Ge",
+            OpCode::Eq => "Eq",
+            OpCode::Ne => "Ne",
+            OpCode::Not => "Not",
+            OpCode::And => "And",
+            OpCode::Or => "Or",
+            OpCode::Jump(_) => "Jump",
+            OpCode::JumpIfFalse(_) => "JumpIfFalse",
+            OpCode::JumpIfTrue(_) => "JumpIfTrue",
+            OpCode::LoadLocal(_) => "LoadLocal",
+            OpCode::StoreLocal(_) => "StoreLocal",
+            OpCode::Call(_, _) => "Call",
+            OpCode::CallHost(_, _) => "CallHost",
+            OpCode::Return => "Return",
+            OpCode::ConstStr(_) => "ConstStr",
+            OpCode::NewArray(_) => "NewArray",
+            OpCode::NewStruct(_) => "NewStruct",
+            OpCode::NewMap => "NewMap",
+            OpCode::Index => "Index",
+            OpCode::SetIndex => "SetIndex",
+            OpCode::GetField(_) => "GetField",
+            OpCode::SetField(_) => "SetField",
+            OpCode::Pop => "Pop",
+            OpCode::Dup => "Dup",
+            OpCode::Print => "Print",
+            OpCode::Halt => "Halt",
+        }
+    }
+}
+
+/// Fields of a struct or hash map, shared like the interpreter's
+pub type Fields = Rc<RefCell<HashMap<String, VMValue>>>;
+
+/// Stack-based value for VM. Arrays, structs and maps are shared by
+/// reference, as they are in the interpreter.
 #[derive(Debug, Clone)]
 pub enum VMValue {
     Null,
     Bool(bool),
     Int(i64),
+    Float(f64),
+    Str(Rc<str>),
+    Array(Rc<RefCell<Vec<VMValue>>>),
+    /// A struct, or an object literal when the name is empty
+    Struct(Symbol, Fields),
+    Map(Fields),
 }
 
 impl VMValue {
@@ -71,7 +146,8 @@ impl VMValue {
         match self {
             VMValue::Int(n) => *n,
             VMValue::Bool(b) => if *b { 1 } else { 0 },
-            VMValue::Null => 0,
+            VMValue::Float(f) => *f as i64,
+            VMValue::Null | VMValue::Str(_) | VMValue::Array(_) | VMValue::Struct(..) | VMValue::Map(_) => 0,
         }
     }
     
@@ -81,10 +157,136 @@ impl VMValue {
             VMValue::Null => false,
             VMValue::Bool(b) => *b,
             VMValue::Int(n) => *n != 0,
+            VMValue::Float(f) => *f != 0.0,
+            VMValue::Str(s) => !s.is_empty(),
+            VMValue::Array(arr) => !arr.borrow().is_empty(),
+            VMValue::Map(map) => !map.borrow().is_empty(),
+            VMValue::Struct(..) => true,
+        }
+    }
+}
+
+impl std::fmt::Display for VMValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VMValue::Null => write!(f, "null"),
+            VMValue::Bool(b) => write!(f, "{}", b),
+            VMValue::Int(n) => write!(f, "{}", n),
+            VMValue::Float(x) => write!(f, "{}", crate::text::format_float(*x)),
+            VMValue::Str(s) => write!(f, "{}", s),
+            VMValue::Array(arr) => {
+                let items: Vec<String> = arr.borrow().iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            VMValue::Struct(name, fields) => {
+                let items: Vec<String> = fields.borrow().iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{} {{ {} }}", name, items.join(", "))
+            }
+            VMValue::Map(map) => {
+                // Sorted by key, as the interpreter prints maps
+                let map = map.borrow();
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                let items: Vec<String> = keys.into_iter().map(|k| format!("{}: {}", crate::json::quote(k), map[k])).collect();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+        }
+    }
+}
+
+/// Literal stored in a constant pool and referenced by `OpCode::LoadConst`
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Str(Rc<str>),
+    Array(Vec<Constant>),
+}
+
+// Floats compare by bit pattern so the pool can deduplicate them (NaN == NaN, 0.0 != -0.0)
+impl PartialEq for Constant {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Constant::Int(a), Constant::Int(b)) => a == b,
+            (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+            (Constant::Str(a), Constant::Str(b)) => a == b,
+            (Constant::Array(a), Constant::Array(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Constant {}
+
+impl Hash for Constant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Constant::Int(n) => n.hash(state),
+            Constant::Float(f) => f.to_bits().hash(state),
+            Constant::Str(s) => s.hash(state),
+            Constant::Array(items) => items.hash(state),
         }
     }
 }
 
+impl Constant {
+    /// Materialize as a runtime value. Strings share the pooled allocation;
+    /// arrays are mutable, so each load gets a fresh copy.
+    fn to_value(&self) -> VMValue {
+        match self {
+            Constant::Int(n) => VMValue::Int(*n),
+            Constant::Float(f) => VMValue::Float(*f),
+            Constant::Str(s) => VMValue::Str(s.clone()),
+            Constant::Array(items) => {
+                VMValue::Array(Rc::new(RefCell::new(items.iter().map(|c| c.to_value()).collect())))
+            }
+        }
+    }
+}
+
+/// Compile-time builder for a function's constant table.
+/// Identical literals (including equal strings) share one slot.
+#[derive(Debug, Default)]
+pub struct ConstantPool {
+    constants: Vec<Constant>,
+    index: FxHashMap<Constant, usize>,
+}
+
+impl ConstantPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Add a constant, returning the index of the existing entry if it was already pooled
+    pub fn add(&mut self, constant: Constant) -> usize {
+        if let Some(&idx) = self.index.get(&constant) {
+            return idx;
+        }
+        let idx = self.constants.len();
+        self.constants.push(constant.clone());
+        self.index.insert(constant, idx);
+        idx
+    }
+    
+    pub fn add_str(&mut self, s: &str) -> usize {
+        self.add(Constant::Str(Rc::from(s)))
+    }
+    
+    pub fn len(&self) -> usize {
+        self.constants.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+    
+    /// Finish building; the table is stored on the `CompiledFunc`
+    pub fn into_constants(self) -> Vec<Constant> {
+        self.constants
+    }
+}
+
 /// Compiled function
 #[derive(Debug, Clone)]
 pub struct CompiledFunc {
@@ -92,8 +294,54 @@ pub struct CompiledFunc {
     pub arity: usize,
     pub locals: usize,
     pub code: Vec<OpCode>,
+    pub constants: Vec<Constant>,
+    /// Line table: `(first instruction, line, column)` for each run of
+    /// instructions compiled from one statement, in code order
+    pub lines: Vec<(usize, usize, usize)>,
+}
+
+impl CompiledFunc {
+    /// Line and column of the statement instruction `ip` was compiled from
+    pub fn position(&self, ip: usize) -> Option<(usize, usize)> {
+        let at = self.lines.partition_point(|&(start, _, _)| start <= ip);
+        at.checked_sub(1).map(|i| (self.lines[i].1, self.lines[i].2))
+    }
+    
+    /// Whether running this function can leave the VM (`CallHost`)
+    pub fn calls_host(&self) -> bool {
+        self.code.iter().any(|op| matches!(op, OpCode::CallHost(_, _)))
+    }
+    
+    /// Whether this function reads or writes inside arrays, strings, structs or maps
+    pub fn indexes(&self) -> bool {
+        self.code.iter().any(|op| matches!(op, OpCode::Index | OpCode::SetIndex | OpCode::GetField(_) | OpCode::SetField(_)))
+    }
+}
+
+/// Whoever embeds the VM; `OpCode::CallHost` hands it the function name and
+/// arguments and pushes whatever it returns
+pub trait VmHost {
+    fn call_host(&mut self, name: &str, args: Vec<VMValue>) -> Result<VMValue, String>;
+    
+    /// A runtime error that doesn't stop the program, like reading a missing
+    /// field (which gives null)
+    fn runtime_error(&mut self, message: &str) {
+        crate::output::write_line(&format!("Runtime Error: {}", message));
+    }
 }
 
+/// Host for standalone runs, where bytecode has nothing outside to call
+pub struct NoHost;
+
+impl VmHost for NoHost {
+    fn call_host(&mut self, name: &str, _args: Vec<VMValue>) -> Result<VMValue, String> {
+        Err(format!("{}() is not available to bytecode without a host", name))
+    }
+}
+
+/// Nested bytecode calls beyond this depth fail instead of exhausting memory
+const MAX_FRAMES: usize = 100_000;
+
 /// Call frame for function calls
 struct CallFrame {
     func_idx: usize,
@@ -101,254 +349,623 @@ struct CallFrame {
     bp: usize,  // Base pointer for locals
 }
 
+/// A call in progress, handed over from native code that gave up on it
+/// (see jit.rs). `ip` is the next instruction to run; `stack` is the frame's
+/// operand stack, which for a caller excludes the arguments it passed.
+#[derive(Debug, Clone)]
+pub struct ResumeFrame {
+    pub func_idx: usize,
+    pub ip: usize,
+    pub locals: Vec<VMValue>,
+    pub stack: Vec<VMValue>,
+}
+
+/// Execution counters collected when profiling is enabled (`--vm-profile`)
+#[derive(Debug, Default)]
+pub struct VmProfile {
+    /// Executed instructions per opcode mnemonic
+    pub op_counts: FxHashMap<&'static str, u64>,
+    /// Executed instructions per function index
+    pub func_instructions: Vec<u64>,
+    /// Calls per function index
+    pub func_calls: Vec<u64>,
+    /// Executions per (function index, bytecode offset)
+    pub offset_counts: FxHashMap<(usize, usize), u64>,
+}
+
+impl VmProfile {
+    #[inline]
+    fn record(&mut self, func_idx: usize, ip: usize, op: OpCode) {
+        *self.op_counts.entry(op.name()).or_insert(0) += 1;
+        *self.offset_counts.entry((func_idx, ip)).or_insert(0) += 1;
+        if self.func_instructions.len() <= func_idx {
+            self.func_instructions.resize(func_idx + 1, 0);
+        }
+        self.func_instructions[func_idx] += 1;
+    }
+    
+    #[inline]
+    fn record_call(&mut self, func_idx: usize) {
+        if self.func_calls.len() <= func_idx {
+            self.func_calls.resize(func_idx + 1, 0);
+        }
+        self.func_calls[func_idx] += 1;
+    }
+    
+    pub fn total_instructions(&self) -> u64 {
+        self.op_counts.values().sum()
+    }
+    
+    /// The `limit` most executed (function index, offset, count) triples
+    pub fn hottest_offsets(&self, limit: usize) -> Vec<(usize, usize, u64)> {
+        let mut offsets: Vec<(usize, usize, u64)> = self.offset_counts
+            .iter()
+            .map(|(&(f, ip), &n)| (f, ip, n))
+            .collect();
+        offsets.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)).then(a.1.cmp(&b.1)));
+        offsets.truncate(limit);
+        offsets
+    }
+}
+
 /// Bytecode Virtual Machine
 pub struct BytecodeVM {
-    functions: Vec<CompiledFunc>,
-    func_map: FxHashMap<String, usize>,
+    // Shared with VMs made by `share`, so a host can re-enter bytecode while
+    // this VM is mid-call
+    functions: Rc<Vec<CompiledFunc>>,
+    func_map: Rc<FxHashMap<String, usize>>,
     stack: Vec<VMValue>,
     frames: Vec<CallFrame>,
-    ip: usize,
-    bp: usize,
+    profile: Option<VmProfile>,
+    /// Function and instruction the last error came from
+    fault: Option<(usize, usize)>,
 }
 
 impl BytecodeVM {
     pub fn new() -> Self {
         BytecodeVM {
-            functions: Vec::new(),
-            func_map: FxHashMap::default(),
+            functions: Rc::new(Vec::new()),
+            func_map: Rc::new(FxHashMap::default()),
             stack: Vec::with_capacity(4096),
             frames: Vec::with_capacity(256),
-            ip: 0,
-            bp: 0,
+            profile: None,
+            fault: None,
         }
     }
     
-    pub fn add_function(&mut self, func: CompiledFunc) {
+    /// Start counting executed instructions and calls
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(VmProfile::default());
+    }
+    
+    pub fn profile(&self) -> Option<&VmProfile> {
+        self.profile.as_ref()
+    }
+    
+    /// Call counts per function name, for seeding the JIT's tiering decisions
+    pub fn profiled_call_counts(&self) -> Vec<(String, u64)> {
+        let Some(profile) = &self.profile else {
+            return Vec::new();
+        };
+        profile.func_calls
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(idx, &n)| (self.functions[idx].name.clone(), n))
+            .collect()
+    }
+    
+    /// Human-readable profile: opcode mix, per-function totals and the hottest offsets
+    pub fn profile_report(&self, top: usize) -> String {
+        let Some(profile) = &self.profile else {
+            return "VM profiling was not enabled".to_string();
+        };
+        let total = profile.total_instructions().max(1);
+        let mut out = format!("=== VM Profile: {} instructions ===\n", profile.total_instructions());
+        
+        out.push_str("-- Opcodes --\n");
+        let mut ops: Vec<(&str, u64)> = profile.op_counts.iter().map(|(k, v)| (*k, *v)).collect();
+        ops.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        for (name, n) in ops {
+            out.push_str(&format!("{:<12} {:>12} {:>6.2}%\n", name, n, n as f64 * 100.0 / total as f64));
+        }
+        
+        out.push_str("-- Functions --\n");
+        for (idx, func) in self.functions.iter().enumerate() {
+            let instrs = profile.func_instructions.get(idx).copied().unwrap_or(0);
+            let calls = profile.func_calls.get(idx).copied().unwrap_or(0);
+            if instrs > 0 || calls > 0 {
+                out.push_str(&format!("{:<16} calls={:<10} instructions={}\n", func.name, calls, instrs));
+            }
+        }
+        
+        out.push_str("-- Hottest offsets --\n");
+        for (func_idx, ip, n) in profile.hottest_offsets(top) {
+            let func = &self.functions[func_idx];
+            out.push_str(&format!("{}+{:<4} {:<20} {}\n", func.name, ip, format!("{:?}", func.code[ip]), n));
+        }
+        out
+    }
+    
+    /// Add a function, returning its index for `OpCode::Call`
+    pub fn add_function(&mut self, func: CompiledFunc) -> usize {
         let idx = self.functions.len();
-        self.func_map.insert(func.name.clone(), idx);
-        self.functions.push(func);
+        Rc::make_mut(&mut self.func_map).insert(func.name.clone(), idx);
+        Rc::make_mut(&mut self.functions).push(func);
+        idx
     }
     
-    #[inline]
-    fn push(&mut self, val: VMValue) {
-        self.stack.push(val);
+    pub fn function_count(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn functions(&self) -> &[CompiledFunc] {
+        &self.functions
     }
     
-    #[inline]
-    fn pop(&mut self) -> VMValue {
-        self.stack.pop().unwrap_or(VMValue::Null)
+    /// Fresh VM over the same functions, with its own stack and no profile
+    pub fn share(&self) -> BytecodeVM {
+        BytecodeVM {
+            functions: self.functions.clone(),
+            func_map: self.func_map.clone(),
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(16),
+            profile: None,
+            fault: None,
+        }
     }
     
-    #[inline]
-    fn peek(&self) -> &VMValue {
-        self.stack.last().unwrap()
+    /// Line and column of the statement the last error came from, when its
+    /// function has a line table
+    pub fn error_position(&self) -> Option<(usize, usize)> {
+        let (func_idx, ip) = self.fault?;
+        self.functions.get(func_idx)?.position(ip)
     }
     
     pub fn call(&mut self, func_name: &str, args: Vec<VMValue>) -> VMValue {
         let func_idx = *self.func_map.get(func_name).expect("Function not found");
-        let func = &self.functions[func_idx];
+        self.invoke(func_idx, args, &mut NoHost).expect("VM error")
+    }
+    
+    /// Run function `func_idx`. Missing arguments are null and extra ones are
+    /// dropped. On error the stack is unwound to where this call started.
+    pub fn invoke(&mut self, func_idx: usize, args: Vec<VMValue>, host: &mut dyn VmHost) -> Result<VMValue, String> {
+        let func = self.functions.get(func_idx).ok_or_else(|| format!("no bytecode function #{}", func_idx))?;
+        let bp = self.stack.len();
+        let depth = self.frames.len();
         
-        // Set up locals
-        self.bp = self.stack.len();
+        // Arguments become the first locals; the rest start as null
+        self.stack.extend(args.into_iter().take(func.arity));
+        self.stack.resize(bp + func.locals.max(func.arity), VMValue::Null);
         
-        // Push arguments as locals
-        for arg in args {
-            self.stack.push(arg);
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record_call(func_idx);
         }
         
-        // Pad locals
-        for _ in func.arity..func.locals {
-            self.stack.push(VMValue::Null);
-        }
-        
-        // Push initial frame
         self.frames.push(CallFrame {
             func_idx,
             ip: 0,
-            bp: self.bp,
+            bp,
         });
         
-        self.run()
+        let result = if self.profile.is_some() {
+            self.dispatch::<true>(depth, host)
+        } else {
+            self.dispatch::<false>(depth, host)
+        };
+        if result.is_err() {
+            self.frames.truncate(depth);
+            self.stack.truncate(bp);
+        }
+        result
     }
     
-    fn run(&mut self) -> VMValue {
-        loop {
-            let frame = self.frames.last_mut().unwrap();
-            let func = &self.functions[frame.func_idx];
-            
-            if frame.ip >= func.code.len() {
-                // Implicit return null
-                if self.frames.len() <= 1 {
-                    return VMValue::Null;
+    /// Carry on with calls that started elsewhere, `frames` outermost first,
+    /// returning what the outermost one returns
+    pub fn resume(&mut self, frames: Vec<ResumeFrame>, host: &mut dyn VmHost) -> Result<VMValue, String> {
+        let base = self.stack.len();
+        let depth = self.frames.len();
+        if frames.is_empty() {
+            return Err("nothing to resume".to_string());
+        }
+        for frame in frames {
+            let func = self.functions.get(frame.func_idx).ok_or_else(|| format!("no bytecode function #{}", frame.func_idx))?;
+            let bp = self.stack.len();
+            self.stack.extend(frame.locals);
+            self.stack.resize(bp + func.locals.max(func.arity), VMValue::Null);
+            self.stack.extend(frame.stack);
+            self.frames.push(CallFrame { func_idx: frame.func_idx, ip: frame.ip, bp });
+        }
+        let result = if self.profile.is_some() {
+            self.dispatch::<true>(depth, host)
+        } else {
+            self.dispatch::<false>(depth, host)
+        };
+        if result.is_err() {
+            self.frames.truncate(depth);
+            self.stack.truncate(base);
+        }
+        result
+    }
+    
+    /// Main dispatch loop. The current function's code, ip and bp live in locals
+    /// and are only written back to the frame stack on calls; the frame stack is
+    /// touched again on return. Profiling is a const parameter so the counters
+    /// cost nothing in the normal instantiation. Returns once the frame stack
+    /// is back to `depth`.
+    fn dispatch<const PROFILE: bool>(&mut self, depth: usize, host: &mut dyn VmHost) -> Result<VMValue, String> {
+        let functions = &self.functions;
+        let stack = &mut self.stack;
+        let frames = &mut self.frames;
+        let mut profile = self.profile.as_mut();
+        
+        let frame = frames.last().unwrap();
+        let mut func_idx = frame.func_idx;
+        let mut func = &functions[func_idx];
+        let mut code: &[OpCode] = &func.code;
+        let mut ip = frame.ip;
+        let mut bp = frame.bp;
+        
+        macro_rules! int_binop {
+            ($a:ident, $b:ident, $result:expr) => {{
+                let $b = pop_int(stack);
+                let $a = pop_int(stack);
+                stack.push($result);
+            }};
+        }
+        
+        // `?` for the loop below: an error breaks out of it with `ip` and
+        // `func_idx` still saying which instruction failed
+        macro_rules! check {
+            ($result:expr) => {
+                match $result {
+                    Ok(value) => value,
+                    Err(e) => break e,
+                }
+            };
+        }
+        
+        let message: String = loop {
+            let op = if ip < code.len() {
+                fetch(code, ip)
+            } else {
+                // Falling off the end returns null
+                stack.push(VMValue::Null);
+                OpCode::Return
+            };
+            if PROFILE {
+                if let Some(profile) = profile.as_mut() {
+                    profile.record(func_idx, ip, op);
                 }
-                self.frames.pop();
-                continue;
             }
-            
-            let op = func.code[frame.ip];
-            frame.ip += 1;
+            ip += 1;
             
             match op {
-                OpCode::Const(n) => self.push(VMValue::Int(n)),
-                OpCode::ConstTrue => self.push(VMValue::Bool(true)),
-                OpCode::ConstFalse => self.push(VMValue::Bool(false)),
-                OpCode::ConstNull => self.push(VMValue::Null),
+                OpCode::Const(n) => stack.push(VMValue::Int(n)),
+                OpCode::LoadConst(idx) => stack.push(func.constants[idx].to_value()),
+                OpCode::ConstTrue => stack.push(VMValue::Bool(true)),
+                OpCode::ConstFalse => stack.push(VMValue::Bool(false)),
+                OpCode::ConstNull => stack.push(VMValue::Null),
                 
                 OpCode::Add => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Int(a + b));
-                }
-                OpCode::Sub => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Int(a - b));
-                }
-                OpCode::Mul => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Int(a * b));
-                }
-                OpCode::Div => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Int(if b != 0 { a / b } else { 0 }));
+                    let b = stack.pop().unwrap_or(VMValue::Null);
+                    let a = stack.pop().unwrap_or(VMValue::Null);
+                    stack.push(match (a, b) {
+                        (VMValue::Int(a), VMValue::Int(b)) => VMValue::Int(a + b),
+                        // Like the interpreter, a string on either side concatenates
+                        (a @ VMValue::Str(_), b) | (a, b @ VMValue::Str(_)) => VMValue::Str(format!("{}{}", a, b).into()),
+                        (a, b) => VMValue::Int(a.as_int() + b.as_int()),
+                    });
                 }
-                OpCode::Mod => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Int(if b != 0 { a % b } else { 0 }));
+                OpCode::Sub => int_binop!(a, b, VMValue::Int(a - b)),
+                OpCode::Mul => int_binop!(a, b, VMValue::Int(a * b)),
+                OpCode::Div | OpCode::Mod => {
+                    let b = pop_int(stack);
+                    let a = pop_int(stack);
+                    if b == 0 {
+                        break "division by zero".to_string();
+                    }
+                    stack.push(VMValue::Int(if matches!(op, OpCode::Div) { a.wrapping_div(b) } else { a.wrapping_rem(b) }));
                 }
                 OpCode::Neg => {
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Int(-a));
+                    let a = pop_int(stack);
+                    stack.push(VMValue::Int(-a));
                 }
                 
-                OpCode::Lt => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Bool(a < b));
-                }
-                OpCode::Gt => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Bool(a > b));
-                }
-                OpCode::Le => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Bool(a <= b));
-                }
-                OpCode::Ge => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Bool(a >= b));
-                }
+                OpCode::Lt => int_binop!(a, b, VMValue::Bool(a < b)),
+                OpCode::Gt => int_binop!(a, b, VMValue::Bool(a > b)),
+                OpCode::Le => int_binop!(a, b, VMValue::Bool(a <= b)),
+                OpCode::Ge => int_binop!(a, b, VMValue::Bool(a >= b)),
                 OpCode::Eq => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Bool(a == b));
+                    let b = stack.pop().unwrap_or(VMValue::Null);
+                    let a = stack.pop().unwrap_or(VMValue::Null);
+                    stack.push(VMValue::Bool(values_equal(&a, &b)));
                 }
                 OpCode::Ne => {
-                    let b = self.pop().as_int();
-                    let a = self.pop().as_int();
-                    self.push(VMValue::Bool(a != b));
+                    let b = stack.pop().unwrap_or(VMValue::Null);
+                    let a = stack.pop().unwrap_or(VMValue::Null);
+                    stack.push(VMValue::Bool(!values_equal(&a, &b)));
                 }
                 
                 OpCode::Not => {
-                    let a = self.pop().is_truthy();
-                    self.push(VMValue::Bool(!a));
+                    let a = pop_truthy(stack);
+                    stack.push(VMValue::Bool(!a));
                 }
                 OpCode::And => {
-                    let b = self.pop().is_truthy();
-                    let a = self.pop().is_truthy();
-                    self.push(VMValue::Bool(a && b));
+                    let b = pop_truthy(stack);
+                    let a = pop_truthy(stack);
+                    stack.push(VMValue::Bool(a && b));
                 }
                 OpCode::Or => {
-                    let b = self.pop().is_truthy();
-                    let a = self.pop().is_truthy();
-                    self.push(VMValue::Bool(a || b));
+                    let b = pop_truthy(stack);
+                    let a = pop_truthy(stack);
+                    stack.push(VMValue::Bool(a || b));
                 }
                 
-                OpCode::Jump(target) => {
-                    let frame = self.frames.last_mut().unwrap();
-                    frame.ip = target;
-                }
+                OpCode::Jump(target) => ip = target,
                 OpCode::JumpIfFalse(target) => {
-                    if !self.pop().is_truthy() {
-                        let frame = self.frames.last_mut().unwrap();
-                        frame.ip = target;
+                    if !pop_truthy(stack) {
+                        ip = target;
                     }
                 }
                 OpCode::JumpIfTrue(target) => {
-                    if self.pop().is_truthy() {
-                        let frame = self.frames.last_mut().unwrap();
-                        frame.ip = target;
+                    if pop_truthy(stack) {
+                        ip = target;
                     }
                 }
                 
                 OpCode::LoadLocal(idx) => {
-                    let frame = self.frames.last().unwrap();
-                    let val = self.stack[frame.bp + idx].clone();
-                    self.push(val);
+                    let val = match local(stack, bp + idx) {
+                        VMValue::Int(n) => VMValue::Int(*n),
+                        other => other.clone(),
+                    };
+                    stack.push(val);
                 }
                 OpCode::StoreLocal(idx) => {
-                    let val = self.pop();
-                    let frame = self.frames.last().unwrap();
-                    self.stack[frame.bp + idx] = val;
+                    let val = stack.pop().unwrap_or(VMValue::Null);
+                    stack[bp + idx] = val;
                 }
                 
-                OpCode::Call(func_idx, argc) => {
-                    // Get arguments from stack
-                    let new_bp = self.stack.len() - argc;
-                    let func = &self.functions[func_idx];
+                OpCode::Call(callee, argc) => {
+                    if frames.len() >= MAX_FRAMES {
+                        break format!("stack overflow in bytecode function {}", func.name);
+                    }
+                    frames.last_mut().unwrap().ip = ip;
+                    if PROFILE {
+                        if let Some(profile) = profile.as_mut() {
+                            profile.record_call(callee);
+                        }
+                    }
                     
-                    // Pad locals
+                    let new_bp = stack.len() - argc;
+                    func_idx = callee;
+                    func = &functions[func_idx];
                     for _ in argc..func.locals {
-                        self.stack.push(VMValue::Null);
+                        stack.push(VMValue::Null);
                     }
+                    frames.push(CallFrame { func_idx, ip: 0, bp: new_bp });
                     
-                    // Save return address
-                    self.frames.push(CallFrame {
-                        func_idx,
-                        ip: 0,
-                        bp: new_bp,
-                    });
+                    code = &func.code;
+                    ip = 0;
+                    bp = new_bp;
+                }
+                OpCode::CallHost(name, argc) => {
+                    let args = stack.split_off(stack.len() - argc);
+                    let name = check!(const_str(func, name));
+                    stack.push(check!(host.call_host(name, args)));
                 }
                 OpCode::Return => {
-                    let result = self.pop();
-                    let frame = self.frames.pop().unwrap();
-                    
-                    // Pop locals
-                    self.stack.truncate(frame.bp);
+                    let result = stack.pop().unwrap_or(VMValue::Null);
+                    frames.pop();
+                    stack.truncate(bp);
                     
-                    if self.frames.is_empty() {
-                        return result;
+                    if frames.len() == depth {
+                        return Ok(result);
                     }
+                    let caller = frames.last().unwrap();
+                    stack.push(result);
                     
-                    self.push(result);
+                    func_idx = caller.func_idx;
+                    func = &functions[func_idx];
+                    code = &func.code;
+                    ip = caller.ip;
+                    bp = caller.bp;
                 }
                 
-                OpCode::Pop => { self.pop(); }
+                OpCode::ConstStr(idx) => stack.push(VMValue::Str(check!(const_str(func, idx)).clone())),
+                OpCode::NewArray(n) => {
+                    let items = stack.split_off(stack.len() - n);
+                    stack.push(VMValue::Array(Rc::new(RefCell::new(items))));
+                }
+                OpCode::NewStruct(name) => {
+                    let name = Symbol::intern(check!(const_str(func, name)));
+                    stack.push(VMValue::Struct(name, Rc::new(RefCell::new(HashMap::new()))));
+                }
+                OpCode::NewMap => stack.push(VMValue::Map(Rc::new(RefCell::new(HashMap::new())))),
+                OpCode::Index => {
+                    let index = stack.pop().unwrap_or(VMValue::Null);
+                    let target = stack.pop().unwrap_or(VMValue::Null);
+                    stack.push(check!(index_value(&target, &index)));
+                }
+                OpCode::SetIndex => {
+                    let value = stack.pop().unwrap_or(VMValue::Null);
+                    let index = stack.pop().unwrap_or(VMValue::Null);
+                    let target = stack.pop().unwrap_or(VMValue::Null);
+                    check!(set_index(&target, &index, value));
+                }
+                OpCode::GetField(name) => {
+                    let name = check!(const_str(func, name));
+                    let target = stack.pop().unwrap_or(VMValue::Null);
+                    let value = match &target {
+                        VMValue::Struct(_, fields) => {
+                            let fields = fields.borrow();
+                            fields.get(&**name).cloned().unwrap_or_else(|| {
+                                host.runtime_error(&format!("Missing field '{}'. Available: {:?}", name, fields.keys().collect::<Vec<_>>()));
+                                VMValue::Null
+                            })
+                        }
+                        // `pair.0` reads an array element
+                        VMValue::Array(items) => name.parse::<usize>().ok()
+                            .and_then(|i| items.borrow().get(i).cloned())
+                            .unwrap_or(VMValue::Null),
+                        _ => VMValue::Null,
+                    };
+                    stack.push(value);
+                }
+                OpCode::SetField(name) => {
+                    let value = stack.pop().unwrap_or(VMValue::Null);
+                    let target = stack.pop().unwrap_or(VMValue::Null);
+                    if let VMValue::Struct(_, fields) = target {
+                        fields.borrow_mut().insert(check!(const_str(func, name)).to_string(), value);
+                    }
+                }
+                
+                OpCode::Pop => { stack.pop(); }
                 OpCode::Dup => {
-                    let val = self.peek().clone();
-                    self.push(val);
+                    let val = stack.last().cloned().unwrap_or(VMValue::Null);
+                    stack.push(val);
                 }
                 
                 OpCode::Print => {
-                    let val = self.pop();
-                    match val {
-                        VMValue::Int(n) => println!("{}", n),
-                        VMValue::Bool(b) => println!("{}", b),
-                        VMValue::Null => println!("null"),
-                    }
+                    let val = stack.pop().unwrap_or(VMValue::Null);
+                    crate::output::write_line(&val.to_string());
                 }
                 
                 OpCode::Halt => {
-                    return VMValue::Null;
+                    frames.truncate(depth);
+                    return Ok(VMValue::Null);
                 }
             }
+        };
+        self.fault = Some((func_idx, ip - 1));
+        Err(message)
+    }
+}
+
+/// Fetch the instruction at `ip`; the loop has already checked `ip < code.len()`
+#[inline(always)]
+fn fetch(code: &[OpCode], ip: usize) -> OpCode {
+    #[cfg(feature = "unchecked-vm")]
+    {
+        // SAFETY: callers check `ip < code.len()` before fetching
+        unsafe { *code.get_unchecked(ip) }
+    }
+    #[cfg(not(feature = "unchecked-vm"))]
+    {
+        code[ip]
+    }
+}
+
+/// Read a local slot. With `unchecked-vm` the bounds check is skipped, which is
+/// only sound for well-formed bytecode (every LoadLocal index < the function's `locals`).
+#[inline(always)]
+fn local(stack: &[VMValue], slot: usize) -> &VMValue {
+    #[cfg(feature = "unchecked-vm")]
+    {
+        // SAFETY: frames pad the stack to `bp + locals` on entry; see above
+        unsafe { stack.get_unchecked(slot) }
+    }
+    #[cfg(not(feature = "unchecked-vm"))]
+    {
+        &stack[slot]
+    }
+}
+
+/// Pop an operand for integer arithmetic. Ints are matched directly so the
+/// hot path never runs the heap variants' drop glue.
+#[inline(always)]
+fn pop_int(stack: &mut Vec<VMValue>) -> i64 {
+    match stack.pop() {
+        Some(VMValue::Int(n)) => n,
+        Some(other) => other.as_int(),
+        None => 0,
+    }
+}
+
+#[inline(always)]
+fn pop_truthy(stack: &mut Vec<VMValue>) -> bool {
+    match stack.pop() {
+        Some(VMValue::Bool(b)) => b,
+        Some(other) => other.is_truthy(),
+        None => false,
+    }
+}
+
+/// The string constant an operand refers to
+fn const_str(func: &CompiledFunc, idx: usize) -> Result<&Rc<str>, String> {
+    match &func.constants[idx] {
+        Constant::Str(s) => Ok(s),
+        other => Err(format!("operand of {} is not a string constant: {:?}", func.name, other)),
+    }
+}
+
+/// A position counted from the end when negative, or None outside `0..len`
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let i = if index < 0 { index.checked_add(len as i64)? } else { index };
+    usize::try_from(i).ok().filter(|&i| i < len)
+}
+
+fn out_of_range(kind: &str, index: i64, len: usize) -> String {
+    format!("index {} out of range for {} of length {}", index, kind, len)
+}
+
+/// `target[index]`: an array element or a string's character (an error past
+/// either end), a struct or map entry (null when missing), otherwise null
+fn index_value(target: &VMValue, index: &VMValue) -> Result<VMValue, String> {
+    match target {
+        VMValue::Array(items) => {
+            let items = items.borrow();
+            let i = index.as_int();
+            resolve_index(i, items.len()).map(|at| items[at].clone()).ok_or_else(|| out_of_range("array", i, items.len()))
+        }
+        VMValue::Str(s) => {
+            let i = index.as_int();
+            let len = s.chars().count();
+            resolve_index(i, len)
+                .and_then(|at| s.chars().nth(at))
+                .map(|c| VMValue::Str(c.to_string().into()))
+                .ok_or_else(|| out_of_range("string", i, len))
+        }
+        VMValue::Struct(_, fields) | VMValue::Map(fields) => Ok(fields.borrow().get(&index.to_string()).cloned().unwrap_or(VMValue::Null)),
+        _ => Ok(VMValue::Null),
+    }
+}
+
+/// `target[index] = value`. A negative index counts from the end; writing
+/// past the end grows the array with nulls.
+fn set_index(target: &VMValue, index: &VMValue, value: VMValue) -> Result<(), String> {
+    match target {
+        VMValue::Array(items) => {
+            let mut items = items.borrow_mut();
+            let i = index.as_int();
+            let at = if i < 0 {
+                resolve_index(i, items.len()).ok_or_else(|| out_of_range("array", i, items.len()))?
+            } else {
+                i as usize
+            };
+            if at >= items.len() {
+                items.resize(at + 1, VMValue::Null);
+            }
+            items[at] = value;
+        }
+        VMValue::Struct(_, fields) | VMValue::Map(fields) => {
+            fields.borrow_mut().insert(index.to_string(), value);
         }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `==` as the interpreter defines it: same-typed values compare, anything
+/// else is unequal. Arrays, structs and maps are never equal, even to themselves.
+fn values_equal(a: &VMValue, b: &VMValue) -> bool {
+    match (a, b) {
+        (VMValue::Int(a), VMValue::Int(b)) => a == b,
+        (VMValue::Float(a), VMValue::Float(b)) => a == b,
+        (VMValue::Str(a), VMValue::Str(b)) => a == b,
+        (VMValue::Bool(a), VMValue::Bool(b)) => a == b,
+        (VMValue::Null, VMValue::Null) => true,
+        _ => false,
     }
 }
 
@@ -392,6 +1009,8 @@ pub fn compile_fib() -> CompiledFunc {
             Add,                // 14: add results
             Return,             // 15: return sum
         ],
+        constants: Vec::new(),
+        lines: Vec::new(),
     }
 }
 
@@ -407,4 +1026,44 @@ mod tests {
         let result = vm.call("fib", vec![VMValue::Int(10)]);
         assert!(matches!(result, VMValue::Int(55)));
     }
+    
+    #[test]
+    fn test_profile_counts() {
+        let mut vm = BytecodeVM::new();
+        vm.add_function(compile_fib());
+        vm.enable_profiling();
+        vm.call("fib", vec![VMValue::Int(10)]);
+        
+        let profile = vm.profile().unwrap();
+        // fib(10) makes 177 calls in total
+        assert_eq!(vm.profiled_call_counts(), vec![("fib".to_string(), 177)]);
+        assert_eq!(profile.op_counts["Call"], 176);
+        assert_eq!(profile.func_instructions[0], profile.total_instructions());
+        let hottest = profile.hottest_offsets(1);
+        assert_eq!(hottest[0].2, 177); // entry LoadLocal runs once per call
+    }
+    
+    #[test]
+    fn test_constant_pool_dedup() {
+        let mut pool = ConstantPool::new();
+        let a = pool.add_str("hello");
+        let b = pool.add(Constant::Float(1.5));
+        let c = pool.add_str("hello");
+        let d = pool.add(Constant::Array(vec![Constant::Int(1), Constant::Str(Rc::from("hello"))]));
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+        assert_eq!(pool.len(), 3);
+        
+        let mut vm = BytecodeVM::new();
+        vm.add_function(CompiledFunc {
+            name: "main".to_string(),
+            arity: 0,
+            locals: 0,
+            code: vec![OpCode::LoadConst(d), OpCode::Return],
+            constants: pool.into_constants(),
+            lines: Vec::new(),
+        });
+        let result = vm.call("main", vec![]);
+        assert_eq!(result.to_string(), "[1, hello]");
+    }
 }
@@ -1,15 +1,198 @@
-// Cryo Codegen - Placeholder for LLVM IR generation
-// The actual code generation is done by the compiler.ar itself
-// This module is for future direct LLVM IR output from Rust
+// Cryo Codegen - LLVM IR output for `--emit-llvm` and `cryo build`
+// Lowers the final (expanded, optimized) AST with the native compiler,
+// writes the module and can run it under lli or turn it into assembly,
+// an object file or an executable with llc and the system linker
 
-#![allow(dead_code)]
+use crate::native_compiler;
+use crate::parser::TopLevel;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
-pub struct Codegen {
-    // Future LLVM IR generation
+/// IR for the whole program, with DWARF line info pointing at
+/// `debug_source` when given, and runtime checks that panic with
+/// `checked_source` as the location when given
+pub fn lower(ast: &[TopLevel], debug_source: Option<&str>, checked_source: Option<&str>) -> Result<String, String> {
+    let mut compiler = native_compiler::Compiler::new();
+    if let Some(path) = debug_source {
+        compiler = compiler.with_debug_info(path);
+    }
+    if let Some(path) = checked_source {
+        compiler = compiler.with_checks(path);
+    }
+    compiler.compile_items(ast).map_err(|e| format!("Native compilation error: {}", e))
+}
+
+pub fn write_module(path: &str, ir: &str) -> Result<(), String> {
+    std::fs::write(path, ir).map_err(|e| format!("Error writing LLVM IR to {}: {}", path, e))
+}
+
+/// Execute a module with `lli` from PATH, passing `args` through and
+/// returning main's exit code. `load` is a shared library to resolve
+/// symbols from, such as the runtime.
+pub fn run_with_lli(path: &str, args: &[String], load: Option<&Path>) -> Result<i32, String> {
+    let mut lli = Command::new("lli");
+    if let Some(lib) = load {
+        lli.arg(format!("-load={}", lib.display()));
+    }
+    let status = lli
+        .arg(path)
+        .args(args)
+        .status()
+        .map_err(|e| format!("--run needs LLVM's lli on PATH: {}", e))?;
+    // Killed by a signal: report it like a shell would
+    Ok(status.code().unwrap_or(128))
+}
+
+/// What `cryo build --emit=...` produces
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Ir,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl Emit {
+    pub fn parse(s: &str) -> Option<Emit> {
+        match s {
+            "ir" | "llvm-ir" => Some(Emit::Ir),
+            "asm" => Some(Emit::Asm),
+            "obj" => Some(Emit::Obj),
+            "exe" => Some(Emit::Exe),
+            _ => None,
+        }
+    }
+
+    /// Default output name for a source file stem
+    pub fn output_for(self, stem: &str) -> String {
+        match self {
+            Emit::Ir => format!("{}.ll", stem),
+            Emit::Asm => format!("{}.s", stem),
+            Emit::Obj => format!("{}.o", stem),
+            Emit::Exe if cfg!(windows) => format!("{}.exe", stem),
+            Emit::Exe => stem.to_string(),
+        }
+    }
+}
+
+/// How `build` drives the external tools
+pub struct BuildOptions {
+    pub emit: Emit,
+    /// Link with `-fuse-ld=lld` instead of the compiler driver's default linker
+    pub lld: bool,
+    /// Print every command before running it
+    pub verbose: bool,
+    /// Runtime staticlib to link executables against, see `runtime_library`
+    pub runtime: Option<PathBuf>,
 }
 
-impl Codegen {
-    pub fn new() -> Self {
-        Codegen {}
+/// The runtime staticlib: `configured` (from `CRYO_RUNTIME_LIB`), or
+/// libcryo_runtime.a next to the cryo binary, where cargo puts both
+pub fn runtime_library(configured: Option<&str>) -> Option<PathBuf> {
+    if let Some(path) = configured {
+        return Some(PathBuf::from(path));
+    }
+    let exe = std::env::current_exe().ok()?;
+    let lib = exe.parent()?.join(if cfg!(windows) { "cryo_runtime.lib" } else { "libcryo_runtime.a" });
+    lib.exists().then_some(lib)
+}
+
+/// The runtime as a shared library (libcryo_runtime.so) next to the cryo
+/// binary, for `lli -load`
+pub fn runtime_shared_library() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let name = if cfg!(windows) {
+        "cryo_runtime.dll"
+    } else if cfg!(target_os = "macos") {
+        "libcryo_runtime.dylib"
+    } else {
+        "libcryo_runtime.so"
+    };
+    let lib = exe.parent()?.join(name);
+    lib.exists().then_some(lib)
+}
+
+/// Write `ir` as `opts.emit` to `output`, going through llc for asm/obj and
+/// the C compiler driver (`$CC`, default `cc`) to link an executable with the
+/// runtime library when there is one
+pub fn build(ir: &str, output: &str, opts: &BuildOptions) -> Result<(), String> {
+    if opts.emit == Emit::Ir {
+        return write_module(output, ir);
+    }
+    let tmp = std::env::temp_dir().join(format!("cryo-build-{}", std::process::id()));
+    let module = format!("{}.ll", tmp.display());
+    write_module(&module, ir)?;
+    let result = (|| {
+        let llc = |filetype: &str, out: &str| {
+            let args = ["-relocation-model=pic", &format!("-filetype={}", filetype), &module, "-o", out];
+            run_tool("llc", &args, opts.verbose)
+        };
+        match opts.emit {
+            Emit::Asm => llc("asm", output),
+            Emit::Obj => llc("obj", output),
+            _ => {
+                let object = format!("{}.o", tmp.display());
+                llc("obj", &object)?;
+                let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+                let runtime = opts.runtime.as_ref().map(|p| p.to_string_lossy().into_owned());
+                let mut args = vec![object.as_str(), "-o", output];
+                if let Some(runtime) = &runtime {
+                    // The staticlib needs the system libraries std links against;
+                    // -rdynamic lets panic backtraces name the program's functions
+                    args.extend([runtime.as_str(), "-lpthread", "-ldl"]);
+                    if !cfg!(windows) {
+                        args.push("-rdynamic");
+                    }
+                }
+                // For extern math functions like sqrt
+                args.push("-lm");
+                if opts.lld {
+                    args.push("-fuse-ld=lld");
+                }
+                let linked = run_tool(&cc, &args, opts.verbose);
+                let _ = std::fs::remove_file(&object);
+                linked
+            }
+        }
+    })();
+    let _ = std::fs::remove_file(&module);
+    result
+}
+
+fn run_tool(program: &str, args: &[&str], verbose: bool) -> Result<(), String> {
+    if verbose {
+        eprintln!("+ {} {}", program, args.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" "));
+    }
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| format!("cannot run {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} failed ({})", program, status));
+    }
+    Ok(())
+}
+
+/// Quote for display so `--verbose` output can be pasted into a shell
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=+:,".contains(c)) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_kinds() {
+        assert_eq!(Emit::parse("asm"), Some(Emit::Asm));
+        assert_eq!(Emit::parse("wasm"), None);
+        assert_eq!(Emit::Obj.output_for("app"), "app.o");
+        assert_eq!(Emit::Ir.output_for("app"), "app.ll");
+        assert_eq!(shell_quote("-filetype=obj"), "-filetype=obj");
+        assert_eq!(shell_quote("my file"), "'my file'");
     }
 }
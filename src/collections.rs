@@ -0,0 +1,197 @@
+// ============================================
+// Cryo Collections Module
+// Native deque, binary heap and ordered map storage behind collection values
+// ============================================
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Storage for the native collection values. Generic over the element type so
+/// the same structure holds interpreter values and their thread-safe copies;
+/// anything that needs to order elements takes the comparison as an argument.
+#[derive(Debug, Clone)]
+pub enum Collection<V> {
+    Deque(VecDeque<V>),
+    Heap(Heap<V>),
+    Map(BTreeMap<MapKey, V>),
+}
+
+impl<V> Collection<V> {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Collection::Deque(_) => "deque",
+            Collection::Heap(_) => "heap",
+            Collection::Map(_) => "btree_map",
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Collection::Deque(d) => d.is_empty(),
+            Collection::Heap(h) => h.is_empty(),
+            Collection::Map(m) => m.is_empty(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Collection::Deque(d) => d.len(),
+            Collection::Heap(h) => h.len(),
+            Collection::Map(m) => m.len(),
+        }
+    }
+
+    /// Every element, heap priorities included, in storage order
+    pub fn for_each_value(&self, mut f: impl FnMut(&V)) {
+        match self {
+            Collection::Deque(d) => d.iter().for_each(f),
+            Collection::Heap(h) => h.items.iter().for_each(|e| {
+                f(&e.priority);
+                f(&e.value);
+            }),
+            Collection::Map(m) => m.values().for_each(f),
+        }
+    }
+
+    /// Copy of this collection with every element converted by `f`
+    pub fn map<W>(&self, mut f: impl FnMut(&V) -> W) -> Collection<W> {
+        match self {
+            Collection::Deque(d) => Collection::Deque(d.iter().map(f).collect()),
+            Collection::Heap(h) => Collection::Heap(Heap {
+                items: h.items.iter().map(|e| HeapEntry { priority: f(&e.priority), seq: e.seq, value: f(&e.value) }).collect(),
+                next_seq: h.next_seq,
+            }),
+            Collection::Map(m) => Collection::Map(m.iter().map(|(k, v)| (k.clone(), f(v))).collect()),
+        }
+    }
+}
+
+/// Ordered-map key. Ints sort numerically and before all strings.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MapKey {
+    Int(i64),
+    Str(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct HeapEntry<V> {
+    pub priority: V,
+    seq: u64,
+    pub value: V,
+}
+
+/// Min-heap keyed by priority. Equal priorities pop in insertion order, so
+/// the heap doubles as a stable priority queue.
+#[derive(Debug, Clone)]
+pub struct Heap<V> {
+    items: Vec<HeapEntry<V>>,
+    next_seq: u64,
+}
+
+impl<V> Default for Heap<V> {
+    fn default() -> Self {
+        Heap { items: Vec::new(), next_seq: 0 }
+    }
+}
+
+impl<V> Heap<V> {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn peek(&self) -> Option<&HeapEntry<V>> {
+        self.items.first()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    fn less(a: &HeapEntry<V>, b: &HeapEntry<V>, cmp: &impl Fn(&V, &V) -> Ordering) -> bool {
+        cmp(&a.priority, &b.priority).then(a.seq.cmp(&b.seq)) == Ordering::Less
+    }
+
+    pub fn push(&mut self, priority: V, value: V, cmp: impl Fn(&V, &V) -> Ordering) {
+        self.items.push(HeapEntry { priority, seq: self.next_seq, value });
+        self.next_seq += 1;
+        let mut i = self.items.len() - 1;
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !Self::less(&self.items[i], &self.items[parent], &cmp) {
+                break;
+            }
+            self.items.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    pub fn pop(&mut self, cmp: impl Fn(&V, &V) -> Ordering) -> Option<HeapEntry<V>> {
+        if self.items.is_empty() {
+            return None;
+        }
+        let top = self.items.swap_remove(0);
+        let mut i = 0;
+        loop {
+            let (left, right) = (2 * i + 1, 2 * i + 2);
+            let mut smallest = i;
+            if left < self.items.len() && Self::less(&self.items[left], &self.items[smallest], &cmp) {
+                smallest = left;
+            }
+            if right < self.items.len() && Self::less(&self.items[right], &self.items[smallest], &cmp) {
+                smallest = right;
+            }
+            if smallest == i {
+                break;
+            }
+            self.items.swap(i, smallest);
+            i = smallest;
+        }
+        Some(top)
+    }
+
+    /// Entries from smallest to largest, without consuming the heap
+    pub fn sorted(&self, cmp: impl Fn(&V, &V) -> Ordering) -> Vec<&HeapEntry<V>> {
+        let mut entries: Vec<&HeapEntry<V>> = self.items.iter().collect();
+        entries.sort_by(|a, b| cmp(&a.priority, &b.priority).then(a.seq.cmp(&b.seq)));
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap_order_and_stability() {
+        let mut heap = Heap::default();
+        // Values 31..33 share priority 3 and must come out in insertion order
+        for (priority, value) in [(5, 50), (1, 10), (3, 31), (3, 32), (2, 20), (3, 33)] {
+            heap.push(priority, value, i32::cmp);
+        }
+        assert_eq!(heap.peek().map(|e| e.value), Some(10));
+        assert_eq!(heap.sorted(i32::cmp).iter().map(|e| e.value).collect::<Vec<_>>(), [10, 20, 31, 32, 33, 50]);
+        let mut popped = Vec::new();
+        while let Some(entry) = heap.pop(i32::cmp) {
+            popped.push(entry.value);
+        }
+        assert_eq!(popped, [10, 20, 31, 32, 33, 50]);
+        assert!(heap.pop(i32::cmp).is_none());
+    }
+
+    #[test]
+    fn test_map_keys_and_conversion() {
+        let mut map = BTreeMap::new();
+        map.insert(MapKey::Str("b".to_string()), 2);
+        map.insert(MapKey::Int(10), 1);
+        map.insert(MapKey::Int(-3), 0);
+        let keys: Vec<MapKey> = map.keys().cloned().collect();
+        assert_eq!(keys, [MapKey::Int(-3), MapKey::Int(10), MapKey::Str("b".to_string())]);
+        let doubled = Collection::Map(map).map(|v| v * 2);
+        assert_eq!(doubled.kind(), "btree_map");
+        assert_eq!(doubled.len(), 3);
+    }
+}
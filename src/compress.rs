@@ -0,0 +1,125 @@
+// ============================================
+// Cryo Compression Module
+// gzip/deflate codecs and HTTP Content-Encoding negotiation
+// ============================================
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{Read, Write};
+
+/// Bodies smaller than this aren't worth compressing: the gzip header and
+/// framing eat most of the savings
+pub const MIN_COMPRESS_BYTES: usize = 860;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    /// zlib-wrapped deflate, which is what HTTP calls `deflate`
+    Deflate,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, data: &[u8], level: u32) -> Vec<u8> {
+        let level = Compression::new(level.min(9));
+        // Writing into a Vec can't fail
+        match self {
+            Encoding::Gzip => {
+                let mut enc = GzEncoder::new(Vec::new(), level);
+                enc.write_all(data).unwrap();
+                enc.finish().unwrap()
+            }
+            Encoding::Deflate => {
+                let mut enc = ZlibEncoder::new(Vec::new(), level);
+                enc.write_all(data).unwrap();
+                enc.finish().unwrap()
+            }
+        }
+    }
+
+    pub fn decode(self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let result = match self {
+            Encoding::Gzip => GzDecoder::new(data).read_to_end(&mut out),
+            Encoding::Deflate => ZlibDecoder::new(data).read_to_end(&mut out),
+        };
+        result.map(|_| out).map_err(|e| format!("{} decode failed: {}", self.name(), e))
+    }
+}
+
+/// Pick a response encoding from an `Accept-Encoding` header, honouring
+/// `q=0` exclusions; gzip wins ties
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or("");
+        let q = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let Some(enc) = Encoding::from_name(name) else { continue };
+        if q <= 0.0 {
+            continue;
+        }
+        let better = match best {
+            None => true,
+            Some((cur, best_q)) => q > best_q || (q == best_q && enc == Encoding::Gzip && cur != Encoding::Gzip),
+        };
+        if better {
+            best = Some((enc, q));
+        }
+    }
+    best.map(|(enc, _)| enc)
+}
+
+/// Text-like media types that compress well (images, video and archives are
+/// already compressed)
+pub fn is_compressible(content_type: &str) -> bool {
+    let ct = content_type.to_ascii_lowercase();
+    ct.starts_with("text/")
+        || ct.contains("json")
+        || ct.contains("javascript")
+        || ct.contains("xml")
+        || ct.contains("svg")
+        || ct.contains("wasm")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = "hello hello hello hello".repeat(100);
+        for enc in [Encoding::Gzip, Encoding::Deflate] {
+            let packed = enc.encode(data.as_bytes(), 6);
+            assert!(packed.len() < data.len());
+            assert_eq!(enc.decode(&packed).unwrap(), data.as_bytes());
+        }
+        assert!(Encoding::Gzip.decode(b"not gzip").is_err());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, gzip;q=0.5"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, identity"), None);
+        assert_eq!(negotiate(""), None);
+    }
+}
@@ -0,0 +1,281 @@
+// ============================================
+// Cryo Runtime Configuration
+// Process-level tuning from environment variables, read once at startup
+// ============================================
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Stack for the thread running the pipeline. Deep ASTs are still cloned and
+/// dropped recursively, so give them far more than the 8MB default.
+pub const DEFAULT_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+const MIN_STACK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" | "0" => Some(LogLevel::Off),
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+/// Runtime settings a deployment can change without touching the command
+/// line. Each `CRYO_*` variable also accepts the older `ARGON_*` spelling.
+///
+/// | Variable | Effect |
+/// |----------|--------|
+/// | `CRYO_STACK_SIZE` | Main thread stack, bytes with optional `K`/`M`/`G` suffix |
+/// | `CRYO_GC_THRESHOLD` | Allocations between garbage collections |
+/// | `CRYO_JIT` | `0`/`off` disables the JIT, `1`/`on` enables it |
+/// | `CRYO_LOG` | Runtime diagnostics level: `off`, `error`, `warn`, `info`, `debug`, `trace` |
+/// | `CRYO_PATH` | Extra module directories, separated like `PATH` |
+/// | `CRYO_RUNTIME_LIB` | Runtime staticlib `cryo build` links executables against |
+/// | `CRYO_MAX_HEAP` | Stop the interpreter once its values take more than this many bytes (`K`/`M`/`G` suffixes) |
+/// | `CRYO_CRASH_DIR` | Where the crash reporter writes repro bundles (default: the temp directory); `off` turns the reporter off |
+/// | `CRYO_CRASH_MINIMIZE` | `1`/`on` makes the crash reporter re-run the program to cut down `repro.cryo` |
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    pub stack_size: usize,
+    pub gc_threshold: Option<usize>,
+    pub jit: Option<bool>,
+    /// None when unset: runtime diagnostics default to warnings and the
+    /// logger module keeps its own default
+    pub log: Option<LogLevel>,
+    pub module_path: Vec<String>,
+    pub runtime_lib: Option<String>,
+    pub max_heap: Option<usize>,
+    /// False when `CRYO_CRASH_DIR=off`: panics get Rust's own message
+    pub crash_reports: bool,
+    pub crash_dir: Option<String>,
+    /// Whether a crash re-runs the program to minimize it; off by default
+    /// because every run repeats the program's side effects
+    pub crash_minimize: bool,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig { stack_size: DEFAULT_STACK_SIZE, gc_threshold: None, jit: None, log: None, module_path: Vec::new(), runtime_lib: None, max_heap: None, crash_reports: true, crash_dir: None, crash_minimize: false }
+    }
+}
+
+/// Byte count with an optional `K`/`M`/`G` suffix, like `512M`
+pub fn parse_size(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let (digits, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => text.split_at(i),
+        None => (text, ""),
+    };
+    let multiplier: usize = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return None,
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+fn parse_switch(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "1" | "on" | "true" | "yes" => Some(true),
+        "0" | "off" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+impl RuntimeConfig {
+    /// Read the `CRYO_*` / `ARGON_*` variables. Invalid values are reported on
+    /// stderr and the default is kept.
+    pub fn from_env() -> RuntimeConfig {
+        let (config, errors) = RuntimeConfig::from_lookup(|name| std::env::var(name).ok());
+        for e in errors {
+            eprintln!("cryo: warning: {}", e);
+        }
+        config
+    }
+
+    /// Build from any variable source; returns the config plus a message for
+    /// every value that was ignored
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> (RuntimeConfig, Vec<String>) {
+        let var = |suffix: &str| {
+            let cryo = format!("CRYO_{}", suffix);
+            match lookup(&cryo) {
+                Some(v) => Some((cryo, v)),
+                None => {
+                    let argon = format!("ARGON_{}", suffix);
+                    lookup(&argon).map(|v| (argon, v))
+                }
+            }
+        };
+        let mut config = RuntimeConfig::default();
+        let mut errors = Vec::new();
+        let mut invalid = |name: &str, value: &str, expected: &str| {
+            errors.push(format!("ignoring {}={:?}: expected {}", name, value, expected));
+        };
+
+        if let Some((name, v)) = var("STACK_SIZE") {
+            match parse_size(&v) {
+                Some(n) if n >= MIN_STACK_SIZE => config.stack_size = n,
+                _ => invalid(&name, &v, "a size of at least 1M, like 512M"),
+            }
+        }
+        if let Some((name, v)) = var("GC_THRESHOLD") {
+            match v.trim().parse::<usize>() {
+                Ok(n) if n > 0 => config.gc_threshold = Some(n),
+                _ => invalid(&name, &v, "a positive allocation count"),
+            }
+        }
+        if let Some((name, v)) = var("JIT") {
+            match parse_switch(&v) {
+                Some(on) => config.jit = Some(on),
+                None => invalid(&name, &v, "on or off"),
+            }
+        }
+        if let Some((name, v)) = var("LOG") {
+            match LogLevel::from_name(&v) {
+                Some(level) => config.log = Some(level),
+                None => invalid(&name, &v, "off, error, warn, info, debug or trace"),
+            }
+        }
+        if let Some((_, v)) = var("PATH") {
+            config.module_path = std::env::split_paths(&v)
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(|p| p.to_string_lossy().trim_end_matches(['/', '\\']).to_string())
+                .collect();
+        }
+        if let Some((_, v)) = var("RUNTIME_LIB") {
+            config.runtime_lib = Some(v).filter(|v| !v.trim().is_empty());
+        }
+        if let Some((name, v)) = var("MAX_HEAP") {
+            match parse_size(&v) {
+                Some(n) if n > 0 => config.max_heap = Some(n),
+                _ => invalid(&name, &v, "a size like 512M"),
+            }
+        }
+        if let Some((_, v)) = var("CRASH_DIR") {
+            match v.trim() {
+                "" => {}
+                "off" | "0" => config.crash_reports = false,
+                dir => config.crash_dir = Some(dir.to_string()),
+            }
+        }
+        if let Some((name, v)) = var("CRASH_MINIMIZE") {
+            match parse_switch(&v) {
+                Some(on) => config.crash_minimize = on,
+                None => invalid(&name, &v, "on or off"),
+            }
+        }
+        (config, errors)
+    }
+}
+
+/// `LogLevel as u8`, or UNSET until a config with a level is applied
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(UNSET);
+const UNSET: u8 = u8::MAX;
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// The level from `CRYO_LOG`, if one was given
+pub fn configured_log_level() -> Option<LogLevel> {
+    let levels = [LogLevel::Off, LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+    levels.get(LOG_LEVEL.load(Ordering::Relaxed) as usize).copied()
+}
+
+pub fn log_enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level <= configured_log_level().unwrap_or(LogLevel::Warn)
+}
+
+/// Runtime diagnostic on stderr, shown when `CRYO_LOG` allows `level`
+pub fn log(level: LogLevel, message: &str) {
+    if log_enabled(level) {
+        eprintln!("[cryo {}] {}", level.name(), message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from(vars: &[(&str, &str)]) -> (RuntimeConfig, Vec<String>) {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        RuntimeConfig::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_from_env() {
+        let (config, errors) = from(&[]);
+        assert_eq!(config.stack_size, DEFAULT_STACK_SIZE);
+        assert!(config.log.is_none() && config.module_path.is_empty() && errors.is_empty());
+
+        let sep = if cfg!(windows) { ";" } else { ":" };
+        let path = format!("/opt/cryo/lib/{}{}/srv/app/", sep, sep);
+        let (config, errors) = from(&[
+            ("ARGON_STACK_SIZE", "64M"),
+            ("CRYO_GC_THRESHOLD", "5000"),
+            ("ARGON_JIT", "off"),
+            ("CRYO_LOG", "Debug"),
+            ("ARGON_LOG", "error"),
+            ("CRYO_PATH", &path),
+            ("ARGON_RUNTIME_LIB", "/opt/cryo/libcryo_runtime.a"),
+            ("CRYO_MAX_HEAP", "256M"),
+            ("ARGON_CRASH_DIR", "/var/crash/cryo"),
+            ("CRYO_CRASH_MINIMIZE", "1"),
+        ]);
+        assert!(errors.is_empty());
+        assert_eq!(config.stack_size, 64 << 20);
+        assert_eq!(config.gc_threshold, Some(5000));
+        assert_eq!(config.jit, Some(false));
+        // CRYO_* wins over ARGON_*
+        assert_eq!(config.log, Some(LogLevel::Debug));
+        assert_eq!(config.module_path, vec!["/opt/cryo/lib", "/srv/app"]);
+        assert_eq!(config.runtime_lib.as_deref(), Some("/opt/cryo/libcryo_runtime.a"));
+        assert_eq!(config.max_heap, Some(256 << 20));
+        assert!(config.crash_reports);
+        assert_eq!(config.crash_dir.as_deref(), Some("/var/crash/cryo"));
+        assert!(config.crash_minimize);
+        assert!(!from(&[("CRYO_CRASH_DIR", "off")]).0.crash_reports);
+
+        let (config, errors) = from(&[("CRYO_STACK_SIZE", "12k"), ("CRYO_JIT", "maybe"), ("CRYO_GC_THRESHOLD", "-1"), ("CRYO_MAX_HEAP", "0")]);
+        assert_eq!(errors.len(), 4);
+        assert_eq!(config.stack_size, DEFAULT_STACK_SIZE);
+        assert_eq!(config.jit, None);
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(parse_size("1048576"), Some(1 << 20));
+        assert_eq!(parse_size("2 GiB"), Some(2 << 30));
+        assert_eq!(parse_size("1.5G"), None);
+        assert_eq!(parse_size("M"), None);
+    }
+}
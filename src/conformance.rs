@@ -0,0 +1,206 @@
+// Cryo Conformance Suite
+// `cryo test-lang`: run every `.cryo` file under a directory and check it
+// against golden files next to it. `NAME.expected` holds the exact stdout;
+// `NAME.error` holds lines that must appear in the output of a program that
+// is supposed to fail.
+
+use crate::glob;
+use crate::selftest::{self, Engine, Outcome};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A program and its golden files
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub source: PathBuf,
+    pub expected: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Case {
+    pub fn name(&self) -> String {
+        self.source.display().to_string()
+    }
+}
+
+/// Every `.cryo` under `paths` (files, directories searched recursively, or
+/// glob patterns such as `tests/lang/**/parse_*.cryo`), sorted, with whatever golden
+/// files exist
+pub fn collect(paths: &[PathBuf]) -> Result<Vec<Case>, String> {
+    let mut sources = Vec::new();
+    for path in paths {
+        find_sources(path, &mut sources)?;
+    }
+    sources.sort();
+    sources.dedup();
+    Ok(sources
+        .into_iter()
+        .map(|source| {
+            let golden = |ext: &str| std::fs::read_to_string(source.with_extension(ext)).ok();
+            Case { expected: golden("expected"), error: golden("error"), source }
+        })
+        .collect())
+}
+
+fn find_sources(path: &Path, out: &mut Vec<PathBuf>) -> Result<(), String> {
+    if path.is_file() {
+        out.push(path.to_path_buf());
+        return Ok(());
+    }
+    let text = path.to_string_lossy();
+    let (dir, pattern) = if !path.exists() && glob::is_pattern(&text) {
+        glob::split_base(&text)
+    } else {
+        (text.into_owned(), "**/*.cryo".to_string())
+    };
+    let found = glob::glob(&dir, &pattern).map_err(|e| e.trim_start_matches("glob: ").to_string())?;
+    out.extend(found.into_iter().map(PathBuf::from).filter(|p| p.is_file() && p.extension().is_some_and(|x| x == "cryo")));
+    Ok(())
+}
+
+/// Why `outcome` doesn't match `case`, as report lines; empty when it passes
+pub fn check(case: &Case, outcome: &Outcome) -> Vec<String> {
+    let mut problems = Vec::new();
+    if outcome.timed_out {
+        problems.push("timed out".to_string());
+    }
+    if case.expected.is_none() && case.error.is_none() {
+        problems.push(format!("no {} or {} file", case.source.with_extension("expected").display(), case.source.with_extension("error").display()));
+    }
+    match &case.error {
+        Some(error) => {
+            let output = format!("{}\n{}", outcome.stdout, outcome.stderr);
+            for line in error.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                if !output.contains(line) {
+                    problems.push(format!("expected error not reported: {}", line));
+                }
+            }
+        }
+        None if outcome.code != Some(0) && !outcome.timed_out => {
+            problems.push(format!("exited with {}", outcome.code.map_or("a signal".to_string(), |c| c.to_string())));
+            problems.extend(outcome.stderr.lines().take(5).map(|l| format!("  {}", l)));
+        }
+        None => {}
+    }
+    if let Some(expected) = &case.expected {
+        let diff = diff_lines(expected, &outcome.stdout);
+        if !diff.is_empty() {
+            problems.push("stdout differs (-expected +actual):".to_string());
+            problems.extend(diff);
+        }
+    }
+    problems
+}
+
+/// `-`/`+` lines where `actual` departs from `expected`, each hunk headed by
+/// its line number in `expected`. Line endings and trailing whitespace are
+/// ignored.
+pub fn diff_lines(expected: &str, actual: &str) -> Vec<String> {
+    let a: Vec<&str> = expected.lines().map(str::trim_end).collect();
+    let b: Vec<&str> = actual.lines().map(str::trim_end).collect();
+    // Longest common subsequence table, from the end
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+            in_hunk = false;
+            continue;
+        }
+        if !in_hunk {
+            out.push(format!("@@ line {}", i + 1));
+            in_hunk = true;
+        }
+        if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(format!("-{}", a[i]));
+            i += 1;
+        } else {
+            out.push(format!("+{}", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Result of one case
+pub struct CaseResult {
+    pub case: Case,
+    pub outcome: Result<Outcome, String>,
+    pub problems: Vec<String>,
+}
+
+/// Run `cases` on `engine`, `jobs` at a time, calling `on_result` as each
+/// finishes (from worker threads, one at a time)
+pub fn run(
+    exe: &Path,
+    engine: &Engine,
+    cases: Vec<Case>,
+    jobs: usize,
+    timeout: Duration,
+    on_result: impl Fn(&CaseResult) + Sync,
+) -> Vec<CaseResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(cases.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(cases.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(case) = cases.get(i) else { break };
+                let file = case.source.to_string_lossy();
+                let outcome = selftest::run_engine(exe, engine, &file, &[], timeout);
+                let problems = match &outcome {
+                    Ok(outcome) => check(case, outcome),
+                    Err(e) => vec![format!("could not run: {}", e)],
+                };
+                let result = CaseResult { case: case.clone(), outcome, problems };
+                let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+                on_result(&result);
+                results.push((i, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(stdout: &str, stderr: &str, code: i32) -> Outcome {
+        Outcome { stdout: stdout.to_string(), stderr: stderr.to_string(), code: Some(code), timed_out: false }
+    }
+
+    fn case(expected: Option<&str>, error: Option<&str>) -> Case {
+        Case { source: PathBuf::from("t.cryo"), expected: expected.map(String::from), error: error.map(String::from) }
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert!(diff_lines("a\nb\r\nc \n", "a\nb\nc").is_empty());
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nx\nc\nd\n"), ["@@ line 2", "-b", "+x", "@@ line 4", "+d"]);
+        assert_eq!(diff_lines("a\n", ""), ["@@ line 1", "-a"]);
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(&case(Some("1\n2\n"), None), &outcome("1\n2\n", "", 0)).is_empty());
+        assert_eq!(check(&case(Some("1\n"), None), &outcome("1\n", "boom", 1)), ["exited with 1", "  boom"]);
+        // A failing program passes when its error is reported, wherever it's printed
+        let failing = case(None, Some("Runtime Error: division\n"));
+        assert!(check(&failing, &outcome("Runtime Error: division by zero\n", "", 0)).is_empty());
+        assert_eq!(check(&failing, &outcome("", "", 1)), ["expected error not reported: Runtime Error: division"]);
+        assert_eq!(check(&case(None, None), &outcome("", "", 0)).len(), 1);
+    }
+}
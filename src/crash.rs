@@ -0,0 +1,286 @@
+// ============================================
+// Cryo Crash Reporter
+// A panic inside cryo is a bug in cryo, not in the program it runs. Once
+// `install` has replaced the default hook, a panic prints the cryo version,
+// the Cryo call stack and the statement being run instead of a bare Rust
+// message, and writes a repro bundle directory holding:
+//
+//   report.txt     what was printed, plus the command line
+//   original.cryo  the program as it was run
+//   repro.cryo     with CRYO_CRASH_MINIMIZE=1, the program cut down to the
+//                  lines still needed to hit the same panic
+//
+// The cut-down copy is found by re-running cryo on smaller versions of the
+// program in child processes, so it works with panic=abort too. Those runs
+// repeat whatever the program does (files, network, ...), so they only
+// happen when asked for.
+// ============================================
+
+use crate::intern::Symbol;
+use crate::parser::Stmt;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Child runs the minimizer may spend on one crash
+const MAX_RUNS: usize = 200;
+/// Longest a single child run may take before it counts as not crashing
+const RUN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest the minimizer may take in total
+const TOTAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+thread_local! {
+    /// Cryo functions being run on this thread, outermost first, with the
+    /// line of their `fn`
+    static FRAMES: RefCell<Vec<(Symbol, usize)>> = const { RefCell::new(Vec::new()) };
+    /// Innermost statement being run on this thread, or null
+    static STATEMENT: Cell<*const Stmt> = const { Cell::new(std::ptr::null()) };
+}
+
+/// The program being run, for the report and the child runs
+struct Program {
+    path: String,
+    source: String,
+    /// Command line, with `path` where the program goes
+    args: Vec<String>,
+    bundle_dir: PathBuf,
+    minimize: bool,
+}
+
+static PROGRAM: Mutex<Option<Program>> = Mutex::new(None);
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Marks a Cryo function call until dropped
+pub struct FrameGuard(());
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        FRAMES.with(|f| f.borrow_mut().pop());
+    }
+}
+
+/// Record that `name` (defined on `line`) is running
+#[inline]
+pub fn enter(name: Symbol, line: usize) -> FrameGuard {
+    FRAMES.with(|f| f.borrow_mut().push((name, line)));
+    FrameGuard(())
+}
+
+/// Restores the statement that was running before when dropped
+pub struct StatementGuard(*const Stmt);
+
+impl Drop for StatementGuard {
+    fn drop(&mut self) {
+        STATEMENT.with(|s| s.set(self.0));
+    }
+}
+
+/// Record that `stmt` is running. The guard must be dropped before `stmt`
+/// is, which holding it for the statement's execution guarantees.
+#[inline]
+pub fn at(stmt: &Stmt) -> StatementGuard {
+    StatementGuard(STATEMENT.with(|s| s.replace(stmt)))
+}
+
+/// Replace the panic hook with the crash reporter for a run of `path`.
+/// `args` is the command line, `bundle_dir` where repro bundles go, and
+/// `minimize` whether to re-run the program to cut down repro.cryo.
+pub fn install(path: &str, source: &str, args: Vec<String>, bundle_dir: PathBuf, minimize: bool) {
+    *PROGRAM.lock().unwrap_or_else(|e| e.into_inner()) = Some(Program { path: path.to_string(), source: source.to_string(), args, bundle_dir, minimize });
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // A second panic (another thread, or one during the report) gets
+        // Rust's own message
+        if REPORTED.swap(true, Ordering::SeqCst) {
+            return default_hook(info);
+        }
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "<non-string panic>".to_string());
+        let location = info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())).unwrap_or_default();
+        let frames = FRAMES.with(|f| f.borrow().clone());
+        let statement = STATEMENT.with(|s| s.get());
+        // SAFETY: `at` only stores a statement for as long as it runs, and
+        // the hook runs on the panicking thread before anything unwinds
+        let statement = unsafe { statement.as_ref() }.map(|s| s.to_string());
+        let program = PROGRAM.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let path = program.as_ref().map_or("", |p| p.path.as_str());
+        let text = report(&message, &location, path, &frames, statement.as_deref());
+        eprint!("{}", text);
+        if std::env::var("RUST_BACKTRACE").is_ok_and(|v| v != "0") {
+            eprintln!("\nRust backtrace:\n{}", std::backtrace::Backtrace::force_capture());
+        }
+        if let Some(program) = program {
+            match write_bundle(&program, &location, &text) {
+                Ok((dir, Some(kept))) => {
+                    let total = program.source.lines().count();
+                    eprintln!("A repro bundle was written to {} (repro.cryo keeps {} of {} lines).", dir.display(), kept, total);
+                    eprintln!("Please attach it to an issue at https://github.com/TheFahmi/argon-lang/issues");
+                }
+                Ok((dir, None)) => {
+                    eprintln!("A repro bundle was written to {}.", dir.display());
+                    eprintln!("Run again with CRYO_CRASH_MINIMIZE=1 to add a cut-down repro.cryo (this re-runs the program).");
+                    eprintln!("Please attach it to an issue at https://github.com/TheFahmi/argon-lang/issues");
+                }
+                Err(e) => eprintln!("The repro bundle could not be written: {}", e),
+            }
+        }
+    }));
+}
+
+/// What the reporter prints for a panic with `message` at `location`
+pub fn report(message: &str, location: &str, path: &str, frames: &[(Symbol, usize)], statement: Option<&str>) -> String {
+    let mut out = format!("cryo: internal error: {}\n", message);
+    out.push_str(&format!("  --> {}\n", location));
+    out.push_str(&format!("  = cryo {} ({} {})\n", env!("CARGO_PKG_VERSION"), std::env::consts::OS, std::env::consts::ARCH));
+    if let Some(statement) = statement {
+        // Loops and blocks print their whole body; the first line is enough
+        let mut lines = statement.lines();
+        let first = lines.next().unwrap_or("");
+        out.push_str(&format!("  = while running: {}{}\n", first.trim(), if lines.next().is_some() { " ..." } else { "" }));
+    }
+    if !frames.is_empty() {
+        out.push_str("\nCryo call stack (most recent call first):\n");
+        for (i, (name, line)) in frames.iter().rev().enumerate() {
+            match line {
+                0 => out.push_str(&format!("  {}: {}\n", i, name)),
+                _ => out.push_str(&format!("  {}: {} ({}:{})\n", i, name, path, line)),
+            }
+        }
+    }
+    out.push_str("\nThis is a bug in cryo, not in your program.\n");
+    out
+}
+
+/// Write the bundle for a panic at `location`; returns its directory and
+/// how many lines repro.cryo kept, if it was minimized
+fn write_bundle(program: &Program, location: &str, report: &str) -> Result<(PathBuf, Option<usize>), String> {
+    // FNV-1a, so the same crash always lands in the same directory
+    let hash = location.bytes().chain(program.source.bytes()).fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3));
+    let dir = program.bundle_dir.join(format!("cryo-crash-{:016x}", hash));
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let command = program.args.iter().map(|a| if a == &program.path { "original.cryo" } else { a.as_str() }).collect::<Vec<_>>().join(" ");
+    fs::write(dir.join("report.txt"), format!("{}\nCommand: {}\n", report, command)).map_err(|e| e.to_string())?;
+    fs::write(dir.join("original.cryo"), &program.source).map_err(|e| e.to_string())?;
+    if !program.minimize {
+        return Ok((dir, None));
+    }
+
+    eprintln!("\nMinimizing the program...");
+    let candidate = dir.join("candidate.cryo");
+    let deadline = Instant::now() + TOTAL_TIMEOUT;
+    let repro = minimize(&program.source, MAX_RUNS, |text| Instant::now() < deadline && crashes(program, &candidate, &dir, text, location));
+    let _ = fs::remove_file(&candidate);
+    let _ = fs::remove_file(dir.join("candidate.err"));
+    fs::write(dir.join("repro.cryo"), &repro).map_err(|e| e.to_string())?;
+    Ok((dir, Some(repro.lines().count())))
+}
+
+/// Whether cryo, run as before on `source` instead of the program, panics
+/// at `location` again
+fn crashes(program: &Program, candidate: &Path, dir: &Path, source: &str, location: &str) -> bool {
+    let err_path = dir.join("candidate.err");
+    let (Ok(()), Ok(err)) = (fs::write(candidate, source), fs::File::create(&err_path)) else { return false };
+    let Ok(exe) = std::env::current_exe() else { return false };
+    let candidate = candidate.to_string_lossy();
+    let args = program.args.iter().skip(1).map(|a| if a == &program.path { candidate.as_ref() } else { a.as_str() });
+    let mut command = Command::new(exe);
+    // The candidate lives in the bundle; the original's directory goes on
+    // the module path so its imports still resolve
+    if let Some(home) = Path::new(&program.path).parent().filter(|_| program.path != "-") {
+        let home = if home.as_os_str().is_empty() { Path::new(".") } else { home };
+        let rest = std::env::var_os("CRYO_PATH").or_else(|| std::env::var_os("ARGON_PATH")).unwrap_or_default();
+        if let Ok(path) = std::env::join_paths(std::iter::once(home.to_path_buf()).chain(std::env::split_paths(&rest))) {
+            command.env("CRYO_PATH", path);
+        }
+    }
+    let child = command
+        .args(args)
+        .env("CRYO_CRASH_DIR", "off")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(err)
+        .spawn();
+    let Ok(mut child) = child else { return false };
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                return !status.success() && fs::read_to_string(&err_path).is_ok_and(|e| e.contains(&format!("panicked at {}", location)));
+            }
+            Ok(None) if started.elapsed() < RUN_TIMEOUT => std::thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+        }
+    }
+}
+
+/// The smallest version of `source` found, removing ever smaller runs of
+/// lines, for which `still_fails` holds. Calls it at most `max_runs` times.
+pub fn minimize(source: &str, max_runs: usize, mut still_fails: impl FnMut(&str) -> bool) -> String {
+    let join = |lines: &[&str]| lines.iter().map(|l| format!("{}\n", l)).collect::<String>();
+    let mut lines: Vec<&str> = source.lines().collect();
+    let mut runs = 0;
+    // Blank and comment lines rarely matter; drop them all in one go first
+    let code: Vec<&str> = lines.iter().copied().filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with("//")).collect();
+    if code.len() < lines.len() && max_runs > 0 {
+        runs += 1;
+        if still_fails(&join(&code)) {
+            lines = code;
+        }
+    }
+    let mut chunk = lines.len().div_ceil(2);
+    while chunk > 0 && runs < max_runs {
+        let mut start = 0;
+        while start < lines.len() && runs < max_runs {
+            let end = (start + chunk).min(lines.len());
+            let candidate: Vec<&str> = lines[..start].iter().chain(&lines[end..]).copied().collect();
+            runs += 1;
+            if still_fails(&join(&candidate)) {
+                lines = candidate;
+            } else {
+                start = end;
+            }
+        }
+        chunk /= 2;
+    }
+    join(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let frames = [(Symbol::intern("main"), 1), (Symbol::intern("parse"), 7), (Symbol::intern("<lambda>"), 0)];
+        let text = report("index out of bounds", "src/interpreter.rs:10:5", "app.cryo", &frames, Some("(while true\n  ...)"));
+        assert!(text.starts_with("cryo: internal error: index out of bounds\n  --> src/interpreter.rs:10:5\n"));
+        assert!(text.contains("  = while running: (while true ...\n"));
+        assert!(text.contains("  0: <lambda>\n  1: parse (app.cryo:7)\n  2: main (app.cryo:1)\n"));
+    }
+
+    #[test]
+    fn test_minimize() {
+        let source = "fn main() {\n    // setup\n    let a = 1;\n\n    let b = 2;\n    boom(a);\n    print(b);\n}\n";
+        let mut runs = 0;
+        let repro = minimize(source, 100, |text| {
+            runs += 1;
+            text.contains("fn main() {") && text.contains("boom(a)") && text.contains("let a") && text.contains('}')
+        });
+        assert_eq!(repro, "fn main() {\n    let a = 1;\n    boom(a);\n}\n");
+        assert!(runs <= 100);
+        // Out of runs: whatever was found so far
+        assert_eq!(minimize(source, 0, |_| true), source);
+    }
+}
@@ -0,0 +1,182 @@
+// ============================================
+// Cryo Debugger
+// `debugger()` pauses the interpreter at a `(cryo-dbg)` prompt. With
+// `--history N` every call frame also keeps its last N variable
+// assignments, with where they happened, and `history x` lists the ones
+// to `x` newest first.
+// ============================================
+
+use crate::intern::Symbol;
+use crate::interpreter::Value;
+use std::collections::VecDeque;
+
+/// One recorded `let` or assignment
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    /// Counts the frame's assignments from 1
+    pub seq: u64,
+    pub name: Symbol,
+    /// A deep copy, so changing the array in place later doesn't rewrite it
+    pub value: Value,
+    /// 1-based line and column of the statement, when the program has positions
+    pub at: Option<(usize, usize)>,
+}
+
+/// A call frame's last assignments, oldest first
+#[derive(Debug)]
+pub struct History {
+    entries: VecDeque<Assignment>,
+    capacity: usize,
+    recorded: u64,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History { entries: VecDeque::new(), capacity: capacity.max(1), recorded: 0 }
+    }
+
+    /// Record `name = value`, dropping the oldest entry when full
+    pub fn record(&mut self, name: Symbol, value: &Value, at: Option<(usize, usize)>) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.recorded += 1;
+        self.entries.push_back(Assignment { seq: self.recorded, name, value: value.deep_clone(), at });
+    }
+
+    /// The kept assignments to `name`, newest first
+    pub fn of(&self, name: Symbol) -> impl Iterator<Item = &Assignment> {
+        self.entries.iter().rev().filter(move |a| a.name == name)
+    }
+}
+
+/// A line typed at the prompt
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    History(String),
+    Print(String),
+    Where,
+    Continue,
+    Help,
+    Unknown(String),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Command {
+        let mut words = line.split_whitespace();
+        let word = words.next().unwrap_or("");
+        let arg = words.next().map(str::to_string);
+        match (word, arg) {
+            ("history" | "h", Some(name)) => Command::History(name),
+            ("print" | "p", Some(name)) => Command::Print(name),
+            ("where" | "w", None) => Command::Where,
+            ("continue" | "c" | "", None) => Command::Continue,
+            ("help" | "?", None) => Command::Help,
+            _ => Command::Unknown(line.trim().to_string()),
+        }
+    }
+}
+
+pub const HELP: &str = "commands:
+  history NAME (h)   earlier values of NAME in this frame, newest first (needs --history N)
+  print NAME (p)     the current value of NAME
+  where (w)          the statement about to run
+  continue (c)       resume the program; so does an empty line or end of input";
+
+/// A value as the prompt shows it: strings quoted, everything else as printed
+pub fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", &**s),
+        v => v.to_string_val(),
+    }
+}
+
+/// `path:line:col  source line` for a statement position
+pub fn location(at: Option<(usize, usize)>, source: Option<&(String, String)>) -> String {
+    let Some((line, col)) = at else {
+        return "(no position)".to_string();
+    };
+    match source {
+        Some((path, text)) => {
+            let code = text.lines().nth(line - 1).unwrap_or("").trim();
+            format!("{}:{}:{}  {}", path, line, col, code)
+        }
+        None => format!("{}:{}", line, col),
+    }
+}
+
+/// The `history NAME` listing
+pub fn render_history(entries: &[&Assignment], source: Option<&(String, String)>) -> String {
+    let assigned: Vec<String> = entries.iter().map(|a| format!("{} = {}", a.name, show(&a.value))).collect();
+    let width = assigned.iter().map(String::len).max().unwrap_or(0);
+    entries
+        .iter()
+        .zip(&assigned)
+        .map(|(a, text)| format!("  #{:<3} {:<width$}  {}", a.seq, text, location(a.at, source), width = width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::{lexer, output, parser::Parser};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_history_is_a_ring_per_frame() {
+        let (x, y) = (Symbol::intern("x"), Symbol::intern("y"));
+        let mut history = History::new(3);
+        for n in 1..=4 {
+            history.record(x, &Value::Int(n), Some((n as usize, 5)));
+        }
+        history.record(y, &Value::Int(9), None);
+        let seen: Vec<(u64, i64)> = history.of(x).map(|a| (a.seq, a.value.as_int())).collect();
+        assert_eq!(seen, [(4, 4), (3, 3)]);
+        assert_eq!(Command::parse("h total"), Command::History("total".to_string()));
+        assert_eq!(Command::parse(""), Command::Continue);
+        assert_eq!(Command::parse("step"), Command::Unknown("step".to_string()));
+    }
+
+    #[test]
+    fn test_history_command() {
+        let source = "fn add(list) {
+    let total = 0;
+    let i = 0;
+    while (i < len(list)) {
+        total = total + list[i];
+        i = i + 1;
+    }
+    debugger();
+    return total;
+}
+
+fn main() {
+    let items = [10, 20];
+    print(add(items));
+    push(items, 30);
+    debugger();
+}
+";
+        let (tokens, lines, columns, _) = lexer::tokenize_with_positions(source);
+        let ast = Parser::with_lines(&tokens, &lines).with_columns(&columns).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.set_source("sum.cryo", source);
+        interp.set_history(Some(4));
+        let commands = "history total\nprint i\nwhere\nc\nhistory items\nhistory total\nstep\n";
+        interp.set_debug_input(Box::new(Cursor::new(commands)));
+        let (result, printed) = output::capture(|| interp.run(&ast));
+        assert!(result.is_ok());
+        let expected = "(cryo-dbg)   #5   total = 30  sum.cryo:5:9  total = total + list[i];
+  #3   total = 10  sum.cryo:5:9  total = total + list[i];
+(cryo-dbg) 2
+(cryo-dbg) sum.cryo:8:5  debugger();
+(cryo-dbg) 30
+(cryo-dbg)   #1   items = [10, 20]  sum.cryo:13:5  let items = [10, 20];
+(cryo-dbg) no assignments to total recorded in this frame
+(cryo-dbg) unknown command 'step'; try help
+(cryo-dbg) ";
+        assert_eq!(printed, expected);
+    }
+}
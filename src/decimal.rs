@@ -0,0 +1,331 @@
+// ============================================
+// Cryo Decimal Module
+// Exact base-10 fixed-point numbers for money arithmetic
+// ============================================
+
+use crate::bigint::BigInt;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Fraction digits kept by the `/` operator before trailing zeros are trimmed
+pub const DIV_SCALE: u32 = 16;
+
+/// Largest scale a decimal may carry; keeps `10^scale` from exhausting memory
+pub const MAX_SCALE: u32 = 4096;
+
+/// How to round away digits that don't fit the target scale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Ties go away from zero (the usual commercial rounding)
+    HalfUp,
+    /// Ties go toward zero
+    HalfDown,
+    /// Ties go to the even neighbour (banker's rounding)
+    HalfEven,
+    /// Away from zero
+    Up,
+    /// Toward zero (truncate)
+    Down,
+    /// Toward positive infinity
+    Ceiling,
+    /// Toward negative infinity
+    Floor,
+}
+
+impl Rounding {
+    pub fn from_name(name: &str) -> Option<Rounding> {
+        match name.to_ascii_lowercase().replace('-', "_").as_str() {
+            "half_up" => Some(Rounding::HalfUp),
+            "half_down" => Some(Rounding::HalfDown),
+            "half_even" | "bankers" => Some(Rounding::HalfEven),
+            "up" => Some(Rounding::Up),
+            "down" | "truncate" => Some(Rounding::Down),
+            "ceiling" | "ceil" => Some(Rounding::Ceiling),
+            "floor" => Some(Rounding::Floor),
+            _ => None,
+        }
+    }
+}
+
+/// `coeff * 10^-scale`. The scale is part of the value's identity for
+/// display (`1.50` keeps its zero) but not for comparison (`1.5 == 1.50`).
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    coeff: BigInt,
+    scale: u32,
+}
+
+fn pow10(n: u32) -> BigInt {
+    BigInt::from_i64(10).pow(n)
+}
+
+/// `n / d` rounded to an integer with `mode`
+fn div_round(n: &BigInt, d: &BigInt, mode: Rounding) -> BigInt {
+    let (q, r) = match n.div_rem(d) {
+        Some(qr) => qr,
+        None => return BigInt::zero(),
+    };
+    if r.is_zero() {
+        return q;
+    }
+    let negative = n.is_negative() != d.is_negative();
+    // Compare the discarded fraction |r/d| against one half
+    let half = r.abs().mul(&BigInt::from_i64(2)).cmp(&d.abs());
+    let away = match mode {
+        Rounding::Down => false,
+        Rounding::Up => true,
+        Rounding::Ceiling => !negative,
+        Rounding::Floor => negative,
+        Rounding::HalfUp => half != Ordering::Less,
+        Rounding::HalfDown => half == Ordering::Greater,
+        Rounding::HalfEven => half == Ordering::Greater || half == Ordering::Equal && q.is_odd(),
+    };
+    if !away {
+        q
+    } else if negative {
+        q.sub(&BigInt::from_i64(1))
+    } else {
+        q.add(&BigInt::from_i64(1))
+    }
+}
+
+impl Decimal {
+    pub fn new(coeff: BigInt, scale: u32) -> Decimal {
+        Decimal { coeff, scale }
+    }
+
+    pub fn from_bigint(n: BigInt) -> Decimal {
+        Decimal { coeff: n, scale: 0 }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coeff.is_zero()
+    }
+
+    /// Parse `-12.50`, `+3`, `.5` or exponent forms like `1.2e3`. The scale is
+    /// the number of fraction digits written, so `"1.50"` keeps two places.
+    pub fn parse(text: &str) -> Option<Decimal> {
+        let text = text.trim();
+        let (mantissa, exp) = match text.find(['e', 'E']) {
+            Some(i) => (&text[..i], text[i + 1..].parse::<i64>().ok()?),
+            None => (text, 0),
+        };
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let int_digits = int_part.trim_start_matches(['-', '+']);
+        if int_digits.is_empty() && frac_part.is_empty() {
+            return None;
+        }
+        let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if !all_digits(int_digits) || !all_digits(frac_part) || int_part.len() > int_digits.len() + 1 {
+            return None;
+        }
+        let sign = &int_part[..int_part.len() - int_digits.len()];
+        let coeff = BigInt::parse(&format!("{}0{}{}", sign, int_digits, frac_part))?;
+        let scale = frac_part.len() as i64 - exp;
+        if scale > MAX_SCALE as i64 || scale < -(MAX_SCALE as i64) {
+            return None;
+        }
+        if scale < 0 {
+            Some(Decimal::new(coeff.mul(&pow10((-scale) as u32)), 0))
+        } else {
+            Some(Decimal::new(coeff, scale as u32))
+        }
+    }
+
+    pub fn neg(&self) -> Decimal {
+        Decimal::new(self.coeff.neg(), self.scale)
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal::new(self.coeff.abs(), self.scale)
+    }
+
+    /// The integer part, truncated toward zero
+    pub fn trunc(&self) -> BigInt {
+        self.rescale(0, Rounding::Down).coeff
+    }
+
+    /// Same value with exactly `scale` fraction digits, rounding with `mode`
+    /// when digits are dropped
+    pub fn rescale(&self, scale: u32, mode: Rounding) -> Decimal {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => self.clone(),
+            Ordering::Greater => Decimal::new(self.coeff.mul(&pow10(scale - self.scale)), scale),
+            Ordering::Less => Decimal::new(div_round(&self.coeff, &pow10(self.scale - scale), mode), scale),
+        }
+    }
+
+    /// Both coefficients at the larger of the two scales
+    fn aligned(&self, other: &Decimal) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(other.scale);
+        let a = self.rescale(scale, Rounding::Down).coeff;
+        let b = other.rescale(scale, Rounding::Down).coeff;
+        (a, b, scale)
+    }
+
+    pub fn add(&self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        Decimal::new(a.add(&b), scale)
+    }
+
+    pub fn sub(&self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        Decimal::new(a.sub(&b), scale)
+    }
+
+    pub fn mul(&self, other: &Decimal) -> Decimal {
+        Decimal::new(self.coeff.mul(&other.coeff), self.scale + other.scale)
+    }
+
+    /// Quotient with `scale` fraction digits, rounded with `mode`. None when
+    /// dividing by zero.
+    pub fn div(&self, other: &Decimal, scale: u32, mode: Rounding) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        // (a / 10^sa) / (b / 10^sb) = q / 10^scale  =>  q = a * 10^(sb + scale) / (b * 10^sa)
+        let n = self.coeff.mul(&pow10(other.scale + scale));
+        let d = other.coeff.mul(&pow10(self.scale));
+        Some(Decimal::new(div_round(&n, &d, mode), scale))
+    }
+
+    /// Remainder of truncating division; takes the dividend's sign like `%` on ints
+    pub fn rem(&self, other: &Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other);
+        a.div_rem(&b).map(|(_, r)| Decimal::new(r, scale))
+    }
+
+    /// Drop trailing fraction zeros, but keep at least `min_scale` places
+    pub fn trim(&self, min_scale: u32) -> Decimal {
+        let ten = BigInt::from_i64(10);
+        let mut out = self.clone();
+        while out.scale > min_scale {
+            match out.coeff.div_rem(&ten) {
+                Some((q, r)) if r.is_zero() => {
+                    out.coeff = q;
+                    out.scale -= 1;
+                }
+                _ => break,
+            }
+        }
+        out
+    }
+
+    /// Render with a thousands separator in the integer part and a custom
+    /// decimal point, e.g. `1.234.567,89`
+    pub fn format(&self, thousands_sep: &str, decimal_sep: &str) -> String {
+        let plain = self.to_string();
+        let (sign, unsigned) = match plain.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", plain.as_str()),
+        };
+        let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let mut out = format!("{}{}", sign, crate::text::group_digits(int_part, thousands_sep));
+        if !frac_part.is_empty() {
+            out.push_str(decimal_sep);
+            out.push_str(frac_part);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.coeff.abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.coeff.is_negative() { "-" } else { "" };
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = scale + 1);
+        let (int_part, frac_part) = padded.split_at(padded.len() - scale);
+        write!(f, "{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let (a, b, _) = self.aligned(other);
+        a.cmp(&b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for s in ["0", "10.25", "-0.05", "1.50", "123456789012345678901234.000001"] {
+            assert_eq!(dec(s).to_string(), s);
+        }
+        assert_eq!(dec(".5").to_string(), "0.5");
+        assert_eq!(dec("-.5").to_string(), "-0.5");
+        assert_eq!(dec("1.2e3").to_string(), "1200");
+        assert_eq!(dec("12e-3").to_string(), "0.012");
+        assert_eq!(dec("-0.00").to_string(), "0.00");
+        for bad in ["", ".", "-", "1.2.3", "1,5", "--1", "1-", "abc", "1e99999"] {
+            assert!(Decimal::parse(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(dec("0.1").add(&dec("0.2")), dec("0.3"));
+        assert_eq!(dec("10.25").sub(&dec("0.255")).to_string(), "9.995");
+        assert_eq!(dec("19.99").mul(&dec("3")).to_string(), "59.97");
+        assert_eq!(dec("1.5").mul(&dec("-1.5")).to_string(), "-2.25");
+        assert_eq!(dec("7.5").rem(&dec("2")).unwrap().to_string(), "1.5");
+        assert!(dec("1").div(&dec("0"), 2, Rounding::HalfUp).is_none());
+        assert!(dec("1.5") == dec("1.50") && dec("-2") < dec("-1.99"));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let third = dec("10").div(&dec("3"), 4, Rounding::HalfEven).unwrap();
+        assert_eq!(third.to_string(), "3.3333");
+        let cases = [
+            ("2.5", Rounding::HalfUp, "3"), ("2.5", Rounding::HalfDown, "2"), ("2.5", Rounding::HalfEven, "2"),
+            ("3.5", Rounding::HalfEven, "4"), ("-2.5", Rounding::HalfUp, "-3"), ("-2.5", Rounding::HalfEven, "-2"),
+            ("2.1", Rounding::Up, "3"), ("-2.9", Rounding::Down, "-2"), ("-2.1", Rounding::Ceiling, "-2"),
+            ("-2.1", Rounding::Floor, "-3"), ("2.51", Rounding::HalfDown, "3"),
+        ];
+        for (input, mode, expected) in cases {
+            assert_eq!(dec(input).rescale(0, mode).to_string(), expected, "{} {:?}", input, mode);
+        }
+        assert_eq!(dec("1.005").rescale(2, Rounding::HalfUp).to_string(), "1.01");
+        assert_eq!(dec("1.5").rescale(3, Rounding::HalfUp).to_string(), "1.500");
+        assert_eq!(dec("2.500").trim(1).to_string(), "2.5");
+        assert_eq!(dec("2.000").trim(0).to_string(), "2");
+        assert_eq!(Rounding::from_name("HALF-EVEN"), Some(Rounding::HalfEven));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(dec("1234567.89").format(",", "."), "1,234,567.89");
+        assert_eq!(dec("-1234567.891").rescale(2, Rounding::HalfUp).format(".", ","), "-1.234.567,89");
+        assert_eq!(dec("999").format(",", "."), "999");
+    }
+}
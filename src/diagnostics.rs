@@ -0,0 +1,323 @@
+// ============================================
+// Cryo Diagnostic Codes
+// Stable codes for parse and runtime errors, with the longer explanation
+// `cryo explain CODE` prints. Errors are still plain strings: a message gets
+// its code from the first entry with a pattern it contains, so the codes
+// don't change what programs print.
+//
+// E01xx are parse errors and E02xx runtime errors. A code is never reused
+// for a different error; one that stops being reported stays in the table.
+//
+// `located` is how an error with a known place is shown:
+//
+//   app.cryo:3:18: error: Unexpected token: Semi
+//      3 |     let x = (1 + ;
+//        |                  ^
+// ============================================
+
+use crate::lexer::Span;
+
+pub struct Code {
+    pub code: &'static str,
+    pub title: &'static str,
+    /// Text a message contains when it has this code
+    patterns: &'static [&'static str],
+    pub explanation: &'static str,
+    pub example: &'static str,
+    pub fix: &'static str,
+}
+
+pub const CODES: &[Code] = &[
+    Code {
+        code: "E0101",
+        title: "unexpected token at top level",
+        patterns: &["Unexpected token at top level"],
+        explanation: "Outside a function, a file may only contain items: fn, struct, enum, trait, impl, \
+import, let, macro and extern. A statement such as a call or an assignment has to go inside a function.",
+        example: "print(\"hello\");\n\nfn main() {}",
+        fix: "Move the statement into main() or another function.",
+    },
+    Code {
+        code: "E0102",
+        title: "unexpected token",
+        patterns: &["Unexpected token"],
+        explanation: "The parser found a token that can't start or continue an expression here. This is \
+usually an operator with nothing after it, or a bracket that was closed too early.",
+        example: "fn main() {\n    print(1 + );\n}",
+        fix: "Finish the expression, or remove the stray token.",
+    },
+    Code {
+        code: "E0103",
+        title: "missing name",
+        patterns: &[
+            "Expected function name",
+            "Expected variable name",
+            "Expected struct name",
+            "Expected enum name",
+            "Expected trait name",
+            "Expected type name",
+            "Expected macro name",
+            "Expected parameter name",
+            "Expected field name",
+            "Expected static method name",
+            "Expected identifier",
+            "Expected key in object literal",
+        ],
+        explanation: "A keyword that defines something (fn, let, struct, enum, trait, macro), a `.`, \
+a `::` or an `@` must be followed by a name. Keywords and literals can't be used as names.",
+        example: "fn main() {\n    let = 3;\n}",
+        fix: "Add the name, or rename it if it is a keyword such as `fn` or `match`.",
+    },
+    Code {
+        code: "E0104",
+        title: "invalid assignment target",
+        patterns: &["Invalid assignment target"],
+        explanation: "Only a variable, an index (`a[i]`) or a field (`p.x`) can be assigned to. The left \
+side of this `=` is some other expression.",
+        example: "fn main() {\n    let a = 1;\n    a + 1 = 2;\n}",
+        fix: "Assign to a variable. Use `==` if you meant to compare.",
+    },
+    Code {
+        code: "E0105",
+        title: "malformed import",
+        patterns: &["Expected import path", "Expected 'from' after import block"],
+        explanation: "An import names a module as a string: `import \"path\"`, or `import { a, b } from \
+\"path\"` to import some of its names.",
+        example: "import { sqrt } \"math\"",
+        fix: "Quote the path, and write `from` after a braced list of names.",
+    },
+    Code {
+        code: "E0106",
+        title: "expected a different token",
+        patterns: &["Expected "],
+        explanation: "The parser needed a particular token here, such as the `(` around an `if` \
+condition or the `{` of a block, and found something else. The message names both.",
+        example: "fn main() {\n    if x > 1 { print(x); }\n}",
+        fix: "Add the missing token. Conditions of `if` and `while` need parentheses.",
+    },
+    Code {
+        code: "E0201",
+        title: "undefined function",
+        patterns: &["Undefined function"],
+        explanation: "A call names a function that isn't defined in the file, in a module it imports, or \
+as a builtin. Names are case-sensitive, and a function in another file needs an import.",
+        example: "fn main() {\n    prnt(\"hello\");\n}",
+        fix: "Check the spelling, or import the file that defines it. The LSP and `cryo refactor actions` offer the import.",
+    },
+    Code {
+        code: "E0202",
+        title: "undefined method",
+        patterns: &["Undefined method", "Undefined static method"],
+        explanation: "A method call `value.name()` or `Type::name()` names a method that no impl block \
+defines for that type.",
+        example: "struct Point { x, y }\n\nfn main() {\n    let p = Point { x: 1, y: 2 };\n    p.length();\n}",
+        fix: "Define the method in an `impl Point { ... }` block, or call a function that exists.",
+    },
+    Code {
+        code: "E0203",
+        title: "module not found",
+        patterns: &["Module not found"],
+        explanation: "An import names a file that doesn't exist. Paths are looked up next to the \
+importing file, then under stdlib/.",
+        example: "import \"utils/strings\"",
+        fix: "Check the path. The `.cryo` extension can be left out.",
+    },
+    Code {
+        code: "E0204",
+        title: "value is frozen",
+        patterns: &["is frozen"],
+        explanation: "freeze() makes an array or object immutable, along with everything inside it. \
+Pushing, sorting, or assigning to an element or field of it is an error.",
+        example: "fn main() {\n    let hosts = freeze([\"a\", \"b\"]);\n    push(hosts, \"c\");\n}",
+        fix: "Change a copy (`clone(hosts)`), or build the value before freezing it.",
+    },
+    Code {
+        code: "E0205",
+        title: "not permitted in this sandbox",
+        patterns: &["is not permitted in this sandbox"],
+        explanation: "Code run with `eval(code, { sandbox: true })` can't use files, the network, \
+processes or the environment, and a policy's `deny` list refuses more builtins by name.",
+        example: "eval(\"env(\\\"HOME\\\")\", { sandbox: true })",
+        fix: "Name the builtin in the policy's `allow` list, or don't call it from sandboxed code.",
+    },
+    Code {
+        code: "E0206",
+        title: "feature not built in",
+        patterns: &["is not available: cryo was built without"],
+        explanation: "This builtin belongs to an optional cargo feature that this build of cryo \
+doesn't have. The playground, for example, is built without threads.",
+        example: "thread_spawn(\"worker\")",
+        fix: "Use a cryo built with the feature the message names (`cargo build --features ...`).",
+    },
+    Code {
+        code: "E0207",
+        title: "assertion failed",
+        patterns: &["Assertion Error"],
+        explanation: "assert() was called with a false condition. The message is the one given to \
+assert(), if any.",
+        example: "fn main() {\n    assert(1 + 1 == 3, \"math is broken\");\n}",
+        fix: "Fix the code the assertion checks, or the assertion if it is wrong.",
+    },
+    Code {
+        code: "E0208",
+        title: "trait not implemented",
+        patterns: &["does not implement trait", "is missing method"],
+        explanation: "A value was used where a trait is required, but its type has no `impl Trait for \
+Type`, or that impl leaves out one of the trait's methods.",
+        example: "trait Shape { fn area(self); }\nstruct Square { side }\nimpl Shape for Square {}",
+        fix: "Implement every method the trait declares.",
+    },
+    Code {
+        code: "E0209",
+        title: "unknown operator",
+        patterns: &["Unknown operator"],
+        explanation: "The operator can't be applied to these operand types, such as `-` on two strings.",
+        example: "fn main() {\n    print(\"a\" * \"b\");\n}",
+        fix: "Convert the operands first, for example with int() or str().",
+    },
+    Code {
+        code: "E0210",
+        title: "missing field",
+        patterns: &["Missing field"],
+        explanation: "A struct value doesn't have the field being read. The message lists the fields \
+it does have.",
+        example: "struct Point { x, y }\n\nfn main() {\n    let p = Point { x: 1, y: 2 };\n    print(p.z);\n}",
+        fix: "Check the field name against the struct definition.",
+    },
+    Code {
+        code: "E0211",
+        title: "undefined enum variant",
+        patterns: &["has no variant", "Undefined enum"],
+        explanation: "`Enum::Variant` names an enum that isn't declared, or a variant the enum doesn't \
+have. Match patterns are checked the same way when they are tried.",
+        example: "enum Color { Red, Green }\n\nfn main() {\n    print(Color::Blue);\n}",
+        fix: "Check the spelling, or add the variant to the enum.",
+    },
+    Code {
+        code: "E0212",
+        title: "integer out of range",
+        patterns: &["does not fit in", "needs an integer"],
+        explanation: "A variable, parameter or return value annotated with a sized integer type (i8, i16, \
+i32, i64, u8, u16, u32, u64) was given a value outside that type's range, or a value that isn't an \
+integer. Annotations check values; they never truncate them.",
+        example: "fn main() {\n    let b: u8 = 256;\n}",
+        fix: "Use a wider type, or write `value as u8` where wrapping to the low bits is intended.",
+    },
+];
+
+/// The code for an error message, if it has one
+pub fn code_for(message: &str) -> Option<&'static str> {
+    CODES.iter().find(|c| c.patterns.iter().any(|p| message.contains(p))).map(|c| c.code)
+}
+
+/// The entry for `code`, in any case
+pub fn lookup(code: &str) -> Option<&'static Code> {
+    CODES.iter().find(|c| c.code.eq_ignore_ascii_case(code))
+}
+
+/// The line printed after an error that has a code
+pub fn help(message: &str) -> Option<String> {
+    code_for(message).map(|code| format!("  = help: run `cryo explain {}` for more about this error", code))
+}
+
+/// `path:line:col: error: message`, then source line `line` with `^` under
+/// `width` characters from column `col` (to the end of the line when 0).
+/// Lines and columns are 1-based, columns counted in characters; without
+/// the source line only the first line is given.
+pub fn located(path: &str, source: &str, line: usize, col: usize, width: usize, message: &str) -> String {
+    let mut out = format!("{}:{}:{}: error: {}\n", path, line, col, message);
+    let Some(text) = line.checked_sub(1).and_then(|i| source.lines().nth(i)) else {
+        return out;
+    };
+    let text = text.trim_end();
+    let gutter = line.to_string().len();
+    // Tabs stay tabs so the caret lines up however they're shown
+    let pad: String = text.chars().take(col.saturating_sub(1)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let rest = text.chars().count().saturating_sub(col.saturating_sub(1));
+    let width = if width == 0 { rest } else { width.min(rest) };
+    out.push_str(&format!("{:>w$} | {}\n", line, text, w = gutter + 4));
+    out.push_str(&format!("{:>w$} | {}{}\n", "", pad, "^".repeat(width.max(1)), w = gutter + 4));
+    out
+}
+
+/// Line, column and width of a byte span, as `located` takes them. A span
+/// running onto later lines is underlined to the end of its first line.
+pub fn span_position(source: &str, span: Span) -> Option<(usize, usize, usize)> {
+    let text = source.get(span.start..span.end)?;
+    let before = &source[..span.start];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    let width = if text.contains('\n') { 0 } else { text.chars().count() };
+    Some((line, col, width))
+}
+
+/// What `cryo explain` prints for `code`
+pub fn render(code: &Code) -> String {
+    let example: String = code.example.lines().map(|l| format!("    {}\n", l)).collect();
+    format!("{}: {}\n\n{}\n\nExample:\n\n{}\n{}\n", code.code, code.title, wrap(code.explanation), example, wrap(&format!("Fix: {}", code.fix)))
+}
+
+/// `text` broken into lines of at most 76 columns, between words
+fn wrap(text: &str) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+    for word in text.split_whitespace() {
+        if width > 0 && width + 1 + word.len() > 76 {
+            out.push('\n');
+            width = 0;
+        } else if width > 0 {
+            out.push(' ');
+            width += 1;
+        }
+        out.push_str(word);
+        width += word.len();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes() {
+        let codes: HashSet<_> = CODES.iter().map(|c| c.code).collect();
+        assert_eq!(codes.len(), CODES.len());
+        assert_eq!(code_for("Unexpected token at top level: Identifier(\"print\")"), Some("E0101"));
+        assert_eq!(code_for("Unexpected token: Semi"), Some("E0102"));
+        assert_eq!(code_for("Expected variable name"), Some("E0103"));
+        assert_eq!(code_for("Expected LParen, got Identifier(\"x\")"), Some("E0106"));
+        assert_eq!(code_for("sort: array is frozen"), Some("E0204"));
+        assert_eq!(code_for("division by zero"), None);
+        assert_eq!(lookup("e0201").map(|c| c.title), Some("undefined function"));
+        assert!(render(lookup("E0102").unwrap()).starts_with("E0102: unexpected token\n\n"));
+    }
+
+    #[test]
+    fn test_located() {
+        let source = "fn main() {\n\tlet x = (1 + ;\n}\n";
+        assert_eq!(located("app.cryo", source, 2, 15, 1, "Unexpected token: Semi"), [
+            "app.cryo:2:15: error: Unexpected token: Semi",
+            "    2 | \tlet x = (1 + ;",
+            "      | \t             ^",
+            "",
+        ].join("\n"));
+        // Width 0 runs to the end of the line
+        assert!(located("app.cryo", source, 2, 2, 0, "m").ends_with("| \t^^^^^^^^^^^^^^\n"));
+        assert_eq!(located("app.cryo", source, 9, 1, 1, "m"), "app.cryo:9:1: error: m\n");
+        assert_eq!(span_position(source, Span { start: 21, end: 26 }), Some((2, 10, 5)));
+        assert_eq!(span_position(source, Span { start: 21, end: 30 }), Some((2, 10, 0)));
+        assert_eq!(span_position(source, Span { start: 21, end: 99 }), None);
+    }
+
+    #[test]
+    fn test_examples_report_their_code() {
+        // Parse-error examples must still produce their own code
+        for code in CODES.iter().filter(|c| c.code.starts_with("E01")) {
+            let tokens = crate::lexer::tokenize(code.example);
+            let error = crate::parser::Parser::new(&tokens).parse().err();
+            assert_eq!(error.as_deref().and_then(code_for), Some(code.code), "{}: {:?}", code.code, error);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// ============================================
+// Cryo Structural Diff
+// diff(a, b): every place two values differ, with the path to it, for
+// assert_eq failures and for reconciling configs or API responses:
+//
+//   diff({ port: 80, tags: ["a"] }, { port: 8080, tags: ["a", "b"] })
+//   // [{ path: "$.port", kind: "changed", old: 80, new: 8080 },
+//   //  { path: "$.tags[1]", kind: "added", old: null, new: "b" }]
+//
+// Arrays are compared index by index, and objects and hash maps key by key
+// (in sorted order, so reports are stable). Numbers compare by value, so
+// 1 and 1.0 are the same; anything else differs when its type or text does.
+// A pair of arrays or objects is compared once, so cyclic values terminate
+// and a pair shared in several places is reported at the first path to it.
+// ============================================
+
+use crate::interpreter::Value;
+use crate::validate;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Only in the second value
+    Added,
+    /// Only in the first value
+    Removed,
+    Changed,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Added => "added",
+            Kind::Removed => "removed",
+            Kind::Changed => "changed",
+        }
+    }
+}
+
+/// One difference. `old` is null for additions, `new` for removals.
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// `$` for the values themselves, then `.field` and `[index]` steps
+    pub path: String,
+    pub kind: Kind,
+    pub old: Value,
+    pub new: Value,
+}
+
+/// Every difference between `a` and `b`; empty when they are equal
+pub fn diff(a: &Value, b: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    walk(a, b, "$", &mut HashSet::new(), &mut changes);
+    changes
+}
+
+/// (a, b) container pointers already compared
+type Seen = HashSet<(*const (), *const ())>;
+
+fn walk(a: &Value, b: &Value, path: &str, seen: &mut Seen, changes: &mut Vec<Change>) {
+    let pair = match (a, b) {
+        (Value::Array(x), Value::Array(y)) => Some((Rc::as_ptr(x) as *const (), Rc::as_ptr(y) as *const ())),
+        (Value::Struct(_, x), Value::Struct(_, y)) | (Value::Map(x), Value::Map(y)) => Some((Rc::as_ptr(x) as *const (), Rc::as_ptr(y) as *const ())),
+        _ => None,
+    };
+    if let Some((x, y)) = pair {
+        if x == y || !seen.insert((x, y)) {
+            return;
+        }
+    }
+    match (a, b) {
+        (Value::Array(x), Value::Array(y)) => {
+            let (x, y) = (x.borrow(), y.borrow());
+            for i in 0..x.len().max(y.len()) {
+                let path = format!("{}[{}]", path, i);
+                match (x.get(i), y.get(i)) {
+                    (Some(old), Some(new)) => walk(old, new, &path, seen, changes),
+                    (Some(old), None) => changes.push(Change { path, kind: Kind::Removed, old: old.clone(), new: Value::Null }),
+                    (None, Some(new)) => changes.push(Change { path, kind: Kind::Added, old: Value::Null, new: new.clone() }),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Struct(m, x), Value::Struct(n, y)) if m == n => fields(&x.borrow(), &y.borrow(), path, seen, changes),
+        (Value::Map(x), Value::Map(y)) => fields(&x.borrow(), &y.borrow(), path, seen, changes),
+        _ if validate::same(a, b) => {}
+        _ => changes.push(Change { path: path.to_string(), kind: Kind::Changed, old: a.clone(), new: b.clone() }),
+    }
+}
+
+fn fields(x: &HashMap<String, Value>, y: &HashMap<String, Value>, path: &str, seen: &mut Seen, changes: &mut Vec<Change>) {
+    let mut names: Vec<&String> = x.keys().chain(y.keys().filter(|k| !x.contains_key(*k))).collect();
+    names.sort();
+    for name in names {
+        let path = validate::field_path(path, name);
+        match (x.get(name), y.get(name)) {
+            (Some(old), Some(new)) => walk(old, new, &path, seen, changes),
+            (Some(old), None) => changes.push(Change { path, kind: Kind::Removed, old: old.clone(), new: Value::Null }),
+            (None, Some(new)) => changes.push(Change { path, kind: Kind::Added, old: Value::Null, new: new.clone() }),
+            (None, None) => {}
+        }
+    }
+}
+
+/// The changes one per line, as assert_eq prints them:
+/// `~ $.port: 80 -> 8080`, `+ $.tags[1]: "b"`, `- $.debug: true`
+pub fn render(changes: &[Change]) -> String {
+    let lines: Vec<String> = changes
+        .iter()
+        .map(|c| match c.kind {
+            Kind::Added => format!("+ {}: {}", c.path, validate::display(&c.new)),
+            Kind::Removed => format!("- {}: {}", c.path, validate::display(&c.old)),
+            Kind::Changed => format!("~ {}: {} -> {}", c.path, validate::display(&c.old), validate::display(&c.new)),
+        })
+        .collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn arr(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    fn obj(fields: Vec<(&str, Value)>) -> Value {
+        Value::object(fields.into_iter().map(|(k, v)| (k.to_string(), v)))
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = obj(vec![
+            ("port", Value::Int(80)),
+            ("debug", Value::Bool(true)),
+            ("tags", arr(vec![Value::String("a".into()), Value::Int(1)])),
+            ("db", obj(vec![("host", Value::String("x".into()))])),
+        ]);
+        let new = obj(vec![
+            ("port", Value::Int(8080)),
+            ("tags", arr(vec![Value::String("a".into()), Value::Float(1.0), Value::String("b".into())])),
+            ("db", obj(vec![("host", Value::String("y".into()))])),
+            ("odd key", Value::Null),
+        ]);
+        assert_eq!(render(&diff(&old, &new)), [
+            "~ $.db.host: \"x\" -> \"y\"",
+            "- $.debug: true",
+            "+ $[\"odd key\"]: null",
+            "~ $.port: 80 -> 8080",
+            "+ $.tags[2]: \"b\"",
+        ].join("\n"));
+        assert!(diff(&old, &old).is_empty());
+        assert_eq!(diff(&Value::Int(1), &Value::String("1".into()))[0].kind, Kind::Changed);
+        assert_eq!(render(&diff(&arr(vec![]), &Value::Null)), "~ $: [] -> null");
+
+        // Cycles: a = [1, a] against b = [2, b], and an object holding itself
+        let (a, b) = (arr(vec![Value::Int(1)]), arr(vec![Value::Int(2)]));
+        for v in [&a, &b] {
+            if let Value::Array(items) = v {
+                items.borrow_mut().push(v.clone());
+            }
+        }
+        assert_eq!(render(&diff(&a, &b)), "~ $[0]: 1 -> 2");
+        assert!(diff(&a, &a).is_empty());
+        let (x, y) = (obj(vec![("n", Value::Int(1))]), obj(vec![("n", Value::Int(1))]));
+        for v in [&x, &y] {
+            if let Value::Struct(_, fields) = v {
+                fields.borrow_mut().insert("me".to_string(), v.clone());
+            }
+        }
+        assert!(diff(&x, &y).is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+// ============================================
+// Cryo .env Files
+// load_dotenv(path?) and expand_env(text): configuration from a .env file
+// and `$NAME` / `${NAME}` references to the environment.
+//
+//   # comments and blank lines are skipped
+//   export DB_HOST=localhost        # `export` is optional
+//   DB_URL="postgres://${DB_HOST}/app\n"
+//   GREETING='no $expansion here'
+//
+// Unquoted and double-quoted values expand references to the environment
+// and to variables defined earlier in the file; double quotes also take
+// \n, \t, \" and \\ escapes. Single quotes are literal. Loading never
+// overrides a variable that is already set, so neither does expansion.
+// ============================================
+
+/// `text` with every `$NAME` and `${NAME}` replaced by `lookup(NAME)`, or by
+/// nothing when it's unset. `$$` is a literal `$`, as is a `$` that doesn't
+/// start a name.
+pub fn expand(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some((name, after)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+            out.push_str(&lookup(name).unwrap_or_default());
+            rest = after;
+        } else {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            if len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+                out.push('$');
+            } else {
+                out.push_str(&lookup(&rest[..len]).unwrap_or_default());
+                rest = &rest[len..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The `(name, value)` pairs in `.env` text, in order. `lookup` supplies the
+/// environment for expansion; earlier names in the file fill in what it lacks.
+pub fn parse(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<Vec<(String, String)>, String> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+        let bad = |why: &str| format!("line {}: {}", i + 1, why);
+        let (name, raw) = line.split_once('=').ok_or_else(|| bad("expected NAME=value"))?;
+        let name = name.trim();
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(bad(&format!("'{}' is not a variable name", name)));
+        }
+        let known = |n: &str| lookup(n).or_else(|| vars.iter().rev().find(|(k, _)| k == n).map(|(_, v)| v.clone()));
+        let raw = raw.trim();
+        let value = if let Some(quoted) = raw.strip_prefix('\'') {
+            let (value, tail) = quoted.split_once('\'').ok_or_else(|| bad("unclosed '"))?;
+            check_tail(tail).map_err(|e| bad(&e))?;
+            value.to_string()
+        } else if let Some(quoted) = raw.strip_prefix('"') {
+            let (value, tail) = split_double_quoted(quoted).ok_or_else(|| bad("unclosed \""))?;
+            check_tail(tail).map_err(|e| bad(&e))?;
+            expand(&value, known)
+        } else {
+            // An unquoted value ends at a ` #` comment
+            let value = raw.find(" #").map_or(raw, |at| &raw[..at]).trim_end();
+            expand(value, known)
+        };
+        vars.push((name.to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// A double-quoted value up to its closing quote with escapes applied (`\$`
+/// stays escaped as `$$` for `expand`), and what follows the quote
+fn split_double_quoted(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[at + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                '$' => value.push_str("$$"),
+                other => value.push(other),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Only a comment may follow a quoted value
+fn check_tail(tail: &str) -> Result<(), String> {
+    let tail = tail.trim_start();
+    if tail.is_empty() || tail.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected '{}' after the closing quote", tail))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/cryo".to_string()),
+            "PORT" => Some("8080".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("$HOME/x", env), "/home/cryo/x");
+        assert_eq!(expand("${HOME}_old:${PORT}", env), "/home/cryo_old:8080");
+        assert_eq!(expand("$MISSING|${MISSING}|", env), "||");
+        assert_eq!(expand("cost: $$5, $1 and a lone $", env), "cost: $5, $1 and a lone $");
+        assert_eq!(expand("${unclosed", env), "${unclosed");
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "
+            # settings
+            export HOST=localhost   # trailing comment
+            URL=\"http://${HOST}:$PORT/\\n\"
+            RAW='$HOME stays'
+            PORT=9000
+            AGAIN=$PORT
+            EMPTY=
+            PRICE=\"\\$5\"
+        ";
+        let vars = parse(text, env).unwrap();
+        let pairs: Vec<(&str, &str)> = vars.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(pairs, [
+            ("HOST", "localhost"),
+            ("URL", "http://localhost:8080/\n"),
+            ("RAW", "$HOME stays"),
+            ("PORT", "9000"),
+            // PORT is already set, so the file's value won't be loaded
+            ("AGAIN", "8080"),
+            ("EMPTY", ""),
+            ("PRICE", "$5"),
+        ]);
+        assert_eq!(parse("A=1\nnot a pair", env).unwrap_err(), "line 2: expected NAME=value");
+        assert_eq!(parse("1A=x", env).unwrap_err(), "line 1: '1A' is not a variable name");
+        assert_eq!(parse("A=\"open", env).unwrap_err(), "line 1: unclosed \"");
+        assert!(parse("A='x' y", env).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+// ============================================
+// Cryo Encoding Module
+// Base64 (standard and URL-safe), hex and percent-encoding
+// ============================================
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Base64 per RFC 4648. `url_safe` switches to the `-_` alphabet; `pad`
+/// appends `=` so the output length is a multiple of four.
+pub fn base64_encode(data: &[u8], url_safe: bool, pad: bool) -> String {
+    let alphabet = if url_safe { URL_SAFE } else { STANDARD };
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        // 3 input bytes -> 4 symbols; a short final chunk yields len + 1 symbols
+        for i in 0..=chunk.len() {
+            out.push(alphabet[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+        if pad {
+            for _ in chunk.len()..3 {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode base64 in either alphabet, with or without padding. Whitespace is
+/// ignored so wrapped (MIME/PEM-style) input decodes too.
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    let mut padding = 0;
+    for (pos, c) in text.bytes().enumerate() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            c if c.is_ascii_whitespace() => continue,
+            _ => return Err(format!("base64: invalid character at {}", pos)),
+        };
+        if padding > 0 {
+            return Err(format!("base64: data after padding at {}", pos));
+        }
+        acc = acc << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    // A lone trailing symbol carries fewer than 8 bits and can't be valid
+    if bits >= 6 || padding > 2 {
+        return Err("base64: truncated input".to_string());
+    }
+    Ok(out)
+}
+
+pub fn hex_encode(data: &[u8]) -> String {
+    crate::crypto::to_hex(data)
+}
+
+pub fn hex_decode(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    let text = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    if !text.len().is_multiple_of(2) {
+        return Err("hex: odd number of digits".to_string());
+    }
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                .ok_or_else(|| format!("hex: invalid digits '{}'", String::from_utf8_lossy(pair)))
+        })
+        .collect()
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters, so the
+/// result is safe in any URL component
+pub fn url_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len());
+    for &b in data {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        // RFC 4648 section 10 vectors
+        let cases = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+        for (plain, encoded) in cases {
+            assert_eq!(base64_encode(plain.as_bytes(), false, true), encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), plain.as_bytes());
+            assert_eq!(base64_decode(encoded.trim_end_matches('=')).unwrap(), plain.as_bytes());
+        }
+        assert_eq!(base64_encode(&[0xfb, 0xff], true, false), "-_8");
+        assert_eq!(base64_encode(&[0xfb, 0xff], false, true), "+/8=");
+        assert_eq!(base64_decode("-_8").unwrap(), vec![0xfb, 0xff]);
+        assert!(base64_decode("Zm9v!").is_err());
+        assert!(base64_decode("Z").is_err());
+        assert!(base64_decode("Zg==Zg").is_err());
+    }
+
+    #[test]
+    fn test_hex_and_url() {
+        assert_eq!(hex_encode(&[0, 0xab, 0x10]), "00ab10");
+        assert_eq!(hex_decode("00AB10").unwrap(), vec![0, 0xab, 0x10]);
+        assert_eq!(hex_decode("0xff").unwrap(), vec![0xff]);
+        assert!(hex_decode("abc").is_err());
+        assert!(hex_decode("zz").is_err());
+        assert_eq!(url_encode("a b&c=d/é~".as_bytes()), "a%20b%26c%3Dd%2F%C3%A9~");
+    }
+}
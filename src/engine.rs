@@ -0,0 +1,617 @@
+// ============================================
+// Cryo Engine
+// The analysis side of `cryo lsp`: the open documents, kept lexed and parsed
+// while they're edited. An edit re-lexes from the token before it only until
+// the new tokens line up with the old ones again, and only the chunks of
+// top-level items whose tokens changed are parsed again; every other chunk
+// keeps its syntax tree.
+// ============================================
+
+use crate::lexer::{self, Span, Token};
+use crate::parser::{Parser, TopLevel};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A place in a document the way LSP counts: 0-based line, and UTF-16 code
+/// units into the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// One `textDocument/didChange` content change; no range replaces everything
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub range: Option<Range>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Field,
+    Enum,
+    Variant,
+    Global,
+    Import,
+    Trait,
+    Impl,
+    Macro,
+}
+
+impl SymbolKind {
+    const ALL: [SymbolKind; 11] = [
+        SymbolKind::Function,
+        SymbolKind::Method,
+        SymbolKind::Struct,
+        SymbolKind::Field,
+        SymbolKind::Enum,
+        SymbolKind::Variant,
+        SymbolKind::Global,
+        SymbolKind::Import,
+        SymbolKind::Trait,
+        SymbolKind::Impl,
+        SymbolKind::Macro,
+    ];
+
+    /// Lowercase name, as `cryo symbols` prints it
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Method => "method",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Field => "field",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Variant => "variant",
+            SymbolKind::Global => "global",
+            SymbolKind::Import => "import",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Impl => "impl",
+            SymbolKind::Macro => "macro",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<SymbolKind> {
+        SymbolKind::ALL.into_iter().find(|k| k.name() == name)
+    }
+}
+
+/// An entry in a document's outline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The whole definition
+    pub range: Range,
+    /// Just the name
+    pub selection: Range,
+    pub children: Vec<DocumentSymbol>,
+}
+
+/// Top-level items that parse on their own: a chunk starts at an item keyword
+/// or decorator outside any brackets
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// Token indexes
+    pub tokens: std::ops::Range<usize>,
+    pub parsed: Rc<ParsedChunk>,
+}
+
+#[derive(Debug)]
+pub struct ParsedChunk {
+    pub items: Vec<Item>,
+    /// Token index (relative to the chunk) parsing stopped at, and why
+    pub error: Option<(usize, String)>,
+}
+
+#[derive(Debug)]
+pub struct Item {
+    /// Token indexes, relative to the chunk
+    pub tokens: std::ops::Range<usize>,
+    /// Function lines count from the chunk's first line, so a chunk that
+    /// only moved keeps its tree
+    pub node: TopLevel,
+}
+
+/// A source file, its tokens and its chunks
+pub struct Document {
+    text: String,
+    /// Byte offset each line starts at
+    line_starts: Vec<usize>,
+    /// Ends with Eof
+    tokens: Vec<Token>,
+    /// 1-based line each token starts on
+    lines: Vec<usize>,
+    spans: Vec<Span>,
+    chunks: Vec<Chunk>,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Self {
+        let (tokens, lines, spans) = lexer::tokenize_with_spans(text);
+        let mut doc = Document { text: text.to_string(), line_starts: line_starts(text), tokens, lines, spans, chunks: Vec::new() };
+        doc.chunks = chunk_bounds(&doc.tokens).into_iter().map(|range| doc.parse_chunk(range)).collect();
+        doc
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Every token, ending with Eof
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Every parsed item with its absolute token range, in source order
+    pub fn items(&self) -> impl Iterator<Item = (std::ops::Range<usize>, &TopLevel)> {
+        self.chunks.iter().flat_map(|chunk| {
+            let base = chunk.tokens.start;
+            chunk.parsed.items.iter().map(move |item| (base + item.tokens.start..base + item.tokens.end, &item.node))
+        })
+    }
+
+    /// Replace the bytes in `range` with `insert`
+    pub fn edit(&mut self, range: std::ops::Range<usize>, insert: &str) {
+        let (start, end) = (range.start, range.end);
+        let delta = insert.len() as isize - (end - start) as isize;
+        let line_delta = newlines(insert) as isize - newlines(&self.text[start..end]) as isize;
+        self.text.replace_range(start..end, insert);
+        self.line_starts = line_starts(&self.text);
+
+        // Re-lex from the end of the last token the edit can't have touched,
+        // until a token starts where an old token after the edit started:
+        // from there on the old tokens are still right, just moved
+        let first = self.spans.partition_point(|s| s.end < start);
+        let restart = if first == 0 { 0 } else { self.spans[first - 1].end };
+        let inserted_end = start + insert.len();
+        let old_spans = &self.spans;
+        let mut resync = old_spans.len();
+        let (tokens, lines, spans) = lexer::tokenize_from(&self.text, restart, self.line_index(restart) + 1, |at| {
+            if at < inserted_end {
+                return false;
+            }
+            let old = (at as isize - delta) as usize;
+            let k = old_spans.partition_point(|s| s.start < old);
+            if k < old_spans.len() && old_spans[k].start == old {
+                resync = k;
+                return true;
+            }
+            false
+        });
+        for (span, line) in self.spans[resync..].iter_mut().zip(&mut self.lines[resync..]) {
+            span.start = (span.start as isize + delta) as usize;
+            span.end = (span.end as isize + delta) as usize;
+            *line = (*line as isize + line_delta) as usize;
+        }
+        let unchanged = self.tokens[first..resync] == tokens[..];
+        let added = tokens.len();
+        self.tokens.splice(first..resync, tokens);
+        self.lines.splice(first..resync, lines);
+        self.spans.splice(first..resync, spans);
+        self.reparse(first, resync - first, added, unchanged && line_delta == 0);
+    }
+
+    /// Rebuild the chunk list after tokens `first..first + removed` were
+    /// replaced by `added` new ones, reusing the trees of chunks whose tokens
+    /// are the same. `same_tokens`: the replacement was token-for-token equal
+    /// and no line moved.
+    fn reparse(&mut self, first: usize, removed: usize, added: usize, same_tokens: bool) {
+        let old = std::mem::take(&mut self.chunks);
+        let find = |range: std::ops::Range<usize>| {
+            let i = old.partition_point(|c| c.tokens.start < range.start);
+            old.get(i).filter(|c| c.tokens == range).map(|c| c.parsed.clone())
+        };
+        let shift = added as isize - removed as isize;
+        let mut chunks = Vec::new();
+        for range in chunk_bounds(&self.tokens) {
+            let reused = if range.end <= first || same_tokens {
+                find(range.clone())
+            } else if range.start >= first + added {
+                find((range.start as isize - shift) as usize..(range.end as isize - shift) as usize)
+            } else {
+                None
+            };
+            chunks.push(match reused {
+                Some(parsed) => Chunk { tokens: range, parsed },
+                None => self.parse_chunk(range),
+            });
+        }
+        self.chunks = chunks;
+    }
+
+    fn parse_chunk(&self, range: std::ops::Range<usize>) -> Chunk {
+        let tokens = &self.tokens[range.clone()];
+        let first_line = self.lines[range.start];
+        let lines: Vec<usize> = self.lines[range.clone()].iter().map(|l| l - first_line + 1).collect();
+        let mut parser = Parser::with_lines(tokens, &lines);
+        let mut items = Vec::new();
+        let mut error = None;
+        while !parser.at_end() {
+            let start = parser.position();
+            match parser.parse_item() {
+                Ok(node) => items.push(Item { tokens: start..parser.position().min(tokens.len()), node }),
+                Err(e) => {
+                    error = Some((parser.position().min(tokens.len()), e));
+                    break;
+                }
+            }
+        }
+        Chunk { tokens: range, parsed: Rc::new(ParsedChunk { items, error }) }
+    }
+
+    /// 0-based line holding byte `offset`
+    fn line_index(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&s| s <= offset) - 1
+    }
+
+    /// Byte offset of an LSP position; past the end of a line means its end
+    pub fn offset(&self, pos: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(pos.line) else {
+            return self.text.len();
+        };
+        let line_end = self.line_starts.get(pos.line + 1).map_or(self.text.len(), |&next| next - 1);
+        let mut units = 0;
+        for (i, c) in self.text[line_start..line_end].char_indices() {
+            if units >= pos.character {
+                return line_start + i;
+            }
+            units += c.len_utf16();
+        }
+        line_end
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.line_index(offset);
+        let character = self.text[self.line_starts[line]..offset].encode_utf16().count();
+        Position { line, character }
+    }
+
+    pub fn range(&self, span: Span) -> Range {
+        Range { start: self.position(span.start), end: self.position(span.end) }
+    }
+
+    /// From the start of token `from` to the end of token `to - 1`
+    pub fn token_range(&self, tokens: std::ops::Range<usize>) -> Range {
+        let end = tokens.end.max(tokens.start + 1) - 1;
+        Range { start: self.position(self.spans[tokens.start].start), end: self.position(self.spans[end].end) }
+    }
+
+    /// Parse errors, one per chunk at most
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.chunks
+            .iter()
+            .filter_map(|chunk| {
+                let (at, message) = chunk.parsed.error.as_ref()?;
+                let token = chunk.tokens.start + at;
+                let range = if token < chunk.tokens.end {
+                    self.range(self.spans[token])
+                } else {
+                    // Ran out of tokens: point just past the chunk
+                    let end = self.position(self.spans[chunk.tokens.end - 1].end);
+                    Range { start: end, end }
+                };
+                Some(Diagnostic { range, message: message.clone() })
+            })
+            .collect()
+    }
+
+    /// The outline: items, with methods, fields and variants under them
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        let mut symbols = Vec::new();
+        for (tokens, item) in self.items() {
+            let symbol = |name: &str, kind, children| {
+                let selection = self.find_name(tokens.clone(), name).unwrap_or(tokens.start);
+                DocumentSymbol {
+                    name: name.to_string(),
+                    kind,
+                    range: self.token_range(tokens.clone()),
+                    selection: self.range(self.spans[selection]),
+                    children,
+                }
+            };
+            let methods = |functions: &[crate::parser::Function]| -> Vec<DocumentSymbol> {
+                functions.iter().filter_map(|f| self.member(tokens.clone(), f.name.as_str(), SymbolKind::Method)).collect()
+            };
+            symbols.push(match item {
+                TopLevel::Function(f) => symbol(f.name.as_str(), SymbolKind::Function, Vec::new()),
+                TopLevel::Struct(s) => {
+                    let fields = s.fields.iter().filter_map(|(name, _)| self.member(tokens.clone(), name, SymbolKind::Field)).collect();
+                    symbol(s.name.as_str(), SymbolKind::Struct, fields)
+                }
+                TopLevel::Enum(e) => {
+                    let variants = e.variants.iter().filter_map(|(name, _)| self.member(tokens.clone(), name, SymbolKind::Variant)).collect();
+                    symbol(&e.name, SymbolKind::Enum, variants)
+                }
+                TopLevel::Let(name, _) => symbol(name.as_str(), SymbolKind::Global, Vec::new()),
+                TopLevel::Import(path, _) => symbol(path, SymbolKind::Import, Vec::new()),
+                TopLevel::Trait(t) => symbol(&t.name, SymbolKind::Trait, methods(&t.methods)),
+                TopLevel::Impl(i) => {
+                    let mut impl_symbol = symbol(i.type_name.as_str(), SymbolKind::Impl, methods(&i.methods));
+                    if !i.trait_name.is_empty() {
+                        impl_symbol.name = format!("{} for {}", i.trait_name, i.type_name);
+                    }
+                    impl_symbol
+                }
+                TopLevel::Extern(e) => symbol(&format!("extern \"{}\"", e.abi), SymbolKind::Import, methods(&e.functions)),
+                TopLevel::Macro(m) => symbol(m.name.as_str(), SymbolKind::Macro, Vec::new()),
+            });
+        }
+        symbols
+    }
+
+    /// First identifier (or string, for imports) token spelling `name` in `tokens`
+    fn find_name(&self, tokens: std::ops::Range<usize>, name: &str) -> Option<usize> {
+        tokens.clone().find(|&i| match &self.tokens[i] {
+            Token::Identifier(id) => id.as_str() == name,
+            Token::String(s) => s == name,
+            _ => false,
+        })
+    }
+
+    /// A named part of an item, ranging to the end of its body or to the
+    /// next `,` or `;` at its own bracket depth
+    fn member(&self, item: std::ops::Range<usize>, name: &str, kind: SymbolKind) -> Option<DocumentSymbol> {
+        // Skip the item's own name
+        let body = (item.start..item.end).find(|&i| self.tokens[i] == Token::LBrace)?;
+        let at = self.find_name(body..item.end, name)?;
+        let start = if at > 0 && self.tokens[at - 1] == Token::Fn { at - 1 } else { at };
+        let mut depth = 0usize;
+        let mut end = at + 1;
+        while end < item.end {
+            match self.tokens[end] {
+                Token::LBrace | Token::LParen | Token::LBracket => depth += 1,
+                Token::RBrace if depth == 1 && kind == SymbolKind::Method => {
+                    end += 1;
+                    break;
+                }
+                Token::RBrace | Token::RParen | Token::RBracket if depth == 0 => break,
+                Token::RBrace | Token::RParen | Token::RBracket => depth -= 1,
+                Token::Comma | Token::Semi if depth == 0 => break,
+                _ => {}
+            }
+            end += 1;
+        }
+        Some(DocumentSymbol {
+            name: name.to_string(),
+            kind,
+            range: self.token_range(start..end),
+            selection: self.range(self.spans[at]),
+            children: Vec::new(),
+        })
+    }
+}
+
+/// The open documents, by URI
+#[derive(Default)]
+pub struct CryoEngine {
+    documents: HashMap<String, Document>,
+}
+
+impl CryoEngine {
+    pub fn new() -> Self {
+        CryoEngine::default()
+    }
+
+    pub fn open(&mut self, uri: &str, text: &str) {
+        self.documents.insert(uri.to_string(), Document::new(text));
+    }
+
+    pub fn close(&mut self, uri: &str) {
+        self.documents.remove(uri);
+    }
+
+    /// Apply content changes in order
+    pub fn change(&mut self, uri: &str, changes: &[Change]) -> Result<(), String> {
+        let doc = self.documents.get_mut(uri).ok_or_else(|| format!("document not open: {}", uri))?;
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let (start, end) = (doc.offset(range.start), doc.offset(range.end));
+                    if start > end {
+                        return Err(format!("edit range ends before it starts: {:?}", range));
+                    }
+                    doc.edit(start..end, &change.text);
+                }
+                None => *doc = Document::new(&change.text),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn document(&self, uri: &str) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    pub fn documents(&self) -> impl Iterator<Item = (&str, &Document)> {
+        self.documents.iter().map(|(uri, doc)| (uri.as_str(), doc))
+    }
+}
+
+fn line_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect()
+}
+
+fn newlines(text: &str) -> usize {
+    text.bytes().filter(|&b| b == b'\n').count()
+}
+
+/// Token ranges of the chunks, leaving out the final Eof. Outside brackets,
+/// a chunk starts at every item keyword except the one a run of decorators
+/// (or `async` or `extern "C"`) has already started.
+fn chunk_bounds(tokens: &[Token]) -> Vec<std::ops::Range<usize>> {
+    let end = tokens.len().saturating_sub(1);
+    let mut starts = Vec::new();
+    let mut depth = 0usize;
+    let mut decorated = false;
+    for (i, token) in tokens[..end].iter().enumerate() {
+        if depth == 0 {
+            let prefix = matches!(
+                token,
+                Token::Async | Token::Extern | Token::At | Token::WasmExport | Token::WasmImport | Token::DecController(_) | Token::DecGet(_)
+                    | Token::DecPost(_) | Token::DecPut(_) | Token::DecDelete(_) | Token::DecPatch(_) | Token::DecInjectable
+                    | Token::DecModule | Token::DecBody | Token::DecParam(_) | Token::DecQuery(_) | Token::DecGuard(_)
+                    | Token::DecMiddleware(_)
+            );
+            let keyword = matches!(
+                token,
+                Token::Fn | Token::Struct | Token::Enum | Token::Let | Token::Import | Token::Trait | Token::Impl
+                    | Token::Macro
+            );
+            if i == 0 || ((prefix || keyword) && !decorated) {
+                starts.push(i);
+            }
+            if prefix {
+                decorated = true;
+            } else if keyword {
+                decorated = false;
+            }
+        }
+        match token {
+            Token::LBrace | Token::LParen | Token::LBracket => depth += 1,
+            Token::RBrace | Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        // `extern "C" { ... }` is a whole item without a keyword after the prefix
+        if depth == 0 && matches!(token, Token::RBrace | Token::Semi) {
+            decorated = false;
+        }
+    }
+    starts.iter().enumerate().map(|(n, &start)| start..starts.get(n + 1).copied().unwrap_or(end)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "import \"util\"\n\nstruct Point { x: int, y: int }\n\nfn add(a, b) {\n    return a + b;\n}\n\nimpl Point {\n    fn sum(self) { return self.x + self.y; }\n}\n\nfn main() {\n    print(add(1, 2));\n}\n";
+
+    /// The document must match one built from scratch
+    fn assert_fresh(doc: &Document) {
+        let fresh = Document::new(doc.text());
+        assert_eq!(doc.tokens, fresh.tokens);
+        assert_eq!(doc.lines, fresh.lines);
+        assert_eq!(doc.spans, fresh.spans);
+        let ranges = |d: &Document| d.chunks.iter().map(|c| c.tokens.clone()).collect::<Vec<_>>();
+        assert_eq!(ranges(doc), ranges(&fresh));
+        assert_eq!(doc.diagnostics(), fresh.diagnostics());
+        assert_eq!(doc.symbols(), fresh.symbols());
+    }
+
+    fn edit(doc: &mut Document, find: &str, replace: &str) {
+        let start = doc.text().find(find).unwrap();
+        doc.edit(start..start + find.len(), replace);
+        assert_fresh(doc);
+    }
+
+    #[test]
+    fn test_edits_reuse_untouched_items() {
+        let mut doc = Document::new(SOURCE);
+        assert_eq!(doc.chunks().len(), 5);
+        let before: Vec<Rc<ParsedChunk>> = doc.chunks().iter().map(|c| c.parsed.clone()).collect();
+
+        // Inside one function body: only that chunk is parsed again
+        edit(&mut doc, "a + b", "a * b + 1");
+        let same: Vec<bool> = doc.chunks().iter().zip(&before).map(|(c, b)| Rc::ptr_eq(&c.parsed, b)).collect();
+        assert_eq!(same, [true, true, false, true, true]);
+
+        // New lines above move everything below without reparsing it
+        let before: Vec<Rc<ParsedChunk>> = doc.chunks().iter().map(|c| c.parsed.clone()).collect();
+        edit(&mut doc, "\n\nstruct", "\n\n\n// comment\n\nstruct");
+        assert!(doc.chunks().iter().zip(&before).skip(2).all(|(c, b)| Rc::ptr_eq(&c.parsed, b)));
+        assert_eq!(doc.symbols()[1].selection.start, Position { line: 5, character: 7 });
+    }
+
+    #[test]
+    fn test_edits_that_change_token_boundaries() {
+        let mut doc = Document::new(SOURCE);
+        // Identifier grows, operator merges, a string swallows the rest and is closed again
+        edit(&mut doc, "add(a", "adder(a");
+        edit(&mut doc, "a + b", "a > b");
+        let at = doc.text().find("> b").unwrap() + 1;
+        doc.edit(at..at, "=");
+        assert_fresh(&doc);
+        assert!(doc.tokens().contains(&Token::GtEq) && doc.diagnostics().is_empty());
+        edit(&mut doc, "print(", "print(\"");
+        assert_eq!(doc.diagnostics().len(), 1);
+        edit(&mut doc, "print(\"", "print(");
+        assert!(doc.diagnostics().is_empty());
+        // Joining two items, then splitting them again
+        edit(&mut doc, "}\n\nimpl", "\n\nimpl");
+        assert_eq!(doc.diagnostics().len(), 1);
+        edit(&mut doc, "\n\nimpl", "}\n\nimpl");
+        // Everything, and nothing
+        let len = doc.text().len();
+        doc.edit(0..len, "");
+        assert_fresh(&doc);
+        doc.edit(0..0, "#!/usr/bin/env cryo\nfn main() {}");
+        assert_fresh(&doc);
+    }
+
+    #[test]
+    fn test_random_edits_match_a_full_parse() {
+        let snippets = ["", " ", "\n", "}", "{", ";", "\"", "//", "fn g() {}", "=", "x", "1", "@Get(\"/\")", "let y = 2;", "impl"];
+        let mut doc = Document::new(SOURCE);
+        let mut seed: u64 = 42;
+        let mut next = |n: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n.max(1)
+        };
+        for _ in 0..500 {
+            let len = doc.text().len();
+            let start = next(len + 1);
+            let end = (start + next(4)).min(len);
+            let insert = snippets[next(snippets.len())];
+            doc.edit(start..end, insert);
+            assert_fresh(&doc);
+        }
+    }
+
+    #[test]
+    fn test_positions_and_outline() {
+        let mut engine = CryoEngine::new();
+        engine.open("file:///a.cryo", "let s = \"héllo😀\"; let t = 1;\nfn f() {}\n");
+        let doc = engine.document("file:///a.cryo").unwrap();
+        // é is one UTF-16 unit, the emoji two
+        let t = doc.text().find("t =").unwrap();
+        assert_eq!(doc.position(t), Position { line: 0, character: 23 });
+        assert_eq!(doc.offset(Position { line: 0, character: 23 }), t);
+        assert_eq!(doc.offset(Position { line: 1, character: 99 }), doc.text().len() - 1);
+
+        let change = Change { range: Some(Range { start: Position { line: 1, character: 3 }, end: Position { line: 1, character: 4 } }), text: "main".into() };
+        engine.change("file:///a.cryo", &[change]).unwrap();
+        let doc = engine.document("file:///a.cryo").unwrap();
+        let names: Vec<(String, SymbolKind)> = doc.symbols().into_iter().map(|s| (s.name, s.kind)).collect();
+        assert_eq!(names, [("s".to_string(), SymbolKind::Global), ("t".to_string(), SymbolKind::Global), ("main".to_string(), SymbolKind::Function)]);
+
+        engine.open("file:///b.cryo", "struct P { x: int }\nimpl P {\n    fn get(self) { return self.x; }\n}\n");
+        let symbols = engine.document("file:///b.cryo").unwrap().symbols();
+        assert_eq!(symbols[0].children[0].name, "x");
+        let get = &symbols[1].children[0];
+        assert_eq!((get.name.as_str(), get.kind), ("get", SymbolKind::Method));
+        assert_eq!(get.range, Range { start: Position { line: 2, character: 4 }, end: Position { line: 2, character: 35 } });
+    }
+}
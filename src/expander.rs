@@ -1,11 +1,13 @@
 // Cryo Macro Expander
 // Performs AST transformation (Macro Expansion)
 
-use crate::parser::{Expr, Stmt, TopLevel, MacroDef};
+use crate::parser::{Expr, Stmt, TopLevel, MacroDef, MatchArm};
+use crate::stack_guard;
+use crate::intern::Symbol;
 use std::collections::HashMap;
 
 pub struct Expander {
-    macros: HashMap<String, MacroDef>,
+    macros: HashMap<Symbol, MacroDef>,
 }
 
 impl Expander {
@@ -18,7 +20,7 @@ impl Expander {
         let mut remaining_ast = Vec::new();
         for item in ast {
             if let TopLevel::Macro(def) = item {
-                self.macros.insert(def.name.clone(), def);
+                self.macros.insert(def.name, def);
             } else {
                 remaining_ast.push(item);
             }
@@ -54,6 +56,10 @@ impl Expander {
     }
 
     fn expand_stmt(&self, stmt: Stmt) -> Stmt {
+        stack_guard::guard(|| self.expand_stmt_inner(stmt))
+    }
+
+    fn expand_stmt_inner(&self, stmt: Stmt) -> Stmt {
         match stmt {
             Stmt::Expr(Expr::Call(name, args)) => {
                 // Check if macro
@@ -64,7 +70,7 @@ impl Expander {
                         for (i, param) in def.params.iter().enumerate() {
                             // Expand arguments before binding? Yes.
                             let arg = self.expand_expr(args[i].clone());
-                            bindings.insert(param.clone(), arg);
+                            bindings.insert(*param, arg);
                         }
                         
                         // Instantiate body
@@ -77,6 +83,10 @@ impl Expander {
                 let args = args.into_iter().map(|a| self.expand_expr(a)).collect();
                 Stmt::Expr(Expr::Call(name, args))
             }
+            Stmt::Expr(Expr::At(span, call)) if matches!(*call, Expr::Call(..)) => match self.expand_stmt_inner(Stmt::Expr(*call)) {
+                Stmt::Expr(call) => Stmt::Expr(Expr::At(span, Box::new(call))),
+                expanded => expanded,
+            },
             // Recurse other stmts
             Stmt::Block(stmts) => Stmt::Block(self.expand_stmts(stmts)),
             Stmt::If(cond, then_b, else_b) => Stmt::If(self.expand_expr(cond), self.expand_stmts(then_b), else_b.map(|b| self.expand_stmts(b))),
@@ -86,57 +96,82 @@ impl Expander {
             Stmt::Return(Some(e)) => Stmt::Return(Some(self.expand_expr(e))),
             Stmt::Print(e) => Stmt::Print(self.expand_expr(e)),
             Stmt::Defer(s) => Stmt::Defer(Box::new(self.expand_stmt(*s))),
+            Stmt::Match(subject, arms) => {
+                let arms = arms.into_iter().map(|arm| MatchArm { body: self.expand_stmts(arm.body), ..arm }).collect();
+                Stmt::Match(self.expand_expr(subject), arms)
+            }
+            Stmt::Try(body, name, handler) => Stmt::Try(self.expand_stmts(body), name, self.expand_stmts(handler)),
+            Stmt::Throw(e) => Stmt::Throw(self.expand_expr(e)),
+            Stmt::With(name, e, body) => Stmt::With(name, self.expand_expr(e), self.expand_stmts(body)),
+            Stmt::At(line, col, s) => Stmt::At(line, col, Box::new(self.expand_stmt(*s))),
             _ => stmt 
         }
     }
 
     fn expand_expr(&self, expr: Expr) -> Expr {
+        stack_guard::guard(|| self.expand_expr_inner(expr))
+    }
+
+    fn expand_expr_inner(&self, expr: Expr) -> Expr {
         // Expressions usually don't contain macro calls that return Blocks.
         // But we should recurse.
         match expr {
             Expr::UnaryOp(op, e) => Expr::UnaryOp(op, Box::new(self.expand_expr(*e))),
             Expr::BinOp(l, op, r) => Expr::BinOp(Box::new(self.expand_expr(*l)), op, Box::new(self.expand_expr(*r))),
             Expr::Call(n, args) => Expr::Call(n, args.into_iter().map(|a| self.expand_expr(a)).collect()),
+            Expr::At(span, e) => Expr::At(span, Box::new(self.expand_expr(*e))),
             // ...
             _ => expr
         }
     }
 
-    fn instantiate_stmts(&self, stmts: &[Stmt], bindings: &HashMap<String, Expr>) -> Vec<Stmt> {
+    fn instantiate_stmts(&self, stmts: &[Stmt], bindings: &HashMap<Symbol, Expr>) -> Vec<Stmt> {
         stmts.iter().map(|s| self.instantiate_stmt(s, bindings)).collect()
     }
 
-    fn instantiate_stmt(&self, stmt: &Stmt, bindings: &HashMap<String, Expr>) -> Stmt {
+    fn instantiate_stmt(&self, stmt: &Stmt, bindings: &HashMap<Symbol, Expr>) -> Stmt {
         // Recursive instantiation with substitution
         match stmt {
             Stmt::Expr(e) => Stmt::Expr(self.instantiate_expr(e, bindings)),
             Stmt::Print(e) => Stmt::Print(self.instantiate_expr(e, bindings)),
-            Stmt::Let(n, t, e) => Stmt::Let(n.clone(), t.clone(), self.instantiate_expr(e, bindings)),
-            Stmt::Assign(n, e) => Stmt::Assign(n.clone(), self.instantiate_expr(e, bindings)),
+            Stmt::Let(n, t, e) => Stmt::Let(*n, t.clone(), self.instantiate_expr(e, bindings)),
+            Stmt::Assign(n, e) => Stmt::Assign(*n, self.instantiate_expr(e, bindings)),
             Stmt::If(c, t, e) => Stmt::If(self.instantiate_expr(c, bindings), self.instantiate_stmts(t, bindings), e.as_ref().map(|b| self.instantiate_stmts(b, bindings))),
+            Stmt::Match(subject, arms) => Stmt::Match(self.instantiate_expr(subject, bindings), arms.iter().map(|arm| MatchArm {
+                pattern: arm.pattern.clone(),
+                guard: arm.guard.as_ref().map(|g| self.instantiate_expr(g, bindings)),
+                body: self.instantiate_stmts(&arm.body, bindings),
+            }).collect()),
+            Stmt::Try(body, name, handler) => Stmt::Try(self.instantiate_stmts(body, bindings), *name, self.instantiate_stmts(handler, bindings)),
+            Stmt::Throw(e) => Stmt::Throw(self.instantiate_expr(e, bindings)),
+            Stmt::With(name, e, body) => Stmt::With(*name, self.instantiate_expr(e, bindings), self.instantiate_stmts(body, bindings)),
+            Stmt::At(line, col, s) => Stmt::At(*line, *col, Box::new(self.instantiate_stmt(s, bindings))),
             // ...
             _ => stmt.clone() // Fallback clone if deep logic missing
         }
     }
 
-    fn instantiate_expr(&self, expr: &Expr, bindings: &HashMap<String, Expr>) -> Expr {
+    fn instantiate_expr(&self, expr: &Expr, bindings: &HashMap<Symbol, Expr>) -> Expr {
         match expr {
-            Expr::Identifier(name) if name.starts_with('$') => {
-                 let key = &name[1..];
-                 if let Some(val) = bindings.get(key) {
+            Expr::Identifier(name) if name.as_str().starts_with('$') => {
+                 let key = Symbol::intern(&name.as_str()[1..]);
+                 if let Some(val) = bindings.get(&key) {
                      val.clone()
                  } else {
-                     Expr::Identifier(name.clone())
+                     Expr::Identifier(*name)
                  }
             }
             Expr::UnaryOp(op, e) => Expr::UnaryOp(op.clone(), Box::new(self.instantiate_expr(e, bindings))),
             Expr::BinOp(l, op, r) => Expr::BinOp(Box::new(self.instantiate_expr(l, bindings)), op.clone(), Box::new(self.instantiate_expr(r, bindings))),
-            Expr::Call(n, args) => Expr::Call(n.clone(), args.iter().map(|a| self.instantiate_expr(a, bindings)).collect()),
-            Expr::MethodCall(obj, m, args) => Expr::MethodCall(Box::new(self.instantiate_expr(obj, bindings)), m.clone(), args.iter().map(|a| self.instantiate_expr(a, bindings)).collect()),
-            Expr::Field(obj, f) => Expr::Field(Box::new(self.instantiate_expr(obj, bindings)), f.clone()),
+            Expr::Call(n, args) => Expr::Call(*n, args.iter().map(|a| self.instantiate_expr(a, bindings)).collect()),
+            Expr::MethodCall(obj, m, args) => Expr::MethodCall(Box::new(self.instantiate_expr(obj, bindings)), *m, args.iter().map(|a| self.instantiate_expr(a, bindings)).collect()),
+            Expr::Field(obj, f) => Expr::Field(Box::new(self.instantiate_expr(obj, bindings)), *f),
             Expr::Index(arr, idx) => Expr::Index(Box::new(self.instantiate_expr(arr, bindings)), Box::new(self.instantiate_expr(idx, bindings))),
             Expr::Array(items) => Expr::Array(items.iter().map(|e| self.instantiate_expr(e, bindings)).collect()),
-            Expr::StructInit(name, fields) => Expr::StructInit(name.clone(), fields.iter().map(|(k,v)| (k.clone(), self.instantiate_expr(v, bindings))).collect()),
+            Expr::StructInit(name, fields) => Expr::StructInit(*name, fields.iter().map(|(k,v)| (*k, self.instantiate_expr(v, bindings))).collect()),
+            Expr::MapLiteral(entries) => Expr::MapLiteral(entries.iter().map(|(k,v)| (k.clone(), self.instantiate_expr(v, bindings))).collect()),
+            Expr::Propagate(e) => Expr::Propagate(Box::new(self.instantiate_expr(e, bindings))),
+            Expr::At(span, e) => Expr::At(*span, Box::new(self.instantiate_expr(e, bindings))),
             _ => expr.clone()
         }
     }
@@ -3,9 +3,14 @@
 
 #![allow(dead_code)]
 
+#[cfg(feature = "ffi")]
 use libloading::{Library, Symbol};
 use std::collections::HashMap;
 
+// Builds without the `ffi` feature (e.g. wasm) can't load anything
+#[cfg(not(feature = "ffi"))]
+type Library = ();
+
 /// Loaded dynamic libraries
 pub struct FfiManager {
     libraries: HashMap<String, Library>,
@@ -17,7 +22,30 @@ impl FfiManager {
             libraries: HashMap::new(),
         }
     }
-    
+}
+
+#[cfg(not(feature = "ffi"))]
+impl FfiManager {
+    pub fn load_library(&mut self, name: &str) -> Result<(), String> {
+        Err(format!("Failed to load library: {} (this build has no FFI support)", name))
+    }
+
+    pub fn call_i64(&self, lib_name: &str, _func_name: &str, _args: &[i64]) -> Result<i64, String> {
+        Err(format!("Library not loaded: {}", lib_name))
+    }
+
+    pub fn call_f64(&self, lib_name: &str, _func_name: &str, _args: &[f64]) -> Result<f64, String> {
+        Err(format!("Library not loaded: {}", lib_name))
+    }
+
+    pub fn call_void(&self, lib_name: &str, _func_name: &str, _args: &[i64]) -> Result<(), String> {
+        Err(format!("Library not loaded: {}", lib_name))
+    }
+}
+
+#[cfg(feature = "ffi")]
+impl FfiManager {
+
     /// Load a dynamic library (.dll on Windows, .so on Linux)
     pub fn load_library(&mut self, name: &str) -> Result<(), String> {
         if self.libraries.contains_key(name) {
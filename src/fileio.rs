@@ -0,0 +1,239 @@
+// ============================================
+// Cryo File I/O Module
+// Buffered, seekable file handles for streaming reads and writes, and
+// advisory locks for processes sharing a file
+// ============================================
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const BUF_SIZE: usize = 64 * 1024;
+
+/// An open file with its own read and write buffers. Unlike `BufReader` /
+/// `BufWriter`, one handle can mix reads, writes and seeks (modes like `r+`):
+/// switching direction drains the other buffer first so the OS file position
+/// always matches what the script has seen.
+pub struct FileHandle {
+    file: File,
+    readable: bool,
+    writable: bool,
+    rbuf: Vec<u8>,
+    rpos: usize,
+    wbuf: Vec<u8>,
+}
+
+impl FileHandle {
+    /// Open with a C-style mode: `r`, `w`, `a`, optionally followed by `+`
+    /// for read/write. A `b` anywhere is accepted and ignored.
+    pub fn open(path: &str, mode: &str) -> Result<Self, String> {
+        let plus = mode.contains('+');
+        let mut opts = OpenOptions::new();
+        let (readable, writable) = match mode.chars().find(|c| *c != 'b') {
+            Some('r') => {
+                opts.read(true).write(plus);
+                (true, plus)
+            }
+            Some('w') => {
+                opts.write(true).create(true).truncate(true).read(plus);
+                (plus, true)
+            }
+            Some('a') => {
+                opts.append(true).create(true).read(plus);
+                (plus, true)
+            }
+            _ => return Err(format!("open: invalid mode '{}'", mode)),
+        };
+        let file = opts.open(path).map_err(|e| format!("open {}: {}", path, e))?;
+        Ok(FileHandle { file, readable, writable, rbuf: Vec::new(), rpos: 0, wbuf: Vec::new() })
+    }
+
+    /// Refill the read buffer; returns false at end of file
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.rpos < self.rbuf.len() {
+            return Ok(true);
+        }
+        self.flush()?;
+        self.rbuf.resize(BUF_SIZE, 0);
+        let n = self.file.read(&mut self.rbuf)?;
+        self.rbuf.truncate(n);
+        self.rpos = 0;
+        Ok(n > 0)
+    }
+
+    /// Next line without its `\n` / `\r\n`, or None at end of file
+    pub fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if !self.readable {
+            return Err(io::Error::other("file not open for reading"));
+        }
+        let mut line = Vec::new();
+        loop {
+            if !self.fill()? {
+                if line.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+            let pending = &self.rbuf[self.rpos..];
+            if let Some(i) = pending.iter().position(|&b| b == b'\n') {
+                line.extend_from_slice(&pending[..i]);
+                self.rpos += i + 1;
+                break;
+            }
+            line.extend_from_slice(pending);
+            self.rpos = self.rbuf.len();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    /// Up to `n` bytes; fewer only at end of file
+    pub fn read_bytes(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        if !self.readable {
+            return Err(io::Error::other("file not open for reading"));
+        }
+        let mut out = Vec::with_capacity(n.min(BUF_SIZE));
+        while out.len() < n && self.fill()? {
+            let take = (n - out.len()).min(self.rbuf.len() - self.rpos);
+            out.extend_from_slice(&self.rbuf[self.rpos..self.rpos + take]);
+            self.rpos += take;
+        }
+        Ok(out)
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if !self.writable {
+            return Err(io::Error::other("file not open for writing"));
+        }
+        self.discard_read_buffer()?;
+        self.wbuf.extend_from_slice(data);
+        if self.wbuf.len() >= BUF_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    /// Move to `pos`, returning the new offset from the start of the file
+    pub fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.flush()?;
+        self.discard_read_buffer()?;
+        self.file.seek(pos)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.wbuf.is_empty() {
+            self.file.write_all(&self.wbuf)?;
+            self.wbuf.clear();
+        }
+        self.file.flush()
+    }
+
+    /// Rewind the OS position over read-ahead the script hasn't consumed yet
+    fn discard_read_buffer(&mut self) -> io::Result<()> {
+        let unread = self.rbuf.len() - self.rpos;
+        if unread > 0 {
+            self.file.seek(SeekFrom::Current(-(unread as i64)))?;
+        }
+        self.rbuf.clear();
+        self.rpos = 0;
+        Ok(())
+    }
+}
+
+impl Drop for FileHandle {
+    /// Handles that are never closed still get their buffered writes out
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// An advisory lock on a file, held until dropped. Processes that lock the
+/// same path wait their turn; ones that just read or write it aren't stopped.
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Lock `path`, creating it if it doesn't exist. Any number of shared
+    /// locks can be held at once, but an exclusive one excludes all others.
+    /// Without `wait`, returns None instead of waiting for another lock.
+    pub fn acquire(path: &str, shared: bool, wait: bool) -> Result<Option<Self>, String> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let locked = match (shared, wait) {
+            (false, true) => file.lock().map_err(TryLockError::Error),
+            (true, true) => file.lock_shared().map_err(TryLockError::Error),
+            (false, false) => file.try_lock(),
+            (true, false) => file.try_lock_shared(),
+        };
+        match locked {
+            Ok(()) => Ok(Some(FileLock { file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(format!("{}: {}", path, e)),
+        }
+    }
+}
+
+impl Drop for FileLock {
+    /// Closing the file releases the lock too, but not promptly everywhere
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_and_seek() {
+        let path = std::env::temp_dir().join(format!("cryo_fileio_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut f = FileHandle::open(path, "w").unwrap();
+        f.write(b"first\r\nsecond\n").unwrap();
+        f.write(b"third").unwrap();
+        assert!(f.read_line().is_err());
+        drop(f); // flushes
+
+        let mut f = FileHandle::open(path, "r+").unwrap();
+        assert_eq!(f.read_line().unwrap().unwrap(), b"first");
+        // Writing after a read lands right after what was read, not after the read-ahead
+        f.write(b"SECOND").unwrap();
+        assert_eq!(f.read_line().unwrap().unwrap(), b"");
+        assert_eq!(f.read_bytes(100).unwrap(), b"third");
+        assert_eq!(f.read_line().unwrap(), None);
+        assert_eq!(f.seek(SeekFrom::Start(7)).unwrap(), 7);
+        assert_eq!(f.read_bytes(6).unwrap(), b"SECOND");
+        drop(f);
+
+        let mut f = FileHandle::open(path, "a").unwrap();
+        f.write(b"\nfourth").unwrap();
+        drop(f);
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "first\r\nSECOND\nthird\nfourth");
+        std::fs::remove_file(path).unwrap();
+        assert!(FileHandle::open(path, "r").is_err());
+        assert!(FileHandle::open(path, "x").is_err());
+    }
+    #[test]
+    fn test_file_lock() {
+        let path = std::env::temp_dir().join(format!("cryo_lock_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        // Each acquire opens the file again, so locks conflict even within one process
+        let held = FileLock::acquire(path, false, true).unwrap().unwrap();
+        assert!(FileLock::acquire(path, false, false).unwrap().is_none());
+        assert!(FileLock::acquire(path, true, false).unwrap().is_none());
+        drop(held);
+
+        let a = FileLock::acquire(path, true, false).unwrap();
+        let b = FileLock::acquire(path, true, false).unwrap();
+        assert!(a.is_some() && b.is_some());
+        assert!(FileLock::acquire(path, false, false).unwrap().is_none());
+        drop((a, b));
+        assert!(FileLock::acquire(path, false, false).unwrap().is_some());
+        std::fs::remove_file(path).unwrap();
+        assert!(FileLock::acquire("no/such/dir/lock", false, false).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+// ============================================
+// Cryo Frozen Values
+// freeze(value) makes arrays, structs/objects and collections immutable,
+// along with everything they contain. Values have no spare bits for a flag,
+// so the frozen objects are kept in a per-thread registry keyed by address.
+// Each entry holds a weak reference, which keeps the address from being
+// reused by a new object for as long as the entry exists.
+// ============================================
+
+use crate::interpreter::Value;
+use rustc_hash::FxHashMap;
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+/// Registry size below which dead entries aren't worth sweeping
+const MIN_SWEEP: usize = 1024;
+
+#[derive(Default)]
+struct Registry {
+    objects: FxHashMap<usize, Weak<dyn Any>>,
+    /// Sweep dead entries once the registry grows past this
+    sweep_at: usize,
+}
+
+thread_local! {
+    static FROZEN: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+/// Address and weak handle of the shared object behind `value`, if it has
+/// one that can be mutated
+fn object(value: &Value) -> Option<(usize, Weak<dyn Any>)> {
+    fn handle<T: 'static>(rc: &Rc<T>) -> (usize, Weak<dyn Any>) {
+        let weak: Weak<dyn Any> = Rc::downgrade(rc) as Weak<dyn Any>;
+        (Rc::as_ptr(rc) as *const () as usize, weak)
+    }
+    match value {
+        Value::Array(a) => Some(handle(a)),
+        Value::Struct(_, f) | Value::Map(f) => Some(handle(f)),
+        Value::Collection(c) => Some(handle(c)),
+        _ => None,
+    }
+}
+
+fn address(value: &Value) -> Option<usize> {
+    match value {
+        Value::Array(a) => Some(Rc::as_ptr(a) as *const () as usize),
+        Value::Struct(_, f) | Value::Map(f) => Some(Rc::as_ptr(f) as *const () as usize),
+        Value::Collection(c) => Some(Rc::as_ptr(c) as *const () as usize),
+        _ => None,
+    }
+}
+
+/// Freeze `value` and everything reachable from it. Cycles are fine.
+pub fn freeze(value: &Value) {
+    FROZEN.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let mut pending = vec![value.clone()];
+        while let Some(value) = pending.pop() {
+            // Enums can't change, but their payloads can
+            if let Value::Enum(e) = &value {
+                pending.extend(e.fields.iter().cloned());
+                continue;
+            }
+            let Some((addr, weak)) = object(&value) else { continue };
+            if registry.objects.get(&addr).is_some_and(|w| w.strong_count() > 0) {
+                continue;
+            }
+            registry.objects.insert(addr, weak);
+            match &value {
+                Value::Array(items) => pending.extend(items.borrow().iter().cloned()),
+                Value::Struct(_, fields) | Value::Map(fields) => pending.extend(fields.borrow().values().cloned()),
+                Value::Collection(coll) => coll.borrow().for_each_value(|v| pending.push(v.clone())),
+                _ => {}
+            }
+        }
+        if registry.objects.len() >= registry.sweep_at {
+            registry.objects.retain(|_, weak| weak.strong_count() > 0);
+            registry.sweep_at = MIN_SWEEP.max(registry.objects.len() * 2);
+        }
+    });
+}
+
+/// Whether `value` can't be changed in place: frozen containers, and every
+/// string, number, bool and null
+pub fn is_frozen(value: &Value) -> bool {
+    match address(value) {
+        Some(addr) => FROZEN.with(|registry| {
+            let registry = registry.borrow();
+            !registry.objects.is_empty() && registry.objects.get(&addr).is_some_and(|w| w.strong_count() > 0)
+        }),
+        None => true,
+    }
+}
+
+/// Err naming `action` when `value` is a frozen container
+pub fn check_mutable(value: &Value, action: &str) -> Result<(), String> {
+    if address(value).is_none() || !is_frozen(value) {
+        return Ok(());
+    }
+    let what = match value {
+        Value::Array(_) => "array".to_string(),
+        Value::Struct(name, _) if !name.as_str().is_empty() => format!("struct {}", name),
+        Value::Struct(..) => "object".to_string(),
+        Value::Map(_) => "map".to_string(),
+        Value::Collection(c) => c.borrow().kind().to_string(),
+        _ => "value".to_string(),
+    };
+    Err(format!("{}: {} is frozen", action, what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn array(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    #[test]
+    fn test_freeze_is_deep_and_handles_cycles() {
+        let inner = array(vec![Value::Int(1)]);
+        let config = Value::object([("list".to_string(), inner.clone()), ("name".to_string(), Value::String("x".into()))]);
+        // config.self = config
+        if let Value::Struct(_, fields) = &config {
+            fields.borrow_mut().insert("self".to_string(), config.clone());
+        }
+        assert!(!is_frozen(&config) && check_mutable(&inner, "push").is_ok());
+        freeze(&config);
+        assert!(is_frozen(&config) && is_frozen(&inner));
+        assert_eq!(check_mutable(&inner, "push").unwrap_err(), "push: array is frozen");
+        assert_eq!(check_mutable(&config, "field assignment").unwrap_err(), "field assignment: object is frozen");
+        // A copy is a new, mutable object
+        assert!(!is_frozen(&inner.deep_clone()));
+        assert!(is_frozen(&Value::Int(3)) && check_mutable(&Value::String("s".into()), "push").is_ok());
+        if let Value::Struct(_, fields) = &config {
+            fields.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn test_deep_clone_keeps_cycles_and_sharing() {
+        // a = [1, a, shared, shared]
+        let a = array(vec![Value::Int(1)]);
+        let shared = Value::object([("n".to_string(), Value::Int(2))]);
+        if let Value::Array(items) = &a {
+            items.borrow_mut().extend([a.clone(), shared.clone(), shared]);
+        }
+        let copy = a.deep_clone();
+        let (Value::Array(orig), Value::Array(copied)) = (&a, &copy) else { panic!("not an array") };
+        assert!(!Rc::ptr_eq(orig, copied));
+        let items = copied.borrow();
+        // The copy contains itself, not the original
+        let Value::Array(inner) = &items[1] else { panic!("not an array") };
+        assert!(Rc::ptr_eq(inner, copied));
+        let (Value::Struct(_, first), Value::Struct(_, second)) = (&items[2], &items[3]) else { panic!("not objects") };
+        assert!(Rc::ptr_eq(first, second));
+        assert_eq!(first.borrow().get("n").map(Value::as_int), Some(2));
+        drop(items);
+        // Break the cycles so the test doesn't leak them
+        for value in [&a, &copy] {
+            if let Value::Array(items) = value {
+                items.borrow_mut().clear();
+            }
+        }
+    }
+
+    #[test]
+    fn test_dead_objects_are_not_frozen() {
+        let first = array(Vec::new());
+        freeze(&first);
+        let addr = address(&first);
+        drop(first);
+        // The registry's weak handle keeps the address reserved, so a new
+        // array can't inherit the frozen flag
+        let second = array(Vec::new());
+        assert_ne!(address(&second), addr);
+        assert!(!is_frozen(&second));
+    }
+}
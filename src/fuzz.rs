@@ -0,0 +1,668 @@
+// Cryo Fuzzer
+// Feeds arbitrary bytes, mutated corpus programs and grammar-generated
+// programs into the lexer, parser and interpreter under resource limits,
+// collecting the panics. Drives `cryo fuzz`, which runs the inputs in a
+// `cryo fuzz --worker` child so a stack overflow kills the worker rather
+// than the fuzzer; the cargo-fuzz targets in fuzz/ call `exercise` directly.
+
+use crate::expander::Expander;
+use crate::interpreter::Interpreter;
+use crate::lexer;
+use crate::optimizer::Optimizer;
+use crate::parser::Parser;
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How far an input goes down the pipeline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Lex,
+    Parse,
+    Run,
+}
+
+impl Target {
+    pub fn parse(s: &str) -> Option<Target> {
+        match s {
+            "lex" | "lexer" => Some(Target::Lex),
+            "parse" | "parser" => Some(Target::Parse),
+            "run" | "interp" | "interpreter" => Some(Target::Run),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Lex => "lex",
+            Target::Parse => "parse",
+            Target::Run => "run",
+        }
+    }
+}
+
+/// Resource limits for a single input
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Longer inputs are skipped
+    pub max_len: usize,
+    /// Statements and loop iterations before the interpreter gives up.
+    /// Keep it low: variable lookup walks every frame, so runaway recursion
+    /// costs time quadratic in this.
+    pub steps: u64,
+    /// Wall time for one input. Steps don't bound a single statement that
+    /// builds an exponentially large value, so `fuzz` also kills a worker
+    /// that takes longer than this; `exercise` ignores it.
+    pub timeout: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_len: 64 * 1024, steps: 2_000, timeout: Duration::from_secs(10) }
+    }
+}
+
+/// Builtins fuzzed programs may not call on top of the `eval` sandbox list:
+/// anything that blocks or sleeps
+const FUZZ_DENIED: &[&str] = &["sleep", "input", "read_line", "readLine", "tcp_read_line"];
+
+/// Run `input` through `target` and every stage before it. Panics propagate,
+/// which is what cargo-fuzz wants.
+pub fn exercise(target: Target, input: &[u8], limits: &Limits) {
+    if input.len() > limits.max_len {
+        return;
+    }
+    let source = String::from_utf8_lossy(input);
+    let (tokens, lines) = lexer::tokenize_with_lines(&source);
+    if target == Target::Lex {
+        return;
+    }
+    let Ok(ast) = Parser::with_lines(&tokens, &lines).parse() else { return };
+    let ast = Optimizer::new().optimize(Expander::new().expand(ast));
+    if target == Target::Parse {
+        return;
+    }
+    let mut interp = Interpreter::new();
+    interp.set_sandbox(FUZZ_DENIED);
+    interp.set_step_limit(Some(limits.steps));
+    let _ = interp.run(&ast);
+}
+
+/// A panic caught while running one input
+#[derive(Debug, Clone)]
+pub struct Crash {
+    pub message: String,
+    /// `file:line` of the panic, used to tell crashes apart
+    pub location: String,
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<Crash>> = const { RefCell::new(None) };
+}
+
+/// `exercise`, with a panic turned into a `Crash`. The location is only
+/// known while `serve` has its panic hook installed.
+pub fn run_one(target: Target, input: &[u8], limits: &Limits) -> Result<(), Crash> {
+    LAST_PANIC.with(|p| p.borrow_mut().take());
+    let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| exercise(target, input, limits))) else {
+        return Ok(());
+    };
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "<non-string panic>".to_string());
+    let location = LAST_PANIC.with(|p| p.borrow_mut().take()).map(|c| c.location).unwrap_or_else(|| "<unknown>".to_string());
+    Err(Crash { message, location })
+}
+
+/// xorshift64*: small, fast and reproducible from `--seed`
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `0..n` (`n` > 0)
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn chance(&mut self, one_in: usize) -> bool {
+        self.below(one_in) == 0
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Literals that tend to hit edge cases: overflow, empty and multi-byte text
+const INTERESTING: &[&str] = &[
+    "0", "-1", "1", "255", "65536", "9223372036854775807", "-9223372036854775808", "4611686018427387904",
+    "\"\"", "\"é\"", "\"日本語\"", "\"🦀x\"", "\"a,b,,c\"", "\"\\n\"", "[]", "[[]]", "null", "true",
+];
+
+/// Tokens spliced in by the mutator
+const DICTIONARY: &[&str] = &[
+    "fn", "let", "if", "else", "while", "return", "break", "continue", "struct", "impl", "match", "defer", "try", "catch", "throw", "with", "import",
+    "(", ")", "{", "}", "[", "]", ";", ",", ".", ":", "=", "==", "!=", "<", ">", "+", "-", "*", "/", "%", "&&", "||",
+    "!", "->", "=>", "@", "#", "\"", "'", "é", "🦀", "\\",
+];
+
+/// Builtins the generator calls, with their arity
+const BUILTINS: &[(&str, usize)] = &[
+    ("len", 1), ("substr", 3), ("split", 2), ("push", 2), ("pop", 1), ("shift", 1), ("upper", 1), ("lower", 1),
+    ("toString", 1), ("index_of", 2), ("char_at", 2), ("parseInt", 1), ("replace", 3), ("trim", 1), ("join", 2),
+    ("contains", 2), ("starts_with", 2), ("abs", 1), ("min", 2), ("max", 2), ("range", 2), ("slice", 3),
+    ("repeat", 2), ("reverse", 1), ("sort", 1), ("keys", 1), ("insert", 3), ("remove_at", 2), ("splice", 4),
+    ("concat", 2), ("fill", 2), ("pad_left", 3), ("center", 2), ("title_case", 1), ("snake_to_camel", 1),
+    ("strip_prefix", 2),
+];
+
+const BINARY_OPS: &[&str] = &["+", "-", "*", "/", "%", "==", "!=", "<", ">", "<=", ">=", "&&", "||"];
+
+/// Source split into identifier/number runs, string literals, whitespace
+/// runs and single other characters, so mutations keep tokens and UTF-8
+/// sequences whole
+fn pieces(source: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let same_kind: fn(char) -> bool = if c.is_alphanumeric() || c == '_' {
+            |c| c.is_alphanumeric() || c == '_'
+        } else if c.is_whitespace() {
+            char::is_whitespace
+        } else if c == '"' {
+            let mut escaped = false;
+            let mut end = source.len();
+            for (i, c) in chars.by_ref() {
+                if c == '"' && !escaped {
+                    end = i + 1;
+                    break;
+                }
+                escaped = c == '\\' && !escaped;
+            }
+            out.push(&source[start..end]);
+            continue;
+        } else {
+            out.push(&source[start..start + c.len_utf8()]);
+            continue;
+        };
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek() {
+            if !same_kind(c) {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        out.push(&source[start..end]);
+    }
+    out
+}
+
+/// A token-level mutation of `input`, optionally splicing in part of `other`
+pub fn mutate(rng: &mut Rng, input: &[u8], other: &[u8]) -> Vec<u8> {
+    if input.is_empty() || rng.chance(4) {
+        return mutate_bytes(rng, input);
+    }
+    let source = String::from_utf8_lossy(input);
+    let donor = String::from_utf8_lossy(other);
+    let mut parts: Vec<String> = pieces(&source).into_iter().map(str::to_string).collect();
+    let donor_parts = pieces(&donor);
+    for _ in 0..1 + rng.below(4) {
+        let len = parts.len().max(1);
+        let at = rng.below(len);
+        let span = (1 + rng.below(8)).min(len - at);
+        match rng.below(6) {
+            0 if !parts.is_empty() => {
+                parts.drain(at..at + span);
+            }
+            1 if !parts.is_empty() => {
+                let copy: Vec<String> = parts[at..at + span].to_vec();
+                let to = rng.below(parts.len() + 1);
+                parts.splice(to..to, copy);
+            }
+            2 => parts.insert(at.min(parts.len()), rng.pick(DICTIONARY).to_string()),
+            3 if !parts.is_empty() => {
+                // Swap a literal for an interesting one
+                let literals: Vec<usize> = (0..parts.len())
+                    .filter(|&i| parts[i].starts_with(|c: char| c.is_ascii_digit() || c == '"'))
+                    .collect();
+                let i = if literals.is_empty() { at } else { *rng.pick(&literals) };
+                parts[i] = rng.pick(INTERESTING).to_string();
+            }
+            4 if !donor_parts.is_empty() => {
+                let from = rng.below(donor_parts.len());
+                let take = (1 + rng.below(32)).min(donor_parts.len() - from);
+                let to = rng.below(parts.len() + 1);
+                parts.splice(to..to, donor_parts[from..from + take].iter().map(|s| s.to_string()));
+            }
+            _ if parts.len() > 1 => {
+                let other = rng.below(parts.len());
+                parts.swap(at, other);
+            }
+            _ => {}
+        }
+    }
+    parts.concat().into_bytes()
+}
+
+/// Raw byte flips, insertions and truncation, which can leave invalid UTF-8
+fn mutate_bytes(rng: &mut Rng, input: &[u8]) -> Vec<u8> {
+    let mut bytes = input.to_vec();
+    for _ in 0..1 + rng.below(4) {
+        match rng.below(4) {
+            0 if !bytes.is_empty() => {
+                let i = rng.below(bytes.len());
+                bytes[i] ^= 1 << rng.below(8);
+            }
+            1 => {
+                let i = rng.below(bytes.len() + 1);
+                bytes.insert(i, rng.next_u64() as u8);
+            }
+            2 if !bytes.is_empty() => {
+                let i = rng.below(bytes.len());
+                bytes.truncate(i);
+            }
+            _ => {
+                let i = rng.below(bytes.len() + 1);
+                let piece = rng.pick(DICTIONARY).as_bytes();
+                bytes.splice(i..i, piece.iter().copied());
+            }
+        }
+    }
+    bytes
+}
+
+/// Random but syntactically valid programs from a small grammar: functions
+/// with loops, recursion, arrays, strings and builtin calls on edge-case values
+pub struct Generator<'a> {
+    rng: &'a mut Rng,
+    out: String,
+    vars: Vec<String>,
+    functions: Vec<(String, usize)>,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(rng: &'a mut Rng) -> Self {
+        Generator { rng, out: String::new(), vars: Vec::new(), functions: Vec::new() }
+    }
+
+    pub fn program(mut self) -> String {
+        let count = 1 + self.rng.below(4);
+        for i in 0..count {
+            let arity = self.rng.below(3);
+            self.functions.push((format!("f{}", i), arity));
+        }
+        for i in 0..count {
+            let (name, arity) = self.functions[i].clone();
+            self.vars = (0..arity).map(|p| format!("p{}", p)).collect();
+            self.out.push_str(&format!("fn {}({}) {{\n", name, self.vars.join(", ")));
+            self.block(1, 2, 5);
+            self.out.push_str("}\n\n");
+        }
+        self.vars.clear();
+        self.out.push_str("fn main() {\n");
+        self.block(1, 2, 6);
+        self.out.push_str("}\n");
+        self.out
+    }
+
+    /// `min` to `min + spread - 1` statements
+    fn block(&mut self, depth: usize, min: usize, spread: usize) {
+        let scope = self.vars.len();
+        for _ in 0..min + self.rng.below(spread) {
+            self.statement(depth);
+        }
+        self.vars.truncate(scope);
+    }
+
+    fn indent(&mut self, depth: usize) {
+        self.out.push_str(&"    ".repeat(depth));
+    }
+
+    fn statement(&mut self, depth: usize) {
+        let nested = depth < 3;
+        self.indent(depth);
+        match self.rng.below(8) {
+            0 | 1 => {
+                let name = format!("v{}", self.vars.len());
+                let value = self.expr(0);
+                self.out.push_str(&format!("let {} = {};\n", name, value));
+                self.vars.push(name);
+            }
+            2 if !self.vars.is_empty() => {
+                let name = self.rng.pick(&self.vars).clone();
+                let value = self.expr(0);
+                self.out.push_str(&format!("{} = {};\n", name, value));
+            }
+            3 if nested => {
+                let cond = self.expr(0);
+                self.out.push_str(&format!("if ({}) {{\n", cond));
+                self.block(depth + 1, 1, 3);
+                self.indent(depth);
+                self.out.push_str("} else {\n");
+                self.block(depth + 1, 1, 2);
+                self.indent(depth);
+                self.out.push_str("}\n");
+            }
+            4 if nested => {
+                // Bounded loop, with a counter the body can't touch
+                let counter = format!("i{}", depth);
+                let bound = self.rng.pick(&["0", "1", "3", "10"]).to_string();
+                self.out.push_str(&format!("let {} = 0;\n", counter));
+                self.indent(depth);
+                self.out.push_str(&format!("while ({} < {}) {{\n", counter, bound));
+                self.indent(depth + 1);
+                self.out.push_str(&format!("{} = {} + 1;\n", counter, counter));
+                self.block(depth + 1, 1, 3);
+                self.indent(depth);
+                self.out.push_str("}\n");
+            }
+            5 => {
+                let value = self.expr(0);
+                self.out.push_str(&format!("print({});\n", value));
+            }
+            6 if depth > 1 => {
+                let value = self.expr(0);
+                self.out.push_str(&format!("return {};\n", value));
+            }
+            _ => {
+                let value = self.call(0);
+                self.out.push_str(&format!("{};\n", value));
+            }
+        }
+    }
+
+    fn expr(&mut self, depth: usize) -> String {
+        if depth > 3 {
+            return self.atom();
+        }
+        match self.rng.below(7) {
+            0 | 1 => self.atom(),
+            2 => {
+                let op = self.rng.pick(BINARY_OPS);
+                format!("{} {} {}", self.expr(depth + 1), op, self.expr(depth + 1))
+            }
+            3 => format!("({})", self.expr(depth + 1)),
+            4 => {
+                let items: Vec<String> = (0..self.rng.below(4)).map(|_| self.expr(depth + 1)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            5 => format!("{}[{}]", self.atom(), self.expr(depth + 1)),
+            _ => self.call(depth + 1),
+        }
+    }
+
+    fn call(&mut self, depth: usize) -> String {
+        let (name, arity) = if !self.functions.is_empty() && self.rng.chance(3) {
+            self.rng.pick(&self.functions).clone()
+        } else {
+            let (name, arity) = *self.rng.pick(BUILTINS);
+            (name.to_string(), arity)
+        };
+        let args: Vec<String> = (0..arity).map(|_| self.expr(depth + 1)).collect();
+        format!("{}({})", name, args.join(", "))
+    }
+
+    fn atom(&mut self) -> String {
+        if !self.vars.is_empty() && self.rng.chance(2) {
+            return self.rng.pick(&self.vars).clone();
+        }
+        match self.rng.below(3) {
+            0 => (self.rng.below(20) as i64 - 5).to_string(),
+            _ => self.rng.pick(INTERESTING).to_string(),
+        }
+    }
+}
+
+/// Settings for a `cryo fuzz` session
+pub struct FuzzOptions {
+    pub target: Target,
+    pub iterations: u64,
+    pub seed: u64,
+    pub limits: Limits,
+    /// Seed programs to mutate
+    pub corpus: Vec<Vec<u8>>,
+    /// Where crashing inputs are written
+    pub artifacts: PathBuf,
+    /// The `cryo` executable the inputs run in
+    pub worker: PathBuf,
+}
+
+/// One distinct crash found by `fuzz`
+pub struct Finding {
+    pub crash: Crash,
+    /// Saved reproducer, when it could be written
+    pub input_path: Option<PathBuf>,
+    /// How many inputs hit the same location
+    pub hits: u64,
+}
+
+/// Marks the worker's answers among anything else fuzzed programs write
+/// to stderr
+const REPLY: &str = "cryo-fuzz-worker: ";
+
+/// `cryo fuzz --worker`: run each input `fuzz` sends on `requests`, a
+/// length line and then the bytes, answering `ok` or the crash on
+/// `replies`. What the programs print is dropped.
+pub fn serve(target: Target, limits: &Limits, mut requests: impl BufRead, mut replies: impl Write) -> io::Result<()> {
+    panic::set_hook(Box::new(|info| {
+        let location = info.location().map(|l| format!("{}:{}", l.file(), l.line())).unwrap_or_default();
+        // With panic=abort (release builds) nothing unwinds back to `serve`,
+        // so answer now, before the process goes
+        if cfg!(panic = "abort") {
+            eprintln!("\n{}crash\t{}\t{}", REPLY, location, info.to_string().replace('\n', " "));
+        }
+        LAST_PANIC.with(|p| *p.borrow_mut() = Some(Crash { message: String::new(), location }));
+    }));
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if requests.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let len = line.trim().parse::<usize>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut input = vec![0; len];
+        requests.read_exact(&mut input)?;
+        let (result, _) = crate::output::capture(|| run_one(target, &input, limits));
+        match result {
+            Ok(()) => writeln!(replies, "\n{}ok", REPLY)?,
+            Err(crash) => writeln!(replies, "\n{}crash\t{}\t{}", REPLY, crash.location, crash.message.replace('\n', " "))?,
+        }
+        replies.flush()?;
+    }
+}
+
+/// A `cryo fuzz --worker` child. A stack overflow aborts the process instead
+/// of unwinding, so `fuzz` can't catch it; when the worker dies, the input
+/// it was running is the crash.
+struct Worker {
+    child: Child,
+    requests: ChildStdin,
+    /// The worker's stderr, line by line, read on another thread so a hung
+    /// input can time out
+    replies: Receiver<String>,
+    timeout: Duration,
+}
+
+impl Worker {
+    fn spawn(exe: &Path, target: Target, limits: &Limits) -> Result<Worker, String> {
+        let (max_len, steps) = (limits.max_len.to_string(), limits.steps.to_string());
+        let mut child = Command::new(exe)
+            .args(["fuzz", "--worker", "--target", target.name(), "--max-len", &max_len, "--steps", &steps])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot start {}: {}", exe.display(), e))?;
+        let (Some(requests), Some(stderr)) = (child.stdin.take(), child.stderr.take()) else {
+            return Err("worker has no pipes".to_string());
+        };
+        let (send, replies) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                let Ok(line) = line else { break };
+                if send.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Worker { child, requests, replies, timeout: limits.timeout })
+    }
+
+    /// Run one input, Err when it panicked or took the worker down
+    fn run(&mut self, input: &[u8]) -> Result<(), Crash> {
+        let sent = writeln!(self.requests, "{}", input.len()).and_then(|_| self.requests.write_all(input)).and_then(|_| self.requests.flush());
+        // The last other line is usually why the worker died
+        let mut last = String::new();
+        let deadline = Instant::now() + self.timeout;
+        while sent.is_ok() {
+            let line = match self.replies.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = self.child.kill();
+                    return Err(Crash { message: format!("no answer after {:?}", self.timeout), location: "<timeout>".to_string() });
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let Some(at) = line.find(REPLY) else {
+                if !line.trim().is_empty() {
+                    last = line.trim().to_string();
+                }
+                continue;
+            };
+            let reply = line[at + REPLY.len()..].trim_end();
+            if reply == "ok" {
+                return Ok(());
+            }
+            let mut fields = reply.strip_prefix("crash\t").unwrap_or(reply).splitn(2, '\t');
+            let location = fields.next().unwrap_or_default().to_string();
+            return Err(Crash { message: fields.next().unwrap_or_default().to_string(), location });
+        }
+        let status = self.child.wait().map(|s| s.to_string()).unwrap_or_else(|e| e.to_string());
+        let location = if last.contains("stack overflow") { "<stack overflow>".to_string() } else { format!("<worker died: {}>", status) };
+        Err(Crash { message: if last.is_empty() { status } else { last }, location })
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Run `opts.iterations` generated and mutated inputs, reporting each new
+/// crash location through `on_finding` as it is found. Err when the worker
+/// can't be started.
+pub fn fuzz(opts: &FuzzOptions, mut on_finding: impl FnMut(&Finding)) -> Result<Vec<Finding>, String> {
+    let mut rng = Rng::new(opts.seed);
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut worker = None;
+
+    for _ in 0..opts.iterations {
+        let input = match rng.below(3) {
+            0 => Generator::new(&mut rng).program().into_bytes(),
+            _ if !opts.corpus.is_empty() => {
+                let base = rng.pick(&opts.corpus).clone();
+                let donor = rng.pick(&opts.corpus).clone();
+                mutate(&mut rng, &base, &donor)
+            }
+            _ => {
+                let base = Generator::new(&mut rng).program().into_bytes();
+                mutate(&mut rng, &base, &[])
+            }
+        };
+        let running = match &mut worker {
+            Some(running) => running,
+            None => worker.insert(Worker::spawn(&opts.worker, opts.target, &opts.limits)?),
+        };
+        let Err(crash) = running.run(&input) else { continue };
+        // Start afresh: the worker may be gone, or left in a bad state
+        worker = None;
+        if let Some(known) = findings.iter_mut().find(|f| f.crash.location == crash.location) {
+            known.hits += 1;
+            continue;
+        }
+        let input_path = save_input(&opts.artifacts, &input);
+        findings.push(Finding { crash, input_path, hits: 1 });
+        on_finding(findings.last().unwrap());
+    }
+
+    Ok(findings)
+}
+
+/// Write a crashing input as `crash-<hash>.cryo` under `dir`
+fn save_input(dir: &Path, input: &[u8]) -> Option<PathBuf> {
+    // FNV-1a, so the same input always lands in the same file
+    let hash = input.iter().fold(0xcbf2_9ce4_8422_2325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3));
+    let path = dir.join(format!("crash-{:016x}.cryo", hash));
+    std::fs::create_dir_all(dir).ok()?;
+    std::fs::write(&path, input).ok()?;
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_programs_parse() {
+        let mut rng = Rng::new(7);
+        for _ in 0..50 {
+            let source = Generator::new(&mut rng).program();
+            let tokens = lexer::tokenize(&source);
+            assert!(Parser::new(&tokens).parse().is_ok(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_mutations_are_reproducible() {
+        let seed = b"fn main() { let s = \"h\xc3\xa9llo\"; print(substr(s, 1, 2)); }";
+        let run = |seed_value| {
+            let mut rng = Rng::new(seed_value);
+            (0..20).map(|_| mutate(&mut rng, seed, b"let x = [1, 2];")).collect::<Vec<_>>()
+        };
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+        assert_eq!(pieces("let é = \"a \\\" b\";"), ["let", " ", "é", " ", "=", " ", "\"a \\\" b\"", ";"]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits { max_len: 1024, steps: 1000, ..Limits::default() };
+        // Stopped by the step limit instead of hanging
+        assert!(run_one(Target::Run, b"fn main() { while (true) {} }", &limits).is_ok());
+        assert!(run_one(Target::Run, b"fn f(n) { return f(n + 1); } fn main() { f(0); }", &limits).is_ok());
+        // Sandboxed: exit() would end the test process
+        assert!(run_one(Target::Run, b"fn main() { exit(3); }", &limits).is_ok());
+        assert!(run_one(Target::Lex, &[0xff, b'"', 0xc3], &limits).is_ok());
+    }
+
+    #[test]
+    fn test_worker_answers_each_input() {
+        let inputs: &[&[u8]] = &[b"fn main() { print(1); }", b"", &[0xff, 0xfe]];
+        let requests: Vec<u8> = inputs.iter().flat_map(|i| [format!("{}\n", i.len()).into_bytes(), i.to_vec()].concat()).collect();
+        let mut replies = Vec::new();
+        let previous_hook = panic::take_hook();
+        let served = serve(Target::Run, &Limits::default(), &requests[..], &mut replies);
+        panic::set_hook(previous_hook);
+        assert!(served.is_ok());
+        assert_eq!(String::from_utf8(replies).unwrap(), format!("\n{}ok\n", REPLY).repeat(3));
+    }
+}
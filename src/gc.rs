@@ -53,6 +53,11 @@ impl GarbageCollector {
         }
     }
     
+    /// Number of allocations between collections
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold.max(1);
+    }
+    
     /// Allocate a new object on the heap
     pub fn alloc(&mut self, obj: GcObject) -> ObjectId {
         let id = self.next_id;
@@ -0,0 +1,331 @@
+// ============================================
+// Cryo Glob Patterns
+// Shell-style wildcards for glob_match() and glob(), and for finding the
+// files `cryo test-lang` runs:
+//
+//   *       any run of characters within one path segment
+//   ?       any one character except /
+//   [abc]   one of the listed characters; [a-z] ranges, [!abc] or [^abc] negated
+//   **      as a whole segment, zero or more directories
+//   {a,b}   either alternative (may nest)
+//   \x      x itself
+//
+// Malformed brackets and braces match literally. When walking directories,
+// wildcards skip names starting with `.`, as in the shell; write the dot
+// (`.github/*`) to include them.
+// ============================================
+
+use crate::platform::{NativePlatform, Platform};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    Any,
+    Star,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// `**`
+    Dirs,
+    Tokens(Vec<Token>),
+}
+
+/// A compiled pattern: one list of segments per brace alternative
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    alternatives: Vec<Vec<Segment>>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Pattern {
+        let alternatives = expand_braces(pattern)
+            .iter()
+            .map(|p| split_segments(p).iter().map(|s| parse_segment(s)).collect())
+            .collect();
+        Pattern { alternatives }
+    }
+
+    /// Whether `path` (segments separated by `/`) matches. Wildcards match
+    /// leading dots only when `dotfiles` is set.
+    pub fn matches(&self, path: &str, dotfiles: bool) -> bool {
+        let path: Vec<&str> = path.split('/').collect();
+        self.alternatives.iter().any(|segs| match_segments(segs, &path, dotfiles, false))
+    }
+
+    /// Whether something under directory `dir` could still match
+    fn may_contain(&self, dir: &[&str], dotfiles: bool) -> bool {
+        self.alternatives.iter().any(|segs| match_segments(segs, dir, dotfiles, true))
+    }
+}
+
+/// `{a,b}` alternatives, outermost first, as separate patterns
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '{' => {
+                // Find the matching close brace and the top-level commas
+                let (mut depth, mut commas, mut j) = (0, Vec::new(), i + 1);
+                while j < chars.len() {
+                    match chars[j] {
+                        '\\' => j += 1,
+                        '{' => depth += 1,
+                        '}' if depth == 0 => break,
+                        '}' => depth -= 1,
+                        ',' if depth == 0 => commas.push(j),
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                if j >= chars.len() || commas.is_empty() {
+                    i += 1;
+                    continue;
+                }
+                let prefix: String = chars[..i].iter().collect();
+                let suffix: String = chars[j + 1..].iter().collect();
+                let mut bounds = vec![i];
+                bounds.extend(&commas);
+                bounds.push(j);
+                return bounds
+                    .windows(2)
+                    .flat_map(|w| {
+                        let choice: String = chars[w[0] + 1..w[1]].iter().collect();
+                        expand_braces(&format!("{}{}{}", prefix, choice, suffix))
+                    })
+                    .collect();
+            }
+            _ => i += 1,
+        }
+    }
+    vec![pattern.to_string()]
+}
+
+/// Split on `/`, dropping empty segments (`a//b`, a leading `/`)
+fn split_segments(pattern: &str) -> Vec<String> {
+    pattern.split('/').filter(|s| !s.is_empty()).map(str::to_string).collect()
+}
+
+fn parse_segment(segment: &str) -> Segment {
+    if segment == "**" {
+        return Segment::Dirs;
+    }
+    let chars: Vec<char> = segment.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Literal(chars[i + 1]));
+                i += 1;
+            }
+            '*' => {
+                // `**` inside a segment is just `*`
+                if tokens.last() != Some(&Token::Star) {
+                    tokens.push(Token::Star);
+                }
+            }
+            '?' => tokens.push(Token::Any),
+            '[' => match parse_class(&chars[i + 1..]) {
+                Some((class, used)) => {
+                    tokens.push(class);
+                    i += used;
+                }
+                None => tokens.push(Token::Literal('[')),
+            },
+            c => tokens.push(Token::Literal(c)),
+        }
+        i += 1;
+    }
+    Segment::Tokens(tokens)
+}
+
+/// `[...]` body after the `[`: the class and how many chars it used, or
+/// None when there's no closing `]`
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < chars.len() {
+        // A `]` right after the `[` (or `[!`) is a member
+        if chars[i] == ']' && i > start {
+            return Some((Token::Class { ranges, negated }, i + 1));
+        }
+        let lo = if chars[i] == '\\' && i + 1 < chars.len() {
+            i += 1;
+            chars[i]
+        } else {
+            chars[i]
+        };
+        if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+            ranges.push((lo, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((lo, lo));
+            i += 1;
+        }
+    }
+    None
+}
+
+/// `segs` against `path`. With `prefix`, true when `path` is a directory
+/// some deeper path under which could match.
+fn match_segments(segs: &[Segment], path: &[&str], dotfiles: bool, prefix: bool) -> bool {
+    let Some((name, rest)) = path.split_first() else {
+        return if prefix { !segs.is_empty() } else { segs.iter().all(|s| *s == Segment::Dirs) };
+    };
+    match segs.split_first() {
+        None => false,
+        Some((Segment::Dirs, after)) => {
+            match_segments(after, path, dotfiles, prefix)
+                || ((dotfiles || !name.starts_with('.')) && match_segments(segs, rest, dotfiles, prefix))
+        }
+        Some((Segment::Tokens(tokens), after)) => match_name(tokens, name, dotfiles) && match_segments(after, rest, dotfiles, prefix),
+    }
+}
+
+/// One segment: `*` backtracks to the most recent star only, which is enough
+/// since a star can't cross a `/`
+fn match_name(tokens: &[Token], name: &str, dotfiles: bool) -> bool {
+    let text: Vec<char> = name.chars().collect();
+    if !dotfiles && text.first() == Some(&'.') && tokens.first() != Some(&Token::Literal('.')) {
+        return false;
+    }
+    let (mut t, mut c) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while c < text.len() {
+        let matched = match tokens.get(t) {
+            Some(Token::Star) => {
+                star = Some((t, c));
+                t += 1;
+                continue;
+            }
+            Some(Token::Literal(l)) => *l == text[c],
+            Some(Token::Any) => true,
+            Some(Token::Class { ranges, negated }) => ranges.iter().any(|&(lo, hi)| lo <= text[c] && text[c] <= hi) != *negated,
+            None => false,
+        };
+        if matched {
+            t += 1;
+            c += 1;
+        } else if let Some((star_t, star_c)) = star {
+            // Let the star swallow one more character and retry
+            t = star_t + 1;
+            c = star_c + 1;
+            star = Some((star_t, star_c + 1));
+        } else {
+            return false;
+        }
+    }
+    tokens[t..].iter().all(|tok| *tok == Token::Star)
+}
+
+/// Whether `text` contains any wildcard or brace
+pub fn is_pattern(text: &str) -> bool {
+    text.contains(['*', '?', '[', '{'])
+}
+
+/// The directory a pattern starts from and the pattern relative to it:
+/// `tests/lang/**/*.cryo` is `("tests/lang", "**/*.cryo")`
+pub fn split_base(pattern: &str) -> (String, String) {
+    let segments: Vec<&str> = pattern.split('/').collect();
+    let literal = segments.iter().take(segments.len() - 1).take_while(|s| !is_pattern(s) && !s.contains('\\')).count();
+    let base = match segments[..literal].join("/") {
+        base if base.is_empty() && pattern.starts_with('/') => "/".to_string(),
+        base if base.is_empty() => ".".to_string(),
+        base => base,
+    };
+    (base, segments[literal..].join("/"))
+}
+
+/// Whether `text` matches `pattern`, dotfiles included (`glob_match("*", ".env")`)
+pub fn matches(pattern: &str, text: &str) -> bool {
+    Pattern::new(pattern).matches(text, true)
+}
+
+/// Files and directories under `dir` whose path relative to `dir` matches
+/// `pattern`, sorted, each prefixed with `dir` (unless it's `.`). Symlinks are
+/// listed but not followed; unreadable subdirectories are skipped.
+pub fn glob(dir: &str, pattern: &str) -> Result<Vec<String>, String> {
+    glob_in(&NativePlatform, dir, pattern)
+}
+
+/// `glob` over `platform`'s files
+pub fn glob_in(platform: &dyn Platform, dir: &str, pattern: &str) -> Result<Vec<String>, String> {
+    let pattern = Pattern::new(pattern);
+    let root = if dir.is_empty() { "." } else { dir };
+    platform.read_dir(root).map_err(|e| format!("glob: {}: {}", root, e))?;
+    let mut found = Vec::new();
+    let mut pending = vec![String::new()];
+    while let Some(rel) = pending.pop() {
+        let dir = Path::new(root).join(&rel);
+        let Ok(entries) = platform.read_dir(&dir.to_string_lossy()) else { continue };
+        for entry in entries {
+            let path = if rel.is_empty() { entry.name } else { format!("{}/{}", rel, entry.name) };
+            if pattern.matches(&path, false) {
+                found.push(path.clone());
+            }
+            if entry.is_dir && pattern.may_contain(&path.split('/').collect::<Vec<_>>(), false) {
+                pending.push(path);
+            }
+        }
+    }
+    found.sort();
+    let prefix = match root.trim_end_matches('/') {
+        "." => None,
+        trimmed => Some(trimmed),
+    };
+    Ok(found.into_iter().map(|p| prefix.map_or(p.clone(), |d| format!("{}/{}", d, p))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.cryo", "main.cryo"));
+        assert!(!matches("*.cryo", "src/main.cryo"));
+        assert!(matches("**/*.cryo", "main.cryo") && matches("**/*.cryo", "a/b/main.cryo"));
+        assert!(matches("src/**", "src/a/b") && !matches("src/**", "lib/a"));
+        assert!(matches("test_?.cryo", "test_1.cryo") && !matches("test_?.cryo", "test_10.cryo"));
+        assert!(matches("[a-c]x[!0-9]", "bxy") && !matches("[a-c]x[!0-9]", "bx7"));
+        assert!(matches("[]]", "]") && matches("[x", "[x"));
+        assert!(matches("*.{cryo,ar}", "a.ar") && matches("{src,tests/{lang,parser}}/*", "tests/parser/x"));
+        assert!(matches("a\\*", "a*") && !matches("a\\*", "ab"));
+        assert!(matches("*a*b*", "xxaybzb") && !matches("*a*b", "ba"));
+        assert!(matches("*", ".env"));
+    }
+
+    #[test]
+    fn test_split_base() {
+        assert_eq!(split_base("tests/lang/**/*.cryo"), ("tests/lang".to_string(), "**/*.cryo".to_string()));
+        assert_eq!(split_base("*.cryo"), (".".to_string(), "*.cryo".to_string()));
+        assert_eq!(split_base("/tmp/*/x"), ("/tmp".to_string(), "*/x".to_string()));
+    }
+
+    #[test]
+    fn test_glob_walks_and_skips_hidden() {
+        let dir = std::env::temp_dir().join(format!("cryo_glob_{}", std::process::id()));
+        for file in ["a.cryo", "sub/b.cryo", "sub/deep/c.cryo", "sub/notes.txt", ".hidden/d.cryo"] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let root = dir.to_string_lossy().into_owned();
+        let rel = |paths: Vec<String>| paths.into_iter().map(|p| p[root.len() + 1..].to_string()).collect::<Vec<_>>();
+        assert_eq!(rel(glob(&root, "**/*.cryo").unwrap()), ["a.cryo", "sub/b.cryo", "sub/deep/c.cryo"]);
+        assert_eq!(rel(glob(&root, "sub/*").unwrap()), ["sub/b.cryo", "sub/deep", "sub/notes.txt"]);
+        assert_eq!(rel(glob(&root, ".hidden/*.cryo").unwrap()), [".hidden/d.cryo"]);
+        assert!(glob(&format!("{}/missing", root), "*").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+// ============================================
+// Cryo Sized Integers
+// Every int is an i64 at runtime; `i8` ... `u64` annotations and casts give
+// the fixed-width view FFI and binary protocols need:
+//
+//   let b: u8 = 255;        // checked: 256 here is a runtime error
+//   let w = 300 as u8;      // explicit cast: wraps to 44
+//   let s = 200 as i8;      // sign-extends: -56
+//
+// Casts keep the low bits of the value in two's complement, like Rust's `as`
+// between integers; floats truncate toward zero first. Annotations never
+// convert: a let, parameter, return value or assignment whose value is out
+// of range is an error, so truncation only happens where the code asks for
+// it. u64 values above i64::MAX are bigints.
+// ============================================
+
+use crate::bigint::BigInt;
+use crate::interpreter::Value;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntType {
+    pub const ALL: [IntType; 8] = [IntType::I8, IntType::I16, IntType::I32, IntType::I64, IntType::U8, IntType::U16, IntType::U32, IntType::U64];
+
+    /// The type an annotation or `as` target names, if it's a sized int
+    pub fn parse(name: &str) -> Option<IntType> {
+        IntType::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntType::I8 => "i8",
+            IntType::I16 => "i16",
+            IntType::I32 => "i32",
+            IntType::I64 => "i64",
+            IntType::U8 => "u8",
+            IntType::U16 => "u16",
+            IntType::U32 => "u32",
+            IntType::U64 => "u64",
+        }
+    }
+
+    /// The builtin `x as T` calls
+    pub fn cast_builtin(self) -> &'static str {
+        match self {
+            IntType::I8 => "to_i8",
+            IntType::I16 => "to_i16",
+            IntType::I32 => "to_i32",
+            IntType::I64 => "to_i64",
+            IntType::U8 => "to_u8",
+            IntType::U16 => "to_u16",
+            IntType::U32 => "to_u32",
+            IntType::U64 => "to_u64",
+        }
+    }
+
+    fn bits(self) -> u32 {
+        match self {
+            IntType::I8 | IntType::U8 => 8,
+            IntType::I16 | IntType::U16 => 16,
+            IntType::I32 | IntType::U32 => 32,
+            IntType::I64 | IntType::U64 => 64,
+        }
+    }
+
+    fn signed(self) -> bool {
+        matches!(self, IntType::I8 | IntType::I16 | IntType::I32 | IntType::I64)
+    }
+
+    pub fn min(self) -> i128 {
+        if self.signed() { -(1i128 << (self.bits() - 1)) } else { 0 }
+    }
+
+    pub fn max(self) -> i128 {
+        if self.signed() { (1i128 << (self.bits() - 1)) - 1 } else { (1i128 << self.bits()) - 1 }
+    }
+
+    /// `bits` (a two's complement u64) cut to this width: the low bits,
+    /// sign-extended for signed types
+    pub fn wrap(self, bits: u64) -> i128 {
+        let shift = 64 - self.bits();
+        if self.signed() {
+            (((bits << shift) as i64) >> shift) as i128
+        } else {
+            ((bits << shift) >> shift) as i128
+        }
+    }
+}
+
+/// `value as t`: wraps out-of-range ints, truncates floats and decimals
+/// toward zero, and parses numeric strings
+pub fn cast(value: &Value, t: IntType) -> Result<Value, String> {
+    let bits = match value {
+        Value::Int(n) => *n as u64,
+        Value::BigInt(n) => n.low_u64(),
+        // Saturating, and NaN becomes 0, like Rust's float-to-int `as`
+        Value::Float(f) => f.trunc() as i64 as u64,
+        Value::Decimal(_) | Value::Bool(_) => value.as_int() as u64,
+        Value::String(s) => match s.trim().parse::<i64>() {
+            Ok(n) => n as u64,
+            Err(_) => match BigInt::parse(s.trim()) {
+                Some(n) => n.low_u64(),
+                None => return Err(format!("cannot cast \"{}\" to {}", s, t.name())),
+            },
+        },
+        other => return Err(format!("cannot cast {} to {}", type_name(other), t.name())),
+    };
+    Ok(int_value(t.wrap(bits)))
+}
+
+/// Ok when `value` is an int in `t`'s range. `what` names the annotated
+/// thing for the error, like "let x".
+pub fn check(value: &Value, t: IntType, what: &str) -> Result<(), String> {
+    let exact = match value {
+        Value::Int(n) => Some(*n as i128),
+        Value::BigInt(n) => n.to_i128(),
+        other => return Err(format!("{}: {} needs an integer, got {}", what, t.name(), type_name(other))),
+    };
+    match exact {
+        Some(n) if n >= t.min() && n <= t.max() => Ok(()),
+        _ => Err(format!(
+            "{}: {} does not fit in {} ({} to {}); use `as {}` to truncate",
+            what,
+            value.to_string_val(),
+            t.name(),
+            t.min(),
+            t.max(),
+            t.name()
+        )),
+    }
+}
+
+/// An Int, or a BigInt for u64 values past i64::MAX
+fn int_value(n: i128) -> Value {
+    match i64::try_from(n) {
+        Ok(n) => Value::Int(n),
+        Err(_) => Value::BigInt(Rc::new(BigInt::from_i128(n))),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Int(_) | Value::BigInt(_) => "int",
+        Value::Float(_) => "float",
+        Value::Decimal(_) => "decimal",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Map(_) => "map",
+        Value::Function(_) => "function",
+        Value::Enum(_) => "enum",
+        Value::Struct(..) | Value::Collection(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(IntType::U8.wrap(300), 44);
+        assert_eq!(IntType::I8.wrap(200), -56);
+        assert_eq!(IntType::I8.wrap(-1i64 as u64), -1);
+        assert_eq!(IntType::U16.wrap(-1i64 as u64), 65535);
+        assert_eq!(IntType::I32.wrap(1 << 31), i32::MIN as i128);
+        assert_eq!(IntType::U64.wrap(u64::MAX), u64::MAX as i128);
+        assert_eq!(IntType::I64.wrap(u64::MAX), -1);
+        assert_eq!((IntType::U32.min(), IntType::U32.max()), (0, u32::MAX as i128));
+        assert_eq!(IntType::parse("i16"), Some(IntType::I16));
+        assert_eq!(IntType::parse("int"), None);
+    }
+
+    #[test]
+    fn test_cast_and_check() {
+        assert_eq!(cast(&Value::Int(-1), IntType::U64).unwrap().to_string_val(), "18446744073709551615");
+        assert_eq!(cast(&Value::Float(-3.9), IntType::U8).unwrap().as_int(), 253);
+        assert_eq!(cast(&Value::String(" 513 ".into()), IntType::U8).unwrap().as_int(), 1);
+        assert!(cast(&Value::Null, IntType::I32).is_err());
+
+        assert!(check(&Value::Int(255), IntType::U8, "let b").is_ok());
+        assert_eq!(
+            check(&Value::Int(256), IntType::U8, "let b").unwrap_err(),
+            "let b: 256 does not fit in u8 (0 to 255); use `as u8` to truncate"
+        );
+        assert!(check(&cast(&Value::Int(-1), IntType::U64).unwrap(), IntType::U64, "x").is_ok());
+        assert_eq!(check(&Value::Float(1.0), IntType::I32, "x").unwrap_err(), "x: i32 needs an integer, got float");
+    }
+}
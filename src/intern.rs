@@ -0,0 +1,137 @@
+// Cryo String Interner
+// Identifiers are interned once in the lexer so later passes compare and
+// hash a u32 instead of a heap String
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+
+/// Interned identifier handle
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Global symbol table. Strings are leaked so `as_str` can hand out `&'static str`;
+/// the set of identifiers in a program is small and lives for the whole run.
+struct Interner {
+    map: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+/// Symbols the runtime looks up on hot paths, pre-seeded in this order
+/// so they can be used as constants without touching the table
+const PREDEFINED: &[&str] = &["", "self", "main", "Array", "string", "i32"];
+
+impl Symbol {
+    pub const EMPTY: Symbol = Symbol(0);
+    pub const SELF: Symbol = Symbol(1);
+    pub const MAIN: Symbol = Symbol(2);
+    pub const ARRAY: Symbol = Symbol(3);
+    pub const STRING: Symbol = Symbol(4);
+    pub const I32: Symbol = Symbol(5);
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| {
+        let mut table = Interner {
+            map: HashMap::new(),
+            strings: Vec::new(),
+        };
+        for (i, s) in PREDEFINED.iter().enumerate() {
+            table.strings.push(s);
+            table.map.insert(s, Symbol(i as u32));
+        }
+        RwLock::new(table)
+    })
+}
+
+impl Symbol {
+    /// Intern a string, returning the existing symbol if already known
+    pub fn intern(s: &str) -> Symbol {
+        if let Some(&sym) = interner().read().unwrap().map.get(s) {
+            return sym;
+        }
+        let mut table = interner().write().unwrap();
+        if let Some(&sym) = table.map.get(s) {
+            return sym;
+        }
+        let sym = Symbol(table.strings.len() as u32);
+        let leaked: &'static str = Box::leak(s.to_string().into_boxed_str());
+        table.strings.push(leaked);
+        table.map.insert(leaked, sym);
+        sym
+    }
+
+    /// Resolve back to the interned string. Hot paths (builtin dispatch) call this
+    /// on every call, so each thread keeps a lock-free mirror of the table.
+    pub fn as_str(self) -> &'static str {
+        thread_local! {
+            static STRINGS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+        }
+        let idx = self.0 as usize;
+        STRINGS.with(|cache| {
+            if let Some(s) = cache.borrow().get(idx) {
+                return *s;
+            }
+            let table = interner().read().unwrap();
+            let mut cache = cache.borrow_mut();
+            let known = cache.len();
+            cache.extend_from_slice(&table.strings[known..]);
+            cache[idx]
+        })
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Symbol::intern(s)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_dedup() {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern("counter");
+        let c = Symbol::intern("other");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a.as_str(), "counter");
+        assert!(a == "counter");
+    }
+
+    #[test]
+    fn test_predefined_symbols() {
+        assert_eq!(Symbol::intern("main"), Symbol::MAIN);
+        assert_eq!(Symbol::intern("Array"), Symbol::ARRAY);
+        assert_eq!(Symbol::EMPTY.as_str(), "");
+    }
+}
@@ -6,9 +6,10 @@
 use std::collections::HashMap;
 use cranelift::prelude::*;
 use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::{DataContext, Linkage, Module, FuncId};
+use cranelift_module::{Linkage, Module, FuncId};
 use cranelift_codegen::ir::AbiParam;
 use cranelift_codegen::settings::{self, Configurable};
+use crate::bytecode_vm::{CompiledFunc, Constant, OpCode, ResumeFrame, VMValue};
 
 /// Represents a compiled function
 pub struct CompiledFunction {
@@ -26,8 +27,6 @@ pub struct JitCompiler {
     builder_context: FunctionBuilderContext,
     /// Cranelift context
     ctx: codegen::Context,
-    /// Data context for constants
-    data_ctx: DataContext,
     /// Compiled functions cache
     compiled_functions: HashMap<String, CompiledFunction>,
     /// Hot path threshold
@@ -36,6 +35,10 @@ pub struct JitCompiler {
     call_counts: HashMap<String, u64>,
     /// Whether JIT is enabled
     enabled: bool,
+    /// Bytecode functions compiled so far, by VM function index
+    native: HashMap<usize, (FuncId, NativeFn)>,
+    /// Native calls that handed over to the VM
+    deopts: u64,
 }
 
 impl JitCompiler {
@@ -56,7 +59,8 @@ impl JitCompiler {
             .map_err(|e| format!("Failed to create ISA: {:?}", e))?;
         
         // Create JIT module
-        let builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        builder.symbol("record_deopt_frame", record_deopt_frame as *const u8);
         let module = JITModule::new(builder);
         let ctx = module.make_context();
         
@@ -64,11 +68,12 @@ impl JitCompiler {
             module,
             builder_context: FunctionBuilderContext::new(),
             ctx,
-            data_ctx: DataContext::new(),
             compiled_functions: HashMap::new(),
             hot_threshold: 100,
             call_counts: HashMap::new(),
             enabled: true,
+            native: HashMap::new(),
+            deopts: 0,
         })
     }
     
@@ -94,6 +99,14 @@ impl JitCompiler {
         *count >= self.hot_threshold
     }
     
+    /// Seed call counts from an interpreter/VM profile so functions that were
+    /// hot in a profiled run are compiled on their first call next time
+    pub fn seed_call_counts(&mut self, counts: &[(String, u64)]) {
+        for (name, n) in counts {
+            *self.call_counts.entry(name.clone()).or_insert(0) += n;
+        }
+    }
+    
     /// Check if a function should be JIT compiled
     pub fn should_compile(&self, name: &str) -> bool {
         if !self.enabled {
@@ -217,6 +230,10 @@ impl JitCompiler {
     }
     
     /// Call a compiled function
+    ///
+    /// # Safety
+    /// `name` must have been compiled from a function taking and returning
+    /// one integer.
     pub unsafe fn call_compiled(&self, name: &str, arg: i64) -> Option<i64> {
         if let Some(func) = self.compiled_functions.get(name) {
             let code_ptr = self.module.get_finalized_function(func.func_id);
@@ -249,6 +266,7 @@ impl JitCompiler {
             total_functions_tracked: self.call_counts.len(),
             compiled_functions: self.compiled_functions.len(),
             hot_functions: self.get_hot_functions().len(),
+            deopts: self.deopts,
         }
     }
     
@@ -284,22 +302,740 @@ pub struct JitStats {
     pub total_functions_tracked: usize,
     pub compiled_functions: usize,
     pub hot_functions: usize,
+    pub deopts: u64,
 }
 
 impl std::fmt::Display for JitStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "JIT Stats: enabled={}, threshold={}, tracked={}, compiled={}, hot={}",
+            "JIT Stats: enabled={}, threshold={}, tracked={}, compiled={}, hot={}, deopts={}",
             self.enabled,
             self.hot_threshold,
             self.total_functions_tracked,
             self.compiled_functions,
-            self.hot_functions
+            self.hot_functions,
+            self.deopts
         )
     }
 }
 
+// ============================================
+// BYTECODE LOWERING
+// Native code speculates: that ints don't overflow, that divisors aren't
+// zero, that a local set on only some paths has been set, that the recursion
+// isn't too deep. A guard checks each assumption, and when one fails the
+// code deoptimizes: it hands the VM the frame it was running (and those of
+// the native callers waiting on it) and the VM carries on from that
+// instruction, with its own semantics for whatever went wrong.
+// ============================================
+
+/// Stack the native frames of one call may use between them. A call that
+/// would go past it deoptimizes, and the VM, which keeps its frames on the
+/// heap, carries on with the recursion.
+const NATIVE_STACK: usize = 1 << 20;
+
+/// How native code tags a local or stack value it hands to the VM
+const TAG_NULL: i64 = 0;
+const TAG_INT: i64 = 1;
+const TAG_BOOL: i64 = 2;
+
+fn tag_of(kind: Kind) -> i64 {
+    match kind {
+        Some(SpecializedType::Bool) => TAG_BOOL,
+        _ => TAG_INT,
+    }
+}
+
+/// What native code leaves behind when it deoptimizes. Only `deopted` is
+/// read by native code, which checks it after every call.
+#[repr(C)]
+struct Deopt {
+    deopted: i64,
+    /// Innermost first
+    frames: Vec<ResumeFrame>,
+}
+
+/// Called by native code to record one frame: `slots` holds (tag, value)
+/// pairs for its `locals` locals and then its `stack` stack values
+extern "C" fn record_deopt_frame(deopt: *mut Deopt, func_idx: i64, ip: i64, slots: *const i64, locals: i64, stack: i64) {
+    // SAFETY: native code passes the `Deopt` it was called with and a stack
+    // slot holding this many pairs
+    let (deopt, slots) = unsafe { (&mut *deopt, std::slice::from_raw_parts(slots, 2 * (locals + stack) as usize)) };
+    let mut values: Vec<VMValue> = slots.chunks(2).map(|pair| match pair[0] {
+        TAG_INT => VMValue::Int(pair[1]),
+        TAG_BOOL => VMValue::Bool(pair[1] != 0),
+        _ => VMValue::Null,
+    }).collect();
+    let stack = values.split_off(locals as usize);
+    deopt.frames.push(ResumeFrame { func_idx: func_idx as usize, ip: ip as usize, locals: values, stack });
+    deopt.deopted = 1;
+}
+
+/// Native code for a bytecode function, called as
+/// `extern "C" fn(args: *const i64, deopt: *mut Deopt, stack_used: i64) -> i64`.
+/// Valid while the `JitCompiler` that made it is alive.
+#[derive(Debug, Clone, Copy)]
+pub struct NativeFn {
+    code: *const u8,
+    arity: usize,
+    returns: SpecializedType,
+}
+
+impl NativeFn {
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// `Int64`, or `Bool` for a function whose result `call` gives as 0 or 1
+    pub fn returns(&self) -> SpecializedType {
+        self.returns
+    }
+
+    /// Run the function on exactly `arity` ints. When a guard fails, the
+    /// frames to finish the call with `BytecodeVM::resume`, outermost first.
+    pub fn call(&self, args: &[i64]) -> Result<i64, Vec<ResumeFrame>> {
+        assert_eq!(args.len(), self.arity, "native call with the wrong number of arguments");
+        let mut deopt = Deopt { deopted: 0, frames: Vec::new() };
+        // SAFETY: `code` came from `compile_bytecode`, which gives every
+        // function this signature and reads only `arity` arguments
+        let func: extern "C" fn(*const i64, *mut Deopt, i64) -> i64 = unsafe { std::mem::transmute(self.code) };
+        let result = stacker::maybe_grow(NATIVE_STACK + 64 * 1024, 2 * NATIVE_STACK, || func(args.as_ptr(), &mut deopt, 0));
+        if deopt.deopted == 0 {
+            return Ok(result);
+        }
+        deopt.frames.reverse();
+        Err(deopt.frames)
+    }
+}
+
+impl JitCompiler {
+    /// Compile VM function `idx` of `functions`, along with every function it
+    /// calls that isn't native yet. Arguments must be ints; the functions may
+    /// use int and bool values, locals, arithmetic, comparisons, branches,
+    /// loops and calls to each other. Null is left to the VM by deoptimizing
+    /// where it turns up. Anything else (floats, strings, heap values, host
+    /// calls) is an error naming it, and they stay in the VM.
+    pub fn compile_bytecode(&mut self, functions: &[CompiledFunc], idx: usize) -> Result<NativeFn, String> {
+        if let Some((_, native)) = self.native.get(&idx) {
+            return Ok(*native);
+        }
+        let mut group = vec![idx];
+        let mut next = 0;
+        while next < group.len() {
+            let func = functions.get(group[next]).ok_or_else(|| format!("no bytecode function #{}", group[next]))?;
+            for op in &func.code {
+                if let OpCode::Call(callee, _) = *op {
+                    if !group.contains(&callee) && !self.native.contains_key(&callee) {
+                        group.push(callee);
+                    }
+                }
+            }
+            next += 1;
+        }
+
+        // Calls within the group return values of unknown type until
+        // analysing the callee says what it returns
+        let mut returns: HashMap<usize, Kind> = group.iter().map(|&i| (i, None)).collect();
+        let analyses = loop {
+            let callee = |i: usize| {
+                let kind = self.native.get(&i).map(|(_, n)| Some(n.returns)).or_else(|| returns.get(&i).copied())?;
+                Some((functions.get(i)?.arity, kind))
+            };
+            let analyses = group.iter()
+                .map(|&i| analyze(&functions[i], &callee).map_err(|e| match i == idx {
+                    true => e,
+                    false => format!("calls fn {}, which {}", functions[i].name, e),
+                }))
+                .collect::<Result<Vec<Analysis>, String>>()?;
+            let learned: Vec<(usize, Kind)> = group.iter().zip(&analyses)
+                .filter(|(i, a)| returns[i] != a.returns)
+                .map(|(&i, a)| (i, a.returns))
+                .collect();
+            if learned.is_empty() {
+                break analyses;
+            }
+            // Types only go from unknown to known, so this ends
+            if learned.iter().any(|(i, _)| returns[i].is_some()) {
+                return Err("returns ints or bools depending on its own result".to_string());
+            }
+            returns.extend(learned);
+        };
+        if let Some(i) = group.iter().find(|i| returns[i].is_none()) {
+            let e = "never returns an int or bool".to_string();
+            return Err(if *i == idx { e } else { format!("calls fn {}, which {}", functions[*i].name, e) });
+        }
+
+        let ptr = self.module.target_config().pointer_type();
+        let mut signature = self.module.make_signature();
+        signature.params.extend([AbiParam::new(ptr), AbiParam::new(ptr), AbiParam::new(types::I64)]);
+        signature.returns.push(AbiParam::new(types::I64));
+        let mut ids: HashMap<usize, FuncId> = self.native.iter().map(|(&i, (id, _))| (i, *id)).collect();
+        for &i in &group {
+            ids.insert(i, self.module.declare_anonymous_function(&signature).map_err(|e| e.to_string())?);
+        }
+        let mut record_signature = self.module.make_signature();
+        record_signature.params.extend([ptr, types::I64, types::I64, ptr, types::I64, types::I64].map(AbiParam::new));
+        let record_frame = self.module.declare_function("record_deopt_frame", Linkage::Import, &record_signature).map_err(|e| e.to_string())?;
+        // Build every body before defining any, so a failure leaves nothing half-linked
+        let mut bodies = Vec::with_capacity(group.len());
+        for (&i, analysis) in group.iter().zip(&analyses) {
+            let mut ctx = self.module.make_context();
+            ctx.func.signature = signature.clone();
+            let mut lowering = Lowering { module: &mut self.module, ids: &ids, record_frame, ptr, func_idx: i };
+            if let Err(e) = lowering.lower(&functions[i], analysis, &mut ctx.func, &mut self.builder_context) {
+                // The builder stopped halfway, so its context isn't reset for the next function
+                self.builder_context = FunctionBuilderContext::new();
+                return Err(e);
+            }
+            bodies.push(ctx);
+        }
+        for (&i, mut ctx) in group.iter().zip(bodies) {
+            self.module.define_function(ids[&i], &mut ctx).map_err(|e| e.to_string())?;
+        }
+        self.module.finalize_definitions().map_err(|e| e.to_string())?;
+
+        for (&i, analysis) in group.iter().zip(&analyses) {
+            let func = &functions[i];
+            let native = NativeFn { code: self.module.get_finalized_function(ids[&i]), arity: func.arity, returns: analysis.returns.unwrap_or(SpecializedType::Int64) };
+            self.native.insert(i, (ids[&i], native));
+            self.compiled_functions.insert(func.name.clone(), CompiledFunction {
+                name: func.name.clone(),
+                func_id: ids[&i],
+                call_count: *self.call_counts.get(&func.name).unwrap_or(&0),
+                is_hot: true,
+            });
+        }
+        Ok(self.native[&idx].1)
+    }
+
+    /// Count a native call that deoptimized, for `get_stats`
+    pub fn record_deopt(&mut self) {
+        self.deopts += 1;
+    }
+}
+
+/// A value's type during analysis: None while it comes from a call whose
+/// return type isn't known yet
+type Kind = Option<SpecializedType>;
+
+/// The type of a value that's `a` along one path and `b` along another
+fn join(a: Kind, b: Kind) -> Option<Kind> {
+    match (a, b) {
+        (Some(a), Some(b)) => (a == b).then_some(Some(a)),
+        (a, b) => Some(a.or(b)),
+    }
+}
+
+/// A local during analysis
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Unset,
+    Set(Kind),
+    /// Set on some paths and still null on others: reading it needs a guard
+    Maybe(Kind),
+    /// An int on some paths and a bool on others
+    Mixed,
+}
+
+impl Slot {
+    fn join(self, other: Slot) -> Slot {
+        match (self, other) {
+            (Slot::Mixed, _) | (_, Slot::Mixed) => Slot::Mixed,
+            (Slot::Unset, Slot::Unset) => Slot::Unset,
+            (Slot::Set(a), Slot::Set(b)) => join(a, b).map_or(Slot::Mixed, Slot::Set),
+            (Slot::Unset, Slot::Set(k) | Slot::Maybe(k)) | (Slot::Set(k) | Slot::Maybe(k), Slot::Unset) => Slot::Maybe(k),
+            (Slot::Set(a) | Slot::Maybe(a), Slot::Set(b) | Slot::Maybe(b)) => join(a, b).map_or(Slot::Mixed, Slot::Maybe),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FrameState {
+    stack: Vec<Kind>,
+    locals: Vec<Slot>,
+}
+
+impl FrameState {
+    /// Fold in the state along another path; whether anything changed
+    fn merge(&mut self, other: &FrameState) -> Result<bool, String> {
+        let mismatch = || "leaves different values on the stack depending on the path".to_string();
+        if self.stack.len() != other.stack.len() {
+            return Err(mismatch());
+        }
+        let before = self.clone();
+        for (mine, theirs) in self.stack.iter_mut().zip(&other.stack) {
+            *mine = join(*mine, *theirs).ok_or_else(mismatch)?;
+        }
+        for (mine, theirs) in self.locals.iter_mut().zip(&other.locals) {
+            *mine = mine.join(*theirs);
+        }
+        Ok(*self != before)
+    }
+}
+
+/// What lowering needs to know about a function's bytecode
+struct Analysis {
+    /// The state on entry to each instruction; None if unreachable
+    entry: Vec<Option<FrameState>>,
+    /// None if it only ever returns what calls with unknown types return
+    returns: Kind,
+}
+
+/// Check that every reachable instruction of `func` works on ints and bools
+/// whose types are known (or will be once `callee` knows more), and find
+/// what it returns. `callee` gives the arity and return type of a function
+/// it calls. A path that reaches null ends there, as native code
+/// deoptimizes, unless the null only unsets a local (`let x = null`).
+fn analyze(func: &CompiledFunc, callee: &dyn Fn(usize) -> Option<(usize, Kind)>) -> Result<Analysis, String> {
+    use SpecializedType::{Bool, Int64};
+    let malformed = || format!("fn {} has malformed bytecode", func.name);
+    let slots = func.locals.max(func.arity);
+    let mut states: Vec<Option<FrameState>> = vec![None; func.code.len() + 1];
+    states[0] = Some(FrameState {
+        stack: Vec::new(),
+        locals: (0..slots).map(|slot| if slot < func.arity { Slot::Set(Some(Int64)) } else { Slot::Unset }).collect(),
+    });
+    let jump_targets = jump_targets(func);
+    let mut work = vec![0];
+    let mut returns: Kind = None;
+    while let Some(ip) = work.pop() {
+        let mut state = states[ip].clone().ok_or_else(malformed)?;
+        let Some(&op) = func.code.get(ip) else {
+            return Err("can run off its end and return null".to_string());
+        };
+        let stack = &mut state.stack;
+        let pop = |stack: &mut Vec<Kind>| stack.pop().ok_or_else(malformed);
+        let mut targets = vec![ip + 1];
+        match op {
+            OpCode::Const(_) => stack.push(Some(Int64)),
+            OpCode::LoadConst(idx) => match func.constants.get(idx) {
+                Some(Constant::Int(_)) => stack.push(Some(Int64)),
+                _ => return Err("uses a float, string or array constant".to_string()),
+            },
+            OpCode::ConstTrue | OpCode::ConstFalse => stack.push(Some(Bool)),
+            OpCode::ConstNull => match null_store(func, &jump_targets, ip) {
+                // `let x = null` leaves x unset
+                Some(slot) => {
+                    *state.locals.get_mut(slot).ok_or_else(malformed)? = Slot::Unset;
+                    targets = vec![ip + 2];
+                }
+                None => targets.clear(),
+            },
+            OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div | OpCode::Mod => {
+                pop(stack)?;
+                pop(stack)?;
+                stack.push(Some(Int64));
+            }
+            OpCode::Neg => {
+                pop(stack)?;
+                stack.push(Some(Int64));
+            }
+            OpCode::Lt | OpCode::Gt | OpCode::Le | OpCode::Ge | OpCode::And | OpCode::Or => {
+                pop(stack)?;
+                pop(stack)?;
+                stack.push(Some(Bool));
+            }
+            OpCode::Eq | OpCode::Ne => {
+                if join(pop(stack)?, pop(stack)?).is_none() {
+                    return Err("compares an int with a bool".to_string());
+                }
+                stack.push(Some(Bool));
+            }
+            OpCode::Not => {
+                pop(stack)?;
+                stack.push(Some(Bool));
+            }
+            OpCode::Jump(target) => targets = vec![target],
+            OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) => {
+                pop(stack)?;
+                targets.push(target);
+            }
+            OpCode::LoadLocal(slot) => match state.locals.get(slot).ok_or_else(malformed)? {
+                Slot::Set(kind) | Slot::Maybe(kind) => stack.push(*kind),
+                Slot::Unset => targets.clear(),
+                Slot::Mixed => return Err("reads a local that holds an int on some paths and a bool on others".to_string()),
+            },
+            OpCode::StoreLocal(slot) => {
+                let kind = pop(stack)?;
+                *state.locals.get_mut(slot).ok_or_else(malformed)? = Slot::Set(kind);
+            }
+            OpCode::Call(idx, argc) => {
+                let (arity, result) = callee(idx).ok_or_else(malformed)?;
+                if argc != arity {
+                    return Err("calls a function with the wrong number of arguments".to_string());
+                }
+                for _ in 0..argc {
+                    if pop(stack)? == Some(Bool) {
+                        return Err("passes a bool as an argument".to_string());
+                    }
+                }
+                stack.push(result);
+            }
+            OpCode::Return => {
+                returns = join(returns, pop(stack)?).ok_or("returns ints on some paths and bools on others")?;
+                targets.clear();
+            }
+            OpCode::Pop => {
+                pop(stack)?;
+            }
+            OpCode::Dup => {
+                let top = *stack.last().ok_or_else(malformed)?;
+                stack.push(top);
+            }
+            OpCode::CallHost(_, _) => return Err("calls back into the interpreter".to_string()),
+            OpCode::ConstStr(_) | OpCode::NewArray(_) | OpCode::NewStruct(_) | OpCode::NewMap
+            | OpCode::Index | OpCode::SetIndex | OpCode::GetField(_) | OpCode::SetField(_) => {
+                return Err("uses strings, arrays, structs or maps".to_string());
+            }
+            OpCode::Print | OpCode::Halt => return Err(format!("uses {}", op.name())),
+        }
+        for target in targets {
+            let slot = states.get_mut(target).ok_or_else(malformed)?;
+            let changed = match slot {
+                Some(existing) => existing.merge(&state)?,
+                None => {
+                    *slot = Some(state.clone());
+                    true
+                }
+            };
+            if changed {
+                work.push(target);
+            }
+        }
+    }
+    states.truncate(func.code.len());
+    Ok(Analysis { entry: states, returns })
+}
+
+fn jump_targets(func: &CompiledFunc) -> Vec<usize> {
+    func.code.iter().filter_map(|op| match *op {
+        OpCode::Jump(target) | OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) => Some(target),
+        _ => None,
+    }).collect()
+}
+
+/// The local that the null pushed at `ip` is stored straight into, if any
+fn null_store(func: &CompiledFunc, jump_targets: &[usize], ip: usize) -> Option<usize> {
+    match func.code.get(ip + 1) {
+        Some(&OpCode::StoreLocal(slot)) if !jump_targets.contains(&(ip + 1)) => Some(slot),
+        _ => None,
+    }
+}
+
+/// Builds one function's IR. The VM's operand stack becomes SSA values
+/// (block parameters where it's live across a jump) and its locals
+/// Cranelift variables, each with a second variable holding its tag.
+struct Lowering<'a> {
+    module: &'a mut JITModule,
+    ids: &'a HashMap<usize, FuncId>,
+    record_frame: FuncId,
+    ptr: Type,
+    /// The function's index in the VM, for deopt frames
+    func_idx: usize,
+}
+
+/// The parts of a function's entry block every deopt needs
+#[derive(Clone, Copy)]
+struct Frame {
+    deopt: Value,
+    locals: usize,
+    /// Where the (tag, value) pairs for the VM are laid out
+    scratch: codegen::ir::StackSlot,
+}
+
+fn local(slot: usize) -> Variable {
+    Variable::new(2 * slot)
+}
+
+fn local_tag(slot: usize) -> Variable {
+    Variable::new(2 * slot + 1)
+}
+
+impl Lowering<'_> {
+    fn lower(&mut self, func: &CompiledFunc, analysis: &Analysis, ir: &mut codegen::ir::Function, builder_context: &mut FunctionBuilderContext) -> Result<(), String> {
+        let malformed = || format!("fn {} has malformed bytecode", func.name);
+        let mut builder = FunctionBuilder::new(ir, builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let [args, deopt, stack_used] = builder.block_params(entry) else { return Err(malformed()) };
+        let (args, deopt, stack_used) = (*args, *deopt, *stack_used);
+        let locals = func.locals.max(func.arity);
+        let max_stack = analysis.entry.iter().flatten().map(|e| e.stack.len()).max().unwrap_or(0);
+        let max_args = func.code.iter().map(|op| match *op { OpCode::Call(_, argc) => argc, _ => 0 }).max().unwrap_or(0);
+        // Room to hand every local and stack value to the VM, and to pass arguments
+        let scratch = builder.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, ((locals + max_stack).max(1) * 16) as u32));
+        let call_args = builder.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, (max_args.max(1) * 8) as u32));
+        // A generous guess at this frame's size, spills and all
+        let frame_size = 128 + 32 * (locals + max_stack + max_args) as i64;
+        let frame = Frame { deopt, locals, scratch };
+        for slot in 0..frame.locals {
+            builder.declare_var(local(slot), types::I64);
+            builder.declare_var(local_tag(slot), types::I64);
+            let (value, tag) = if slot < func.arity {
+                (builder.ins().load(types::I64, MemFlags::trusted(), args, (slot * 8) as i32), TAG_INT)
+            } else {
+                (builder.ins().iconst(types::I64, 0), TAG_NULL)
+            };
+            let tag = builder.ins().iconst(types::I64, tag);
+            builder.def_var(local(slot), value);
+            builder.def_var(local_tag(slot), tag);
+        }
+        let stack_used = builder.ins().iadd_imm(stack_used, frame_size);
+        let too_deep = builder.ins().icmp_imm(IntCC::SignedGreaterThan, stack_used, NATIVE_STACK as i64);
+        self.guard(&mut builder, frame, too_deep, 0, &[]);
+
+        // A block for each instruction that starts one: the first, jump
+        // targets, and whatever follows a jump or a return
+        let state = |ip: usize| analysis.entry.get(ip).and_then(|e| e.as_ref());
+        let mut blocks: HashMap<usize, Block> = HashMap::new();
+        for (ip, op) in func.code.iter().enumerate().filter(|(ip, _)| state(*ip).is_some()) {
+            let mut leaders = vec![];
+            match *op {
+                OpCode::Jump(target) | OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) => leaders.extend([target, ip + 1]),
+                OpCode::Return => leaders.push(ip + 1),
+                _ => {}
+            }
+            if ip == 0 {
+                leaders.push(0);
+            }
+            for leader in leaders {
+                let Some(entry) = state(leader) else { continue };
+                blocks.entry(leader).or_insert_with(|| {
+                    let block = builder.create_block();
+                    for _ in &entry.stack {
+                        builder.append_block_param(block, types::I64);
+                    }
+                    block
+                });
+            }
+        }
+        let block_at = |ip: usize| blocks.get(&ip).copied().ok_or_else(malformed);
+        builder.ins().jump(block_at(0)?, &[]);
+        let jump_targets = jump_targets(func);
+
+        let mut stack: Vec<Value> = Vec::new();
+        let mut open = false;
+        for (ip, &op) in func.code.iter().enumerate() {
+            let Some(entry) = state(ip) else { continue };
+            if let Some(&block) = blocks.get(&ip) {
+                if open {
+                    builder.ins().jump(block, &stack);
+                }
+                builder.switch_to_block(block);
+                stack = builder.block_params(block).to_vec();
+                open = true;
+            }
+            if !open {
+                return Err(malformed());
+            }
+            // The stack as the VM would have it before this instruction
+            let before: Vec<(Value, Kind)> = stack.iter().copied().zip(entry.stack.iter().copied()).collect();
+            let pop = |stack: &mut Vec<Value>| stack.pop().ok_or_else(malformed);
+            match op {
+                OpCode::Const(n) => stack.push(builder.ins().iconst(types::I64, n)),
+                OpCode::LoadConst(idx) => match func.constants.get(idx) {
+                    Some(Constant::Int(n)) => stack.push(builder.ins().iconst(types::I64, *n)),
+                    _ => return Err(malformed()),
+                },
+                OpCode::ConstTrue => stack.push(builder.ins().iconst(types::I64, 1)),
+                OpCode::ConstFalse => stack.push(builder.ins().iconst(types::I64, 0)),
+                OpCode::ConstNull => match null_store(func, &jump_targets, ip) {
+                    Some(slot) => {
+                        let (zero, tag) = (builder.ins().iconst(types::I64, 0), builder.ins().iconst(types::I64, TAG_NULL));
+                        builder.def_var(local(slot), zero);
+                        builder.def_var(local_tag(slot), tag);
+                    }
+                    None => {
+                        self.deoptimize(&mut builder, frame, ip, &before);
+                        open = false;
+                    }
+                },
+                OpCode::Add | OpCode::Sub | OpCode::Mul => {
+                    let b = pop(&mut stack)?;
+                    let a = pop(&mut stack)?;
+                    let (result, overflowed) = match op {
+                        OpCode::Add => {
+                            // Overflow when the result's sign differs from both operands'
+                            let sum = builder.ins().iadd(a, b);
+                            let (x, y) = (builder.ins().bxor(a, sum), builder.ins().bxor(b, sum));
+                            let both = builder.ins().band(x, y);
+                            (sum, builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0))
+                        }
+                        OpCode::Sub => {
+                            // Overflow when the operands' signs differ and the result's isn't a's
+                            let difference = builder.ins().isub(a, b);
+                            let (x, y) = (builder.ins().bxor(a, b), builder.ins().bxor(a, difference));
+                            let both = builder.ins().band(x, y);
+                            (difference, builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0))
+                        }
+                        _ => {
+                            // Overflow when the high half isn't the low half's sign
+                            let product = builder.ins().imul(a, b);
+                            let high = builder.ins().smulhi(a, b);
+                            let sign = builder.ins().sshr_imm(product, 63);
+                            (product, builder.ins().icmp(IntCC::NotEqual, high, sign))
+                        }
+                    };
+                    self.guard(&mut builder, frame, overflowed, ip, &before);
+                    stack.push(result);
+                }
+                OpCode::Div | OpCode::Mod => {
+                    let b = pop(&mut stack)?;
+                    let a = pop(&mut stack)?;
+                    let by_zero = builder.ins().icmp_imm(IntCC::Equal, b, 0);
+                    self.guard(&mut builder, frame, by_zero, ip, &before);
+                    // The VM wraps i64::MIN / -1 where the hardware would trap,
+                    // so -1 is handled without dividing
+                    let minus_one = builder.ins().icmp_imm(IntCC::Equal, b, -1);
+                    let one = builder.ins().iconst(types::I64, 1);
+                    let divisor = builder.ins().select(minus_one, one, b);
+                    stack.push(if matches!(op, OpCode::Div) {
+                        let quotient = builder.ins().sdiv(a, divisor);
+                        let negated = builder.ins().ineg(a);
+                        builder.ins().select(minus_one, negated, quotient)
+                    } else {
+                        builder.ins().srem(a, divisor)
+                    });
+                }
+                OpCode::Neg => {
+                    let a = pop(&mut stack)?;
+                    let overflowed = builder.ins().icmp_imm(IntCC::Equal, a, i64::MIN);
+                    self.guard(&mut builder, frame, overflowed, ip, &before);
+                    stack.push(builder.ins().ineg(a));
+                }
+                OpCode::Lt | OpCode::Gt | OpCode::Le | OpCode::Ge | OpCode::Eq | OpCode::Ne => {
+                    let b = pop(&mut stack)?;
+                    let a = pop(&mut stack)?;
+                    let cc = match op {
+                        OpCode::Lt => IntCC::SignedLessThan,
+                        OpCode::Gt => IntCC::SignedGreaterThan,
+                        OpCode::Le => IntCC::SignedLessThanOrEqual,
+                        OpCode::Ge => IntCC::SignedGreaterThanOrEqual,
+                        OpCode::Eq => IntCC::Equal,
+                        _ => IntCC::NotEqual,
+                    };
+                    let flag = builder.ins().icmp(cc, a, b);
+                    stack.push(builder.ins().uextend(types::I64, flag));
+                }
+                OpCode::Not => {
+                    let a = pop(&mut stack)?;
+                    let flag = builder.ins().icmp_imm(IntCC::Equal, a, 0);
+                    stack.push(builder.ins().uextend(types::I64, flag));
+                }
+                OpCode::And | OpCode::Or => {
+                    let b = pop(&mut stack)?;
+                    let a = pop(&mut stack)?;
+                    let a = builder.ins().icmp_imm(IntCC::NotEqual, a, 0);
+                    let b = builder.ins().icmp_imm(IntCC::NotEqual, b, 0);
+                    let flag = if matches!(op, OpCode::And) { builder.ins().band(a, b) } else { builder.ins().bor(a, b) };
+                    stack.push(builder.ins().uextend(types::I64, flag));
+                }
+                OpCode::Jump(target) => {
+                    builder.ins().jump(block_at(target)?, &stack);
+                    open = false;
+                }
+                OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) => {
+                    let cond = pop(&mut stack)?;
+                    let (jump, fall) = (block_at(target)?, block_at(ip + 1)?);
+                    if matches!(op, OpCode::JumpIfTrue(_)) {
+                        builder.ins().brif(cond, jump, &stack, fall, &stack);
+                    } else {
+                        builder.ins().brif(cond, fall, &stack, jump, &stack);
+                    }
+                    open = false;
+                }
+                OpCode::LoadLocal(slot) => match entry.locals.get(slot).ok_or_else(malformed)? {
+                    Slot::Set(_) => stack.push(builder.use_var(local(slot))),
+                    Slot::Maybe(kind) => {
+                        // Speculate that this path set it
+                        let tag = builder.use_var(local_tag(slot));
+                        let unset = builder.ins().icmp_imm(IntCC::NotEqual, tag, tag_of(*kind));
+                        self.guard(&mut builder, frame, unset, ip, &before);
+                        stack.push(builder.use_var(local(slot)));
+                    }
+                    Slot::Unset => {
+                        self.deoptimize(&mut builder, frame, ip, &before);
+                        open = false;
+                    }
+                    Slot::Mixed => return Err(malformed()),
+                },
+                OpCode::StoreLocal(slot) => {
+                    let value = pop(&mut stack)?;
+                    let tag = builder.ins().iconst(types::I64, tag_of(entry.stack.last().copied().flatten()));
+                    builder.def_var(local(slot), value);
+                    builder.def_var(local_tag(slot), tag);
+                }
+                OpCode::Call(callee, argc) => {
+                    let id = *self.ids.get(&callee).ok_or_else(malformed)?;
+                    let callee_ref = self.module.declare_func_in_func(id, builder.func);
+                    let waiting = stack.len().checked_sub(argc).ok_or_else(malformed)?;
+                    for (i, arg) in stack.split_off(waiting).into_iter().enumerate() {
+                        builder.ins().stack_store(arg, call_args, (i * 8) as i32);
+                    }
+                    let args_ptr = builder.ins().stack_addr(self.ptr, call_args, 0);
+                    let call = builder.ins().call(callee_ref, &[args_ptr, deopt, stack_used]);
+                    let result = builder.inst_results(call)[0];
+                    // If the callee deoptimized, so does this frame, to resume
+                    // once the VM has finished the call
+                    let deopted = builder.ins().load(types::I64, MemFlags::trusted(), deopt, 0);
+                    self.guard(&mut builder, frame, deopted, ip + 1, &before[..waiting]);
+                    stack.push(result);
+                }
+                OpCode::Return => {
+                    let result = pop(&mut stack)?;
+                    builder.ins().return_(&[result]);
+                    open = false;
+                }
+                OpCode::Pop => {
+                    pop(&mut stack)?;
+                }
+                OpCode::Dup => stack.push(*stack.last().ok_or_else(malformed)?),
+                _ => return Err(malformed()),
+            }
+        }
+        if open {
+            return Err(malformed());
+        }
+        builder.seal_all_blocks();
+        builder.finalize();
+        Ok(())
+    }
+
+    /// Deoptimize to `ip` with `stack` when `failed` is nonzero
+    fn guard(&mut self, builder: &mut FunctionBuilder, frame: Frame, failed: Value, ip: usize, stack: &[(Value, Kind)]) {
+        let (fail, ok) = (builder.create_block(), builder.create_block());
+        builder.ins().brif(failed, fail, &[], ok, &[]);
+        builder.switch_to_block(fail);
+        self.deoptimize(builder, frame, ip, stack);
+        builder.switch_to_block(ok);
+    }
+
+    /// Record this frame, to carry on at `ip` with `stack`, and return
+    fn deoptimize(&mut self, builder: &mut FunctionBuilder, frame: Frame, ip: usize, stack: &[(Value, Kind)]) {
+        let slots = frame.scratch;
+        for slot in 0..frame.locals {
+            let tag = builder.use_var(local_tag(slot));
+            let value = builder.use_var(local(slot));
+            builder.ins().stack_store(tag, slots, (slot * 16) as i32);
+            builder.ins().stack_store(value, slots, (slot * 16 + 8) as i32);
+        }
+        for (i, &(value, kind)) in stack.iter().enumerate() {
+            let offset = ((frame.locals + i) * 16) as i32;
+            let tag = builder.ins().iconst(types::I64, tag_of(kind));
+            builder.ins().stack_store(tag, slots, offset);
+            builder.ins().stack_store(value, slots, offset + 8);
+        }
+        let record = self.module.declare_func_in_func(self.record_frame, builder.func);
+        let slots = builder.ins().stack_addr(self.ptr, slots, 0);
+        let args = [self.func_idx as i64, ip as i64, frame.locals as i64, stack.len() as i64].map(|n| builder.ins().iconst(types::I64, n));
+        builder.ins().call(record, &[frame.deopt, args[0], args[1], slots, args[2], args[3]]);
+        let zero = builder.ins().iconst(types::I64, 0);
+        builder.ins().return_(&[zero]);
+    }
+}
+
 // ============================================
 // METHOD INLINING
 // ============================================
@@ -342,7 +1078,7 @@ impl InliningConfig {
     }
     
     /// Check if a function should be inlined
-    pub fn should_inline(&self, name: &str, size: usize, call_count: u64) -> bool {
+    pub fn should_inline(&self, _name: &str, size: usize, call_count: u64) -> bool {
         size <= self.max_inline_size && call_count >= self.min_call_count
     }
     
@@ -396,7 +1132,7 @@ pub enum SpecializedType {
 
 impl SpecializedType {
     /// Get Cranelift type for this specialized type
-    pub fn to_cranelift_type(&self) -> Option<types::Type> {
+    pub fn to_cranelift_type(self) -> Option<types::Type> {
         match self {
             SpecializedType::Int64 => Some(types::I64),
             SpecializedType::Float64 => Some(types::F64),
@@ -406,7 +1142,7 @@ impl SpecializedType {
     }
     
     /// Check if this type can be unboxed for optimization
-    pub fn is_unboxable(&self) -> bool {
+    pub fn is_unboxable(self) -> bool {
         matches!(self, SpecializedType::Int64 | SpecializedType::Float64 | SpecializedType::Bool)
     }
 }
@@ -447,7 +1183,7 @@ impl TypeSpecialization {
     
     /// Record observed type for a function parameter
     pub fn record_type(&mut self, func_name: &str, param_index: usize, observed: SpecializedType) {
-        let types = self.observed_types.entry(func_name.to_string()).or_insert_with(Vec::new);
+        let types = self.observed_types.entry(func_name.to_string()).or_default();
         while types.len() <= param_index {
             types.push(SpecializedType::Unknown);
         }
@@ -531,6 +1267,7 @@ pub enum CompareOp {
 
 /// Guard types for deoptimization
 #[derive(Clone, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum GuardType {
     TypeCheck(SpecializedType),
     OverflowCheck,
@@ -636,6 +1373,7 @@ impl TraceRecorder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bytecode_vm::{BytecodeVM, NoHost};
     
     #[test]
     fn test_jit_compile_double() {
@@ -665,6 +1403,155 @@ mod tests {
         }
     }
     
+    /// Every function in `src`, compiled for the VM and numbered in order
+    fn compile_fns(src: &str) -> Vec<CompiledFunc> {
+        let tokens = crate::lexer::tokenize(src);
+        let funcs: Vec<crate::parser::Function> = crate::parser::Parser::new(&tokens).parse().unwrap().into_iter()
+            .filter_map(|item| match item {
+                crate::parser::TopLevel::Function(f) => Some(f),
+                _ => None,
+            })
+            .collect();
+        let resolve = |name| funcs.iter().position(|f| f.name == name).map(|i| (i, funcs[i].params.len()));
+        funcs.iter().map(|f| crate::bytecode_compiler::compile(f, resolve).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_compile_bytecode() {
+        let mut jit = JitCompiler::new().expect("Failed to create JIT");
+        let funcs = compile_fns("
+            fn poly(x, y) { let z = x * y; z = z - -x; return z * 2 + 1; }
+            fn abs(x) { if (x < 0) { return -x; } return x; }
+            fn fib(n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }
+            fn is_even(n) { if (n == 0) { return true; } return is_odd(n - 1); }
+            fn is_odd(n) { if (n == 0) { return false; } return is_even(n - 1); }
+            fn sum_odd(limit) {
+                let total = 0;
+                let i = 0;
+                while (i < limit) {
+                    if (is_odd(i) && !(i % 5 == 0)) { total = total + i / 3; }
+                    i = i + 1;
+                }
+                return total;
+            }
+            fn greet(n) { print(n); return n; }
+            fn calls_greet(n) { return greet(n) + 1; }
+            fn mixed(n) { let r = 1; if (n > 0) { r = true; } return r; }
+        ");
+        let mut compile = |name: &str| {
+            let idx = funcs.iter().position(|f| f.name == name).unwrap();
+            jit.compile_bytecode(&funcs, idx)
+        };
+        assert_eq!(compile("poly").unwrap().call(&[3, 4]).ok(), Some(31));
+        let abs = compile("abs").unwrap();
+        assert_eq!((abs.call(&[-7]).ok(), abs.call(&[7]).ok()), (Some(7), Some(7)));
+        assert_eq!(compile("fib").unwrap().call(&[20]).ok(), Some(6765));
+        let is_even = compile("is_even").unwrap();
+        assert_eq!(is_even.returns(), SpecializedType::Bool);
+        assert_eq!((is_even.call(&[10]).ok(), is_even.call(&[7]).ok()), (Some(1), Some(0)));
+        assert_eq!(compile("sum_odd").unwrap().call(&[40]).ok(), Some(102));
+
+        assert_eq!(compile("greet").unwrap_err(), "calls back into the interpreter");
+        assert_eq!(compile("calls_greet").unwrap_err(), "calls fn greet, which calls back into the interpreter");
+        assert_eq!(compile("mixed").unwrap_err(), "reads a local that holds an int on some paths and a bool on others");
+    }
+
+    #[test]
+    fn test_deoptimization() {
+        let mut jit = JitCompiler::new().expect("Failed to create JIT");
+        let funcs = compile_fns("
+            fn twice(x) { return x + x; }
+            fn ratio(a, b) { return a / b + a % b; }
+            fn sum_to(n) { if (n == 0) { return 0; } return n + sum_to(n - 1); }
+            fn maybe(n) { if (n > 0) { return 1; } }
+            fn pick(n) { let r = null; if (n > 0) { r = n * 2; } return r; }
+            fn scaled(a, b) { let k = 10; return k + ratio(a, b) * 2; }
+            fn plus_maybe(n) { let k = 10; return k + maybe(n); }
+        ");
+        let mut compile = |name: &str| {
+            let idx = funcs.iter().position(|f| f.name == name).unwrap();
+            jit.compile_bytecode(&funcs, idx).unwrap()
+        };
+        let mut vm = BytecodeVM::new();
+        for func in &funcs {
+            vm.add_function(func.clone());
+        }
+        let mut resume = |frames| vm.resume(frames, &mut NoHost).map(|v| v.to_string());
+        let shown = |values: &[VMValue]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+
+        // An overflowing add hands the VM its operands, to run the add itself
+        let frames = compile("twice").call(&[i64::MAX]).unwrap_err();
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].func_idx, funcs[0].code[frames[0].ip].name()), (0, "Add"));
+        assert_eq!((shown(&frames[0].locals), shown(&frames[0].stack)), (i64::MAX.to_string(), format!("{0},{0}", i64::MAX)));
+
+        let ratio = compile("ratio");
+        assert_eq!(ratio.call(&[17, 5]).ok(), Some(5));
+        assert_eq!(ratio.call(&[i64::MIN, -1]).ok(), Some(i64::MIN));
+        assert_eq!(resume(ratio.call(&[1, 0]).unwrap_err()).unwrap_err(), "division by zero");
+
+        // Too deep: every native frame is handed over, and the VM finishes them
+        let sum_to = compile("sum_to");
+        assert_eq!(sum_to.call(&[100]).ok(), Some(5050));
+        let n = 50_000;
+        let frames = sum_to.call(&[n]).unwrap_err();
+        assert!(frames.len() > 1000 && frames.len() < n as usize, "{}", frames.len());
+        assert_eq!((frames[0].ip, frames.last().unwrap().ip), (frames[1].ip, 0));
+        assert_eq!(resume(frames), Ok((n * (n + 1) / 2).to_string()));
+
+        // Null isn't native: reaching it, or reading a local that holds it, deoptimizes
+        let maybe = compile("maybe");
+        assert_eq!(maybe.call(&[1]).ok(), Some(1));
+        assert_eq!(resume(maybe.call(&[0]).unwrap_err()), Ok("null".to_string()));
+        let pick = compile("pick");
+        assert_eq!(pick.call(&[3]).ok(), Some(6));
+        let frames = pick.call(&[0]).unwrap_err();
+        assert_eq!(shown(&frames[0].locals), "0,null");
+        assert_eq!(resume(frames), Ok("null".to_string()));
+
+        // A caller waiting on a callee that deoptimized is resumed after the call
+        let frames = compile("scaled").call(&[1, 0]).unwrap_err();
+        assert_eq!((frames.len(), shown(&frames[0].stack)), (2, "10".to_string()));
+        assert_eq!(resume(frames).unwrap_err(), "division by zero");
+        let plus_maybe = compile("plus_maybe");
+        assert_eq!(plus_maybe.call(&[4]).ok(), Some(11));
+        assert_eq!(resume(plus_maybe.call(&[0]).unwrap_err()), Ok("10".to_string()));
+    }
+
+    #[test]
+    fn test_interpreter_runs_hot_functions_natively() {
+        let mut interp = crate::interpreter::Interpreter::new();
+        interp.set_jit(true);
+        let source = "fn poly(x) { let y = x * x; return y * 3 - x + 7; }
+            fn is_small(x) { return x < 10; }
+            fn ratio(a, b) { return a / b; }
+            fn main() {
+                let i = 0;
+                let total = 0;
+                let small = 0;
+                while (i < 1200) {
+                    total = total + poly(i);
+                    if (is_small(i)) { small = small + 1; }
+                    total = total + ratio(i, 7);
+                    i = i + 1;
+                }
+                print(total);
+                print(small);
+                print(is_small(3));
+                try {
+                    ratio(1, 0);
+                } catch (e) {
+                    print(e);
+                }
+            }";
+        let (result, printed) = interp.run_source(source);
+        assert_eq!(result, Ok(0));
+        assert_eq!(printed, "1725231858\n10\ntrue\ndivision by zero\n");
+        assert!(interp.jit_stats().unwrap().contains("compiled=3"), "{:?}", interp.jit_stats());
+        // The division by zero was handed to the VM
+        assert!(interp.jit_stats().unwrap().contains("deopts=1"), "{:?}", interp.jit_stats());
+    }
+
     #[test]
     fn test_jit_hot_path_detection() {
         let mut jit = JitCompiler::new().expect("Failed to create JIT");
@@ -0,0 +1,211 @@
+// ============================================
+// Cryo JSON Module
+// JSON parsing into SendValue, and string quoting for serializers
+// ============================================
+
+use crate::intern::Symbol;
+use crate::threading::SendValue;
+use std::collections::HashMap;
+
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parse JSON text. Objects become anonymous structs; numbers with a fraction
+/// or exponent are truncated to integers.
+pub fn parse(text: &str) -> Result<SendValue, String> {
+    let mut p = JsonParser { src: text.as_bytes(), pos: 0 };
+    let value = p.value()?;
+    p.skip_ws();
+    if p.pos != p.src.len() {
+        return Err(format!("JSON: trailing characters at {}", p.pos));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_ws(&mut self) {
+        while self.pos < self.src.len() && self.src[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_ws();
+        if self.src.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("JSON: expected '{}' at {}", byte as char, self.pos))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: SendValue) -> Result<SendValue, String> {
+        if self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(format!("JSON: unexpected token at {}", self.pos))
+        }
+    }
+
+    fn value(&mut self) -> Result<SendValue, String> {
+        self.skip_ws();
+        match self.src.get(self.pos) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(SendValue::String(self.string()?)),
+            Some(b't') => self.literal("true", SendValue::Bool(true)),
+            Some(b'f') => self.literal("false", SendValue::Bool(false)),
+            Some(b'n') => self.literal("null", SendValue::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(format!("JSON: unexpected character at {}", self.pos)),
+            None => Err("JSON: unexpected end of input".to_string()),
+        }
+    }
+
+    fn object(&mut self) -> Result<SendValue, String> {
+        self.pos += 1;
+        let mut fields = HashMap::new();
+        self.skip_ws();
+        if self.src.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(SendValue::Struct(Symbol::EMPTY, fields));
+        }
+        loop {
+            self.skip_ws();
+            if self.src.get(self.pos) != Some(&b'"') {
+                return Err(format!("JSON: expected object key at {}", self.pos));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.insert(key, self.value()?);
+            self.skip_ws();
+            match self.src.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(SendValue::Struct(Symbol::EMPTY, fields));
+                }
+                _ => return Err(format!("JSON: expected ',' or '}}' at {}", self.pos)),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<SendValue, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.src.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(SendValue::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_ws();
+            match self.src.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(SendValue::Array(items));
+                }
+                _ => return Err(format!("JSON: expected ',' or ']' at {}", self.pos)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1; // opening quote
+        let mut out = Vec::new();
+        loop {
+            let b = *self.src.get(self.pos).ok_or("JSON: unterminated string")?;
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let esc = *self.src.get(self.pos).ok_or("JSON: unterminated string")?;
+                    self.pos += 1;
+                    match esc {
+                        b'n' => out.push(b'\n'),
+                        b't' => out.push(b'\t'),
+                        b'r' => out.push(b'\r'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0c),
+                        b'u' => {
+                            let hex = self.src.get(self.pos..self.pos + 4).ok_or("JSON: bad \\u escape")?;
+                            let code = u32::from_str_radix(std::str::from_utf8(hex).unwrap_or(""), 16)
+                                .map_err(|_| "JSON: bad \\u escape".to_string())?;
+                            self.pos += 4;
+                            let c = char::from_u32(code).unwrap_or('\u{fffd}');
+                            out.extend_from_slice(c.to_string().as_bytes());
+                        }
+                        other => out.push(other),
+                    }
+                }
+                b => out.push(b),
+            }
+        }
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+
+    fn number(&mut self) -> Result<SendValue, String> {
+        let start = self.pos;
+        while self.pos < self.src.len() && matches!(self.src[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or("");
+        if let Ok(n) = text.parse::<i64>() {
+            return Ok(SendValue::Int(n));
+        }
+        text.parse::<f64>()
+            .map(SendValue::Float)
+            .map_err(|_| format!("JSON: bad number at {}", start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let value = parse(r#"{"name":"a \"b\"","tags":[1,true,null],"user":{"id":7}}"#).unwrap();
+        match value {
+            SendValue::Struct(_, fields) => {
+                assert!(matches!(&fields["name"], SendValue::String(s) if s == "a \"b\""));
+                assert!(matches!(&fields["tags"], SendValue::Array(items) if items.len() == 3));
+                assert!(matches!(&fields["user"], SendValue::Struct(_, user) if matches!(user["id"], SendValue::Int(7))));
+            }
+            other => panic!("expected object, got {:?}", other),
+        }
+        assert!(parse("[1,").is_err());
+        assert_eq!(quote("a\n\"b\""), r#""a\n\"b\"""#);
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        for f in [0.1 + 0.2, 1.0 / 3.0, 2.0, 1e-9, 1.7976931348623157e308] {
+            let text = crate::text::format_float(f);
+            assert!(matches!(parse(&text), Ok(SendValue::Float(g)) if g == f), "{}", text);
+        }
+    }
+}
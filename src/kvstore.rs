@@ -0,0 +1,209 @@
+// ============================================
+// Cryo KV Store Module
+// Embedded persistent key-value store backed by an append-only log
+// ============================================
+
+use crate::crypto;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+
+const OP_SET: u8 = 1;
+const OP_DELETE: u8 = 2;
+/// op + key length + value length
+const HEADER_LEN: usize = 9;
+/// Logs smaller than this are never compacted automatically
+const COMPACT_MIN_BYTES: u64 = 1 << 20;
+
+/// Every write appends one record to the log:
+///
+/// ```text
+/// [op: u8][key_len: u32 LE][value_len: u32 LE][key][value][crc32 of all previous bytes: u32 LE]
+/// ```
+///
+/// Opening replays the log into an in-memory index. A torn or corrupt record
+/// at the tail (a crash mid-write) is dropped and the file truncated back to
+/// the last good record. Once dead records outweigh live data the log is
+/// rewritten to hold just the live keys.
+pub struct KvStore {
+    path: String,
+    file: File,
+    index: BTreeMap<String, String>,
+    /// Size of the log file in bytes
+    log_bytes: u64,
+    /// Bytes the live entries would take in a freshly compacted log
+    live_bytes: u64,
+    sync: bool,
+}
+
+fn record_len(key: &str, value: &str) -> u64 {
+    (HEADER_LEN + key.len() + value.len() + 4) as u64
+}
+
+fn encode(op: u8, key: &str, value: &str) -> Vec<u8> {
+    let mut rec = Vec::with_capacity(record_len(key, value) as usize);
+    rec.push(op);
+    rec.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    rec.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    rec.extend_from_slice(key.as_bytes());
+    rec.extend_from_slice(value.as_bytes());
+    let crc = crypto::crc32(&rec);
+    rec.extend_from_slice(&crc.to_le_bytes());
+    rec
+}
+
+/// Next record at `data[pos..]` as (op, key, value, record length), or None
+/// if what's left is truncated or fails its checksum
+fn decode(data: &[u8], pos: usize) -> Option<(u8, String, String, usize)> {
+    let header = data.get(pos..pos + HEADER_LEN)?;
+    let key_len = u32::from_le_bytes(header[1..5].try_into().ok()?) as usize;
+    let value_len = u32::from_le_bytes(header[5..9].try_into().ok()?) as usize;
+    let body_end = pos.checked_add(HEADER_LEN + key_len)?.checked_add(value_len)?;
+    let crc = data.get(body_end..body_end + 4)?;
+    if crypto::crc32(&data[pos..body_end]) != u32::from_le_bytes(crc.try_into().ok()?) {
+        return None;
+    }
+    let key = String::from_utf8(data[pos + HEADER_LEN..pos + HEADER_LEN + key_len].to_vec()).ok()?;
+    let value = String::from_utf8(data[pos + HEADER_LEN + key_len..body_end].to_vec()).ok()?;
+    Some((header[0], key, value, body_end + 4 - pos))
+}
+
+impl KvStore {
+    /// Open or create the store at `path`. With `sync`, every write is
+    /// fsynced before returning.
+    pub fn open(path: &str, sync: bool) -> Result<KvStore, String> {
+        let err = |e: std::io::Error| format!("kv_open {}: {}", path, e);
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path).map_err(err)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).map_err(err)?;
+
+        let mut index = BTreeMap::new();
+        let mut pos = 0;
+        while let Some((op, key, value, len)) = decode(&data, pos) {
+            match op {
+                OP_SET => {
+                    index.insert(key, value);
+                }
+                OP_DELETE => {
+                    index.remove(&key);
+                }
+                _ => break,
+            }
+            pos += len;
+        }
+        if pos < data.len() {
+            file.set_len(pos as u64).map_err(err)?;
+        }
+        let live_bytes = index.iter().map(|(k, v)| record_len(k, v)).sum();
+        Ok(KvStore { path: path.to_string(), file, index, log_bytes: pos as u64, live_bytes, sync })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.index.get(key).map(|v| v.as_str())
+    }
+
+    /// Keys starting with `prefix`, in sorted order
+    pub fn keys(&self, prefix: &str) -> Vec<String> {
+        self.index.range(prefix.to_string()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.append(&encode(OP_SET, key, value))?;
+        if let Some(old) = self.index.insert(key.to_string(), value.to_string()) {
+            self.live_bytes -= record_len(key, &old);
+        }
+        self.live_bytes += record_len(key, value);
+        self.maybe_compact()
+    }
+
+    /// Returns whether the key existed
+    pub fn delete(&mut self, key: &str) -> Result<bool, String> {
+        if !self.index.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(&encode(OP_DELETE, key, ""))?;
+        if let Some(old) = self.index.remove(key) {
+            self.live_bytes -= record_len(key, &old);
+        }
+        self.maybe_compact()?;
+        Ok(true)
+    }
+
+    fn append(&mut self, record: &[u8]) -> Result<(), String> {
+        self.file.write_all(record).map_err(|e| format!("kv write {}: {}", self.path, e))?;
+        if self.sync {
+            self.file.sync_data().map_err(|e| format!("kv sync {}: {}", self.path, e))?;
+        }
+        self.log_bytes += record.len() as u64;
+        Ok(())
+    }
+
+    fn maybe_compact(&mut self) -> Result<(), String> {
+        if self.log_bytes >= COMPACT_MIN_BYTES && self.log_bytes > self.live_bytes * 2 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the log with only the live entries. The new log is written
+    /// beside the old one and renamed over it, so a crash leaves one or the other.
+    pub fn compact(&mut self) -> Result<(), String> {
+        let tmp = format!("{}.compact", self.path);
+        let err = |e: std::io::Error| format!("kv compact {}: {}", self.path, e);
+        let mut out = Vec::with_capacity(self.live_bytes as usize);
+        for (k, v) in &self.index {
+            out.extend_from_slice(&encode(OP_SET, k, v));
+        }
+        let mut file = File::create(&tmp).map_err(err)?;
+        file.write_all(&out).map_err(err)?;
+        file.sync_all().map_err(err)?;
+        fs::rename(&tmp, &self.path).map_err(err)?;
+        self.file = OpenOptions::new().append(true).open(&self.path).map_err(err)?;
+        self.log_bytes = out.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persist_and_recover() {
+        let path = std::env::temp_dir().join(format!("cryo_kv_{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let mut kv = KvStore::open(path, false).unwrap();
+        kv.set("user:1", "ada").unwrap();
+        kv.set("user:2", "alan").unwrap();
+        kv.set("config", "{}").unwrap();
+        kv.set("user:1", "grace").unwrap();
+        assert!(kv.delete("user:2").unwrap());
+        assert!(!kv.delete("missing").unwrap());
+        drop(kv);
+
+        // Simulate a crash halfway through appending a record
+        let torn = encode(OP_SET, "user:3", "half-written");
+        OpenOptions::new().append(true).open(path).unwrap().write_all(&torn[..torn.len() - 3]).unwrap();
+
+        let mut kv = KvStore::open(path, false).unwrap();
+        assert_eq!(kv.get("user:1"), Some("grace"));
+        assert_eq!(kv.get("user:2"), None);
+        assert_eq!(kv.keys("user:"), vec!["user:1"]);
+        assert_eq!(kv.keys(""), vec!["config", "user:1"]);
+        kv.set("user:4", "linus").unwrap();
+        kv.compact().unwrap();
+        let compacted = fs::metadata(path).unwrap().len();
+        assert_eq!(compacted, record_len("config", "{}") + record_len("user:1", "grace") + record_len("user:4", "linus"));
+        drop(kv);
+
+        let kv = KvStore::open(path, false).unwrap();
+        assert_eq!(kv.keys("").len(), 3);
+        assert_eq!(kv.get("user:4"), Some("linus"));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,28 +1,34 @@
 // Cryo Lexer - Tokenizes Cryo source code
 // Compatible with compiler.ar v3.0.0
 
+use crate::intern::Symbol;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     // Keywords
     Fn, Let, Return, If, Else, While, Print, True, False,
     Break, Continue, Struct, Enum, Match, Import,
     Async, Await, Extern, Defer, Macro,
+    Try, Catch, Throw, With,
     // FFI & Traits keywords
     Trait, Impl, For, SelfType,
     
     // Literals
     Number(i64),
+    Float(f64),
     String(String),
-    Identifier(String),
+    /// A string literal with `${...}` in it: `"Hi ${name}"`
+    Interpolated(Vec<StrPart>),
+    Identifier(Symbol),
     
     // Operators
     Plus, Minus, Star, Slash, Percent,
     Eq, EqEq, NotEq, Lt, Gt, LtEq, GtEq,
-    And, Or, Not,
+    And, Or, Not, Question,
     
     // Delimiters
     LParen, RParen, LBrace, RBrace, LBracket, RBracket,
-    Semi, Comma, Colon, ColonColon, Dot, Arrow,
+    Semi, Comma, Colon, ColonColon, Dot, Arrow, FatArrow,
     
     // Attributes & Decorators
     At, WasmExport, WasmImport,
@@ -46,10 +52,45 @@ pub enum Token {
     Eof,
 }
 
+/// A piece of an interpolated string: literal text, or the tokens of one
+/// `${...}` expression (ending with Eof)
+#[derive(Debug, Clone, PartialEq)]
+pub enum StrPart {
+    Text(String),
+    Code(Vec<Token>),
+}
+
+impl StrPart {
+    /// The tokens of a `${...}` part; none for text
+    pub fn tokens(&self) -> &[Token] {
+        match self {
+            StrPart::Code(tokens) => tokens,
+            StrPart::Text(_) => &[],
+        }
+    }
+}
+
+/// Byte range of a token in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 pub struct Lexer {
     source: Vec<char>,
     pos: usize,
+    /// Byte offset of `pos` in the original source
+    offset: usize,
     line: usize,
+    /// 1-based column of `pos`, in characters
+    col: usize,
+    /// Line each token starts on, parallel to the token list
+    lines: Vec<usize>,
+    /// Column each token starts on, parallel to the token list
+    columns: Vec<usize>,
+    /// Where each token is, parallel to the token list
+    spans: Vec<Span>,
 }
 
 impl Lexer {
@@ -57,7 +98,12 @@ impl Lexer {
         Lexer {
             source: source.chars().collect(),
             pos: 0,
+            offset: 0,
             line: 1,
+            col: 1,
+            lines: Vec::new(),
+            columns: Vec::new(),
+            spans: Vec::new(),
         }
     }
     
@@ -71,13 +117,33 @@ impl Lexer {
     
     fn advance(&mut self) -> Option<char> {
         let c = self.peek();
-        if c == Some('\n') {
-            self.line += 1;
+        if let Some(c) = c {
+            if c == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+            self.offset += c.len_utf8();
         }
         self.pos += 1;
         c
     }
     
+    /// Skip a byte-order mark and a `#!/usr/bin/env cryo` line so scripts
+    /// can be run directly on Unix
+    fn skip_preamble(&mut self) {
+        if self.peek() == Some('\u{feff}') {
+            self.advance();
+            self.col = 1;
+        }
+        if self.peek() == Some('#') && self.peek_next() == Some('!') {
+            while self.peek().is_some() && self.peek() != Some('\n') {
+                self.advance();
+            }
+        }
+    }
+    
     fn skip_whitespace(&mut self) {
         while let Some(c) = self.peek() {
             if c.is_whitespace() {
@@ -93,8 +159,10 @@ impl Lexer {
         }
     }
     
-    fn read_string(&mut self) -> String {
+    /// A string literal, split at each `${...}`. `\$` is a literal `$`.
+    fn read_string(&mut self) -> Token {
         self.advance(); // consume opening quote
+        let mut parts = Vec::new();
         let mut s = String::new();
         while let Some(c) = self.peek() {
             if c == '"' {
@@ -114,25 +182,87 @@ impl Lexer {
                     }
                     self.advance();
                 }
+            } else if c == '$' && self.peek_next() == Some('{') {
+                self.advance();
+                self.advance();
+                parts.push(StrPart::Text(std::mem::take(&mut s)));
+                parts.push(StrPart::Code(tokenize(&self.read_interpolation())));
             } else {
                 s.push(c);
                 self.advance();
             }
         }
-        s
+        if parts.is_empty() {
+            return Token::String(s);
+        }
+        parts.push(StrPart::Text(s));
+        parts.retain(|p| *p != StrPart::Text(String::new()));
+        Token::Interpolated(parts)
+    }
+
+    /// The source of a `${...}` expression, after the `${`, through its
+    /// closing brace. Braces and string literals inside it may nest.
+    fn read_interpolation(&mut self) -> String {
+        let mut code = String::new();
+        let mut depth = 0;
+        let mut quoted = false;
+        while let Some(c) = self.advance() {
+            match c {
+                '\\' if quoted => {
+                    code.push(c);
+                    if let Some(escaped) = self.advance() {
+                        code.push(escaped);
+                    }
+                    continue;
+                }
+                '"' => quoted = !quoted,
+                '{' if !quoted => depth += 1,
+                '}' if !quoted && depth == 0 => break,
+                '}' if !quoted => depth -= 1,
+                _ => {}
+            }
+            code.push(c);
+        }
+        code
     }
     
-    fn read_number(&mut self) -> i64 {
+    /// An int, or a float when the digits go on with `.` and a digit or
+    /// with an exponent: `3.14`, `1e9`, `2.5e-3`
+    fn read_number(&mut self) -> Token {
         let mut num_str = String::new();
-        while let Some(c) = self.peek() {
-            if c.is_ascii_digit() {
-                num_str.push(c);
+        self.read_digits(&mut num_str);
+        let mut float = false;
+        if self.peek() == Some('.') && self.peek_next().is_some_and(|c| c.is_ascii_digit()) {
+            float = true;
+            num_str.push('.');
+            self.advance();
+            self.read_digits(&mut num_str);
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let sign = matches!(self.peek_next(), Some('+' | '-'));
+            let digit_at = if sign { self.pos + 2 } else { self.pos + 1 };
+            if self.source.get(digit_at).is_some_and(|c| c.is_ascii_digit()) {
+                float = true;
+                num_str.push('e');
                 self.advance();
-            } else {
-                break;
+                if sign {
+                    num_str.push(self.advance().unwrap_or('+'));
+                }
+                self.read_digits(&mut num_str);
             }
         }
-        num_str.parse().unwrap_or(0)
+        if float {
+            Token::Float(num_str.parse().unwrap_or(0.0))
+        } else {
+            Token::Number(num_str.parse().unwrap_or(0))
+        }
+    }
+
+    fn read_digits(&mut self, out: &mut String) {
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+            out.push(c);
+            self.advance();
+        }
     }
     
     fn read_identifier(&mut self) -> String {
@@ -149,21 +279,37 @@ impl Lexer {
     }
     
     pub fn tokenize(&mut self) -> Vec<Token> {
+        self.skip_preamble();
+        self.tokenize_until(|_| false)
+    }
+
+    /// Tokens up to the end of input (ending with Eof), or up to the first
+    /// token that would start at a byte offset `stop` accepts
+    fn tokenize_until(&mut self, mut stop: impl FnMut(usize) -> bool) -> Vec<Token> {
         let mut tokens = Vec::new();
         
         loop {
             self.skip_whitespace();
             
+            let line = self.line;
+            let col = self.col;
+            let start = self.offset;
+            if stop(start) {
+                break;
+            }
             let c = match self.peek() {
                 Some(c) => c,
                 None => {
                     tokens.push(Token::Eof);
+                    self.lines.push(line);
+                    self.columns.push(col);
+                    self.spans.push(Span { start, end: start });
                     break;
                 }
             };
             
             let token = match c {
-                '"' => Token::String(self.read_string()),
+                '"' => self.read_string(),
                 
                 '+' => { self.advance(); Token::Plus }
                 '-' => { 
@@ -198,12 +344,16 @@ impl Lexer {
                     }
                 }
                 '.' => { self.advance(); Token::Dot }
+                '?' => { self.advance(); Token::Question }
                 
                 '=' => {
                     self.advance();
                     if self.peek() == Some('=') {
                         self.advance();
                         Token::EqEq
+                    } else if self.peek() == Some('>') {
+                        self.advance();
+                        Token::FatArrow
                     } else {
                         Token::Eq
                     }
@@ -305,12 +455,12 @@ impl Lexer {
                             // Unknown @ identifier - this is a builtin call like @sleep(1)
                             // Push Token::At, then push identifier, let parser handle the rest
                             tokens.push(Token::At);
-                            Token::Identifier(attr)
+                            Token::Identifier(Symbol::intern(&attr))
                         }
                     }
                 }
                 
-                _ if c.is_ascii_digit() => Token::Number(self.read_number()),
+                _ if c.is_ascii_digit() => self.read_number(),
                 
                 _ if c.is_alphabetic() || c == '_' || c == '$' => {
                     let id = self.read_identifier();
@@ -337,11 +487,15 @@ impl Lexer {
                         "await" => Token::Await,
                         "extern" => Token::Extern,
                         "defer" => Token::Defer,
+                        "try" => Token::Try,
+                        "catch" => Token::Catch,
+                        "throw" => Token::Throw,
+                        "with" => Token::With,
                         "trait" => Token::Trait,
                         "impl" => Token::Impl,
                         "for" => Token::For,
                         "Self" => Token::SelfType,
-                        _ => Token::Identifier(id),
+                        _ => Token::Identifier(Symbol::intern(&id)),
                     }
                 }
                 
@@ -352,6 +506,10 @@ impl Lexer {
             };
             
             tokens.push(token);
+            // `@name` pushes two tokens; both start on this line and share a span
+            self.lines.resize(tokens.len(), line);
+            self.columns.resize(tokens.len(), col);
+            self.spans.resize(tokens.len(), Span { start, end: self.offset });
         }
         
         tokens
@@ -362,3 +520,58 @@ pub fn tokenize(source: &str) -> Vec<Token> {
     let mut lexer = Lexer::new(source);
     lexer.tokenize()
 }
+
+/// Tokens plus the 1-based line each one starts on
+pub fn tokenize_with_lines(source: &str) -> (Vec<Token>, Vec<usize>) {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize();
+    (tokens, lexer.lines)
+}
+
+/// Tokens, their lines and their byte spans
+pub fn tokenize_with_spans(source: &str) -> (Vec<Token>, Vec<usize>, Vec<Span>) {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize();
+    (tokens, lexer.lines, lexer.spans)
+}
+
+/// Tokens with the line, column and byte span of each, for reporting
+/// errors at a place in the source
+pub fn tokenize_with_positions(source: &str) -> (Vec<Token>, Vec<usize>, Vec<usize>, Vec<Span>) {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize();
+    (tokens, lexer.lines, lexer.columns, lexer.spans)
+}
+
+/// Re-lex `source` starting at byte `offset`, which must be where a token
+/// could start (the end of an earlier token) on 1-based `line`. Stops before
+/// the first token starting at an offset `stop` accepts, or after Eof.
+/// Lines and spans are for the whole source.
+pub fn tokenize_from(source: &str, offset: usize, line: usize, stop: impl FnMut(usize) -> bool) -> (Vec<Token>, Vec<usize>, Vec<Span>) {
+    let mut lexer = Lexer::new(&source[offset..]);
+    lexer.offset = offset;
+    lexer.line = line;
+    lexer.col = source[..offset].rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    if offset == 0 {
+        lexer.skip_preamble();
+    }
+    let tokens = lexer.tokenize_until(stop);
+    (tokens, lexer.lines, lexer.spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shebang_line_is_skipped() {
+        let script = "#!/usr/bin/env cryo\nfn main() { print(1); }\n";
+        let (tokens, lines, columns, spans) = tokenize_with_positions(script);
+        assert_eq!(tokens, tokenize("fn main() { print(1); }\n"));
+        // Positions still count the skipped line
+        assert_eq!((lines[0], columns[0], spans[0].start), (2, 1, 20));
+        // After a byte-order mark too, but only on the first line
+        assert_eq!(tokenize(&format!("\u{feff}{}", script)), tokens);
+        assert!(tokenize("fn main() {}\n#!/usr/bin/env cryo\n").len() > tokenize("fn main() {}\n").len());
+    }
+}
@@ -0,0 +1,73 @@
+// Cryo v4.0.0 - library crate
+// Everything but the command line lives here, so the `cryo` binary, the
+// fuzz targets in fuzz/ and other tools share one build of the language
+
+// Engine types are built with `new()`; there is no meaningful default
+#![allow(clippy::new_without_default)]
+
+pub mod intern;
+pub mod lexer;
+pub mod parser;
+pub mod interpreter;
+pub mod codegen;
+pub mod optimizer;
+pub mod lints;
+pub mod diagnostics;
+pub mod dotenv;
+pub mod crash;
+pub mod expander;
+pub mod fuzz;
+pub mod bytecode_vm;
+pub mod bytecode_compiler;
+pub mod bytecode_file;
+pub mod fast_vm;
+pub mod ffi;
+pub mod gc;
+pub mod native_compiler;
+pub mod threading;
+pub mod sync;
+pub mod http;
+pub mod lifecycle;
+pub mod crypto;
+pub mod bigint;
+pub mod int_types;
+pub mod decimal;
+pub mod linalg;
+pub mod collections;
+pub mod json;
+pub mod selftest;
+pub mod session;
+pub mod compress;
+pub mod encoding;
+pub mod fileio;
+pub mod kvstore;
+pub mod argparse;
+pub mod term;
+pub mod text;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod stack_guard;
+pub mod config;
+pub mod conformance;
+pub mod bundle;
+pub mod ast_value;
+pub mod ast_display;
+pub mod bench;
+pub mod timings;
+pub mod memory;
+pub mod freeze;
+pub mod debugger;
+pub mod glob;
+pub mod validate;
+pub mod diff;
+pub mod template;
+pub mod smtp;
+pub mod output;
+pub mod platform;
+pub mod vfs;
+pub mod trace;
+pub mod engine;
+pub mod lsp;
+pub mod symbols;
+pub mod refactor;
+pub mod semantic;
@@ -0,0 +1,229 @@
+// ============================================
+// Cryo Server Lifecycle Module
+// Server registry, accept-loop cancellation and signal-driven shutdown
+// ============================================
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Shared stop flag for one listening socket
+pub struct ServerControl {
+    closing: AtomicBool,
+    addr: SocketAddr,
+}
+
+impl ServerControl {
+    pub fn new(listener: &TcpListener) -> io::Result<Self> {
+        Ok(ServerControl {
+            closing: AtomicBool::new(false),
+            addr: listener.local_addr()?,
+        })
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::SeqCst)
+    }
+
+    /// Stop accepting. A blocking `accept()` can't be interrupted portably, so
+    /// after raising the flag we wake it with a throwaway connection.
+    pub fn close(&self) {
+        if self.closing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let mut wake = self.addr;
+        if wake.ip().is_unspecified() {
+            wake.set_ip(match wake.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let _ = TcpStream::connect_timeout(&wake, Duration::from_secs(1));
+    }
+}
+
+struct Server {
+    control: Arc<ServerControl>,
+    /// Accept-loop thread, for servers that run in the background
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Process-wide so any isolate (or the signal watcher) can close any server
+fn servers() -> &'static Mutex<HashMap<i64, Server>> {
+    static SERVERS: OnceLock<Mutex<HashMap<i64, Server>>> = OnceLock::new();
+    SERVERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+static NEXT_SERVER_ID: AtomicI64 = AtomicI64::new(1);
+
+pub fn register(control: Arc<ServerControl>, thread: Option<JoinHandle<()>>) -> i64 {
+    install_signal_handlers();
+    let id = NEXT_SERVER_ID.fetch_add(1, Ordering::SeqCst);
+    servers().lock().unwrap().insert(id, Server { control, thread });
+    id
+}
+
+pub fn unregister(id: i64) {
+    servers().lock().unwrap().remove(&id);
+}
+
+/// Stop server `id` from accepting. In-flight requests still complete;
+/// use `wait` to block until they have.
+pub fn close(id: i64) -> bool {
+    let control = servers().lock().unwrap().get(&id).map(|s| s.control.clone());
+    match control {
+        Some(c) => {
+            c.close();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Close every registered server, returning how many there were
+pub fn close_all() -> usize {
+    let controls: Vec<_> = servers().lock().unwrap().values().map(|s| s.control.clone()).collect();
+    for c in &controls {
+        c.close();
+    }
+    controls.len()
+}
+
+/// Block until server `id`'s accept loop has exited and its workers drained
+pub fn wait(id: i64) -> bool {
+    let thread = match servers().lock().unwrap().get_mut(&id) {
+        Some(server) => server.thread.take(),
+        None => return false,
+    };
+    if let Some(t) = thread {
+        let _ = t.join();
+    }
+    unregister(id);
+    true
+}
+
+/// A script-level TCP listener (`cryo_listen`) that takes part in shutdown
+pub struct ManagedListener {
+    socket: TcpListener,
+    control: Arc<ServerControl>,
+    pub server_id: i64,
+}
+
+impl ManagedListener {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let socket = TcpListener::bind(addr)?;
+        let control = Arc::new(ServerControl::new(&socket)?);
+        let server_id = register(control.clone(), None);
+        Ok(ManagedListener { socket, control, server_id })
+    }
+
+    /// Next connection, or None once the listener is closing
+    pub fn accept(&self) -> Option<TcpStream> {
+        if self.control.is_closing() {
+            return None;
+        }
+        let (stream, _) = self.socket.accept().ok()?;
+        if self.control.is_closing() {
+            return None; // the wake-up connection
+        }
+        Some(stream)
+    }
+}
+
+impl Drop for ManagedListener {
+    fn drop(&mut self) {
+        unregister(self.server_id);
+    }
+}
+
+// ============================================
+// Signals
+// ============================================
+
+/// Number of SIGINT/SIGTERM deliveries seen
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn shutdown_requested() -> bool {
+    SIGNALS.load(Ordering::SeqCst) > 0
+}
+
+/// Route SIGINT/SIGTERM to a graceful shutdown: the first signal closes every
+/// registered server so blocked accept loops return and workers drain; a
+/// second signal, or one arriving with no server to close, exits immediately.
+pub fn install_signal_handlers() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        sys::install();
+        thread::Builder::new()
+            .name("cryo-signals".to_string())
+            .spawn(watch_signals)
+            .expect("failed to spawn signal watcher");
+    });
+}
+
+/// Signal handlers may only touch atomics, so the real work happens here
+fn watch_signals() {
+    let mut handled = 0;
+    loop {
+        thread::sleep(Duration::from_millis(50));
+        let seen = SIGNALS.load(Ordering::SeqCst);
+        if seen == handled {
+            continue;
+        }
+        if handled > 0 || close_all() == 0 {
+            std::process::exit(130);
+        }
+        eprintln!("\nShutting down (press Ctrl+C again to force)...");
+        handled = seen;
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::SIGNALS;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_signal(_signum: i32) {
+        SIGNALS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        let handler = on_signal as extern "C" fn(i32) as usize;
+        unsafe {
+            signal(SIGINT, handler);
+            signal(SIGTERM, handler);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    pub fn install() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_close_wakes_accept() {
+        let listener = ManagedListener::bind("127.0.0.1:0").unwrap();
+        let id = listener.server_id;
+        let waiter = thread::spawn(move || listener.accept().is_none());
+        thread::sleep(Duration::from_millis(50));
+        assert!(close(id));
+        assert!(waiter.join().unwrap());
+        assert!(!close(id)); // dropped listener unregisters itself
+    }
+}
@@ -0,0 +1,147 @@
+// ============================================
+// Cryo Linear Algebra Module
+// Vector and matrix kernels over flat i64 slices
+// ============================================
+
+// The loops below are written as straight zips over slices with wrapping
+// arithmetic: no bounds checks and no overflow branches in the body, so LLVM
+// auto-vectorizes them to SIMD on every target without needing std::simd.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+}
+
+impl Op {
+    #[inline(always)]
+    fn apply(self, a: i64, b: i64) -> i64 {
+        match self {
+            Op::Add => a.wrapping_add(b),
+            Op::Sub => a.wrapping_sub(b),
+            Op::Mul => a.wrapping_mul(b),
+            // Same convention as `/` in scripts: dividing by zero gives zero
+            Op::Div => if b == 0 { 0 } else { a.wrapping_div(b) },
+            Op::Min => a.min(b),
+            Op::Max => a.max(b),
+        }
+    }
+}
+
+/// `a[i] op b[i]`; the vectors must be the same length
+pub fn elementwise(a: &[i64], b: &[i64], op: Op) -> Result<Vec<i64>, String> {
+    if a.len() != b.len() {
+        return Err(format!("vector length mismatch: {} vs {}", a.len(), b.len()));
+    }
+    // Dispatch once so each loop body is a single branch-free kernel
+    Ok(match op {
+        Op::Add => a.iter().zip(b).map(|(x, y)| x.wrapping_add(*y)).collect(),
+        Op::Sub => a.iter().zip(b).map(|(x, y)| x.wrapping_sub(*y)).collect(),
+        Op::Mul => a.iter().zip(b).map(|(x, y)| x.wrapping_mul(*y)).collect(),
+        _ => a.iter().zip(b).map(|(x, y)| op.apply(*x, *y)).collect(),
+    })
+}
+
+/// `a[i] op k` for every element
+pub fn scalar(a: &[i64], k: i64, op: Op) -> Vec<i64> {
+    match op {
+        Op::Add => a.iter().map(|x| x.wrapping_add(k)).collect(),
+        Op::Sub => a.iter().map(|x| x.wrapping_sub(k)).collect(),
+        Op::Mul => a.iter().map(|x| x.wrapping_mul(k)).collect(),
+        _ => a.iter().map(|x| op.apply(*x, k)).collect(),
+    }
+}
+
+pub fn dot(a: &[i64], b: &[i64]) -> Result<i64, String> {
+    if a.len() != b.len() {
+        return Err(format!("vector length mismatch: {} vs {}", a.len(), b.len()));
+    }
+    // Independent accumulators break the add dependency chain
+    let mut acc = [0i64; 4];
+    let (a_chunks, b_chunks) = (a.chunks_exact(4), b.chunks_exact(4));
+    let tail: i64 = a_chunks.remainder().iter().zip(b_chunks.remainder()).fold(0, |s, (x, y)| s.wrapping_add(x.wrapping_mul(*y)));
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for ((s, x), y) in acc.iter_mut().zip(x).zip(y) {
+            *s = s.wrapping_add(x.wrapping_mul(*y));
+        }
+    }
+    Ok(acc.iter().fold(tail, |s, v| s.wrapping_add(*v)))
+}
+
+/// Most elements a `mat_mul` result may have (512 MiB of i64s); the
+/// dimensions alone decide its size, so an empty input can ask for any
+const MAX_PRODUCT_ELEMENTS: usize = 1 << 26;
+
+/// Row-major `(rows x inner) * (inner x cols)` product
+pub fn mat_mul(a: &[i64], b: &[i64], rows: usize, inner: usize, cols: usize) -> Result<Vec<i64>, String> {
+    if rows.checked_mul(inner) != Some(a.len()) {
+        return Err(format!("mat_mul: left matrix has {} elements, expected {}x{}", a.len(), rows, inner));
+    }
+    if inner.checked_mul(cols) != Some(b.len()) {
+        return Err(format!("mat_mul: right matrix has {} elements, expected {}x{}", b.len(), inner, cols));
+    }
+    let size = rows.checked_mul(cols).filter(|&n| n <= MAX_PRODUCT_ELEMENTS)
+        .ok_or_else(|| format!("mat_mul: a {}x{} result is too large (limit {} elements)", rows, cols, MAX_PRODUCT_ELEMENTS))?;
+    let mut out = vec![0i64; size];
+    if cols == 0 {
+        return Ok(out);
+    }
+    // i-k-j order: the inner loop walks a row of `b` and a row of `out`
+    // contiguously, which is both cache friendly and vectorizable
+    for (a_row, out_row) in a.chunks_exact(inner.max(1)).zip(out.chunks_exact_mut(cols)) {
+        for (&aik, b_row) in a_row.iter().zip(b.chunks_exact(cols)) {
+            for (o, &bkj) in out_row.iter_mut().zip(b_row) {
+                *o = o.wrapping_add(aik.wrapping_mul(bkj));
+            }
+        }
+    }
+    Ok(out)
+}
+
+pub fn transpose(a: &[i64], rows: usize, cols: usize) -> Result<Vec<i64>, String> {
+    if rows.checked_mul(cols) != Some(a.len()) {
+        return Err(format!("mat_transpose: matrix has {} elements, expected {}x{}", a.len(), rows, cols));
+    }
+    let mut out = vec![0i64; a.len()];
+    for r in 0..rows {
+        for c in 0..cols {
+            out[c * rows + r] = a[r * cols + c];
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(elementwise(&[1, 2, 3], &[10, 20, 30], Op::Add).unwrap(), vec![11, 22, 33]);
+        assert_eq!(elementwise(&[7, 8], &[2, 0], Op::Div).unwrap(), vec![3, 0]);
+        assert_eq!(elementwise(&[1, 9], &[5, 5], Op::Max).unwrap(), vec![5, 9]);
+        assert!(elementwise(&[1], &[1, 2], Op::Sub).is_err());
+        assert_eq!(scalar(&[1, 2, 3], 3, Op::Mul), vec![3, 6, 9]);
+        let a: Vec<i64> = (1..=11).collect();
+        assert_eq!(dot(&a, &a).unwrap(), 506);
+        assert_eq!(dot(&[], &[]).unwrap(), 0);
+        assert!(dot(&[1], &[]).is_err());
+    }
+
+    #[test]
+    fn test_matrices() {
+        // [1 2 3; 4 5 6] * [7 8; 9 10; 11 12]
+        let a = [1, 2, 3, 4, 5, 6];
+        let b = [7, 8, 9, 10, 11, 12];
+        assert_eq!(mat_mul(&a, &b, 2, 3, 2).unwrap(), vec![58, 64, 139, 154]);
+        assert_eq!(mat_mul(&[], &[], 2, 0, 2).unwrap(), vec![0, 0, 0, 0]);
+        assert!(mat_mul(&a, &b, 3, 3, 2).is_err());
+        assert!(mat_mul(&[], &[], usize::MAX / 2, 0, usize::MAX / 2).unwrap_err().contains("too large"));
+        assert!(mat_mul(&[], &[], 100_000, 0, 100_000).unwrap_err().contains("too large"));
+        assert_eq!(transpose(&a, 2, 3).unwrap(), vec![1, 4, 2, 5, 3, 6]);
+    }
+}
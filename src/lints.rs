@@ -0,0 +1,581 @@
+// ============================================
+// Cryo Lints
+// Warnings about code that runs but probably isn't what was meant, for
+// `cryo lint` and the LSP. Lints work on a `Document`'s tokens and spans, so
+// every warning points at source text, and the `=`-in-a-condition lint still
+// fires when that mistake stops the item from parsing.
+//
+// Variables are resolved by block: a `let` is visible from the end of its
+// statement to the closing brace of its block. Unused functions are only
+// reported in programs (files with a `main`), since a library's functions
+// are used by the files that import it.
+//
+// Levels come from `[lints]` in the nearest cryo.toml, then from
+// `@allow(...)`, `@warn(...)` and `@deny(...)` on the item a lint is in:
+//
+//   [lints]
+//   unused = "allow"              # every unused-* lint
+//   shadowed-variable = "deny"
+// ============================================
+
+use crate::engine::{Document, Range};
+use crate::lexer::{StrPart, Token};
+use crate::optimizer::Optimizer;
+use crate::parser::{Expr, Parser, Stmt, TopLevel};
+use crate::symbols;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// The manifest `[lints]` is read from
+pub const MANIFEST: &str = "cryo.toml";
+
+/// Every lint, with what it reports
+pub const LINTS: [(&str, &str); 7] = [
+    ("unused-variable", "a local variable that is never read"),
+    ("unused-function", "a function nothing calls, in a file with a main()"),
+    ("unused-import", "an import none of whose names are used"),
+    ("shadowed-variable", "a let that hides a parameter or an outer variable"),
+    ("unreachable-code", "statements after return, break or continue"),
+    ("constant-condition", "an if or while condition that is always true or false"),
+    ("assignment-in-condition", "`=` in a condition, where `==` was probably meant"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    /// Reported as an error
+    Deny,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// One of `LINTS`
+    pub name: &'static str,
+    pub level: Level,
+    pub range: Range,
+    pub message: String,
+}
+
+/// Lint levels set by a project, in the order given; a later setting wins
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: Vec<(String, Level)>,
+}
+
+impl LintConfig {
+    /// The `[lints]` table of a cryo.toml. Only `name = "level"` lines are
+    /// read; the rest of the manifest is skipped.
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let mut config = LintConfig::default();
+        let mut in_lints = false;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.starts_with('[') {
+                in_lints = line == "[lints]";
+                continue;
+            }
+            if !in_lints || line.is_empty() {
+                continue;
+            }
+            let bad = || format!("line {}: expected `lint-name = \"allow\" | \"warn\" | \"deny\"`", n + 1);
+            let (name, value) = line.split_once('=').ok_or_else(bad)?;
+            let level = Level::parse(value.trim().trim_matches('"')).ok_or_else(bad)?;
+            let name = normalize(name.trim().trim_matches('"'));
+            if !known(&name) {
+                return Err(format!("line {}: unknown lint `{}`", n + 1, name));
+            }
+            config.levels.push((name, level));
+        }
+        Ok(config)
+    }
+
+    /// The config of the nearest cryo.toml in `dir` or above it; the
+    /// default when there is none
+    pub fn find(dir: &Path) -> Result<LintConfig, String> {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        for ancestor in dir.ancestors() {
+            let path = ancestor.join(MANIFEST);
+            if path.is_file() {
+                let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                return LintConfig::parse(&text).map_err(|e| format!("{}: {}", path.display(), e));
+            }
+        }
+        Ok(LintConfig::default())
+    }
+
+    pub fn level(&self, lint: &str) -> Level {
+        self.levels.iter().rev().find(|(name, _)| covers(name, lint)).map_or(Level::Warn, |(_, level)| *level)
+    }
+}
+
+/// `unused_variable` and `unused-variable` are the same lint
+fn normalize(name: &str) -> String {
+    name.replace('_', "-")
+}
+
+/// Whether `name` (a lint or a group like `unused`) covers `lint`
+fn covers(name: &str, lint: &str) -> bool {
+    name == lint || lint.strip_prefix(name).is_some_and(|rest| rest.starts_with('-'))
+}
+
+fn known(name: &str) -> bool {
+    LINTS.iter().any(|(lint, _)| covers(name, lint))
+}
+
+/// Every lint for `doc` that isn't allowed, in source order. `module_names`
+/// gives the names an import makes visible (None when it can't be found).
+pub fn check(doc: &Document, config: &LintConfig, module_names: &dyn Fn(&str) -> Option<HashSet<String>>) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let members = symbols::member_tokens(doc.tokens());
+    for scope in symbols::scopes(doc) {
+        if doc.tokens()[scope.tokens.start] == Token::Fn {
+            variables(doc, &scope, &members, &mut lints);
+            unreachable(doc, &scope, &mut lints);
+        }
+    }
+    conditions(doc, &mut lints);
+    unused_items(doc, &members, module_names, &mut lints);
+    let attributes = attributes(doc);
+    for lint in &mut lints {
+        lint.level = config.level(lint.name);
+        // Innermost attribute last
+        for (range, names, level) in &attributes {
+            if range.start <= lint.range.start && lint.range.end <= range.end && names.iter().any(|n| covers(n, lint.name)) {
+                lint.level = *level;
+            }
+        }
+    }
+    lints.retain(|lint| lint.level != Level::Allow);
+    lints.sort_by_key(|lint| lint.range.start);
+    lints
+}
+
+/// `check` with imports found next to `path` or under `stdlib/`, the way
+/// `cryo lint` reads them
+pub fn check_file(path: &Path, doc: &Document, config: &LintConfig) -> Vec<Lint> {
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    check(doc, config, &|import| {
+        let mut names = HashSet::new();
+        let mut seen = HashSet::new();
+        collect_module(&dir, import, &mut names, &mut seen).then_some(names)
+    })
+}
+
+/// The names `import` defines, and those of everything it imports
+fn collect_module(dir: &Path, import: &str, names: &mut HashSet<String>, seen: &mut HashSet<PathBuf>) -> bool {
+    let file = format!("{}.cryo", import.trim_end_matches(".cryo"));
+    let Some(path) = [dir.join(&file), Path::new("stdlib").join(&file)].into_iter().find(|p| p.is_file()) else {
+        return false;
+    };
+    if !seen.insert(path.clone()) {
+        return true;
+    }
+    let Ok(text) = std::fs::read_to_string(&path) else { return false };
+    let doc = Document::new(&text);
+    let module_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    for (_, item) in doc.items() {
+        if let TopLevel::Import(inner, _) = item {
+            collect_module(&module_dir, inner, names, seen);
+        }
+    }
+    names.extend(doc.symbols().into_iter().map(|s| s.name));
+    true
+}
+
+/// `@allow(a, b)`, `@warn(...)` and `@deny(...)`: the text from the
+/// attribute to the end of the item after it, the lints named, and the level
+fn attributes(doc: &Document) -> Vec<(Range, Vec<String>, Level)> {
+    let tokens = doc.tokens();
+    let mut found = Vec::new();
+    for i in 0..tokens.len().saturating_sub(3) {
+        let (Token::At, Token::Identifier(name), Token::LParen) = (&tokens[i], &tokens[i + 1], &tokens[i + 2]) else { continue };
+        let Some(level) = Level::parse(name.as_str()) else { continue };
+        let Some(close) = matching_paren(tokens, i + 2) else { continue };
+        // Names are read from the source, since `-` lexes as an operator
+        let text = &doc.text()[doc.spans()[i + 2].end..doc.spans()[close].start];
+        let names = text.split(',').map(|n| normalize(n.trim())).filter(|n| !n.is_empty()).collect();
+        // The item ends at the brace closing its first block, or at a `;` before one
+        let mut end = close + 1;
+        while end < tokens.len() - 1 && !matches!(tokens[end], Token::LBrace | Token::Semi) {
+            end += 1;
+        }
+        if tokens[end] == Token::LBrace {
+            end = block_end(tokens, end + 1);
+        }
+        found.push((doc.token_range(i..end + 1), names, level));
+    }
+    found
+}
+
+struct Local {
+    name: String,
+    /// Token index of the name, None for parameters
+    token: Option<usize>,
+    read: bool,
+}
+
+/// unused-variable and shadowed-variable in one function body
+fn variables(doc: &Document, scope: &symbols::Scope, members: &[bool], lints: &mut Vec<Lint>) {
+    let tokens = doc.tokens();
+    let mut locals: Vec<Local> = scope.params.iter().map(|p| Local { name: p.clone(), token: None, read: true }).collect();
+    // Visible locals per open block; the first holds the parameters
+    let mut blocks: Vec<Vec<usize>> = vec![(0..locals.len()).collect()];
+    // Declared but not visible until the `;` at this depth
+    let mut pending: Vec<(usize, usize)> = Vec::new();
+    let mut i = scope.body;
+    while i < scope.tokens.end {
+        match &tokens[i] {
+            Token::LBrace => blocks.push(Vec::new()),
+            Token::RBrace => {
+                blocks.pop();
+            }
+            Token::Semi => {
+                let depth = blocks.len();
+                for (local, _) in pending.iter().filter(|(_, d)| *d == depth) {
+                    if let Some(block) = blocks.last_mut() {
+                        block.push(*local);
+                    }
+                }
+                pending.retain(|(_, d)| *d != depth);
+            }
+            Token::Let => {
+                if let Some(Token::Identifier(name)) = tokens.get(i + 1) {
+                    if let Some(&outer) = blocks.iter().flatten().rev().find(|&&l| locals[l].name == name.as_str()) {
+                        let what = match locals[outer].token {
+                            Some(token) => format!("the variable from line {}", doc.range(doc.spans()[token]).start.line + 1),
+                            None => "a parameter".to_string(),
+                        };
+                        lints.push(Lint {
+                            level: Level::Warn,
+                            name: "shadowed-variable",
+                            range: doc.range(doc.spans()[i + 1]),
+                            message: format!("`{}` shadows {}", name, what),
+                        });
+                    }
+                    pending.push((locals.len(), blocks.len()));
+                    locals.push(Local { name: name.to_string(), token: Some(i + 1), read: false });
+                    i += 1;
+                }
+            }
+            Token::Identifier(name) if !members[i] => {
+                let assigned = tokens.get(i + 1) == Some(&Token::Eq);
+                if let Some(&local) = blocks.iter().flatten().rev().find(|&&l| locals[l].name == name.as_str()) {
+                    locals[local].read |= !assigned;
+                }
+            }
+            Token::Interpolated(parts) => {
+                for token in parts.iter().flat_map(StrPart::tokens) {
+                    if let Token::Identifier(name) = token {
+                        if let Some(&local) = blocks.iter().flatten().rev().find(|&&l| locals[l].name == name.as_str()) {
+                            locals[local].read = true;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    for local in locals.iter().filter(|l| !l.read && !l.name.starts_with('_')) {
+        if let Some(token) = local.token {
+            lints.push(Lint {
+                level: Level::Warn,
+                name: "unused-variable",
+                range: doc.range(doc.spans()[token]),
+                message: format!("`{}` is never read (name it `_{}` if that's intended)", local.name, local.name),
+            });
+        }
+    }
+}
+
+/// unreachable-code: a block that goes on after return, break or continue
+fn unreachable(doc: &Document, scope: &symbols::Scope, lints: &mut Vec<Lint>) {
+    let tokens = doc.tokens();
+    let mut i = scope.body;
+    while i < scope.tokens.end {
+        let keyword = match &tokens[i] {
+            Token::Return => "return",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        // End of the statement: its `;`, or the block's `}` when there isn't one
+        let mut end = i + 1;
+        let mut depth = 0usize;
+        while end < scope.tokens.end {
+            match &tokens[end] {
+                Token::LBrace | Token::LParen | Token::LBracket => depth += 1,
+                Token::RBrace if depth == 0 => break,
+                Token::RBrace | Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+                Token::Semi if depth == 0 => {
+                    end += 1;
+                    break;
+                }
+                _ if keyword != "return" && depth == 0 => break,
+                _ => {}
+            }
+            end += 1;
+        }
+        if end >= scope.tokens.end || tokens[end] == Token::RBrace {
+            i = end;
+            continue;
+        }
+        let close = block_end(tokens, end).min(scope.tokens.end - 1);
+        lints.push(Lint {
+            level: Level::Warn,
+            name: "unreachable-code",
+            range: doc.token_range(end..close),
+            message: format!("unreachable: the block always leaves at the `{}` before this", keyword),
+        });
+        i = close;
+    }
+}
+
+/// Index of the `}` closing the block that token `from` is in
+fn block_end(tokens: &[Token], from: usize) -> usize {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(from) {
+        match token {
+            Token::LBrace => depth += 1,
+            Token::RBrace if depth == 0 => return i,
+            Token::RBrace => depth -= 1,
+            Token::Eof => return i,
+            _ => {}
+        }
+    }
+    tokens.len() - 1
+}
+
+/// constant-condition and assignment-in-condition on every `if (...)` and
+/// `while (...)`
+fn conditions(doc: &Document, lints: &mut Vec<Lint>) {
+    let tokens = doc.tokens();
+    for (i, token) in tokens.iter().enumerate() {
+        let keyword = match token {
+            Token::If => "if",
+            Token::While => "while",
+            _ => continue,
+        };
+        if tokens.get(i + 1) != Some(&Token::LParen) {
+            continue;
+        }
+        let Some(close) = matching_paren(tokens, i + 1) else { continue };
+        let condition = &tokens[i + 2..close];
+        let mut depth = 0usize;
+        let assignment = condition.iter().position(|t| {
+            match t {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            depth == 0 && t == &Token::Eq
+        });
+        if let Some(at) = assignment {
+            lints.push(Lint {
+                level: Level::Warn,
+                name: "assignment-in-condition",
+                range: doc.range(doc.spans()[i + 2 + at]),
+                message: "`=` assigns; use `==` to compare".to_string(),
+            });
+            continue;
+        }
+        let mut expr_tokens = condition.to_vec();
+        expr_tokens.extend([Token::Semi, Token::Eof]);
+        let Ok(stmts) = Parser::new(&expr_tokens).parse_stmts() else { continue };
+        let [Stmt::Expr(expr)] = stmts.as_slice() else { continue };
+        // `while (true)` is how a loop that exits with break is written
+        if keyword == "while" && matches!(expr, Expr::Bool(true)) {
+            continue;
+        }
+        if let Expr::Bool(value) = Optimizer::new().optimize_expr(expr.clone()) {
+            lints.push(Lint {
+                level: Level::Warn,
+                name: "constant-condition",
+                range: doc.token_range(i + 2..close),
+                message: format!("this `{}` condition is always {}", keyword, value),
+            });
+        }
+    }
+}
+
+fn matching_paren(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 1 => return Some(i),
+            Token::RParen => depth -= 1,
+            Token::Eof => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// unused-function and unused-import
+fn unused_items(doc: &Document, members: &[bool], module_names: &dyn Fn(&str) -> Option<HashSet<String>>, lints: &mut Vec<Lint>) {
+    let tokens = doc.tokens();
+    let scopes = symbols::scopes(doc);
+    // Names used anywhere, and where: function names also count as used
+    // when they're written as a string, e.g. handed to a builtin by name
+    let uses: Vec<(usize, &str)> = tokens
+        .iter()
+        .enumerate()
+        .flat_map(|(i, t)| match t {
+            Token::Identifier(name) if !members[i] => vec![(i, name.as_str())],
+            Token::String(text) => vec![(i, text.as_str())],
+            // Names used inside "${...}" count as used at the string
+            Token::Interpolated(parts) => parts
+                .iter()
+                .flat_map(StrPart::tokens)
+                .filter_map(|t| match t {
+                    Token::Identifier(name) => Some((i, name.as_str())),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        })
+        .collect();
+    let definitions: HashSet<usize> = doc.symbols().iter().map(|s| doc.offset(s.selection.start)).collect();
+    let used = |name: &str, outside: Option<&std::ops::Range<usize>>| {
+        uses.iter().any(|(i, n)| *n == name && !definitions.contains(&doc.spans()[*i].start) && !outside.is_some_and(|r| r.contains(i)))
+    };
+
+    // From the tokens, so a `main` that doesn't parse still counts
+    let program = tokens.windows(2).any(|w| w[0] == Token::Fn && matches!(&w[1], Token::Identifier(n) if n == "main"));
+    for (range, item) in doc.items() {
+        match item {
+            TopLevel::Function(f) if program && f.name != "main" && f.decorators.iter().all(|d| Level::parse(&d.name).is_some()) && f.body.is_some() => {
+                let body = scopes.iter().find(|s| s.name == f.name.as_str() && range.contains(&s.tokens.start)).map(|s| &s.tokens);
+                if !used(f.name.as_str(), body) {
+                    let name = (range.start..range.end).find(|&i| matches!(&tokens[i], Token::Identifier(n) if *n == f.name));
+                    if let Some(name) = name {
+                        lints.push(Lint { level: Level::Warn, name: "unused-function", range: doc.range(doc.spans()[name]), message: format!("function `{}` is never called", f.name) });
+                    }
+                }
+            }
+            TopLevel::Import(path, names) => {
+                let unused: Vec<&String> = if names.is_empty() {
+                    match module_names(path) {
+                        Some(defined) if !defined.iter().any(|name| used(name, None)) => vec![path],
+                        _ => Vec::new(),
+                    }
+                } else {
+                    names.iter().filter(|name| !used(name, Some(&range))).collect()
+                };
+                for name in unused {
+                    let message = if names.is_empty() { format!("nothing from \"{}\" is used", path) } else { format!("`{}` is imported but never used", name) };
+                    lints.push(Lint { level: Level::Warn, name: "unused-import", range: doc.token_range(range.clone()), message });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lints(source: &str) -> Vec<(&'static str, usize, String)> {
+        let doc = Document::new(source);
+        let modules = |path: &str| (path == "util").then(|| HashSet::from(["helper".to_string()]));
+        check(&doc, &LintConfig::default(), &modules).into_iter().map(|l| (l.name, l.range.start.line + 1, l.message)).collect()
+    }
+
+    #[test]
+    fn test_variables_and_flow() {
+        let source = "fn main(n) {
+    let total = 0;
+    let unused = 1;
+    let _ignored = 2;
+    let n = n + 1;
+    if (n > 0) {
+        let total = total + n;
+        print(total);
+        return;
+        print(\"never\");
+    }
+    while (false) { total = 1; }
+    while (true) { break; }
+    if (1 < 2) { print(n); }
+    let shown = 3;
+    print(\"${shown}\");
+}
+";
+        assert_eq!(lints(source), [
+            ("unused-variable", 3, "`unused` is never read (name it `_unused` if that's intended)".to_string()),
+            ("shadowed-variable", 5, "`n` shadows a parameter".to_string()),
+            ("shadowed-variable", 7, "`total` shadows the variable from line 2".to_string()),
+            ("unreachable-code", 10, "unreachable: the block always leaves at the `return` before this".to_string()),
+            ("constant-condition", 12, "this `while` condition is always false".to_string()),
+            ("constant-condition", 14, "this `if` condition is always true".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_items_and_assignment() {
+        let source = "import \"util\"
+import \"other\"
+import { a, b } from \"lib\"
+
+fn used() { return a; }
+fn unused() { return unused(); }
+fn by_name() {}
+
+fn main() {
+    thread_spawn(\"by_name\");
+    if (x = 1) { print(used()); }
+}
+";
+        assert_eq!(lints(source), [
+            ("unused-import", 1, "nothing from \"util\" is used".to_string()),
+            ("unused-import", 3, "`b` is imported but never used".to_string()),
+            ("unused-function", 6, "function `unused` is never called".to_string()),
+            ("assignment-in-condition", 11, "`=` assigns; use `==` to compare".to_string()),
+        ]);
+        // A library's functions are for its importers
+        assert!(lints("fn helper() {}\n").is_empty());
+    }
+
+    #[test]
+    fn test_levels() {
+        let config = LintConfig::parse("[package]\nname = \"app\"\n\n[lints]\nunused = \"allow\"  # for now\nunused_import = \"warn\"\nshadowed-variable = \"deny\"\n").unwrap();
+        assert_eq!(config.level("unused-variable"), Level::Allow);
+        assert_eq!(config.level("unused-import"), Level::Warn);
+        assert_eq!(config.level("shadowed-variable"), Level::Deny);
+        assert_eq!(config.level("unreachable-code"), Level::Warn);
+        assert_eq!(LintConfig::parse("[lints]\nunusd = \"allow\"\n").unwrap_err(), "line 2: unknown lint `unusd`");
+        assert!(LintConfig::parse("[lints]\nunused = \"off\"\n").is_err());
+
+        let source = "@allow(unused-variable, shadowed_variable)
+fn quiet(n) { let n = 1; }
+
+@deny(unused)
+fn loud() { let x = 1; }
+
+fn main() { let y = 2; loud(); quiet(1); }
+";
+        let doc = Document::new(source);
+        let found: Vec<_> = check(&doc, &config, &|_| None).into_iter().map(|l| (l.name, l.level, l.range.start.line + 1)).collect();
+        assert_eq!(found, [("unused-variable", Level::Deny, 5)]);
+        let found: Vec<_> = check(&doc, &LintConfig::default(), &|_| None).into_iter().map(|l| (l.name, l.level, l.range.start.line + 1)).collect();
+        assert_eq!(found, [("unused-variable", Level::Deny, 5), ("unused-variable", Level::Warn, 7)]);
+    }
+}
@@ -0,0 +1,581 @@
+// ============================================
+// Cryo Language Server
+// `cryo lsp`: the Language Server Protocol over stdin/stdout, backed by
+// CryoEngine. Documents are synced incrementally, so a keystroke re-lexes
+// and re-parses only around the edit. Serves parse errors and lint warnings (see lints.rs), the
+// document outline, plus go-to-definition and find-references across the
+// workspace from the symbol index (see symbols.rs), rename and code actions
+// on top of it (see refactor.rs), and semantic tokens (see semantic.rs).
+// ============================================
+
+use crate::diagnostics;
+use crate::engine::{Change, CryoEngine, DocumentSymbol, Position, Range, SymbolKind};
+use crate::json;
+use crate::lints;
+use crate::refactor::{self, FileEdits};
+use crate::semantic::{self, TokenType};
+use crate::symbols::{Location, SymbolIndex};
+use crate::threading::SendValue;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INVALID_REQUEST: i64 = -32600;
+const REQUEST_FAILED: i64 = -32803;
+
+/// Serve messages from `input` until `exit` or end of input. Ok(true) when
+/// `shutdown` came first, which is the clean way to stop.
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write) -> Result<bool, String> {
+    let mut server = Server { engine: CryoEngine::new(), index: None, workspace: false, shut_down: false };
+    while let Some(body) = read_message(input)? {
+        let message = match json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("cryo lsp: bad message: {}", e);
+                continue;
+            }
+        };
+        let method = field(&message, "method").map(|m| m.to_string_val()).unwrap_or_default();
+        let params = field(&message, "params");
+        match field(&message, "id") {
+            // A request; answer it
+            Some(id) if !method.is_empty() => {
+                let id = match id {
+                    SendValue::String(s) => json::quote(s),
+                    other => other.to_string_val(),
+                };
+                let reply = match server.request(&method, params) {
+                    Ok(result) => format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{}}}", id, result),
+                    Err((code, message)) => format!(
+                        "{{\"jsonrpc\":\"2.0\",\"id\":{},\"error\":{{\"code\":{},\"message\":{}}}}}",
+                        id,
+                        code,
+                        json::quote(&message)
+                    ),
+                };
+                write_message(output, &reply)?;
+            }
+            // A response to something we never send
+            Some(_) => {}
+            None if method == "exit" => return Ok(server.shut_down),
+            None => {
+                for notification in server.notify(&method, params) {
+                    write_message(output, &notification)?;
+                }
+            }
+        }
+    }
+    Ok(server.shut_down)
+}
+
+struct Server {
+    engine: CryoEngine,
+    /// The workspace's symbols, once `initialize` names a root; without
+    /// one, just the open documents'
+    index: Option<SymbolIndex>,
+    /// Whether `index` covers a workspace root, rather than open documents only
+    workspace: bool,
+    shut_down: bool,
+}
+
+impl Server {
+    /// The JSON result of a request, or an error code and message
+    fn request(&mut self, method: &str, params: Option<&SendValue>) -> Result<String, (i64, String)> {
+        if self.shut_down {
+            return Err((INVALID_REQUEST, "the server is shutting down".to_string()));
+        }
+        match method {
+            "initialize" => {
+                let root = params.and_then(|p| match field(p, "rootUri") {
+                    Some(SendValue::String(uri)) => uri_path(uri),
+                    _ => field(p, "rootPath").filter(|p| !matches!(p, SendValue::Null)).map(|p| PathBuf::from(p.to_string_val())),
+                });
+                if let Some(root) = root {
+                    match SymbolIndex::open(&root) {
+                        Ok(index) => {
+                            self.index = Some(index);
+                            self.workspace = true;
+                        }
+                        Err(e) => eprintln!("cryo lsp: indexing {}: {}", root.display(), e),
+                    }
+                }
+                Ok(format!(
+                    concat!(
+                        "{{\"capabilities\":{{\"textDocumentSync\":{{\"openClose\":true,\"change\":2}},",
+                        "\"documentSymbolProvider\":true,\"definitionProvider\":true,\"referencesProvider\":true,",
+                        "\"renameProvider\":{{\"prepareProvider\":true}},",
+                        "\"codeActionProvider\":{{\"codeActionKinds\":[\"quickfix\",\"refactor.extract\"]}},",
+                        "\"semanticTokensProvider\":{{\"legend\":{{\"tokenTypes\":[{}],\"tokenModifiers\":[{}]}},\"full\":true,\"range\":true}}}},",
+                        "\"serverInfo\":{{\"name\":\"cryo\",\"version\":\"{}\"}}}}"
+                    ),
+                    TokenType::ALL.iter().map(|t| json::quote(t.name())).collect::<Vec<_>>().join(","),
+                    semantic::MODIFIERS.iter().map(|m| json::quote(m)).collect::<Vec<_>>().join(","),
+                    env!("CARGO_PKG_VERSION")
+                ))
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                if let Some(Err(e)) = self.index.as_ref().filter(|_| self.workspace).map(|index| index.save()) {
+                    eprintln!("cryo lsp: {}", e);
+                }
+                Ok("null".to_string())
+            }
+            "textDocument/documentSymbol" => {
+                let uri = document_uri(params).ok_or((INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+                let Some(doc) = self.engine.document(&uri) else {
+                    return Ok("[]".to_string());
+                };
+                Ok(format!("[{}]", doc.symbols().iter().map(symbol_json).collect::<Vec<_>>().join(",")))
+            }
+            "textDocument/semanticTokens/full" | "textDocument/semanticTokens/range" => {
+                let uri = document_uri(params).ok_or((INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+                let file = self.indexed(&uri);
+                let Some(doc) = self.engine.document(&uri) else {
+                    return Ok("{\"data\":[]}".to_string());
+                };
+                let index = self.index.as_ref().zip(file.as_deref());
+                let mut tokens = semantic::tokens(doc, index);
+                if method == "textDocument/semanticTokens/range" {
+                    let range = params.and_then(|p| field(p, "range")).and_then(range_from).ok_or((INVALID_PARAMS, "missing range".to_string()))?;
+                    tokens.retain(|t| range.start <= t.start && t.start < range.end);
+                }
+                let data: Vec<String> = semantic::encode(&tokens).iter().map(|n| n.to_string()).collect();
+                Ok(format!("{{\"data\":[{}]}}", data.join(",")))
+            }
+            "textDocument/definition" | "textDocument/references" => {
+                let uri = document_uri(params).ok_or((INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+                let pos = params.and_then(|p| field(p, "position")).and_then(position_from).ok_or((INVALID_PARAMS, "missing position".to_string()))?;
+                let Some(file) = self.indexed(&uri) else {
+                    return Ok("[]".to_string());
+                };
+                let Some(index) = &self.index else {
+                    return Ok("[]".to_string());
+                };
+                let locations = if method == "textDocument/definition" {
+                    index.definition(&file, pos)
+                } else {
+                    let context = params.and_then(|p| field(p, "context")).and_then(|c| field(c, "includeDeclaration"));
+                    index.references(&file, pos, matches!(context, Some(SendValue::Bool(true))))
+                };
+                Ok(format!("[{}]", locations.iter().map(|l| location_json(index.root(), l)).collect::<Vec<_>>().join(",")))
+            }
+            "textDocument/prepareRename" | "textDocument/rename" | "textDocument/codeAction" => {
+                let uri = document_uri(params).ok_or((INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+                let position = params.and_then(|p| field(p, "position")).and_then(position_from);
+                let (Some(file), Some(params)) = (self.indexed(&uri), params) else {
+                    return Ok("null".to_string());
+                };
+                let Some(index) = &self.index else {
+                    return Ok("null".to_string());
+                };
+                let engine = &self.engine;
+                // Open documents as the editor has them, the rest from disk
+                let source = |key: &str| {
+                    let open = engine.documents().find(|(uri, _)| uri_path(uri).is_some_and(|p| index.key(&p) == key));
+                    open.map(|(_, doc)| doc.text().to_string()).or_else(|| std::fs::read_to_string(index.path(key)).ok())
+                };
+                match method {
+                    "textDocument/codeAction" => {
+                        let range = field(params, "range").and_then(range_from).ok_or((INVALID_PARAMS, "missing range".to_string()))?;
+                        let actions: Vec<String> = refactor::code_actions(index, &file, range, &source)
+                            .iter()
+                            .map(|a| format!("{{\"title\":{},\"kind\":\"{}\",\"edit\":{}}}", json::quote(&a.title), a.kind, workspace_edit_json(index.root(), &a.edits)))
+                            .collect();
+                        Ok(format!("[{}]", actions.join(",")))
+                    }
+                    "textDocument/prepareRename" => {
+                        let pos = position.ok_or((INVALID_PARAMS, "missing position".to_string()))?;
+                        refactor::prepare_rename(index, &file, pos, &source).map(range_json).map_err(|e| (REQUEST_FAILED, e))
+                    }
+                    _ => {
+                        let pos = position.ok_or((INVALID_PARAMS, "missing position".to_string()))?;
+                        let new_name = field(params, "newName").map(|n| n.to_string_val()).ok_or((INVALID_PARAMS, "missing newName".to_string()))?;
+                        let edits = refactor::rename(index, &file, pos, &new_name, &source).map_err(|e| (REQUEST_FAILED, e))?;
+                        Ok(workspace_edit_json(index.root(), &edits))
+                    }
+                }
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method {}", method))),
+        }
+    }
+
+    /// Index key of `uri`, with every open document indexed as it is in
+    /// the editor. Without a workspace root, only open documents are: the
+    /// file's directory could be anything up to `/`.
+    fn indexed(&mut self, uri: &str) -> Option<String> {
+        let path = uri_path(uri)?;
+        let index = self.index.get_or_insert_with(|| SymbolIndex::new(path.parent().unwrap_or(Path::new("."))));
+        for (open, doc) in self.engine.documents() {
+            if let Some(open) = uri_path(open) {
+                index.update(&index.key(&open), doc);
+            }
+        }
+        Some(index.key(&path))
+    }
+
+    /// Handle a notification; returns the notifications to send back
+    fn notify(&mut self, method: &str, params: Option<&SendValue>) -> Vec<String> {
+        let Some(uri) = document_uri(params) else {
+            return Vec::new();
+        };
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.and_then(|p| field(p, "textDocument")).and_then(|d| field(d, "text"));
+                self.engine.open(&uri, &text.map(|t| t.to_string_val()).unwrap_or_default());
+            }
+            "textDocument/didChange" => {
+                let changes: Vec<Change> = match params.and_then(|p| field(p, "contentChanges")) {
+                    Some(SendValue::Array(changes)) => changes
+                        .iter()
+                        .map(|c| Change {
+                            range: field(c, "range").and_then(range_from),
+                            text: field(c, "text").map(|t| t.to_string_val()).unwrap_or_default(),
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                if let Err(e) = self.engine.change(&uri, &changes) {
+                    eprintln!("cryo lsp: {}", e);
+                }
+            }
+            "textDocument/didClose" => {
+                self.engine.close(&uri);
+                match (&mut self.index, uri_path(&uri)) {
+                    // Back to what's saved on disk
+                    (Some(index), _) if self.workspace => {
+                        if let Err(e) = index.refresh() {
+                            eprintln!("cryo lsp: {}", e);
+                        }
+                    }
+                    (Some(index), Some(path)) => {
+                        let key = index.key(&path);
+                        index.remove(&key);
+                    }
+                    _ => {}
+                }
+                return vec![publish_diagnostics(&uri, "")];
+            }
+            _ => return Vec::new(),
+        }
+        let Some(doc) = self.engine.document(&uri) else {
+            return Vec::new();
+        };
+        let mut diagnostics: Vec<String> = doc
+            .diagnostics()
+            .iter()
+            .map(|d| {
+                let code = diagnostics::code_for(&d.message).map(|c| format!(",\"code\":{}", json::quote(c))).unwrap_or_default();
+                format!("{{\"range\":{},\"severity\":1{},\"source\":\"cryo\",\"message\":{}}}", range_json(d.range), code, json::quote(&d.message))
+            })
+            .collect();
+        let path = uri_path(&uri);
+        let config = match path.as_deref().and_then(Path::parent).map(lints::LintConfig::find) {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                eprintln!("cryo lsp: {}", e);
+                lints::LintConfig::default()
+            }
+            None => lints::LintConfig::default(),
+        };
+        let lints = match (path, &self.index) {
+            (Some(path), Some(index)) => {
+                let key = index.key(&path);
+                lints::check(doc, &config, &|import| {
+                    let module = index.resolve_import(&key, import)?;
+                    let files = index.visible(&module);
+                    let definitions = files.iter().filter_map(|f| index.file(f)).flat_map(|f| &f.definitions);
+                    Some(definitions.filter(|d| d.container.is_none()).map(|d| d.name.clone()).collect())
+                })
+            }
+            (Some(path), None) => lints::check_file(&path, doc, &config),
+            (None, _) => lints::check(doc, &config, &|_| None),
+        };
+        diagnostics.extend(lints.iter().map(|l| {
+            let severity = if l.level == lints::Level::Deny { 1 } else { 2 };
+            format!("{{\"range\":{},\"severity\":{},\"code\":{},\"source\":\"cryo\",\"message\":{}}}", range_json(l.range), severity, json::quote(l.name), json::quote(&l.message))
+        }));
+        vec![publish_diagnostics(&uri, &diagnostics.join(","))]
+    }
+}
+
+/// Next message body, or None at end of input
+fn read_message(input: &mut impl BufRead) -> Result<Option<String>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse::<usize>().map_err(|_| format!("bad Content-Length: {}", value.trim()))?);
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body).map_err(|e| e.to_string())?;
+    String::from_utf8(body).map(Some).map_err(|_| "message is not UTF-8".to_string())
+}
+
+fn write_message(output: &mut impl Write, body: &str) -> Result<(), String> {
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body).and_then(|_| output.flush()).map_err(|e| e.to_string())
+}
+
+fn field<'a>(value: &'a SendValue, name: &str) -> Option<&'a SendValue> {
+    match value {
+        SendValue::Struct(_, fields) => fields.get(name),
+        _ => None,
+    }
+}
+
+fn document_uri(params: Option<&SendValue>) -> Option<String> {
+    let uri = field(field(params?, "textDocument")?, "uri")?;
+    Some(uri.to_string_val())
+}
+
+fn position_from(value: &SendValue) -> Option<Position> {
+    let number = |name| match field(value, name) {
+        Some(SendValue::Int(n)) if *n >= 0 => Some(*n as usize),
+        _ => None,
+    };
+    Some(Position { line: number("line")?, character: number("character")? })
+}
+
+fn range_from(value: &SendValue) -> Option<Range> {
+    Some(Range { start: position_from(field(value, "start")?)?, end: position_from(field(value, "end")?)? })
+}
+
+fn range_json(range: Range) -> String {
+    format!(
+        "{{\"start\":{{\"line\":{},\"character\":{}}},\"end\":{{\"line\":{},\"character\":{}}}}}",
+        range.start.line, range.start.character, range.end.line, range.end.character
+    )
+}
+
+/// The protocol's SymbolKind number
+fn kind_number(kind: SymbolKind) -> u32 {
+    match kind {
+        SymbolKind::Import => 2,
+        SymbolKind::Impl => 5,
+        SymbolKind::Method => 6,
+        SymbolKind::Field => 8,
+        SymbolKind::Enum => 10,
+        SymbolKind::Trait => 11,
+        SymbolKind::Function | SymbolKind::Macro => 12,
+        SymbolKind::Global => 13,
+        SymbolKind::Variant => 22,
+        SymbolKind::Struct => 23,
+    }
+}
+
+fn symbol_json(symbol: &DocumentSymbol) -> String {
+    format!(
+        "{{\"name\":{},\"kind\":{},\"range\":{},\"selectionRange\":{},\"children\":[{}]}}",
+        json::quote(&symbol.name),
+        kind_number(symbol.kind),
+        range_json(symbol.range),
+        range_json(symbol.selection),
+        symbol.children.iter().map(symbol_json).collect::<Vec<_>>().join(",")
+    )
+}
+
+/// The file path of a `file://` URI
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/// The `file://` URI of a path, percent-encoding what isn't safe in one
+fn path_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn location_json(root: &Path, location: &Location) -> String {
+    format!("{{\"uri\":{},\"range\":{}}}", json::quote(&path_uri(&root.join(&location.file))), range_json(location.range))
+}
+
+/// A WorkspaceEdit of `changes` by file URI
+fn workspace_edit_json(root: &Path, edits: &FileEdits) -> String {
+    let changes: Vec<String> = edits
+        .iter()
+        .map(|(file, edits)| {
+            let edits: Vec<String> = edits.iter().map(|e| format!("{{\"range\":{},\"newText\":{}}}", range_json(e.range), json::quote(&e.new_text))).collect();
+            format!("{}:[{}]", json::quote(&path_uri(&root.join(file))), edits.join(","))
+        })
+        .collect();
+    format!("{{\"changes\":{{{}}}}}", changes.join(","))
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: &str) -> String {
+    format!(
+        "{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/publishDiagnostics\",\"params\":{{\"uri\":{},\"diagnostics\":[{}]}}}}",
+        json::quote(uri),
+        diagnostics
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    /// Bodies of every message written to `output`
+    fn bodies(output: &[u8]) -> Vec<String> {
+        let mut input = output;
+        let mut found = Vec::new();
+        while let Some(body) = read_message(&mut input).unwrap() {
+            found.push(body);
+        }
+        found
+    }
+
+    #[test]
+    fn test_session() {
+        // No workspace root: only the open document is indexed, not whatever
+        // else is in its directory, and no cache is written there
+        let dir = std::env::temp_dir().join(format!("cryo lsp session {}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("other.cryo"), "fn other() {}\n").unwrap();
+        let uri = &path_uri(&dir.join("a.cryo"));
+        let input = [
+            frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#),
+            frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+            frame(&format!(
+                r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{}","languageId":"cryo","version":1,"text":"fn main() {{\n    print(1)\n}}\n"}}}}}}"#,
+                uri
+            )),
+            frame(&format!(
+                r#"{{"jsonrpc":"2.0","method":"textDocument/didChange","params":{{"textDocument":{{"uri":"{}","version":2}},"contentChanges":[{{"range":{{"start":{{"line":1,"character":12}},"end":{{"line":1,"character":12}}}},"text":";"}}]}}}}"#,
+                uri
+            )),
+            frame(&format!(r#"{{"jsonrpc":"2.0","id":"sym","method":"textDocument/documentSymbol","params":{{"textDocument":{{"uri":"{}"}}}}}}"#, uri)),
+            frame(&format!(r#"{{"jsonrpc":"2.0","id":5,"method":"textDocument/semanticTokens/full","params":{{"textDocument":{{"uri":"{}"}}}}}}"#, uri)),
+            frame(r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{}}"#),
+            frame(r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#),
+            frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+        ]
+        .concat();
+        let mut output = Vec::new();
+        assert!(serve(&mut input.as_bytes(), &mut output).unwrap());
+        let replies = bodies(&output);
+        assert_eq!(replies.len(), 7);
+        assert!(replies[0].starts_with(r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"textDocumentSync":{"openClose":true,"change":2}"#));
+        assert!(replies[0].contains(r#""semanticTokensProvider":{"legend":{"tokenTypes":["keyword","number","#));
+        // `print(1)` without a semicolon is an error until the edit adds one
+        assert!(replies[1].contains(r#""range":{"start":{"line":2,"character":0},"end":{"line":2,"character":1}},"severity":1"#), "{}", replies[1]);
+        assert!(replies[2].ends_with(r#""diagnostics":[]}}"#));
+        assert_eq!(
+            replies[3],
+            concat!(
+                r#"{"jsonrpc":"2.0","id":"sym","result":[{"name":"main","kind":12,"#,
+                r#""range":{"start":{"line":0,"character":0},"end":{"line":2,"character":1}},"#,
+                r#""selectionRange":{"start":{"line":0,"character":3},"end":{"line":0,"character":7}},"children":[]}]}"#
+            )
+        );
+        // fn, main (declared), print (builtin), 1
+        assert_eq!(replies[4], r#"{"jsonrpc":"2.0","id":5,"result":{"data":[0,0,2,0,0,0,3,4,4,1,1,4,5,4,8,0,6,1,1,0]}}"#);
+        assert!(replies[5].contains(r#""error":{"code":-32601"#));
+        assert_eq!(replies[6], r#"{"jsonrpc":"2.0","id":4,"result":null}"#);
+        assert!(!dir.join(crate::symbols::CACHE_FILE).exists());
+
+        let mut server = Server { engine: CryoEngine::new(), index: None, workspace: false, shut_down: false };
+        server.engine.open(uri, "fn main() {}\n");
+        assert_eq!(server.indexed(uri).as_deref(), Some("a.cryo"));
+        let indexed: Vec<&str> = server.index.as_ref().unwrap().files().map(|(key, _)| key).collect();
+        assert_eq!(indexed, ["a.cryo"]);
+        server.notify("textDocument/didClose", Some(&json::parse(&format!(r#"{{"textDocument":{{"uri":{}}}}}"#, json::quote(uri))).unwrap()));
+        assert_eq!(server.index.as_ref().unwrap().files().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_navigation() {
+        let root = std::env::temp_dir().join(format!("cryo lsp {}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("util.cryo"), "fn helper() {\n    return 1;\n}\n").unwrap();
+        let util = path_uri(&root.join("util.cryo"));
+        let main = path_uri(&root.join("main.cryo"));
+        assert!(main.contains("cryo%20lsp"));
+        let position = |id: i64, method: &str, line: usize, character: usize| {
+            frame(&format!(
+                r#"{{"jsonrpc":"2.0","id":{},"method":"textDocument/{}","params":{{"textDocument":{{"uri":"{}"}},"position":{{"line":{},"character":{}}},"context":{{"includeDeclaration":true}}}}}}"#,
+                id, method, main, line, character
+            ))
+        };
+        let input = [
+            frame(&format!(r#"{{"jsonrpc":"2.0","id":1,"method":"initialize","params":{{"rootUri":{}}}}}"#, json::quote(&path_uri(&root)))),
+            // Never saved: the index sees the editor's text
+            frame(&format!(
+                r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{}","languageId":"cryo","version":1,"text":"import \"util\"\nfn main() {{\n    print(helper());\n}}\n"}}}}}}"#,
+                main
+            )),
+            position(2, "definition", 2, 12),
+            position(3, "references", 2, 12),
+            frame(&format!(
+                r#"{{"jsonrpc":"2.0","id":5,"method":"textDocument/rename","params":{{"textDocument":{{"uri":"{}"}},"position":{{"line":2,"character":12}},"newName":"assist"}}}}"#,
+                main
+            )),
+            frame(r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#),
+        ]
+        .concat();
+        let mut output = Vec::new();
+        serve(&mut input.as_bytes(), &mut output).unwrap();
+        let replies = bodies(&output);
+        assert!(replies[0].contains(r#""definitionProvider":true,"referencesProvider":true"#));
+        let util_range = r#""range":{"start":{"line":0,"character":3},"end":{"line":0,"character":9}}"#;
+        assert_eq!(replies[2], format!(r#"{{"jsonrpc":"2.0","id":2,"result":[{{"uri":{},{}}}]}}"#, json::quote(&util), util_range));
+        assert_eq!(
+            replies[3],
+            format!(
+                r#"{{"jsonrpc":"2.0","id":3,"result":[{{"uri":{},"range":{{"start":{{"line":2,"character":10}},"end":{{"line":2,"character":16}}}}}},{{"uri":{},{}}}]}}"#,
+                json::quote(&main),
+                json::quote(&util),
+                util_range
+            )
+        );
+        assert_eq!(
+            replies[4],
+            format!(
+                r#"{{"jsonrpc":"2.0","id":5,"result":{{"changes":{{{}:[{{"range":{{"start":{{"line":2,"character":10}},"end":{{"line":2,"character":16}}}},"newText":"assist"}}],{}:[{{{},"newText":"assist"}}]}}}}}}"#,
+                json::quote(&main),
+                json::quote(&util),
+                util_range
+            )
+        );
+        assert!(root.join(crate::symbols::CACHE_FILE).exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -2,49 +2,202 @@
 // High-performance self-hosted systems programming language
 // Default mode: Native compilation via LLVM for maximum performance
 
-mod lexer;
-mod parser;
-mod interpreter;
-mod codegen;
-mod optimizer;
-mod expander;
-mod bytecode_vm;
-mod fast_vm;
-mod ffi;
-mod gc;
-mod native_compiler;
-mod threading;
-mod jit;
-
+use cryo::platform::{NativePlatform, Platform};
+use cryo::trace::{RecordingPlatform, ReplayPlatform};
+use cryo::vfs::{Vfs, VfsPlatform};
+use cryo::{ast_display, bench, bundle, bytecode_file, bytecode_vm, codegen, config, conformance, crash, diagnostics, engine, expander, fast_vm, fuzz, interpreter, lexer, lints, lsp, optimizer, parser, refactor, selftest, symbols, timings};
 use std::env;
 use std::fs;
 use std::process;
+use std::sync::Arc;
 
 fn main() {
+    let config = config::RuntimeConfig::from_env();
+    let child = std::thread::Builder::new()
+        .name("cryo-main".to_string())
+        .stack_size(config.stack_size)
+        .spawn(move || run_cli(config))
+        .expect("failed to spawn main thread");
+    if child.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run_cli(config: config::RuntimeConfig) {
     let args: Vec<String> = env::args().collect();
+    if let Some(level) = config.log {
+        config::set_log_level(level);
+    }
+    config::log(config::LogLevel::Debug, &format!("runtime config: {:?}", config));
+
+    // A bundled executable runs its own program; every argument belongs to it
+    if let Some(bundle) = bundle::read_embedded() {
+        match bundle {
+            Ok(bundle) => run_bundled(&config, bundle, args),
+            Err(e) => {
+                eprintln!("Error loading bundled program: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("bundle") {
+        bundle_command(&config, &args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("build") {
+        build_command(&config, &args[2..]);
+        return;
+    }
+
+    // `cryo --compile FILE -o OUT` is `cryo build FILE -o OUT`, always to an
+    // executable. Unlike build, the output is never guessed from FILE.
+    if args.get(1).map(|s| s.as_str()) == Some("--compile") {
+        if !args[2..].windows(2).any(|w| matches!(w[0].as_str(), "-o" | "--output")) {
+            eprintln!("cryo --compile: missing -o OUT");
+            eprintln!("USAGE: cryo --compile FILE -o OUT [-g] [--checked] [--lld] [--verbose] [--timings]");
+            process::exit(2);
+        }
+        let build_args: Vec<String> = std::iter::once("--emit=exe".to_string()).chain(args[2..].iter().cloned()).collect();
+        build_command(&config, &build_args);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("explain") {
+        explain_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("fuzz") {
+        fuzz_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("lint") {
+        lint_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("lsp") {
+        lsp_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("optimize") {
+        optimize_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("parse") {
+        parse_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("refactor") {
+        refactor_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("selftest") {
+        selftest_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("symbols") {
+        symbols_command(&args[2..]);
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("test-lang") {
+        test_lang_command(&args[2..]);
+        return;
+    }
 
     if args.len() < 2 {
         println!("Cryo v4.0.0 - High-Performance Systems Language");
-        println!("USAGE: cryo [OPTIONS] [FILE]");
+        println!("USAGE: cryo [OPTIONS] [FILE | -] [ARGS...]");
         println!("OPTIONS:");
         println!("    -h, --help          Print help");
         println!("    -v, --version       Print version");
         println!("    --native            Run with native compilation (default)");
         println!("    --interpret         Run with tree-walking interpreter");
+        println!("    --value-semantics   Copy arrays/structs on write instead of sharing them");
+        println!("    --bigint-overflow   Promote overflowing int arithmetic to bigint");
+        println!("    --lenient-index     Out-of-range [i] reads give null or \"\" instead of an error");
+        println!("    --tier-bytecode     Move hot int-only functions into the bytecode VM");
+        println!("    --bytecode          Run every function the bytecode VM can compile in the VM");
+        println!("    --jit               Compile hot bytecode functions to native code (implies --tier-bytecode)");
+        println!("    --compile FILE -o OUT  Compile FILE to a native executable (`cryo build FILE -o OUT`)");
         println!("    --emit-llvm FILE    Compile & emit LLVM IR");
+        println!("    --run               With --emit-llvm: execute the IR with lli");
+        println!("    -g, --debug-info    With --emit-llvm: add DWARF line info for gdb/lldb");
+        println!("    --checked           With --emit-llvm: panic on overflow and division by zero (needs the runtime)");
+        println!("    --max-heap SIZE     Stop the interpreter once its values take more than SIZE (e.g. 512M)");
+        println!("    --mem-stats         Report peak and final heap usage on stderr at exit");
+        println!("    --history N         Keep each call's last N assignments for debugger()'s `history x`");
+        println!("    --vfs FILE          Run the interpreter on an in-memory filesystem loaded from a JSON snapshot");
+        println!("    --record FILE       Log every file, socket, clock, env and random call with its result to FILE");
+        println!("    --replay FILE       Answer those calls from a --record trace instead of the system");
+        println!("    --timings           Report time and memory per pipeline stage and module on stderr");
         println!("    --vm-bench N        Run fibonacci(N) via bytecode VM");
+        println!("    --vm-profile        With --vm-bench: report per-opcode/function counts and hot offsets");
         println!("    --native-bench N    Run fibonacci(N) as native Rust (40ms for N=35)");
+        println!("    --parse-bench N|FILE  Lex & parse FILE, or a generated N-line program");
+        println!("    --interp-bench N    Run fib/string/struct workloads of size N in the interpreter");
+        println!("COMMANDS:");
+        println!("    bundle FILE [-o OUT]  Pack FILE, its imports and the runtime into one executable");
+        println!("    build FILE [--emit=ir|asm|obj|exe|arb] [-o OUT] [-g] [--checked] [--lld] [--verbose] [--timings]");
+        println!("                          Compile FILE ahead of time through LLVM (default: exe), or with");
+        println!("                          --emit=arb (or -o OUT.arb) to bytecode that `cryo OUT.arb` runs");
+        println!("    explain [CODE]        Describe an error code such as E0102, or list them all");
+        println!("    fuzz [--target lex|parse|run] [-n N] [--seed S] [--corpus DIR] [-o DIR] [FILE...]");
+        println!("                          Fuzz the lexer/parser/interpreter, or replay crash FILEs");
+        println!("    lint FILE... [--deny-warnings]");
+        println!("                          Warn about unused names, unreachable code and suspicious conditions");
+        println!("    lsp                   Language server on stdin/stdout (incremental parsing)");
+        println!("    optimize FILE [--explain]");
+        println!("                          Show how the optimizer rewrites FILE, or with --explain which rules fired where");
+        println!("    parse FILE [--json]   Print the syntax tree FILE parses to");
+        println!("    refactor rename FILE:LINE:COL NEW_NAME | extract FILE:LINE:COL-LINE:COL NAME");
+        println!("    refactor actions FILE:LINE:COL[-LINE:COL] [--apply N]  [--root DIR] [--dry-run]");
+        println!("                          Rename a symbol, extract statements into a function, or apply a code action");
+        println!("    selftest FILE... [--engines LIST] [--timeout SECS] [-- ARGS]");
+        println!("                          Run FILE on every engine and report where output differs");
+        println!("    symbols [NAME|FILE:LINE:COL] [--refs] [--kind KIND] [--root DIR] [--json]");
+        println!("                          Find definitions (or with --refs, uses) in the project's symbol index");
+        println!("    test-lang [DIR|FILE|PATTERN...] [--engine NAME] [-j N] [--bless]");
+        println!("                          Check programs against NAME.expected / NAME.error (default: tests/lang)");
         return;
     }
 
     let mut emit_llvm = false;
+    let mut run_ir = false;
+    let mut debug_info = false;
+    let mut checked = false;
     let mut llvm_output = String::new();
     let mut source_file = String::new();
     let mut program_args: Vec<String> = Vec::new();
     let mut found_source = false;
     let mut vm_bench: Option<i64> = None;
+    let mut vm_profile = false;
     let mut native_bench: Option<i64> = None;
+    let mut parse_bench: Option<String> = None;
+    let mut interp_bench: Option<i64> = None;
     let mut use_interpreter = false;  // Default: native mode
+    let mut value_semantics = false;
+    let mut bigint_overflow = false;
+    let mut lenient_index = false;
+    let mut tier_bytecode = false;
+    let mut bytecode_all = false;
+    let mut jit = false;
+    let mut max_heap: Option<usize> = None;
+    let mut mem_stats = false;
+    let mut history: Option<usize> = None;
+    let mut vfs: Option<String> = None;
+    let mut record: Option<String> = None;
+    let mut replay: Option<String> = None;
 
     let mut i = 1;
     while i < args.len() {
@@ -68,25 +221,108 @@ fn main() {
                 "--native" => {
                     use_interpreter = false;
                 }
+                "--value-semantics" => {
+                    value_semantics = true;
+                }
+                "--bigint-overflow" => {
+                    bigint_overflow = true;
+                }
+                "--lenient-index" => {
+                    lenient_index = true;
+                }
+                "--tier-bytecode" => {
+                    tier_bytecode = true;
+                }
+                "--bytecode" => {
+                    bytecode_all = true;
+                }
+                "--jit" => {
+                    jit = true;
+                }
                 "--emit-llvm" => {
                     emit_llvm = true;
-                    if i + 1 < args.len() {
+                    if i + 1 < args.len() && !args[i + 1].starts_with('-') {
                         llvm_output = args[i + 1].clone();
                         i += 1;
                     }
                 }
+                "--run" => {
+                    run_ir = true;
+                }
+                "-g" | "--debug-info" => {
+                    debug_info = true;
+                }
+                "--checked" => {
+                    checked = true;
+                }
+                "--timings" => {
+                    timings::enable();
+                }
+                "--mem-stats" => {
+                    mem_stats = true;
+                }
+                "--history" => {
+                    history = args.get(i + 1).and_then(|v| v.parse().ok()).filter(|&n| n > 0);
+                    if history.is_none() {
+                        eprintln!("cryo: --history needs a number of assignments, like 50");
+                        process::exit(2);
+                    }
+                    i += 1;
+                }
+                "--max-heap" => {
+                    max_heap = args.get(i + 1).and_then(|v| config::parse_size(v)).filter(|&n| n > 0);
+                    if max_heap.is_none() {
+                        eprintln!("cryo: --max-heap needs a size, like 512M");
+                        process::exit(2);
+                    }
+                    i += 1;
+                }
+                "--vfs" | "--record" | "--replay" => {
+                    let Some(path) = args.get(i + 1) else {
+                        eprintln!("cryo: {} needs a file", args[i]);
+                        process::exit(2);
+                    };
+                    let slot = match args[i].as_str() {
+                        "--vfs" => &mut vfs,
+                        "--record" => &mut record,
+                        _ => &mut replay,
+                    };
+                    *slot = Some(path.clone());
+                    i += 1;
+                }
                 "--vm-bench" => {
                     if i + 1 < args.len() {
                         vm_bench = args[i + 1].parse().ok();
                         i += 1;
                     }
                 }
+                "--vm-profile" => {
+                    vm_profile = true;
+                }
                 "--native-bench" => {
                     if i + 1 < args.len() {
                         native_bench = args[i + 1].parse().ok();
                         i += 1;
                     }
                 }
+                "--parse-bench" => {
+                    if i + 1 < args.len() {
+                        parse_bench = Some(args[i + 1].clone());
+                        i += 1;
+                    }
+                }
+                "--interp-bench" => {
+                    if i + 1 < args.len() {
+                        interp_bench = args[i + 1].parse().ok();
+                        i += 1;
+                    }
+                }
+                // Anything after the script belongs to the script; an unknown option
+                // before it is a typo, not a file name
+                opt if opt.starts_with('-') && opt.len() > 1 => {
+                    eprintln!("cryo: unknown option '{}' (see cryo --help)", opt);
+                    process::exit(2);
+                }
                 _ => {
                     source_file = args[i].clone();
                     found_source = true;
@@ -97,6 +333,14 @@ fn main() {
         i += 1;
     }
 
+    let (platform, replayer) = match platform_from_args(vfs, record, replay) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("cryo: {}", e);
+            process::exit(1);
+        }
+    };
+
     // Native benchmark mode - shows target performance
     if let Some(n) = native_bench {
         println!("Cryo Native: Running Fib({})...", n);
@@ -106,6 +350,48 @@ fn main() {
         return;
     }
 
+    // Frontend benchmark: lex + parse a large (generated) file
+    if let Some(target) = parse_bench {
+        let source = match target.parse::<usize>() {
+            Ok(lines) => bench::generate_source(lines),
+            Err(_) => match fs::read_to_string(&target) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Error reading '{}': {}", target, e);
+                    process::exit(1);
+                }
+            },
+        };
+        match bench::run_parse_bench(&source) {
+            Ok(r) => {
+                println!("Cryo Parse: {} lines, {} bytes, {} tokens, {} items", r.lines, r.bytes, r.tokens, r.items);
+                println!("Cryo Parse: Lex = {}ms", r.lex_time.as_millis());
+                println!("Cryo Parse: Parse = {}ms", r.parse_time.as_millis());
+            }
+            Err(e) => {
+                report_parse_error(&e, None);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Interpreter benchmark: call-, string- and struct-heavy workloads
+    if let Some(n) = interp_bench {
+        match bench::run_interp_bench(n) {
+            Ok(results) => {
+                for (name, result, elapsed) in results {
+                    println!("Cryo Interp: {:<8} = {:<12} {}ms", name, result, elapsed.as_millis());
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
     // Fast path: bytecode VM benchmark mode
     if let Some(n) = vm_bench {
         println!("Cryo VM: Running Fib({})...", n);
@@ -113,6 +399,9 @@ fn main() {
         
         let mut vm = bytecode_vm::BytecodeVM::new();
         vm.add_function(bytecode_vm::compile_fib());
+        if vm_profile {
+            vm.enable_profiling();
+        }
         let result = vm.call("fib", vec![bytecode_vm::VMValue::Int(n)]);
         
         let elapsed = start.elapsed();
@@ -121,6 +410,19 @@ fn main() {
             _ => println!("Cryo VM: Result = {:?}", result),
         }
         println!("Cryo VM: Time = {}ms", elapsed.as_millis());
+        
+        if vm_profile {
+            print!("{}", vm.profile_report(10));
+            // Feed the profile into the JIT's tiering heuristics
+            #[cfg(feature = "jit")]
+            if let Ok(mut jit) = cryo::jit::JitCompiler::new() {
+                if let Some(enabled) = config.jit {
+                    jit.set_enabled(enabled);
+                }
+                jit.seed_call_counts(&vm.profiled_call_counts());
+                println!("JIT candidates: {:?}", jit.get_hot_functions());
+            }
+        }
         return;
     }
 
@@ -128,8 +430,52 @@ fn main() {
         eprintln!("Error: No source file specified");
         process::exit(1);
     }
+    // CRYO_JIT=on/off overrides --jit
+    let jit = config.jit.unwrap_or(jit);
 
-    let source = match fs::read_to_string(&source_file) {
+    // Precompiled bytecode from `cryo build --emit=arb` skips the front end
+    if is_bytecode_path(&source_file) {
+        let funcs = match fs::read(&source_file).map_err(|e| e.to_string()).and_then(|data| bytecode_file::decode(&data)) {
+            Ok(funcs) => funcs,
+            Err(e) => {
+                eprintln!("Error loading '{}': {}", source_file, e);
+                process::exit(1);
+            }
+        };
+        let mut interp = interpreter::Interpreter::new();
+        interp.apply_config(&config);
+        interp.set_base_path(&source_file);
+        interp.set_args(program_args);
+        interp.set_bigint_overflow(bigint_overflow);
+        interp.set_jit(jit);
+        if max_heap.is_some() {
+            interp.set_max_heap(max_heap);
+        }
+        interp.set_mem_stats(mem_stats);
+        if let Some(platform) = &platform {
+            interp.set_platform(platform.clone());
+        }
+        let result = timings::time(&source_file, "execute", || interp.run_bytecode(funcs));
+        if let Some(report) = interp.memory_report() {
+            eprint!("{}", report);
+        }
+        report_replay(replayer.as_deref());
+        timings::finish();
+        if let Err(e) = result {
+            eprintln!("Runtime error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // `cryo -` reads the program from stdin
+    let read_source = if source_file == "-" {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text).map(|_| text)
+    } else {
+        fs::read_to_string(&source_file)
+    };
+    let source = match read_source {
         Ok(c) => c,
         Err(e) => {
             eprintln!("Error reading '{}': {}", source_file, e);
@@ -137,36 +483,68 @@ fn main() {
         }
     };
 
+    if config.crash_reports {
+        let bundle_dir = config.crash_dir.clone().map(std::path::PathBuf::from).unwrap_or_else(env::temp_dir);
+        crash::install(&source_file, &source, args.clone(), bundle_dir, config.crash_minimize);
+    }
+
     // Default: Native mode (compile & run)
     // Fallback: Interpreter mode (--interpret flag)
     if use_interpreter {
         // Tree-walking interpreter mode
-        let tokens = lexer::tokenize(&source);
-        let mut parser = parser::Parser::new(tokens);
+        let (tokens, lines, columns, spans) = timings::time(&source_file, "lex", || lexer::tokenize_with_positions(&source));
+        let mut parser = parser::Parser::with_lines(&tokens, &lines).with_columns(&columns).with_spans(&spans);
         
-        let ast = match parser.parse() {
+        let ast = match timings::time(&source_file, "parse", || parser.parse()) {
             Ok(ast) => ast,
             Err(e) => {
-                eprintln!("Parse error: {}", e);
+                report_parse_error(&e, Some((&source_file, &source, parser.position())));
+                timings::finish();
                 process::exit(1);
             }
         };
 
         // Macro Expansion Pass
         let mut expander = expander::Expander::new();
-        let expanded_ast = expander.expand(ast);
+        let expanded_ast = timings::time(&source_file, "expand", || expander.expand(ast));
 
         let optimizer = crate::optimizer::Optimizer::new();
-        let final_ast = optimizer.optimize(expanded_ast);
+        let final_ast = timings::time(&source_file, "optimize", || optimizer.optimize(expanded_ast));
 
-        let mut interp = interpreter::Interpreter::new();
-        interp.set_base_path(&source_file);
         if emit_llvm {
-            interp.set_emit_llvm(true, &llvm_output);
+            let debug_source = debug_info.then_some(source_file.as_str());
+            let checked_source = checked.then_some(source_file.as_str());
+            emit_llvm_ir(&final_ast, &source_file, debug_source, checked_source, &llvm_output, run_ir, &program_args);
+            return;
         }
+
+        let mut interp = interpreter::Interpreter::new();
+        interp.apply_config(&config);
+        interp.set_source(&source_file, &source);
+        interp.set_base_path(&source_file);
         interp.set_args(program_args);
+        interp.set_value_semantics(value_semantics);
+        interp.set_bigint_overflow(bigint_overflow);
+        interp.set_lenient_index(lenient_index);
+        interp.set_bytecode_tiering(tier_bytecode || jit);
+        interp.set_jit(jit);
+        interp.set_bytecode_all(bytecode_all);
+        interp.set_history(history);
+        if max_heap.is_some() {
+            interp.set_max_heap(max_heap);
+        }
+        interp.set_mem_stats(mem_stats);
+        if let Some(platform) = &platform {
+            interp.set_platform(platform.clone());
+        }
 
-        match interp.run(&final_ast) {
+        let result = timings::time(&source_file, "execute", || interp.run(&final_ast));
+        if let Some(report) = interp.memory_report() {
+            eprint!("{}", report);
+        }
+        report_replay(replayer.as_deref());
+        timings::finish();
+        match result {
             Ok(_) => {},
             Err(e) => {
                 eprintln!("Runtime error: {}", e);
@@ -178,52 +556,60 @@ fn main() {
         // For now, we use the optimized interpreter as the native backend
         // until full LLVM JIT integration is complete
         
-        let tokens = lexer::tokenize(&source);
-        let mut parser = parser::Parser::new(tokens);
+        let (tokens, lines, columns, spans) = timings::time(&source_file, "lex", || lexer::tokenize_with_positions(&source));
+        let mut parser = parser::Parser::with_lines(&tokens, &lines).with_columns(&columns).with_spans(&spans);
         
-        let ast = match parser.parse() {
+        let ast = match timings::time(&source_file, "parse", || parser.parse()) {
             Ok(ast) => ast,
             Err(e) => {
-                eprintln!("Parse error: {}", e);
+                report_parse_error(&e, Some((&source_file, &source, parser.position())));
+                timings::finish();
                 process::exit(1);
             }
         };
 
         // Macro Expansion Pass
         let mut expander = expander::Expander::new();
-        let expanded_ast = expander.expand(ast);
+        let expanded_ast = timings::time(&source_file, "expand", || expander.expand(ast));
 
         let optimizer = crate::optimizer::Optimizer::new();
-        let final_ast = optimizer.optimize(expanded_ast);
+        let final_ast = timings::time(&source_file, "optimize", || optimizer.optimize(expanded_ast));
 
-        // If emit_llvm is set, generate LLVM IR using native_compiler
         if emit_llvm {
-            match native_compiler::compile_to_llvm(&source) {
-                Ok(llvm_ir) => {
-                    if llvm_output.is_empty() {
-                        println!("{}", llvm_ir);
-                    } else {
-                        if let Err(e) = fs::write(&llvm_output, llvm_ir) {
-                            eprintln!("Error writing LLVM IR: {}", e);
-                            process::exit(1);
-                        }
-                        println!("LLVM IR written to: {}", llvm_output);
-                    }
-                    return;
-                }
-                Err(e) => {
-                    eprintln!("Native compilation error: {}", e);
-                    process::exit(1);
-                }
-            }
+            let debug_source = debug_info.then_some(source_file.as_str());
+            let checked_source = checked.then_some(source_file.as_str());
+            emit_llvm_ir(&final_ast, &source_file, debug_source, checked_source, &llvm_output, run_ir, &program_args);
+            return;
         }
 
         // Run with optimized interpreter (native-like performance)
         let mut interp = interpreter::Interpreter::new();
+        interp.apply_config(&config);
+        interp.set_source(&source_file, &source);
         interp.set_base_path(&source_file);
         interp.set_args(program_args);
+        interp.set_value_semantics(value_semantics);
+        interp.set_bigint_overflow(bigint_overflow);
+        interp.set_lenient_index(lenient_index);
+        interp.set_bytecode_tiering(tier_bytecode || jit);
+        interp.set_jit(jit);
+        interp.set_bytecode_all(bytecode_all);
+        interp.set_history(history);
+        if max_heap.is_some() {
+            interp.set_max_heap(max_heap);
+        }
+        interp.set_mem_stats(mem_stats);
+        if let Some(platform) = &platform {
+            interp.set_platform(platform.clone());
+        }
 
-        match interp.run(&final_ast) {
+        let result = timings::time(&source_file, "execute", || interp.run(&final_ast));
+        if let Some(report) = interp.memory_report() {
+            eprint!("{}", report);
+        }
+        report_replay(replayer.as_deref());
+        timings::finish();
+        match result {
             Ok(_) => {},
             Err(e) => {
                 eprintln!("Runtime error: {}", e);
@@ -232,3 +618,1059 @@ fn main() {
         }
     }
 }
+
+/// `platform_from_args`: the platform to install, and the replayer if any
+type ArgPlatform = (Option<Arc<dyn Platform>>, Option<Arc<ReplayPlatform>>);
+
+/// The platform for `--vfs`, `--record` and `--replay`, or None for the
+/// plain operating system. Replays also come back on their own so the
+/// caller can report how far they got.
+fn platform_from_args(vfs: Option<String>, record: Option<String>, replay: Option<String>) -> Result<ArgPlatform, String> {
+    let native: Arc<dyn Platform> = Arc::new(NativePlatform);
+    if let Some(path) = replay {
+        if vfs.is_some() || record.is_some() {
+            return Err("--replay answers every call from the trace; it can't be combined with --vfs or --record".to_string());
+        }
+        let replayer = Arc::new(ReplayPlatform::open(&path, native).map_err(|e| format!("--replay {}", e))?);
+        return Ok((Some(replayer.clone()), Some(replayer)));
+    }
+    let mut platform = None;
+    if let Some(path) = vfs {
+        let tree = Vfs::load(&path).map_err(|e| format!("--vfs {}", e))?;
+        platform = Some(Arc::new(VfsPlatform::new(tree, native.clone())) as Arc<dyn Platform>);
+    }
+    if let Some(path) = record {
+        let inner = platform.unwrap_or(native);
+        platform = Some(Arc::new(RecordingPlatform::create(&path, inner).map_err(|e| format!("--record {}", e))?));
+    }
+    Ok((platform, None))
+}
+
+/// Warn when a replayed program stopped before using the whole trace
+fn report_replay(replayer: Option<&ReplayPlatform>) {
+    if let Some((done, total)) = replayer.map(ReplayPlatform::progress).filter(|(done, total)| done < total) {
+        eprintln!("cryo: --replay: the program stopped after {} of the trace's {} calls", done, total);
+    }
+}
+
+/// `cryo bundle app.cryo -o app`
+/// `--emit-llvm [FILE] [--run]`: lower the final AST to IR, then print it,
+/// write it to FILE, or (with `--run`) execute it with lli
+fn emit_llvm_ir(
+    final_ast: &[parser::TopLevel],
+    source_file: &str,
+    debug_source: Option<&str>,
+    checked_source: Option<&str>,
+    output: &str,
+    run: bool,
+    program_args: &[String],
+) {
+    let ir = match timings::time(source_file, "codegen", || codegen::lower(final_ast, debug_source, checked_source)) {
+        Ok(ir) => ir,
+        Err(e) => {
+            eprintln!("{}", e);
+            timings::finish();
+            process::exit(1);
+        }
+    };
+    if output.is_empty() && !run {
+        println!("{}", ir);
+        timings::finish();
+        return;
+    }
+    let path = if output.is_empty() {
+        std::env::temp_dir().join(format!("cryo-{}.ll", process::id())).to_string_lossy().into_owned()
+    } else {
+        output.to_string()
+    };
+    if let Err(e) = codegen::write_module(&path, &ir) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    if !run {
+        println!("LLVM IR written to: {}", path);
+        timings::finish();
+        return;
+    }
+    // Checked code calls cryo_panic_at, which lives in the runtime
+    let runtime = match checked_source.map(|_| codegen::runtime_shared_library()) {
+        Some(None) => {
+            eprintln!("--checked --run needs libcryo_runtime.so next to cryo (cargo build --workspace)");
+            process::exit(1);
+        }
+        found => found.flatten(),
+    };
+    // program_args[0] is the script; lli passes the module path as argv[0]
+    let result = timings::time(source_file, "execute", || codegen::run_with_lli(&path, program_args.get(1..).unwrap_or_default(), runtime.as_deref()));
+    timings::finish();
+    if output.is_empty() {
+        let _ = fs::remove_file(&path);
+    }
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn build_command(config: &config::RuntimeConfig, args: &[String]) {
+    let mut source_file = String::new();
+    let mut output = String::new();
+    let mut debug_info = false;
+    let mut checked = false;
+    let runtime = codegen::runtime_library(config.runtime_lib.as_deref());
+    let mut opts = codegen::BuildOptions { emit: codegen::Emit::Exe, lld: false, verbose: false, runtime };
+    // None until --emit says; an `.arb` output then picks bytecode
+    let mut bytecode = None;
+    let mut i = 0;
+    while i < args.len() {
+        let emit = match args[i].as_str() {
+            "--emit" if i + 1 < args.len() => {
+                i += 1;
+                Some(args[i].as_str())
+            }
+            arg => arg.strip_prefix("--emit="),
+        };
+        if let Some(kind) = emit {
+            bytecode = Some(kind == bytecode_file::EXTENSION);
+            if kind != bytecode_file::EXTENSION {
+                opts.emit = codegen::Emit::parse(kind).unwrap_or_else(|| {
+                    eprintln!("cryo build: unknown --emit kind '{}' (expected ir, asm, obj, exe or arb)", kind);
+                    process::exit(2);
+                });
+            }
+            i += 1;
+            continue;
+        }
+        match args[i].as_str() {
+            "-o" | "--output" if i + 1 < args.len() => {
+                output = args[i + 1].clone();
+                i += 1;
+            }
+            "-g" | "--debug-info" => debug_info = true,
+            "--checked" => checked = true,
+            "--lld" => opts.lld = true,
+            "--verbose" => opts.verbose = true,
+            "--timings" => timings::enable(),
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo build: unknown option '{}'", opt);
+                process::exit(2);
+            }
+            file => source_file = file.to_string(),
+        }
+        i += 1;
+    }
+    if source_file.is_empty() {
+        eprintln!("USAGE: cryo build FILE [--emit=ir|asm|obj|exe|arb] [-o OUT] [-g] [--checked] [--lld] [--verbose] [--timings]");
+        process::exit(2);
+    }
+    let bytecode = bytecode.unwrap_or_else(|| is_bytecode_path(&output));
+    if output.is_empty() {
+        let stem = std::path::Path::new(&source_file).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        output = if bytecode { format!("{}.{}", stem, bytecode_file::EXTENSION) } else { opts.emit.output_for(&stem) };
+    }
+
+    let source = match fs::read_to_string(&source_file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", source_file, e);
+            process::exit(1);
+        }
+    };
+    let (tokens, lines) = timings::time(&source_file, "lex", || lexer::tokenize_with_lines(&source));
+    let mut parser = parser::Parser::with_lines(&tokens, &lines);
+    let ast = match timings::time(&source_file, "parse", || parser.parse()) {
+        Ok(ast) => ast,
+        Err(e) => {
+            report_parse_error(&e, Some((&source_file, &source, parser.position())));
+            timings::finish();
+            process::exit(1);
+        }
+    };
+    let expanded = timings::time(&source_file, "expand", || expander::Expander::new().expand(ast));
+    let final_ast = timings::time(&source_file, "optimize", || optimizer::Optimizer::new().optimize(expanded));
+    if bytecode {
+        let result = timings::time(&source_file, "bytecode", || bytecode_file::compile_program(&final_ast))
+            .and_then(|funcs| fs::write(&output, bytecode_file::encode(&funcs)).map_err(|e| format!("{}: {}", output, e)));
+        timings::finish();
+        if let Err(e) = result {
+            eprintln!("Build error: {}: {}", source_file, e);
+            process::exit(1);
+        }
+        if opts.verbose {
+            eprintln!("Built {}", output);
+        }
+        return;
+    }
+    if checked && opts.emit == codegen::Emit::Exe && opts.runtime.is_none() {
+        eprintln!("cryo build: --checked executables need the runtime library (set CRYO_RUNTIME_LIB)");
+        process::exit(1);
+    }
+    let source = Some(source_file.as_str());
+    let result = timings::time(&source_file, "codegen", || codegen::lower(&final_ast, source.filter(|_| debug_info), source.filter(|_| checked)))
+        .and_then(|ir| timings::time(&source_file, "build", || codegen::build(&ir, &output, &opts)));
+    timings::finish();
+    if let Err(e) = result {
+        eprintln!("Build error: {}", e);
+        process::exit(1);
+    }
+    if opts.verbose {
+        eprintln!("Built {}", output);
+    }
+}
+
+fn is_bytecode_path(path: &str) -> bool {
+    std::path::Path::new(path).extension().is_some_and(|ext| ext == bytecode_file::EXTENSION)
+}
+
+/// `cryo explain E0102`: the long form of an error code, with an example and
+/// the usual fix. A lint name explains the lint; no argument lists every code.
+fn explain_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo explain [CODE | LINT]";
+    match args {
+        [] => {
+            for code in diagnostics::CODES {
+                println!("{}  {}", code.code, code.title);
+            }
+        }
+        [name] => {
+            if let Some(code) = diagnostics::lookup(name) {
+                print!("{}", diagnostics::render(code));
+            } else if let Some((lint, description)) = lints::LINTS.iter().find(|(lint, _)| *lint == name.replace('_', "-")) {
+                println!("{}: lint, warns about {}
+
+See docs/lints.md, and `[lints]` in cryo.toml to change its level.", lint, description);
+            } else {
+                eprintln!("cryo explain: no error code or lint named '{}' (`cryo explain` lists them)", name);
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Report a parse error, with the `cryo explain` hint when it has a code.
+/// `at` is the file, its text and the token the parser stopped at; the error
+/// is then shown as `file:line:col: error: ...` over that line.
+fn report_parse_error(message: &str, at: Option<(&str, &str, usize)>) {
+    match at {
+        Some((path, source, token)) => {
+            let (_, lines, columns, spans) = lexer::tokenize_with_positions(source);
+            let width = |i: usize| source[spans[i].start..spans[i].end].chars().take_while(|&c| c != '\n').count();
+            // Past the end means the Eof token, which is always last; point
+            // just after the token before it instead
+            let last = lines.len() - 1;
+            let (line, col, len) = match token.min(last) {
+                i if i == last && i > 0 => (lines[i - 1], columns[i - 1] + width(i - 1), 1),
+                i => (lines[i], columns[i], width(i).max(1)),
+            };
+            eprint!("{}", diagnostics::located(path, source, line, col, len, message));
+        }
+        None => eprintln!("Parse error: {}", message),
+    }
+    if let Some(help) = diagnostics::help(message) {
+        eprintln!("{}", help);
+    }
+}
+
+/// `cryo fuzz`: generate and mutate programs until something panics, saving
+/// each distinct crash under `-o`; with FILE arguments, replay those instead
+fn fuzz_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo fuzz [--target lex|parse|run] [-n N] [--seed S] [--max-len BYTES] [--steps N] [--timeout SECS] [--corpus DIR]... [-o DIR] [FILE...]";
+    let mut target = fuzz::Target::Run;
+    let mut iterations = 10_000;
+    let mut seed = None;
+    let mut limits = fuzz::Limits::default();
+    let mut corpus_paths = Vec::new();
+    let mut artifacts = std::path::PathBuf::from("fuzz-crashes");
+    let mut replay = Vec::new();
+    let mut worker = false;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|s| s.as_str());
+        let number = |v: Option<&str>| {
+            v.and_then(|v| v.parse::<u64>().ok()).unwrap_or_else(|| {
+                eprintln!("cryo fuzz: {} needs a number\n{}", args[i], USAGE);
+                process::exit(2);
+            })
+        };
+        match args[i].as_str() {
+            "--target" => {
+                target = value.and_then(fuzz::Target::parse).unwrap_or_else(|| {
+                    eprintln!("cryo fuzz: --target is lex, parse or run");
+                    process::exit(2);
+                });
+                i += 1;
+            }
+            "-n" | "--iterations" => {
+                iterations = number(value);
+                i += 1;
+            }
+            "--seed" => {
+                seed = Some(number(value));
+                i += 1;
+            }
+            "--max-len" => {
+                limits.max_len = number(value) as usize;
+                i += 1;
+            }
+            "--steps" => {
+                limits.steps = number(value);
+                i += 1;
+            }
+            "--timeout" => {
+                limits.timeout = std::time::Duration::from_secs(number(value));
+                i += 1;
+            }
+            "--corpus" if value.is_some() => {
+                corpus_paths.push(std::path::PathBuf::from(&args[i + 1]));
+                i += 1;
+            }
+            "-o" | "--artifacts" if value.is_some() => {
+                artifacts = std::path::PathBuf::from(&args[i + 1]);
+                i += 1;
+            }
+            // Internal: the child `fuzz` runs its inputs in
+            "--worker" => worker = true,
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo fuzz: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            file => replay.push(file.to_string()),
+        }
+        i += 1;
+    }
+
+    if worker {
+        if let Err(e) = fuzz::serve(target, &limits, std::io::stdin().lock(), std::io::stderr().lock()) {
+            eprintln!("cryo fuzz: worker: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if !replay.is_empty() {
+        let mut crashed = false;
+        for file in &replay {
+            let input = fs::read(file).unwrap_or_else(|e| {
+                eprintln!("Error reading '{}': {}", file, e);
+                process::exit(1);
+            });
+            match fuzz::run_one(target, &input, &limits) {
+                Ok(()) => eprintln!("{}: ok", file),
+                Err(crash) => {
+                    crashed = true;
+                    eprintln!("{}: crash: {}", file, crash.message);
+                }
+            }
+        }
+        process::exit(if crashed { 1 } else { 0 });
+    }
+
+    let mut corpus = Vec::new();
+    for path in &corpus_paths {
+        let files = match fs::read_dir(path) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.extension().is_some_and(|x| x == "cryo")).collect(),
+            Err(_) => vec![path.clone()],
+        };
+        for file in files {
+            match fs::read(&file) {
+                Ok(bytes) => corpus.push(bytes),
+                Err(e) => eprintln!("cryo fuzz: skipping {}: {}", file.display(), e),
+            }
+        }
+    }
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(1)
+    });
+    eprintln!("fuzz: {:?}, {} iterations, seed {}, {} corpus files", target, iterations, seed, corpus.len());
+    let worker = env::current_exe().unwrap_or_else(|e| {
+        eprintln!("cryo fuzz: cannot find the cryo executable: {}", e);
+        process::exit(1);
+    });
+    let opts = fuzz::FuzzOptions { target, iterations, seed, limits, corpus, artifacts, worker };
+    let start = std::time::Instant::now();
+    let findings = fuzz::fuzz(&opts, |finding| {
+        eprintln!("fuzz: crash at {}: {}", finding.crash.location, finding.crash.message);
+        if let Some(path) = &finding.input_path {
+            eprintln!("fuzz: input saved to {}", path.display());
+        }
+    })
+    .unwrap_or_else(|e| {
+        eprintln!("cryo fuzz: {}", e);
+        process::exit(1);
+    });
+    eprintln!("fuzz: {} inputs in {:.1?}, {} distinct crashes", iterations, start.elapsed(), findings.len());
+    for finding in &findings {
+        eprintln!("  {:>6}x  {}", finding.hits, finding.crash.location);
+    }
+    if !findings.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// `cryo lint FILE... [--deny-warnings]`: exits with 1 on a parse error, or
+/// on any warning with `--deny-warnings`
+fn lint_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo lint FILE... [--deny-warnings]";
+    let mut files = Vec::new();
+    let mut deny = false;
+    for arg in args {
+        match arg.as_str() {
+            "--deny-warnings" => deny = true,
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo lint: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            file => files.push(file.to_string()),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let (mut warnings, mut errors) = (0, 0);
+    for file in &files {
+        let text = fs::read_to_string(file).unwrap_or_else(|e| {
+            eprintln!("Error reading '{}': {}", file, e);
+            process::exit(1);
+        });
+        let path = std::path::Path::new(file);
+        let config = lints::LintConfig::find(path.parent().unwrap_or(path)).unwrap_or_else(|e| {
+            eprintln!("cryo lint: {}", e);
+            process::exit(2);
+        });
+        let doc = engine::Document::new(&text);
+        for d in doc.diagnostics() {
+            let code = diagnostics::code_for(&d.message).map(|c| format!("[{}]", c)).unwrap_or_default();
+            eprintln!("{}:{}:{}: error{}: {}", file, d.range.start.line + 1, d.range.start.character + 1, code, d.message);
+            errors += 1;
+        }
+        for lint in lints::check_file(path, &doc, &config) {
+            let kind = if lint.level == lints::Level::Deny { "error" } else { "warning" };
+            eprintln!("{}:{}:{}: {}[{}]: {}", file, lint.range.start.line + 1, lint.range.start.character + 1, kind, lint.name, lint.message);
+            if lint.level == lints::Level::Deny {
+                errors += 1;
+            } else {
+                warnings += 1;
+            }
+        }
+    }
+    if warnings + errors > 0 {
+        eprintln!("{} warning{}, {} error{}", warnings, if warnings == 1 { "" } else { "s" }, errors, if errors == 1 { "" } else { "s" });
+    }
+    if errors > 0 || (deny && warnings > 0) {
+        process::exit(1);
+    }
+}
+
+/// `cryo lsp`: serve the Language Server Protocol on stdin/stdout. Exits 1
+/// if the client leaves without asking for a shutdown first.
+fn lsp_command(args: &[String]) {
+    // Clients launching a server over stdio pass --stdio
+    if let Some(opt) = args.iter().find(|a| a.as_str() != "--stdio") {
+        eprintln!("cryo lsp: unknown option '{}'\nUSAGE: cryo lsp [--stdio]", opt);
+        process::exit(2);
+    }
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    match lsp::serve(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("cryo lsp: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// `cryo parse`: print the AST, as the indented tree the parser snapshots
+/// use or as JSON
+fn parse_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo parse FILE [--json]";
+    let mut source_file = String::new();
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            opt if opt.starts_with('-') && opt != "-" => {
+                eprintln!("cryo parse: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            file => source_file = file.to_string(),
+        }
+    }
+    if source_file.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let source = if source_file == "-" {
+        let mut source = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut source).map(|_| source)
+    } else {
+        fs::read_to_string(&source_file)
+    };
+    let source = source.unwrap_or_else(|e| {
+        eprintln!("Error reading '{}': {}", source_file, e);
+        process::exit(1);
+    });
+    let (tokens, lines) = lexer::tokenize_with_lines(&source);
+    let mut parser = parser::Parser::with_lines(&tokens, &lines);
+    let ast = match parser.parse() {
+        Ok(ast) => ast,
+        Err(e) => {
+            report_parse_error(&e, Some((&source_file, &source, parser.position())));
+            process::exit(1);
+        }
+    };
+    if json {
+        println!("{}", ast_display::to_json(&ast));
+    } else {
+        print!("{}", ast_display::program(&ast));
+    }
+}
+
+/// `cryo optimize FILE [--explain]`: the syntax tree before and after the
+/// optimizer as a diff, or with `--explain` one line per rewrite
+fn optimize_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo optimize FILE [--explain]";
+    let mut source_file = String::new();
+    let mut explain = false;
+    for arg in args {
+        match arg.as_str() {
+            "--explain" => explain = true,
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo optimize: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            file => source_file = file.to_string(),
+        }
+    }
+    if source_file.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let source = fs::read_to_string(&source_file).unwrap_or_else(|e| {
+        eprintln!("Error reading '{}': {}", source_file, e);
+        process::exit(1);
+    });
+    let (tokens, lines) = lexer::tokenize_with_lines(&source);
+    let mut parser = parser::Parser::with_lines(&tokens, &lines);
+    let ast = match parser.parse() {
+        Ok(ast) => ast,
+        Err(e) => {
+            report_parse_error(&e, Some((&source_file, &source, parser.position())));
+            process::exit(1);
+        }
+    };
+    let before = expander::Expander::new().expand(ast);
+    let before_text = ast_display::program(&before);
+    let (after, rewrites) = optimizer::Optimizer::explain(before);
+    if rewrites.is_empty() {
+        println!("{}: nothing to optimize", source_file);
+        return;
+    }
+    if explain {
+        for rewrite in &rewrites {
+            let place = if rewrite.line > 0 { format!("{}:{}", source_file, rewrite.line) } else { source_file.clone() };
+            println!("{} in {}: {}: {} => {}", place, rewrite.item, rewrite.rule, rewrite.before, rewrite.after);
+        }
+        println!("{} rewrite{}", rewrites.len(), if rewrites.len() == 1 { "" } else { "s" });
+        return;
+    }
+    println!("--- {} (parsed)", source_file);
+    println!("+++ {} (optimized)", source_file);
+    for line in conformance::diff_lines(&before_text, &ast_display::program(&after)) {
+        println!("{}", line);
+    }
+}
+
+/// `cryo selftest`: differential testing of the execution engines. Exits
+/// with 1 when any engine disagrees with the interpreter.
+fn selftest_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo selftest FILE... [--engines interp,vm,tiered,jit,native] [--timeout SECS] [-- ARGS]";
+    let mut files = Vec::new();
+    let mut engine_names: Option<Vec<String>> = None;
+    let mut timeout = std::time::Duration::from_secs(10);
+    let mut program_args = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--" => {
+                program_args = args[i + 1..].to_vec();
+                break;
+            }
+            "--engines" if i + 1 < args.len() => {
+                engine_names = Some(args[i + 1].split(',').map(|s| s.trim().to_string()).collect());
+                i += 1;
+            }
+            "--timeout" if i + 1 < args.len() => {
+                timeout = args[i + 1].parse().map(std::time::Duration::from_secs_f64).unwrap_or_else(|_| {
+                    eprintln!("cryo selftest: --timeout needs a number of seconds");
+                    process::exit(2);
+                });
+                i += 1;
+            }
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo selftest: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            file => files.push(file.to_string()),
+        }
+        i += 1;
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let engines: Vec<&selftest::Engine> = match &engine_names {
+        None => selftest::ENGINES.iter().collect(),
+        Some(names) => names.iter().map(|name| {
+            selftest::ENGINES.iter().find(|e| e.name == name).unwrap_or_else(|| {
+                let known: Vec<&str> = selftest::ENGINES.iter().map(|e| e.name).collect();
+                eprintln!("cryo selftest: unknown engine '{}' (expected {})", name, known.join(", "));
+                process::exit(2);
+            })
+        }).collect(),
+    };
+    let exe = env::current_exe().unwrap_or_else(|e| {
+        eprintln!("cryo selftest: cannot find the cryo binary: {}", e);
+        process::exit(1);
+    });
+
+    let mut diverged = false;
+    for file in &files {
+        if let Err(e) = fs::metadata(file) {
+            eprintln!("Error reading '{}': {}", file, e);
+            process::exit(1);
+        }
+        println!("{}", file);
+        // The first engine that runs is what the rest are held to
+        let mut reference: Option<selftest::Outcome> = None;
+        for engine in &engines {
+            let outcome = match selftest::run_engine(&exe, engine, file, &program_args, timeout) {
+                Ok(outcome) => outcome,
+                Err(reason) => {
+                    println!("  {:<8} skipped: {}", engine.name, reason.lines().next().unwrap_or_default());
+                    continue;
+                }
+            };
+            let Some(expected) = &reference else {
+                let ending = match outcome.code {
+                    _ if outcome.timed_out => "timed out".to_string(),
+                    Some(code) => format!("exit {}", code),
+                    None => "killed".to_string(),
+                };
+                println!("  {:<8} reference ({}, {} lines)", engine.name, ending, outcome.stdout.lines().count());
+                reference = Some(outcome);
+                continue;
+            };
+            match selftest::compare(expected, &outcome) {
+                None => println!("  {:<8} ok", engine.name),
+                Some(difference) => {
+                    diverged = true;
+                    println!("  {:<8} DIFFERS: {}", engine.name, difference);
+                }
+            }
+        }
+    }
+    if diverged {
+        process::exit(1);
+    }
+}
+
+/// `cryo symbols`: query the project's symbol index. With no arguments,
+/// lists every definition; NAME matches definitions containing it; a
+/// FILE:LINE:COL position (1-based) finds what the name there refers to.
+fn symbols_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo symbols [NAME|FILE:LINE:COL] [--refs] [--kind KIND] [--root DIR] [--json]";
+    let mut query = None;
+    let mut refs = false;
+    let mut kind = None;
+    let mut root = std::path::PathBuf::from(".");
+    let mut json = false;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match args[i].as_str() {
+            "--refs" => refs = true,
+            "--json" => json = true,
+            "--kind" => {
+                kind = Some(value.and_then(|k| engine::SymbolKind::parse(k)).unwrap_or_else(|| {
+                    eprintln!("cryo symbols: --kind is function, method, struct, field, enum, variant, global, trait or macro");
+                    process::exit(2);
+                }));
+                i += 1;
+            }
+            "--root" if value.is_some() => {
+                root = std::path::PathBuf::from(&args[i + 1]);
+                i += 1;
+            }
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo symbols: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            q if query.is_none() => query = Some(q.to_string()),
+            extra => {
+                eprintln!("cryo symbols: unexpected argument '{}'\n{}", extra, USAGE);
+                process::exit(2);
+            }
+        }
+        i += 1;
+    }
+    let index = symbols::SymbolIndex::open(&root).unwrap_or_else(|e| {
+        eprintln!("cryo symbols: {}", e);
+        process::exit(1);
+    });
+
+    // FILE:LINE:COL, as editors and compilers print positions
+    let position = query.as_deref().and_then(|q| {
+        let mut parts = q.rsplitn(3, ':');
+        let col = parts.next()?.parse::<usize>().ok()?;
+        let line = parts.next()?.parse::<usize>().ok()?;
+        let pos = engine::Position { line: line.checked_sub(1)?, character: col.checked_sub(1)? };
+        Some((index.key(std::path::Path::new(parts.next()?)), pos))
+    });
+    // (file, range, kind, qualified name) of each result
+    let mut found: Vec<(String, engine::Range, &str, String)> = Vec::new();
+    if let Some((file, pos)) = position {
+        if index.file(&file).is_none() {
+            eprintln!("cryo symbols: {} is not in the index under {}", file, root.display());
+            process::exit(1);
+        }
+        let locations = if refs { index.references(&file, pos, false) } else { index.definition(&file, pos) };
+        found.extend(locations.into_iter().map(|l| (l.file, l.range, if refs { "reference" } else { "definition" }, String::new())));
+    } else {
+        for (file, symbols) in index.files() {
+            for d in &symbols.definitions {
+                let matches = query.as_deref().is_none_or(|q| if refs { d.name == q } else { d.name.contains(q) });
+                if !matches || kind.is_some_and(|k| k != d.kind) {
+                    continue;
+                }
+                if refs {
+                    for l in index.references(file, d.selection.start, false) {
+                        found.push((l.file, l.range, "reference", String::new()));
+                    }
+                } else {
+                    let name = d.container.as_ref().map_or(d.name.clone(), |c| format!("{}::{}", c, d.name));
+                    found.push((file.to_string(), d.selection, d.kind.name(), name));
+                }
+            }
+        }
+        found.sort_by(|a, b| (&a.0, a.1.start).cmp(&(&b.0, b.1.start)));
+        found.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+    }
+
+    if json {
+        let items: Vec<String> = found
+            .iter()
+            .map(|(file, range, kind, name)| {
+                format!(
+                    "{{\"file\":{},\"line\":{},\"column\":{},\"kind\":{},\"name\":{}}}",
+                    cryo::json::quote(file),
+                    range.start.line + 1,
+                    range.start.character + 1,
+                    cryo::json::quote(kind),
+                    cryo::json::quote(name)
+                )
+            })
+            .collect();
+        println!("[{}]", items.join(",\n "));
+    } else {
+        for (file, range, kind, name) in &found {
+            let line = format!("{}:{}:{}  {:<10} {}", file, range.start.line + 1, range.start.character + 1, kind, name);
+            println!("{}", line.trim_end());
+        }
+    }
+    if found.is_empty() && query.is_some() {
+        process::exit(1);
+    }
+}
+
+/// `cryo refactor`: rename the symbol at a position, extract a range of
+/// statements into a new function, or list the code actions for a position
+/// or range and apply one with --apply. The edits are written to the files,
+/// or with --dry-run only printed.
+fn refactor_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo refactor rename FILE:LINE:COL NEW_NAME [--root DIR] [--dry-run]
+       cryo refactor extract FILE:LINE:COL-LINE:COL NAME [--root DIR] [--dry-run]
+       cryo refactor actions FILE:LINE:COL[-LINE:COL] [--apply N] [--root DIR] [--dry-run]";
+    let mut positional = Vec::new();
+    let mut root = std::path::PathBuf::from(".");
+    let mut dry_run = false;
+    let mut apply = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match args[i].as_str() {
+            "--dry-run" => dry_run = true,
+            "--root" if value.is_some() => {
+                root = std::path::PathBuf::from(&args[i + 1]);
+                i += 1;
+            }
+            "--apply" => {
+                apply = Some(value.and_then(|n| n.parse::<usize>().ok()).filter(|&n| n > 0).unwrap_or_else(|| {
+                    eprintln!("cryo refactor: --apply takes the number of an action");
+                    process::exit(2);
+                }));
+                i += 1;
+            }
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo refactor: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            arg => positional.push(arg.to_string()),
+        }
+        i += 1;
+    }
+    let (command, at, name) = match positional.as_slice() {
+        [command, at] if command == "actions" => (command.as_str(), at, ""),
+        [command, at, name] if command == "rename" || command == "extract" => (command.as_str(), at, name.as_str()),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let index = symbols::SymbolIndex::open(&root).unwrap_or_else(|e| {
+        eprintln!("cryo refactor: {}", e);
+        process::exit(1);
+    });
+
+    // FILE:LINE:COL, or FILE:LINE:COL-LINE:COL for a range (1-based)
+    let line_col = |text: &str| {
+        let (line, col) = text.split_once(':')?;
+        Some(engine::Position { line: line.parse::<usize>().ok()?.checked_sub(1)?, character: col.parse::<usize>().ok()?.checked_sub(1)? })
+    };
+    let place = at.rsplit_once('-').and_then(|(start, end)| Some((start, line_col(end)?))).map_or((at.as_str(), None), |(start, end)| (start, Some(end)));
+    let start = place.0.rsplitn(3, ':').collect::<Vec<_>>();
+    let (file, range) = match start.as_slice() {
+        [col, line, file] => match line_col(&format!("{}:{}", line, col)) {
+            Some(pos) => (index.key(std::path::Path::new(file)), engine::Range { start: pos, end: place.1.unwrap_or(pos) }),
+            None => {
+                eprintln!("cryo refactor: bad position '{}'\n{}", at, USAGE);
+                process::exit(2);
+            }
+        },
+        _ => {
+            eprintln!("cryo refactor: bad position '{}'\n{}", at, USAGE);
+            process::exit(2);
+        }
+    };
+    if index.file(&file).is_none() {
+        eprintln!("cryo refactor: {} is not in the index under {}", file, root.display());
+        process::exit(1);
+    }
+    let source = |key: &str| fs::read_to_string(index.path(key)).ok();
+    let edits = match command {
+        "rename" => refactor::rename(&index, &file, range.start, name, &source),
+        "extract" => refactor::extract_function(&index, &file, range, name, &source),
+        _ => {
+            let actions = refactor::code_actions(&index, &file, range, &source);
+            match apply {
+                Some(n) if n <= actions.len() => Ok(actions[n - 1].edits.clone()),
+                Some(n) => Err(format!("there are {} actions here, not {}", actions.len(), n)),
+                None => {
+                    for (n, action) in actions.iter().enumerate() {
+                        println!("{:>3}. {}  ({})", n + 1, action.title, action.kind);
+                    }
+                    if actions.is_empty() {
+                        eprintln!("cryo refactor: no actions at {}", at);
+                        process::exit(1);
+                    }
+                    return;
+                }
+            }
+        }
+    };
+    let edits = edits.unwrap_or_else(|e| {
+        eprintln!("cryo refactor: {}", e);
+        process::exit(1);
+    });
+
+    let mut count = 0;
+    for (key, file_edits) in &edits {
+        count += file_edits.len();
+        if dry_run {
+            for e in file_edits {
+                let (start, end) = (e.range.start, e.range.end);
+                println!("{}:{}:{}-{}:{}  {}", key, start.line + 1, start.character + 1, end.line + 1, end.character + 1, cryo::json::quote(&e.new_text));
+            }
+            continue;
+        }
+        let path = index.path(key);
+        let written = fs::read_to_string(&path).and_then(|text| fs::write(&path, refactor::apply(&text, file_edits)));
+        if let Err(e) = written {
+            eprintln!("cryo refactor: {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+    if !dry_run {
+        println!("{} edit{} in {} file{}", count, if count == 1 { "" } else { "s" }, edits.len(), if edits.len() == 1 { "" } else { "s" });
+    }
+}
+
+/// `cryo test-lang`: the golden-file conformance suite. Exits with 1 when
+/// any case fails.
+fn test_lang_command(args: &[String]) {
+    const USAGE: &str = "USAGE: cryo test-lang [DIR|FILE|PATTERN...] [--engine interp|vm|tiered|jit|native] [-j N] [--timeout SECS] [--bless]";
+    let mut paths = Vec::new();
+    let mut engine = &selftest::ENGINES[0];
+    let mut jobs = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let mut timeout = std::time::Duration::from_secs(10);
+    let mut bless = false;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|s| s.as_str());
+        match args[i].as_str() {
+            "--engine" if value.is_some() => {
+                engine = selftest::ENGINES.iter().find(|e| Some(e.name) == value).unwrap_or_else(|| {
+                    eprintln!("cryo test-lang: unknown engine '{}'\n{}", value.unwrap_or_default(), USAGE);
+                    process::exit(2);
+                });
+                i += 1;
+            }
+            "-j" | "--jobs" if value.is_some() => {
+                jobs = value.and_then(|v| v.parse().ok()).unwrap_or_else(|| {
+                    eprintln!("cryo test-lang: -j needs a number");
+                    process::exit(2);
+                });
+                i += 1;
+            }
+            "--timeout" if value.is_some() => {
+                timeout = value.and_then(|v| v.parse().ok()).map(std::time::Duration::from_secs_f64).unwrap_or_else(|| {
+                    eprintln!("cryo test-lang: --timeout needs a number of seconds");
+                    process::exit(2);
+                });
+                i += 1;
+            }
+            "--bless" => bless = true,
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo test-lang: unknown option '{}'\n{}", opt, USAGE);
+                process::exit(2);
+            }
+            path => paths.push(std::path::PathBuf::from(path)),
+        }
+        i += 1;
+    }
+    if paths.is_empty() {
+        paths.push(std::path::PathBuf::from("tests/lang"));
+    }
+    let cases = conformance::collect(&paths).unwrap_or_else(|e| {
+        eprintln!("cryo test-lang: {}", e);
+        process::exit(1);
+    });
+    let exe = env::current_exe().unwrap_or_else(|e| {
+        eprintln!("cryo test-lang: cannot find the cryo binary: {}", e);
+        process::exit(1);
+    });
+
+    println!("test-lang: {} programs on {} ({} jobs)", cases.len(), engine.name, jobs);
+    let start = std::time::Instant::now();
+    let results = conformance::run(&exe, engine, cases, jobs, timeout, |result| {
+        if bless || result.problems.is_empty() {
+            return;
+        }
+        println!("FAIL {}", result.case.name());
+        for problem in &result.problems {
+            println!("     {}", problem);
+        }
+    });
+
+    if bless {
+        let mut written = 0;
+        for result in &results {
+            match &result.outcome {
+                // Programs meant to fail keep their .error file as the check
+                Ok(outcome) if result.case.error.is_none() && outcome.code == Some(0) => {
+                    let path = result.case.source.with_extension("expected");
+                    if result.case.expected.as_deref() != Some(outcome.stdout.as_str()) {
+                        if let Err(e) = fs::write(&path, &outcome.stdout) {
+                            eprintln!("cryo test-lang: {}: {}", path.display(), e);
+                            process::exit(1);
+                        }
+                        println!("wrote {}", path.display());
+                        written += 1;
+                    }
+                }
+                Ok(_) if result.case.error.is_some() => {}
+                _ => println!("skipped {}: it doesn't run cleanly", result.case.name()),
+            }
+        }
+        println!("test-lang: updated {} expected files", written);
+        return;
+    }
+
+    let failed = results.iter().filter(|r| !r.problems.is_empty()).count();
+    println!("test-lang: {} passed, {} failed in {:.2?}", results.len() - failed, failed, start.elapsed());
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn bundle_command(config: &config::RuntimeConfig, args: &[String]) {
+    let mut source_file = String::new();
+    let mut output = String::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-o" | "--output" if i + 1 < args.len() => {
+                output = args[i + 1].clone();
+                i += 1;
+            }
+            opt if opt.starts_with('-') => {
+                eprintln!("cryo bundle: unknown option '{}'", opt);
+                process::exit(2);
+            }
+            file => source_file = file.to_string(),
+        }
+        i += 1;
+    }
+    if source_file.is_empty() {
+        eprintln!("USAGE: cryo bundle FILE [-o OUT]");
+        process::exit(2);
+    }
+    if output.is_empty() {
+        let stem = std::path::Path::new(&source_file).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        output = if cfg!(windows) { format!("{}.exe", stem) } else { stem };
+    }
+
+    // Resolve imports exactly as running the script would
+    let mut resolver = interpreter::Interpreter::new();
+    resolver.apply_config(config);
+    resolver.set_base_path(&source_file);
+    let bundle = match bundle::Bundle::collect(&source_file, |path| resolver.resolve_module(path)) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Bundle error: {}", e);
+            process::exit(1);
+        }
+    };
+    let runtime = match env::current_exe() {
+        Ok(p) => p.to_string_lossy().to_string(),
+        Err(e) => {
+            eprintln!("Bundle error: cannot locate the cryo executable: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = bundle.write_executable(&runtime, &output) {
+        eprintln!("Bundle error: {}", e);
+        process::exit(1);
+    }
+    println!("Bundled {} ({} modules) into {}", source_file, bundle.modules.len(), output);
+}
+
+fn run_bundled(config: &config::RuntimeConfig, bundle: bundle::Bundle, args: Vec<String>) {
+    let mut interp = interpreter::Interpreter::new();
+    interp.apply_config(config);
+    interp.set_bundled_modules(bundle.modules);
+    interp.set_args(args);
+    if let Err(e) = interp.run(&bundle.main) {
+        eprintln!("Runtime error: {}", e);
+        process::exit(1);
+    }
+}
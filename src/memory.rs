@@ -0,0 +1,278 @@
+// ============================================
+// Cryo Heap Accounting
+// Approximate live bytes of interpreter values for memory_usage(),
+// `--mem-stats` and `--max-heap`. Values are reference counted, so nothing
+// frees memory on a schedule; instead allocation sites report roughly what
+// they allocated, and once enough has piled up the interpreter measures what
+// is actually reachable from its variables.
+// ============================================
+
+use crate::collections::{Collection, MapKey};
+use crate::interpreter::Value;
+use crate::parser::Expr;
+use rustc_hash::FxHashSet;
+
+/// One array element or struct field
+const SLOT: usize = std::mem::size_of::<Value>();
+/// Reference counts in front of every shared object
+const RC_HEADER: usize = 16;
+/// Allocation between measurements when the heap is small
+const MIN_STEP: usize = 256 * 1024;
+/// Allocation between measurements however close the heap is to its limit
+const MIN_CHECK: usize = 16 * 1024;
+
+/// Objects of one kind and their bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Kind {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl Kind {
+    fn add(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// What one measurement found reachable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub strings: Kind,
+    pub arrays: Kind,
+    pub structs: Kind,
+    /// Collections, bigints and decimals
+    pub other: Kind,
+}
+
+impl Usage {
+    pub fn bytes(&self) -> usize {
+        self.strings.bytes + self.arrays.bytes + self.structs.bytes + self.other.bytes
+    }
+}
+
+/// Bytes owned directly by `value`, not counting the values it contains
+pub fn shallow_size(value: &Value) -> usize {
+    match value {
+        Value::Null | Value::Bool(_) | Value::Int(_) | Value::Float(_) | Value::Function(_) => 0,
+        Value::String(s) => RC_HEADER + s.len(),
+        Value::Array(items) => RC_HEADER + 32 + items.borrow().capacity() * SLOT,
+        Value::Struct(_, fields) | Value::Map(fields) => {
+            let fields = fields.borrow();
+            // Hash tables keep about one spare bucket per entry
+            RC_HEADER + 48 + fields.capacity() * (24 + SLOT + 8) + fields.keys().map(|k| k.len()).sum::<usize>()
+        }
+        Value::Collection(coll) => {
+            let coll = coll.borrow();
+            let keys = match &*coll {
+                Collection::Map(m) => m.keys().map(|k| if let MapKey::Str(s) = k { s.len() } else { 0 }).sum(),
+                _ => 0,
+            };
+            RC_HEADER + 48 + coll.len() * (SLOT + 32) + keys
+        }
+        Value::BigInt(_) | Value::Decimal(_) => RC_HEADER + 32,
+        Value::Enum(e) => RC_HEADER + 32 + e.fields.capacity() * SLOT,
+    }
+}
+
+/// Everything reachable from `roots`, each shared object counted once
+pub fn measure<'a>(roots: impl IntoIterator<Item = &'a Value>) -> Usage {
+    let mut usage = Usage::default();
+    let mut seen: FxHashSet<usize> = FxHashSet::default();
+    let mut pending: Vec<Value> = roots.into_iter().cloned().collect();
+    while let Some(value) = pending.pop() {
+        let address = match &value {
+            Value::String(s) => s.as_ptr() as usize,
+            Value::Array(a) => a.as_ptr() as usize,
+            Value::Struct(_, f) | Value::Map(f) => f.as_ptr() as usize,
+            Value::Collection(c) => c.as_ptr() as usize,
+            Value::BigInt(n) => std::rc::Rc::as_ptr(n) as usize,
+            Value::Decimal(d) => std::rc::Rc::as_ptr(d) as usize,
+            Value::Enum(e) => std::rc::Rc::as_ptr(e) as usize,
+            _ => continue,
+        };
+        if !seen.insert(address) {
+            continue;
+        }
+        let bytes = shallow_size(&value);
+        match &value {
+            Value::String(_) => usage.strings.add(bytes),
+            Value::Array(items) => {
+                usage.arrays.add(bytes);
+                pending.extend(items.borrow().iter().cloned());
+            }
+            // Hash maps are counted with the structs they're built like
+            Value::Struct(_, fields) | Value::Map(fields) => {
+                usage.structs.add(bytes);
+                pending.extend(fields.borrow().values().cloned());
+            }
+            Value::Collection(coll) => {
+                usage.other.add(bytes);
+                coll.borrow().for_each_value(|v| pending.push(v.clone()));
+            }
+            Value::Enum(e) => {
+                usage.other.add(bytes);
+                pending.extend(e.fields.iter().cloned());
+            }
+            _ => usage.other.add(bytes),
+        }
+    }
+    usage
+}
+
+/// Roughly what evaluating `expr` to `result` allocated. Only expressions
+/// that can create objects count; reading a variable allocates nothing.
+pub fn allocated_by(expr: &Expr, result: &Value) -> usize {
+    match expr {
+        // push() returns null but grows its array
+        Expr::Call(name, args) if *name == "push" => args.len().saturating_sub(1) * SLOT,
+        Expr::String(_) | Expr::BinOp(..) | Expr::Array(_) | Expr::StructInit(..) | Expr::ObjectLiteral(_) | Expr::MapLiteral(_)
+        | Expr::Call(..) | Expr::MethodCall(..) | Expr::StaticMethodCall(..) | Expr::Index(..) => shallow_size(result),
+        // The operation inside was counted when it was evaluated
+        Expr::At(..) => 0,
+        _ => 0,
+    }
+}
+
+/// Running totals between measurements, and the `--max-heap` limit
+#[derive(Debug, Default)]
+pub struct HeapAccount {
+    max_heap: Option<usize>,
+    /// Measure as the program runs so `--mem-stats` can report the peak
+    stats: bool,
+    /// Either of the above; checked on every expression
+    enabled: bool,
+    since_measure: usize,
+    allocated: u64,
+    live: usize,
+    peak: Usage,
+    measurements: u64,
+}
+
+impl HeapAccount {
+    pub fn new() -> Self {
+        HeapAccount::default()
+    }
+
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn max_heap(&self) -> Option<usize> {
+        self.max_heap
+    }
+
+    pub fn set_max_heap(&mut self, bytes: Option<usize>) {
+        self.max_heap = bytes;
+        self.enabled = self.stats || self.max_heap.is_some();
+    }
+
+    pub fn stats(&self) -> bool {
+        self.stats
+    }
+
+    pub fn set_stats(&mut self, enabled: bool) {
+        self.stats = enabled;
+        self.enabled = self.stats || self.max_heap.is_some();
+    }
+
+    /// Count `bytes` as allocated; true when it's time to measure
+    pub fn note(&mut self, bytes: usize) -> bool {
+        self.since_measure += bytes;
+        self.allocated += bytes as u64;
+        // Measure after the heap could have grown by half, so the walks cost
+        // about as much as the allocations did; sooner near the limit
+        let mut step = MIN_STEP.max(self.live / 2);
+        if let Some(max) = self.max_heap {
+            step = step.min(max.saturating_sub(self.live)).max(MIN_CHECK);
+        }
+        self.since_measure >= step
+    }
+
+    /// Take a measurement; Err with the message to stop the program with
+    /// when it's over `max_heap`
+    pub fn record(&mut self, usage: Usage) -> Result<(), String> {
+        self.since_measure = 0;
+        self.measurements += 1;
+        self.live = usage.bytes();
+        if self.live > self.peak.bytes() {
+            self.peak = usage;
+        }
+        match self.max_heap {
+            Some(max) if self.live > max => Err(format!("heap limit exceeded: {} live, --max-heap is {}", mib(self.live), mib(max))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn live(&self) -> usize {
+        self.live
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.bytes()
+    }
+
+    pub fn allocated(&self) -> u64 {
+        self.allocated
+    }
+
+    /// The `--mem-stats` report, given a final measurement
+    pub fn report(&self, now: &Usage) -> String {
+        let kinds = |u: &Usage| {
+            format!(
+                "{} strings {}, {} arrays {}, {} structs {}, {} other {}",
+                u.strings.count, mib(u.strings.bytes),
+                u.arrays.count, mib(u.arrays.bytes),
+                u.structs.count, mib(u.structs.bytes),
+                u.other.count, mib(u.other.bytes),
+            )
+        };
+        let mut out = String::from("memory:\n");
+        out.push_str(&format!("  peak      {} ({})\n", mib(self.peak()), kinds(&self.peak)));
+        out.push_str(&format!("  at exit   {} ({})\n", mib(now.bytes()), kinds(now)));
+        out.push_str(&format!("  allocated {} (approximate, {} measurements)\n", mib(self.allocated as usize), self.measurements));
+        if let Some(max) = self.max_heap {
+            out.push_str(&format!("  limit     {}\n", mib(max)));
+        }
+        out
+    }
+}
+
+fn mib(bytes: usize) -> String {
+    format!("{:.1}MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_measure_counts_shared_objects_once() {
+        let text: Value = Value::String("x".repeat(1000).into());
+        let inner = Value::Array(Rc::new(RefCell::new(vec![text.clone(), text.clone(), Value::Int(1)])));
+        let outer = Value::Array(Rc::new(RefCell::new(vec![inner.clone(), inner.clone()])));
+        let usage = measure([&outer, &inner, &text]);
+        assert_eq!(usage.strings, Kind { count: 1, bytes: 1000 + RC_HEADER });
+        assert_eq!(usage.arrays.count, 2);
+        assert_eq!(usage.bytes(), shallow_size(&outer) + shallow_size(&inner) + shallow_size(&text));
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut account = HeapAccount::new();
+        account.set_max_heap(Some(1 << 20));
+        assert!(account.enabled());
+        assert!(!account.note(1000));
+        assert!(account.note(MIN_STEP));
+        let big = Usage { strings: Kind { count: 1, bytes: 2 << 20 }, ..Usage::default() };
+        assert!(account.record(Usage::default()).is_ok());
+        assert_eq!(account.record(big).unwrap_err(), "heap limit exceeded: 2.0MiB live, --max-heap is 1.0MiB");
+        assert_eq!(account.peak(), 2 << 20);
+        // Close to the limit, measure after every few KiB
+        account.record(Usage { arrays: Kind { count: 1, bytes: (1 << 20) - 100 }, ..Usage::default() }).unwrap();
+        assert!(account.note(MIN_CHECK));
+    }
+}
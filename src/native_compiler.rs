@@ -2,13 +2,126 @@
 // Compiles Cryo source directly to LLVM IR
 // Much faster than self-hosted compiler.ar
 
+use crate::intern::Symbol;
 use crate::parser::{Parser, TopLevel, Stmt, Expr, Function};
 use crate::lexer;
+use std::collections::{HashMap, HashSet};
 
+/// Every value is an i64; booleans are 0/1 and null is 0. Constructs without
+/// a lowering yet (strings outside `print`, arrays, structs, methods, ...) are
+/// reported as errors rather than silently dropped.
 pub struct Compiler {
     output: String,
     func_counter: usize,
     label_counter: usize,
+    /// Arity of every function the module defines or declares
+    signatures: HashMap<Symbol, usize>,
+    /// C parameter and return types of extern functions that aren't all i64
+    extern_types: HashMap<Symbol, (Vec<&'static str>, &'static str)>,
+    /// Top-level `let`s with an int literal value, emitted as i64 globals
+    globals: HashSet<Symbol>,
+    /// String constant definitions, appended after the functions
+    strings: String,
+    string_counter: usize,
+    /// Constant already defined for each string, so repeats share one
+    string_names: HashMap<String, (String, usize)>,
+    // Per function: allocas are hoisted into the entry block so loops don't grow the stack
+    allocas: String,
+    body: String,
+    scopes: Vec<HashMap<Symbol, String>>,
+    /// (continue target, break target) for each enclosing loop
+    loops: Vec<(String, String)>,
+    debug: Option<DebugInfo>,
+    /// Source path for `cryo_panic_at` locations when runtime checks are on
+    checks: Option<String>,
+    /// Name and line of the function being compiled
+    func_name: String,
+    line: usize,
+}
+
+/// DWARF metadata for `-g`: a compile unit, a subprogram per function and a
+/// location on every instruction. Locations are per function (the line of its
+/// `fn`) because statements don't carry source positions yet.
+struct DebugInfo {
+    /// `!N = ...` definitions, N being the index
+    nodes: Vec<String>,
+    locations: HashMap<(usize, usize), usize>,
+    subprogram: usize,
+    line: usize,
+}
+
+// Fixed nodes, created by `DebugInfo::new`
+const DI_UNIT: usize = 0;
+const DI_FILE: usize = 1;
+const DI_FN_TYPE: usize = 4;
+const DI_INT: usize = 5;
+
+impl DebugInfo {
+    fn new(path: &str) -> Self {
+        let path = std::path::Path::new(path);
+        let file = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_else(|| "<stdin>".to_string());
+        let dir = std::fs::canonicalize(path)
+            .ok()
+            .and_then(|p| p.parent().map(|d| d.to_string_lossy().into_owned()))
+            .or_else(|| std::env::current_dir().ok().map(|d| d.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        DebugInfo {
+            nodes: vec![
+                format!("distinct !DICompileUnit(language: DW_LANG_C, file: !{}, producer: \"cryo {}\", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)", DI_FILE, env!("CARGO_PKG_VERSION")),
+                format!("!DIFile(filename: \"{}\", directory: \"{}\")", escape_metadata(&file), escape_metadata(&dir)),
+                "!{i32 2, !\"Debug Info Version\", i32 3}".to_string(),
+                "!{i32 7, !\"Dwarf Version\", i32 4}".to_string(),
+                "!DISubroutineType(types: !{})".to_string(),
+                "!DIBasicType(name: \"int\", size: 64, encoding: DW_ATE_signed)".to_string(),
+            ],
+            locations: HashMap::new(),
+            subprogram: 0,
+            line: 0,
+        }
+    }
+
+    fn node(&mut self, def: String) -> usize {
+        self.nodes.push(def);
+        self.nodes.len() - 1
+    }
+
+    fn begin_function(&mut self, func: &Function) -> usize {
+        self.line = func.line;
+        self.subprogram = self.node(format!(
+            "distinct !DISubprogram(name: \"{}\", scope: !{}, file: !{}, line: {}, type: !{}, scopeLine: {}, spFlags: DISPFlagDefinition, unit: !{})",
+            func.name, DI_FILE, DI_FILE, func.line, DI_FN_TYPE, func.line, DI_UNIT
+        ));
+        self.subprogram
+    }
+
+    fn location(&mut self) -> usize {
+        let key = (self.subprogram, self.line);
+        if let Some(&loc) = self.locations.get(&key) {
+            return loc;
+        }
+        let loc = self.node(format!("!DILocation(line: {}, scope: !{})", self.line, self.subprogram));
+        self.locations.insert(key, loc);
+        loc
+    }
+
+    fn variable(&mut self, name: Symbol, arg: Option<usize>) -> usize {
+        let arg = arg.map(|n| format!("arg: {}, ", n)).unwrap_or_default();
+        self.node(format!(
+            "!DILocalVariable(name: \"{}\", {}scope: !{}, file: !{}, line: {}, type: !{})",
+            name, arg, self.subprogram, DI_FILE, self.line, DI_INT
+        ))
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push_str(&format!("\n!llvm.dbg.cu = !{{!{}}}\n!llvm.module.flags = !{{!2, !3}}\n", DI_UNIT));
+        for (i, node) in self.nodes.iter().enumerate() {
+            out.push_str(&format!("!{} = {}\n", i, node));
+        }
+    }
+}
+
+fn escape_metadata(s: &str) -> String {
+    s.chars().map(|c| if c == '"' || c == '\\' { format!("\\{:02X}", c as u32) } else { c.to_string() }).collect()
 }
 
 impl Compiler {
@@ -17,126 +130,403 @@ impl Compiler {
             output: String::new(),
             func_counter: 0,
             label_counter: 0,
+            signatures: HashMap::new(),
+            extern_types: HashMap::new(),
+            globals: HashSet::new(),
+            strings: String::new(),
+            string_counter: 0,
+            string_names: HashMap::new(),
+            allocas: String::new(),
+            body: String::new(),
+            scopes: Vec::new(),
+            loops: Vec::new(),
+            debug: None,
+            checks: None,
+            func_name: String::new(),
+            line: 0,
         }
     }
 
+    /// Trap division by zero and integer overflow with a `cryo_panic_at` call
+    /// (from the runtime library) instead of yielding 0 / wrapping like the
+    /// interpreter. Functions keep frame pointers so the panic handler can
+    /// print a backtrace.
+    pub fn with_checks(mut self, source_path: &str) -> Self {
+        self.checks = Some(source_path.to_string());
+        self
+    }
+
+    /// Attach DWARF line info, with `source_path` as the compile unit's file
+    pub fn with_debug_info(mut self, source_path: &str) -> Self {
+        self.debug = Some(DebugInfo::new(source_path));
+        self
+    }
+
     fn new_label(&mut self) -> String {
         self.label_counter += 1;
         format!("L{}", self.label_counter)
     }
 
+    fn new_tmp(&mut self) -> String {
+        self.func_counter += 1;
+        format!("%t{}", self.func_counter)
+    }
+
+    fn emit(&mut self, line: &str) {
+        self.body.push_str("  ");
+        self.body.push_str(line);
+        if let Some(debug) = &mut self.debug {
+            self.body.push_str(&format!(", !dbg !{}", debug.location()));
+        }
+        self.body.push('\n');
+    }
+
+    fn label(&mut self, label: &str) {
+        self.body.push_str(label);
+        self.body.push_str(":\n");
+    }
+
+    /// Emit a terminator. Anything compiled after it (e.g. code following a
+    /// `return`) lands in a fresh, unreachable block.
+    fn terminate(&mut self, line: &str) {
+        self.emit(line);
+        let dead = self.new_label();
+        self.label(&dead);
+    }
+
     pub fn compile(&mut self, source: &str) -> Result<String, String> {
         let tokens = lexer::tokenize(source);
-        let mut parser = Parser::new(tokens);
+        let mut parser = Parser::new(&tokens);
         let ast = parser.parse().map_err(|e| format!("Parse error: {}", e))?;
+        self.compile_items(&ast)
+    }
 
+    /// Compile an already parsed program, e.g. one built by a script
+    pub fn compile_items(&mut self, ast: &[TopLevel]) -> Result<String, String> {
         // LLVM IR Header
         self.output.push_str("; Cryo Native Compiler Output\n");
         self.output.push_str("target triple = \"x86_64-pc-linux-gnu\"\n\n");
-        
+
         // External declarations
         self.output.push_str("declare i32 @printf(i8*, ...)\n");
         self.output.push_str("declare i64 @time(i64*)\n");
+        if self.debug.is_some() {
+            self.output.push_str("declare void @llvm.dbg.declare(metadata, metadata, metadata)\n");
+        }
+        if self.checks.is_some() {
+            self.output.push_str("declare void @cryo_panic_at(i8*, i8*, i64, i8*) noreturn\n");
+            self.output.push_str("declare i8* @llvm.frameaddress.p0i8(i32)\n");
+            for op in ["sadd", "ssub", "smul"] {
+                self.output.push_str(&format!("declare {{ i64, i1 }} @llvm.{}.with.overflow.i64(i64, i64)\n", op));
+            }
+        }
         self.output.push_str("@.str_int = private unnamed_addr constant [5 x i8] c\"%ld\\0A\\00\"\n");
         self.output.push_str("@.str_s = private unnamed_addr constant [4 x i8] c\"%s\\0A\\00\"\n\n");
 
-        // Compile all top-level items
-        for item in &ast {
+        // Signatures and globals first, so calls and loads can be checked
+        for item in ast {
             match item {
-                TopLevel::Function(f) => self.compile_function(f)?,
+                TopLevel::Function(f) if f.body.is_some() => {
+                    self.signatures.insert(f.name, f.params.len());
+                }
+                TopLevel::Extern(block) => {
+                    for f in &block.functions {
+                        let params: Vec<&str> = f.params.iter().map(|p| c_type(p.typ.as_deref())).collect();
+                        let ret = f.return_type.as_deref().map_or("i64", |t| if t == "void" { "void" } else { c_type(Some(t)) });
+                        self.output.push_str(&format!("declare {} @{}({})\n", ret, f.name, params.join(", ")));
+                        self.signatures.insert(f.name, f.params.len());
+                        if ret != "i64" || params.iter().any(|&p| p != "i64") {
+                            self.extern_types.insert(f.name, (params, ret));
+                        }
+                    }
+                }
+                TopLevel::Let(name, Expr::Number(n)) => {
+                    self.output.push_str(&format!("@{} = global i64 {}\n", name, n));
+                    self.globals.insert(*name);
+                }
                 _ => {}
             }
         }
+        self.output.push('\n');
 
+        // Compile all top-level items
+        for item in ast {
+            if let TopLevel::Function(f) = item {
+                if f.body.is_some() {
+                    self.compile_function(f).map_err(|e| format!("fn {}: {}", f.name, e))?;
+                }
+            }
+        }
+
+        let strings = std::mem::take(&mut self.strings);
+        self.output.push_str(&strings);
+        if self.checks.is_some() {
+            self.output.push_str("\nattributes #0 = { \"frame-pointer\"=\"all\" }\n");
+        }
+        if let Some(debug) = &self.debug {
+            debug.write(&mut self.output);
+        }
         Ok(self.output.clone())
     }
 
     fn compile_function(&mut self, func: &Function) -> Result<(), String> {
-        let name = &func.name;
+        self.allocas.clear();
+        self.body.clear();
+        self.scopes = vec![HashMap::new()];
+        self.loops.clear();
+        self.func_name = func.name.to_string();
+        self.line = func.line;
+        let subprogram = self.debug.as_mut().map(|d| d.begin_function(func));
+
         let params: Vec<String> = func.params.iter()
             .map(|p| format!("i64 %{}", p.name))
             .collect();
-        
-        self.output.push_str(&format!("define i64 @{}({}) {{\n", name, params.join(", ")));
-        self.output.push_str("entry:\n");
-
-        // Allocate space for parameters
-        for param in &func.params {
-            self.output.push_str(&format!("  %{}.addr = alloca i64\n", param.name));
-            self.output.push_str(&format!("  store i64 %{}, i64* %{}.addr\n", param.name, param.name));
+        for (i, param) in func.params.iter().enumerate() {
+            let slot = self.declare_var(param.name, Some(i + 1));
+            self.emit(&format!("store i64 %{}, i64* {}", param.name, slot));
         }
 
         // Compile function body
         if let Some(body) = &func.body {
-            for stmt in body {
-                self.compile_stmt(stmt)?;
-            }
+            self.compile_stmts(body)?;
         }
 
         // Default return
-        self.output.push_str("  ret i64 0\n");
+        self.emit("ret i64 0");
+
+        let attrs = if self.checks.is_some() { " #0" } else { "" };
+        let dbg = subprogram.map(|sp| format!(" !dbg !{}", sp)).unwrap_or_default();
+        self.output.push_str(&format!("define i64 @{}({}){}{} {{\n", func.name, params.join(", "), attrs, dbg));
+        self.output.push_str("entry:\n");
+        self.output.push_str(&self.allocas);
+        self.output.push_str(&self.body);
         self.output.push_str("}\n\n");
-        
+        Ok(())
+    }
+
+    /// A fresh stack slot for a new binding; shadowing gets its own slot
+    fn declare(&mut self, name: Symbol) -> String {
+        self.declare_var(name, None)
+    }
+
+    /// `arg` is the 1-based parameter number, for debug info
+    fn declare_var(&mut self, name: Symbol, arg: Option<usize>) -> String {
+        self.func_counter += 1;
+        let slot = format!("%{}.addr{}", name, self.func_counter);
+        self.allocas.push_str(&format!("  {} = alloca i64\n", slot));
+        if let Some(debug) = &mut self.debug {
+            let var = debug.variable(name, arg);
+            self.allocas.push_str(&format!(
+                "  call void @llvm.dbg.declare(metadata i64* {}, metadata !{}, metadata !DIExpression()), !dbg !{}\n",
+                slot, var, debug.location()
+            ));
+        }
+        self.scopes.last_mut().unwrap().insert(name, slot.clone());
+        slot
+    }
+
+    fn lookup(&self, name: Symbol) -> Result<String, String> {
+        if let Some(slot) = self.scopes.iter().rev().find_map(|s| s.get(&name)) {
+            return Ok(slot.clone());
+        }
+        if self.globals.contains(&name) {
+            return Ok(format!("@{}", name));
+        }
+        Err(format!("undefined variable '{}' (only locals and int globals are compiled)", name))
+    }
+
+    fn compile_stmts(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        for stmt in stmts {
+            self.compile_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn compile_block(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        self.scopes.push(HashMap::new());
+        let result = self.compile_stmts(stmts);
+        self.scopes.pop();
+        result
+    }
+
+    /// Branch on `cond != 0`
+    fn branch(&mut self, cond: &Expr, then_label: &str, else_label: &str) -> Result<(), String> {
+        let val = self.compile_expr(cond)?;
+        let flag = self.new_tmp();
+        self.emit(&format!("{} = icmp ne i64 {}, 0", flag, val));
+        self.emit(&format!("br i1 {}, label %{}, label %{}", flag, then_label, else_label));
         Ok(())
     }
 
     fn compile_stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
         match stmt {
             Stmt::Let(name, _typ, expr) => {
-                self.output.push_str(&format!("  %{}.addr = alloca i64\n", name));
                 let val = self.compile_expr(expr)?;
-                self.output.push_str(&format!("  store i64 {}, i64* %{}.addr\n", val, name));
+                let slot = self.declare(*name);
+                self.emit(&format!("store i64 {}, i64* {}", val, slot));
+            }
+            Stmt::Assign(name, expr) => {
+                let val = self.compile_expr(expr)?;
+                let slot = self.lookup(*name)?;
+                self.emit(&format!("store i64 {}, i64* {}", val, slot));
             }
             Stmt::Return(expr_opt) => {
-                if let Some(expr) = expr_opt {
-                    let val = self.compile_expr(expr)?;
-                    self.output.push_str(&format!("  ret i64 {}\n", val));
-                } else {
-                    self.output.push_str("  ret i64 0\n");
-                }
+                let val = match expr_opt {
+                    Some(expr) => self.compile_expr(expr)?,
+                    None => "0".to_string(),
+                };
+                self.terminate(&format!("ret i64 {}", val));
             }
+            Stmt::Print(expr) => self.compile_print(expr)?,
             Stmt::If(cond, then_block, else_block) => {
-                let cond_val = self.compile_expr(cond)?;
                 let then_label = self.new_label();
                 let else_label = self.new_label();
                 let end_label = self.new_label();
+                self.branch(cond, &then_label, &else_label)?;
 
-                self.output.push_str(&format!("  %cmp{} = icmp ne i64 {}, 0\n", self.label_counter, cond_val));
-                self.output.push_str(&format!("  br i1 %cmp{}, label %{}, label %{}\n", 
-                    self.label_counter, then_label, else_label));
+                self.label(&then_label);
+                self.compile_block(then_block)?;
+                self.emit(&format!("br label %{}", end_label));
 
-                self.output.push_str(&format!("{}:\n", then_label));
-                for s in then_block {
-                    self.compile_stmt(s)?;
-                }
-                self.output.push_str(&format!("  br label %{}\n", end_label));
-
-                self.output.push_str(&format!("{}:\n", else_label));
+                self.label(&else_label);
                 if let Some(else_stmts) = else_block {
-                    for s in else_stmts {
-                        self.compile_stmt(s)?;
-                    }
+                    self.compile_block(else_stmts)?;
                 }
-                self.output.push_str(&format!("  br label %{}\n", end_label));
+                self.emit(&format!("br label %{}", end_label));
 
-                self.output.push_str(&format!("{}:\n", end_label));
+                self.label(&end_label);
+            }
+            Stmt::While(cond, body) => {
+                let cond_label = self.new_label();
+                let body_label = self.new_label();
+                let end_label = self.new_label();
+                self.emit(&format!("br label %{}", cond_label));
+                self.label(&cond_label);
+                self.branch(cond, &body_label, &end_label)?;
+
+                self.label(&body_label);
+                self.loops.push((cond_label.clone(), end_label.clone()));
+                let result = self.compile_block(body);
+                self.loops.pop();
+                result?;
+                self.emit(&format!("br label %{}", cond_label));
+
+                self.label(&end_label);
+            }
+            Stmt::Break | Stmt::Continue => {
+                let (continue_label, break_label) = self.loops.last().cloned().ok_or("break/continue outside a loop")?;
+                let target = if matches!(stmt, Stmt::Break) { break_label } else { continue_label };
+                self.terminate(&format!("br label %{}", target));
             }
             Stmt::Expr(expr) => {
                 self.compile_expr(expr)?;
             }
-            _ => {}
+            Stmt::Block(stmts) => self.compile_block(stmts)?,
+            Stmt::IndexAssign(..) => return Err("index assignment is not supported yet".to_string()),
+            Stmt::FieldAssign(..) => return Err("field assignment is not supported yet".to_string()),
+            Stmt::Defer(_) => return Err("defer is not supported yet".to_string()),
+            Stmt::Match(..) => return Err("match is not supported yet".to_string()),
+            Stmt::Try(..) | Stmt::Throw(_) => return Err("try/throw is not supported yet".to_string()),
+            Stmt::With(..) => return Err("with is not supported yet".to_string()),
+            Stmt::At(_, _, inner) => self.compile_stmt(inner)?,
         }
         Ok(())
     }
 
-    fn compile_expr(&mut self, expr: &Expr) -> Result<String, String> {
-        self.func_counter += 1;
-        let tmp = format!("%t{}", self.func_counter);
+    /// `print` takes an int expression or a string literal
+    fn compile_print(&mut self, expr: &Expr) -> Result<(), String> {
+        let tmp = self.new_tmp();
+        if let Expr::String(s) = expr.inner() {
+            let (name, len) = self.string_constant(s);
+            self.emit(&format!(
+                "{} = call i32 (i8*, ...) @printf(i8* getelementptr ([4 x i8], [4 x i8]* @.str_s, i32 0, i32 0), i8* getelementptr ([{} x i8], [{} x i8]* {}, i32 0, i32 0))",
+                tmp, len, len, name
+            ));
+        } else {
+            let val = self.compile_expr(expr)?;
+            self.emit(&format!(
+                "{} = call i32 (i8*, ...) @printf(i8* getelementptr ([5 x i8], [5 x i8]* @.str_int, i32 0, i32 0), i64 {})",
+                tmp, val
+            ));
+        }
+        Ok(())
+    }
+
+    /// Define a NUL-terminated constant, returning its name and array length
+    fn string_constant(&mut self, s: &str) -> (String, usize) {
+        if let Some(existing) = self.string_names.get(s) {
+            return existing.clone();
+        }
+        self.string_counter += 1;
+        let name = format!("@.s{}", self.string_counter);
+        let mut escaped = String::new();
+        for b in s.bytes() {
+            if (b' '..=b'~').contains(&b) && b != b'"' && b != b'\\' {
+                escaped.push(b as char);
+            } else {
+                escaped.push_str(&format!("\\{:02X}", b));
+            }
+        }
+        let len = s.len() + 1;
+        self.strings.push_str(&format!("{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"\n", name, len, escaped));
+        self.string_names.insert(s.to_string(), (name.clone(), len));
+        (name, len)
+    }
 
+    /// With checks on, branch to a `cryo_panic_at` call when `cond` is true.
+    /// The frame address lets the runtime walk the stack for a backtrace.
+    fn trap_if(&mut self, cond: &str, message: &str) {
+        let Some(path) = self.checks.clone() else { return };
+        let (msg, msg_len) = self.string_constant(&format!("{} in {}()", message, self.func_name));
+        let (file, file_len) = self.string_constant(&path);
+        let panic_label = self.new_label();
+        let ok_label = self.new_label();
+        self.emit(&format!("br i1 {}, label %{}, label %{}", cond, panic_label, ok_label));
+        self.label(&panic_label);
+        let frame = self.new_tmp();
+        self.emit(&format!("{} = call i8* @llvm.frameaddress.p0i8(i32 0)", frame));
+        self.emit(&format!(
+            "call void @cryo_panic_at(i8* getelementptr ([{} x i8], [{} x i8]* {}, i32 0, i32 0), i8* getelementptr ([{} x i8], [{} x i8]* {}, i32 0, i32 0), i64 {}, i8* {})",
+            msg_len, msg_len, msg, file_len, file_len, file, self.line, frame
+        ));
+        self.emit("unreachable");
+        self.label(&ok_label);
+    }
+
+    /// `l op r` for add/sub/mul, trapping on overflow when checks are on
+    fn arith(&mut self, op: &str, l: &str, r: &str) -> String {
+        let tmp = self.new_tmp();
+        if self.checks.is_none() {
+            self.emit(&format!("{} = {} i64 {}, {}", tmp, op, l, r));
+            return tmp;
+        }
+        let pair = self.new_tmp();
+        self.emit(&format!("{} = call {{ i64, i1 }} @llvm.s{}.with.overflow.i64(i64 {}, i64 {})", pair, op, l, r));
+        self.emit(&format!("{} = extractvalue {{ i64, i1 }} {}, 0", tmp, pair));
+        let overflow = self.new_tmp();
+        self.emit(&format!("{} = extractvalue {{ i64, i1 }} {}, 1", overflow, pair));
+        self.trap_if(&overflow, "integer overflow");
+        tmp
+    }
+
+    /// `icmp` result widened back to an i64 0/1
+    fn compare(&mut self, pred: &str, l: &str, r: &str) -> String {
+        let flag = self.new_tmp();
+        self.emit(&format!("{} = icmp {} i64 {}, {}", flag, pred, l, r));
+        let tmp = self.new_tmp();
+        self.emit(&format!("{} = zext i1 {} to i64", tmp, flag));
+        tmp
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> Result<String, String> {
         match expr {
             Expr::Number(n) => Ok(format!("{}", n)),
+            Expr::Bool(b) => Ok(if *b { "1" } else { "0" }.to_string()),
+            Expr::Null => Ok("0".to_string()),
             Expr::Identifier(name) => {
-                self.output.push_str(&format!("  {} = load i64, i64* %{}.addr\n", tmp, name));
+                let slot = self.lookup(*name)?;
+                let tmp = self.new_tmp();
+                self.emit(&format!("{} = load i64, i64* {}", tmp, slot));
                 Ok(tmp)
             }
             Expr::BinOp(left, op, right) => {
@@ -148,55 +538,202 @@ impl Compiler {
                     "*" => "mul",
                     "/" => "sdiv",
                     "%" => "srem",
-                    "<" => {
-                        self.output.push_str(&format!("  %cmp{} = icmp slt i64 {}, {}\n", self.func_counter, l, r));
-                        self.output.push_str(&format!("  {} = zext i1 %cmp{} to i64\n", tmp, self.func_counter));
+                    "<" => return Ok(self.compare("slt", &l, &r)),
+                    ">" => return Ok(self.compare("sgt", &l, &r)),
+                    "<=" => return Ok(self.compare("sle", &l, &r)),
+                    ">=" => return Ok(self.compare("sge", &l, &r)),
+                    "==" => return Ok(self.compare("eq", &l, &r)),
+                    "!=" => return Ok(self.compare("ne", &l, &r)),
+                    // Both sides are evaluated, as in the interpreter
+                    "&&" | "||" => {
+                        let a = self.compare("ne", &l, "0");
+                        let b = self.compare("ne", &r, "0");
+                        let tmp = self.new_tmp();
+                        self.emit(&format!("{} = {} i64 {}, {}", tmp, if op == "&&" { "and" } else { "or" }, a, b));
                         return Ok(tmp);
                     }
-                    ">" => {
-                        self.output.push_str(&format!("  %cmp{} = icmp sgt i64 {}, {}\n", self.func_counter, l, r));
-                        self.output.push_str(&format!("  {} = zext i1 %cmp{} to i64\n", tmp, self.func_counter));
-                        return Ok(tmp);
-                    }
-                    "==" => {
-                        self.output.push_str(&format!("  %cmp{} = icmp eq i64 {}, {}\n", self.func_counter, l, r));
-                        self.output.push_str(&format!("  {} = zext i1 %cmp{} to i64\n", tmp, self.func_counter));
-                        return Ok(tmp);
-                    }
-                    _ => "add"
+                    other => return Err(format!("operator {} is not supported yet", other)),
                 };
-                self.output.push_str(&format!("  {} = {} i64 {}, {}\n", tmp, op_str, l, r));
-                Ok(tmp)
+                if (op_str == "sdiv" || op_str == "srem") && self.checks.is_some() {
+                    let is_zero = self.new_tmp();
+                    self.emit(&format!("{} = icmp eq i64 {}, 0", is_zero, r));
+                    self.trap_if(&is_zero, "division by zero");
+                    // i64::MIN / -1 doesn't fit
+                    let is_min = self.new_tmp();
+                    self.emit(&format!("{} = icmp eq i64 {}, {}", is_min, l, i64::MIN));
+                    let is_neg_one = self.new_tmp();
+                    self.emit(&format!("{} = icmp eq i64 {}, -1", is_neg_one, r));
+                    let overflow = self.new_tmp();
+                    self.emit(&format!("{} = and i1 {}, {}", overflow, is_min, is_neg_one));
+                    self.trap_if(&overflow, "integer overflow");
+                    let tmp = self.new_tmp();
+                    self.emit(&format!("{} = {} i64 {}, {}", tmp, op_str, l, r));
+                    return Ok(tmp);
+                }
+                if op_str == "sdiv" || op_str == "srem" {
+                    // Unchecked, division by zero gives 0 instead of UB
+                    let is_zero = self.new_tmp();
+                    self.emit(&format!("{} = icmp eq i64 {}, 0", is_zero, r));
+                    let divisor = self.new_tmp();
+                    self.emit(&format!("{} = select i1 {}, i64 1, i64 {}", divisor, is_zero, r));
+                    let quotient = self.new_tmp();
+                    self.emit(&format!("{} = {} i64 {}, {}", quotient, op_str, l, divisor));
+                    let tmp = self.new_tmp();
+                    self.emit(&format!("{} = select i1 {}, i64 0, i64 {}", tmp, is_zero, quotient));
+                    return Ok(tmp);
+                }
+                Ok(self.arith(op_str, &l, &r))
+            }
+            Expr::UnaryOp(op, inner) => {
+                let val = self.compile_expr(inner)?;
+                if op == "!" {
+                    return Ok(self.compare("eq", &val, "0"));
+                }
+                Ok(self.arith("sub", "0", &val))
             }
             Expr::Call(name, args) => {
                 if name == "print" {
-                    // For now, print integers
                     if let Some(arg) = args.first() {
-                        let val = self.compile_expr(arg)?;
-                        self.output.push_str(&format!(
-                            "  call i32 (i8*, ...) @printf(i8* getelementptr ([5 x i8], [5 x i8]* @.str_int, i32 0, i32 0), i64 {})\n",
-                            val
-                        ));
+                        self.compile_print(arg)?;
                     }
                     return Ok("0".to_string());
                 }
+                let arity = *self.signatures.get(name)
+                    .ok_or_else(|| format!("call to '{}', which is not defined in this program", name))?;
 
-                // Compile arguments
+                // Missing arguments are 0 (null) and extra ones are dropped, as in the interpreter
                 let mut arg_vals = Vec::new();
                 for arg in args {
                     arg_vals.push(self.compile_expr(arg)?);
                 }
+                arg_vals.resize(arity, "0".to_string());
 
-                let args_str: Vec<String> = arg_vals.iter().map(|a| format!("i64 {}", a)).collect();
-                self.output.push_str(&format!("  {} = call i64 @{}({})\n", tmp, name, args_str.join(", ")));
-                Ok(tmp)
+                let Some((params, ret)) = self.extern_types.get(name).cloned() else {
+                    let args_str: Vec<String> = arg_vals.iter().map(|a| format!("i64 {}", a)).collect();
+                    let tmp = self.new_tmp();
+                    self.emit(&format!("{} = call i64 @{}({})", tmp, name, args_str.join(", ")));
+                    return Ok(tmp);
+                };
+                // Ints convert to and from C floating point at the call
+                let mut args_str = Vec::new();
+                for (val, ty) in arg_vals.iter().zip(params) {
+                    if ty == "i64" {
+                        args_str.push(format!("i64 {}", val));
+                    } else {
+                        let conv = self.new_tmp();
+                        self.emit(&format!("{} = sitofp i64 {} to {}", conv, val, ty));
+                        args_str.push(format!("{} {}", ty, conv));
+                    }
+                }
+                let call = format!("call {} @{}({})", ret, name, args_str.join(", "));
+                if ret == "void" {
+                    self.emit(&call);
+                    return Ok("0".to_string());
+                }
+                let tmp = self.new_tmp();
+                self.emit(&format!("{} = {}", tmp, call));
+                if ret == "i64" {
+                    return Ok(tmp);
+                }
+                let int = self.new_tmp();
+                self.emit(&format!("{} = fptosi {} {} to i64", int, ret, tmp));
+                Ok(int)
+            }
+            Expr::String(_) => Err("strings are only supported as print arguments".to_string()),
+            Expr::At(_, inner) => self.compile_expr(inner),
+            other => {
+                let kind = format!("{:?}", other);
+                Err(format!("{} expressions are not supported yet", kind.split('(').next().unwrap_or_default()))
             }
-            _ => Ok("0".to_string())
         }
     }
 }
 
+/// LLVM type for an extern parameter or return annotation; anything that
+/// isn't floating point is passed as i64
+fn c_type(typ: Option<&str>) -> &'static str {
+    match typ {
+        Some("f64") | Some("float") => "double",
+        Some("f32") => "float",
+        _ => "i64",
+    }
+}
+
 pub fn compile_to_llvm(source: &str) -> Result<String, String> {
     let mut compiler = Compiler::new();
     compiler.compile(source)
 }
+
+pub fn compile_items_to_llvm(items: &[TopLevel]) -> Result<String, String> {
+    let mut compiler = Compiler::new();
+    compiler.compile_items(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lowering() {
+        let ir = compile_to_llvm(r#"
+let LIMIT = 10;
+fn main() {
+    let i = 0;
+    let total = 0;
+    while (i < LIMIT) {
+        i = i + 1;
+        if (i % 2 == 0) { continue; }
+        total = total + i / 0;
+    }
+    print("done");
+    print(total);
+    return 0;
+}
+"#).unwrap();
+        assert!(ir.contains("@LIMIT = global i64 10"));
+        // Loop locals are allocated once, in the entry block
+        let entry = &ir[ir.find("entry:").unwrap()..ir.find("store").unwrap()];
+        assert_eq!(entry.matches("alloca").count(), 2);
+        assert!(ir.contains("c\"done\\00\""));
+        assert!(ir.contains("select i1"));
+
+        // Floating-point externs convert at the call boundary
+        let ir = compile_to_llvm("extern \"C\" { fn pow(x: f64, y: f64) -> f64; fn srand(seed); }\nfn main() { return pow(2, 10); }").unwrap();
+        assert!(ir.contains("declare double @pow(double, double)"));
+        assert!(ir.contains("sitofp i64 2 to double"));
+        assert!(ir.contains("fptosi double"));
+
+        let err = compile_to_llvm("fn main() { return len([1]); }").unwrap_err();
+        assert!(err.starts_with("fn main: call to 'len'"), "{}", err);
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let (tokens, lines) = lexer::tokenize_with_lines("fn add(a, b) {\n    return a + b;\n}\n\nfn main() {\n    print(add(1, 2));\n}\n");
+        let items = Parser::with_lines(&tokens, &lines).parse().unwrap();
+        let ir = Compiler::new().with_debug_info("dir/prog.cryo").compile_items(&items).unwrap();
+        assert!(ir.contains("!DIFile(filename: \"prog.cryo\""));
+        assert!(ir.contains("define i64 @main() !dbg !"));
+        assert!(ir.contains("name: \"main\", scope: !1, file: !1, line: 5"));
+        assert!(ir.contains("!DILocalVariable(name: \"b\", arg: 2"));
+        // Every instruction in a function with a subprogram needs a location
+        for line in ir.lines().filter(|l| l.starts_with("  ") && !l.contains("alloca")) {
+            assert!(line.contains("!dbg"), "{}", line);
+        }
+        assert!(!compile_items_to_llvm(&items).unwrap().contains("!dbg"));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let (tokens, lines) = lexer::tokenize_with_lines("fn div(a, b) {\n    return a / b + 1;\n}\n");
+        let items = Parser::with_lines(&tokens, &lines).parse().unwrap();
+        let ir = Compiler::new().with_checks("prog.cryo").compile_items(&items).unwrap();
+        assert!(ir.contains("declare void @cryo_panic_at(i8*, i8*, i64, i8*) noreturn"));
+        assert!(ir.contains("call { i64, i1 } @llvm.sadd.with.overflow.i64"));
+        assert!(ir.contains("c\"division by zero in div()\\00\""));
+        assert!(ir.contains("i64 1, i8* %"));
+        assert!(ir.contains("define i64 @div(i64 %a, i64 %b) #0"));
+        let unchecked = compile_items_to_llvm(&items).unwrap();
+        assert!(!unchecked.contains("cryo_panic_at") && !unchecked.contains("with.overflow"));
+    }
+}
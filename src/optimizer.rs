@@ -1,37 +1,85 @@
 // Cryo AST Optimizer
-// Performs primitive constant folding
+// Performs primitive constant folding. `explain` also reports each rewrite,
+// for `cryo optimize --explain`.
 
-use crate::parser::{Expr, Stmt, TopLevel};
+use crate::parser::{Expr, MatchArm, Stmt, TopLevel};
+use crate::stack_guard;
+use std::cell::RefCell;
 
-pub struct Optimizer;
+/// One rule firing, as reported by `Optimizer::explain`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rewrite {
+    /// `main`, `Point.norm`, or `let LIMIT` for a global
+    pub item: String,
+    /// Line of the enclosing `fn`, 0 for globals
+    pub line: usize,
+    pub rule: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+pub struct Optimizer {
+    // Rewrites so far and the item being optimized, when explaining
+    log: Option<RefCell<(Vec<Rewrite>, String, usize)>>,
+}
 
 impl Optimizer {
     pub fn new() -> Self {
-        Optimizer
+        Optimizer { log: None }
     }
 
     pub fn optimize(&self, ast: Vec<TopLevel>) -> Vec<TopLevel> {
         ast.into_iter().map(|item| self.optimize_toplevel(item)).collect()
     }
 
+    /// `optimize`, plus every rewrite in the order it was made
+    pub fn explain(ast: Vec<TopLevel>) -> (Vec<TopLevel>, Vec<Rewrite>) {
+        let optimizer = Optimizer { log: Some(RefCell::new((Vec::new(), String::new(), 0))) };
+        let ast = optimizer.optimize(ast);
+        let rewrites = optimizer.log.map(|log| log.into_inner().0).unwrap_or_default();
+        (ast, rewrites)
+    }
+
+    fn enter(&self, item: String, line: usize) {
+        if let Some(log) = &self.log {
+            let mut log = log.borrow_mut();
+            log.1 = item;
+            log.2 = line;
+        }
+    }
+
+    fn fired(&self, rule: &'static str, before: impl FnOnce() -> String, after: impl FnOnce() -> String) {
+        if let Some(log) = &self.log {
+            let mut log = log.borrow_mut();
+            let rewrite = Rewrite { item: log.1.clone(), line: log.2, rule, before: before(), after: after() };
+            log.0.push(rewrite);
+        }
+    }
+
     fn optimize_toplevel(&self, item: TopLevel) -> TopLevel {
         match item {
             TopLevel::Function(mut f) => {
                 if let Some(body) = f.body {
+                    self.enter(f.name.to_string(), f.line);
                     f.body = Some(self.optimize_stmts(body));
                 }
                 TopLevel::Function(f)
             }
             TopLevel::Impl(mut impl_def) => {
+                let type_name = impl_def.type_name;
                 impl_def.methods = impl_def.methods.into_iter().map(|mut m| {
                     if let Some(body) = m.body {
+                        self.enter(format!("{}.{}", type_name, m.name), m.line);
                         m.body = Some(self.optimize_stmts(body));
                     }
                     m
                 }).collect();
                 TopLevel::Impl(impl_def)
             }
-            TopLevel::Let(name, expr) => TopLevel::Let(name, self.optimize_expr(expr)),
+            TopLevel::Let(name, expr) => {
+                self.enter(format!("let {}", name), 0);
+                TopLevel::Let(name, self.optimize_expr(expr))
+            }
             _ => item,
         }
     }
@@ -41,6 +89,10 @@ impl Optimizer {
     }
 
     fn optimize_stmt(&self, stmt: Stmt) -> Stmt {
+        stack_guard::guard(|| self.optimize_stmt_inner(stmt))
+    }
+
+    fn optimize_stmt_inner(&self, stmt: Stmt) -> Stmt {
         match stmt {
             Stmt::Let(name, typ, expr) => Stmt::Let(name, typ, self.optimize_expr(expr)),
             Stmt::Assign(name, expr) => Stmt::Assign(name, self.optimize_expr(expr)),
@@ -54,8 +106,13 @@ impl Optimizer {
 
                 // Const if optimization
                 match &cond {
-                    Expr::Bool(true) => Stmt::Block(then_block),
+                    Expr::Bool(true) => {
+                        self.fired("const-if", || format!("if {}", cond), || "then branch".to_string());
+                        Stmt::Block(then_block)
+                    }
                     Expr::Bool(false) => {
+                        let kept = if else_block.is_some() { "else branch" } else { "nothing" };
+                        self.fired("const-if", || format!("if {}", cond), || kept.to_string());
                         if let Some(else_stmts) = else_block {
                             Stmt::Block(else_stmts)
                         } else {
@@ -70,7 +127,10 @@ impl Optimizer {
                 let body = self.optimize_stmts(body);
                 // While false -> remove?
                  match &cond {
-                    Expr::Bool(false) => Stmt::Block(vec![]),
+                    Expr::Bool(false) => {
+                        self.fired("dead-while", || "while false".to_string(), || "nothing".to_string());
+                        Stmt::Block(vec![])
+                    }
                     _ => Stmt::While(cond, body)
                 }
             }
@@ -78,17 +138,42 @@ impl Optimizer {
             Stmt::Defer(stmt) => Stmt::Defer(Box::new(self.optimize_stmt(*stmt))),
             Stmt::FieldAssign(obj, f, val) => Stmt::FieldAssign(self.optimize_expr(obj), f, self.optimize_expr(val)),
             Stmt::IndexAssign(arr, idx, val) => Stmt::IndexAssign(self.optimize_expr(arr), self.optimize_expr(idx), self.optimize_expr(val)),
+            Stmt::Match(subject, arms) => Stmt::Match(self.optimize_expr(subject), self.optimize_arms(arms)),
+            Stmt::Try(body, name, handler) => Stmt::Try(self.optimize_stmts(body), name, self.optimize_stmts(handler)),
+            Stmt::Throw(expr) => Stmt::Throw(self.optimize_expr(expr)),
+            Stmt::With(name, expr, body) => Stmt::With(name, self.optimize_expr(expr), self.optimize_stmts(body)),
+            Stmt::At(line, col, stmt) => Stmt::At(line, col, Box::new(self.optimize_stmt(*stmt))),
             _ => stmt,
         }
     }
 
-    fn optimize_expr(&self, expr: Expr) -> Expr {
+    fn optimize_arms(&self, arms: Vec<MatchArm>) -> Vec<MatchArm> {
+        arms.into_iter().map(|arm| MatchArm {
+            pattern: arm.pattern,
+            guard: arm.guard.map(|g| self.optimize_expr(g)),
+            body: self.optimize_stmts(arm.body),
+        }).collect()
+    }
+
+    /// One expression, folded as far as the rules go
+    pub fn optimize_expr(&self, expr: Expr) -> Expr {
+        stack_guard::guard(|| self.optimize_expr_inner(expr))
+    }
+
+    fn optimize_expr_inner(&self, expr: Expr) -> Expr {
         match expr {
             Expr::BinOp(left, op, right) => {
                 let l = self.optimize_expr(*left);
                 let r = self.optimize_expr(*right);
+                let before = self.log.as_ref().map(|_| Expr::BinOp(Box::new(l.clone()), op.clone(), Box::new(r.clone())));
+
+                let rule = match op.as_str() {
+                    "+" | "-" | "*" | "/" | "%" => "fold-arithmetic",
+                    "&&" | "||" => "fold-logic",
+                    _ => "fold-comparison",
+                };
 
-                match (l, op.as_str(), r) {
+                let folded = match (l, op.as_str(), r) {
                     // Int Arithmetic
                     (Expr::Number(a), "+", Expr::Number(b)) => Expr::Number(a + b),
                     (Expr::Number(a), "-", Expr::Number(b)) => Expr::Number(a - b),
@@ -116,13 +201,23 @@ impl Optimizer {
 
                     // Fallback
                     (l, op, r) => Expr::BinOp(Box::new(l), op.to_string(), Box::new(r)),
+                };
+                if let (Some(before), Expr::Number(_) | Expr::Bool(_)) = (before, &folded) {
+                    self.fired(rule, || before.to_string(), || folded.to_string());
                 }
+                folded
             }
             Expr::UnaryOp(op, expr) => {
                 let e = self.optimize_expr(*expr);
                 match (op.as_str(), e) {
-                    ("-", Expr::Number(a)) => Expr::Number(-a),
-                    ("!", Expr::Bool(a)) => Expr::Bool(!a),
+                    ("-", Expr::Number(a)) => {
+                        self.fired("fold-negation", || format!("(- {})", a), || (-a).to_string());
+                        Expr::Number(-a)
+                    }
+                    ("!", Expr::Bool(a)) => {
+                        self.fired("fold-negation", || format!("(! {})", a), || (!a).to_string());
+                        Expr::Bool(!a)
+                    }
                     (op, e) => Expr::UnaryOp(op.to_string(), Box::new(e)),
                 }
             }
@@ -148,8 +243,43 @@ impl Optimizer {
                 let fields = fields.into_iter().map(|(k, v)| (k, self.optimize_expr(v))).collect();
                 Expr::StructInit(name, fields)
             }
+            Expr::MapLiteral(entries) => {
+                Expr::MapLiteral(entries.into_iter().map(|(k, v)| (k, self.optimize_expr(v))).collect())
+            }
+            Expr::Match(subject, arms) => Expr::Match(Box::new(self.optimize_expr(*subject)), self.optimize_arms(arms)),
+            Expr::Propagate(inner) => Expr::Propagate(Box::new(self.optimize_expr(*inner))),
+            // A span stays on what's left to evaluate; a folded constant can't fail
+            Expr::At(span, inner) => match self.optimize_expr(*inner) {
+                folded @ (Expr::Number(_) | Expr::Float(_) | Expr::String(_) | Expr::Bool(_) | Expr::Null) => folded,
+                inner => Expr::At(span, Box::new(inner)),
+            },
             // Leaf nodes
             _ => expr,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_explain() {
+        let source = "let DAY = 24 * 60 * 60;\nfn main() {\n    if (1 < 2) { print(-3); }\n    print(x + 1);\n}\n";
+        let (tokens, lines) = lexer::tokenize_with_lines(source);
+        let ast = Parser::with_lines(&tokens, &lines).parse().unwrap();
+        let (optimized, rewrites) = Optimizer::explain(ast.clone());
+        let fired: Vec<_> = rewrites.iter().map(|r| (r.item.as_str(), r.line, r.rule, r.before.as_str(), r.after.as_str())).collect();
+        assert_eq!(fired, [
+            ("let DAY", 0, "fold-arithmetic", "(* 24 60)", "1440"),
+            ("let DAY", 0, "fold-arithmetic", "(* 1440 60)", "86400"),
+            ("main", 2, "fold-comparison", "(< 1 2)", "true"),
+            ("main", 2, "fold-negation", "(- 3)", "-3"),
+            ("main", 2, "const-if", "if true", "then branch"),
+        ]);
+        let plain = crate::ast_display::program(&Optimizer::new().optimize(ast));
+        assert_eq!(crate::ast_display::program(&optimized), plain);
+    }
+}
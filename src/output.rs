@@ -0,0 +1,97 @@
+// ============================================
+// Cryo Program Output
+// Everything a program prints goes through here so it can be captured:
+// run_script() runs a child interpreter on the same thread and collects what
+// it prints instead of letting it reach the process's stdout. Captures nest;
+// output goes to the innermost one. Uncaptured output goes to the thread's
+// platform (see platform.rs), or the process's stdout if none was set.
+// ============================================
+
+use crate::platform::Platform;
+use std::cell::RefCell;
+use std::io::Write;
+use std::sync::Arc;
+
+thread_local! {
+    static CAPTURES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static PLATFORM: RefCell<Option<Arc<dyn Platform>>> = const { RefCell::new(None) };
+}
+
+/// Send this thread's uncaptured output to `platform`
+pub fn set_platform(platform: Arc<dyn Platform>) {
+    PLATFORM.with(|current| *current.borrow_mut() = Some(platform));
+}
+
+/// Uncaptured output: the thread's platform, else stdout
+fn emit(text: &str) {
+    let platform = PLATFORM.with(|current| current.borrow().clone());
+    match platform {
+        Some(platform) => platform.write_stdout(text),
+        None => {
+            let _ = std::io::stdout().lock().write_all(text.as_bytes());
+        }
+    }
+}
+
+/// Print `text` to stdout, or append it to the innermost capture
+pub fn write(text: &str) {
+    let captured = CAPTURES.with(|captures| match captures.borrow_mut().last_mut() {
+        Some(buffer) => {
+            buffer.push_str(text);
+            true
+        }
+        None => false,
+    });
+    if !captured {
+        emit(text);
+    }
+}
+
+/// `write` plus a newline
+pub fn write_line(text: &str) {
+    let captured = CAPTURES.with(|captures| match captures.borrow_mut().last_mut() {
+        Some(buffer) => {
+            buffer.push_str(text);
+            buffer.push('\n');
+            true
+        }
+        None => false,
+    });
+    if !captured {
+        emit(&format!("{}\n", text));
+    }
+}
+
+/// Run `f`, returning its result and everything it printed on this thread
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, String) {
+    /// Pops the capture even if `f` panics, so later output isn't swallowed
+    struct Pop;
+    impl Drop for Pop {
+        fn drop(&mut self) {
+            CAPTURES.with(|captures| captures.borrow_mut().pop());
+        }
+    }
+    CAPTURES.with(|captures| captures.borrow_mut().push(String::new()));
+    let pop = Pop;
+    let result = f();
+    let text = CAPTURES.with(|captures| captures.borrow_mut().last_mut().map(std::mem::take)).unwrap_or_default();
+    drop(pop);
+    (result, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_capture() {
+        let (inner, outer) = capture(|| {
+            write("a");
+            let ((), inner) = capture(|| write_line("b"));
+            write_line("c");
+            inner
+        });
+        assert_eq!(inner, "b\n");
+        assert_eq!(outer, "ac\n");
+    }
+}
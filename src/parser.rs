@@ -3,34 +3,60 @@
 
 #![allow(dead_code)]
 
-use crate::lexer::Token;
+use crate::lexer::{Span, StrPart, Token};
+use crate::intern::Symbol;
+use crate::int_types::IntType;
+use crate::stack_guard;
 
 #[derive(Debug, Clone)]
 pub enum Expr {
     Number(i64),
+    Float(f64),
     String(String),
     Bool(bool),
     Null,
-    Identifier(String),
+    Identifier(Symbol),
     BinOp(Box<Expr>, String, Box<Expr>),
     UnaryOp(String, Box<Expr>),
-    Call(String, Vec<Expr>),
-    MethodCall(Box<Expr>, String, Vec<Expr>),
+    Call(Symbol, Vec<Expr>),
+    MethodCall(Box<Expr>, Symbol, Vec<Expr>),
     Index(Box<Expr>, Box<Expr>),
-    Field(Box<Expr>, String),
+    Field(Box<Expr>, Symbol),
     Array(Vec<Expr>),
-    StructInit(String, Vec<(String, Expr)>),
-    ObjectLiteral(Vec<(String, Expr)>),  // Anonymous object: { key: value }
+    StructInit(Symbol, Vec<(Symbol, Expr)>),
+    ObjectLiteral(Vec<(Symbol, Expr)>),  // Anonymous object: { key: value }
+    MapLiteral(Vec<(String, Expr)>),  // Hash map: { "key": value }
     Await(Box<Expr>),
-    StaticMethodCall(String, String, Vec<Expr>),
+    StaticMethodCall(Symbol, Symbol, Vec<Expr>),
+    /// `Enum::Variant`
+    Variant(Symbol, Symbol),
+    /// `match subject { arms }` as a value: every arm body is one `Stmt::Expr`
+    Match(Box<Expr>, Vec<MatchArm>),
+    /// `value?`: the payload of `Result::Ok` or `Option::Some`; `Result::Err`
+    /// and `Option::None` are returned from the enclosing function
+    Propagate(Box<Expr>),
+    /// An operation and the bytes of source it was parsed from, for runtime
+    /// error carets. Only a parser given a span table adds these, around
+    /// binary operators, calls, indexing and member access.
+    At(Span, Box<Expr>),
+}
+
+impl Expr {
+    /// The expression without any `At` span around it
+    pub fn inner(&self) -> &Expr {
+        match self {
+            Expr::At(_, expr) => expr.inner(),
+            expr => expr,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
-    Let(String, Option<String>, Expr),
-    Assign(String, Expr),
+    Let(Symbol, Option<String>, Expr),
+    Assign(Symbol, Expr),
     IndexAssign(Expr, Expr, Expr),
-    FieldAssign(Expr, String, Expr),
+    FieldAssign(Expr, Symbol, Expr),
     Return(Option<Expr>),
     Print(Expr),
     If(Expr, Vec<Stmt>, Option<Vec<Stmt>>),
@@ -40,11 +66,51 @@ pub enum Stmt {
     Expr(Expr),
     Block(Vec<Stmt>),
     Defer(Box<Stmt>),
+    Match(Expr, Vec<MatchArm>),
+    /// `try { body } catch (name) { handler }`
+    Try(Vec<Stmt>, Symbol, Vec<Stmt>),
+    Throw(Expr),
+    /// `with (let name = value) { body }`: the value is closed when the body exits
+    With(Symbol, Expr, Vec<Stmt>),
+    /// A statement and the 1-based line and column it starts on, for runtime
+    /// errors. Only a parser given a column table adds these; everything
+    /// else looks straight through them.
+    At(usize, usize, Box<Stmt>),
+}
+
+impl Stmt {
+    /// The statement without any `At` position around it
+    pub fn inner(&self) -> &Stmt {
+        match self {
+            Stmt::At(_, _, stmt) => stmt.inner(),
+            stmt => stmt,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    /// A number, string, bool or null, compared with `==`
+    Literal(Expr),
+    /// A name, bound to the value
+    Binding(Symbol),
+    /// `Enum::Variant`, or `Enum::Variant(p, ...)` matching its payload
+    Variant(Symbol, Symbol, Vec<Pattern>),
+}
+
+/// `pattern if guard => body`
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Vec<Stmt>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Param {
-    pub name: String,
+    pub name: Symbol,
     pub typ: Option<String>,
 }
 
@@ -57,17 +123,18 @@ pub struct Decorator {
 
 #[derive(Debug, Clone)]
 pub struct Function {
-    pub name: String,
+    pub name: Symbol,
     pub params: Vec<Param>,
     pub body: Option<Vec<Stmt>>, // Body is optional for traits/extern
     pub is_async: bool,
     pub return_type: Option<String>,
     pub decorators: Vec<Decorator>, // @Get, @Post, etc.
+    pub line: usize, // Line of `fn`, 0 when the parser had no line table
 }
 
 #[derive(Debug, Clone)]
 pub struct StructDef {
-    pub name: String,
+    pub name: Symbol,
     pub fields: Vec<(String, String)>,
     pub decorators: Vec<Decorator>, // @Controller, @Injectable, etc.
 }
@@ -75,7 +142,9 @@ pub struct StructDef {
 #[derive(Debug, Clone)]
 pub struct EnumDef {
     pub name: String,
-    pub variants: Vec<String>,
+    /// Each variant's name and the names of its payload values:
+    /// `Ok(value)` is ("Ok", ["value"]), `None` is ("None", [])
+    pub variants: Vec<(String, Vec<String>)>,
 }
 
 #[derive(Debug, Clone)]
@@ -87,7 +156,7 @@ pub struct TraitDef {
 #[derive(Debug, Clone)]
 pub struct ImplDef {
     pub trait_name: String,
-    pub type_name: String,
+    pub type_name: Symbol,
     pub methods: Vec<Function>,
 }
 
@@ -99,8 +168,8 @@ pub struct ExternBlock {
 
 #[derive(Debug, Clone)]
 pub struct MacroDef {
-    pub name: String,
-    pub params: Vec<String>,
+    pub name: Symbol,
+    pub params: Vec<Symbol>,
     pub body: Vec<Stmt>,
 }
 
@@ -109,7 +178,7 @@ pub enum TopLevel {
     Function(Function),
     Struct(StructDef),
     Enum(EnumDef),
-    Let(String, Expr),
+    Let(Symbol, Expr),
     Import(String, Vec<String>),
     Trait(TraitDef),
     Impl(ImplDef),
@@ -117,22 +186,65 @@ pub enum TopLevel {
     Macro(MacroDef),
 }
 
-pub struct Parser {
-    tokens: Vec<Token>,
+/// Sentinel returned when reading past the end of the token stream
+static EOF: Token = Token::Eof;
+
+/// Recursive-descent parser over a borrowed token slice.
+/// Tokens are never cloned; `peek`/`advance` hand out references that
+/// outlive the parser borrow, so AST construction copies only what it keeps.
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    lines: &'a [usize],
+    columns: &'a [usize],
+    spans: &'a [Span],
     pos: usize,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, pos: 0 }
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Parser { tokens, lines: &[], columns: &[], spans: &[], pos: 0 }
+    }
+
+    /// Parser that records source lines, using the table from `lexer::tokenize_with_lines`
+    pub fn with_lines(tokens: &'a [Token], lines: &'a [usize]) -> Self {
+        Parser { tokens, lines, columns: &[], spans: &[], pos: 0 }
+    }
+
+    /// Also wrap each statement in `Stmt::At` with where it starts, using the
+    /// column table from `lexer::tokenize_with_positions`
+    pub fn with_columns(mut self, columns: &'a [usize]) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Also wrap operations in `Expr::At` with the source they span, using
+    /// the span table from `lexer::tokenize_with_positions`
+    pub fn with_spans(mut self, spans: &'a [Span]) -> Self {
+        self.spans = spans;
+        self
+    }
+
+    /// `expr`, parsed from token `start` up to here, wrapped in its span
+    /// when the parser has a span table
+    fn spanned(&self, start: usize, expr: Expr) -> Expr {
+        match (self.spans.get(start), self.pos.checked_sub(1).and_then(|end| self.spans.get(end))) {
+            (Some(first), Some(last)) => Expr::At(Span { start: first.start, end: last.end }, Box::new(expr)),
+            _ => expr,
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.lines.get(self.pos).copied().unwrap_or(0)
     }
     
-    fn peek(&self) -> &Token {
-        self.tokens.get(self.pos).unwrap_or(&Token::Eof)
+    #[inline]
+    fn peek(&self) -> &'a Token {
+        self.tokens.get(self.pos).unwrap_or(&EOF)
     }
     
-    fn advance(&mut self) -> Token {
-        let tok = self.peek().clone();
+    #[inline]
+    fn advance(&mut self) -> &'a Token {
+        let tok = self.peek();
         self.pos += 1;
         tok
     }
@@ -158,22 +270,45 @@ impl Parser {
     fn collect_decorators(&mut self) -> Vec<Decorator> {
         let mut decorators = Vec::new();
         loop {
-            let token = self.peek().clone();
-            let decorator_opt = match token {
-                Token::DecController(arg) => Some(("Controller", arg)),
-                Token::DecGet(arg) => Some(("Get", arg)),
-                Token::DecPost(arg) => Some(("Post", arg)),
-                Token::DecPut(arg) => Some(("Put", arg)),
-                Token::DecDelete(arg) => Some(("Delete", arg)),
-                Token::DecPatch(arg) => Some(("Patch", arg)),
+            let decorator_opt = match self.peek() {
+                Token::DecController(arg) => Some(("Controller", arg.clone())),
+                Token::DecGet(arg) => Some(("Get", arg.clone())),
+                Token::DecPost(arg) => Some(("Post", arg.clone())),
+                Token::DecPut(arg) => Some(("Put", arg.clone())),
+                Token::DecDelete(arg) => Some(("Delete", arg.clone())),
+                Token::DecPatch(arg) => Some(("Patch", arg.clone())),
                 Token::DecInjectable => Some(("Injectable", "".to_string())),
                 Token::DecModule => Some(("Module", "".to_string())),
                 Token::DecBody => Some(("Body", "".to_string())),
-                Token::DecParam(arg) => Some(("Param", arg)),
-                Token::DecQuery(arg) => Some(("Query", arg)),
-                Token::DecGuard(arg) => Some(("Guard", arg)),
-                Token::DecMiddleware(arg) => Some(("Middleware", arg)),
+                Token::DecParam(arg) => Some(("Param", arg.clone())),
+                Token::DecQuery(arg) => Some(("Query", arg.clone())),
+                Token::DecGuard(arg) => Some(("Guard", arg.clone())),
+                Token::DecMiddleware(arg) => Some(("Middleware", arg.clone())),
                 
+                // Any other `@name` (lexed as At + Identifier) is kept as a
+                // marker, e.g. @bytecode
+                Token::At if matches!(self.tokens.get(self.pos + 1), Some(Token::Identifier(_))) => {
+                    self.advance();
+                    let name = match self.advance() {
+                        Token::Identifier(name) => name.as_str().to_string(),
+                        _ => unreachable!(),
+                    };
+                    let mut arg = String::new();
+                    if self.match_token(&Token::LParen) {
+                        while self.peek() != &Token::RParen && self.peek() != &Token::Eof {
+                            match self.advance() {
+                                Token::String(s) => arg.push_str(s),
+                                Token::Identifier(s) => arg.push_str(s.as_str()),
+                                Token::Number(n) => arg.push_str(&n.to_string()),
+                                Token::Float(f) => arg.push_str(&f.to_string()),
+                                _ => {}
+                            }
+                        }
+                        self.advance();
+                    }
+                    decorators.push(Decorator { name, arg });
+                    None
+                }
                 Token::At | Token::WasmExport | Token::WasmImport => {
                     self.advance();
                     if self.peek() == &Token::LParen {
@@ -198,53 +333,58 @@ impl Parser {
     
     pub fn parse(&mut self) -> Result<Vec<TopLevel>, String> {
         let mut items = Vec::new();
-        
-        while self.peek() != &Token::Eof {
-            let decorators = self.collect_decorators();
-            
-            match self.peek() {
-                Token::Fn | Token::Async => {
-                    items.push(TopLevel::Function(self.parse_function_with_decorators(decorators)?));
-                }
-                Token::Struct => {
-                    items.push(TopLevel::Struct(self.parse_struct_with_decorators(decorators)?));
-                }
-                Token::Enum => {
-                    items.push(TopLevel::Enum(self.parse_enum()?));
-                }
-                // ... rest of match arms need to be preserved/modified if decorators apply
-                Token::Let => {
-                    let (name, expr) = self.parse_global_let()?;
-                    items.push(TopLevel::Let(name, expr));
-                }
-                Token::Import => {
-                    let (path, names) = self.parse_import()?;
-                    items.push(TopLevel::Import(path, names));
-                }
-                Token::Extern => {
-                    items.push(TopLevel::Extern(self.parse_extern()?));
-                }
-                Token::Trait => {
-                    items.push(TopLevel::Trait(self.parse_trait()?));
-                }
-                Token::Impl => {
-                    items.push(TopLevel::Impl(self.parse_impl()?));
-                }
-                Token::Macro => {
-                    items.push(TopLevel::Macro(self.parse_macro()?));
-                }
-                _ => return Err(format!("Unexpected token at top level: {:?}", self.peek())),
-            }
+        while !self.at_end() {
+            items.push(self.parse_item()?);
         }
-
-        
         Ok(items)
     }
 
+    /// Whether every token has been consumed
+    pub fn at_end(&self) -> bool {
+        self.peek() == &Token::Eof
+    }
+
+    /// Index of the next token; after an error, the token it was found at
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Parse one top-level item, with its decorators
+    pub fn parse_item(&mut self) -> Result<TopLevel, String> {
+        let decorators = self.collect_decorators();
+        Ok(match self.peek() {
+            Token::Fn | Token::Async => TopLevel::Function(self.parse_function_with_decorators(decorators)?),
+            Token::Struct => TopLevel::Struct(self.parse_struct_with_decorators(decorators)?),
+            Token::Enum => TopLevel::Enum(self.parse_enum()?),
+            Token::Let => {
+                let (name, expr) = self.parse_global_let()?;
+                TopLevel::Let(name, expr)
+            }
+            Token::Import => {
+                let (path, names) = self.parse_import()?;
+                TopLevel::Import(path, names)
+            }
+            Token::Extern => TopLevel::Extern(self.parse_extern()?),
+            Token::Trait => TopLevel::Trait(self.parse_trait()?),
+            Token::Impl => TopLevel::Impl(self.parse_impl()?),
+            Token::Macro => TopLevel::Macro(self.parse_macro()?),
+            _ => return Err(format!("Unexpected token at top level: {:?}", self.peek())),
+        })
+    }
+
+    /// Parse a bare statement list up to end of input (the body of `eval`)
+    pub fn parse_stmts(&mut self) -> Result<Vec<Stmt>, String> {
+        let mut stmts = Vec::new();
+        while self.peek() != &Token::Eof {
+            stmts.push(self.parse_stmt()?);
+        }
+        Ok(stmts)
+    }
+    
     fn parse_macro(&mut self) -> Result<MacroDef, String> {
         self.expect(Token::Macro)?;
         let name = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => *s,
             t => return Err(format!("Expected macro name, got {:?}", t)),
         };
         
@@ -253,7 +393,7 @@ impl Parser {
         if self.peek() != &Token::RParen {
             loop {
                 match self.advance() {
-                    Token::Identifier(s) => params.push(s),
+                    Token::Identifier(s) => params.push(*s),
                     t => return Err(format!("Expected parameter name, got {:?}", t)),
                 }
                 if !self.match_token(&Token::Comma) {
@@ -275,11 +415,12 @@ impl Parser {
     }
     
     fn parse_function_with_decorators(&mut self, decorators: Vec<Decorator>) -> Result<Function, String> {
+        let line = self.line();
         let is_async = self.match_token(&Token::Async);
         self.expect(Token::Fn)?;
         
         let name = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => *s,
             _ => return Err("Expected function name".to_string()),
         };
         
@@ -297,8 +438,8 @@ impl Parser {
         while self.peek() != &Token::RParen {
             // Parse parameter name - allow SelfType as well
             let pname = match self.advance() {
-                Token::Identifier(s) => s,
-                Token::SelfType => "self".to_string(),
+                Token::Identifier(s) => *s,
+                Token::SelfType => Symbol::SELF,
                 _t => {
                     // Return the token so we exit cleanly
                     self.pos -= 1; // Put token back
@@ -335,6 +476,7 @@ impl Parser {
             is_async,
             return_type,
             decorators,
+            line,
         })
     }
     
@@ -358,7 +500,7 @@ impl Parser {
         }
         
         let mut typ = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => s.as_str().to_string(),
             t => return Err(format!("Expected type, got {:?}", t)),
         };
         
@@ -368,7 +510,7 @@ impl Parser {
             self.advance();
             while self.peek() != &Token::Gt && self.peek() != &Token::Eof {
                 match self.advance() {
-                    Token::Identifier(s) => typ.push_str(&s),
+                    Token::Identifier(s) => typ.push_str(s.as_str()),
                     Token::Comma => typ.push(','),
                     _ => {}
                 }
@@ -382,7 +524,7 @@ impl Parser {
     fn parse_trait(&mut self) -> Result<TraitDef, String> {
         self.expect(Token::Trait)?;
         let name = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => s.as_str().to_string(),
             _ => return Err("Expected trait name".to_string()),
         };
         
@@ -399,7 +541,7 @@ impl Parser {
     fn parse_impl(&mut self) -> Result<ImplDef, String> {
         self.expect(Token::Impl)?;
         let name = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => *s,
             _ => return Err("Expected identifier".to_string()),
         };
         
@@ -407,9 +549,9 @@ impl Parser {
         let mut type_name = name; 
         
         if self.match_token(&Token::For) {
-            trait_name = type_name;
+            trait_name = type_name.as_str().to_string();
             type_name = match self.advance() {
-                Token::Identifier(s) => s,
+                Token::Identifier(s) => *s,
                 _ => return Err("Expected type name".to_string()),
             };
         }
@@ -461,7 +603,7 @@ impl Parser {
     fn parse_struct_with_decorators(&mut self, decorators: Vec<Decorator>) -> Result<StructDef, String> {
         self.expect(Token::Struct)?;
         let name = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => *s,
             _ => return Err("Expected struct name".to_string()),
         };
         
@@ -478,7 +620,7 @@ impl Parser {
         let mut fields = Vec::new();
         while self.peek() != &Token::RBrace {
             let fname = match self.advance() {
-                Token::Identifier(s) => s,
+                Token::Identifier(s) => s.as_str().to_string(),
                 _ => break,
             };
             self.expect(Token::Colon)?;
@@ -496,17 +638,31 @@ impl Parser {
     fn parse_enum(&mut self) -> Result<EnumDef, String> {
         self.expect(Token::Enum)?;
         let name = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => s.as_str().to_string(),
             _ => return Err("Expected enum name".to_string()),
         };
         
         self.expect(Token::LBrace)?;
         let mut variants = Vec::new();
         while self.peek() != &Token::RBrace {
-            match self.advance() {
-                Token::Identifier(s) => variants.push(s),
+            let variant = match self.advance() {
+                Token::Identifier(s) => s.as_str().to_string(),
                 _ => break,
+            };
+            let mut fields = Vec::new();
+            if self.match_token(&Token::LParen) {
+                while self.peek() != &Token::RParen {
+                    match self.advance() {
+                        Token::Identifier(s) => fields.push(s.as_str().to_string()),
+                        t => return Err(format!("Expected field name in variant {}, got {:?}", variant, t)),
+                    }
+                    if !self.match_token(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(Token::RParen)?;
             }
+            variants.push((variant, fields));
             self.match_token(&Token::Comma);
         }
         self.expect(Token::RBrace)?;
@@ -514,10 +670,10 @@ impl Parser {
         Ok(EnumDef { name, variants })
     }
     
-    fn parse_global_let(&mut self) -> Result<(String, Expr), String> {
+    fn parse_global_let(&mut self) -> Result<(Symbol, Expr), String> {
         self.expect(Token::Let)?;
         let name = match self.advance() {
-            Token::Identifier(s) => s,
+            Token::Identifier(s) => *s,
             _ => return Err("Expected variable name".to_string()),
         };
         self.expect(Token::Eq)?;
@@ -534,9 +690,8 @@ impl Parser {
         if self.peek() == &Token::LBrace {
             self.advance();
             while self.peek() != &Token::RBrace {
-                match self.advance() {
-                    Token::Identifier(s) => names.push(s),
-                    _ => {}
+                if let Token::Identifier(s) = self.advance() {
+                    names.push(s.as_str().to_string());
                 }
                 self.match_token(&Token::Comma);
             }
@@ -549,7 +704,7 @@ impl Parser {
         }
         
         let path = match self.advance() {
-            Token::String(s) => s,
+            Token::String(s) => s.clone(),
             _ => return Err("Expected import path".to_string()),
         };
         self.match_token(&Token::Semi);
@@ -568,11 +723,21 @@ impl Parser {
     }
     
     fn parse_stmt(&mut self) -> Result<Stmt, String> {
-        match self.peek().clone() {
+        let col = self.columns.get(self.pos).copied();
+        let line = self.line();
+        let stmt = stack_guard::guard(|| self.parse_stmt_inner())?;
+        Ok(match col {
+            Some(col) => Stmt::At(line, col, Box::new(stmt)),
+            None => stmt,
+        })
+    }
+    
+    fn parse_stmt_inner(&mut self) -> Result<Stmt, String> {
+        match self.peek() {
             Token::Let => {
                 self.advance();
                 let name = match self.advance() {
-                    Token::Identifier(s) => s,
+                    Token::Identifier(s) => *s,
                     _ => return Err("Expected variable name".to_string()),
                 };
                 let mut typ = None;
@@ -627,6 +792,11 @@ impl Parser {
                 let body = self.parse_block()?;
                 Ok(Stmt::While(cond, body))
             }
+            Token::Match => {
+                let (subject, arms) = self.parse_match(true)?;
+                self.match_token(&Token::Semi);
+                Ok(Stmt::Match(subject, arms))
+            }
             Token::Break => {
                 self.advance();
                 self.match_token(&Token::Semi);
@@ -646,12 +816,45 @@ impl Parser {
                 let stmt = self.parse_stmt()?;
                 Ok(Stmt::Defer(Box::new(stmt)))
             }
+            Token::Try => {
+                self.advance();
+                let body = self.parse_block()?;
+                self.expect(Token::Catch)?;
+                self.expect(Token::LParen)?;
+                let name = match self.advance() {
+                    Token::Identifier(name) => *name,
+                    t => return Err(format!("Expected error name after catch, got {:?}", t)),
+                };
+                self.expect(Token::RParen)?;
+                let handler = self.parse_block()?;
+                Ok(Stmt::Try(body, name, handler))
+            }
+            Token::Throw => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect(Token::Semi)?;
+                Ok(Stmt::Throw(expr))
+            }
+            Token::With => {
+                self.advance();
+                self.expect(Token::LParen)?;
+                self.expect(Token::Let)?;
+                let name = match self.advance() {
+                    Token::Identifier(name) => *name,
+                    t => return Err(format!("Expected variable name after with (let, got {:?}", t)),
+                };
+                self.expect(Token::Eq)?;
+                let value = self.parse_expr()?;
+                self.expect(Token::RParen)?;
+                let body = self.parse_block()?;
+                Ok(Stmt::With(name, value, body))
+            }
             Token::Identifier(name) => {
                 self.advance();
                 if self.match_token(&Token::Eq) {
                     let expr = self.parse_expr()?;
                     self.expect(Token::Semi)?;
-                    Ok(Stmt::Assign(name, expr))
+                    Ok(Stmt::Assign(*name, expr))
                 } else {
                     // Could be function call or other expression
                     self.pos -= 1; // Go back
@@ -660,10 +863,12 @@ impl Parser {
                     if self.match_token(&Token::Eq) {
                          let val = self.parse_expr()?;
                          self.expect(Token::Semi)?;
-                         match expr {
+                         // The target's own span isn't needed; errors point at the statement
+                         let target = match expr { Expr::At(_, inner) => *inner, e => e };
+                         match target {
                              Expr::Field(obj, field) => Ok(Stmt::FieldAssign(*obj, field, val)),
                              Expr::Index(arr, idx) => Ok(Stmt::IndexAssign(*arr, *idx, val)),
-                             _ => Err(format!("Invalid assignment target: {:?}", expr)),
+                             _ => Err(format!("Invalid assignment target: {:?}", target)),
                          }
                     } else {
                         self.expect(Token::Semi)?;
@@ -680,30 +885,129 @@ impl Parser {
     }
     
     fn parse_expr(&mut self) -> Result<Expr, String> {
-        self.parse_or()
+        stack_guard::guard(|| self.parse_or())
+    }
+
+    /// `match subject { pattern [if guard] => body, ... }`. A statement's arms
+    /// may be blocks or `print`, `return`, `break` and `continue` without the
+    /// `;`; an expression's arms are expressions, so `{` starts an object
+    /// literal there.
+    fn parse_match(&mut self, blocks: bool) -> Result<(Expr, Vec<MatchArm>), String> {
+        self.expect(Token::Match)?;
+        let subject = self.parse_expr()?;
+        self.expect(Token::LBrace)?;
+        let mut arms = Vec::new();
+        while self.peek() != &Token::RBrace {
+            let pattern = self.parse_pattern()?;
+            let guard = if self.match_token(&Token::If) { Some(self.parse_expr()?) } else { None };
+            self.expect(Token::FatArrow)?;
+            let block = blocks && self.peek() == &Token::LBrace;
+            let body = if block {
+                self.parse_block()?
+            } else if blocks {
+                vec![self.parse_arm_stmt()?]
+            } else {
+                vec![Stmt::Expr(self.parse_expr()?)]
+            };
+            arms.push(MatchArm { pattern, guard, body });
+            // The comma after a block arm is optional
+            if !self.match_token(&Token::Comma) && !block {
+                break;
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Ok((subject, arms))
+    }
+
+    /// A statement match arm that isn't a block, ended by `,` or `}`
+    fn parse_arm_stmt(&mut self) -> Result<Stmt, String> {
+        match self.peek() {
+            Token::Print => {
+                self.advance();
+                self.expect(Token::LParen)?;
+                let expr = self.parse_expr()?;
+                self.expect(Token::RParen)?;
+                Ok(Stmt::Print(expr))
+            }
+            Token::Return => {
+                self.advance();
+                if matches!(self.peek(), Token::Comma | Token::RBrace) {
+                    Ok(Stmt::Return(None))
+                } else {
+                    Ok(Stmt::Return(Some(self.parse_expr()?)))
+                }
+            }
+            Token::Break => {
+                self.advance();
+                Ok(Stmt::Break)
+            }
+            Token::Continue => {
+                self.advance();
+                Ok(Stmt::Continue)
+            }
+            _ => Ok(Stmt::Expr(self.parse_expr()?)),
+        }
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, String> {
+        match self.advance() {
+            Token::Identifier(name) if name.as_str() == "_" => Ok(Pattern::Wildcard),
+            Token::Identifier(name) if self.match_token(&Token::ColonColon) => match self.advance() {
+                Token::Identifier(variant) => {
+                    let mut fields = Vec::new();
+                    if self.match_token(&Token::LParen) {
+                        while self.peek() != &Token::RParen {
+                            fields.push(self.parse_pattern()?);
+                            if !self.match_token(&Token::Comma) {
+                                break;
+                            }
+                        }
+                        self.expect(Token::RParen)?;
+                    }
+                    Ok(Pattern::Variant(*name, *variant, fields))
+                }
+                t => Err(format!("Expected variant name, got {:?}", t)),
+            },
+            Token::Identifier(name) => Ok(Pattern::Binding(*name)),
+            Token::Number(n) => Ok(Pattern::Literal(Expr::Number(*n))),
+            Token::Float(f) => Ok(Pattern::Literal(Expr::Float(*f))),
+            Token::Minus => match self.advance() {
+                Token::Number(n) => Ok(Pattern::Literal(Expr::Number(n.wrapping_neg()))),
+                Token::Float(f) => Ok(Pattern::Literal(Expr::Float(-f))),
+                t => Err(format!("Expected a number after - in a pattern, got {:?}", t)),
+            },
+            Token::String(s) => Ok(Pattern::Literal(Expr::String(s.clone()))),
+            Token::True => Ok(Pattern::Literal(Expr::Bool(true))),
+            Token::False => Ok(Pattern::Literal(Expr::Bool(false))),
+            Token::Null => Ok(Pattern::Literal(Expr::Null)),
+            t => Err(format!("Expected a pattern, got {:?}", t)),
+        }
     }
     
     fn parse_or(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
         let mut left = self.parse_and()?;
         while self.peek() == &Token::Or {
             self.advance();
             let right = self.parse_and()?;
-            left = Expr::BinOp(Box::new(left), "||".to_string(), Box::new(right));
+            left = self.spanned(start, Expr::BinOp(Box::new(left), "||".to_string(), Box::new(right)));
         }
         Ok(left)
     }
     
     fn parse_and(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
         let mut left = self.parse_equality()?;
         while self.peek() == &Token::And {
             self.advance();
             let right = self.parse_equality()?;
-            left = Expr::BinOp(Box::new(left), "&&".to_string(), Box::new(right));
+            left = self.spanned(start, Expr::BinOp(Box::new(left), "&&".to_string(), Box::new(right)));
         }
         Ok(left)
     }
     
     fn parse_equality(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
         let mut left = self.parse_comparison()?;
         loop {
             let op = match self.peek() {
@@ -713,12 +1017,13 @@ impl Parser {
             };
             self.advance();
             let right = self.parse_comparison()?;
-            left = Expr::BinOp(Box::new(left), op.to_string(), Box::new(right));
+            left = self.spanned(start, Expr::BinOp(Box::new(left), op.to_string(), Box::new(right)));
         }
         Ok(left)
     }
     
     fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
         let mut left = self.parse_additive()?;
         loop {
             let op = match self.peek() {
@@ -730,12 +1035,13 @@ impl Parser {
             };
             self.advance();
             let right = self.parse_additive()?;
-            left = Expr::BinOp(Box::new(left), op.to_string(), Box::new(right));
+            left = self.spanned(start, Expr::BinOp(Box::new(left), op.to_string(), Box::new(right)));
         }
         Ok(left)
     }
     
     fn parse_additive(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
         let mut left = self.parse_multiplicative()?;
         loop {
             let op = match self.peek() {
@@ -745,13 +1051,14 @@ impl Parser {
             };
             self.advance();
             let right = self.parse_multiplicative()?;
-            left = Expr::BinOp(Box::new(left), op.to_string(), Box::new(right));
+            left = self.spanned(start, Expr::BinOp(Box::new(left), op.to_string(), Box::new(right)));
         }
         Ok(left)
     }
     
     fn parse_multiplicative(&mut self) -> Result<Expr, String> {
-        let mut left = self.parse_unary()?;
+        let start = self.pos;
+        let mut left = self.parse_cast()?;
         loop {
             let op = match self.peek() {
                 Token::Star => "*",
@@ -760,13 +1067,36 @@ impl Parser {
                 _ => break,
             };
             self.advance();
-            let right = self.parse_unary()?;
-            left = Expr::BinOp(Box::new(left), op.to_string(), Box::new(right));
+            let right = self.parse_cast()?;
+            left = self.spanned(start, Expr::BinOp(Box::new(left), op.to_string(), Box::new(right)));
         }
         Ok(left)
     }
+
+    /// `expr as i32`: a contextual keyword, so `as` stays usable as a name.
+    /// Casts become calls to the `to_i32`-style builtins.
+    fn parse_cast(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
+        let mut expr = self.parse_unary()?;
+        while matches!(self.peek(), Token::Identifier(s) if *s == "as") {
+            self.advance();
+            let target = match self.advance() {
+                Token::Identifier(s) => *s,
+                t => return Err(format!("Expected type name after 'as', got {:?}", t)),
+            };
+            let Some(int_type) = IntType::parse(target.as_str()) else {
+                return Err(format!("Cannot cast to '{}': 'as' takes i8, i16, i32, i64, u8, u16, u32 or u64", target));
+            };
+            expr = self.spanned(start, Expr::Call(Symbol::intern(int_type.cast_builtin()), vec![expr]));
+        }
+        Ok(expr)
+    }
     
     fn parse_unary(&mut self) -> Result<Expr, String> {
+        stack_guard::guard(|| self.parse_unary_inner())
+    }
+    
+    fn parse_unary_inner(&mut self) -> Result<Expr, String> {
         match self.peek() {
             Token::Not => {
                 self.advance();
@@ -788,17 +1118,18 @@ impl Parser {
     }
     
     fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
         let mut expr = self.parse_primary()?;
         
         loop {
             match self.peek() {
                 Token::LParen => {
                     // Function call
-                    if let Expr::Identifier(name) = expr.clone() {
+                    if let Expr::Identifier(name) = expr {
                         self.advance();
                         let args = self.parse_args()?;
                         self.expect(Token::RParen)?;
-                        expr = Expr::Call(name, args);
+                        expr = self.spanned(start, Expr::Call(name, args));
                     } else {
                         break;
                     }
@@ -808,42 +1139,49 @@ impl Parser {
                     self.advance();
                     let index = self.parse_expr()?;
                     self.expect(Token::RBracket)?;
-                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                    expr = self.spanned(start, Expr::Index(Box::new(expr), Box::new(index)));
                 }
                 Token::Dot => {
                     // Field access or method call
                     self.advance();
                     let field = match self.advance() {
-                        Token::Identifier(s) => s,
+                        Token::Identifier(s) => *s,
                         _ => return Err("Expected field name".to_string()),
                     };
                     if self.peek() == &Token::LParen {
                         self.advance();
                         let args = self.parse_args()?;
                         self.expect(Token::RParen)?;
-                        expr = Expr::MethodCall(Box::new(expr), field, args);
+                        expr = self.spanned(start, Expr::MethodCall(Box::new(expr), field, args));
                     } else {
-                        expr = Expr::Field(Box::new(expr), field);
+                        expr = self.spanned(start, Expr::Field(Box::new(expr), field));
                     }
                 }
                 Token::ColonColon => {
-                     // Static method call: Type::Method()
+                     // Static method call: Type::Method(), or an enum variant
                      if let Expr::Identifier(type_name) = expr {
                          self.advance(); // ::
                          let method_name = match self.advance() {
-                             Token::Identifier(s) => s,
+                             Token::Identifier(s) => *s,
                              _ => return Err("Expected static method name".to_string()),
                          };
                          
-                         self.expect(Token::LParen)?;
+                         if !self.match_token(&Token::LParen) {
+                             expr = Expr::Variant(type_name, method_name);
+                             continue;
+                         }
                          let args = self.parse_args()?;
                          self.expect(Token::RParen)?;
                          
-                         expr = Expr::StaticMethodCall(type_name, method_name, args);
+                         expr = self.spanned(start, Expr::StaticMethodCall(type_name, method_name, args));
                      } else {
                          return Err("Expected identifier before ::".to_string());
                      }
                 }
+                Token::Question => {
+                    self.advance();
+                    expr = Expr::Propagate(Box::new(expr));
+                }
                 _ => break,
             }
         }
@@ -863,14 +1201,47 @@ impl Parser {
     }
     
     fn parse_primary(&mut self) -> Result<Expr, String> {
-        match self.peek().clone() {
+        match self.peek() {
             Token::Number(n) => {
                 self.advance();
-                Ok(Expr::Number(n))
+                Ok(Expr::Number(*n))
+            }
+            Token::Float(f) => {
+                self.advance();
+                Ok(Expr::Float(*f))
             }
             Token::String(s) => {
                 self.advance();
-                Ok(Expr::String(s))
+                Ok(Expr::String(s.clone()))
+            }
+            Token::Interpolated(parts) => {
+                self.advance();
+                // "a ${x} b" is "a " + x + " b"; a leading "" makes the first + a string concat
+                let mut result = match parts.first() {
+                    Some(StrPart::Text(_)) => None,
+                    _ => Some(Expr::String(String::new())),
+                };
+                for part in parts {
+                    let expr = match part {
+                        StrPart::Text(text) => Expr::String(text.clone()),
+                        StrPart::Code(tokens) => {
+                            let mut inner = Parser::new(tokens);
+                            if inner.peek() == &Token::Eof {
+                                return Err("Expected expression in string interpolation '${}'".to_string());
+                            }
+                            let expr = inner.parse_expr().map_err(|e| format!("In string interpolation: {}", e))?;
+                            if inner.peek() != &Token::Eof {
+                                return Err(format!("Expected '}}' to end string interpolation, got {:?}", inner.peek()));
+                            }
+                            expr
+                        }
+                    };
+                    result = Some(match result {
+                        Some(left) => Expr::BinOp(Box::new(left), "+".to_string(), Box::new(expr)),
+                        None => expr,
+                    });
+                }
+                Ok(result.unwrap_or(Expr::String(String::new())))
             }
             Token::True => {
                 self.advance();
@@ -884,8 +1255,9 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Null)
             }
-            Token::Identifier(name) => {
+            Token::Identifier(sym) => {
                 self.advance();
+                let name = *sym;
                 // Check for struct init: Name { field: value }
                 if self.peek() == &Token::LBrace {
                     // Could be struct init - peek ahead
@@ -904,7 +1276,7 @@ impl Parser {
                             let mut fields = Vec::new();
                             while self.peek() != &Token::RBrace {
                                 let fname = match self.advance() {
-                                    Token::Identifier(s) => s,
+                                    Token::Identifier(s) => *s,
                                     _ => break,
                                 };
                                 self.expect(Token::Colon)?;
@@ -939,11 +1311,15 @@ impl Parser {
                 self.expect(Token::RParen)?;
                 Ok(expr)
             }
+            Token::Match => {
+                let (subject, arms) = self.parse_match(false)?;
+                Ok(Expr::Match(Box::new(subject), arms))
+            }
             Token::At => {
                 // Built-in function call: @name(args)
                 self.advance();
                 let name = match self.advance() {
-                    Token::Identifier(s) => s,
+                    Token::Identifier(s) => *s,
                     t => return Err(format!("Expected identifier after @, got {:?}", t)),
                 };
                 self.expect(Token::LParen)?;
@@ -951,6 +1327,24 @@ impl Parser {
                 self.expect(Token::RParen)?;
                 Ok(Expr::Call(name, args))
             }
+            Token::LBrace if matches!(self.tokens.get(self.pos + 1), Some(Token::String(_))) => {
+                // Map literal: { "key": value, "key2": value2 }
+                self.advance();
+                let mut entries = Vec::new();
+                while self.peek() != &Token::RBrace {
+                    let key = match self.advance() {
+                        Token::String(s) => s.to_string(),
+                        t => return Err(format!("Expected string key in map literal, got {:?}", t)),
+                    };
+                    self.expect(Token::Colon)?;
+                    entries.push((key, self.parse_expr()?));
+                    if !self.match_token(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(Token::RBrace)?;
+                Ok(Expr::MapLiteral(entries))
+            }
             Token::LBrace => {
                 // Object literal: { key: value, key2: value2 }
                 self.advance();
@@ -959,8 +1353,8 @@ impl Parser {
                 while self.peek() != &Token::RBrace {
                     // Parse key (identifier)
                     let key = match self.advance() {
-                        Token::Identifier(s) => s,
-                        Token::String(s) => s,
+                        Token::Identifier(s) => *s,
+                        Token::String(s) => Symbol::intern(s),
                         t => return Err(format!("Expected key in object literal, got {:?}", t)),
                     };
                     
@@ -987,6 +1381,6 @@ impl Parser {
 }
 
 pub fn parse(tokens: &[Token]) -> Result<Vec<TopLevel>, String> {
-    let mut parser = Parser::new(tokens.to_vec());
+    let mut parser = Parser::new(tokens);
     parser.parse()
 }
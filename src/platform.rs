@@ -0,0 +1,398 @@
+// ============================================
+// Cryo Platform Layer
+// The interpreter's builtins reach files, the network, the clock, the
+// environment and stdout through a `Platform` instead of calling std
+// directly. `NativePlatform` is the real operating system; `MemoryPlatform`
+// keeps everything in memory, for tests, sandboxes and the wasm playground;
+// `VfsPlatform` (vfs.rs) swaps in-memory files into any other platform.
+//
+// Still direct: open() file handles, kv_open, lock_file, http_* and cryo_listen.
+// ============================================
+
+use crate::vfs::Vfs;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// One name in a directory listing
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// A connection from `Platform::connect`
+pub trait Socket: Read + Write + Send {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+pub trait Platform: Send + Sync {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>>;
+    /// Create or replace the file at `path`
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()>;
+    /// Move `from` to `to`, replacing any file there. Natively this is atomic
+    /// when both are on the same filesystem.
+    fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+    fn remove_file(&self, path: &str) -> io::Result<()>;
+    fn exists(&self, path: &str) -> bool;
+    /// The entries of directory `path`, in no particular order
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>>;
+    /// The directory for temporary files
+    fn temp_dir(&self) -> String;
+
+    /// Open a TCP connection to `addr` ("host:port")
+    fn connect(&self, addr: &str) -> io::Result<Box<dyn Socket>>;
+
+    /// Time since the Unix epoch
+    fn now(&self) -> Duration;
+    /// Time since a fixed point early in the process, which never goes back
+    /// when the wall clock is adjusted
+    fn monotonic(&self) -> Duration;
+    fn sleep(&self, duration: Duration);
+    /// `n` bytes for rand(), rand_int() and generate_id(), Err when the
+    /// platform has no secure source
+    fn random_bytes(&self, n: usize) -> io::Result<Vec<u8>>;
+
+    fn env_var(&self, name: &str) -> Option<String>;
+    /// Set a variable for the rest of the run (and, natively, for child processes)
+    fn set_env_var(&self, name: &str, value: &str);
+
+    /// Program output that isn't being captured (see output.rs)
+    fn write_stdout(&self, text: &str);
+
+    /// `read_file` as UTF-8, the way `std::fs::read_to_string` reports errors
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        String::from_utf8(self.read_file(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// The operating system
+pub struct NativePlatform;
+
+impl Platform for NativePlatform {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::write(path, data)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        std::path::Path::new(path).exists()
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        Ok(std::fs::read_dir(path)?
+            .filter_map(|e| e.ok())
+            .map(|e| DirEntry {
+                name: e.file_name().to_string_lossy().into_owned(),
+                is_dir: e.file_type().is_ok_and(|t| t.is_dir()),
+            })
+            .collect())
+    }
+
+    fn temp_dir(&self) -> String {
+        std::env::temp_dir().to_string_lossy().into_owned()
+    }
+
+    fn connect(&self, addr: &str) -> io::Result<Box<dyn Socket>> {
+        Ok(Box::new(TcpStream::connect(addr)?))
+    }
+
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    fn monotonic(&self) -> Duration {
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn random_bytes(&self, n: usize) -> io::Result<Vec<u8>> {
+        crate::crypto::random_bytes(n)
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn set_env_var(&self, name: &str, value: &str) {
+        std::env::set_var(name, value);
+    }
+
+    fn write_stdout(&self, text: &str) {
+        let _ = io::stdout().lock().write_all(text.as_bytes());
+    }
+}
+
+/// Files (a `Vfs`), environment, clock and stdout held in memory. The clock
+/// starts at the epoch and only moves when the program sleeps; random bytes
+/// come from a fixed seed; connections are refused.
+pub struct MemoryPlatform {
+    files: Mutex<Vfs>,
+    env: Mutex<HashMap<String, String>>,
+    clock: Mutex<Duration>,
+    rng: Mutex<u64>,
+    stdout: Mutex<String>,
+}
+
+impl MemoryPlatform {
+    pub fn new() -> Self {
+        MemoryPlatform {
+            files: Mutex::new(Vfs::new()),
+            env: Mutex::new(HashMap::new()),
+            clock: Mutex::new(Duration::ZERO),
+            rng: Mutex::new(0x9e37_79b9_7f4a_7c15),
+            stdout: Mutex::new(String::new()),
+        }
+    }
+
+    pub fn with_file(self, path: &str, data: impl Into<Vec<u8>>) -> Self {
+        let _ = lock(&self.files).write(path, &data.into());
+        self
+    }
+
+    pub fn with_vfs(self, vfs: Vfs) -> Self {
+        *lock(&self.files) = vfs;
+        self
+    }
+
+    pub fn with_env(self, name: &str, value: &str) -> Self {
+        self.set_env_var(name, value);
+        self
+    }
+
+    pub fn with_clock(self, since_epoch: Duration) -> Self {
+        *lock(&self.clock) = since_epoch;
+        self
+    }
+
+    /// Contents of the file at `path`, if there is one
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        lock(&self.files).read(path).ok()
+    }
+
+    /// The files as they are now
+    pub fn vfs(&self) -> Vfs {
+        lock(&self.files).clone()
+    }
+
+    /// Everything written to stdout so far
+    pub fn stdout(&self) -> String {
+        lock(&self.stdout).clone()
+    }
+}
+
+impl Platform for MemoryPlatform {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        lock(&self.files).read(path)
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        lock(&self.files).write(path, data)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        lock(&self.files).rename(from, to)
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        lock(&self.files).remove_file(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        lock(&self.files).exists(path)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        lock(&self.files).list(path)
+    }
+
+    fn temp_dir(&self) -> String {
+        crate::vfs::TEMP_DIR.to_string()
+    }
+
+    fn connect(&self, addr: &str) -> io::Result<Box<dyn Socket>> {
+        Err(io::Error::new(io::ErrorKind::ConnectionRefused, format!("{}: no network in memory", addr)))
+    }
+
+    fn now(&self) -> Duration {
+        *lock(&self.clock)
+    }
+
+    /// Only sleep() moves the clock, so this is the time since the epoch too
+    fn monotonic(&self) -> Duration {
+        *lock(&self.clock)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *lock(&self.clock) += duration;
+    }
+
+    fn random_bytes(&self, n: usize) -> io::Result<Vec<u8>> {
+        // xorshift64*
+        let mut state = lock(&self.rng);
+        Ok((0..n).map(|_| {
+            *state ^= *state >> 12;
+            *state ^= *state << 25;
+            *state ^= *state >> 27;
+            (state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8
+        }).collect())
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        lock(&self.env).get(name).cloned()
+    }
+
+    fn set_env_var(&self, name: &str, value: &str) {
+        lock(&self.env).insert(name.to_string(), value.to_string());
+    }
+
+    fn write_stdout(&self, text: &str) {
+        lock(&self.stdout).push_str(text);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, Value};
+    use std::sync::Arc;
+
+    /// An interpreter whose builtins go to `platform`
+    fn interpreter_on(platform: &Arc<MemoryPlatform>) -> Interpreter {
+        let mut interp = Interpreter::new();
+        interp.set_platform(platform.clone());
+        interp
+    }
+
+    /// What `source` prints on `platform`; it has to exit with 0
+    fn run_on(platform: &Arc<MemoryPlatform>, source: &str) -> String {
+        let (result, printed) = interpreter_on(platform).run_source(source);
+        assert_eq!(result, Ok(0), "{}", printed);
+        printed
+    }
+
+    #[test]
+    fn test_memory_platform() {
+        let platform = MemoryPlatform::new().with_file("./src/main.cryo", "fn main() {}").with_env("HOME", "/home/cryo");
+        assert_eq!(platform.read_to_string("src/../src/main.cryo").unwrap(), "fn main() {}");
+        assert!(platform.exists("src") && platform.exists(".") && !platform.exists("lib"));
+        platform.write_file("src/lib/util.cryo", b"").unwrap();
+        let names: Vec<_> = platform.read_dir("src").unwrap().into_iter().map(|e| (e.name, e.is_dir)).collect();
+        assert_eq!(names, [("lib".to_string(), true), ("main.cryo".to_string(), false)]);
+        assert_eq!(platform.read_file("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(platform.env_var("HOME").as_deref(), Some("/home/cryo"));
+        platform.sleep(Duration::from_millis(1500));
+        assert_eq!(platform.now(), Duration::from_millis(1500));
+        assert!(platform.connect("127.0.0.1:80").is_err());
+    }
+
+    #[test]
+    fn test_builtins_use_platform() {
+        let platform = Arc::new(MemoryPlatform::new().with_file("data/in.txt", "hello").with_env("NAME", "cryo"));
+        let source = "fn main() {
+            writeFile(\"data/out.txt\", readFile(\"data/in.txt\") + \" \" + env(\"NAME\"));
+            sleep(2000);
+            print(timestamp());
+            print(glob(\"data\", \"*.txt\"));
+            print(fileExists(\"data/out.txt\"));
+        }";
+        assert_eq!(run_on(&platform, source), "2\n[data/in.txt, data/out.txt]\ntrue\n");
+        assert_eq!(platform.file("data/out.txt").unwrap(), b"hello cryo");
+    }
+
+    #[test]
+    fn test_timing_builtins() {
+        let platform = Arc::new(MemoryPlatform::new().with_clock(Duration::from_secs(1_700_000_000)));
+        let source = "fn nap() { sleep(5); }
+            fn main() {
+                let start = monotonic_ns();
+                let stats = bench(nap, 4);
+                print(stats.iterations + \" \" + stats.total_ms + \" \" + stats.median_ms + \" \" + stats.max_ms);
+                sleep(2);
+                print(elapsed_ms(start));
+            }";
+        assert_eq!(run_on(&platform, source), "4 20.0 5.0 5.0\n22.0\n");
+    }
+
+    #[test]
+    fn test_call_with_timeout() {
+        let source = "let naps = 0;
+            fn nap_forever() { while (true) { sleep(10); naps = naps + 1; } }
+            fn spin(n) { while (true) { n = n + 1; } }
+            fn count() { return naps; }
+            fn add(a, b) { return a + b; }";
+        // On the memory clock only the sleeps move time forward
+        let mut interp = interpreter_on(&Arc::new(MemoryPlatform::new()));
+        assert_eq!(interp.run_source(source).0, Ok(0));
+        let err = interp.call_with_timeout("nap_forever", vec![], Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err, "Timeout: nap_forever() did not finish within 100ms");
+        let naps = interp.call_with_timeout("count", vec![], Duration::from_millis(100)).unwrap().as_int();
+        assert!(naps >= 10, "{} naps", naps);
+
+        // A loop that never calls out, in a function the VM would otherwise run
+        let mut interp = Interpreter::new();
+        interp.set_bytecode_all(true);
+        assert_eq!(interp.run_source(source).0, Ok(0));
+        let err = interp.call_with_timeout("spin", vec![Value::Int(0)], Duration::from_millis(20)).unwrap_err();
+        assert!(err.starts_with("Timeout: spin()"), "{}", err);
+        let sum = interp.call_with_timeout("add", vec![Value::Int(2), Value::Int(3)], Duration::from_secs(1));
+        assert_eq!(sum.unwrap().as_int(), 5);
+    }
+
+    #[test]
+    fn test_dotenv() {
+        let platform = Arc::new(MemoryPlatform::new().with_file(".env", "NAME=file\nGREETING=\"hi $NAME\"\n").with_env("NAME", "cryo"));
+        let source = "fn main() {
+            print(load_dotenv());
+            print(env(\"GREETING\") + \" from \" + expand_env(\"$NAME/.config\"));
+            print(load_dotenv(\"missing.env\"));
+        }";
+        let printed = run_on(&platform, source);
+        assert!(printed.starts_with("1\nhi cryo from cryo/.config\nRuntime Error: load_dotenv: missing.env: "), "{}", printed);
+        assert_eq!(platform.env_var("NAME").as_deref(), Some("cryo"));
+    }
+
+    #[test]
+    fn test_temp_files_and_atomic_writes() {
+        let platform = Arc::new(MemoryPlatform::new().with_file("config.json", "{}"));
+        let source = "fn main() {
+            let t = temp_file(\"cache\");
+            print(starts_with(t, temp_dir() + \"/cache-\") && ends_with(t, \".tmp\"));
+            print(t != temp_file(\"cache\") && readFile(t) == \"\");
+            writeFileAtomic(\"config.json\", \"{ \\\"v\\\": 2 }\");
+            print(readFile(\"config.json\"));
+            writeFileAtomic(\"config.json/x\", \"nope\");
+        }";
+        let printed = run_on(&platform, source);
+        assert!(printed.starts_with("true\ntrue\n{ \"v\": 2 }\nRuntime Error: writeFileAtomic: config.json/x: "), "{}", printed);
+        // No temp file is left next to the target
+        assert_eq!(platform.read_dir(".").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,461 @@
+// ============================================
+// Cryo Refactoring
+// Rename and code actions, for `cryo lsp` and `cryo refactor`. A refactoring
+// is a set of text edits per file, built from the symbol index (see
+// symbols.rs); nothing is written here. A rename that would change what some
+// other name refers to is refused instead of made.
+//
+// Code actions: import the module that defines a name nothing visible
+// defines, and extract whole statements into a new function. Cryo has no
+// `for` statement, so there's no loop conversion to offer.
+// ============================================
+
+use crate::engine::{Document, Position, Range};
+use crate::lexer::{self, Token};
+use crate::parser::{Parser, Stmt, TopLevel};
+use crate::symbols::{self, scopes, Scope, SymbolIndex};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range,
+    pub new_text: String,
+}
+
+/// Edits by index key, in source order within a file
+pub type FileEdits = BTreeMap<String, Vec<TextEdit>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeAction {
+    pub title: String,
+    /// The LSP CodeActionKind
+    pub kind: &'static str,
+    pub edits: FileEdits,
+}
+
+/// The text of an indexed file by key: the editor's, when it's open there
+pub type Source<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+const NOT_STATEMENTS: &str = "select whole statements inside a function body";
+
+/// What a name being renamed is
+enum Target {
+    /// In the index: a top-level definition or a member
+    Symbol { name: String },
+    /// A parameter or local variable
+    Local { name: String, scope: Scope },
+}
+
+fn document(key: &str, source: Source) -> Result<Document, String> {
+    source(key).map(|text| Document::new(&text)).ok_or_else(|| format!("can't read {}", key))
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    match lexer::tokenize(name).as_slice() {
+        [Token::Identifier(id), Token::Eof] if id.as_str() == name => Ok(()),
+        _ => Err(format!("`{}` isn't a valid name", name)),
+    }
+}
+
+fn is_name(token: &Token, name: &str) -> bool {
+    matches!(token, Token::Identifier(id) if id.as_str() == name)
+}
+
+/// Index of the first token starting at or after `offset`
+fn token_at(doc: &Document, offset: usize) -> usize {
+    doc.spans().partition_point(|s| s.start < offset)
+}
+
+/// The name at `pos` and what it names
+fn target(index: &SymbolIndex, file: &str, doc: &Document, pos: Position) -> Result<(Target, Range), String> {
+    let offset = doc.offset(pos);
+    let (tokens, spans) = (doc.tokens(), doc.spans());
+    let i = (0..tokens.len())
+        .find(|&i| spans[i].start <= offset && offset <= spans[i].end && matches!(tokens[i], Token::Identifier(_)))
+        .ok_or_else(|| format!("no name at {}:{}", pos.line + 1, pos.character + 1))?;
+    let Token::Identifier(name) = &tokens[i] else { unreachable!() };
+    let range = doc.range(spans[i]);
+    if let Some((name, _, _)) = index.file(file).and_then(|f| f.name_at(pos)) {
+        if index.definition(file, pos).is_empty() {
+            return Err(format!("`{}` isn't defined in this project", name));
+        }
+        return Ok((Target::Symbol { name: name.to_string() }, range));
+    }
+    match scopes(doc).into_iter().find(|s| s.tokens.contains(&i) && s.locals.contains(name.as_str())) {
+        Some(scope) => Ok((Target::Local { name: name.to_string(), scope }, range)),
+        None => Err(format!("`{}` isn't defined in this project", name)),
+    }
+}
+
+/// The range of the name a rename at `pos` would change, or why it can't
+pub fn prepare_rename(index: &SymbolIndex, file: &str, pos: Position, source: Source) -> Result<Range, String> {
+    let doc = document(file, source)?;
+    target(index, file, &doc, pos).map(|(_, range)| range)
+}
+
+/// Rename what the name at `pos` refers to, everywhere it's used
+pub fn rename(index: &SymbolIndex, file: &str, pos: Position, new_name: &str, source: Source) -> Result<FileEdits, String> {
+    check_name(new_name)?;
+    let doc = document(file, source)?;
+    let (target, _) = target(index, file, &doc, pos)?;
+    let mut edits = FileEdits::new();
+    match target {
+        Target::Local { name, .. } if name == new_name => {}
+        Target::Symbol { name } if name == new_name => {}
+        Target::Local { name, scope } => {
+            let (tokens, spans) = (doc.tokens(), doc.spans());
+            let members = symbols::member_tokens(tokens);
+            let uses = |name: &str| scope.tokens.clone().filter(|&i| !members[i] && is_name(&tokens[i], name)).collect::<Vec<_>>();
+            if scope.locals.contains(new_name) {
+                return Err(format!("`{}` is already a variable in `{}`", new_name, scope.name));
+            }
+            if !uses(new_name).is_empty() {
+                return Err(format!("`{}` is used in `{}`, where the renamed variable would hide it", new_name, scope.name));
+            }
+            let renamed = uses(&name).into_iter().map(|i| TextEdit { range: doc.range(spans[i]), new_text: new_name.to_string() });
+            edits.insert(file.to_string(), renamed.collect());
+        }
+        Target::Symbol { name } => {
+            let member = index.file(file).and_then(|f| f.name_at(pos)).is_some_and(|(_, member, _)| member);
+            let locations = index.references(file, pos, true);
+            if member {
+                // Members match by name alone, so every type's goes together
+                let containers: BTreeSet<&str> = index
+                    .definitions_named(&name)
+                    .into_iter()
+                    .filter(|(_, d)| symbols::is_member(d.kind))
+                    .filter_map(|(_, d)| d.container.as_deref())
+                    .collect();
+                if containers.len() > 1 {
+                    let list: Vec<String> = containers.iter().map(|c| format!("`{}`", c)).collect();
+                    return Err(format!("`{}` is a member of {}; members are matched by name, so they can't be renamed apart", name, list.join(" and ")));
+                }
+                let taken = index.definitions_named(new_name).into_iter().find(|(_, d)| symbols::is_member(d.kind) && containers.contains(d.container.as_deref().unwrap_or_default()));
+                if let Some((_, d)) = taken {
+                    return Err(format!("`{}` already has a member named `{}`", d.container.as_deref().unwrap_or_default(), new_name));
+                }
+            } else {
+                let files: BTreeSet<&str> = locations.iter().map(|l| l.file.as_str()).collect();
+                for key in files {
+                    let defines = |k: &String| index.file(k).is_some_and(|f| f.definitions.iter().any(|d| d.name == new_name && !symbols::is_member(d.kind)));
+                    if let Some(other) = index.visible(key).iter().find(|k| defines(k)) {
+                        return Err(format!("`{}` is already defined in {}", new_name, other));
+                    }
+                    // A local of the new name would hide it where it's used
+                    let other_doc;
+                    let doc = if key == file {
+                        &doc
+                    } else {
+                        other_doc = document(key, source)?;
+                        &other_doc
+                    };
+                    let scopes = scopes(doc);
+                    for l in locations.iter().filter(|l| l.file == key) {
+                        let i = token_at(doc, doc.offset(l.range.start));
+                        if let Some(s) = scopes.iter().find(|s| s.tokens.contains(&i) && s.locals.contains(new_name)) {
+                            return Err(format!("`{}` is a variable in `{}` ({}:{}), where it would hide `{}`", new_name, s.name, key, l.range.start.line + 1, name));
+                        }
+                    }
+                }
+            }
+            for l in locations {
+                edits.entry(l.file).or_default().push(TextEdit { range: l.range, new_text: new_name.to_string() });
+            }
+        }
+    }
+    Ok(edits)
+}
+
+/// The first of `return`, `defer`, or `break`/`continue` outside a loop
+/// that `stmts` hold: what can't leave the function it's in
+fn escapes(stmts: &[Stmt], in_loop: bool) -> Option<&'static str> {
+    stmts.iter().find_map(|stmt| match stmt {
+        Stmt::Return(_) => Some("return"),
+        Stmt::Defer(_) => Some("defer"),
+        Stmt::Break if !in_loop => Some("break"),
+        Stmt::Continue if !in_loop => Some("continue"),
+        Stmt::If(_, then, other) => escapes(then, in_loop).or_else(|| escapes(other.as_deref().unwrap_or_default(), in_loop)),
+        Stmt::While(_, body) => escapes(body, true),
+        Stmt::Block(body) => escapes(body, in_loop),
+        Stmt::Match(_, arms) => arms.iter().find_map(|arm| escapes(&arm.body, in_loop)),
+        Stmt::Try(body, _, handler) => escapes(body, in_loop).or_else(|| escapes(handler, in_loop)),
+        Stmt::With(_, _, body) => escapes(body, in_loop),
+        Stmt::At(_, _, inner) => escapes(std::slice::from_ref(&**inner), in_loop),
+        _ => None,
+    })
+}
+
+/// Variables `stmts` assign to with `name = ...`, in nested blocks too
+fn collect_assigned(stmts: &[Stmt], names: &mut HashSet<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign(name, _) => {
+                names.insert(name.to_string());
+            }
+            Stmt::If(_, then, other) => {
+                collect_assigned(then, names);
+                collect_assigned(other.as_deref().unwrap_or_default(), names);
+            }
+            Stmt::While(_, body) | Stmt::Block(body) | Stmt::With(_, _, body) => collect_assigned(body, names),
+            Stmt::Defer(inner) | Stmt::At(_, _, inner) => collect_assigned(std::slice::from_ref(&**inner), names),
+            Stmt::Match(_, arms) => arms.iter().for_each(|arm| collect_assigned(&arm.body, names)),
+            Stmt::Try(body, _, handler) => {
+                collect_assigned(body, names);
+                collect_assigned(handler, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Move the statements in `selection` into a new function `name`, placed
+/// after the item they're in, and call it in their place. The variables they
+/// read become parameters; one variable they set that's used elsewhere in
+/// the function becomes the return value.
+pub fn extract_function(index: &SymbolIndex, file: &str, selection: Range, name: &str, source: Source) -> Result<FileEdits, String> {
+    check_name(name)?;
+    let doc = document(file, source)?;
+    let (tokens, spans) = (doc.tokens(), doc.spans());
+    let (start, end) = (doc.offset(selection.start), doc.offset(selection.end));
+    // Whole tokens only, leaving out Eof
+    let first = spans.partition_point(|s| s.end <= start);
+    let last = spans[..tokens.len() - 1].partition_point(|s| s.start < end);
+    if first >= last || spans[first].start < start || spans[last - 1].end > end {
+        return Err(NOT_STATEMENTS.to_string());
+    }
+    let scope = scopes(&doc).into_iter().find(|s| s.body < first && last < s.tokens.end).ok_or(NOT_STATEMENTS)?;
+    let boundary = |t: &Token| matches!(t, Token::Semi | Token::LBrace | Token::RBrace);
+    if !boundary(&tokens[first - 1]) || !boundary(&tokens[last - 1]) || tokens[last] == Token::Else {
+        return Err(NOT_STATEMENTS.to_string());
+    }
+    let mut selected = tokens[first..last].to_vec();
+    selected.push(Token::Eof);
+    let stmts = Parser::new(&selected).parse_stmts().map_err(|_| NOT_STATEMENTS)?;
+    if let Some(what) = escapes(&stmts, false) {
+        return Err(format!("can't extract statements that use `{}`", what));
+    }
+    if scope.locals.contains(name) || index.visible(file).iter().any(|k| index.file(k).is_some_and(|f| f.definitions.iter().any(|d| d.name == name))) {
+        return Err(format!("`{}` is already defined", name));
+    }
+
+    let members = symbols::member_tokens(tokens);
+    let variable = |i: usize| match &tokens[i] {
+        Token::Identifier(id) if !members[i] && scope.locals.contains(id.as_str()) => Some(id.as_str()),
+        _ => None,
+    };
+    let mut declared = HashSet::new();
+    symbols::collect_locals(&stmts, &mut declared);
+    let mut assigned = HashSet::new();
+    collect_assigned(&stmts, &mut assigned);
+    // Used before the selection too, for a selection inside a loop
+    let outside: HashSet<&str> = scope.tokens.clone().filter(|i| !(first..last).contains(i)).filter_map(variable).collect();
+    let mut params = Vec::new();
+    let mut outputs = Vec::new();
+    for n in (first..last).filter_map(variable) {
+        if !declared.contains(n) && !params.contains(&n) {
+            params.push(n);
+        }
+        let output = if declared.contains(n) { outside.contains(n) } else { assigned.contains(n) };
+        if output && !outputs.contains(&n) {
+            outputs.push(n);
+        }
+    }
+    if outputs.len() > 1 {
+        let list: Vec<String> = outputs.iter().map(|o| format!("`{}`", o)).collect();
+        return Err(format!("the selection sets {}, which are used outside it; a function can only return one value", list.join(", ")));
+    }
+
+    // The body, indented one level instead of where it was
+    let text = doc.text();
+    let (s, e) = (spans[first].start, spans[last - 1].end);
+    let line_start = text[..s].rfind('\n').map_or(0, |i| i + 1);
+    let indent: String = text[line_start..s].chars().take_while(|c| c.is_whitespace()).collect();
+    let mut body = String::new();
+    for (n, line) in text[s..e].lines().enumerate() {
+        let line = if n == 0 { line } else { line.strip_prefix(indent.as_str()).unwrap_or(line.trim_start()) };
+        if !line.is_empty() {
+            body.push_str("    ");
+            body.push_str(line);
+        }
+        body.push('\n');
+    }
+    let call = format!("{}({})", name, params.join(", "));
+    let call = match outputs.first() {
+        None => format!("{};", call),
+        Some(out) => {
+            body.push_str(&format!("    return {};\n", out));
+            if declared.contains(*out) {
+                format!("let {} = {};", out, call)
+            } else {
+                format!("{} = {};", out, call)
+            }
+        }
+    };
+    let item_end = doc.items().find(|(range, _)| range.contains(&scope.tokens.start)).map_or(scope.tokens.end, |(range, _)| range.end);
+    let after = doc.position(spans[item_end - 1].end);
+    let function = format!("\n\nfn {}({}) {{\n{}}}", name, params.join(", "), body);
+    let edits = vec![
+        TextEdit { range: Range { start: doc.position(s), end: doc.position(e) }, new_text: call },
+        TextEdit { range: Range { start: after, end: after }, new_text: function },
+    ];
+    Ok(FileEdits::from([(file.to_string(), edits)]))
+}
+
+/// What `from` would import `target` as: the shortest path the interpreter
+/// resolves back to it
+fn import_path(index: &SymbolIndex, from: &str, target: &str) -> Option<String> {
+    let module = target.strip_suffix(".cryo")?;
+    let dir = from.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let mut candidates = Vec::new();
+    if let Some(rest) = module.strip_prefix(dir).and_then(|r| r.strip_prefix('/')) {
+        candidates.push(rest);
+    }
+    for prefix in ["stdlib/", "examples/", "libs/", ""] {
+        if let Some(rest) = module.strip_prefix(prefix) {
+            candidates.push(rest);
+        }
+    }
+    candidates.into_iter().find(|c| index.resolve_import(from, c).as_deref() == Some(target)).map(str::to_string)
+}
+
+/// An `import` line after the last one, or above the first item
+fn import_edit(doc: &Document, path: &str) -> TextEdit {
+    let last = doc.items().filter(|(_, item)| matches!(item, TopLevel::Import(..))).last().map(|(tokens, _)| tokens.end - 1);
+    let (at, new_text) = match last {
+        Some(t) => (Position { line: doc.range(doc.spans()[t]).end.line + 1, character: 0 }, format!("import \"{}\"\n", path)),
+        None => (Position { line: doc.range(doc.spans()[0]).start.line, character: 0 }, format!("import \"{}\"\n\n", path)),
+    };
+    TextEdit { range: Range { start: at, end: at }, new_text }
+}
+
+/// The code actions for `range` in `file`: an import for each name there
+/// that only a module `file` doesn't import defines, and extracting the
+/// range into a function when it's whole statements
+pub fn code_actions(index: &SymbolIndex, file: &str, range: Range, source: Source) -> Vec<CodeAction> {
+    let mut actions = Vec::new();
+    let (Some(entry), Ok(doc)) = (index.file(file), document(file, source)) else {
+        return actions;
+    };
+    let visible = index.visible(file);
+    let top_level = |d: &symbols::Definition| !symbols::is_member(d.kind) && d.kind != crate::engine::SymbolKind::Import;
+    let mut offered = HashSet::new();
+    for r in entry.references.iter().filter(|r| !r.member && r.range.start <= range.end && range.start <= r.range.end) {
+        if visible.iter().any(|k| index.file(k).is_some_and(|f| f.definitions.iter().any(|d| d.name == r.name && top_level(d)))) {
+            continue;
+        }
+        for (key, _) in index.definitions_named(&r.name).into_iter().filter(|(_, d)| top_level(d)) {
+            let Some(path) = import_path(index, file, key) else { continue };
+            if offered.insert(path.clone()) {
+                actions.push(CodeAction {
+                    title: format!("Import \"{}\" for `{}`", path, r.name),
+                    kind: "quickfix",
+                    edits: FileEdits::from([(file.to_string(), vec![import_edit(&doc, &path)])]),
+                });
+            }
+        }
+    }
+    if range.start != range.end {
+        let name = (1..).map(|n| if n == 1 { "extracted".to_string() } else { format!("extracted{}", n) }).find(|n| index.definitions_named(n).is_empty()).unwrap_or_default();
+        if let Ok(edits) = extract_function(index, file, range, &name, source) {
+            actions.push(CodeAction { title: format!("Extract into function `{}`", name), kind: "refactor.extract", edits });
+        }
+    }
+    actions
+}
+
+/// `text` with `edits` made together, each at the place it names in `text`
+pub fn apply(text: &str, edits: &[TextEdit]) -> String {
+    let doc = Document::new(text);
+    let mut replacements: Vec<(usize, usize, &str)> = edits.iter().map(|e| (doc.offset(e.range.start), doc.offset(e.range.end), e.new_text.as_str())).collect();
+    replacements.sort_by_key(|&(start, end, _)| std::cmp::Reverse((start, end)));
+    let mut result = text.to_string();
+    for (start, end, new_text) in replacements {
+        result.replace_range(start..end, new_text);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn at(line: usize, character: usize) -> Position {
+        Position { line, character }
+    }
+
+    fn project(name: &str, files: &[(&str, &str)]) -> (PathBuf, SymbolIndex) {
+        let root = std::env::temp_dir().join(format!("cryo_refactor_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (name, text) in files {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        let index = SymbolIndex::open(&root).unwrap();
+        (root, index)
+    }
+
+    /// Every file with the edits made
+    fn edited(index: &SymbolIndex, edits: &FileEdits) -> BTreeMap<String, String> {
+        edits.iter().map(|(key, e)| (key.clone(), apply(&std::fs::read_to_string(index.path(key)).unwrap(), e))).collect()
+    }
+
+    #[test]
+    fn test_rename() {
+        let util = "struct Point { x: int, y: int }\nfn norm(p) {\n    let total = p.x * p.x;\n    return total + p.y * p.y;\n}\n";
+        let main = "import \"util\"\n\nfn main() {\n    let p = Point { x: 3, y: 4 };\n    let size = 2;\n    print(norm(p) + p.x + size);\n}\n";
+        let (root, index) = project("rename", &[("util.cryo", util), ("main.cryo", main)]);
+        let source = |key: &str| std::fs::read_to_string(root.join(key)).ok();
+
+        // A function, from a call in another module
+        let edits = rename(&index, "main.cryo", at(5, 11), "length", &source).unwrap();
+        let files = edited(&index, &edits);
+        assert!(files["util.cryo"].contains("fn length(p)"));
+        assert!(files["main.cryo"].contains("print(length(p) + p.x + size)"));
+        // A field: its definition, `p.x` and the `x:` key, but not other names
+        let files = edited(&index, &rename(&index, "util.cryo", at(0, 15), "left", &source).unwrap());
+        assert!(files["util.cryo"].starts_with("struct Point { left: int, y: int }\nfn norm(p) {\n    let total = p.left * p.left;"));
+        assert!(files["main.cryo"].contains("Point { left: 3, y: 4 }") && files["main.cryo"].contains("p.left + size"));
+        // A local variable stays inside its function
+        let edits = rename(&index, "util.cryo", at(2, 9), "sum", &source).unwrap();
+        assert_eq!(edits["util.cryo"].iter().map(|e| e.range.start).collect::<Vec<_>>(), [at(2, 8), at(3, 11)]);
+        assert_eq!(prepare_rename(&index, "main.cryo", at(4, 10), &source), Ok(Range { start: at(4, 8), end: at(4, 12) }));
+
+        // Refused: a taken name, a local that would hide it, a builtin, a bad name
+        assert!(rename(&index, "util.cryo", at(0, 8), "norm", &source).unwrap_err().contains("already defined"));
+        assert!(rename(&index, "util.cryo", at(1, 4), "size", &source).unwrap_err().contains("would hide"));
+        assert!(rename(&index, "main.cryo", at(5, 4), "show", &source).is_err());
+        assert!(rename(&index, "main.cryo", at(4, 9), "while", &source).unwrap_err().contains("valid name"));
+        assert!(rename(&index, "util.cryo", at(2, 9), "p", &source).unwrap_err().contains("already a variable"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_code_actions() {
+        let util = "fn helper(n) {\n    return n * 2;\n}\n";
+        let main = "fn main() {\n    let a = 1;\n    let b = a + 1;\n    let c = b * 2;\n    print(helper(c) + b);\n}\n";
+        let (root, index) = project("actions", &[("lib/util.cryo", util), ("main.cryo", main)]);
+        let source = |key: &str| std::fs::read_to_string(root.join(key)).ok();
+
+        // `helper` is only in lib/util.cryo, which main.cryo doesn't import
+        let actions = code_actions(&index, "main.cryo", Range { start: at(4, 12), end: at(4, 12) }, &source);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Import \"lib/util\" for `helper`");
+        assert!(edited(&index, &actions[0].edits)["main.cryo"].starts_with("import \"lib/util\"\n\nfn main() {"));
+
+        // `b` is set in the selection and read after it, `a` read from before
+        let actions = code_actions(&index, "main.cryo", Range { start: at(1, 4), end: at(2, 18) }, &source);
+        assert_eq!(actions.last().unwrap().title, "Extract into function `extracted`");
+        let edits = extract_function(&index, "main.cryo", Range { start: at(2, 4), end: at(2, 18) }, "step", &source).unwrap();
+        assert_eq!(
+            edited(&index, &edits)["main.cryo"],
+            "fn main() {\n    let a = 1;\n    let b = step(a);\n    let c = b * 2;\n    print(helper(c) + b);\n}\n\nfn step(a) {\n    let b = a + 1;\n    return b;\n}\n"
+        );
+        // Both `b` and `c` are used afterwards; half a statement isn't a statement
+        let lines = Range { start: at(2, 4), end: at(3, 18) };
+        assert!(extract_function(&index, "main.cryo", lines, "step", &source).unwrap_err().contains("only return one value"));
+        assert_eq!(extract_function(&index, "main.cryo", Range { start: at(2, 12), end: at(2, 18) }, "step", &source), Err(NOT_STATEMENTS.to_string()));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+// Cryo Differential Testing
+// `cryo selftest`: run one program through every execution engine as a
+// child process and compare what each prints and its exit code against the
+// tree-walking interpreter
+
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// One way of running a program: the `cryo` flags that select it
+pub struct Engine {
+    pub name: &'static str,
+    pub flags: &'static [&'static str],
+    /// stderr prefixes meaning the engine can't run the program at all
+    pub unsupported: &'static [&'static str],
+}
+
+/// Every engine, reference first
+pub const ENGINES: &[Engine] = &[
+    Engine { name: "interp", flags: &["--interpret"], unsupported: &[] },
+    // Every function the VM can compile runs there, the rest stay interpreted
+    Engine { name: "vm", flags: &["--interpret", "--bytecode"], unsupported: &[] },
+    Engine { name: "tiered", flags: &["--interpret", "--tier-bytecode"], unsupported: &[] },
+    // Tiered, with hot int functions compiled on to machine code
+    Engine { name: "jit", flags: &["--interpret", "--jit"], unsupported: &[] },
+    // Native compiler output run under lli
+    Engine { name: "native", flags: &["--emit-llvm", "--run"], unsupported: &["Native compilation error", "--run needs LLVM's lli"] },
+];
+
+/// What a run printed and how it ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub stdout: String,
+    pub stderr: String,
+    /// None when the child was killed (signal or timeout)
+    pub code: Option<i32>,
+    pub timed_out: bool,
+}
+
+/// Run `cryo FLAGS FILE ARGS` with `exe`, killing it after `timeout`.
+/// Err when the engine couldn't start or doesn't support the program.
+pub fn run_engine(exe: &Path, engine: &Engine, file: &str, args: &[String], timeout: Duration) -> Result<Outcome, String> {
+    let mut child = Command::new(exe)
+        .args(engine.flags)
+        .arg(file)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot start {}: {}", exe.display(), e))?;
+    // Drain the pipes on other threads so a chatty program can't fill one and stall
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            String::from_utf8_lossy(&buf).into_owned()
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let start = Instant::now();
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            let _ = child.kill();
+            timed_out = true;
+            break child.wait().map_err(|e| e.to_string())?;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    let outcome = Outcome { stdout: stdout.join().unwrap_or_default(), stderr: stderr.join().unwrap_or_default(), code: status.code(), timed_out };
+    match engine.unsupported.iter().find_map(|prefix| outcome.stderr.lines().find(|l| l.starts_with(prefix))) {
+        Some(reason) if outcome.code != Some(0) => Err(reason.to_string()),
+        _ => Ok(outcome),
+    }
+}
+
+/// How `other` differs from `reference`, or None when they agree
+pub fn compare(reference: &Outcome, other: &Outcome) -> Option<String> {
+    if other.timed_out && !reference.timed_out {
+        return Some("timed out".to_string());
+    }
+    let expected: Vec<&str> = reference.stdout.lines().collect();
+    let actual: Vec<&str> = other.stdout.lines().collect();
+    if let Some(i) = (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i)) {
+        let show = |line: Option<&&str>| line.map_or("<end of output>".to_string(), |l| format!("{:?}", l));
+        return Some(format!("stdout line {}: expected {}, got {}", i + 1, show(expected.get(i)), show(actual.get(i))));
+    }
+    if reference.code != other.code {
+        let show = |code: Option<i32>| code.map_or("killed".to_string(), |c| c.to_string());
+        return Some(format!("exit code: expected {}, got {}", show(reference.code), show(other.code)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(stdout: &str, code: i32) -> Outcome {
+        Outcome { stdout: stdout.to_string(), stderr: String::new(), code: Some(code), timed_out: false }
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(&outcome("1\n2\n", 0), &outcome("1\n2\n", 0)), None);
+        assert_eq!(compare(&outcome("1\n2\n", 0), &outcome("1\n3\n", 0)).unwrap(), "stdout line 2: expected \"2\", got \"3\"");
+        assert_eq!(compare(&outcome("1\n", 0), &outcome("1\n2\n", 0)).unwrap(), "stdout line 2: expected <end of output>, got \"2\"");
+        assert_eq!(compare(&outcome("", 0), &outcome("", 101)).unwrap(), "exit code: expected 0, got 101");
+    }
+}
@@ -0,0 +1,295 @@
+// ============================================
+// Cryo Semantic Tokens
+// What each token of a document is, for `textDocument/semanticTokens` in
+// `cryo lsp`, so editors color Cryo from the parser's view of it instead of
+// a grammar of their own. Names are classified from the file's syntax tree,
+// its functions' parameters and locals, and what the modules it imports
+// define (see symbols.rs). A variable or global is `mutable` when something
+// assigns to it after its `let`, and `readonly` when nothing does.
+//
+// Comments never reach the lexer, so they're left to the editor.
+// ============================================
+
+use crate::engine::{Document, Position, SymbolKind};
+use crate::lexer::{Span, Token};
+use crate::symbols::{self, FileSymbols, SymbolIndex};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenType {
+    Keyword,
+    Number,
+    String,
+    Decorator,
+    Function,
+    Method,
+    Macro,
+    Struct,
+    Enum,
+    EnumMember,
+    Interface,
+    Type,
+    Property,
+    Parameter,
+    Variable,
+}
+
+impl TokenType {
+    /// In legend order
+    pub const ALL: [TokenType; 15] = [
+        TokenType::Keyword,
+        TokenType::Number,
+        TokenType::String,
+        TokenType::Decorator,
+        TokenType::Function,
+        TokenType::Method,
+        TokenType::Macro,
+        TokenType::Struct,
+        TokenType::Enum,
+        TokenType::EnumMember,
+        TokenType::Interface,
+        TokenType::Type,
+        TokenType::Property,
+        TokenType::Parameter,
+        TokenType::Variable,
+    ];
+
+    /// The protocol's name for it
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenType::Keyword => "keyword",
+            TokenType::Number => "number",
+            TokenType::String => "string",
+            TokenType::Decorator => "decorator",
+            TokenType::Function => "function",
+            TokenType::Method => "method",
+            TokenType::Macro => "macro",
+            TokenType::Struct => "struct",
+            TokenType::Enum => "enum",
+            TokenType::EnumMember => "enumMember",
+            TokenType::Interface => "interface",
+            TokenType::Type => "type",
+            TokenType::Property => "property",
+            TokenType::Parameter => "parameter",
+            TokenType::Variable => "variable",
+        }
+    }
+
+    fn of(kind: SymbolKind) -> TokenType {
+        match kind {
+            SymbolKind::Function => TokenType::Function,
+            SymbolKind::Method => TokenType::Method,
+            SymbolKind::Struct | SymbolKind::Impl => TokenType::Struct,
+            SymbolKind::Field => TokenType::Property,
+            SymbolKind::Enum => TokenType::Enum,
+            SymbolKind::Variant => TokenType::EnumMember,
+            SymbolKind::Global => TokenType::Variable,
+            SymbolKind::Import => TokenType::String,
+            SymbolKind::Trait => TokenType::Interface,
+            SymbolKind::Macro => TokenType::Macro,
+        }
+    }
+}
+
+/// Modifier names, in legend order: bit `n` of a token's modifiers is `MODIFIERS[n]`
+pub const MODIFIERS: [&str; 4] = ["declaration", "readonly", "mutable", "defaultLibrary"];
+pub const DECLARATION: u32 = 1;
+pub const READONLY: u32 = 2;
+pub const MUTABLE: u32 = 4;
+/// A call to nothing the project defines: a builtin
+pub const DEFAULT_LIBRARY: u32 = 8;
+
+/// One token on one line; a string spanning lines is one per line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub start: Position,
+    /// UTF-16 code units
+    pub length: usize,
+    pub kind: TokenType,
+    pub modifiers: u32,
+}
+
+fn is_keyword(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Fn | Token::Let | Token::Return | Token::If | Token::Else | Token::While | Token::True | Token::False
+            | Token::Break | Token::Continue | Token::Struct | Token::Enum | Token::Match | Token::Import | Token::Async
+            | Token::Await | Token::Extern | Token::Defer | Token::Macro | Token::Trait | Token::Impl | Token::For
+            | Token::Try | Token::Catch | Token::Throw | Token::With | Token::Null
+    )
+}
+
+fn is_decorator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::At | Token::WasmExport | Token::WasmImport | Token::DecController(_) | Token::DecGet(_) | Token::DecPost(_)
+            | Token::DecPut(_) | Token::DecDelete(_) | Token::DecPatch(_) | Token::DecInjectable | Token::DecModule
+            | Token::DecBody | Token::DecParam(_) | Token::DecQuery(_) | Token::DecGuard(_) | Token::DecMiddleware(_)
+    )
+}
+
+/// Every token of `doc` worth coloring, in order. With an index, `file` is
+/// the document's key in it, and names the modules it imports define are
+/// classified too.
+pub fn tokens(doc: &Document, index: Option<(&SymbolIndex, &str)>) -> Vec<SemanticToken> {
+    let (tokens, spans) = (doc.tokens(), doc.spans());
+    let own = FileSymbols::scan(doc);
+    let declared: HashMap<usize, SymbolKind> = own.definitions.iter().map(|d| (doc.offset(d.selection.start), d.kind)).collect();
+    // Top-level names this file can see, its own last so they win
+    let mut visible: HashMap<&str, SymbolKind> = HashMap::new();
+    if let Some((index, file)) = index {
+        for key in index.visible(file).iter().skip(1) {
+            let definitions = index.file(key).map(|f| f.definitions.as_slice()).unwrap_or_default();
+            visible.extend(definitions.iter().filter(|d| d.container.is_none()).map(|d| (d.name.as_str(), d.kind)));
+        }
+    }
+    visible.extend(own.definitions.iter().filter(|d| d.container.is_none()).map(|d| (d.name.as_str(), d.kind)));
+    let members = symbols::member_tokens(tokens);
+    let scopes = symbols::scopes(doc);
+    let scope_of = |i: usize| scopes.iter().position(|s| s.tokens.contains(&i));
+
+    // Names assigned after their `let`: (scope, name) for locals, else globals
+    let mut assigned_locals = HashSet::new();
+    let mut assigned_globals = HashSet::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Identifier(name) = token else { continue };
+        if members[i] || tokens.get(i + 1) != Some(&Token::Eq) || i.checked_sub(1).map(|p| &tokens[p]) == Some(&Token::Let) {
+            continue;
+        }
+        match scope_of(i).filter(|&s| scopes[s].locals.contains(name.as_str())) {
+            Some(s) => assigned_locals.insert((s, name.as_str())),
+            None => assigned_globals.insert(name.as_str()),
+        };
+    }
+    let global = |name: &str| if assigned_globals.contains(name) { MUTABLE } else { READONLY };
+
+    let mut found = Vec::new();
+    let mut last: Option<Span> = None;
+    for (i, token) in tokens.iter().enumerate() {
+        // `@name` is two tokens sharing one span
+        if last == Some(spans[i]) || *token == Token::Eof {
+            continue;
+        }
+        last = Some(spans[i]);
+        let prev = i.checked_sub(1).map(|p| &tokens[p]);
+        let next = tokens.get(i + 1);
+        let (kind, modifiers) = match token {
+            Token::Number(_) | Token::Float(_) => (TokenType::Number, 0),
+            Token::String(_) | Token::Interpolated(_) => (TokenType::String, 0),
+            Token::Print => (TokenType::Function, DEFAULT_LIBRARY),
+            Token::SelfType => (TokenType::Type, 0),
+            t if is_keyword(t) => (TokenType::Keyword, 0),
+            t if is_decorator(t) => (TokenType::Decorator, 0),
+            Token::Identifier(name) => {
+                let name = name.as_str();
+                let local = scope_of(i).filter(|&s| scopes[s].locals.contains(name));
+                if let Some(&kind) = declared.get(&spans[i].start) {
+                    let modifiers = if kind == SymbolKind::Global { global(name) } else { 0 };
+                    (TokenType::of(kind), DECLARATION | modifiers)
+                } else if members[i] {
+                    match prev {
+                        Some(Token::ColonColon) if next != Some(&Token::LParen) => (TokenType::EnumMember, 0),
+                        Some(Token::Dot | Token::ColonColon) if next == Some(&Token::LParen) => (TokenType::Method, 0),
+                        _ => (TokenType::Property, 0),
+                    }
+                } else if let Some(s) = local {
+                    let scope = &scopes[s];
+                    let kind = if scope.params.iter().any(|p| p == name) { TokenType::Parameter } else { TokenType::Variable };
+                    let declaration = if prev == Some(&Token::Let) || i < scope.body { DECLARATION } else { 0 };
+                    let mutable = if assigned_locals.contains(&(s, name)) { MUTABLE } else { READONLY };
+                    (kind, declaration | mutable)
+                } else if let Some(&kind) = visible.get(name) {
+                    let modifiers = if kind == SymbolKind::Global { global(name) } else { 0 };
+                    (TokenType::of(kind), modifiers)
+                } else if next == Some(&Token::LParen) {
+                    (TokenType::Function, DEFAULT_LIBRARY)
+                } else if next == Some(&Token::ColonColon) {
+                    (TokenType::Type, 0)
+                } else if matches!(prev, Some(Token::Colon | Token::Arrow | Token::Lt | Token::LBracket | Token::Star)) {
+                    // In a type annotation: nothing else would be unresolved there
+                    (TokenType::Type, 0)
+                } else {
+                    (TokenType::Variable, 0)
+                }
+            }
+            _ => continue,
+        };
+        push_lines(doc, spans[i], kind, modifiers, &mut found);
+    }
+    found
+}
+
+/// A token as one entry per line it covers
+fn push_lines(doc: &Document, span: Span, kind: TokenType, modifiers: u32, found: &mut Vec<SemanticToken>) {
+    let mut offset = span.start;
+    for piece in doc.text()[span.start..span.end].split_inclusive('\n') {
+        let line = piece.trim_end_matches(['\n', '\r']);
+        if !line.is_empty() {
+            found.push(SemanticToken { start: doc.position(offset), length: line.encode_utf16().count(), kind, modifiers });
+        }
+        offset += piece.len();
+    }
+}
+
+/// The protocol's encoding: five numbers a token, its line and start
+/// relative to the token before it
+pub fn encode(tokens: &[SemanticToken]) -> Vec<u32> {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let mut prev = Position::default();
+    for token in tokens {
+        let line = token.start.line - prev.line;
+        let character = if line == 0 { token.start.character - prev.character } else { token.start.character };
+        let kind = TokenType::ALL.iter().position(|&k| k == token.kind).unwrap_or_default();
+        data.extend([line, character, token.length, kind, token.modifiers as usize].map(|n| n as u32));
+        prev = token.start;
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classification() {
+        let source = "import \"shapes\"\n\nlet count = 0;\nlet LIMIT = 10;\n\nenum Color { Red, Green }\n\n\
+                      @Get(\"/\")\nfn bump(step, body: Config) {\n    let total = step;\n    let fixed = \"a\n b\";\n    total = total + Circle::area(step) + len(fixed);\n    count = count + body.size;\n    return LIMIT;\n}\n";
+        let doc = Document::new(source);
+        let tokens = tokens(&doc, None);
+        let text = |t: &SemanticToken| {
+            let line = source.lines().nth(t.start.line).unwrap();
+            line.chars().skip(t.start.character).take(t.length).collect::<String>()
+        };
+        let find = |name: &str, nth: usize| *tokens.iter().filter(|t| text(t) == name).nth(nth).unwrap_or_else(|| panic!("no {} #{}", name, nth));
+        let is = |name: &str, nth: usize, kind: TokenType, modifiers: u32| {
+            let t = find(name, nth);
+            assert_eq!((t.kind, t.modifiers), (kind, modifiers), "{} #{}", name, nth);
+        };
+
+        is("count", 0, TokenType::Variable, DECLARATION | MUTABLE);
+        is("LIMIT", 0, TokenType::Variable, DECLARATION | READONLY);
+        is("count", 2, TokenType::Variable, MUTABLE);
+        is("Color", 0, TokenType::Enum, DECLARATION);
+        is("Red", 0, TokenType::EnumMember, DECLARATION);
+        is("LIMIT", 1, TokenType::Variable, READONLY);
+        is("bump", 0, TokenType::Function, DECLARATION);
+        is("step", 0, TokenType::Parameter, DECLARATION | READONLY);
+        is("step", 2, TokenType::Parameter, READONLY);
+        is("body", 0, TokenType::Parameter, DECLARATION | READONLY);
+        is("Config", 0, TokenType::Type, 0);
+        is("total", 0, TokenType::Variable, DECLARATION | MUTABLE);
+        is("fixed", 1, TokenType::Variable, READONLY);
+        is("Circle", 0, TokenType::Type, 0);
+        is("area", 0, TokenType::Method, 0);
+        is("len", 0, TokenType::Function, DEFAULT_LIBRARY);
+        is("size", 0, TokenType::Property, 0);
+        is("fn", 0, TokenType::Keyword, 0);
+        is("\"shapes\"", 0, TokenType::String, 0);
+        // The string across two lines is two tokens
+        assert_eq!(tokens.iter().filter(|t| t.kind == TokenType::Decorator).count(), 1);
+        is(" b\"", 0, TokenType::String, 0);
+
+        let data = encode(&tokens[..3]);
+        assert_eq!(data, [0, 0, 6, 0, 0, 0, 7, 8, 2, 0, 2, 0, 3, 0, 0]);
+    }
+}
@@ -0,0 +1,303 @@
+// ============================================
+// Cryo Session Module
+// Signed session ids and pluggable session stores for the HTTP server
+// ============================================
+
+use crate::crypto;
+#[cfg(feature = "ffi")]
+use libloading::Library;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Session data is stored as serialized JSON so every backend handles it the same way
+pub trait SessionStore: Send + Sync {
+    fn load(&self, id: &str) -> Option<String>;
+    fn save(&self, id: &str, data: &str, expires_at: i64);
+    fn destroy(&self, id: &str);
+}
+
+pub fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+/// Process-local store; sessions are lost on restart
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, (String, i64)>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore { sessions: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Option<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(id) {
+            Some((_, expires)) if *expires <= now_secs() => {
+                sessions.remove(id);
+                None
+            }
+            Some((data, _)) => Some(data.clone()),
+            None => None,
+        }
+    }
+
+    fn save(&self, id: &str, data: &str, expires_at: i64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        // Opportunistic sweep so abandoned sessions don't accumulate
+        if sessions.len() % 256 == 255 {
+            let now = now_secs();
+            sessions.retain(|_, (_, expires)| *expires > now);
+        }
+        sessions.insert(id.to_string(), (data.to_string(), expires_at));
+    }
+
+    fn destroy(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+/// Tells sqlite3_bind_text to copy the buffer
+const SQLITE_TRANSIENT: isize = -1;
+
+#[cfg(feature = "ffi")]
+type OpenFn = unsafe extern "C" fn(*const c_char, *mut *mut c_void) -> c_int;
+type ExecFn = unsafe extern "C" fn(*mut c_void, *const c_char, *const c_void, *mut c_void, *mut *mut c_char) -> c_int;
+type PrepareFn = unsafe extern "C" fn(*mut c_void, *const c_char, c_int, *mut *mut c_void, *mut *const c_char) -> c_int;
+type BindTextFn = unsafe extern "C" fn(*mut c_void, c_int, *const c_char, c_int, isize) -> c_int;
+type BindInt64Fn = unsafe extern "C" fn(*mut c_void, c_int, i64) -> c_int;
+type StepFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+type ColumnTextFn = unsafe extern "C" fn(*mut c_void, c_int) -> *const c_char;
+type FinalizeFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+type CloseFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+enum Param<'a> {
+    Text(&'a str),
+    Int(i64),
+}
+
+/// Minimal binding to the system SQLite library, loaded at runtime so the
+/// interpreter doesn't link against it unless a SQLite store is used
+struct Sqlite {
+    db: *mut c_void,
+    exec: ExecFn,
+    prepare: PrepareFn,
+    bind_text: BindTextFn,
+    bind_int64: BindInt64Fn,
+    step: StepFn,
+    column_text: ColumnTextFn,
+    finalize: FinalizeFn,
+    close: CloseFn,
+    _lib: Library,
+}
+
+// Builds without the `ffi` feature (e.g. wasm) can't load libsqlite3
+#[cfg(not(feature = "ffi"))]
+type Library = ();
+
+// The connection is only ever used behind SqliteStore's mutex
+unsafe impl Send for Sqlite {}
+
+impl Sqlite {
+    #[cfg(not(feature = "ffi"))]
+    fn open(_path: &str) -> Result<Self, String> {
+        Err("SQLite session store: this build has no FFI support".to_string())
+    }
+
+    #[cfg(feature = "ffi")]
+    fn open(path: &str) -> Result<Self, String> {
+        let names: &[&str] = if cfg!(windows) {
+            &["sqlite3.dll", "libsqlite3.dll"]
+        } else if cfg!(target_os = "macos") {
+            &["libsqlite3.dylib"]
+        } else {
+            &["libsqlite3.so.0", "libsqlite3.so"]
+        };
+        let lib = names.iter()
+            .find_map(|name| unsafe { Library::new(name) }.ok())
+            .ok_or("SQLite session store: libsqlite3 not found")?;
+        unsafe {
+            let open: OpenFn = *lib.get(b"sqlite3_open\0").map_err(|e| e.to_string())?;
+            let c_path = CString::new(path).map_err(|e| e.to_string())?;
+            let mut db = std::ptr::null_mut();
+            if open(c_path.as_ptr(), &mut db) != SQLITE_OK {
+                return Err(format!("SQLite session store: cannot open {}", path));
+            }
+            Ok(Sqlite {
+                db,
+                exec: *lib.get(b"sqlite3_exec\0").map_err(|e| e.to_string())?,
+                prepare: *lib.get(b"sqlite3_prepare_v2\0").map_err(|e| e.to_string())?,
+                bind_text: *lib.get(b"sqlite3_bind_text\0").map_err(|e| e.to_string())?,
+                bind_int64: *lib.get(b"sqlite3_bind_int64\0").map_err(|e| e.to_string())?,
+                step: *lib.get(b"sqlite3_step\0").map_err(|e| e.to_string())?,
+                column_text: *lib.get(b"sqlite3_column_text\0").map_err(|e| e.to_string())?,
+                finalize: *lib.get(b"sqlite3_finalize\0").map_err(|e| e.to_string())?,
+                close: *lib.get(b"sqlite3_close\0").map_err(|e| e.to_string())?,
+                _lib: lib,
+            })
+        }
+    }
+
+    fn exec(&self, sql: &str) -> Result<(), String> {
+        let c_sql = CString::new(sql).map_err(|e| e.to_string())?;
+        let rc = unsafe { (self.exec)(self.db, c_sql.as_ptr(), std::ptr::null(), std::ptr::null_mut(), std::ptr::null_mut()) };
+        if rc == SQLITE_OK { Ok(()) } else { Err(format!("SQLite error {}", rc)) }
+    }
+
+    /// Run one statement, returning the first column of the first row, if any
+    fn query(&self, sql: &str, params: &[Param]) -> Option<String> {
+        let c_sql = CString::new(sql).ok()?;
+        let texts: Vec<CString> = params.iter()
+            .filter_map(|p| match p {
+                Param::Text(s) => CString::new(*s).ok(),
+                Param::Int(_) => None,
+            })
+            .collect();
+        unsafe {
+            let mut stmt = std::ptr::null_mut();
+            if (self.prepare)(self.db, c_sql.as_ptr(), -1, &mut stmt, std::ptr::null_mut()) != SQLITE_OK {
+                return None;
+            }
+            let mut texts = texts.iter();
+            for (i, p) in params.iter().enumerate() {
+                let idx = i as c_int + 1;
+                match p {
+                    Param::Text(_) => {
+                        let text = texts.next()?;
+                        (self.bind_text)(stmt, idx, text.as_ptr(), -1, SQLITE_TRANSIENT);
+                    }
+                    Param::Int(n) => {
+                        (self.bind_int64)(stmt, idx, *n);
+                    }
+                }
+            }
+            let mut result = None;
+            if (self.step)(stmt) == SQLITE_ROW {
+                let text = (self.column_text)(stmt, 0);
+                if !text.is_null() {
+                    result = Some(CStr::from_ptr(text).to_string_lossy().into_owned());
+                }
+            }
+            (self.finalize)(stmt);
+            result
+        }
+    }
+}
+
+impl Drop for Sqlite {
+    fn drop(&mut self) {
+        unsafe {
+            (self.close)(self.db);
+        }
+    }
+}
+
+/// Sessions persisted in a SQLite database file, surviving restarts
+pub struct SqliteStore {
+    conn: Mutex<Sqlite>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let conn = Sqlite::open(path)?;
+        conn.exec("CREATE TABLE IF NOT EXISTS cryo_sessions (id TEXT PRIMARY KEY, data TEXT NOT NULL, expires INTEGER NOT NULL)")?;
+        Ok(SqliteStore { conn: Mutex::new(conn) })
+    }
+}
+
+impl SessionStore for SqliteStore {
+    fn load(&self, id: &str) -> Option<String> {
+        self.conn.lock().unwrap().query(
+            "SELECT data FROM cryo_sessions WHERE id = ?1 AND expires > ?2",
+            &[Param::Text(id), Param::Int(now_secs())],
+        )
+    }
+
+    fn save(&self, id: &str, data: &str, expires_at: i64) {
+        let conn = self.conn.lock().unwrap();
+        conn.query(
+            "INSERT OR REPLACE INTO cryo_sessions (id, data, expires) VALUES (?1, ?2, ?3)",
+            &[Param::Text(id), Param::Text(data), Param::Int(expires_at)],
+        );
+        conn.query("DELETE FROM cryo_sessions WHERE expires <= ?1", &[Param::Int(now_secs())]);
+    }
+
+    fn destroy(&self, id: &str) {
+        self.conn.lock().unwrap().query("DELETE FROM cryo_sessions WHERE id = ?1", &[Param::Text(id)]);
+    }
+}
+
+/// Server-wide session settings, set once by `session_config` and shared by all workers
+pub struct SessionConfig {
+    pub secret: Vec<u8>,
+    pub cookie_name: String,
+    pub max_age: i64,
+    pub secure: bool,
+    pub store: Arc<dyn SessionStore>,
+}
+
+impl SessionConfig {
+    /// Fresh random session id with its signature, in cookie form `id.sig`
+    pub fn new_id(&self) -> Result<(String, String), String> {
+        let id = crypto::to_hex(&crypto::random_bytes(16).map_err(|e| format!("session id: {}", e))?);
+        let cookie = format!("{}.{}", id, self.sign(&id));
+        Ok((id, cookie))
+    }
+
+    fn sign(&self, id: &str) -> String {
+        crypto::to_hex(&crypto::hmac_sha256(&self.secret, id.as_bytes()))
+    }
+
+    /// Session id from a cookie value, if its signature is valid
+    pub fn verify(&self, cookie: &str) -> Option<String> {
+        let (id, sig) = cookie.rsplit_once('.')?;
+        if crypto::constant_time_eq(sig.as_bytes(), self.sign(id).as_bytes()) {
+            Some(id.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+fn config_slot() -> &'static RwLock<Option<Arc<SessionConfig>>> {
+    static CONFIG: RwLock<Option<Arc<SessionConfig>>> = RwLock::new(None);
+    &CONFIG
+}
+
+pub fn configure(config: SessionConfig) {
+    *config_slot().write().unwrap() = Some(Arc::new(config));
+}
+
+pub fn config() -> Option<Arc<SessionConfig>> {
+    config_slot().read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_ids() {
+        let config = SessionConfig {
+            secret: b"s3cret".to_vec(),
+            cookie_name: "sid".to_string(),
+            max_age: 60,
+            secure: false,
+            store: Arc::new(MemoryStore::new()),
+        };
+        let (id, cookie) = config.new_id().unwrap();
+        assert_eq!(config.verify(&cookie), Some(id.clone()));
+        assert_eq!(config.verify(&format!("{}.{}", id, "0".repeat(64))), None);
+
+        config.store.save(&id, "{\"n\":1}", now_secs() + 60);
+        assert_eq!(config.store.load(&id).as_deref(), Some("{\"n\":1}"));
+        config.store.save(&id, "{}", now_secs() - 1);
+        assert_eq!(config.store.load(&id), None);
+    }
+}
@@ -0,0 +1,445 @@
+// ============================================
+// Cryo SMTP Client
+// smtp_send(): deliver one message to a mail server (submission on port
+// 587 with STARTTLS by default), authenticating with AUTH PLAIN or LOGIN.
+// TLS comes from the `tls` cargo feature (on by default); without it only
+// plain connections (`tls: "none"`) work.
+// ============================================
+
+use crate::crypto;
+use crate::encoding;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How the connection is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// Plain connection upgraded with STARTTLS; fails if the server can't
+    StartTls,
+    /// TLS from the first byte (port 465)
+    Implicit,
+    /// No encryption; credentials are refused
+    None,
+}
+
+impl Security {
+    pub fn parse(name: &str) -> Result<Security, String> {
+        match name {
+            "starttls" => Ok(Security::StartTls),
+            "tls" | "implicit" => Ok(Security::Implicit),
+            "none" => Ok(Security::None),
+            other => Err(format!("unknown tls mode '{}' (expected \"starttls\", \"tls\" or \"none\")", other)),
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Security::StartTls => 587,
+            Security::Implicit => 465,
+            Security::None => 25,
+        }
+    }
+}
+
+/// Where and how to connect
+#[derive(Debug, Clone)]
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+    pub security: Security,
+    /// Credentials for AUTH, if the server wants them
+    pub login: Option<(String, String)>,
+    /// Name sent with EHLO
+    pub helo: String,
+    pub timeout: Duration,
+}
+
+/// One message. Addresses may be bare (`ada@example.com`) or named
+/// (`Ada <ada@example.com>`).
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    pub from: String,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    /// Receives the message without appearing in its headers
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    /// Plain-text body
+    pub body: Option<String>,
+    /// HTML body; sent as multipart/alternative when there is also `body`
+    pub html: Option<String>,
+}
+
+/// What the server said when it accepted the message
+#[derive(Debug, Clone)]
+pub struct Sent {
+    pub message_id: String,
+    pub response: String,
+}
+
+enum Stream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<native_tls::TlsStream<TcpStream>>),
+}
+
+impl Stream {
+    /// The TCP connection, if it isn't encrypted yet
+    fn into_plain(self) -> Option<TcpStream> {
+        match self {
+            Stream::Plain(s) => Some(s),
+            #[cfg(feature = "tls")]
+            Stream::Tls(_) => None,
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Plain(s) => s.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(s) => s.flush(),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn start_tls(stream: TcpStream, host: &str) -> Result<Stream, String> {
+    let connector = native_tls::TlsConnector::new().map_err(|e| format!("TLS setup failed: {}", e))?;
+    let tls = connector.connect(host, stream).map_err(|e| format!("TLS handshake with {} failed: {}", host, e))?;
+    Ok(Stream::Tls(Box::new(tls)))
+}
+
+#[cfg(not(feature = "tls"))]
+fn start_tls(_stream: TcpStream, _host: &str) -> Result<Stream, String> {
+    Err("this cryo was built without TLS support (cargo feature `tls`); use tls: \"none\" for a plain connection".to_string())
+}
+
+struct Session {
+    conn: BufReader<Stream>,
+}
+
+impl Session {
+    /// Read one reply, joining continuation lines; Err unless its code is one of `expect`
+    fn reply(&mut self, expect: &[u16]) -> Result<(u16, String), String> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            let n = self.conn.read_line(&mut line).map_err(|e| format!("reading from server: {}", e))?;
+            if n == 0 {
+                return Err("server closed the connection".to_string());
+            }
+            let line = line.trim_end();
+            let code: u16 = line.get(..3).and_then(|c| c.parse().ok()).ok_or_else(|| format!("bad reply from server: {}", line))?;
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(line);
+            // "250-..." continues, "250 ..." ends
+            if line.as_bytes().get(3) != Some(&b'-') {
+                if !expect.contains(&code) {
+                    return Err(text);
+                }
+                return Ok((code, text));
+            }
+        }
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        let stream = self.conn.get_mut();
+        stream.write_all(line.as_bytes()).and_then(|_| stream.write_all(b"\r\n")).and_then(|_| stream.flush()).map_err(|e| format!("writing to server: {}", e))
+    }
+
+    fn command(&mut self, line: &str, expect: &[u16]) -> Result<String, String> {
+        self.send(line)?;
+        self.reply(expect).map(|(_, text)| text)
+    }
+}
+
+/// Deliver `message` through `server`
+pub fn send(server: &Server, message: &Message) -> Result<Sent, String> {
+    let recipients: Vec<String> = message.to.iter().chain(&message.cc).chain(&message.bcc).map(|a| mailbox(a)).collect::<Result<_, _>>()?;
+    if recipients.is_empty() {
+        return Err("no recipients".to_string());
+    }
+    let sender = mailbox(&message.from)?;
+    let unique = crypto::random_bytes(8).map_err(|e| e.to_string())?;
+    let message_id = format!("<{}.{}@{}>", crate::session::now_secs(), crypto::to_hex(&unique), sender.rsplit('@').next().unwrap_or("localhost"));
+    let data = format_message(message, &message_id, crate::session::now_secs())?;
+    if server.login.is_some() && server.security == Security::None {
+        return Err("refusing to send credentials over an unencrypted connection; use tls: \"starttls\" or \"tls\"".to_string());
+    }
+
+    let addr = (server.host.as_str(), server.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("{}:{}: {}", server.host, server.port, e))?
+        .next()
+        .ok_or_else(|| format!("{}: no address", server.host))?;
+    let tcp = TcpStream::connect_timeout(&addr, server.timeout).map_err(|e| format!("connecting to {}:{}: {}", server.host, server.port, e))?;
+    let _ = tcp.set_read_timeout(Some(server.timeout));
+    let _ = tcp.set_write_timeout(Some(server.timeout));
+    let stream = match server.security {
+        Security::Implicit => start_tls(tcp, &server.host)?,
+        _ => Stream::Plain(tcp),
+    };
+    let mut session = Session { conn: BufReader::new(stream) };
+    session.reply(&[220])?;
+    let mut features = session.command(&format!("EHLO {}", server.helo), &[250])?;
+    if server.security == Security::StartTls {
+        if !has_extension(&features, "STARTTLS") {
+            return Err(format!("{} does not offer STARTTLS", server.host));
+        }
+        session.command("STARTTLS", &[220])?;
+        let tcp = session.conn.into_inner().into_plain().ok_or("STARTTLS on an encrypted connection")?;
+        session = Session { conn: BufReader::new(start_tls(tcp, &server.host)?) };
+        // Capabilities may differ once encrypted (AUTH is often only offered now)
+        features = session.command(&format!("EHLO {}", server.helo), &[250])?;
+    }
+    if let Some((user, pass)) = &server.login {
+        if has_extension(&features, "AUTH") && !auth_mechanisms(&features).contains(&"PLAIN".to_string()) {
+            session.command("AUTH LOGIN", &[334])?;
+            session.command(&encoding::base64_encode(user.as_bytes(), false, true), &[334])?;
+            session.command(&encoding::base64_encode(pass.as_bytes(), false, true), &[235])?;
+        } else {
+            let token = format!("\0{}\0{}", user, pass);
+            session.command(&format!("AUTH PLAIN {}", encoding::base64_encode(token.as_bytes(), false, true)), &[235])?;
+        }
+    }
+    session.command(&format!("MAIL FROM:<{}>", sender), &[250])?;
+    for rcpt in &recipients {
+        session.command(&format!("RCPT TO:<{}>", rcpt), &[250, 251])?;
+    }
+    session.command("DATA", &[354])?;
+    let stream = session.conn.get_mut();
+    stream.write_all(data.as_bytes()).and_then(|_| stream.write_all(b".\r\n")).map_err(|e| format!("writing to server: {}", e))?;
+    let (_, response) = session.reply(&[250])?;
+    let _ = session.command("QUIT", &[221]);
+    Ok(Sent { message_id, response })
+}
+
+fn has_extension(ehlo: &str, name: &str) -> bool {
+    ehlo.lines().skip(1).any(|l| l.get(4..).is_some_and(|ext| ext.split_whitespace().next().is_some_and(|e| e.eq_ignore_ascii_case(name))))
+}
+
+fn auth_mechanisms(ehlo: &str) -> Vec<String> {
+    ehlo.lines()
+        .filter_map(|l| l.get(4..))
+        .find(|ext| ext.to_ascii_uppercase().starts_with("AUTH "))
+        .map(|ext| ext.split_whitespace().skip(1).map(|m| m.to_ascii_uppercase()).collect())
+        .unwrap_or_default()
+}
+
+/// Reject header values that could smuggle in extra headers or commands
+fn header_safe(name: &str, value: &str) -> Result<(), String> {
+    if value.contains(['\r', '\n']) {
+        return Err(format!("{} must not contain line breaks", name));
+    }
+    Ok(())
+}
+
+/// The bare address of `Name <addr>` or `addr`
+fn mailbox(address: &str) -> Result<String, String> {
+    header_safe("address", address)?;
+    let addr = match (address.rfind('<'), address.rfind('>')) {
+        (Some(open), Some(close)) if open < close => &address[open + 1..close],
+        _ => address,
+    }
+    .trim();
+    let valid = addr.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty()) && !addr.contains([' ', '<', '>']);
+    if !valid {
+        return Err(format!("invalid email address '{}'", address));
+    }
+    Ok(addr.to_string())
+}
+
+/// RFC 2047 encoded-word for non-ASCII header text
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        text.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", encoding::base64_encode(text.as_bytes(), false, true))
+    }
+}
+
+/// A body part's headers and content: 7bit when it's plain ASCII, else base64
+fn body_part(content: &str, content_type: &str) -> String {
+    let text = content.replace("\r\n", "\n").replace('\n', "\r\n");
+    if text.is_ascii() {
+        // Dot-stuffing: a line starting with "." gets another, so it can't end DATA
+        let stuffed = text.split("\r\n").map(|l| if l.starts_with('.') { format!(".{}", l) } else { l.to_string() }).collect::<Vec<_>>().join("\r\n");
+        format!("Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: 7bit\r\n\r\n{}\r\n", content_type, stuffed)
+    } else {
+        let encoded = encoding::base64_encode(text.as_bytes(), false, true);
+        let lines: Vec<&str> = encoded.as_bytes().chunks(76).map(|c| std::str::from_utf8(c).unwrap_or_default()).collect();
+        format!("Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n", content_type, lines.join("\r\n"))
+    }
+}
+
+/// The DATA section: headers and body with CRLF line endings, ready to send
+/// (the terminating "." line is added by `send`)
+pub fn format_message(message: &Message, message_id: &str, now: i64) -> Result<String, String> {
+    header_safe("subject", &message.subject)?;
+    for address in std::iter::once(&message.from).chain(&message.to).chain(&message.cc) {
+        mailbox(address)?;
+    }
+    let mut out = String::new();
+    out.push_str(&format!("From: {}\r\n", message.from));
+    if !message.to.is_empty() {
+        out.push_str(&format!("To: {}\r\n", message.to.join(", ")));
+    }
+    if !message.cc.is_empty() {
+        out.push_str(&format!("Cc: {}\r\n", message.cc.join(", ")));
+    }
+    if let Some(reply_to) = &message.reply_to {
+        mailbox(reply_to)?;
+        out.push_str(&format!("Reply-To: {}\r\n", reply_to));
+    }
+    out.push_str(&format!("Subject: {}\r\n", encode_header(&message.subject)));
+    out.push_str(&format!("Date: {}\r\n", rfc2822_date(now)));
+    out.push_str(&format!("Message-ID: {}\r\n", message_id));
+    out.push_str("MIME-Version: 1.0\r\n");
+    match (&message.body, &message.html) {
+        (Some(text), Some(html)) => {
+            let boundary = format!("=_cryo_{}", crypto::to_hex(&crypto::random_bytes(12).map_err(|e| e.to_string())?));
+            out.push_str(&format!("Content-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n", boundary));
+            out.push_str(&format!("--{}\r\n{}", boundary, body_part(text, "text/plain")));
+            out.push_str(&format!("--{}\r\n{}", boundary, body_part(html, "text/html")));
+            out.push_str(&format!("--{}--\r\n", boundary));
+        }
+        (None, Some(html)) => out.push_str(&body_part(html, "text/html")),
+        (text, None) => out.push_str(&body_part(text.as_deref().unwrap_or(""), "text/plain")),
+    }
+    Ok(out)
+}
+
+/// `Thu, 01 Jan 1970 00:00:00 +0000` for a Unix time
+pub fn rfc2822_date(secs: i64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        DAYS[days.rem_euclid(7) as usize], day, MONTHS[(month - 1) as usize], year, rem / 3600, rem % 3600 / 60, rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_format_message() {
+        assert_eq!(rfc2822_date(0), "Thu, 01 Jan 1970 00:00:00 +0000");
+        assert_eq!(rfc2822_date(1_709_210_096), "Thu, 29 Feb 2024 12:34:56 +0000");
+        let message = Message {
+            from: "Ops <ops@example.com>".into(),
+            to: vec!["ada@example.com".into()],
+            bcc: vec!["audit@example.com".into()],
+            subject: "Disk ≥ 90%".into(),
+            body: Some("line one\n.hidden dot\n".into()),
+            ..Default::default()
+        };
+        let data = format_message(&message, "<1@example.com>", 0).unwrap();
+        assert!(data.contains("Subject: =?UTF-8?B?"));
+        assert!(!data.contains("audit@"));
+        assert!(data.ends_with("\r\n\r\nline one\r\n..hidden dot\r\n\r\n"));
+        let injected = Message { subject: "hi\r\nBcc: x@evil.com".into(), ..message };
+        assert!(format_message(&injected, "<1@x>", 0).is_err());
+        assert!(mailbox("no-at-sign").is_err());
+        assert_eq!(mailbox("Ada Lovelace <ada@example.com>").unwrap(), "ada@example.com");
+    }
+
+    /// A scripted server: replies to each command in turn and records what it got
+    #[test]
+    fn test_send_over_plain_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut transcript = Vec::new();
+            writer.write_all(b"220 test ESMTP\r\n").unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 2.0.0 queued as 42\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-test\r\n250 8BITMIME\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    transcript.push(line);
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                transcript.push(line);
+                writer.write_all(reply).unwrap();
+            }
+            transcript
+        });
+        let config = Server { host: "127.0.0.1".into(), port, security: Security::None, login: None, helo: "me".into(), timeout: Duration::from_secs(5) };
+        let message = Message { from: "a@example.com".into(), to: vec!["b@example.com".into()], cc: vec!["C <c@example.com>".into()], subject: "hi".into(), body: Some("hello".into()), ..Default::default() };
+        let sent = send(&config, &message).unwrap();
+        assert_eq!(sent.response, "250 2.0.0 queued as 42");
+        assert_eq!(server.join().unwrap(), ["EHLO me", "MAIL FROM:<a@example.com>", "RCPT TO:<b@example.com>", "RCPT TO:<c@example.com>", "DATA", ".", "QUIT"]);
+
+        // No STARTTLS offered: refuse rather than fall back to plain text
+        let config = Server { security: Security::StartTls, login: Some(("u".into(), "p".into())), ..config };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = Server { port: listener.local_addr().unwrap().port(), ..config };
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"220 test\r\n").unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap()).read_line(&mut line).unwrap();
+            stream.write_all(b"250 test\r\n").unwrap();
+        });
+        assert_eq!(send(&config, &message).unwrap_err(), "127.0.0.1 does not offer STARTTLS");
+    }
+}
@@ -0,0 +1,35 @@
+// Cryo Stack Guard
+// Grows the native stack on demand so deeply nested programs
+// (long concatenation chains, machine-generated code) don't overflow
+// the recursive lexer -> parser -> optimizer -> interpreter passes
+
+/// Remaining stack below which a new segment is allocated
+const RED_ZONE: usize = 256 * 1024;
+
+/// Size of each newly allocated stack segment
+const SEGMENT_SIZE: usize = 8 * 1024 * 1024;
+
+/// Run `f`, switching to a fresh stack segment first if the current one is nearly exhausted.
+/// Wrap the entry point of every recursive AST walk with this.
+#[inline(always)]
+pub fn guard<R, F: FnOnce() -> R>(f: F) -> R {
+    stacker::maybe_grow(RED_ZONE, SEGMENT_SIZE, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(n: usize) -> usize {
+        guard(|| {
+            // Large frame to make overflow likely without the guard
+            let pad = std::hint::black_box([0u8; 1024]);
+            if n == 0 { 0 } else { depth(n - 1) + 1 + pad[n % 1024] as usize }
+        })
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        assert_eq!(depth(100_000), 100_000);
+    }
+}
@@ -0,0 +1,612 @@
+// ============================================
+// Cryo Symbol Index
+// Definitions and references across a project, for go-to-definition and
+// find-references in `cryo lsp` and for `cryo symbols`. Each file's entry is
+// built from its tokens and syntax tree and keyed by a hash of its text. The
+// index is saved to `.cryo/symbols.json` under the project root, so a later
+// run only re-reads the files that changed.
+//
+// Names resolve the way the interpreter loads them: a file shares one
+// namespace with every module it imports, directly or not. Parameters and
+// local variables aren't indexed. Member names (after `.` or `::`, and
+// object keys) are matched by name alone, since nothing records types.
+// ============================================
+
+use crate::crypto;
+use crate::engine::{Document, Position, Range, SymbolKind};
+use crate::glob;
+use crate::json;
+use crate::lexer::Token;
+use crate::parser::{Function, Pattern, Stmt, TopLevel};
+use crate::threading::SendValue;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Where the index is saved, relative to the project root
+pub const CACHE_FILE: &str = ".cryo/symbols.json";
+const CACHE_VERSION: i64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The struct, enum, trait or impl type a member belongs to
+    pub container: Option<String>,
+    /// The whole definition
+    pub range: Range,
+    /// Just the name
+    pub selection: Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: String,
+    /// A field, method or variant name: after `.` or `::`, or an object key
+    pub member: bool,
+    /// `Type` in `Type::name`
+    pub qualifier: Option<String>,
+    pub range: Range,
+}
+
+/// What one file defines, imports and refers to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileSymbols {
+    /// XXH64 of the text the entry was built from
+    pub hash: u64,
+    pub imports: Vec<String>,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+/// A range in an indexed file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Index key: the path relative to the project root, with `/`
+    pub file: String,
+    pub range: Range,
+}
+
+impl FileSymbols {
+    pub fn scan(doc: &Document) -> FileSymbols {
+        let mut definitions = Vec::new();
+        let mut imports = Vec::new();
+        // Item index -> names local to it
+        let mut locals: Vec<(std::ops::Range<usize>, HashSet<String>)> = Vec::new();
+        for ((tokens, item), symbol) in doc.items().zip(doc.symbols()) {
+            let container = match item {
+                TopLevel::Impl(i) => i.type_name.to_string(),
+                _ => symbol.name.clone(),
+            };
+            match item {
+                TopLevel::Import(path, _) => imports.push(path.clone()),
+                TopLevel::Impl(_) | TopLevel::Extern(_) => {}
+                _ => definitions.push(Definition { name: symbol.name.clone(), kind: symbol.kind, container: None, range: symbol.range, selection: symbol.selection }),
+            }
+            for child in symbol.children {
+                definitions.push(Definition { name: child.name, kind: child.kind, container: Some(container.clone()), range: child.range, selection: child.selection });
+            }
+            let mut names = HashSet::new();
+            let functions: Vec<&crate::parser::Function> = match item {
+                TopLevel::Function(f) => vec![f],
+                TopLevel::Impl(i) => i.methods.iter().collect(),
+                TopLevel::Trait(t) => t.methods.iter().collect(),
+                TopLevel::Macro(m) => {
+                    names.extend(m.params.iter().map(|p| p.to_string()));
+                    collect_locals(&m.body, &mut names);
+                    Vec::new()
+                }
+                _ => Vec::new(),
+            };
+            for f in functions {
+                names.extend(f.params.iter().map(|p| p.name.to_string()));
+                collect_locals(f.body.as_deref().unwrap_or_default(), &mut names);
+            }
+            locals.push((tokens, names));
+        }
+
+        let defined: HashSet<usize> = definitions.iter().map(|d| doc.offset(d.selection.start)).collect();
+        let tokens = doc.tokens();
+        let spans = doc.spans();
+        let members = member_tokens(tokens);
+        let mut references = Vec::new();
+        let mut item = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            let Token::Identifier(name) = token else { continue };
+            if defined.contains(&spans[i].start) {
+                continue;
+            }
+            let prev = i.checked_sub(1).map(|p| &tokens[p]);
+            let member = members[i];
+            while locals.get(item).is_some_and(|(range, _)| range.end <= i) {
+                item += 1;
+            }
+            let local = locals.get(item).is_some_and(|(range, names)| range.contains(&i) && names.contains(name.as_str()));
+            if !member && local {
+                continue;
+            }
+            let qualifier = match (prev, i.checked_sub(2).map(|p| &tokens[p])) {
+                (Some(Token::ColonColon), Some(Token::Identifier(qualifier))) => Some(qualifier.to_string()),
+                _ => None,
+            };
+            references.push(Reference { name: name.to_string(), member, qualifier, range: doc.range(spans[i]) });
+        }
+        FileSymbols { hash: crypto::xxh64(doc.text().as_bytes(), 0), imports, definitions, references }
+    }
+
+    /// The name at `pos`, whether it's a member name, and its `Type::`
+    pub fn name_at(&self, pos: Position) -> Option<(&str, bool, Option<&str>)> {
+        let contains = |r: &Range| r.start <= pos && pos <= r.end;
+        if let Some(d) = self.definitions.iter().find(|d| contains(&d.selection)) {
+            return Some((&d.name, is_member(d.kind), d.container.as_deref()));
+        }
+        self.references.iter().find(|r| contains(&r.range)).map(|r| (r.name.as_str(), r.member, r.qualifier.as_deref()))
+    }
+}
+
+pub(crate) fn is_member(kind: SymbolKind) -> bool {
+    matches!(kind, SymbolKind::Method | SymbolKind::Field | SymbolKind::Variant)
+}
+
+/// Which tokens are member names: after `.` or `::`, or an object key
+pub(crate) fn member_tokens(tokens: &[Token]) -> Vec<bool> {
+    // Open brackets, to tell object keys from parameters
+    let mut brackets = Vec::new();
+    let mut members = vec![false; tokens.len()];
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LBrace | Token::LParen | Token::LBracket => brackets.push(token),
+            Token::RBrace | Token::RParen | Token::RBracket => {
+                brackets.pop();
+            }
+            _ => {}
+        }
+        if !matches!(token, Token::Identifier(_)) {
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|p| &tokens[p]);
+        let key = matches!(tokens.get(i + 1), Some(Token::Colon))
+            && matches!(prev, Some(Token::LBrace | Token::Comma))
+            && brackets.last() == Some(&&Token::LBrace);
+        members[i] = key || matches!(prev, Some(Token::Dot | Token::ColonColon));
+    }
+    members
+}
+
+/// Variables a block declares, in any nested block too
+pub(crate) fn collect_locals(stmts: &[Stmt], names: &mut HashSet<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Let(name, _, _) => {
+                names.insert(name.to_string());
+            }
+            Stmt::If(_, then, other) => {
+                collect_locals(then, names);
+                collect_locals(other.as_deref().unwrap_or_default(), names);
+            }
+            Stmt::While(_, body) | Stmt::Block(body) => collect_locals(body, names),
+            Stmt::Defer(inner) | Stmt::At(_, _, inner) => collect_locals(std::slice::from_ref(&**inner), names),
+            Stmt::Match(_, arms) => {
+                for arm in arms {
+                    if let Pattern::Binding(name) = &arm.pattern {
+                        names.insert(name.to_string());
+                    }
+                    collect_locals(&arm.body, names);
+                }
+            }
+            Stmt::Try(body, name, handler) => {
+                collect_locals(body, names);
+                names.insert(name.to_string());
+                collect_locals(handler, names);
+            }
+            Stmt::With(name, _, body) => {
+                names.insert(name.to_string());
+                collect_locals(body, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A function (or macro) body and the names local to it
+pub(crate) struct Scope {
+    pub name: String,
+    /// Token indexes, from the keyword to the closing brace
+    pub tokens: std::ops::Range<usize>,
+    /// Token index of the body's `{`
+    pub body: usize,
+    pub params: Vec<String>,
+    /// Parameters too
+    pub locals: HashSet<String>,
+}
+
+/// Every function, method and macro body in `doc`
+pub(crate) fn scopes(doc: &Document) -> Vec<Scope> {
+    let mut scopes = Vec::new();
+    for (tokens, item) in doc.items() {
+        let functions: Vec<&Function> = match item {
+            TopLevel::Function(f) => vec![f],
+            TopLevel::Impl(i) => i.methods.iter().collect(),
+            TopLevel::Trait(t) => t.methods.iter().collect(),
+            TopLevel::Macro(m) => {
+                let params: Vec<String> = m.params.iter().map(|p| p.to_string()).collect();
+                let mut locals: HashSet<String> = params.iter().cloned().collect();
+                collect_locals(&m.body, &mut locals);
+                if let Some((range, body)) = body_tokens(doc.tokens(), tokens.clone(), &Token::Macro, m.name.as_str()) {
+                    scopes.push(Scope { name: m.name.to_string(), tokens: range, body, params, locals });
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        for f in functions {
+            let Some(stmts) = &f.body else { continue };
+            let params: Vec<String> = f.params.iter().map(|p| p.name.to_string()).collect();
+            let mut locals: HashSet<String> = params.iter().cloned().collect();
+            collect_locals(stmts, &mut locals);
+            if let Some((range, body)) = body_tokens(doc.tokens(), tokens.clone(), &Token::Fn, f.name.as_str()) {
+                scopes.push(Scope { name: f.name.to_string(), tokens: range, body, params, locals });
+            }
+        }
+    }
+    scopes
+}
+
+/// Tokens from `keyword name` to the brace closing its body, and where the
+/// body opens
+fn body_tokens(tokens: &[Token], item: std::ops::Range<usize>, keyword: &Token, name: &str) -> Option<(std::ops::Range<usize>, usize)> {
+    let start = (item.start..item.end.saturating_sub(1))
+        .find(|&i| &tokens[i] == keyword && matches!(&tokens[i + 1], Token::Identifier(id) if id.as_str() == name))?;
+    let body = (start..item.end).find(|&i| tokens[i] == Token::LBrace)?;
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().take(item.end).skip(body) {
+        match token {
+            Token::LBrace => depth += 1,
+            Token::RBrace if depth == 1 => return Some((start..i + 1, body)),
+            Token::RBrace => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Every indexed file of a project
+pub struct SymbolIndex {
+    root: PathBuf,
+    files: BTreeMap<String, FileSymbols>,
+}
+
+impl SymbolIndex {
+    /// An empty index of the `.cryo` files under `root`
+    pub fn new(root: &Path) -> Self {
+        SymbolIndex { root: root.to_path_buf(), files: BTreeMap::new() }
+    }
+
+    /// The saved index brought up to date with the files on disk, and saved
+    /// again if anything changed
+    pub fn open(root: &Path) -> Result<Self, String> {
+        let mut index = SymbolIndex::new(root);
+        index.load();
+        if index.refresh()? > 0 {
+            index.save()?;
+        }
+        Ok(index)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&str, &FileSymbols)> {
+        self.files.iter().map(|(key, file)| (key.as_str(), file))
+    }
+
+    pub fn file(&self, key: &str) -> Option<&FileSymbols> {
+        self.files.get(key)
+    }
+
+    /// Index key of `path`: relative to the root when it's under it
+    pub fn key(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        relative.to_string_lossy().replace('\\', "/")
+    }
+
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    /// Re-index files whose text changed and drop deleted ones; returns how
+    /// many entries changed
+    pub fn refresh(&mut self) -> Result<usize, String> {
+        let root = self.root.to_string_lossy().into_owned();
+        let found: HashSet<String> = glob::glob(&root, "**/*.cryo")?
+            .into_iter()
+            .map(|p| self.key(Path::new(&p)))
+            .filter(|key| !key.split('/').any(|part| part == "target"))
+            .collect();
+        let mut changed = 0;
+        let before = self.files.len();
+        self.files.retain(|key, _| found.contains(key));
+        changed += before - self.files.len();
+        for key in found {
+            let Ok(text) = std::fs::read_to_string(self.path(&key)) else { continue };
+            if self.files.get(&key).is_some_and(|f| f.hash == crypto::xxh64(text.as_bytes(), 0)) {
+                continue;
+            }
+            self.files.insert(key, FileSymbols::scan(&Document::new(&text)));
+            changed += 1;
+        }
+        Ok(changed)
+    }
+
+    /// Forget a file, such as a document closed in an editor with no workspace
+    pub fn remove(&mut self, key: &str) {
+        self.files.remove(key);
+    }
+
+    /// Index an open document in place of what's on disk
+    pub fn update(&mut self, key: &str, doc: &Document) {
+        if self.files.get(key).is_some_and(|f| f.hash == crypto::xxh64(doc.text().as_bytes(), 0)) {
+            return;
+        }
+        self.files.insert(key.to_string(), FileSymbols::scan(doc));
+    }
+
+    /// Read the saved index; a missing or outdated cache leaves it empty
+    pub fn load(&mut self) {
+        let Ok(text) = std::fs::read_to_string(self.root.join(CACHE_FILE)) else { return };
+        let Ok(saved) = json::parse(&text) else { return };
+        if field(&saved, "version").map(int) != Some(CACHE_VERSION) {
+            return;
+        }
+        let Some(SendValue::Struct(_, files)) = field(&saved, "files") else { return };
+        for (key, file) in files {
+            if let Some(file) = file_from_json(file) {
+                self.files.insert(key.clone(), file);
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.root.join(CACHE_FILE);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let files: Vec<String> = self.files.iter().map(|(key, file)| format!("{}:{}", json::quote(key), file_json(file))).collect();
+        let text = format!("{{\"version\":{},\"files\":{{{}}}}}\n", CACHE_VERSION, files.join(",\n"));
+        std::fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The indexed file an `import` in `from` loads, looked up the way the
+    /// interpreter does: next to `from`, then the standard directories
+    pub fn resolve_import(&self, from: &str, import: &str) -> Option<String> {
+        let dir = from.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let mut candidates = Vec::new();
+        if !dir.is_empty() {
+            candidates.push(format!("{}/{}.cryo", dir, import));
+        }
+        for prefix in ["stdlib/", "", "examples/", "libs/"] {
+            candidates.push(format!("{}{}.cryo", prefix, import));
+        }
+        candidates.into_iter().find(|c| self.files.contains_key(c))
+    }
+
+    /// `file` and every file it imports, directly or not
+    pub fn visible(&self, file: &str) -> Vec<String> {
+        let mut seen = vec![file.to_string()];
+        let mut i = 0;
+        while i < seen.len() {
+            if let Some(entry) = self.files.get(&seen[i]) {
+                for import in &entry.imports {
+                    if let Some(found) = self.resolve_import(&seen[i], import) {
+                        if !seen.contains(&found) {
+                            seen.push(found);
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+        seen
+    }
+
+    /// Every definition of `name`, in any file
+    pub fn definitions_named(&self, name: &str) -> Vec<(&str, &Definition)> {
+        self.files().flat_map(|(key, file)| file.definitions.iter().filter(move |d| d.name == name).map(move |d| (key, d))).collect()
+    }
+
+    /// Where the name at `pos` in `file` is defined: among the files it can
+    /// see, or anywhere in the project if none of them defines it. `Type::name`
+    /// narrows to members of `Type` when there are any.
+    pub fn definition(&self, file: &str, pos: Position) -> Vec<Location> {
+        let Some((name, member, qualifier)) = self.files.get(file).and_then(|f| f.name_at(pos)) else {
+            return Vec::new();
+        };
+        let matching = |keys: &[String]| -> Vec<(&Definition, Location)> {
+            keys.iter()
+                .flat_map(|key| self.files.get(key).into_iter().flat_map(|f| &f.definitions).map(move |d| (key, d)))
+                .filter(|(_, d)| d.name == name && is_member(d.kind) == member)
+                .map(|(key, d)| (d, Location { file: key.clone(), range: d.selection }))
+                .collect()
+        };
+        let mut found = matching(&self.visible(file));
+        if found.is_empty() {
+            found = matching(&self.files.keys().cloned().collect::<Vec<_>>());
+        }
+        if found.iter().any(|(d, _)| qualifier.is_some() && d.container.as_deref() == qualifier) {
+            found.retain(|(d, _)| d.container.as_deref() == qualifier);
+        }
+        found.into_iter().map(|(_, location)| location).collect()
+    }
+
+    /// Every use of the name at `pos` in `file`, with its definitions when
+    /// `declarations` is set. Members match by name anywhere; other names in
+    /// the files that see one of the definitions.
+    pub fn references(&self, file: &str, pos: Position, declarations: bool) -> Vec<Location> {
+        let Some((name, member, _)) = self.files.get(file).and_then(|f| f.name_at(pos)) else {
+            return Vec::new();
+        };
+        let definitions = self.definition(file, pos);
+        let mut found = Vec::new();
+        for (key, entry) in &self.files {
+            let sees = member || definitions.is_empty() || {
+                let visible = self.visible(key);
+                definitions.iter().any(|d| visible.contains(&d.file))
+            };
+            if !sees {
+                continue;
+            }
+            if declarations {
+                let defs = entry.definitions.iter().filter(|d| d.name == name && is_member(d.kind) == member);
+                found.extend(defs.filter(|d| definitions.iter().any(|l| l.file == *key && l.range == d.selection)).map(|d| Location { file: key.clone(), range: d.selection }));
+            }
+            let refs = entry.references.iter().filter(|r| r.name == name && r.member == member);
+            found.extend(refs.map(|r| Location { file: key.clone(), range: r.range }));
+        }
+        found.sort_by(|a, b| (&a.file, a.range.start).cmp(&(&b.file, b.range.start)));
+        found
+    }
+}
+
+fn field<'a>(value: &'a SendValue, name: &str) -> Option<&'a SendValue> {
+    match value {
+        SendValue::Struct(_, fields) => fields.get(name),
+        _ => None,
+    }
+}
+
+fn int(value: &SendValue) -> i64 {
+    match value {
+        SendValue::Int(n) => *n,
+        _ => -1,
+    }
+}
+
+fn optional(value: &SendValue) -> Option<String> {
+    match value {
+        SendValue::Null => None,
+        other => Some(other.to_string_val()),
+    }
+}
+
+fn range_json(range: Range) -> String {
+    format!("{},{},{},{}", range.start.line, range.start.character, range.end.line, range.end.character)
+}
+
+/// Definitions as `[name, kind, container, range..., selection...]` and
+/// references as `[name, member, qualifier, range...]`, to keep the file small
+fn file_json(file: &FileSymbols) -> String {
+    let imports: Vec<String> = file.imports.iter().map(|i| json::quote(i)).collect();
+    let definitions: Vec<String> = file
+        .definitions
+        .iter()
+        .map(|d| {
+            let container = d.container.as_deref().map_or("null".to_string(), json::quote);
+            format!("[{},\"{}\",{},{},{}]", json::quote(&d.name), d.kind.name(), container, range_json(d.range), range_json(d.selection))
+        })
+        .collect();
+    let references: Vec<String> = file.references.iter().map(|r| {
+            let qualifier = r.qualifier.as_deref().map_or("null".to_string(), json::quote);
+            format!("[{},{},{},{}]", json::quote(&r.name), r.member as u8, qualifier, range_json(r.range))
+        })
+        .collect();
+    format!(
+        "{{\"hash\":\"{:016x}\",\"imports\":[{}],\"definitions\":[{}],\"references\":[{}]}}",
+        file.hash,
+        imports.join(","),
+        definitions.join(","),
+        references.join(",")
+    )
+}
+
+fn file_from_json(value: &SendValue) -> Option<FileSymbols> {
+    let list = |name| match field(value, name) {
+        Some(SendValue::Array(items)) => Some(items),
+        _ => None,
+    };
+    let range = |items: &[SendValue]| -> Option<Range> {
+        let n: Vec<usize> = items.iter().map(|v| usize::try_from(int(v)).ok()).collect::<Option<_>>()?;
+        Some(Range { start: Position { line: n[0], character: n[1] }, end: Position { line: n[2], character: n[3] } })
+    };
+    let hash = u64::from_str_radix(&field(value, "hash")?.to_string_val(), 16).ok()?;
+    let imports = list("imports")?.iter().map(|i| i.to_string_val()).collect();
+    let mut definitions = Vec::new();
+    for d in list("definitions")? {
+        let SendValue::Array(d) = d else { return None };
+        if d.len() != 11 {
+            return None;
+        }
+        definitions.push(Definition {
+            name: d[0].to_string_val(),
+            kind: SymbolKind::parse(&d[1].to_string_val())?,
+            container: optional(&d[2]),
+            range: range(&d[3..7])?,
+            selection: range(&d[7..11])?,
+        });
+    }
+    let mut references = Vec::new();
+    for r in list("references")? {
+        let SendValue::Array(r) = r else { return None };
+        if r.len() != 7 {
+            return None;
+        }
+        references.push(Reference { name: r[0].to_string_val(), member: int(&r[1]) == 1, qualifier: optional(&r[2]), range: range(&r[3..7])? });
+    }
+    Some(FileSymbols { hash, imports, definitions, references })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, character: usize) -> Position {
+        Position { line, character }
+    }
+
+    fn project(files: &[(&str, &str)]) -> (PathBuf, SymbolIndex) {
+        let root = std::env::temp_dir().join(format!("cryo_symbols_{}_{}", std::process::id(), files.len()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (name, text) in files {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        let index = SymbolIndex::open(&root).unwrap();
+        (root, index)
+    }
+
+    #[test]
+    fn test_definitions_and_references_across_modules() {
+        let util = "struct Point { x: int, y: int }\nfn norm(p) {\n    let x = p.x;\n    return x * x + p.y * p.y;\n}\n\
+                    impl Point { fn new() { return 0; } }\nstruct Line {}\nimpl Line { fn new() { return Point::new(); } }\n";
+        let main = "import \"util\"\n\nfn main() {\n    let p = Point { x: 3, y: 4 };\n    print(norm(p));\n    print(p.x);\n}\n";
+        let other = "fn norm(v) { return v; }\n";
+        let (root, index) = project(&[("lib/util.cryo", util), ("lib/main.cryo", main), ("other.cryo", other)]);
+
+        // `norm` in main.cryo is util's, not other.cryo's
+        let def = index.definition("lib/main.cryo", at(4, 11));
+        assert_eq!(def, [Location { file: "lib/util.cryo".to_string(), range: Range { start: at(1, 3), end: at(1, 7) } }]);
+        let refs = index.references("lib/util.cryo", at(1, 4), true);
+        let places: Vec<(&str, usize)> = refs.iter().map(|l| (l.file.as_str(), l.range.start.line)).collect();
+        assert_eq!(places, [("lib/main.cryo", 4), ("lib/util.cryo", 1)]);
+
+        // The local `x` in norm isn't the field; `p.x`, `x:` and the field definition are
+        let refs = index.references("lib/util.cryo", at(0, 15), true);
+        let places: Vec<(&str, Position)> = refs.iter().map(|l| (l.file.as_str(), l.range.start)).collect();
+        assert_eq!(places, [("lib/main.cryo", at(3, 20)), ("lib/main.cryo", at(5, 12)), ("lib/util.cryo", at(0, 15)), ("lib/util.cryo", at(2, 14))]);
+        assert!(index.definition("lib/util.cryo", at(3, 11)).is_empty());
+        // `Point::new` is Point's, not Line's
+        let def = index.definition("lib/util.cryo", at(7, 37));
+        assert_eq!(def.iter().map(|l| l.range.start).collect::<Vec<_>>(), [at(5, 16)]);
+
+        // Saved, then loaded without re-reading anything
+        let mut reloaded = SymbolIndex::new(&root);
+        reloaded.load();
+        assert_eq!(reloaded.files().collect::<Vec<_>>(), index.files().collect::<Vec<_>>());
+        assert_eq!(reloaded.refresh().unwrap(), 0);
+        std::fs::write(root.join("other.cryo"), "fn other() {}\n").unwrap();
+        std::fs::remove_file(root.join("lib/main.cryo")).unwrap();
+        assert_eq!(reloaded.refresh().unwrap(), 2);
+        assert_eq!(reloaded.definitions_named("other").len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+// ============================================
+// Cryo Sync Module
+// Atomics and concurrent maps shared between isolates
+// ============================================
+
+use crate::threading::SendValue;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+const SHARD_COUNT: usize = 16;
+
+/// Concurrent string-keyed map split into independently locked shards,
+/// so threads touching different keys rarely contend
+pub struct SharedMap {
+    shards: Vec<RwLock<HashMap<String, SendValue>>>,
+}
+
+impl SharedMap {
+    pub fn new() -> Self {
+        SharedMap {
+            shards: (0..SHARD_COUNT).map(|_| RwLock::new(HashMap::new())).collect(),
+        }
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<HashMap<String, SendValue>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARD_COUNT]
+    }
+
+    pub fn get(&self, key: &str) -> Option<SendValue> {
+        self.shard(key).read().unwrap().get(key).cloned()
+    }
+
+    pub fn set(&self, key: &str, value: SendValue) {
+        self.shard(key).write().unwrap().insert(key.to_string(), value);
+    }
+
+    pub fn remove(&self, key: &str) -> Option<SendValue> {
+        self.shard(key).write().unwrap().remove(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.shard(key).read().unwrap().contains_key(key)
+    }
+
+    /// Add `delta` to an integer entry (missing or non-integer counts as 0), returning the new value
+    pub fn increment(&self, key: &str, delta: i64) -> i64 {
+        let mut shard = self.shard(key).write().unwrap();
+        let entry = shard.entry(key.to_string()).or_insert(SendValue::Int(0));
+        let next = match entry {
+            SendValue::Int(n) => *n + delta,
+            _ => delta,
+        };
+        *entry = SendValue::Int(next);
+        next
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.read().unwrap().is_empty())
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.shards.iter()
+            .flat_map(|s| s.read().unwrap().keys().cloned().collect::<Vec<_>>())
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+/// Process-wide registry so handles created in one isolate resolve in every other
+#[derive(Default)]
+struct Registry {
+    next_id: i64,
+    atomics: HashMap<i64, Arc<AtomicI64>>,
+    maps: HashMap<i64, Arc<SharedMap>>,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry { next_id: 1, ..Default::default() }))
+}
+
+/// Per-interpreter view of the registry. Resolved handles are cached locally so
+/// hot counters only pay for the atomic operation, not the registry lock.
+pub struct SyncManager {
+    atomics: HashMap<i64, Arc<AtomicI64>>,
+    maps: HashMap<i64, Arc<SharedMap>>,
+}
+
+impl SyncManager {
+    pub fn new() -> Self {
+        SyncManager {
+            atomics: HashMap::new(),
+            maps: HashMap::new(),
+        }
+    }
+
+    pub fn atomic_new(&mut self, initial: i64) -> i64 {
+        let atomic = Arc::new(AtomicI64::new(initial));
+        let mut reg = registry().lock().unwrap();
+        let id = reg.next_id;
+        reg.next_id += 1;
+        reg.atomics.insert(id, atomic.clone());
+        self.atomics.insert(id, atomic);
+        id
+    }
+
+    pub fn atomic(&mut self, id: i64) -> Option<Arc<AtomicI64>> {
+        if let Some(a) = self.atomics.get(&id) {
+            return Some(a.clone());
+        }
+        let a = registry().lock().unwrap().atomics.get(&id).cloned()?;
+        self.atomics.insert(id, a.clone());
+        Some(a)
+    }
+
+    pub fn atomic_load(&mut self, id: i64) -> Option<i64> {
+        Some(self.atomic(id)?.load(Ordering::SeqCst))
+    }
+
+    pub fn atomic_store(&mut self, id: i64, value: i64) -> bool {
+        match self.atomic(id) {
+            Some(a) => {
+                a.store(value, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the new value
+    pub fn atomic_add(&mut self, id: i64, delta: i64) -> Option<i64> {
+        Some(self.atomic(id)?.fetch_add(delta, Ordering::SeqCst) + delta)
+    }
+
+    /// Compare-and-swap; true if the value was `expected` and is now `new`
+    pub fn atomic_cas(&mut self, id: i64, expected: i64, new: i64) -> bool {
+        match self.atomic(id) {
+            Some(a) => a.compare_exchange(expected, new, Ordering::SeqCst, Ordering::SeqCst).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn map_new(&mut self) -> i64 {
+        let map = Arc::new(SharedMap::new());
+        let mut reg = registry().lock().unwrap();
+        let id = reg.next_id;
+        reg.next_id += 1;
+        reg.maps.insert(id, map.clone());
+        self.maps.insert(id, map);
+        id
+    }
+
+    pub fn map(&mut self, id: i64) -> Option<Arc<SharedMap>> {
+        if let Some(m) = self.maps.get(&id) {
+            return Some(m.clone());
+        }
+        let m = registry().lock().unwrap().maps.get(&id).cloned()?;
+        self.maps.insert(id, m.clone());
+        Some(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_atomic_across_threads() {
+        let mut sync = SyncManager::new();
+        let id = sync.atomic_new(0);
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            thread::spawn(move || {
+                // Fresh manager per thread, like an isolate
+                let mut local = SyncManager::new();
+                for _ in 0..1000 {
+                    local.atomic_add(id, 1);
+                }
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(sync.atomic_load(id), Some(4000));
+        assert!(sync.atomic_cas(id, 4000, 1));
+        assert!(!sync.atomic_cas(id, 4000, 2));
+        assert_eq!(sync.atomic_load(id), Some(1));
+    }
+
+    #[test]
+    fn test_shared_map() {
+        let map = SharedMap::new();
+        map.set("a", SendValue::Int(1));
+        assert_eq!(map.increment("a", 2), 3);
+        assert_eq!(map.increment("hits", 1), 1);
+        assert_eq!(map.keys(), vec!["a".to_string(), "hits".to_string()]);
+        assert!(map.remove("a").is_some());
+        assert_eq!(map.len(), 1);
+    }
+}
@@ -0,0 +1,596 @@
+// ============================================
+// Cryo Templates
+// render(template, data): text and HTML templates with the same tag syntax
+// as stdlib/template.cryo, compiled and run natively.
+//
+//   {{ user.name }}            value, HTML-escaped
+//   {{ body | raw }}           value as is; other filters: upper, lower,
+//                              trim, length, json
+//   {% if a and not b %} ... {% elif x == "y" %} ... {% else %} ... {% endif %}
+//   {% for item in items %} ... {% else %} (when empty) ... {% endfor %}
+//   {% for key, value in object %} ... {% endfor %}
+//   {# comment #}
+//   {% raw %} {{ kept literally }} {% endraw %}
+//
+// Inside a loop, `loop.index` (from 1), `loop.index0`, `loop.first`,
+// `loop.last` and `loop.length` describe the iteration. Missing variables
+// render as nothing and are false in conditions.
+// ============================================
+
+use crate::collections::Collection;
+use crate::interpreter::Value;
+
+const FILTERS: &[&str] = &["raw", "upper", "lower", "trim", "length", "json"];
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output { value: Operand, filters: Vec<String> },
+    If { branches: Vec<(Cond, Vec<Node>)>, otherwise: Vec<Node> },
+    For { key: Option<String>, name: String, iterable: Operand, body: Vec<Node>, empty: Vec<Node> },
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Path(Vec<String>),
+    Literal(Value),
+}
+
+#[derive(Debug)]
+enum Cond {
+    Value(Operand),
+    Not(Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+    Compare(Operand, String, Operand),
+}
+
+/// Render `template` with the fields of `data` as its variables
+pub fn render(template: &str, data: &Value) -> Result<String, String> {
+    let tokens = tokenize(template)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let (nodes, _) = parser.nodes(&[])?;
+    let mut out = String::with_capacity(template.len());
+    let mut scope = Scope { data, locals: Vec::new() };
+    write_nodes(&nodes, &mut scope, &mut out);
+    Ok(out)
+}
+
+// -------------------- Tokens --------------------
+
+enum Token {
+    Text(String),
+    /// `{{ ... }}` body and its line
+    Output(String, usize),
+    /// `{% ... %}` body and its line
+    Tag(String, usize),
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    let mut line = 1;
+    while let Some(start) = rest.find('{') {
+        let (open, close) = match rest[start..].get(..2) {
+            Some("{{") => ("{{", "}}"),
+            Some("{%") => ("{%", "%}"),
+            Some("{#") => ("{#", "#}"),
+            _ => {
+                // A lone brace is text
+                let (text, tail) = rest.split_at(start + 1);
+                push_text(&mut tokens, text);
+                line += text.matches('\n').count();
+                rest = tail;
+                continue;
+            }
+        };
+        let text = &rest[..start];
+        push_text(&mut tokens, text);
+        line += text.matches('\n').count();
+        let body_start = start + open.len();
+        let Some(len) = rest[body_start..].find(close) else {
+            return Err(format!("render: line {}: {} is never closed with {}", line, open, close));
+        };
+        let body = &rest[body_start..body_start + len];
+        rest = &rest[body_start + len + close.len()..];
+        match open {
+            "{{" => tokens.push(Token::Output(body.trim().to_string(), line)),
+            "{%" if body.trim() == "raw" => {
+                // Everything up to {% endraw %} is text
+                let Some(end) = find_endraw(rest) else {
+                    return Err(format!("render: line {}: {{% raw %}} is never closed with {{% endraw %}}", line));
+                };
+                push_text(&mut tokens, &rest[..end.0]);
+                line += rest[..end.1].matches('\n').count();
+                rest = &rest[end.1..];
+                continue;
+            }
+            "{%" => tokens.push(Token::Tag(body.trim().to_string(), line)),
+            _ => {}
+        }
+        line += body.matches('\n').count();
+    }
+    push_text(&mut tokens, rest);
+    Ok(tokens)
+}
+
+/// Start and end of the `{% endraw %}` tag in `text`
+fn find_endraw(text: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(i) = text[from..].find("{%") {
+        let start = from + i;
+        let close = start + text[start..].find("%}")?;
+        if text[start + 2..close].trim() == "endraw" {
+            return Some((start, close + 2));
+        }
+        from = start + 2;
+    }
+    None
+}
+
+fn push_text(tokens: &mut Vec<Token>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match tokens.last_mut() {
+        Some(Token::Text(prev)) => prev.push_str(text),
+        _ => tokens.push(Token::Text(text.to_string())),
+    }
+}
+
+// -------------------- Parsing --------------------
+
+/// A block's nodes and the tag that ended it, with its line
+type Block = (Vec<Node>, Option<(String, usize)>);
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    /// Nodes up to one of the `ends` tags (returned with its line), or the end
+    fn nodes(&mut self, ends: &[&str]) -> Result<Block, String> {
+        let mut nodes = Vec::new();
+        while self.pos < self.tokens.len() {
+            let token = std::mem::replace(&mut self.tokens[self.pos], Token::Text(String::new()));
+            self.pos += 1;
+            match token {
+                Token::Text(text) => nodes.push(Node::Text(text)),
+                Token::Output(body, line) => nodes.push(output(&body, line)?),
+                Token::Tag(body, line) => {
+                    let keyword = body.split_whitespace().next().unwrap_or("");
+                    if ends.contains(&keyword) {
+                        return Ok((nodes, Some((body, line))));
+                    }
+                    match keyword {
+                        "if" => nodes.push(self.if_block(&body, line)?),
+                        "for" => nodes.push(self.for_block(&body, line)?),
+                        _ => return Err(format!("render: line {}: unexpected {{% {} %}}", line, body)),
+                    }
+                }
+            }
+        }
+        Ok((nodes, None))
+    }
+
+    fn if_block(&mut self, first: &str, line: usize) -> Result<Node, String> {
+        let mut branches = Vec::new();
+        let mut cond = condition(&first[2..], line)?;
+        loop {
+            let (body, end) = self.nodes(&["elif", "else", "endif"])?;
+            branches.push((cond, body));
+            match end {
+                Some((tag, line)) if tag.starts_with("elif") => cond = condition(&tag[4..], line)?,
+                Some((tag, _)) if tag == "else" => {
+                    let (otherwise, end) = self.nodes(&["endif"])?;
+                    if end.is_none() {
+                        break;
+                    }
+                    return Ok(Node::If { branches, otherwise });
+                }
+                Some((tag, _)) if tag == "endif" => return Ok(Node::If { branches, otherwise: Vec::new() }),
+                _ => break,
+            }
+        }
+        Err(format!("render: line {}: {{% if %}} is never closed with {{% endif %}}", line))
+    }
+
+    fn for_block(&mut self, tag: &str, line: usize) -> Result<Node, String> {
+        // for NAME in EXPR, or for KEY, NAME in EXPR
+        let spec = tag[3..].trim();
+        let Some((names, iterable)) = spec.split_once(" in ") else {
+            return Err(format!("render: line {}: expected {{% for NAME in VALUE %}}, got {{% {} %}}", line, tag));
+        };
+        let names: Vec<String> = names.split(',').map(|n| n.trim().to_string()).collect();
+        if names.len() > 2 || names.iter().any(|n| !is_identifier(n)) {
+            return Err(format!("render: line {}: bad loop variables '{}'", line, spec));
+        }
+        let iterable = operand(iterable.trim(), line)?;
+        let (body, end) = self.nodes(&["else", "endfor"])?;
+        let empty = match end {
+            Some((tag, _)) if tag == "else" => match self.nodes(&["endfor"])? {
+                (empty, Some(_)) => empty,
+                (_, None) => return Err(format!("render: line {}: {{% for %}} is never closed with {{% endfor %}}", line)),
+            },
+            Some(_) => Vec::new(),
+            None => return Err(format!("render: line {}: {{% for %}} is never closed with {{% endfor %}}", line)),
+        };
+        let mut names = names.into_iter();
+        let (key, name) = match (names.next(), names.next()) {
+            (Some(key), Some(name)) => (Some(key), name),
+            (Some(name), None) => (None, name),
+            _ => unreachable!(),
+        };
+        Ok(Node::For { key, name, iterable, body, empty })
+    }
+}
+
+fn output(body: &str, line: usize) -> Result<Node, String> {
+    let mut parts = body.split('|').map(str::trim);
+    let value = operand(parts.next().unwrap_or(""), line)?;
+    let filters: Vec<String> = parts.map(str::to_string).collect();
+    if let Some(unknown) = filters.iter().find(|f| !FILTERS.contains(&f.as_str())) {
+        return Err(format!("render: line {}: unknown filter '{}'", line, unknown));
+    }
+    Ok(Node::Output { value, filters })
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_') && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// A variable path (`user.name`, `items.0`) or a string, number, bool or null literal
+fn operand(text: &str, line: usize) -> Result<Operand, String> {
+    let literal = match text {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        "null" => Some(Value::Null),
+        _ if text.len() >= 2 && (text.starts_with('"') && text.ends_with('"') || text.starts_with('\'') && text.ends_with('\'')) => {
+            Some(Value::String(text[1..text.len() - 1].into()))
+        }
+        _ => text.parse::<i64>().ok().map(Value::Int),
+    };
+    if let Some(value) = literal {
+        return Ok(Operand::Literal(value));
+    }
+    let path: Vec<String> = text.split('.').map(str::to_string).collect();
+    let valid = is_identifier(&path[0]) && path[1..].iter().all(|p| is_identifier(p) || p.parse::<usize>().is_ok());
+    if !valid {
+        return Err(format!("render: line {}: can't read '{}'", line, text));
+    }
+    Ok(Operand::Path(path))
+}
+
+/// Words of a condition: quoted strings stay whole, operators split off
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let end = chars[i + 1..].iter().position(|&d| d == c).map_or(chars.len(), |p| i + 2 + p);
+            words.push(chars[i..end.min(chars.len())].iter().collect());
+            i = end;
+        } else if "()".contains(c) {
+            words.push(c.to_string());
+            i += 1;
+        } else if "=!<>".contains(c) {
+            let len = if chars.get(i + 1) == Some(&'=') { 2 } else { 1 };
+            words.push(chars[i..i + len].iter().collect());
+            i += len;
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() && !"()=!<>\"'".contains(chars[i]) {
+                i += 1;
+            }
+            words.push(chars[start..i].iter().collect());
+        }
+    }
+    words
+}
+
+fn condition(text: &str, line: usize) -> Result<Cond, String> {
+    let words = words(text);
+    let mut pos = 0;
+    let cond = or_cond(&words, &mut pos, line)?;
+    match words.get(pos) {
+        None if !words.is_empty() => Ok(cond),
+        None => Err(format!("render: line {}: missing condition", line)),
+        Some(extra) => Err(format!("render: line {}: unexpected '{}' in condition", line, extra)),
+    }
+}
+
+fn or_cond(words: &[String], pos: &mut usize, line: usize) -> Result<Cond, String> {
+    let mut left = and_cond(words, pos, line)?;
+    while words.get(*pos).is_some_and(|w| w == "or") {
+        *pos += 1;
+        left = Cond::Or(Box::new(left), Box::new(and_cond(words, pos, line)?));
+    }
+    Ok(left)
+}
+
+fn and_cond(words: &[String], pos: &mut usize, line: usize) -> Result<Cond, String> {
+    let mut left = not_cond(words, pos, line)?;
+    while words.get(*pos).is_some_and(|w| w == "and") {
+        *pos += 1;
+        left = Cond::And(Box::new(left), Box::new(not_cond(words, pos, line)?));
+    }
+    Ok(left)
+}
+
+fn not_cond(words: &[String], pos: &mut usize, line: usize) -> Result<Cond, String> {
+    match words.get(*pos).map(String::as_str) {
+        Some("not") | Some("!") => {
+            *pos += 1;
+            Ok(Cond::Not(Box::new(not_cond(words, pos, line)?)))
+        }
+        Some("(") => {
+            *pos += 1;
+            let inner = or_cond(words, pos, line)?;
+            if words.get(*pos).map(String::as_str) != Some(")") {
+                return Err(format!("render: line {}: missing ')' in condition", line));
+            }
+            *pos += 1;
+            Ok(inner)
+        }
+        Some(word) => {
+            let left = operand(word, line)?;
+            *pos += 1;
+            match words.get(*pos).map(String::as_str) {
+                Some(op @ ("==" | "!=" | "<" | ">" | "<=" | ">=")) => {
+                    let op = op.to_string();
+                    let Some(right) = words.get(*pos + 1) else {
+                        return Err(format!("render: line {}: nothing after '{}'", line, op));
+                    };
+                    let right = operand(right, line)?;
+                    *pos += 2;
+                    Ok(Cond::Compare(left, op, right))
+                }
+                _ => Ok(Cond::Value(left)),
+            }
+        }
+        None => Err(format!("render: line {}: missing condition", line)),
+    }
+}
+
+// -------------------- Rendering --------------------
+
+struct Scope<'a> {
+    data: &'a Value,
+    /// Loop variables, innermost last
+    locals: Vec<(String, Value)>,
+}
+
+impl Scope<'_> {
+    fn get(&self, operand: &Operand) -> Value {
+        let path = match operand {
+            Operand::Literal(value) => return value.clone(),
+            Operand::Path(path) => path,
+        };
+        let mut value = match self.locals.iter().rev().find(|(name, _)| *name == path[0]) {
+            Some((_, value)) => value.clone(),
+            None => self.data.field(&path[0]).unwrap_or(Value::Null),
+        };
+        for step in &path[1..] {
+            value = match &value {
+                Value::Struct(_, fields) | Value::Map(fields) => fields.borrow().get(step).cloned().unwrap_or(Value::Null),
+                Value::Array(items) => step.parse::<usize>().ok().and_then(|i| items.borrow().get(i).cloned()).unwrap_or(Value::Null),
+                _ => Value::Null,
+            };
+        }
+        value
+    }
+
+    fn test(&self, cond: &Cond) -> bool {
+        match cond {
+            Cond::Value(operand) => truthy(&self.get(operand)),
+            Cond::Not(inner) => !self.test(inner),
+            Cond::And(a, b) => self.test(a) && self.test(b),
+            Cond::Or(a, b) => self.test(a) || self.test(b),
+            Cond::Compare(a, op, b) => compare(&self.get(a), op, &self.get(b)),
+        }
+    }
+}
+
+/// Empty arrays, objects and maps are false too, so `{% if items %}` reads naturally
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Struct(_, fields) | Value::Map(fields) => !fields.borrow().is_empty(),
+        Value::Collection(c) => !c.borrow().is_empty(),
+        other => other.is_truthy(),
+    }
+}
+
+fn compare(a: &Value, op: &str, b: &Value) -> bool {
+    let numeric = |v: &Value| matches!(v, Value::Int(_) | Value::BigInt(_) | Value::Decimal(_));
+    let ordering = if numeric(a) && numeric(b) {
+        Some(a.to_decimal().cmp(&b.to_decimal()))
+    } else if let (Value::String(x), Value::String(y)) = (a, b) {
+        Some(x.cmp(y))
+    } else {
+        None
+    };
+    match (op, ordering) {
+        ("==", Some(o)) => o.is_eq(),
+        ("!=", Some(o)) => o.is_ne(),
+        ("==", None) => std::mem::discriminant(a) == std::mem::discriminant(b) && a.to_string_val() == b.to_string_val(),
+        ("!=", None) => std::mem::discriminant(a) != std::mem::discriminant(b) || a.to_string_val() != b.to_string_val(),
+        ("<", Some(o)) => o.is_lt(),
+        (">", Some(o)) => o.is_gt(),
+        ("<=", Some(o)) => o.is_le(),
+        (">=", Some(o)) => o.is_ge(),
+        _ => false,
+    }
+}
+
+fn write_nodes(nodes: &[Node], scope: &mut Scope, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output { value, filters } => write_value(&scope.get(value), filters, out),
+            Node::If { branches, otherwise } => {
+                let body = branches.iter().find(|(cond, _)| scope.test(cond)).map_or(otherwise, |(_, body)| body);
+                write_nodes(body, scope, out);
+            }
+            Node::For { key, name, iterable, body, empty } => {
+                let entries = entries(&scope.get(iterable), key.is_some());
+                if entries.is_empty() {
+                    write_nodes(empty, scope, out);
+                    continue;
+                }
+                let length = entries.len();
+                for (i, (k, v)) in entries.into_iter().enumerate() {
+                    let depth = scope.locals.len();
+                    let info = Value::object([
+                        ("index".to_string(), Value::Int(i as i64 + 1)),
+                        ("index0".to_string(), Value::Int(i as i64)),
+                        ("first".to_string(), Value::Bool(i == 0)),
+                        ("last".to_string(), Value::Bool(i + 1 == length)),
+                        ("length".to_string(), Value::Int(length as i64)),
+                    ]);
+                    scope.locals.push(("loop".to_string(), info));
+                    if let Some(key) = key {
+                        scope.locals.push((key.clone(), k));
+                    }
+                    scope.locals.push((name.clone(), v));
+                    write_nodes(body, scope, out);
+                    scope.locals.truncate(depth);
+                }
+            }
+        }
+    }
+}
+
+/// (key, value) pairs to loop over: arrays give their indexes, objects their
+/// fields and maps their entries in key order. With one loop variable an object gives its keys.
+fn entries(value: &Value, keyed: bool) -> Vec<(Value, Value)> {
+    match value {
+        Value::Array(items) => items.borrow().iter().enumerate().map(|(i, v)| (Value::Int(i as i64), v.clone())).collect(),
+        Value::Struct(_, fields) | Value::Map(fields) => {
+            let fields = fields.borrow();
+            let mut names: Vec<&String> = fields.keys().collect();
+            names.sort();
+            names
+                .into_iter()
+                .map(|k| {
+                    let key = Value::String(k.as_str().into());
+                    if keyed { (key, fields[k].clone()) } else { (Value::Null, key) }
+                })
+                .collect()
+        }
+        Value::Collection(coll) => match &*coll.borrow() {
+            Collection::Map(m) => m
+                .iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        crate::collections::MapKey::Int(n) => Value::Int(*n),
+                        crate::collections::MapKey::Str(s) => Value::String(s.as_str().into()),
+                    };
+                    if keyed { (key, v.clone()) } else { (Value::Null, key) }
+                })
+                .collect(),
+            other => {
+                let mut items = Vec::new();
+                other.for_each_value(|v| items.push(v.clone()));
+                items.into_iter().enumerate().map(|(i, v)| (Value::Int(i as i64), v)).collect()
+            }
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn write_value(value: &Value, filters: &[String], out: &mut String) {
+    let mut text = match value {
+        Value::Null => String::new(),
+        other => other.to_string_val(),
+    };
+    let mut escape = true;
+    for filter in filters {
+        match filter.as_str() {
+            "raw" => escape = false,
+            "upper" => text = text.to_uppercase(),
+            "lower" => text = text.to_lowercase(),
+            "trim" => text = text.trim().to_string(),
+            "length" => {
+                text = match value {
+                    Value::Array(items) => items.borrow().len(),
+                    Value::Struct(_, fields) | Value::Map(fields) => fields.borrow().len(),
+                    Value::Collection(c) => c.borrow().len(),
+                    _ => text.chars().count(),
+                }
+                .to_string()
+            }
+            "json" => text = value.to_json(),
+            _ => {}
+        }
+    }
+    if escape {
+        escape_html(&text, out);
+    } else {
+        out.push_str(&text);
+    }
+}
+
+pub fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn s(text: &str) -> Value {
+        Value::String(text.into())
+    }
+
+    fn data() -> Value {
+        let items = Value::Array(Rc::new(RefCell::new(vec![s("a<b"), s("c")])));
+        Value::object([
+            ("title".to_string(), s("<Hi & \"bye\">")),
+            ("items".to_string(), items),
+            ("user".to_string(), Value::object([("name".to_string(), s("Ada")), ("age".to_string(), Value::Int(36))])),
+            ("none".to_string(), Value::Array(Rc::new(RefCell::new(Vec::new())))),
+        ])
+    }
+
+    #[test]
+    fn test_output_and_escaping() {
+        let out = render("<h1>{{ title }}</h1>{{title|raw}} {{ user.name | upper }} {{ items.1 }} {{ missing }}{# note #}", &data()).unwrap();
+        assert_eq!(out, "<h1>&lt;Hi &amp; &quot;bye&quot;&gt;</h1><Hi & \"bye\"> ADA c ");
+        assert_eq!(render("{% raw %}{{ title }}{% endraw %} {x}", &data()).unwrap(), "{{ title }} {x}");
+        assert_eq!(render("{{ items | length }} {{ user.age | json }}", &data()).unwrap(), "2 36");
+    }
+
+    #[test]
+    fn test_blocks() {
+        let template = "{% for item in items %}{{ loop.index }}:{{ item }}{% if not loop.last %},{% endif %}{% endfor %}";
+        assert_eq!(render(template, &data()).unwrap(), "1:a&lt;b,2:c");
+        let template = "{% for k, v in user %}{{ k }}={{ v }};{% endfor %}{% for x in none %}x{% else %}empty{% endfor %}";
+        assert_eq!(render(template, &data()).unwrap(), "age=36;name=Ada;empty");
+        let template = "{% if user.age >= 40 %}old{% elif user.name == \"Ada\" and (items or none) %}ada{% else %}?{% endif %}";
+        assert_eq!(render(template, &data()).unwrap(), "ada");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(render("a\n{% if x %}", &data()).unwrap_err(), "render: line 2: {% if %} is never closed with {% endif %}");
+        assert_eq!(render("{{ x | shout }}", &data()).unwrap_err(), "render: line 1: unknown filter 'shout'");
+        assert_eq!(render("\n\n{% endfor %}", &data()).unwrap_err(), "render: line 3: unexpected {% endfor %}");
+        assert!(render("{{ x", &data()).is_err());
+    }
+}
@@ -0,0 +1,425 @@
+// ============================================
+// Cryo Terminal Module
+// TTY detection, terminal size, ANSI styling and raw-mode key input
+// ============================================
+
+use std::io::{self, IsTerminal, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn from_name(name: &str) -> Option<Stream> {
+        match name {
+            "stdin" => Some(Stream::Stdin),
+            "stdout" => Some(Stream::Stdout),
+            "stderr" => Some(Stream::Stderr),
+            _ => None,
+        }
+    }
+}
+
+pub fn is_tty(stream: Stream) -> bool {
+    match stream {
+        Stream::Stdin => io::stdin().is_terminal(),
+        Stream::Stdout => io::stdout().is_terminal(),
+        Stream::Stderr => io::stderr().is_terminal(),
+    }
+}
+
+/// (columns, rows) of the terminal, falling back to `$COLUMNS`/`$LINES` and
+/// then 80x24 when output isn't a terminal
+pub fn size() -> (u16, u16) {
+    if let Some(size) = sys::size() {
+        return size;
+    }
+    let env = |name: &str, default: u16| std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    (env("COLUMNS", 80), env("LINES", 24))
+}
+
+/// Whether `style` should emit escape codes: off under `NO_COLOR`, on under
+/// `FORCE_COLOR`, otherwise only when stdout is a terminal
+pub fn color_enabled() -> bool {
+    if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return false;
+    }
+    std::env::var_os("FORCE_COLOR").is_some_and(|v| !v.is_empty()) || is_tty(Stream::Stdout)
+}
+
+/// Make sure escape sequences are interpreted rather than printed. Only
+/// Windows consoles need this, and only once.
+pub fn enable_ansi() {
+    static ONCE: std::sync::Once = std::sync::Once::new();
+    ONCE.call_once(sys::enable_virtual_terminal);
+}
+
+const COLORS: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+fn color_code(name: &str, background: bool) -> Option<String> {
+    let base = if background { 40 } else { 30 };
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(format!("{};2;{};{};{}", base + 8, channel(0)?, channel(2)?, channel(4)?));
+    }
+    if let Some(bright) = name.strip_prefix("bright_") {
+        let idx = COLORS.iter().position(|c| *c == bright)?;
+        return Some((base + 60 + idx).to_string());
+    }
+    if name == "gray" || name == "grey" {
+        return Some((base + 60).to_string());
+    }
+    COLORS.iter().position(|c| *c == name).map(|idx| (base + idx).to_string())
+}
+
+/// SGR parameters for a space-separated style such as `"bold red on_white"`.
+/// Colors: the eight ANSI names, `bright_*`, `gray` and `#rrggbb`; prefix
+/// with `on_` for the background.
+pub fn sgr(spec: &str) -> Result<String, String> {
+    let mut codes = Vec::new();
+    for word in spec.split_whitespace() {
+        let word = word.to_ascii_lowercase();
+        let code = match word.as_str() {
+            "bold" => Some("1".to_string()),
+            "dim" => Some("2".to_string()),
+            "italic" => Some("3".to_string()),
+            "underline" => Some("4".to_string()),
+            "blink" => Some("5".to_string()),
+            "reverse" => Some("7".to_string()),
+            "hidden" => Some("8".to_string()),
+            "strike" => Some("9".to_string()),
+            w => match w.strip_prefix("on_") {
+                Some(bg) => color_code(bg, true),
+                None => color_code(w, false),
+            },
+        };
+        codes.push(code.ok_or_else(|| format!("style: unknown style '{}'", word))?);
+    }
+    Ok(codes.join(";"))
+}
+
+/// Wrap `text` in the escape codes for `spec`, or return it unchanged when
+/// color is disabled
+pub fn style(text: &str, spec: &str) -> Result<String, String> {
+    let codes = sgr(spec)?;
+    if codes.is_empty() || !color_enabled() {
+        return Ok(text.to_string());
+    }
+    enable_ansi();
+    Ok(format!("\x1b[{}m{}\x1b[0m", codes, text))
+}
+
+/// Remove CSI escape sequences, e.g. to measure the visible width of styled text
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Parameters and intermediates, up to the final byte in @..~
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Write escape sequences (or any text) straight to stdout, unbuffered
+pub fn emit(text: &str) {
+    enable_ansi();
+    let mut out = io::stdout();
+    let _ = out.write_all(text.as_bytes());
+    let _ = out.flush();
+}
+
+/// Block for one key press with the terminal in raw mode, returning a name
+/// such as `"a"`, `"enter"`, `"up"`, `"ctrl+c"` or `"f5"`. None at end of input.
+/// When stdin isn't a terminal this reads the next character instead.
+pub fn read_key() -> Option<String> {
+    let raw = sys::RawMode::enable();
+    let mut stdin = io::stdin().lock();
+    let mut byte = [0u8; 1];
+    let mut next = |stdin: &mut io::StdinLock| -> Option<u8> {
+        match stdin.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    };
+    // Raw reads time out so a bare ESC can be told apart from an escape
+    // sequence; only a non-terminal stdin can really reach end of input
+    let first = loop {
+        match next(&mut stdin) {
+            Some(b) => break b,
+            None if raw.active() => continue,
+            None => return None,
+        }
+    };
+    let key = match first {
+        b'\r' | b'\n' => "enter".to_string(),
+        b'\t' => "tab".to_string(),
+        0x7f | 0x08 => "backspace".to_string(),
+        0x1b => {
+            // A bare ESC key is followed by nothing before the read times out
+            match next(&mut stdin) {
+                Some(b'[') | Some(b'O') => {
+                    let mut seq = String::new();
+                    while let Some(b) = next(&mut stdin) {
+                        seq.push(b as char);
+                        if (0x40..=0x7e).contains(&b) {
+                            break;
+                        }
+                    }
+                    escape_key(&seq).to_string()
+                }
+                Some(b) => format!("alt+{}", b as char),
+                None => "escape".to_string(),
+            }
+        }
+        b @ 1..=26 => format!("ctrl+{}", (b'a' + b - 1) as char),
+        b if b < 0x80 => (b as char).to_string(),
+        b => {
+            // Multi-byte UTF-8 character: the lead byte says how many follow
+            let len = if b >= 0xf0 { 4 } else if b >= 0xe0 { 3 } else { 2 };
+            let mut buf = vec![b];
+            for _ in 1..len {
+                buf.push(next(&mut stdin)?);
+            }
+            String::from_utf8_lossy(&buf).into_owned()
+        }
+    };
+    Some(key)
+}
+
+fn escape_key(seq: &str) -> &'static str {
+    match seq {
+        "A" => "up",
+        "B" => "down",
+        "C" => "right",
+        "D" => "left",
+        "H" | "1~" | "7~" => "home",
+        "F" | "4~" | "8~" => "end",
+        "2~" => "insert",
+        "3~" => "delete",
+        "5~" => "pageup",
+        "6~" => "pagedown",
+        "P" | "11~" => "f1",
+        "Q" | "12~" => "f2",
+        "R" | "13~" => "f3",
+        "S" | "14~" => "f4",
+        "15~" => "f5",
+        "17~" => "f6",
+        "18~" => "f7",
+        "19~" => "f8",
+        "20~" => "f9",
+        "21~" => "f10",
+        "23~" => "f11",
+        "24~" => "f12",
+        "Z" => "shift+tab",
+        _ => "unknown",
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    pub fn size() -> Option<(u16, u16)> {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+        (ok && ws.ws_col > 0).then_some((ws.ws_col, ws.ws_row))
+    }
+
+    pub fn enable_virtual_terminal() {}
+
+    /// Puts stdin in raw mode (no echo, no line buffering, no signals) until dropped
+    pub struct RawMode {
+        saved: Option<libc::termios>,
+    }
+
+    impl RawMode {
+        pub fn enable() -> RawMode {
+            unsafe {
+                let mut saved: libc::termios = std::mem::zeroed();
+                if libc::isatty(libc::STDIN_FILENO) != 1 || libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                    return RawMode { saved: None };
+                }
+                let mut raw = saved;
+                libc::cfmakeraw(&mut raw);
+                // Keep output post-processing so "\n" still returns the carriage
+                raw.c_oflag = saved.c_oflag;
+                // Reads return after 100ms even with no input
+                raw.c_cc[libc::VMIN] = 0;
+                raw.c_cc[libc::VTIME] = 1;
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+                RawMode { saved: Some(saved) }
+            }
+        }
+    }
+
+    impl RawMode {
+        pub fn active(&self) -> bool {
+            self.saved.is_some()
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            if let Some(saved) = &self.saved {
+                unsafe {
+                    libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+
+    const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_PROCESSED_INPUT: u32 = 0x0001;
+    const ENABLE_LINE_INPUT: u32 = 0x0002;
+    const ENABLE_ECHO_INPUT: u32 = 0x0004;
+    const ENABLE_VIRTUAL_TERMINAL_INPUT: u32 = 0x0200;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Coord {
+        x: i16,
+        y: i16,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SmallRect {
+        left: i16,
+        top: i16,
+        right: i16,
+        bottom: i16,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ScreenBufferInfo {
+        size: Coord,
+        cursor: Coord,
+        attributes: u16,
+        window: SmallRect,
+        max_window: Coord,
+    }
+
+    extern "system" {
+        fn GetStdHandle(which: u32) -> *mut c_void;
+        fn GetConsoleMode(handle: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(handle: *mut c_void, mode: u32) -> i32;
+        fn GetConsoleScreenBufferInfo(handle: *mut c_void, info: *mut ScreenBufferInfo) -> i32;
+    }
+
+    pub fn size() -> Option<(u16, u16)> {
+        let mut info = ScreenBufferInfo::default();
+        let ok = unsafe { GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) } != 0;
+        let w = &info.window;
+        ok.then(|| ((w.right - w.left + 1) as u16, (w.bottom - w.top + 1) as u16))
+    }
+
+    /// Windows 10+ consoles understand ANSI escapes once asked to
+    pub fn enable_virtual_terminal() {
+        unsafe {
+            let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+            let mut mode = 0;
+            if GetConsoleMode(handle, &mut mode) != 0 {
+                SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING);
+            }
+        }
+    }
+
+    pub struct RawMode {
+        saved: Option<u32>,
+    }
+
+    impl RawMode {
+        /// Key presses arrive immediately, unechoed, with arrows and function
+        /// keys reported as the same VT sequences a Unix terminal sends
+        pub fn enable() -> RawMode {
+            unsafe {
+                let handle = GetStdHandle(STD_INPUT_HANDLE);
+                let mut mode = 0;
+                if GetConsoleMode(handle, &mut mode) == 0 {
+                    return RawMode { saved: None };
+                }
+                let raw = (mode & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT | ENABLE_PROCESSED_INPUT)) | ENABLE_VIRTUAL_TERMINAL_INPUT;
+                SetConsoleMode(handle, raw);
+                RawMode { saved: Some(mode) }
+            }
+        }
+    }
+
+    impl RawMode {
+        /// Console reads block without a timeout, so a bare ESC is only
+        /// recognised once the next key arrives
+        pub fn active(&self) -> bool {
+            false
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            if let Some(mode) = self.saved {
+                unsafe {
+                    SetConsoleMode(GetStdHandle(STD_INPUT_HANDLE), mode);
+                }
+            }
+        }
+    }
+}
+
+// No terminal at all (e.g. wasm): sizes come from the environment and raw
+// mode is never active
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    pub fn size() -> Option<(u16, u16)> {
+        None
+    }
+
+    pub fn enable_virtual_terminal() {}
+
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> RawMode {
+            RawMode
+        }
+
+        pub fn active(&self) -> bool {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sgr() {
+        assert_eq!(sgr("bold red").unwrap(), "1;31");
+        assert_eq!(sgr("bright_green on_blue underline").unwrap(), "92;44;4");
+        assert_eq!(sgr("#ff8000 on_gray").unwrap(), "38;2;255;128;0;100");
+        assert!(sgr("sparkly").is_err());
+        assert!(sgr("#12345").is_err());
+        assert_eq!(strip_ansi("\x1b[1;31mhi\x1b[0m there\x1b[2K"), "hi there");
+        assert_eq!(escape_key("15~"), "f5");
+    }
+}
@@ -0,0 +1,344 @@
+// ============================================
+// Cryo Text Module
+// Locale-independent number formatting/parsing, Unicode case folding and
+// normalization, padding and identifier case conversion
+// ============================================
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Group the digits of `n` in threes: `format_int(1234567, ",")` is `1,234,567`
+pub fn format_int(n: i64, thousands_sep: &str) -> String {
+    let digits = group_digits(&n.unsigned_abs().to_string(), thousands_sep);
+    if n < 0 { format!("-{}", digits) } else { digits }
+}
+
+/// Insert `sep` between groups of three in a string of decimal digits
+pub fn group_digits(digits: &str, sep: &str) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3 * sep.len());
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push_str(sep);
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Strict integer parsing: surrounding whitespace and a leading `+` are
+/// allowed, anything else that isn't a digit is an error
+pub fn parse_int(text: &str) -> Option<i64> {
+    text.trim().parse().ok()
+}
+
+/// Parse a decimal or exponent-form number (`" 2.50 "`, `-1e3`). Non-finite
+/// values and trailing garbage are rejected.
+pub fn parse_float(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let is_numeric = text.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E'));
+    if !is_numeric {
+        return None; // f64::from_str would also take "inf" and "NaN"
+    }
+    text.parse().ok().filter(|f: &f64| f.is_finite())
+}
+
+/// A float the way `print` shows it: the shortest digits that read back as
+/// the same float, with `.0` on whole numbers so they don't look like ints.
+/// Magnitudes from 1e21 up or below 1e-7 use exponent form (`1e300`,
+/// `1.5e-8`) instead of hundreds of digits.
+pub fn format_float(f: f64) -> String {
+    let magnitude = f.abs();
+    if !f.is_finite() {
+        f.to_string()
+    } else if magnitude != 0.0 && !(1e-7..1e21).contains(&magnitude) {
+        format!("{:e}", f)
+    } else if f == f.trunc() {
+        format!("{:.1}", f)
+    } else {
+        f.to_string()
+    }
+}
+
+/// `f` with exactly `digits` digits after the point, rounded to nearest
+/// (ties to even on the binary value, like printf)
+pub fn format_float_fixed(f: f64, digits: usize) -> String {
+    if f.is_finite() { format!("{:.*}", digits, f) } else { f.to_string() }
+}
+
+/// Decimal digits and a power of ten: the value is `digits * 10^exp`
+struct DecimalParts {
+    negative: bool,
+    digits: Vec<u8>,
+    exp: i64,
+}
+
+fn decimal_parts(text: &str) -> Option<DecimalParts> {
+    let text = text.trim();
+    let (negative, rest) = match text.as_bytes().first()? {
+        b'-' => (true, &text[1..]),
+        b'+' => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (mantissa, exponent) = match rest.find(['e', 'E']) {
+        Some(i) => (&rest[..i], rest[i + 1..].parse::<i64>().ok()?),
+        None => (rest, 0),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    let digits: Vec<u8> = int_part.bytes().chain(frac_part.bytes()).collect();
+    if !digits.iter().all(u8::is_ascii_digit) || exponent.abs() > 4096 {
+        return None;
+    }
+    Some(DecimalParts {
+        negative,
+        digits: digits.into_iter().map(|d| d - b'0').collect(),
+        exp: exponent - frac_part.len() as i64,
+    })
+}
+
+/// Format a decimal string (or integer) with exactly `places` fraction
+/// digits, rounding half away from zero on the decimal digits themselves, so
+/// `to_fixed("1.005", 2)` is `1.01` rather than binary floating point's `1.00`
+pub fn to_fixed(number: &str, places: usize) -> Option<String> {
+    let DecimalParts { negative, mut digits, exp } = decimal_parts(number)?;
+    let shift = exp + places as i64;
+    if shift >= 0 {
+        digits.extend(std::iter::repeat_n(0, shift as usize));
+    } else {
+        let drop = (-shift) as usize;
+        if digits.len() <= drop {
+            // Keep one leading zero so the first dropped digit is still there to round on
+            let pad = drop + 1 - digits.len();
+            digits.splice(0..0, std::iter::repeat_n(0, pad));
+        }
+        let round_up = digits[digits.len() - drop] >= 5;
+        digits.truncate(digits.len() - drop);
+        if round_up {
+            let mut i = digits.len();
+            loop {
+                if i == 0 {
+                    digits.insert(0, 1);
+                    break;
+                }
+                i -= 1;
+                if digits[i] == 9 {
+                    digits[i] = 0;
+                } else {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+    }
+    // At least one integer digit, then drop redundant leading zeros
+    while digits.len() < places + 1 {
+        digits.insert(0, 0);
+    }
+    let leading = digits.iter().take(digits.len() - places - 1).take_while(|d| **d == 0).count();
+    digits.drain(..leading);
+
+    let mut out = String::with_capacity(digits.len() + 2);
+    if negative && digits.iter().any(|d| *d != 0) {
+        out.push('-');
+    }
+    let int_len = digits.len() - places;
+    for (i, d) in digits.iter().enumerate() {
+        if i == int_len {
+            out.push('.');
+        }
+        out.push((b'0' + d) as char);
+    }
+    Some(out)
+}
+
+/// Full Unicode case folding for caseless comparison. Beyond lowercasing,
+/// folding expands characters like `ß` to `ss` and merges final sigma.
+pub fn casefold(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            'ß' | 'ẞ' => out.push_str("ss"),
+            'ς' => out.push('σ'),
+            'ſ' => out.push('s'),
+            'ŉ' => out.push_str("ʼn"),
+            'ﬀ' => out.push_str("ff"),
+            'ﬁ' => out.push_str("fi"),
+            'ﬂ' => out.push_str("fl"),
+            'ﬃ' => out.push_str("ffi"),
+            'ﬄ' => out.push_str("ffl"),
+            'ﬅ' | 'ﬆ' => out.push_str("st"),
+            // Cherokee folds to its uppercase letters, unlike every other script
+            'Ꭰ'..='Ᏽ' => out.push(c),
+            'ꭰ'..='ꮿ' | 'ᏸ'..='ᏽ' => out.extend(c.to_uppercase()),
+            c => out.extend(c.to_lowercase()),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+impl NormalForm {
+    pub fn from_name(name: &str) -> Option<NormalForm> {
+        match name.to_ascii_uppercase().as_str() {
+            "NFC" => Some(NormalForm::Nfc),
+            "NFD" => Some(NormalForm::Nfd),
+            "NFKC" => Some(NormalForm::Nfkc),
+            "NFKD" => Some(NormalForm::Nfkd),
+            _ => None,
+        }
+    }
+}
+
+pub fn normalize(text: &str, form: NormalForm) -> String {
+    match form {
+        NormalForm::Nfc => text.nfc().collect(),
+        NormalForm::Nfd => text.nfd().collect(),
+        NormalForm::Nfkc => text.nfkc().collect(),
+        NormalForm::Nfkd => text.nfkd().collect(),
+    }
+}
+
+/// Where `pad` puts the text within the width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// `text` widened to `width` characters with copies of `fill` (repeated and
+/// cut to fit, so a multi-character fill works). Centering puts the odd
+/// character on the right. Text already that wide comes back unchanged.
+pub fn pad(text: &str, width: usize, fill: &str, align: Align) -> String {
+    let missing = width.saturating_sub(text.chars().count());
+    if missing == 0 || fill.is_empty() {
+        return text.to_string();
+    }
+    let run = |n: usize| fill.chars().cycle().take(n).collect::<String>();
+    let before = match align {
+        Align::Left => 0,
+        Align::Right => missing,
+        Align::Center => missing / 2,
+    };
+    format!("{}{}{}", run(before), text, run(missing - before))
+}
+
+/// Each whitespace-separated word capitalized and the rest lowercased:
+/// `title_case("the QUICK fox")` is `The Quick Fox`
+pub fn title_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut word_start = true;
+    for c in text.chars() {
+        if word_start {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace();
+    }
+    out
+}
+
+/// `user_id` as `userId`. Leading underscores are kept and repeated ones
+/// collapse: `__max__len` is `__maxLen`.
+pub fn snake_to_camel(text: &str) -> String {
+    let body = text.trim_start_matches('_');
+    let mut out = text[..text.len() - body.len()].to_string();
+    for (i, part) in body.split('_').filter(|p| !p.is_empty()).enumerate() {
+        let mut chars = part.chars();
+        match chars.next() {
+            Some(first) if i > 0 => {
+                out.extend(first.to_uppercase());
+                out.push_str(chars.as_str());
+            }
+            _ => out.push_str(part),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(format_int(1234567, ","), "1,234,567");
+        assert_eq!(format_int(-1000, "\u{a0}"), "-1\u{a0}000");
+        assert_eq!(format_int(999, ","), "999");
+        assert_eq!(format_int(i64::MIN, "_"), "-9_223_372_036_854_775_808");
+        assert_eq!(parse_int(" +42 "), Some(42));
+        assert_eq!(parse_int("42abc"), None);
+        assert_eq!(parse_float(" 2.50 "), Some(2.5));
+        assert_eq!(parse_float("-1e3"), Some(-1000.0));
+        assert_eq!(parse_float("-0.0"), Some(0.0));
+        assert_eq!(parse_float("inf"), None);
+        assert_eq!(parse_float("1.2.3"), None);
+        assert_eq!(parse_float(""), None);
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(2.0), "2.0");
+        assert_eq!(format_float(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_float(1e16), "10000000000000000.0");
+        assert_eq!(format_float(1e21), "1e21");
+        assert_eq!(format_float(-1.5e-8), "-1.5e-8");
+        assert_eq!(format_float(f64::NEG_INFINITY), "-inf");
+        assert_eq!(format_float_fixed(2.0 / 3.0, 3), "0.667");
+        assert_eq!(format_float_fixed(f64::NAN, 2), "NaN");
+        // Every finite float reads back exactly
+        for f in [0.1, 1.0 / 3.0, 5e-324, f64::MAX, -f64::MIN_POSITIVE, 123456.789e10, 9007199254740993.0] {
+            assert_eq!(parse_float(&format_float(f)), Some(f), "{}", f);
+        }
+    }
+
+    #[test]
+    fn test_to_fixed() {
+        assert_eq!(to_fixed("1.005", 2).as_deref(), Some("1.01"));
+        assert_eq!(to_fixed("2.5", 0).as_deref(), Some("3"));
+        assert_eq!(to_fixed("-2.5", 0).as_deref(), Some("-3"));
+        assert_eq!(to_fixed("9.995", 2).as_deref(), Some("10.00"));
+        assert_eq!(to_fixed("0.0004", 3).as_deref(), Some("0.000"));
+        assert_eq!(to_fixed("-0.0004", 3).as_deref(), Some("0.000"));
+        assert_eq!(to_fixed("0.0005", 3).as_deref(), Some("0.001"));
+        assert_eq!(to_fixed("42", 2).as_deref(), Some("42.00"));
+        assert_eq!(to_fixed("1.5e2", 1).as_deref(), Some("150.0"));
+        assert_eq!(to_fixed("007.10", 1).as_deref(), Some("7.1"));
+        assert_eq!(to_fixed(".5", 0).as_deref(), Some("1"));
+        assert_eq!(to_fixed("abc", 2), None);
+        assert_eq!(to_fixed("1e99999", 2), None);
+    }
+
+    #[test]
+    fn test_pad_and_case() {
+        assert_eq!(pad("7", 3, "0", Align::Right), "007");
+        assert_eq!(pad("ab", 5, "-", Align::Left), "ab---");
+        assert_eq!(pad("ab", 5, "*", Align::Center), "*ab**");
+        assert_eq!(pad("x", 6, "ab", Align::Right), "ababax");
+        assert_eq!(pad("héllo", 3, " ", Align::Left), "héllo");
+        assert_eq!(pad("é", 3, "·", Align::Center), "·é·");
+        assert_eq!(title_case("the QUICK  brown-fox"), "The Quick  Brown-fox");
+        assert_eq!(snake_to_camel("user_id"), "userId");
+        assert_eq!(snake_to_camel("__max__len_"), "__maxLen");
+        assert_eq!(snake_to_camel("plain"), "plain");
+    }
+
+    #[test]
+    fn test_unicode() {
+        assert_eq!(casefold("Straße"), casefold("STRASSE"));
+        assert_eq!(casefold("ΣΊΣΥΦΟΣ"), casefold("σίσυφος"));
+        assert_eq!(normalize("e\u{301}", NormalForm::Nfc), "\u{e9}");
+        assert_eq!(normalize("\u{e9}", NormalForm::Nfd), "e\u{301}");
+        assert_eq!(normalize("ﬁ", NormalForm::Nfkc), "fi");
+        assert_eq!(NormalForm::from_name("nfkd"), Some(NormalForm::Nfkd));
+    }
+}
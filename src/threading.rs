@@ -5,43 +5,80 @@
 
 #![allow(dead_code)]
 
+use crate::bigint::BigInt;
+use crate::collections::Collection;
+use crate::decimal::Decimal;
+use crate::intern::Symbol;
+use crate::parser::Function;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread::{self, JoinHandle};
 use std::collections::HashMap;
 use std::time::Duration;
 
-/// Thread-safe value that can be passed between threads
+/// Thread-safe deep copy of an interpreter value.
+/// Everything that crosses a thread boundary (spawn arguments and results,
+/// channel messages) is copied into this form: the receiver gets its own
+/// arrays and structs, never a view of the sender's. State that really must be
+/// shared has to go through explicit synchronization primitives.
 #[derive(Debug, Clone)]
-pub enum ThreadValue {
+pub enum SendValue {
     Null,
     Bool(bool),
     Int(i64),
+    Float(f64),
+    BigInt(BigInt),
+    Decimal(Decimal),
     String(String),
-    Array(Vec<ThreadValue>),
+    Array(Vec<SendValue>),
+    Struct(Symbol, HashMap<String, SendValue>),
+    Map(HashMap<String, SendValue>),
+    Function(Arc<Function>),
+    Collection(Box<Collection<SendValue>>),
+    /// Enum name, variant and payload
+    Enum(Symbol, Symbol, Vec<SendValue>),
 }
 
-impl ThreadValue {
+impl SendValue {
     pub fn to_string_val(&self) -> String {
         match self {
-            ThreadValue::Null => "null".to_string(),
-            ThreadValue::Bool(b) => b.to_string(),
-            ThreadValue::Int(n) => n.to_string(),
-            ThreadValue::String(s) => s.clone(),
-            ThreadValue::Array(arr) => {
+            SendValue::Null => "null".to_string(),
+            SendValue::Bool(b) => b.to_string(),
+            SendValue::Int(n) => n.to_string(),
+            SendValue::Float(f) => f.to_string(),
+            SendValue::BigInt(n) => n.to_string(),
+            SendValue::Decimal(d) => d.to_string(),
+            SendValue::String(s) => s.clone(),
+            SendValue::Array(arr) => {
                 let items: Vec<String> = arr.iter().map(|v| v.to_string_val()).collect();
                 format!("[{}]", items.join(", "))
             }
+            SendValue::Struct(name, fields) => {
+                let items: Vec<String> = fields.iter()
+                    .map(|(k, v)| format!("{}: {}", k, v.to_string_val()))
+                    .collect();
+                format!("{} {{ {} }}", name, items.join(", "))
+            }
+            SendValue::Map(map) => format!("<map of {}>", map.len()),
+            SendValue::Function(func) => format!("<fn {}>", func.name),
+            SendValue::Collection(coll) => format!("<{} of {}>", coll.kind(), coll.len()),
+            SendValue::Enum(name, variant, fields) if fields.is_empty() => format!("{}::{}", name, variant),
+            SendValue::Enum(name, variant, fields) => {
+                let fields: Vec<String> = fields.iter().map(|v| v.to_string_val()).collect();
+                format!("{}::{}({})", name, variant, fields.join(", "))
+            }
         }
     }
 }
 
 /// Channel for inter-thread communication
 pub struct Channel {
-    sender: mpsc::Sender<ThreadValue>,
-    receiver: Arc<Mutex<mpsc::Receiver<ThreadValue>>>,
+    sender: mpsc::Sender<SendValue>,
+    receiver: Arc<Mutex<mpsc::Receiver<SendValue>>>,
 }
 
 impl Channel {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> (ChannelSender, ChannelReceiver) {
         let (tx, rx) = mpsc::channel();
         (
@@ -54,32 +91,32 @@ impl Channel {
 /// Send half of a channel
 #[derive(Clone)]
 pub struct ChannelSender {
-    sender: mpsc::Sender<ThreadValue>,
+    sender: mpsc::Sender<SendValue>,
 }
 
 impl ChannelSender {
-    pub fn send(&self, value: ThreadValue) -> Result<(), String> {
+    pub fn send(&self, value: SendValue) -> Result<(), String> {
         self.sender.send(value).map_err(|e| e.to_string())
     }
 }
 
 /// Receive half of a channel
 pub struct ChannelReceiver {
-    receiver: Arc<Mutex<mpsc::Receiver<ThreadValue>>>,
+    receiver: Arc<Mutex<mpsc::Receiver<SendValue>>>,
 }
 
 impl ChannelReceiver {
-    pub fn recv(&self) -> Result<ThreadValue, String> {
+    pub fn recv(&self) -> Result<SendValue, String> {
         let rx = self.receiver.lock().map_err(|e| e.to_string())?;
         rx.recv().map_err(|e| e.to_string())
     }
     
-    pub fn try_recv(&self) -> Option<ThreadValue> {
+    pub fn try_recv(&self) -> Option<SendValue> {
         let rx = self.receiver.lock().ok()?;
         rx.try_recv().ok()
     }
     
-    pub fn recv_timeout(&self, timeout_ms: u64) -> Option<ThreadValue> {
+    pub fn recv_timeout(&self, timeout_ms: u64) -> Option<SendValue> {
         let rx = self.receiver.lock().ok()?;
         rx.recv_timeout(Duration::from_millis(timeout_ms)).ok()
     }
@@ -96,11 +133,11 @@ impl Clone for ChannelReceiver {
 /// Worker handle for spawned threads
 pub struct WorkerHandle {
     pub id: i64,
-    handle: Option<JoinHandle<ThreadValue>>,
+    handle: Option<JoinHandle<SendValue>>,
 }
 
 impl WorkerHandle {
-    pub fn join(&mut self) -> Result<ThreadValue, String> {
+    pub fn join(&mut self) -> Result<SendValue, String> {
         if let Some(h) = self.handle.take() {
             h.join().map_err(|_| "Thread panicked".to_string())
         } else {
@@ -113,10 +150,20 @@ impl WorkerHandle {
     }
 }
 
+/// Channel ids are process-wide so a channel handle keeps its meaning
+/// inside isolates spawned from the interpreter that created it
+static NEXT_CHANNEL_ID: AtomicI64 = AtomicI64::new(1);
+
+/// Clones of every channel endpoint, handed to a spawned isolate
+#[derive(Clone, Default)]
+pub struct ChannelTable {
+    senders: HashMap<i64, ChannelSender>,
+    receivers: HashMap<i64, ChannelReceiver>,
+}
+
 /// Thread manager - handles all concurrency primitives
 pub struct ThreadManager {
     next_worker_id: i64,
-    next_channel_id: i64,
     workers: HashMap<i64, WorkerHandle>,
     senders: HashMap<i64, ChannelSender>,
     receivers: HashMap<i64, ChannelReceiver>,
@@ -126,7 +173,6 @@ impl ThreadManager {
     pub fn new() -> Self {
         ThreadManager {
             next_worker_id: 1,
-            next_channel_id: 1,
             workers: HashMap::new(),
             senders: HashMap::new(),
             receivers: HashMap::new(),
@@ -136,8 +182,7 @@ impl ThreadManager {
     /// Create a new unbuffered channel, returns (channel_id)
     pub fn create_channel(&mut self) -> i64 {
         let (sender, receiver) = Channel::new();
-        let id = self.next_channel_id;
-        self.next_channel_id += 1;
+        let id = NEXT_CHANNEL_ID.fetch_add(1, Ordering::Relaxed);
         self.senders.insert(id, sender);
         self.receivers.insert(id, receiver);
         id
@@ -147,15 +192,14 @@ impl ThreadManager {
     pub fn create_buffered_channel(&mut self, _capacity: usize) -> i64 {
         // Note: mpsc::sync_channel needs different types, simplify to unbuffered for now
         let (sender, receiver) = Channel::new();
-        let id = self.next_channel_id;
-        self.next_channel_id += 1;
+        let id = NEXT_CHANNEL_ID.fetch_add(1, Ordering::Relaxed);
         self.senders.insert(id, sender);
         self.receivers.insert(id, receiver);
         id
     }
     
     /// Send a value to channel
-    pub fn channel_send(&self, channel_id: i64, value: ThreadValue) -> bool {
+    pub fn channel_send(&self, channel_id: i64, value: SendValue) -> bool {
         if let Some(sender) = self.senders.get(&channel_id) {
             sender.send(value).is_ok()
         } else {
@@ -164,7 +208,7 @@ impl ThreadManager {
     }
     
     /// Receive a value from channel (blocking)
-    pub fn channel_recv(&self, channel_id: i64) -> Option<ThreadValue> {
+    pub fn channel_recv(&self, channel_id: i64) -> Option<SendValue> {
         if let Some(receiver) = self.receivers.get(&channel_id) {
             receiver.recv().ok()
         } else {
@@ -173,7 +217,7 @@ impl ThreadManager {
     }
     
     /// Try to receive without blocking
-    pub fn channel_try_recv(&self, channel_id: i64) -> Option<ThreadValue> {
+    pub fn channel_try_recv(&self, channel_id: i64) -> Option<SendValue> {
         if let Some(receiver) = self.receivers.get(&channel_id) {
             receiver.try_recv()
         } else {
@@ -182,7 +226,7 @@ impl ThreadManager {
     }
     
     /// Receive with timeout
-    pub fn channel_recv_timeout(&self, channel_id: i64, timeout_ms: u64) -> Option<ThreadValue> {
+    pub fn channel_recv_timeout(&self, channel_id: i64, timeout_ms: u64) -> Option<SendValue> {
         if let Some(receiver) = self.receivers.get(&channel_id) {
             receiver.recv_timeout(timeout_ms)
         } else {
@@ -190,6 +234,20 @@ impl ThreadManager {
         }
     }
     
+    /// Snapshot of all channel endpoints for a new isolate
+    pub fn channel_table(&self) -> ChannelTable {
+        ChannelTable {
+            senders: self.senders.clone(),
+            receivers: self.receivers.clone(),
+        }
+    }
+    
+    /// Adopt the channels of the interpreter that spawned this isolate
+    pub fn adopt_channels(&mut self, table: ChannelTable) {
+        self.senders.extend(table.senders);
+        self.receivers.extend(table.receivers);
+    }
+    
     /// Close a channel
     pub fn close_channel(&mut self, channel_id: i64) {
         self.senders.remove(&channel_id);
@@ -199,7 +257,7 @@ impl ThreadManager {
     /// Spawn a new worker thread that executes a closure
     pub fn spawn<F>(&mut self, task: F) -> i64 
     where 
-        F: FnOnce() -> ThreadValue + Send + 'static
+        F: FnOnce() -> SendValue + Send + 'static
     {
         let id = self.next_worker_id;
         self.next_worker_id += 1;
@@ -222,18 +280,18 @@ impl ThreadManager {
         
         let handle = thread::spawn(move || {
             match op.as_str() {
-                "double" => ThreadValue::Int(value * 2),
-                "square" => ThreadValue::Int(value * value),
+                "double" => SendValue::Int(value * 2),
+                "square" => SendValue::Int(value * value),
                 "factorial" => {
                     let mut result = 1i64;
                     for i in 1..=value {
                         result *= i;
                     }
-                    ThreadValue::Int(result)
+                    SendValue::Int(result)
                 }
                 "fib" => {
                     if value < 2 {
-                        ThreadValue::Int(value)
+                        SendValue::Int(value)
                     } else {
                         let mut a = 0i64;
                         let mut b = 1i64;
@@ -242,14 +300,14 @@ impl ThreadManager {
                             a = b;
                             b = temp;
                         }
-                        ThreadValue::Int(b)
+                        SendValue::Int(b)
                     }
                 }
                 "sleep" => {
                     thread::sleep(Duration::from_millis(value as u64));
-                    ThreadValue::Int(value)
+                    SendValue::Int(value)
                 }
-                _ => ThreadValue::Int(value)
+                _ => SendValue::Int(value)
             }
         });
         
@@ -262,7 +320,7 @@ impl ThreadManager {
     }
     
     /// Join a worker (wait for completion)
-    pub fn join_worker(&mut self, worker_id: i64) -> Option<ThreadValue> {
+    pub fn join_worker(&mut self, worker_id: i64) -> Option<SendValue> {
         if let Some(mut worker) = self.workers.remove(&worker_id) {
             worker.join().ok()
         } else {
@@ -285,7 +343,7 @@ impl ThreadManager {
     }
     
     /// Join all workers
-    pub fn join_all(&mut self) -> Vec<ThreadValue> {
+    pub fn join_all(&mut self) -> Vec<SendValue> {
         let ids: Vec<i64> = self.workers.keys().cloned().collect();
         ids.into_iter()
             .filter_map(|id| self.join_worker(id))
@@ -305,12 +363,27 @@ mod tests {
         // Send in separate thread
         let sender = tm.senders.get(&ch_id).unwrap().clone();
         thread::spawn(move || {
-            sender.send(ThreadValue::Int(42)).unwrap();
+            sender.send(SendValue::Int(42)).unwrap();
         });
         
         // Receive
         let result = tm.channel_recv(ch_id);
-        assert!(matches!(result, Some(ThreadValue::Int(42))));
+        assert!(matches!(result, Some(SendValue::Int(42))));
+    }
+    
+    #[test]
+    fn test_adopted_channels_keep_ids() {
+        let mut parent = ThreadManager::new();
+        let ch_id = parent.create_channel();
+        
+        let mut isolate = ThreadManager::new();
+        isolate.adopt_channels(parent.channel_table());
+        let other = isolate.create_channel();
+        assert_ne!(other, ch_id);
+        
+        assert!(isolate.channel_send(ch_id, SendValue::String("hi".to_string())));
+        let result = parent.channel_recv(ch_id);
+        assert!(matches!(result, Some(SendValue::String(s)) if s == "hi"));
     }
     
     #[test]
@@ -323,7 +396,7 @@ mod tests {
         let r1 = tm.join_worker(w1);
         let r2 = tm.join_worker(w2);
         
-        assert!(matches!(r1, Some(ThreadValue::Int(120)))); // 5!
-        assert!(matches!(r2, Some(ThreadValue::Int(55))));  // fib(10)
+        assert!(matches!(r1, Some(SendValue::Int(120)))); // 5!
+        assert!(matches!(r2, Some(SendValue::Int(55))));  // fib(10)
     }
 }
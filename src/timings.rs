@@ -0,0 +1,217 @@
+// ============================================
+// Cryo Pipeline Timings
+// `--timings`: wall time and resident memory for every pipeline stage (lex,
+// parse, expand, optimize, codegen, build, execute) of every file,
+// imported modules included. Times are self time: an import parsed while
+// `main` executes is its own row and not counted in `execute`.
+// ============================================
+
+use crate::config::{self, LogLevel};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One stage run of one file
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub file: String,
+    pub stage: &'static str,
+    /// Wall time minus the stages nested inside it
+    pub time: Duration,
+    /// Resident set size when the stage ended, in bytes
+    pub rss: Option<u64>,
+    /// Change in resident set size over the stage
+    pub rss_delta: Option<i64>,
+}
+
+struct Open {
+    index: usize,
+    start: Instant,
+    rss: Option<u64>,
+    nested: Duration,
+}
+
+struct Recorder {
+    /// Stages nest on one stack, so only the thread that enabled timings records
+    thread: std::thread::ThreadId,
+    started: Instant,
+    entries: Vec<Entry>,
+    open: Vec<Open>,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+/// Start recording; until then `time` just runs its closure
+pub fn enable() {
+    *RECORDER.lock().unwrap_or_else(|e| e.into_inner()) = Some(Recorder { thread: std::thread::current().id(), started: Instant::now(), entries: Vec::new(), open: Vec::new() });
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Run `f` as `stage` of `file`, recording it when timings are on
+pub fn time<T>(file: &str, stage: &'static str, f: impl FnOnce() -> T) -> T {
+    if !enabled() {
+        return f();
+    }
+    let mut recording = false;
+    with_recorder(|r| {
+        recording = r.thread == std::thread::current().id();
+        if !recording {
+            return;
+        }
+        r.entries.push(Entry { file: display_name(file), stage, time: Duration::ZERO, rss: None, rss_delta: None });
+        let index = r.entries.len() - 1;
+        r.open.push(Open { index, start: Instant::now(), rss: resident_bytes(), nested: Duration::ZERO });
+    });
+    let result = f();
+    if recording {
+        with_recorder(close_innermost);
+    }
+    result
+}
+
+fn with_recorder(f: impl FnOnce(&mut Recorder)) {
+    if let Some(recorder) = RECORDER.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        f(recorder);
+    }
+}
+
+fn close_innermost(r: &mut Recorder) {
+    let Some(open) = r.open.pop() else { return };
+    let elapsed = open.start.elapsed();
+    if let Some(parent) = r.open.last_mut() {
+        parent.nested += elapsed;
+    }
+    let rss = resident_bytes();
+    let entry = &mut r.entries[open.index];
+    entry.time = elapsed.saturating_sub(open.nested);
+    entry.rss = rss;
+    entry.rss_delta = rss.zip(open.rss).map(|(after, before)| after as i64 - before as i64);
+    config::log(LogLevel::Debug, &format!(
+        "timing file={} stage={} ms={:.3} rss_kib={}",
+        entry.file,
+        entry.stage,
+        entry.time.as_secs_f64() * 1000.0,
+        entry.rss.map_or("-".to_string(), |b| (b / 1024).to_string()),
+    ));
+}
+
+/// Close any stage still running (the program called `exit()`), print the
+/// report to stderr and stop recording. Does nothing when timings are off.
+pub fn finish() {
+    if !ENABLED.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    let Some(mut recorder) = RECORDER.lock().unwrap_or_else(|e| e.into_inner()).take() else { return };
+    while !recorder.open.is_empty() {
+        close_innermost(&mut recorder);
+    }
+    eprint!("{}", report(&recorder.entries, recorder.started.elapsed(), peak_resident_bytes()));
+}
+
+fn display_name(file: &str) -> String {
+    match file {
+        "" | "-" => "<stdin>".to_string(),
+        _ => file.to_string(),
+    }
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1}MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn ms(d: Duration) -> String {
+    format!("{:.2}ms", d.as_secs_f64() * 1000.0)
+}
+
+/// The table `--timings` prints: one row per stage run, then totals per stage
+pub fn report(entries: &[Entry], total: Duration, peak_rss: Option<u64>) -> String {
+    let mut out = String::from("timings:\n");
+    let width = entries.iter().map(|e| e.file.len()).max().unwrap_or(0).max(4);
+    out.push_str(&format!("  {:<width$}  {:<9} {:>10} {:>10} {:>10}\n", "file", "stage", "time", "rss", "+rss"));
+    for e in entries {
+        let delta = e.rss_delta.map_or("-".to_string(), |d| {
+            let sign = if d < 0 { "-" } else { "+" };
+            format!("{}{}", sign, mib(d.unsigned_abs()))
+        });
+        out.push_str(&format!(
+            "  {:<width$}  {:<9} {:>10} {:>10} {:>10}\n",
+            e.file,
+            e.stage,
+            ms(e.time),
+            e.rss.map_or("-".to_string(), mib),
+            delta,
+        ));
+    }
+    // Totals per stage, in the order stages first ran
+    let mut stages: Vec<(&'static str, Duration)> = Vec::new();
+    for e in entries {
+        match stages.iter_mut().find(|(s, _)| *s == e.stage) {
+            Some((_, time)) => *time += e.time,
+            None => stages.push((e.stage, e.time)),
+        }
+    }
+    let summary: Vec<String> = stages.iter().map(|(s, t)| format!("{} {}", s, ms(*t))).collect();
+    out.push_str(&format!("  total {}", ms(total)));
+    if let Some(peak) = peak_rss {
+        out.push_str(&format!(", peak rss {}", mib(peak)));
+    }
+    if !summary.is_empty() {
+        out.push_str(&format!(" ({})", summary.join(", ")));
+    }
+    out.push('\n');
+    out
+}
+
+/// Current resident set size. Only Linux reports it.
+#[cfg(target_os = "linux")]
+fn resident_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn peak_resident_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_resident_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let entry = |file: &str, stage, micros, rss: Option<u64>, rss_delta| Entry { file: file.to_string(), stage, time: Duration::from_micros(micros), rss, rss_delta };
+        let entries = [
+            entry("app.cryo", "lex", 250, Some(4 << 20), Some(1 << 20)),
+            entry("lib/util.cryo", "lex", 500, Some(4 << 20), Some(-(1 << 19))),
+            entry("app.cryo", "execute", 1500, None, None),
+        ];
+        let text = report(&entries, Duration::from_millis(3), Some(6 << 20));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "timings:");
+        assert_eq!(lines[2], "  app.cryo       lex           0.25ms     4.0MiB    +1.0MiB");
+        assert_eq!(lines[3], "  lib/util.cryo  lex           0.50ms     4.0MiB    -0.5MiB");
+        assert_eq!(lines[4], "  app.cryo       execute       1.50ms          -          -");
+        assert_eq!(lines[5], "  total 3.00ms, peak rss 6.0MiB (lex 0.75ms, execute 1.50ms)");
+    }
+}
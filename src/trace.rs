@@ -0,0 +1,579 @@
+// ============================================
+// Cryo IO Traces
+// `cryo --record trace.bin` wraps the platform (platform.rs) so every call a
+// builtin makes - files, connections and socket traffic, the clock, sleeps,
+// environment variables and random bytes - is appended to a trace with its
+// result. `cryo --replay trace.bin` answers the same calls from the trace,
+// so a failure seen in production runs the same way on any machine, without
+// the files, services or clock it originally saw. Program output isn't part
+// of the trace; replays print it again.
+//
+// Replay expects the calls in the recorded order, with the same arguments,
+// and panics at the first one that differs. Programs whose threads share
+// the platform (thread_spawn, http_serve) interleave calls differently from
+// run to run, so only single-threaded programs replay reliably.
+//
+// File format: the magic bytes, then events until end of file. An event is
+// an op byte, the length-prefixed input, then 0 and a length-prefixed output,
+// or 1, an error kind byte and a length-prefixed message. Integers are
+// little-endian. Each event is written as soon as the call returns, so the
+// trace survives a crash.
+// ============================================
+
+use crate::platform::{DirEntry, Platform, Socket};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const MAGIC: &[u8] = b"CRYOTRACE\x01";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    ReadFile = 1,
+    WriteFile,
+    Exists,
+    ReadDir,
+    Connect,
+    SocketRead,
+    SocketWrite,
+    SocketFlush,
+    Now,
+    Sleep,
+    EnvVar,
+    Random,
+    Rename,
+    RemoveFile,
+    TempDir,
+    Monotonic,
+}
+
+const OPS: [Op; 16] = [
+    Op::ReadFile, Op::WriteFile, Op::Exists, Op::ReadDir, Op::Connect, Op::SocketRead,
+    Op::SocketWrite, Op::SocketFlush, Op::Now, Op::Sleep, Op::EnvVar, Op::Random,
+    Op::Rename, Op::RemoveFile, Op::TempDir, Op::Monotonic,
+];
+
+impl Op {
+    fn name(self) -> &'static str {
+        match self {
+            Op::ReadFile => "read_file",
+            Op::WriteFile => "write_file",
+            Op::Exists => "exists",
+            Op::ReadDir => "read_dir",
+            Op::Connect => "connect",
+            Op::SocketRead => "socket read",
+            Op::SocketWrite => "socket write",
+            Op::SocketFlush => "socket flush",
+            Op::Now => "now",
+            Op::Sleep => "sleep",
+            Op::EnvVar => "env_var",
+            Op::Random => "random_bytes",
+            Op::Rename => "rename",
+            Op::RemoveFile => "remove_file",
+            Op::TempDir => "temp_dir",
+            Op::Monotonic => "monotonic",
+        }
+    }
+}
+
+/// Error kinds that survive a round trip; anything else comes back as Other
+const KINDS: [ErrorKind; 10] = [
+    ErrorKind::Other, ErrorKind::NotFound, ErrorKind::PermissionDenied, ErrorKind::ConnectionRefused,
+    ErrorKind::ConnectionReset, ErrorKind::TimedOut, ErrorKind::WouldBlock, ErrorKind::UnexpectedEof,
+    ErrorKind::AlreadyExists, ErrorKind::InvalidData,
+];
+
+type Output = Result<Vec<u8>, (ErrorKind, String)>;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Event {
+    op: Op,
+    input: Vec<u8>,
+    output: Output,
+}
+
+impl Event {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.op as u8];
+        put_bytes(&mut out, &self.input);
+        match &self.output {
+            Ok(data) => {
+                out.push(0);
+                put_bytes(&mut out, data);
+            }
+            Err((kind, message)) => {
+                out.push(1);
+                out.push(KINDS.iter().position(|k| k == kind).unwrap_or(0) as u8);
+                put_bytes(&mut out, message.as_bytes());
+            }
+        }
+        out
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    put_u32(out, data.len() as u32);
+    out.extend_from_slice(data);
+}
+
+/// Input of a rename: the length-prefixed source, then the destination
+fn rename_input(from: &str, to: &str) -> Vec<u8> {
+    let mut input = Vec::new();
+    put_bytes(&mut input, from.as_bytes());
+    input.extend_from_slice(to.as_bytes());
+    input
+}
+
+/// Input of a call on socket `id`: the id, then `rest`
+fn socket_input(id: u32, rest: &[u8]) -> Vec<u8> {
+    let mut input = id.to_le_bytes().to_vec();
+    input.extend_from_slice(rest);
+    input
+}
+
+fn decode(data: &[u8]) -> Result<Vec<Event>, String> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("not a cryo trace".to_string());
+    }
+    let mut events = Vec::new();
+    while reader.pos < data.len() {
+        let code = reader.u8()?;
+        let op = *OPS.get((code as usize).wrapping_sub(1)).ok_or_else(|| format!("unknown event {}", code))?;
+        let input = reader.bytes()?;
+        let output = match reader.u8()? {
+            0 => Ok(reader.bytes()?),
+            _ => {
+                let kind = KINDS.get(reader.u8()? as usize).copied().unwrap_or(ErrorKind::Other);
+                Err((kind, String::from_utf8_lossy(&reader.bytes()?).into_owned()))
+            }
+        };
+        events.push(Event { op, input, output });
+    }
+    Ok(events)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len()).ok_or("trace is truncated")?;
+        let out = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().map_err(|_| "trace is truncated")?);
+        Ok(self.take(len as usize)?.to_vec())
+    }
+}
+
+fn io_output<T>(result: &io::Result<T>, encode: impl FnOnce(&T) -> Vec<u8>) -> Output {
+    match result {
+        Ok(value) => Ok(encode(value)),
+        Err(e) => Err((e.kind(), e.to_string())),
+    }
+}
+
+fn io_result<T>(output: Output, decode: impl FnOnce(Vec<u8>) -> T) -> io::Result<T> {
+    output.map(decode).map_err(|(kind, message)| io::Error::new(kind, message))
+}
+
+fn encode_entries(entries: &[DirEntry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for entry in entries {
+        out.push(entry.is_dir as u8);
+        put_bytes(&mut out, entry.name.as_bytes());
+    }
+    out
+}
+
+fn decode_entries(data: Vec<u8>) -> Vec<DirEntry> {
+    let mut reader = Reader { data: &data, pos: 0 };
+    let mut entries = Vec::new();
+    while let (Ok(is_dir), Ok(name)) = (reader.u8(), reader.bytes()) {
+        entries.push(DirEntry { name: String::from_utf8_lossy(&name).into_owned(), is_dir: is_dir != 0 });
+    }
+    entries
+}
+
+fn nanos(duration: Duration) -> Vec<u8> {
+    (duration.as_nanos() as u64).to_le_bytes().to_vec()
+}
+
+fn duration(data: &[u8]) -> Duration {
+    Duration::from_nanos(data.try_into().map(u64::from_le_bytes).unwrap_or(0))
+}
+
+// ---- Recording ----
+
+struct Recorder {
+    file: Mutex<File>,
+    sockets: AtomicU32,
+}
+
+impl Recorder {
+    fn log(&self, op: Op, input: Vec<u8>, output: Output) {
+        let bytes = Event { op, input, output }.encode();
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write_all(&bytes) {
+            eprintln!("cryo: --record: {}", e);
+        }
+    }
+}
+
+/// `inner`, with every call appended to a trace file
+pub struct RecordingPlatform {
+    inner: Arc<dyn Platform>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingPlatform {
+    pub fn create(path: &str, inner: Arc<dyn Platform>) -> Result<Self, String> {
+        let mut file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        file.write_all(MAGIC).map_err(|e| format!("{}: {}", path, e))?;
+        let recorder = Arc::new(Recorder { file: Mutex::new(file), sockets: AtomicU32::new(0) });
+        Ok(RecordingPlatform { inner, recorder })
+    }
+}
+
+impl Platform for RecordingPlatform {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let result = self.inner.read_file(path);
+        self.recorder.log(Op::ReadFile, path.as_bytes().to_vec(), io_output(&result, |data| data.clone()));
+        result
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let result = self.inner.write_file(path, data);
+        let mut input = Vec::new();
+        put_bytes(&mut input, path.as_bytes());
+        input.extend_from_slice(data);
+        self.recorder.log(Op::WriteFile, input, io_output(&result, |_| Vec::new()));
+        result
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let result = self.inner.rename(from, to);
+        self.recorder.log(Op::Rename, rename_input(from, to), io_output(&result, |_| Vec::new()));
+        result
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        let result = self.inner.remove_file(path);
+        self.recorder.log(Op::RemoveFile, path.as_bytes().to_vec(), io_output(&result, |_| Vec::new()));
+        result
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let exists = self.inner.exists(path);
+        self.recorder.log(Op::Exists, path.as_bytes().to_vec(), Ok(vec![exists as u8]));
+        exists
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        let result = self.inner.read_dir(path);
+        self.recorder.log(Op::ReadDir, path.as_bytes().to_vec(), io_output(&result, |entries| encode_entries(entries)));
+        result
+    }
+
+    fn temp_dir(&self) -> String {
+        let dir = self.inner.temp_dir();
+        self.recorder.log(Op::TempDir, Vec::new(), Ok(dir.as_bytes().to_vec()));
+        dir
+    }
+
+    fn connect(&self, addr: &str) -> io::Result<Box<dyn Socket>> {
+        let result = self.inner.connect(addr);
+        let id = self.recorder.sockets.fetch_add(1, Ordering::SeqCst);
+        self.recorder.log(Op::Connect, addr.as_bytes().to_vec(), io_output(&result, |_| id.to_le_bytes().to_vec()));
+        let inner = result?;
+        Ok(Box::new(RecordingSocket { inner, id, recorder: self.recorder.clone() }))
+    }
+
+    fn now(&self) -> Duration {
+        let now = self.inner.now();
+        self.recorder.log(Op::Now, Vec::new(), Ok(nanos(now)));
+        now
+    }
+
+    fn monotonic(&self) -> Duration {
+        let elapsed = self.inner.monotonic();
+        self.recorder.log(Op::Monotonic, Vec::new(), Ok(nanos(elapsed)));
+        elapsed
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.inner.sleep(duration);
+        self.recorder.log(Op::Sleep, nanos(duration), Ok(Vec::new()));
+    }
+
+    fn random_bytes(&self, n: usize) -> io::Result<Vec<u8>> {
+        let result = self.inner.random_bytes(n);
+        self.recorder.log(Op::Random, (n as u32).to_le_bytes().to_vec(), io_output(&result, |bytes| bytes.clone()));
+        result
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        let value = self.inner.env_var(name);
+        let output = value.as_ref().map(|v| v.as_bytes().to_vec()).ok_or((ErrorKind::NotFound, String::new()));
+        self.recorder.log(Op::EnvVar, name.as_bytes().to_vec(), output);
+        value
+    }
+
+    fn set_env_var(&self, name: &str, value: &str) {
+        self.inner.set_env_var(name, value)
+    }
+
+    fn write_stdout(&self, text: &str) {
+        self.inner.write_stdout(text)
+    }
+}
+
+struct RecordingSocket {
+    inner: Box<dyn Socket>,
+    id: u32,
+    recorder: Arc<Recorder>,
+}
+
+impl Read for RecordingSocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf);
+        let input = socket_input(self.id, &(buf.len() as u32).to_le_bytes());
+        self.recorder.log(Op::SocketRead, input, io_output(&result, |&n| buf[..n].to_vec()));
+        result
+    }
+}
+
+impl Write for RecordingSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.recorder.log(Op::SocketWrite, socket_input(self.id, buf), io_output(&result, |&n| (n as u32).to_le_bytes().to_vec()));
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.recorder.log(Op::SocketFlush, socket_input(self.id, &[]), io_output(&result, |_| Vec::new()));
+        result
+    }
+}
+
+impl Socket for RecordingSocket {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+}
+
+// ---- Replay ----
+
+struct Replayer {
+    events: Vec<Event>,
+    next: Mutex<usize>,
+}
+
+impl Replayer {
+    /// The recorded output of the next call, which must be `op(input)`
+    fn answer(&self, op: Op, input: &[u8]) -> Output {
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(event) = self.events.get(*next) else {
+            panic!("replay diverged: the program called {} after the trace's last event ({})", op.name(), *next);
+        };
+        if event.op != op || event.input != input {
+            panic!(
+                "replay diverged at event {}: the program called {}({}) but the trace has {}({})",
+                *next, op.name(), describe(op, input), event.op.name(), describe(event.op, &event.input)
+            );
+        }
+        *next += 1;
+        event.output.clone()
+    }
+}
+
+/// An event's input for a divergence message
+fn describe(op: Op, input: &[u8]) -> String {
+    let text = |bytes: &[u8]| {
+        let text = String::from_utf8_lossy(bytes);
+        if text.chars().count() > 40 { format!("{:?}...", text.chars().take(40).collect::<String>()) } else { format!("{:?}", text) }
+    };
+    match op {
+        Op::ReadFile | Op::Exists | Op::ReadDir | Op::Connect | Op::EnvVar | Op::RemoveFile => text(input),
+        Op::WriteFile => {
+            let mut reader = Reader { data: input, pos: 0 };
+            let path = reader.bytes().unwrap_or_default();
+            format!("{}, {} bytes", text(&path), input.len() - reader.pos)
+        }
+        Op::Rename => {
+            let mut reader = Reader { data: input, pos: 0 };
+            let from = reader.bytes().unwrap_or_default();
+            format!("{}, {}", text(&from), text(&input[reader.pos..]))
+        }
+        Op::SocketRead | Op::SocketWrite | Op::SocketFlush => format!("socket {}, {} bytes", socket_id(input), input.len().saturating_sub(4)),
+        Op::Sleep => format!("{:?}", duration(input)),
+        Op::Random => format!("{}", u32::from_le_bytes(input.try_into().unwrap_or_default())),
+        Op::Now | Op::TempDir | Op::Monotonic => String::new(),
+    }
+}
+
+/// The leading socket id of a socket event's input (or a connect's output)
+fn socket_id(input: &[u8]) -> u32 {
+    input.get(..4).and_then(|id| id.try_into().ok()).map(u32::from_le_bytes).unwrap_or(0)
+}
+
+/// Answers every call from a trace; only output goes to `host`
+pub struct ReplayPlatform {
+    replayer: Arc<Replayer>,
+    host: Arc<dyn Platform>,
+}
+
+impl ReplayPlatform {
+    pub fn open(path: &str, host: Arc<dyn Platform>) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let events = decode(&data).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(ReplayPlatform { replayer: Arc::new(Replayer { events, next: Mutex::new(0) }), host })
+    }
+
+    /// (events replayed, events in the trace)
+    pub fn progress(&self) -> (usize, usize) {
+        (*self.replayer.next.lock().unwrap_or_else(|e| e.into_inner()), self.replayer.events.len())
+    }
+}
+
+impl Platform for ReplayPlatform {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        io_result(self.replayer.answer(Op::ReadFile, path.as_bytes()), |data| data)
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let mut input = Vec::new();
+        put_bytes(&mut input, path.as_bytes());
+        input.extend_from_slice(data);
+        io_result(self.replayer.answer(Op::WriteFile, &input), |_| ())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        io_result(self.replayer.answer(Op::Rename, &rename_input(from, to)), |_| ())
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        io_result(self.replayer.answer(Op::RemoveFile, path.as_bytes()), |_| ())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.replayer.answer(Op::Exists, path.as_bytes()).is_ok_and(|data| data == [1])
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        io_result(self.replayer.answer(Op::ReadDir, path.as_bytes()), decode_entries)
+    }
+
+    fn temp_dir(&self) -> String {
+        String::from_utf8_lossy(&self.replayer.answer(Op::TempDir, &[]).unwrap_or_default()).into_owned()
+    }
+
+    fn connect(&self, addr: &str) -> io::Result<Box<dyn Socket>> {
+        let id = io_result(self.replayer.answer(Op::Connect, addr.as_bytes()), |data| socket_id(&data))?;
+        Ok(Box::new(ReplaySocket { id, replayer: self.replayer.clone() }))
+    }
+
+    fn now(&self) -> Duration {
+        duration(&self.replayer.answer(Op::Now, &[]).unwrap_or_default())
+    }
+
+    fn monotonic(&self) -> Duration {
+        duration(&self.replayer.answer(Op::Monotonic, &[]).unwrap_or_default())
+    }
+
+    fn sleep(&self, duration: Duration) {
+        // Replays don't wait
+        let _ = self.replayer.answer(Op::Sleep, &nanos(duration));
+    }
+
+    fn random_bytes(&self, n: usize) -> io::Result<Vec<u8>> {
+        io_result(self.replayer.answer(Op::Random, &(n as u32).to_le_bytes()), |bytes| bytes)
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.replayer.answer(Op::EnvVar, name.as_bytes()).ok().map(|v| String::from_utf8_lossy(&v).into_owned())
+    }
+
+    fn set_env_var(&self, _name: &str, _value: &str) {
+        // The recorded env_var answers already reflect it
+    }
+
+    fn write_stdout(&self, text: &str) {
+        self.host.write_stdout(text)
+    }
+}
+
+struct ReplaySocket {
+    id: u32,
+    replayer: Arc<Replayer>,
+}
+
+impl Read for ReplaySocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let input = socket_input(self.id, &(buf.len() as u32).to_le_bytes());
+        let data = io_result(self.replayer.answer(Op::SocketRead, &input), |data| data)?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+}
+
+impl Write for ReplaySocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io_result(self.replayer.answer(Op::SocketWrite, &socket_input(self.id, buf)), |data| socket_id(&data) as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io_result(self.replayer.answer(Op::SocketFlush, &socket_input(self.id, &[])), |_| ())
+    }
+}
+
+impl Socket for ReplaySocket {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::MemoryPlatform;
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("cryo_trace_{}.bin", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let memory = Arc::new(MemoryPlatform::new().with_file("in.txt", "hello").with_env("MODE", "prod"));
+        let calls = |platform: &dyn Platform| {
+            let text = platform.read_to_string("in.txt").unwrap();
+            platform.write_file("out/a.txt", text.as_bytes()).unwrap();
+            platform.sleep(Duration::from_secs(3));
+            let listing = platform.read_dir("out").unwrap();
+            (text, listing, platform.now(), platform.env_var("MODE"), platform.env_var("HOME"), platform.random_bytes(4).unwrap(), platform.exists("missing"), platform.connect("x:1").is_err())
+        };
+        let recorded = calls(&RecordingPlatform::create(&path, memory).unwrap());
+        let replay = ReplayPlatform::open(&path, Arc::new(MemoryPlatform::new())).unwrap();
+        assert_eq!(calls(&replay), recorded);
+        assert_eq!(replay.progress(), (10, 10));
+        // Calls past the end of the trace are a divergence
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| replay.now())).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
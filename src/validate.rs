@@ -0,0 +1,289 @@
+// ============================================
+// Cryo Value Validation
+// validate(value, schema): check a value (typically a parsed request body)
+// against a JSON-Schema-like description and report every problem with the
+// path to it:
+//
+//   let schema = {
+//       type: "object",
+//       required: ["name", "items"],
+//       properties: {
+//           name: { type: "string", min_length: 1 },
+//           items: { type: "array", items: { type: "int", min: 1 } },
+//       },
+//   };
+//   validate(body, schema)  // [{ path: "$.items[1]", message: "must be at least 1" }]
+// ============================================
+
+use crate::glob;
+use crate::interpreter::Value;
+
+/// Every key a schema may use
+const SCHEMA_KEYS: &[&str] = &[
+    "type", "required", "properties", "additional", "items", "min", "max", "min_length", "max_length", "enum", "pattern",
+];
+
+/// One failed check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// `$` for the value itself, then `.field` and `[index]` steps
+    pub path: String,
+    pub message: String,
+}
+
+/// Every way `value` fails `schema`, in the order found; empty when it's valid.
+/// Err when the schema itself is malformed.
+pub fn validate(value: &Value, schema: &Value) -> Result<Vec<Issue>, String> {
+    let mut issues = Vec::new();
+    check(value, schema, "$", &mut issues)?;
+    Ok(issues)
+}
+
+fn check(value: &Value, schema: &Value, path: &str, issues: &mut Vec<Issue>) -> Result<(), String> {
+    let Value::Struct(_, rules) = schema else {
+        return Err(format!("validate: schema at {} must be an object, got {}", path, schema.to_string_val()));
+    };
+    let rules = rules.borrow();
+    if let Some(key) = rules.keys().find(|k| !SCHEMA_KEYS.contains(&k.as_str())) {
+        return Err(format!("validate: unknown schema key '{}' at {}", key, path));
+    }
+    let mut fail = |message: String| issues.push(Issue { path: path.to_string(), message });
+
+    if let Some(types) = rules.get("type") {
+        let names = strings(types, "type", path)?;
+        if !names.iter().any(|t| has_type(value, t)) {
+            fail(format!("expected {}, got {}", names.join(" or "), type_name(value)));
+            // Nothing else about the value can be checked meaningfully
+            return Ok(());
+        }
+    }
+    if let Some(allowed) = rules.get("enum") {
+        let Value::Array(allowed) = allowed else {
+            return Err(format!("validate: 'enum' at {} must be an array", path));
+        };
+        let allowed = allowed.borrow();
+        if !allowed.iter().any(|a| same(a, value)) {
+            let options: Vec<String> = allowed.iter().map(display).collect();
+            fail(format!("must be one of {}", options.join(", ")));
+        }
+    }
+    if is_number(value) {
+        if let Some(min) = number(&rules, "min", path)? {
+            if value.to_decimal() < min.to_decimal() {
+                fail(format!("must be at least {}", min.to_string_val()));
+            }
+        }
+        if let Some(max) = number(&rules, "max", path)? {
+            if value.to_decimal() > max.to_decimal() {
+                fail(format!("must be at most {}", max.to_string_val()));
+            }
+        }
+    }
+    let length = match value {
+        Value::String(s) => Some(s.chars().count() as i64),
+        Value::Array(items) => Some(items.borrow().len() as i64),
+        Value::Collection(c) => Some(c.borrow().len() as i64),
+        Value::Map(m) => Some(m.borrow().len() as i64),
+        _ => None,
+    };
+    if let Some(length) = length {
+        if let Some(min) = number(&rules, "min_length", path)? {
+            if length < min.as_int() {
+                fail(format!("length must be at least {}", min.as_int()));
+            }
+        }
+        if let Some(max) = number(&rules, "max_length", path)? {
+            if length > max.as_int() {
+                fail(format!("length must be at most {}", max.as_int()));
+            }
+        }
+    }
+    if let (Some(pattern), Value::String(s)) = (rules.get("pattern"), value) {
+        let pattern = pattern.to_string_val();
+        if !glob::matches(&pattern, s) {
+            fail(format!("must match {}", pattern));
+        }
+    }
+
+    if let Value::Struct(_, fields) = value {
+        let fields = fields.borrow();
+        if let Some(required) = rules.get("required") {
+            for name in strings(required, "required", path)? {
+                if fields.get(&name).is_none_or(|v| matches!(v, Value::Null)) {
+                    issues.push(Issue { path: field_path(path, &name), message: "is required".to_string() });
+                }
+            }
+        }
+        let properties = match rules.get("properties") {
+            Some(Value::Struct(_, p)) => Some(p.borrow()),
+            Some(other) => return Err(format!("validate: 'properties' at {} must be an object, got {}", path, other.to_string_val())),
+            None => None,
+        };
+        let mut names: Vec<&String> = fields.keys().collect();
+        // Fields are a hash map; sort so reports are stable
+        names.sort();
+        for name in names {
+            match properties.as_ref().and_then(|p| p.get(name)) {
+                Some(field_schema) => check(&fields[name], field_schema, &field_path(path, name), issues)?,
+                None if rules.get("additional").is_some_and(|a| !a.is_truthy()) => {
+                    issues.push(Issue { path: field_path(path, name), message: "is not allowed".to_string() });
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Some(item_schema), Value::Array(items)) = (rules.get("items"), value) {
+        for (i, item) in items.borrow().iter().enumerate() {
+            check(item, item_schema, &format!("{}[{}]", path, i), issues)?;
+        }
+    }
+    Ok(())
+}
+
+/// A string, or an array of strings, as a list
+fn strings(value: &Value, key: &str, path: &str) -> Result<Vec<String>, String> {
+    match value {
+        Value::String(s) => Ok(vec![s.to_string()]),
+        Value::Array(items) => items
+            .borrow()
+            .iter()
+            .map(|v| match v {
+                Value::String(s) => Ok(s.to_string()),
+                other => Err(format!("validate: '{}' at {} must list strings, got {}", key, path, other.to_string_val())),
+            })
+            .collect(),
+        other => Err(format!("validate: '{}' at {} must be a string or array of strings, got {}", key, path, other.to_string_val())),
+    }
+}
+
+fn number(rules: &std::collections::HashMap<String, Value>, key: &str, path: &str) -> Result<Option<Value>, String> {
+    match rules.get(key) {
+        None => Ok(None),
+        Some(v) if is_number(v) => Ok(Some(v.clone())),
+        Some(other) => Err(format!("validate: '{}' at {} must be a number, got {}", key, path, other.to_string_val())),
+    }
+}
+
+fn is_number(value: &Value) -> bool {
+    matches!(value, Value::Int(_) | Value::Float(_) | Value::BigInt(_) | Value::Decimal(_))
+}
+
+/// The names `typeof` uses, except that objects are "object"
+fn type_name(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "bool".to_string(),
+        Value::Int(_) => "int".to_string(),
+        Value::Float(_) => "float".to_string(),
+        Value::BigInt(_) => "bigint".to_string(),
+        Value::Decimal(_) => "decimal".to_string(),
+        Value::String(_) => "string".to_string(),
+        Value::Array(_) => "array".to_string(),
+        Value::Struct(name, _) if name.as_str().is_empty() => "object".to_string(),
+        Value::Struct(name, _) => name.to_string(),
+        Value::Function(_) => "function".to_string(),
+        Value::Map(_) => "map".to_string(),
+        Value::Enum(e) => e.name.to_string(),
+        Value::Collection(c) => c.borrow().kind().to_string(),
+    }
+}
+
+/// `type` names: any `typeof` name, "number" for int/float/bigint/decimal,
+/// "object" for any struct, a struct name, or "any"
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "any" => true,
+        "number" => is_number(value),
+        "object" | "struct" => matches!(value, Value::Struct(..)),
+        _ => type_name(value) == name,
+    }
+}
+
+/// Equality for `enum` and diff(): numbers by value, everything else by kind and text
+pub(crate) fn same(a: &Value, b: &Value) -> bool {
+    if is_number(a) && is_number(b) {
+        return a.to_decimal() == b.to_decimal();
+    }
+    type_name(a) == type_name(b) && a.to_string_val() == b.to_string_val()
+}
+
+pub(crate) fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => crate::json::quote(s),
+        other => other.to_string_val(),
+    }
+}
+
+/// `$.name`, or `$["odd key"]` when the name isn't an identifier
+pub(crate) fn field_path(path: &str, name: &str) -> String {
+    let plain = name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_') && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if plain {
+        format!("{}.{}", path, name)
+    } else {
+        format!("{}[{}]", path, crate::json::quote(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn s(text: &str) -> Value {
+        Value::String(text.into())
+    }
+
+    fn arr(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    fn obj(fields: Vec<(&str, Value)>) -> Value {
+        Value::object(fields.into_iter().map(|(k, v)| (k.to_string(), v)))
+    }
+
+    fn report(issues: Vec<Issue>) -> Vec<String> {
+        issues.into_iter().map(|i| format!("{}: {}", i.path, i.message)).collect()
+    }
+
+    #[test]
+    fn test_nested_shape() {
+        let schema = obj(vec![
+            ("type", s("object")),
+            ("required", arr(vec![s("name"), s("items"), s("email")])),
+            ("additional", Value::Bool(false)),
+            ("properties", obj(vec![
+                ("name", obj(vec![("type", s("string")), ("min_length", Value::Int(1))])),
+                ("role", obj(vec![("enum", arr(vec![s("admin"), s("user")]))])),
+                ("email", obj(vec![("type", arr(vec![s("string"), s("null")])), ("pattern", s("*@*.*"))])),
+                ("items", obj(vec![("type", s("array")), ("items", obj(vec![("type", s("int")), ("min", Value::Int(1)), ("max", Value::Int(10))]))])),
+            ])),
+        ]);
+        let good = obj(vec![("name", s("a")), ("email", s("a@b.c")), ("items", arr(vec![Value::Int(1)]))]);
+        assert_eq!(validate(&good, &schema).unwrap(), []);
+
+        let bad = obj(vec![
+            ("name", s("")),
+            ("role", s("root")),
+            ("items", arr(vec![Value::Int(3), Value::Int(0), s("x"), Value::Int(11)])),
+            ("extra key", Value::Int(1)),
+        ]);
+        assert_eq!(report(validate(&bad, &schema).unwrap()), [
+            "$.email: is required",
+            "$[\"extra key\"]: is not allowed",
+            "$.items[1]: must be at least 1",
+            "$.items[2]: expected int, got string",
+            "$.items[3]: must be at most 10",
+            "$.name: length must be at least 1",
+            "$.role: must be one of \"admin\", \"user\"",
+        ]);
+        assert_eq!(report(validate(&Value::Int(1), &schema).unwrap()), ["$: expected object, got int"]);
+    }
+
+    #[test]
+    fn test_bad_schema() {
+        assert_eq!(validate(&Value::Int(1), &obj(vec![("minimum", Value::Int(1))])).unwrap_err(), "validate: unknown schema key 'minimum' at $");
+        assert!(validate(&Value::Int(1), &obj(vec![("min", s("1"))])).is_err());
+        assert!(validate(&Value::Int(1), &s("int")).is_err());
+    }
+}
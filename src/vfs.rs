@@ -0,0 +1,361 @@
+// ============================================
+// Cryo Virtual Filesystem
+// An in-memory directory tree for readFile/writeFile/listDir/glob, used by
+// `MemoryPlatform` and by `cryo --vfs snapshot.json`. Paths are relative to
+// the tree's root: `/etc/x`, `./etc/x` and `a/../etc/x` all name `etc/x`,
+// so nothing outside the tree can be reached.
+//
+// Snapshots are JSON; objects are directories and strings are files:
+//
+//   { "data": { "in.txt": "hello", "logo.png": { "$base64": "iVBORw0..." } }, "tmp": {} }
+//
+// A file that isn't UTF-8 is written as `{ "$base64": ... }`.
+// ============================================
+
+use crate::encoding;
+use crate::json;
+use crate::platform::{DirEntry, Platform, Socket};
+use crate::threading::SendValue;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Node>),
+}
+
+/// Always a `Node::Dir`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vfs {
+    root: Node,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Vfs { root: Node::Dir(BTreeMap::new()) }
+    }
+
+    /// Load a snapshot file from the real filesystem
+    pub fn load(path: &str) -> Result<Vfs, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Vfs::from_snapshot(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn from_snapshot(text: &str) -> Result<Vfs, String> {
+        match node_from_json(&json::parse(text)?, "")? {
+            Node::File(_) => Err("VFS snapshot must be a JSON object".to_string()),
+            root => Ok(Vfs { root }),
+        }
+    }
+
+    /// The tree as snapshot JSON, with sorted keys and two-space indents
+    pub fn snapshot(&self) -> String {
+        let mut out = String::new();
+        if let Node::Dir(entries) = &self.root {
+            write_dir(entries, 0, &mut out);
+        }
+        out.push('\n');
+        out
+    }
+
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self.find(path) {
+            Some(Node::File(data)) => Ok(data.clone()),
+            Some(Node::Dir(_)) => Err(error(io::ErrorKind::Other, path, "is a directory")),
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// Create or replace a file, creating its parent directories
+    pub fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        let mut parts = split(path);
+        let Some(name) = parts.pop() else {
+            return Err(error(io::ErrorKind::Other, path, "is a directory"));
+        };
+        let dir = self.dir_mut(&parts, path)?;
+        if let Some(Node::Dir(_)) = dir.get(name) {
+            return Err(error(io::ErrorKind::Other, path, "is a directory"));
+        }
+        dir.insert(name.to_string(), Node::File(data.to_vec()));
+        Ok(())
+    }
+
+    /// Create a directory and any missing parents
+    pub fn create_dir_all(&mut self, path: &str) -> io::Result<()> {
+        self.dir_mut(&split(path), path).map(|_| ())
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    /// Move a file or directory to `to`, replacing a file already there
+    pub fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        if let Some(Node::Dir(_)) = self.find(to) {
+            return Err(error(io::ErrorKind::Other, to, "is a directory"));
+        }
+        let mut target = split(to);
+        let Some(name) = target.pop() else {
+            return Err(error(io::ErrorKind::Other, to, "is a directory"));
+        };
+        if !self.exists(from) {
+            return Err(not_found(from));
+        }
+        if split(to).len() > split(from).len() && split(to).starts_with(&split(from)) {
+            return Err(error(io::ErrorKind::Other, to, "is inside the directory being moved"));
+        }
+        // Make the destination's parents before taking anything out of the tree
+        self.dir_mut(&target, to)?;
+        let node = self.take(from)?;
+        self.dir_mut(&target, to)?.insert(name.to_string(), node);
+        Ok(())
+    }
+
+    /// Delete a file
+    pub fn remove_file(&mut self, path: &str) -> io::Result<()> {
+        match self.find(path) {
+            Some(Node::File(_)) => self.take(path).map(|_| ()),
+            Some(Node::Dir(_)) => Err(error(io::ErrorKind::Other, path, "is a directory")),
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// Entries of directory `path`, sorted by name
+    pub fn list(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        match self.find(path) {
+            Some(Node::Dir(entries)) => Ok(entries.iter()
+                .map(|(name, node)| DirEntry { name: name.clone(), is_dir: matches!(node, Node::Dir(_)) })
+                .collect()),
+            Some(Node::File(_)) => Err(error(io::ErrorKind::Other, path, "is not a directory")),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn find(&self, path: &str) -> Option<&Node> {
+        let mut node = &self.root;
+        for name in split(path) {
+            match node {
+                Node::Dir(entries) => node = entries.get(name)?,
+                Node::File(_) => return None,
+            }
+        }
+        Some(node)
+    }
+
+    /// Remove the node at `path` from its directory
+    fn take(&mut self, path: &str) -> io::Result<Node> {
+        let mut parts = split(path);
+        let name = parts.pop().ok_or_else(|| error(io::ErrorKind::Other, path, "is the root"))?;
+        if !self.exists(path) {
+            return Err(not_found(path));
+        }
+        Ok(self.dir_mut(&parts, path)?.remove(name).expect("checked above"))
+    }
+
+    /// The directory at `parts`, creating missing ones
+    fn dir_mut(&mut self, parts: &[&str], path: &str) -> io::Result<&mut BTreeMap<String, Node>> {
+        let Node::Dir(root) = &mut self.root else { unreachable!("the VFS root is a directory") };
+        let mut dir = root;
+        for name in parts {
+            let node = dir.entry(name.to_string()).or_insert_with(|| Node::Dir(BTreeMap::new()));
+            match node {
+                Node::Dir(entries) => dir = entries,
+                Node::File(_) => return Err(error(io::ErrorKind::Other, path, "a parent is a file")),
+            }
+        }
+        Ok(dir)
+    }
+}
+
+/// `path` as names from the root: `.` and empty segments dropped, `..`
+/// resolved, never above the root
+fn split(path: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts
+}
+
+fn error(kind: io::ErrorKind, path: &str, what: &str) -> io::Error {
+    io::Error::new(kind, format!("{}: {}", path, what))
+}
+
+fn not_found(path: &str) -> io::Error {
+    error(io::ErrorKind::NotFound, path, "no such file or directory")
+}
+
+fn node_from_json(value: &SendValue, path: &str) -> Result<Node, String> {
+    match value {
+        SendValue::String(text) => Ok(Node::File(text.as_bytes().to_vec())),
+        SendValue::Struct(_, fields) => {
+            if let (1, Some(SendValue::String(data))) = (fields.len(), fields.get("$base64")) {
+                return encoding::base64_decode(data).map(Node::File).map_err(|e| format!("{}: {}", path, e));
+            }
+            let mut entries = BTreeMap::new();
+            for (name, value) in fields {
+                let child = if path.is_empty() { name.clone() } else { format!("{}/{}", path, name) };
+                if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+                    return Err(format!("{}: not a file name", child));
+                }
+                entries.insert(name.clone(), node_from_json(value, &child)?);
+            }
+            Ok(Node::Dir(entries))
+        }
+        _ => Err(format!("{}: expected a string (file) or object (directory)", if path.is_empty() { "/" } else { path })),
+    }
+}
+
+fn write_dir(entries: &BTreeMap<String, Node>, depth: usize, out: &mut String) {
+    if entries.is_empty() {
+        out.push_str("{}");
+        return;
+    }
+    out.push('{');
+    for (i, (name, node)) in entries.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&"  ".repeat(depth + 1));
+        out.push_str(&json::quote(name));
+        out.push_str(": ");
+        match node {
+            Node::Dir(children) => write_dir(children, depth + 1, out),
+            Node::File(data) => match std::str::from_utf8(data) {
+                Ok(text) => out.push_str(&json::quote(text)),
+                Err(_) => {
+                    out.push_str("{ \"$base64\": ");
+                    out.push_str(&json::quote(&encoding::base64_encode(data, false, true)));
+                    out.push_str(" }");
+                }
+            },
+        }
+    }
+    out.push('\n');
+    out.push_str(&"  ".repeat(depth));
+    out.push('}');
+}
+
+/// Where temp_file() puts files in a `Vfs`
+pub const TEMP_DIR: &str = "/tmp";
+
+/// Files from a `Vfs`; the network, clock, environment and stdout from `host`
+pub struct VfsPlatform {
+    vfs: Mutex<Vfs>,
+    host: Arc<dyn Platform>,
+}
+
+impl VfsPlatform {
+    pub fn new(vfs: Vfs, host: Arc<dyn Platform>) -> Self {
+        VfsPlatform { vfs: Mutex::new(vfs), host }
+    }
+
+    /// The tree as it is now, including everything the program wrote
+    pub fn vfs(&self) -> Vfs {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vfs> {
+        self.vfs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Platform for VfsPlatform {
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.lock().read(path)
+    }
+
+    fn write_file(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.lock().write(path, data)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        self.lock().rename(from, to)
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        self.lock().remove_file(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.lock().exists(path)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        self.lock().list(path)
+    }
+
+    fn temp_dir(&self) -> String {
+        TEMP_DIR.to_string()
+    }
+
+    fn connect(&self, addr: &str) -> io::Result<Box<dyn Socket>> {
+        self.host.connect(addr)
+    }
+
+    fn now(&self) -> Duration {
+        self.host.now()
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.host.monotonic()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.host.sleep(duration)
+    }
+
+    fn random_bytes(&self, n: usize) -> io::Result<Vec<u8>> {
+        self.host.random_bytes(n)
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.host.env_var(name)
+    }
+
+    fn set_env_var(&self, name: &str, value: &str) {
+        self.host.set_env_var(name, value)
+    }
+
+    fn write_stdout(&self, text: &str) {
+        self.host.write_stdout(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vfs_tree_and_snapshot() {
+        let mut vfs = Vfs::from_snapshot(r#"{ "data": { "in.txt": "hi", "bin": { "$base64": "/wA=" } }, "tmp": {} }"#).unwrap();
+        assert_eq!(vfs.read("/data/../data/in.txt").unwrap(), b"hi");
+        assert_eq!(vfs.read("data/bin").unwrap(), [0xff, 0]);
+        assert!(vfs.exists("tmp") && vfs.exists(".") && !vfs.exists("data/in.txt/x"));
+        vfs.write("tmp/out/a.txt", b"A").unwrap();
+        assert!(vfs.write("data/in.txt/x", b"").is_err() && vfs.write("tmp", b"").is_err());
+        assert_eq!(vfs.list("data").unwrap(), [
+            DirEntry { name: "bin".to_string(), is_dir: false },
+            DirEntry { name: "in.txt".to_string(), is_dir: false },
+        ]);
+        assert_eq!(vfs.list("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+        let text = vfs.snapshot();
+        assert!(text.contains("\"bin\": { \"$base64\": \"/wA=\" }") && text.contains("\"a.txt\": \"A\""));
+        assert_eq!(Vfs::from_snapshot(&text).unwrap(), vfs);
+        vfs.rename("tmp/out/a.txt", "data/in.txt").unwrap();
+        assert_eq!(vfs.read("data/in.txt").unwrap(), b"A");
+        assert!(!vfs.exists("tmp/out/a.txt") && vfs.exists("tmp/out"));
+        assert!(vfs.rename("data/in.txt", "tmp").is_err() && vfs.rename("data/in.txt", "data/bin/x").is_err());
+        assert_eq!(vfs.rename("nope", "x").unwrap_err().kind(), io::ErrorKind::NotFound);
+        vfs.remove_file("data/in.txt").unwrap();
+        assert!(!vfs.exists("data/in.txt") && vfs.remove_file("tmp").is_err());
+        assert!(Vfs::from_snapshot(r#"{ "a": 1 }"#).is_err() && Vfs::from_snapshot(r#"{ "..": "x" }"#).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+// Cryo CLI Tests
+// Run the built `cryo` binary end to end, for behaviour a unit test can't
+// reach: the executable from `--compile`, or a script piped to `cryo -`.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn cryo(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cryo")).args(args).output().expect("cannot run cryo")
+}
+
+/// Whether `tool --version` runs, for tests that need the LLVM toolchain
+fn have(tool: &str) -> bool {
+    Command::new(tool).arg("--version").output().is_ok_and(|o| o.status.success())
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cryo {} {}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_compile_runs() {
+    if !have("llc") || !have("cc") {
+        eprintln!("skipping: --compile needs llc and cc");
+        return;
+    }
+    let dir = scratch("compile");
+    let source = dir.join("squares.cryo");
+    std::fs::write(&source, "fn square(n) {\n    return n * n;\n}\n\nfn main() {\n    let i = 1;\n    while (i <= 3) {\n        print(square(i));\n        i = i + 1;\n    }\n    print(\"done\");\n}\n").unwrap();
+    let exe = dir.join("squares");
+    let built = cryo(&["--compile", source.to_str().unwrap(), "-o", exe.to_str().unwrap()]);
+    assert!(built.status.success(), "{}", String::from_utf8_lossy(&built.stderr));
+    let run = Command::new(&exe).output().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(run.status.success());
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "1\n4\n9\ndone\n");
+}
+
+#[test]
+fn test_compile_needs_output() {
+    let dir = scratch("compile no output");
+    let source = dir.join("app.cryo");
+    std::fs::write(&source, "fn main() { print(1); }\n").unwrap();
+    let built = cryo(&["--compile", source.to_str().unwrap()]);
+    let written = std::fs::read_dir(&dir).unwrap().count();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(built.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&built.stderr).contains("USAGE: cryo --compile FILE -o OUT"));
+    // Nothing guessed: the source is the only file
+    assert_eq!(written, 1);
+}
+
+#[test]
+fn test_script_from_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cryo"))
+        .args(["--interpret", "-", "from-stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let script = "#!/usr/bin/env cryo\nfn main() {\n    print(\"hello \" + get_args()[1]);\n}\n";
+    child.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+    let run = child.wait_with_output().unwrap();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "hello from-stdin\n");
+}